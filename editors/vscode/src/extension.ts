@@ -10,7 +10,16 @@ export function activate(context: vscode.ExtensionContext) {
     };
     const clientOptions: LanguageClientOptions = {
         documentSelector: [{ scheme: 'file', language: 'mumei' }],
+        initializationOptions: {
+            stdPath: config.get<string>('stdPath', ''),
+            verifyMode: config.get<string>('verifyMode', 'onChange'),
+            timeoutMs: config.get<number>('timeoutMs', 5000),
+            maxDiagnostics: config.get<number>('maxDiagnostics', 100),
+            maxDocumentBytes: config.get<number>('maxDocumentBytes', 5242880),
+        },
         synchronize: {
+            // 設定変更は workspace/didChangeConfiguration（settings.mumei）で送られる
+            configurationSection: 'mumei',
             fileEvents: vscode.workspace.createFileSystemWatcher('**/*.mm'),
         },
     };
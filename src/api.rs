@@ -0,0 +1,249 @@
+//! # ライブラリ API
+//!
+//! ビルドスクリプトやテストハーネスから、ソース文字列をそのまま検証・変換するための入口。
+//! lib.rs が crate のルートに再公開する（`mumei::parse` / `mumei::verify_items` / `mumei::transpile_items`）。
+//!
+//! どの関数も process::exit や stdout / stderr への出力をしない。準備段階の diagnostic と
+//! 検証の進捗は VerifyProgress に通知し、結果は戻り値で返す。表示と終了コードへの変換は
+//! 呼び出し側の責務で、`mumei verify`（main.rs）もこの API の上の薄い層になっている。
+use std::path::{Path, PathBuf};
+use crate::parser::{self, Item, ParseError};
+use crate::pipeline::{self, Diagnostic, ManifestLookup, PreparedModule, VerifyEvent, VerifySummary};
+use crate::report::{self, Severity};
+use crate::resolver;
+use crate::theory;
+use crate::transpiler::{self, TargetLanguage, UnsupportedPolicy, UnsupportedSummary};
+use crate::verification::{CounterexampleFormat, ModuleEnv, DEFAULT_TIMEOUT_MS};
+
+/// verify_items / transpile_items の設定
+#[derive(Debug, Clone)]
+pub struct VerifyOptions {
+    /// ソースの置き場所（実在しなくてよい）。import はこのファイルのディレクトリから解決し、
+    /// レポートのモジュール名とリソース定義の衝突の報告にも使う
+    pub path: PathBuf,
+    /// mumei.toml の探索方法（既定は探さない）。見つかれば [dependencies] と [proof] の設定を使う
+    pub manifest: ManifestLookup,
+    /// atom 1 件あたりの Z3 のタイムアウト。None なら mumei.toml の `[proof] timeout_ms`
+    /// （mumei.toml がなければ verification::DEFAULT_TIMEOUT_MS）
+    pub timeout_ms: Option<u64>,
+    /// 並列に検証する atom の最大数
+    pub jobs: usize,
+    pub counterexample_format: CounterexampleFormat,
+    /// path のディレクトリの .mumei_build_cache を読み込み、検証後に保存する（既定は使わない）
+    pub build_cache: bool,
+    /// atom 単位の report.json の書き出し先（既定は一時ディレクトリ）
+    pub output_dir: PathBuf,
+    /// 標準ライブラリのディレクトリ（既定は None で、MUMEI_STD_PATH を含む通常の探索順序）
+    pub std_path: Option<PathBuf>,
+}
+
+impl Default for VerifyOptions {
+    fn default() -> Self {
+        VerifyOptions {
+            path: PathBuf::from("main.mm"),
+            manifest: ManifestLookup::Disabled,
+            timeout_ms: None,
+            jobs: 1,
+            counterexample_format: CounterexampleFormat::default(),
+            build_cache: false,
+            output_dir: std::env::temp_dir().join("mumei"),
+            std_path: None,
+        }
+    }
+}
+
+/// verify_items_with の進捗の受け取り手。どのメソッドも既定では何もしない
+pub trait VerifyProgress {
+    /// 準備段階（prelude・依存・import・単相化・定義の登録）の diagnostic。発生順に、検証の前に通知する
+    fn diagnostic(&mut self, _diagnostic: &Diagnostic) {}
+
+    /// impl / atom の検証の進捗
+    fn event(&mut self, _event: VerifyEvent<'_>, _module_env: &ModuleEnv) {}
+}
+
+/// 進捗を受け取らない（verify_items）
+impl VerifyProgress for () {}
+
+/// verify_items の結果
+#[derive(Debug)]
+pub struct VerificationReport {
+    /// 準備段階の diagnostic（発生順）。Error があれば検証は行っていない
+    pub diagnostics: Vec<Diagnostic>,
+    pub summary: VerifySummary,
+    /// impl / atom ごとの結果（`mumei verify` の report.json と同じスキーマ。ファイルには書かない）
+    pub report: report::Report,
+}
+
+impl VerificationReport {
+    /// 準備段階で致命的なエラーがあった（検証は行っていない）
+    pub fn prepare_failed(&self) -> bool {
+        self.diagnostics.iter().any(|d| d.severity == Severity::Error)
+    }
+
+    /// 準備に成功し、検証に失敗した impl / atom がない
+    pub fn passed(&self) -> bool {
+        !self.prepare_failed() && self.summary.failed == 0
+    }
+}
+
+/// ソース文字列をパースする。import の解決と単相化は verify_items / transpile_items が行う
+pub fn parse(source: &str) -> Result<Vec<Item>, ParseError> {
+    parser::parse_module(source)
+}
+
+/// items を options.path に置かれたモジュールとして Z3 で検証する
+pub fn verify_items(items: &[Item], options: &VerifyOptions) -> VerificationReport {
+    verify_items_with(items, options, &mut ())
+}
+
+/// verify_items と同じ。準備段階の diagnostic と検証の進捗を progress に通知する
+pub fn verify_items_with(items: &[Item], options: &VerifyOptions, progress: &mut dyn VerifyProgress) -> VerificationReport {
+    let started = std::time::Instant::now();
+    let mut report = report::Report::new("verify", &options.path.display().to_string());
+    let mut diagnostics = Vec::new();
+    let mut summary = VerifySummary::default();
+
+    if let Some(mut prepared) = prepare(items, options, &mut diagnostics, progress) {
+        prepared.module_env.counterexample_format = options.counterexample_format;
+        let cache_dir = options.build_cache.then(|| base_dir(&options.path));
+        let timeout_ms = options.timeout_ms.or(prepared.module_env.timeout_ms).unwrap_or(DEFAULT_TIMEOUT_MS);
+        summary = pipeline::verify_items(
+            &prepared.items, &mut prepared.module_env, cache_dir, &options.output_dir,
+            timeout_ms, options.jobs.max(1), &mut report,
+            &mut |event, module_env| progress.event(event, module_env),
+        );
+    }
+
+    report.finish(started.elapsed().as_millis() as u64);
+    VerificationReport { diagnostics, summary, report }
+}
+
+/// items を 1 つのバンドル（モジュールのヘッダー + 型定義 + atom）に変換する。検証はしない。
+/// import は VerifyOptions::default() と同じくカレントディレクトリから解決し、
+/// 解決・単相化・登録に失敗すればその diagnostic を返す。
+/// 未対応構文は生成先でコンパイルエラーになるマーカー（`mumei build` の既定）として出力する
+pub fn transpile_items(items: &[Item], lang: TargetLanguage) -> Result<String, Vec<Diagnostic>> {
+    let options = VerifyOptions::default();
+    let mut diagnostics = Vec::new();
+    let prepared = match prepare(items, &options, &mut diagnostics, &mut ()) {
+        Some(prepared) => prepared,
+        None => return Err(diagnostics),
+    };
+    let module_env = &prepared.module_env;
+
+    let mut unsupported = UnsupportedSummary::default();
+    let mut bundle = String::new();
+    for item in &prepared.items {
+        let code = match item {
+            Item::Atom(atom) => transpiler::transpile_for_bundle(atom, lang, module_env, UnsupportedPolicy::CompileError, false, &mut unsupported)
+                .map(|code| format!("{}\n\n", code)),
+            _ => transpiler::transpile_definition(item, lang, module_env, false, false),
+        };
+        bundle.push_str(&code.unwrap_or_default());
+    }
+
+    // ヘッダーにはバンドルが実際に参照する import だけを出す（Go は未使用の import がコンパイルエラー）
+    let import_definitions: Vec<_> = prepared.imports.iter()
+        .map(|import| resolver::import_definitions(import, base_dir(&options.path)).ok())
+        .collect();
+    let imports = transpiler::referenced_imports(&prepared.imports, &import_definitions, &transpiler::bundle_references(&prepared.items, lang));
+    let has_async = prepared.items.iter().any(|item| matches!(item, Item::Atom(atom) if atom.is_async));
+    let module_name = options.path.file_stem().map_or_else(|| "main".to_string(), |stem| stem.to_string_lossy().into_owned());
+    bundle.insert_str(0, &transpiler::transpile_module_header(&imports, &module_name, lang, has_async));
+    if unsupported.has_backend(TargetLanguage::Go) {
+        bundle.insert_str(0, transpiler::golang::UNSUPPORTED_BUILD_TAG);
+    }
+    Ok(bundle)
+}
+
+/// import の解決の起点（path のディレクトリ）
+fn base_dir(path: &Path) -> &Path {
+    path.parent().unwrap_or(Path::new("."))
+}
+
+/// items を options.path のモジュールとして準備する（pipeline::prepare_base + prepare_items）。
+/// diagnostic は発生順に progress へ通知しながら diagnostics に積み、致命的なエラーがあれば None
+fn prepare(items: &[Item], options: &VerifyOptions, diagnostics: &mut Vec<Diagnostic>, progress: &mut dyn VerifyProgress) -> Option<PreparedModule> {
+    theory::register_builtin();
+    let base = pipeline::prepare_base(base_dir(&options.path), &options.manifest, options.std_path.as_deref());
+    let (prepared, unit_diagnostics) = match pipeline::prepare_items(items.to_vec(), &options.path, &base) {
+        Ok(mut prepared) => {
+            let unit_diagnostics = std::mem::take(&mut prepared.diagnostics);
+            (Some(prepared), unit_diagnostics)
+        }
+        Err(unit_diagnostics) => (None, unit_diagnostics),
+    };
+    for diagnostic in base.diagnostics.into_iter().chain(unit_diagnostics) {
+        progress.diagnostic(&diagnostic);
+        diagnostics.push(diagnostic);
+    }
+    prepared
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline::Stage;
+
+    /// 進捗を記録するだけの VerifyProgress
+    #[derive(Default)]
+    struct Recorder {
+        lines: Vec<String>,
+    }
+
+    impl VerifyProgress for Recorder {
+        fn diagnostic(&mut self, diagnostic: &Diagnostic) {
+            self.lines.push(format!("diagnostic {:?}", diagnostic.stage));
+        }
+
+        fn event(&mut self, event: VerifyEvent<'_>, _module_env: &ModuleEnv) {
+            if let VerifyEvent::Finished(Item::Atom(atom), verification) = event {
+                self.lines.push(format!("{} {:?}", atom.name, verification.result.status));
+            }
+        }
+    }
+
+    #[test]
+    fn test_verify_items_in_memory() {
+        let items = parse(r#"
+type Nat = i64 where v >= 0;
+atom inc(x: Nat) requires: true; ensures: result > x; body: x + 1;
+atom broken(x: i64) requires: true; ensures: result > x; body: x - 1;
+"#).unwrap();
+        let mut recorder = Recorder::default();
+        let verification = verify_items_with(&items, &VerifyOptions::default(), &mut recorder);
+        assert!(!verification.prepare_failed() && !verification.passed(), "{:?}", verification.diagnostics);
+        assert_eq!((verification.summary.verified, verification.summary.failed), (1, 1));
+        assert_eq!(verification.report.module.path, "main.mm");
+        let statuses: Vec<(&str, report::AtomStatus)> = verification.report.atoms.iter().map(|a| (a.name.as_str(), a.status)).collect();
+        assert_eq!(statuses, [("inc", report::AtomStatus::Verified), ("broken", report::AtomStatus::Failed)]);
+        // 失敗した atom には検証器が決めた失敗カテゴリが付く
+        let failures: Vec<Option<report::FailureKind>> = verification.report.atoms.iter().map(|a| a.failure).collect();
+        assert_eq!(failures, [None, Some(report::FailureKind::Postcondition)]);
+        let events: Vec<&String> = recorder.lines.iter().filter(|line| !line.starts_with("diagnostic")).collect();
+        assert_eq!(events, ["inc Verified", "broken Failed"]);
+
+        // 準備段階のエラーは検証せずに diagnostic として返す
+        let items = parse("import \"./missing.mm\";\natom f(x: i64) requires: true; ensures: true; body: x;\n").unwrap();
+        let verification = verify_items(&items, &VerifyOptions::default());
+        assert!(verification.prepare_failed() && !verification.passed());
+        assert!(verification.report.atoms.is_empty());
+        assert_eq!(verification.diagnostics.last().map(|d| d.stage), Some(Stage::Imports));
+        let err = transpile_items(&items, TargetLanguage::Rust).unwrap_err();
+        assert_eq!(err.last().map(|d| d.stage), Some(Stage::Imports));
+    }
+
+    #[test]
+    fn test_transpile_items_bundles_definitions_and_atoms() {
+        let items = parse(r#"
+struct Point { x: i64, y: i64 }
+atom double(x: i64) requires: true; ensures: result == x + x; body: x + x;
+@exclude(go) atom twice(x: i64) requires: true; ensures: result == x * 2; body: x * 2;
+"#).unwrap();
+        let rust = transpile_items(&items, TargetLanguage::Rust).unwrap();
+        assert!(rust.contains("pub struct Point") && rust.contains("pub fn double(") && rust.contains("pub fn twice("), "{}", rust);
+        assert!(rust.find("pub struct Point") < rust.find("pub fn double("), "{}", rust);
+        let go = transpile_items(&items, TargetLanguage::Go).unwrap();
+        assert!(go.starts_with("package main") && !go.contains("twice"), "{}", go);
+    }
+}
@@ -23,9 +23,16 @@ impl TypeRef {
         TypeRef { name: name.to_string(), type_args: args }
     }
 
-    /// 表示用の正規化名を返す（例: "Stack<i64>"）
+    /// 配列（スライス）型 `[T]` の型参照を作成する（合成コンストラクタ "slice"）
+    pub fn slice(elem: TypeRef) -> Self {
+        TypeRef { name: "slice".to_string(), type_args: vec![elem] }
+    }
+
+    /// 表示用の正規化名を返す（例: "Stack<i64>", "[f64]"）
     pub fn display_name(&self) -> String {
-        if self.type_args.is_empty() {
+        if let Some(elem) = self.slice_elem() {
+            format!("[{}]", elem.display_name())
+        } else if self.type_args.is_empty() {
             self.name.clone()
         } else {
             let args: Vec<String> = self.type_args.iter().map(|a| a.display_name()).collect();
@@ -33,6 +40,24 @@ impl TypeRef {
         }
     }
 
+    /// `Option<T>`（nullable 糖衣 `T?` の脱糖先）なら中身の型 T を返す
+    pub fn option_inner(&self) -> Option<&TypeRef> {
+        if self.name == "Option" && self.type_args.len() == 1 {
+            self.type_args.first()
+        } else {
+            None
+        }
+    }
+
+    /// 配列型 `[T]` なら要素型 T を返す
+    pub fn slice_elem(&self) -> Option<&TypeRef> {
+        if self.name == "slice" && self.type_args.len() == 1 {
+            self.type_args.first()
+        } else {
+            None
+        }
+    }
+
     /// 型パラメータ（型変数）かどうかを判定する。
     /// 大文字1文字（T, U, V など）を型パラメータとして扱う。
     pub fn is_type_param(&self) -> bool {
@@ -66,6 +91,305 @@ impl TypeRef {
     }
 }
 
+/// 型名文字列（例: "Option<Nat>"）が Option 型なら中身の型名（"Nat"）を返す。
+/// 検証器・コード生成・トランスパイラは Param.type_name しか持たないため、この形で判定する。
+pub fn option_inner_type(type_name: &str) -> Option<String> {
+    parse_type_ref(type_name).option_inner().map(|t| t.display_name())
+}
+
+/// 型名文字列（例: "[f64]"）が配列型なら要素型名（"f64"）を返す
+pub fn slice_elem_type(type_name: &str) -> Option<String> {
+    parse_type_ref(type_name).slice_elem().map(|t| t.display_name())
+}
+
+/// パラメータの型が Str（`Str` そのもの、またはベース型が Str の精緻型）か
+pub fn is_str_param(param: &Param, module_env: &ModuleEnv) -> bool {
+    param.type_name.as_deref().map_or(false, |t| module_env.resolve_base_type(t) == "Str")
+}
+
+/// atom の本体が返す値の種類。戻り値型の宣言（`-> T`）がなければ本体から推論し、
+/// 検証（result のソート）・LLVM の戻り値型・各 transpiler の戻り値型をこの分類で揃える。
+/// 構造体を返す atom（struct_return）は別扱いで、ここでは本体からの推論（Int）になる
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResultKind {
+    Int,
+    Float,
+    Bool,
+    Str,
+}
+
+impl ResultKind {
+    /// エラーメッセージ用の呼び方
+    pub fn describe(&self) -> &'static str {
+        match self {
+            ResultKind::Int | ResultKind::Float => "a number",
+            ResultKind::Bool => "a boolean",
+            ResultKind::Str => "a string",
+        }
+    }
+}
+
+/// atom の本体が返す値の種類（スカラー型の宣言があればそれ、決められなければ Int）。
+/// module_env に登録された atom の分類は ModuleEnv::result_kinds にメモし、各層で共有する
+pub fn result_kind(atom: &Atom, module_env: &ModuleEnv) -> ResultKind {
+    let registered = module_env.get_atom(&atom.name)
+        .is_some_and(|r| r.body_expr == atom.body_expr && r.return_type == atom.return_type);
+    let mut visiting = Visiting::default();
+    if registered {
+        return atom_kind(atom, module_env, &mut visiting).unwrap_or(ResultKind::Int);
+    }
+    // 未登録の atom（登録前の検査など）はメモせずに推論する（呼び出し先はメモを使う）
+    visiting.stack.push(atom.name.clone());
+    declared_or_body_kind(atom, module_env, &mut visiting).unwrap_or(ResultKind::Int)
+}
+
+/// 推論中の atom のスタック（相互再帰の打ち切り用）と、打ち切った atom のうちスタックで最も外側の位置
+#[derive(Default)]
+struct Visiting {
+    stack: Vec<String>,
+    outermost_cut: Option<usize>,
+}
+
+/// 登録済みの atom の値の種類（メモがあればそれ）。推論中の atom への再帰呼び出しは None
+fn atom_kind(atom: &Atom, module_env: &ModuleEnv, visiting: &mut Visiting) -> Option<ResultKind> {
+    if let Some(i) = visiting.stack.iter().position(|name| name == &atom.name) {
+        visiting.outermost_cut = Some(visiting.outermost_cut.map_or(i, |cut| cut.min(i)));
+        return None;
+    }
+    if let Some(kind) = module_env.result_kinds.get(&atom.name) {
+        return Some(kind);
+    }
+    let depth = visiting.stack.len();
+    let outer_cut = visiting.outermost_cut.take();
+    visiting.stack.push(atom.name.clone());
+    let kind = declared_or_body_kind(atom, module_env, visiting).unwrap_or(ResultKind::Int);
+    visiting.stack.pop();
+    // 打ち切りがこの atom より内側だけなら、推論は呼び出し元の状況によらないのでメモする
+    let cut = visiting.outermost_cut.take();
+    if cut.is_none_or(|cut| cut >= depth) {
+        module_env.result_kinds.insert(&atom.name, kind);
+    }
+    visiting.outermost_cut = match (outer_cut, cut.filter(|&cut| cut < depth)) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    };
+    Some(kind)
+}
+
+fn declared_or_body_kind(atom: &Atom, module_env: &ModuleEnv, visiting: &mut Visiting) -> Option<ResultKind> {
+    if let Some(declared) = atom.return_type.as_deref().filter(|t| module_env.get_struct(t).is_none()) {
+        return Some(type_kind(declared, module_env));
+    }
+    crate::parser::parse_expression(&atom.body_expr).ok()
+        .and_then(|body| expr_kind(&body, atom, module_env, &mut HashMap::new(), visiting))
+}
+
+/// `-> T` で構造体型を返すと宣言した atom の構造体定義（宣言がない・構造体でなければ None）
+pub fn struct_return<'m>(atom: &Atom, module_env: &'m ModuleEnv) -> Option<&'m StructDef> {
+    atom.return_type.as_deref().and_then(|t| module_env.get_struct(t))
+}
+
+/// atom の本体に現れる式の値の種類（transpiler が match などを値として包む関数の戻り値型に使う）。
+/// 式より前の let は見えないので、決められなければ Int
+pub fn expr_result_kind(expr: &Expr, atom: &Atom, module_env: &ModuleEnv) -> ResultKind {
+    let mut visiting = Visiting { stack: vec![atom.name.clone()], outermost_cut: None };
+    expr_kind(expr, atom, module_env, &mut HashMap::new(), &mut visiting).unwrap_or(ResultKind::Int)
+}
+
+/// 型名（精緻型はベース型に解決）の値の種類
+fn type_kind(type_name: &str, module_env: &ModuleEnv) -> ResultKind {
+    if let Some(inner) = option_inner_type(type_name) {
+        return type_kind(&inner, module_env);
+    }
+    match module_env.resolve_base_type(type_name).as_str() {
+        "f64" => ResultKind::Float,
+        "Str" => ResultKind::Str,
+        "bool" => ResultKind::Bool,
+        _ => ResultKind::Int,
+    }
+}
+
+/// 式の値の種類。自身への再帰呼び出しなど決められない式は None（if / match は他の分岐で決める）。
+/// locals は let で束縛した変数の種類
+fn expr_kind(
+    expr: &Expr,
+    atom: &Atom,
+    module_env: &ModuleEnv,
+    locals: &mut HashMap<String, ResultKind>,
+    visiting: &mut Visiting,
+) -> Option<ResultKind> {
+    match expr {
+        Expr::Number(_) | Expr::While { .. } | Expr::Assume { .. } => Some(ResultKind::Int),
+        Expr::Float(_) => Some(ResultKind::Float),
+        Expr::StringLit(_) => Some(ResultKind::Str),
+        Expr::Bool(_) => Some(ResultKind::Bool),
+        Expr::Variable(name) => locals.get(name).copied().or_else(|| {
+            atom.params.iter().find(|p| &p.name == name)
+                .map(|p| p.type_name.as_deref().map_or(ResultKind::Int, |t| type_kind(t, module_env)))
+        }),
+        Expr::ArrayAccess(name, _) => atom.params.iter().find(|p| &p.name == name)
+            .and_then(|p| p.type_name.as_deref())
+            .and_then(slice_elem_type)
+            .map(|elem| type_kind(&elem, module_env)),
+        Expr::BinaryOp(_, op, _) => match op {
+            Op::Add | Op::Sub | Op::Mul | Op::Div => {
+                // 左に連なる算術の連鎖は再帰せずに辿る。どれかのオペランドが f64 なら f64
+                let mut rights = Vec::new();
+                let mut leaf = expr;
+                while let Expr::BinaryOp(l, Op::Add | Op::Sub | Op::Mul | Op::Div, r) = leaf {
+                    rights.push(r.as_ref());
+                    leaf = l;
+                }
+                let mut is_float = expr_kind(leaf, atom, module_env, locals, visiting) == Some(ResultKind::Float);
+                for r in rights.into_iter().rev() {
+                    is_float |= expr_kind(r, atom, module_env, locals, visiting) == Some(ResultKind::Float);
+                }
+                if is_float { Some(ResultKind::Float) } else { Some(ResultKind::Int) }
+            }
+            _ => Some(ResultKind::Bool),
+        },
+        Expr::IfThenElse { then_branch, else_branch, .. } => {
+            let then_kind = expr_kind(then_branch, atom, module_env, &mut locals.clone(), visiting);
+            let else_kind = expr_kind(else_branch, atom, module_env, &mut locals.clone(), visiting);
+            if then_kind == Some(ResultKind::Float) || else_kind == Some(ResultKind::Float) {
+                Some(ResultKind::Float)
+            } else {
+                then_kind.or(else_kind)
+            }
+        }
+        Expr::Let { var, value } | Expr::Assign { var, value } => {
+            let kind = expr_kind(value, atom, module_env, locals, visiting);
+            if let Some(kind) = kind {
+                locals.insert(var.clone(), kind);
+            }
+            kind
+        }
+        Expr::Block(stmts) => {
+            let mut scope = locals.clone();
+            stmts.iter().map(|stmt| expr_kind(stmt, atom, module_env, &mut scope, visiting)).last().flatten()
+        }
+        Expr::Call(name, args) => match name.as_str() {
+            "approx_eq" | "is_some" | "is_none" => Some(ResultKind::Bool),
+            "sqrt" => Some(ResultKind::Float),
+            "len" => Some(ResultKind::Int),
+            _ => {
+                if let Some(callee) = module_env.get_atom(name).or_else(|| module_env.get_atom(&name.replace('.', "::"))) {
+                    atom_kind(callee, module_env, visiting)
+                } else if let Some(extension) = crate::theory::find_intrinsic(name) {
+                    extension.lower_call(name, args).ok().and_then(|lowered| expr_kind(&lowered, atom, module_env, locals, visiting))
+                } else {
+                    // 曖昧な呼び出しは検証がエラーにする。ここでは種類を決めない
+                    module_env.find_trait_method(name).ok().flatten()
+                        .filter(|(_, method)| method.return_type == "bool")
+                        .map(|_| ResultKind::Bool)
+                }
+            }
+        },
+        Expr::Match { arms, .. } => arms.iter().find_map(|arm| {
+            let mut scope = locals.clone();
+            bind_pattern_kinds(&arm.pattern, module_env, &mut scope);
+            expr_kind(&arm.body, atom, module_env, &mut scope, visiting)
+        }),
+        Expr::Acquire { body, .. } | Expr::Async { body } => expr_kind(body, atom, module_env, locals, visiting),
+        Expr::Await { expr } | Expr::RefArg { expr, .. } => expr_kind(expr, atom, module_env, locals, visiting),
+        Expr::StructInit { .. } | Expr::FieldAccess(..) => None,
+    }
+}
+
+/// Variant パターンのフィールドで束縛される変数に、Variant 定義のフィールド型の種類を割り当てる
+fn bind_pattern_kinds(pattern: &Pattern, module_env: &ModuleEnv, locals: &mut HashMap<String, ResultKind>) {
+    if let Pattern::Variant { variant_name, fields } = pattern {
+        let field_types = module_env.find_enum_by_variant(variant_name)
+            .and_then(|e| e.variants.iter().find(|v| &v.name == variant_name))
+            .map(|v| v.fields.clone())
+            .unwrap_or_default();
+        for (field, type_name) in fields.iter().zip(&field_types) {
+            match field {
+                Pattern::Variable(v) => { locals.insert(v.clone(), type_kind(type_name, module_env)); }
+                _ => bind_pattern_kinds(field, module_env, locals),
+            }
+        }
+    }
+}
+
+/// 式の中の Str の誤用を探し、最初の誤用の説明を返す。検証は Str の値を長さでしか扱わないので、
+/// 算術・大小比較・論理演算のオペランドの Str、Str と他の種類の等値比較、
+/// Str とそれ以外が混ざる if の分岐・match のアームを誤用とする。
+/// let の束縛はソース順に一つの表へ積む（ブロックのスコープは区別しない）
+pub fn str_misuse(expr: &Expr, atom: &Atom, module_env: &ModuleEnv) -> Option<String> {
+    let mut locals = HashMap::from([("result".to_string(), result_kind(atom, module_env))]);
+    let mut visiting = Visiting { stack: vec![atom.name.clone()], outermost_cut: None };
+    let mut misuse = None;
+    expr.walk(|e| {
+        if misuse.is_some() {
+            return false;
+        }
+        let mut kind_of = |e: &Expr, locals: &HashMap<String, ResultKind>| operand_kind(e, atom, module_env, locals, &mut visiting);
+        misuse = match e {
+            Expr::BinaryOp(l, op, r) => {
+                let (left, right) = (kind_of(l, &locals), kind_of(r, &locals));
+                let has_str = left == Some(ResultKind::Str) || right == Some(ResultKind::Str);
+                match op {
+                    Op::Add | Op::Sub | Op::Mul | Op::Div if has_str => Some("a string cannot be used in arithmetic".to_string()),
+                    Op::Gt | Op::Lt | Op::Ge | Op::Le if has_str => Some("strings cannot be ordered (compare len(s) instead)".to_string()),
+                    Op::And | Op::Or | Op::Implies if has_str => Some("a string cannot be used as a boolean".to_string()),
+                    Op::Eq | Op::Neq => match (left, right) {
+                        (Some(ResultKind::Str), Some(other)) | (Some(other), Some(ResultKind::Str)) if other != ResultKind::Str =>
+                            Some(format!("a string cannot be compared with {}", other.describe())),
+                        _ => None,
+                    },
+                    _ => None,
+                }
+            }
+            Expr::IfThenElse { then_branch, else_branch, .. } => {
+                let kinds = [kind_of(then_branch, &locals), kind_of(else_branch, &locals)];
+                mixed_with_str(&kinds).map(|other| format!("the branches of an if mix a string with {}", other.describe()))
+            }
+            Expr::Match { arms, .. } => {
+                let kinds: Vec<_> = arms.iter().map(|arm| {
+                    let mut scope = locals.clone();
+                    bind_pattern_kinds(&arm.pattern, module_env, &mut scope);
+                    kind_of(&arm.body, &scope)
+                }).collect();
+                for arm in arms {
+                    bind_pattern_kinds(&arm.pattern, module_env, &mut locals);
+                }
+                mixed_with_str(&kinds).map(|other| format!("the arms of a match mix a string with {}", other.describe()))
+            }
+            Expr::Let { var, value } | Expr::Assign { var, value } => {
+                if let Some(kind) = kind_of(value, &locals) {
+                    locals.insert(var.clone(), kind);
+                }
+                None
+            }
+            _ => None,
+        };
+        misuse.is_none()
+    });
+    misuse
+}
+
+/// str_misuse で見るオペランドの種類。演算の値は Str にならないので、演算の連鎖は辿り直さない
+fn operand_kind(
+    expr: &Expr,
+    atom: &Atom,
+    module_env: &ModuleEnv,
+    locals: &HashMap<String, ResultKind>,
+    visiting: &mut Visiting,
+) -> Option<ResultKind> {
+    match expr {
+        Expr::BinaryOp(_, Op::Add | Op::Sub | Op::Mul | Op::Div, _) => Some(ResultKind::Int),
+        Expr::BinaryOp(..) => Some(ResultKind::Bool),
+        _ => expr_kind(expr, atom, module_env, &mut locals.clone(), visiting),
+    }
+}
+
+/// 分岐の種類に Str とそれ以外が混ざっていれば、Str 以外の種類
+fn mixed_with_str(kinds: &[Option<ResultKind>]) -> Option<ResultKind> {
+    let other = kinds.iter().flatten().find(|kind| **kind != ResultKind::Str).copied();
+    other.filter(|_| kinds.contains(&Some(ResultKind::Str)))
+}
+
 impl std::fmt::Display for TypeRef {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", self.display_name())
@@ -83,11 +407,20 @@ impl std::fmt::Display for TypeRef {
 // - コンパイル時に Stack<i64>, Stack<f64> など使用されている型ごとにコードを複製
 // - 実行時の型消去やオーバーヘッドがない
 
-use std::collections::{HashMap, HashSet};
+use std::collections::{HashMap, HashSet, VecDeque};
 use crate::parser::{
     Item, Atom, Param, StructDef, StructField, EnumDef, EnumVariant,
-    Expr, parse_type_ref,
+    Expr, Op, Pattern, parse_type_ref,
 };
+use crate::verification::{ModuleEnv, MumeiError, MumeiResult};
+
+/// 推移的な単相化で辿るインスタンス化チェーンの長さの上限（デフォルト）。
+/// `mumei.toml` の `[build] max_mono_depth` で変更できる。
+pub const DEFAULT_MAX_MONO_DEPTH: usize = 16;
+
+/// 単相化で生成するインスタンス総数の上限。
+/// 深さの上限内でも型引数の組み合わせが爆発する場合の打ち切りに使う。
+const MAX_MONO_INSTANCES: usize = 4096;
 
 /// 単相化コンテキスト: ジェネリック定義と使用インスタンスを管理する
 #[derive(Debug, Default)]
@@ -100,15 +433,29 @@ pub struct Monomorphizer {
     generic_atoms: HashMap<String, Atom>,
     /// 使用されている具体的な型インスタンス（例: "Stack<i64>"）
     instances: HashSet<String>,
+    /// インスタンス化チェーンの長さの上限（0 なら DEFAULT_MAX_MONO_DEPTH）
+    max_depth: usize,
+}
+
+/// 型パラメータを（型引数の入れ子の中にも）含まない具体型か
+fn is_ground_type(type_ref: &TypeRef) -> bool {
+    !type_ref.is_type_param() && type_ref.type_args.iter().all(is_ground_type)
 }
 
 impl Monomorphizer {
     pub fn new() -> Self {
-        Self::default()
+        Self::with_max_depth(DEFAULT_MAX_MONO_DEPTH)
     }
 
-    /// Phase 1: Items からジェネリック定義を登録し、使用箇所を収集する
-    pub fn collect(&mut self, items: &[Item]) {
+    /// インスタンス化チェーンの長さの上限を指定して生成する
+    pub fn with_max_depth(max_depth: usize) -> Self {
+        Self { max_depth, ..Self::default() }
+    }
+
+    /// Phase 1: Items からジェネリック定義を登録し、使用箇所を収集する。
+    /// 収集したインスタンスのフィールド型・パラメータ型・body から到達する
+    /// インスタンスも推移的に収集し、上限を超えたらチェーンを示してエラーにする。
+    pub fn collect(&mut self, items: &[Item]) -> MumeiResult<()> {
         // ジェネリック定義を登録
         for item in items {
             match item {
@@ -136,8 +483,9 @@ impl Monomorphizer {
                         }
                     }
                     // body 内の式から収集
-                    let body_expr = crate::parser::parse_expression(&atom.body_expr);
-                    self.collect_from_expr(&body_expr);
+                    if let Ok(body_expr) = crate::parser::parse_expression(&atom.body_expr) {
+                        self.collect_from_expr(&body_expr);
+                    }
                 }
                 Item::StructDef(sdef) => {
                     for field in &sdef.fields {
@@ -154,94 +502,126 @@ impl Monomorphizer {
                 _ => {}
             }
         }
+
+        self.expand_transitive()
     }
 
     /// TypeRef から具体的なジェネリック型インスタンスを収集する
     fn collect_from_type_ref(&mut self, type_ref: &TypeRef) {
+        let mut found = Vec::new();
+        self.concrete_instances(type_ref, &mut found);
+        for tref in found {
+            self.instances.insert(tref.display_name());
+        }
+    }
+
+    /// TypeRef に含まれる具体的なジェネリック型インスタンスを（型引数も含めて）列挙する
+    fn concrete_instances(&self, type_ref: &TypeRef, out: &mut Vec<TypeRef>) {
         if !type_ref.type_args.is_empty() {
-            // 型引数がすべて具体型（型パラメータでない）場合のみインスタンスとして登録
-            let all_concrete = type_ref.type_args.iter().all(|a| !a.is_type_param());
+            // 型引数がすべて具体型（入れ子の中にも型パラメータを含まない）場合のみインスタンスとして登録
+            let all_concrete = type_ref.type_args.iter().all(is_ground_type);
             if all_concrete && (self.generic_structs.contains_key(&type_ref.name)
                 || self.generic_enums.contains_key(&type_ref.name)
                 || self.generic_atoms.contains_key(&type_ref.name))
             {
-                self.instances.insert(type_ref.display_name());
+                out.push(type_ref.clone());
             }
             // 再帰的に型引数も収集
             for arg in &type_ref.type_args {
-                self.collect_from_type_ref(arg);
+                self.concrete_instances(arg, out);
             }
         }
     }
 
     /// 式から StructInit の type_name を走査してジェネリック使用箇所を収集する
     fn collect_from_expr(&mut self, expr: &Expr) {
-        match expr {
-            Expr::StructInit { type_name, fields } => {
-                let tref = parse_type_ref(type_name);
-                self.collect_from_type_ref(&tref);
-                for (_, field_expr) in fields {
-                    self.collect_from_expr(field_expr);
+        let mut trefs = Vec::new();
+        type_refs_in_expr(expr, &mut trefs);
+        for tref in &trefs {
+            self.collect_from_type_ref(tref);
+        }
+    }
+
+    /// 収集済みインスタンスを起点に、インスタンス化で新たに必要になるインスタンスを
+    /// 幅優先で推移的に収集する。
+    ///
+    /// 各インスタンスは起点（root）からのチェーンを持ち、チェーン長が max_depth を、
+    /// またはインスタンス総数が MAX_MONO_INSTANCES を超えたら
+    /// `Wrap<i64> → Wrap<Wrap<i64>> → …` の形でチェーンを示してエラーにする。
+    /// 構造的に同一のインスタンス（display_name が同じもの）は経路によらず 1 つにまとめる。
+    fn expand_transitive(&mut self) -> MumeiResult<()> {
+        let max_depth = if self.max_depth == 0 { DEFAULT_MAX_MONO_DEPTH } else { self.max_depth };
+        let mut roots: Vec<String> = self.instances.iter().cloned().collect();
+        roots.sort();
+        let mut queue: VecDeque<Vec<String>> = roots.into_iter().map(|r| vec![r]).collect();
+
+        while let Some(chain) = queue.pop_front() {
+            let current = parse_type_ref(&chain[chain.len() - 1]);
+            for child in self.instance_dependencies(&current) {
+                let child_name = child.display_name();
+                if self.instances.contains(&child_name) {
+                    continue;
                 }
-            }
-            Expr::Call(name, args) => {
-                let tref = parse_type_ref(name);
-                self.collect_from_type_ref(&tref);
-                for arg in args {
-                    self.collect_from_expr(arg);
+                let mut child_chain = chain.clone();
+                child_chain.push(child_name.clone());
+                if child_chain.len() > max_depth {
+                    return Err(MumeiError::TypeError(format!(
+                        "Monomorphization depth limit ({}) exceeded: {} → … \
+                         A generic definition keeps instantiating itself at a larger type; \
+                         raise [build] max_mono_depth in mumei.toml if the nesting is intended.",
+                        max_depth, child_chain.join(" → ")
+                    )));
                 }
-            }
-            Expr::BinaryOp(l, _, r) => {
-                self.collect_from_expr(l);
-                self.collect_from_expr(r);
-            }
-            Expr::IfThenElse { cond, then_branch, else_branch } => {
-                self.collect_from_expr(cond);
-                self.collect_from_expr(then_branch);
-                self.collect_from_expr(else_branch);
-            }
-            Expr::Block(stmts) => {
-                for s in stmts {
-                    self.collect_from_expr(s);
+                if self.instances.len() >= MAX_MONO_INSTANCES {
+                    return Err(MumeiError::TypeError(format!(
+                        "Monomorphization instance limit ({}) exceeded while instantiating: {}",
+                        MAX_MONO_INSTANCES, child_chain.join(" → ")
+                    )));
                 }
+                self.instances.insert(child_name);
+                queue.push_back(child_chain);
             }
-            Expr::Let { value, .. } | Expr::Assign { value, .. } => {
-                self.collect_from_expr(value);
-            }
-            Expr::While { cond, invariant, decreases, body } => {
-                self.collect_from_expr(cond);
-                self.collect_from_expr(invariant);
-                if let Some(dec) = decreases {
-                    self.collect_from_expr(dec);
+        }
+        Ok(())
+    }
+
+    /// インスタンスを展開したときに必要になる具体的なインスタンスを列挙する
+    /// （Struct のフィールド型、Enum の Variant フィールド型、Atom のパラメータ型と body）
+    fn instance_dependencies(&self, instance: &TypeRef) -> Vec<TypeRef> {
+        let mut deps = Vec::new();
+        if let Some(generic) = self.generic_structs.get(&instance.name) {
+            if let Some(type_map) = self.build_type_map(&generic.type_params, &instance.type_args) {
+                for field in &generic.fields {
+                    self.concrete_instances(&field.type_ref.substitute(&type_map), &mut deps);
                 }
-                self.collect_from_expr(body);
             }
-            Expr::Match { target, arms } => {
-                self.collect_from_expr(target);
-                for arm in arms {
-                    self.collect_from_expr(&arm.body);
-                    if let Some(guard) = &arm.guard {
-                        self.collect_from_expr(guard);
+        }
+        if let Some(generic) = self.generic_enums.get(&instance.name) {
+            if let Some(type_map) = self.build_type_map(&generic.type_params, &instance.type_args) {
+                for variant in &generic.variants {
+                    for ft in &variant.field_types {
+                        self.concrete_instances(&ft.substitute(&type_map), &mut deps);
                     }
                 }
             }
-            Expr::FieldAccess(expr, _) => {
-                self.collect_from_expr(expr);
-            }
-            Expr::ArrayAccess(_, idx) => {
-                self.collect_from_expr(idx);
-            }
-            Expr::Acquire { body, .. } => {
-                self.collect_from_expr(body);
-            }
-            Expr::Async { body } => {
-                self.collect_from_expr(body);
-            }
-            Expr::Await { expr } => {
-                self.collect_from_expr(expr);
+        }
+        if let Some(generic) = self.generic_atoms.get(&instance.name) {
+            if let Some(type_map) = self.build_type_map(&generic.type_params, &instance.type_args) {
+                for param in &generic.params {
+                    if let Some(tref) = &param.type_ref {
+                        self.concrete_instances(&tref.substitute(&type_map), &mut deps);
+                    }
+                }
+                let mut body_trefs = Vec::new();
+                if let Ok(body_expr) = crate::parser::parse_expression(&generic.body_expr) {
+                    type_refs_in_expr(&body_expr, &mut body_trefs);
+                }
+                for tref in &body_trefs {
+                    self.concrete_instances(&tref.substitute(&type_map), &mut deps);
+                }
             }
-            Expr::Number(_) | Expr::Float(_) | Expr::Variable(_) => {}
         }
+        deps
     }
 
     /// Phase 2: 収集したインスタンスを単相化し、具体的な Item のリストを返す。
@@ -316,6 +696,7 @@ impl Monomorphizer {
             type_params: vec![], // 単相化後は型パラメータなし
             fields,
             method_names: vec![],
+            invariant: generic.invariant.clone(),
         })
     }
 
@@ -375,16 +756,23 @@ impl Monomorphizer {
             type_params: vec![],
             where_bounds: vec![], // 単相化後は境界なし
             params,
+            return_type: generic.return_type.as_deref().map(|t| parse_type_ref(t).substitute(&type_map).display_name()),
             requires: generic.requires.clone(),
+            requires_raw: generic.requires_raw.clone(),
+            where_clause: generic.where_clause.clone(),
             forall_constraints: generic.forall_constraints.clone(),
             ensures: generic.ensures.clone(),
+            ensures_raw: generic.ensures_raw.clone(),
             body_expr: generic.body_expr.clone(),
             consumed_params: generic.consumed_params.clone(),
             resources: generic.resources.clone(),
             is_async: generic.is_async,
+            is_total: generic.is_total,
             trust_level: generic.trust_level.clone(),
             max_unroll: generic.max_unroll,
             invariant: generic.invariant.clone(),
+            decreases: generic.decreases.clone(),
+            targets: generic.targets.clone(),
         })
     }
 
@@ -413,3 +801,162 @@ impl Monomorphizer {
         &self.instances
     }
 }
+
+/// 式中の StructInit の型名・Call の呼び出し名を TypeRef として列挙する
+fn type_refs_in_expr(expr: &Expr, out: &mut Vec<TypeRef>) {
+    expr.walk(|e| {
+        match e {
+            Expr::StructInit { type_name, .. } => out.push(parse_type_ref(type_name)),
+            Expr::Call(name, _) => out.push(parse_type_ref(name)),
+            _ => {}
+        }
+        true
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_module;
+
+    fn mono_struct_names(items: &[Item]) -> Vec<String> {
+        let mut names: Vec<String> = items.iter().filter_map(|i| match i {
+            Item::StructDef(s) => Some(s.name.clone()),
+            _ => None,
+        }).collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn test_transitive_instantiation_deep_but_finite() {
+        // L3<i64> → L2<i64> → L1<i64> → L0<i64>。Pair<i64> からは L0<i64> と L1<i64> の
+        // 両方の経路で L0<i64> に到達するが、インスタンスは 1 つにまとめられる。
+        let source = r#"
+struct L0<T> { x: T }
+struct L1<T> { x: L0<T> }
+struct L2<T> { x: L1<T> }
+struct L3<T> { x: L2<T> }
+struct Pair<T> { a: L0<T>, b: L1<T> }
+
+atom use_deep(p: L3<i64>, q: Pair<i64>)
+requires: true;
+ensures: true;
+body: 0;
+"#;
+        let items = parse_module(source).unwrap();
+        let mut mono = Monomorphizer::with_max_depth(4);
+        mono.collect(&items).expect("chain of 4 fits the limit");
+        let mono_items = mono.monomorphize(&items);
+        assert_eq!(
+            mono_struct_names(&mono_items),
+            vec!["L0<i64>", "L1<i64>", "L2<i64>", "L3<i64>", "Pair<i64>"]
+        );
+
+        // Pair<i64> 経由の近道がなければ、上限 3 ではチェーン L3 → L2 → L1 → L0 は長すぎる
+        let items = parse_module(&source.replace(", q: Pair<i64>", "")).unwrap();
+        let mut strict = Monomorphizer::with_max_depth(3);
+        let err = strict.collect(&items).expect_err("chain of 4 exceeds 3");
+        assert!(err.to_string().contains("L3<i64> → L2<i64> → L1<i64> → L0<i64> → …"), "{}", err);
+    }
+
+    #[test]
+    fn test_transitive_instantiation_runaway() {
+        let source = r#"
+enum Wrap<T> { Leaf(T), Deep(Wrap<Wrap<T>>) }
+
+atom unwrap_all(w: Wrap<i64>)
+requires: true;
+ensures: true;
+body: 0;
+"#;
+        let items = parse_module(source).unwrap();
+        let mut mono = Monomorphizer::new();
+        let err = mono.collect(&items).expect_err("Wrap<T> grows without bound");
+        let msg = err.to_string();
+        assert!(msg.contains(&format!("depth limit ({})", DEFAULT_MAX_MONO_DEPTH)), "{}", msg);
+        assert!(msg.starts_with("Type Error: Monomorphization depth limit"), "{}", msg);
+        assert!(msg.contains("Wrap<i64> → Wrap<Wrap<i64>> → Wrap<Wrap<Wrap<i64>>> → "), "{}", msg);
+    }
+
+    #[test]
+    fn test_result_kind_is_memoized_per_atom() {
+        // f0 は f1 を 2 回、f1 は f2 を 2 回 … 呼ぶ。メモがなければ推論は 2^40 回の本体の走査になる
+        let mut source = String::new();
+        for i in 0..40 {
+            source.push_str(&format!("atom f{}(x: f64) requires: true; ensures: true; body: f{}(x) + f{}(x);\n", i, i + 1, i + 1));
+        }
+        source.push_str("atom f40(x: f64) requires: true; ensures: true; body: x;\n");
+        // 相互再帰は打ち切った分岐以外から決める
+        source.push_str("atom even(n: i64) requires: true; ensures: true; body: if n == 0 { true } else { odd(n - 1) };\n");
+        source.push_str("atom odd(n: i64) requires: true; ensures: true; body: if n == 0 { false } else { even(n - 1) };\n");
+        let items = parse_module(&source).unwrap();
+        let mut module_env = ModuleEnv::new();
+        for item in &items {
+            if let Item::Atom(atom) = item {
+                module_env.register_atom(atom);
+            }
+        }
+        let atom = |module_env: &ModuleEnv, name: &str| module_env.get_atom(name).unwrap().clone();
+
+        assert_eq!(result_kind(&atom(&module_env, "f0"), &module_env), ResultKind::Float);
+        assert_eq!(module_env.result_kinds.get("f40"), Some(ResultKind::Float));
+        assert_eq!(result_kind(&atom(&module_env, "even"), &module_env), ResultKind::Bool);
+        assert_eq!(result_kind(&atom(&module_env, "odd"), &module_env), ResultKind::Bool);
+
+        // 登録し直すとメモは捨てられ、新しい本体から推論し直す
+        let mut changed = atom(&module_env, "f40");
+        changed.body_expr = "x > 0.0".into();
+        module_env.register_atom(&changed);
+        assert_eq!(module_env.result_kinds.get("f40"), None);
+        assert_eq!(result_kind(&atom(&module_env, "f39"), &module_env), ResultKind::Int);
+        assert_eq!(result_kind(&changed, &module_env), ResultKind::Bool);
+    }
+
+    #[test]
+    fn test_str_misuse_checks_operands_and_every_branch() {
+        let source = r#"
+enum Label { Named(Str), Anonymous }
+atom add_one(s: Str) requires: true; ensures: true; body: s + 1;
+atom compare(s: Str) requires: true; ensures: true; body: { let t = s; t == 3 };
+atom ordered(s: Str) requires: true; ensures: true; body: s < "b";
+atom else_int(s: Str, n: i64) requires: true; ensures: true; body: if n > 0 { s } else { n };
+atom last_arm(l: Label) requires: true; ensures: true; body: match l { Named(name) => name, Anonymous => 0 };
+atom lengths(s: Str) requires: true; ensures: true; body: if len(s) > 0 { s } else { "empty" };
+atom same(s: Str) requires: true; ensures: true; body: s == "a";
+"#;
+        let items = parse_module(source).unwrap();
+        let mut module_env = ModuleEnv::new();
+        for item in &items {
+            match item {
+                Item::Atom(atom) => module_env.register_atom(atom),
+                Item::EnumDef(enum_def) => module_env.register_enum(enum_def),
+                _ => {}
+            }
+        }
+        let misuse = |name: &str| {
+            let atom = module_env.get_atom(name).unwrap();
+            str_misuse(&crate::parser::parse_expression(&atom.body_expr).unwrap(), atom, &module_env)
+        };
+
+        assert_eq!(misuse("add_one").as_deref(), Some("a string cannot be used in arithmetic"));
+        assert_eq!(misuse("compare").as_deref(), Some("a string cannot be compared with a number"));
+        assert!(misuse("ordered").is_some_and(|m| m.contains("cannot be ordered")));
+        // then 側だけでなく else 側・後のアームも見る
+        assert_eq!(misuse("else_int").as_deref(), Some("the branches of an if mix a string with a number"));
+        assert_eq!(misuse("last_arm").as_deref(), Some("the arms of a match mix a string with a number"));
+        assert_eq!(misuse("lengths"), None);
+        assert_eq!(misuse("same"), None);
+    }
+
+    #[test]
+    fn test_type_refs_in_deep_left_chain() {
+        // 10 万項の `f(0) + ... + f(99999)` を再帰せずに辿り、呼び出しを左から順に集める。
+        let call = |i: i64| Expr::Call("f".into(), vec![Expr::Number(i)]);
+        let expr = (1..100_000).fold(call(0), |acc, i| Expr::BinaryOp(Box::new(acc), Op::Add, Box::new(call(i))));
+        let mut out = Vec::new();
+        type_refs_in_expr(&expr, &mut out);
+        assert_eq!(out.len(), 100_000);
+        assert!(out.iter().all(|tref| tref.display_name() == "f"));
+    }
+}
@@ -0,0 +1,468 @@
+// =============================================================================
+// mumei bench: 検証済み atom のベンチマークハーネス生成
+// =============================================================================
+//
+// 正しさ（検証）に加えて、生成コードの性能を言語間で比較するための開発者向け機能。
+// 指定された atom ごとに:
+//
+// 1. selftest と同じ契約ベースのサンプラーで requires を満たす入力を生成する
+// 2. バックエンドごとにそのまま実行できるハーネスを出力する
+//    - rust/: Criterion のベンチ（Cargo プロジェクト）
+//    - go/:   testing.B のベンチマーク
+//    - node/: process.hrtime による計測スクリプト（TypeScript を型除去で実行）
+// 3. `mumei inspect` と同じ方法で検出したツールチェーンでハーネスを実行し、
+//    出力を解析して atom × バックエンドの比較表を作る
+//
+// ハーネスの 1 反復は「サンプリングした全入力で atom を 1 回ずつ呼ぶ」こと。
+// 表には 1 呼び出しあたりの時間（1 反復の時間 / 入力数）を出す。
+
+use crate::interpreter::Value;
+use crate::parser::{Atom, Item};
+use crate::selftest::{self, Rng};
+use crate::transpiler::{transpile, transpile_module_header, TargetLanguage};
+use crate::verification::ModuleEnv;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Go パッケージ名 / Rust クレート名
+const PACKAGE: &str = "mumeibench";
+
+/// ベンチマークを生成するバックエンド
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    Rust,
+    Go,
+    Node,
+}
+
+impl Backend {
+    pub const ALL: [Backend; 3] = [Backend::Rust, Backend::Go, Backend::Node];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Backend::Rust => "rust",
+            Backend::Go => "go",
+            Backend::Node => "node",
+        }
+    }
+
+    fn lang(&self) -> TargetLanguage {
+        match self {
+            Backend::Rust => TargetLanguage::Rust,
+            Backend::Go => TargetLanguage::Go,
+            Backend::Node => TargetLanguage::TypeScript,
+        }
+    }
+
+    /// ハーネスの実行に使うコマンド（`mumei inspect` と同じ検出方法）
+    fn toolchain(&self) -> (&'static str, &'static str) {
+        match self {
+            Backend::Rust => ("cargo", "--version"),
+            Backend::Go => ("go", "version"),
+            Backend::Node => ("node", "--version"),
+        }
+    }
+
+    /// ツールチェーンが利用可能か
+    pub fn available(&self) -> bool {
+        let (cmd, arg) = self.toolchain();
+        Command::new(cmd).arg(arg).output().map(|o| o.status.success()).unwrap_or(false)
+    }
+}
+
+/// ベンチマーク対象の atom 1 件分
+#[derive(Debug, Clone)]
+pub struct BenchAtom {
+    pub name: String,
+    pub params: Vec<String>,
+    /// requires を満たす入力（1 反復でこれらを順に呼ぶ）
+    pub inputs: Vec<Vec<Value>>,
+    /// このハーネスに含めるバックエンド（生成コードが未対応構文のスタブになるものは除く）
+    pub backends: Vec<Backend>,
+    /// 除外したバックエンドとその理由
+    pub notes: Vec<(Backend, String)>,
+}
+
+impl BenchAtom {
+    fn supports(&self, backend: Backend) -> bool {
+        self.backends.contains(&backend)
+    }
+}
+
+/// atom の入力をサンプリングし、ベンチマーク対象にできるバックエンドを決める。
+/// requires からサンプリングできない atom はスキップ理由を返す。
+pub fn plan_atom(atom: &Atom, module_env: &ModuleEnv, samples: usize, rng: &mut Rng) -> Result<BenchAtom, String> {
+    if atom.is_async {
+        return Err("async atom".to_string());
+    }
+    let (params, inputs) = selftest::sample_inputs(atom, module_env, samples, rng)?;
+    let mut plan = BenchAtom { name: atom.name.clone(), params, inputs, backends: Vec::new(), notes: Vec::new() };
+    for backend in Backend::ALL {
+        match transpile(atom, backend.lang(), module_env) {
+            Ok(_) => plan.backends.push(backend),
+            Err(unsupported) => plan.notes.push((backend, format!("{} not supported", unsupported.construct))),
+        }
+    }
+    Ok(plan)
+}
+
+fn call(atom: &BenchAtom, input: &[Value]) -> String {
+    let args: Vec<String> = input.iter().map(|v| v.to_string()).collect();
+    format!("{}({})", atom.name, args.join(", "))
+}
+
+// =============================================================================
+// ハーネスのテンプレート
+// =============================================================================
+
+/// rust/Cargo.toml（外側のワークスペースに取り込まれないよう空の [workspace] を置く）
+pub fn criterion_manifest() -> String {
+    format!(
+        "[package]\nname = \"{}\"\nversion = \"0.0.0\"\nedition = \"2021\"\npublish = false\n\n[workspace]\n\n[dev-dependencies]\ncriterion = \"0.5\"\n\n[[bench]]\nname = \"mumei\"\nharness = false\n",
+        PACKAGE
+    )
+}
+
+/// rust/benches/mumei.rs: atom ごとの Criterion ベンチ
+pub fn criterion_bench(atoms: &[BenchAtom]) -> String {
+    let mut out = format!(
+        "// Generated by `mumei bench`. Run with `cargo bench --bench mumei`.\nuse criterion::{{criterion_group, criterion_main, Criterion}};\nuse std::hint::black_box;\nuse {}::*;\n\nfn bench_atoms(c: &mut Criterion) {{\n",
+        PACKAGE
+    );
+    for atom in atoms.iter().filter(|a| a.supports(Backend::Rust)) {
+        out.push_str(&format!("    // {}: {} input(s) per iteration\n", atom.name, atom.inputs.len()));
+        out.push_str(&format!("    c.bench_function(\"{}\", |b| b.iter(|| {{\n", atom.name));
+        for input in &atom.inputs {
+            let args: Vec<String> = input.iter().map(|v| format!("black_box({})", v)).collect();
+            out.push_str(&format!("        black_box({}({}));\n", atom.name, args.join(", ")));
+        }
+        out.push_str("    }));\n");
+    }
+    out.push_str("}\n\ncriterion_group!(benches, bench_atoms);\ncriterion_main!(benches);\n");
+    out
+}
+
+/// go/go.mod
+pub fn go_mod() -> String {
+    format!("module {}\n\ngo 1.18\n", PACKAGE)
+}
+
+/// go/mumei_bench_test.go: atom ごとの testing.B ベンチマーク。
+/// 結果は noinline の keep に渡し、呼び出しが最適化で消えないようにする
+pub fn go_bench(atoms: &[BenchAtom]) -> String {
+    let mut out = format!(
+        "// Generated by `mumei bench`. Run with `go test -run '^$' -bench .`.\npackage {}\n\nimport \"testing\"\n\n//go:noinline\nfunc keep[T any](v T) {{}}\n",
+        PACKAGE
+    );
+    for atom in atoms.iter().filter(|a| a.supports(Backend::Go)) {
+        out.push_str(&format!("\n// {}: {} input(s) per iteration\nfunc Benchmark_{}(b *testing.B) {{\n\tfor i := 0; i < b.N; i++ {{\n", atom.name, atom.inputs.len(), atom.name));
+        for input in &atom.inputs {
+            out.push_str(&format!("\t\tkeep({})\n", call(atom, input)));
+        }
+        out.push_str("\t}\n}\n");
+    }
+    out
+}
+
+/// node/bench.ts の計測部分（トランスパイル結果の後ろに付ける）。
+/// 200ms 以上かかるまで反復回数を倍にしていき、"BENCH <atom> <ns/反復>" を出力する
+pub fn node_harness(atoms: &[BenchAtom]) -> String {
+    let mut out = String::from(
+        "// Generated by `mumei bench`. Run with `node --experimental-strip-types bench.ts`.\nlet sink: unknown;\n\nfunction bench(name: string, body: () => void): void {\n    for (let i = 0; i < 1000; i++) body();\n    for (let iterations = 1; ; iterations *= 2) {\n        const start = process.hrtime.bigint();\n        for (let i = 0; i < iterations; i++) body();\n        const elapsed = Number(process.hrtime.bigint() - start);\n        if (elapsed >= 200_000_000 || iterations >= 1 << 30) {\n            console.log(`BENCH ${name} ${elapsed / iterations}`);\n            return;\n        }\n    }\n}\n",
+    );
+    for atom in atoms.iter().filter(|a| a.supports(Backend::Node)) {
+        out.push_str(&format!("\n// {}: {} input(s) per iteration\nbench(\"{}\", () => {{\n", atom.name, atom.inputs.len(), atom.name));
+        for input in &atom.inputs {
+            out.push_str(&format!("    sink = {};\n", call(atom, input)));
+        }
+        out.push_str("});\n");
+    }
+    out
+}
+
+/// 全バックエンドのハーネスを dir 以下に書き出し、書き出したバックエンドとそのディレクトリを返す。
+/// どの atom も対象にしないバックエンドは書き出さない。
+pub fn write_harnesses(items: &[Item], module_env: &ModuleEnv, atoms: &[BenchAtom], dir: &Path) -> std::io::Result<Vec<(Backend, PathBuf)>> {
+    let has_async = items.iter().any(|item| matches!(item, Item::Atom(atom) if atom.is_async));
+    let mut written = Vec::new();
+    for backend in Backend::ALL {
+        if !atoms.iter().any(|a| a.supports(backend)) {
+            continue;
+        }
+        let backend_dir = dir.join(backend.label());
+        let bundle = selftest::bundle(items, module_env, backend.lang());
+        match backend {
+            Backend::Rust => {
+                fs::create_dir_all(backend_dir.join("src"))?;
+                fs::create_dir_all(backend_dir.join("benches"))?;
+                fs::write(backend_dir.join("Cargo.toml"), criterion_manifest())?;
+                fs::write(backend_dir.join("src").join("lib.rs"), format!("#![allow(unused)]\n{}", bundle))?;
+                fs::write(backend_dir.join("benches").join("mumei.rs"), criterion_bench(atoms))?;
+            }
+            Backend::Go => {
+                fs::create_dir_all(&backend_dir)?;
+                fs::write(backend_dir.join("go.mod"), go_mod())?;
+                let header = transpile_module_header(&[], PACKAGE, TargetLanguage::Go, has_async);
+                fs::write(backend_dir.join("mumei.go"), format!("{}\n{}", header, bundle))?;
+                fs::write(backend_dir.join("mumei_bench_test.go"), go_bench(atoms))?;
+            }
+            Backend::Node => {
+                fs::create_dir_all(&backend_dir)?;
+                fs::write(backend_dir.join("bench.ts"), format!("{}\n{}", bundle, node_harness(atoms)))?;
+            }
+        }
+        written.push((backend, backend_dir));
+    }
+    Ok(written)
+}
+
+// =============================================================================
+// 実行と結果の解析
+// =============================================================================
+
+/// ハーネスを実行し、atom 名 → 1 反復あたりのナノ秒を返す
+pub fn run_backend(backend: Backend, dir: &Path) -> Result<HashMap<String, f64>, String> {
+    let mut command = match backend {
+        Backend::Rust => {
+            let mut c = Command::new("cargo");
+            c.args(["bench", "--quiet", "--bench", "mumei", "--", "--noplot"]);
+            c
+        }
+        Backend::Go => {
+            let mut c = Command::new("go");
+            c.args(["test", "-run", "^$", "-bench", "."]);
+            c
+        }
+        Backend::Node => {
+            let mut c = Command::new("node");
+            c.args(["--experimental-strip-types", "bench.ts"]);
+            c
+        }
+    };
+    let output = command.current_dir(dir).output().map_err(|e| format!("failed to run {}: {}", backend.toolchain().0, e))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let head: Vec<&str> = stderr.lines().take(20).collect();
+        return Err(format!("{} harness failed:\n{}", backend.label(), head.join("\n")));
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    Ok(match backend {
+        Backend::Rust => parse_criterion(&stdout),
+        Backend::Go => parse_go_bench(&stdout),
+        Backend::Node => parse_node(&stdout),
+    })
+}
+
+/// "1.2345 ns" のような時間表記をナノ秒に変換する
+fn to_nanos(value: &str, unit: &str) -> Option<f64> {
+    let scale = match unit {
+        "ps" => 0.001,
+        "ns" => 1.0,
+        "us" | "µs" => 1_000.0,
+        "ms" => 1_000_000.0,
+        "s" => 1_000_000_000.0,
+        _ => return None,
+    };
+    value.parse::<f64>().ok().map(|v| v * scale)
+}
+
+/// Criterion の出力（"name  time:   [low unit mid unit high unit]"）から中央値を取り出す。
+/// 長いベンチ名は名前と time: が別の行になる。
+pub fn parse_criterion(stdout: &str) -> HashMap<String, f64> {
+    let mut results = HashMap::new();
+    let mut current: Option<String> = None;
+    for line in stdout.lines() {
+        if !line.starts_with(char::is_whitespace) && !line.trim().is_empty() {
+            current = line.split_whitespace().next().map(|s| s.to_string());
+        }
+        let estimates = match line.split_once("time:") {
+            Some((_, rest)) => rest.trim().trim_start_matches('[').trim_end_matches(']').split_whitespace().collect::<Vec<_>>(),
+            None => continue,
+        };
+        if let (Some(name), [_, _, mid, unit, ..]) = (&current, estimates.as_slice()) {
+            if let Some(ns) = to_nanos(mid, unit) {
+                results.insert(name.clone(), ns);
+            }
+        }
+    }
+    results
+}
+
+/// `go test -bench` の出力（"Benchmark_name-8   1000000   123.4 ns/op"）を解析する
+pub fn parse_go_bench(stdout: &str) -> HashMap<String, f64> {
+    let mut results = HashMap::new();
+    for line in stdout.lines() {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let name = match fields.first().and_then(|f| f.strip_prefix("Benchmark_")) {
+            Some(name) => name,
+            None => continue,
+        };
+        // GOMAXPROCS の接尾辞（-8 など）を取り除く
+        let name = match name.rsplit_once('-') {
+            Some((base, procs)) if procs.chars().all(|c| c.is_ascii_digit()) => base,
+            _ => name,
+        };
+        if let Some(pos) = fields.iter().position(|f| *f == "ns/op") {
+            if let Some(ns) = pos.checked_sub(1).and_then(|p| fields.get(p)).and_then(|v| v.parse::<f64>().ok()) {
+                results.insert(name.to_string(), ns);
+            }
+        }
+    }
+    results
+}
+
+/// Node ハーネスの出力（"BENCH name ns"）を解析する
+pub fn parse_node(stdout: &str) -> HashMap<String, f64> {
+    stdout.lines().filter_map(|line| {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        match fields.as_slice() {
+            ["BENCH", name, ns] => ns.parse::<f64>().ok().map(|ns| (name.to_string(), ns)),
+            _ => None,
+        }
+    }).collect()
+}
+
+/// 1 呼び出しあたりの時間の表示（例: "12.3 ns", "1.52 µs"）
+fn format_nanos(ns: f64) -> String {
+    if ns >= 1_000_000.0 {
+        format!("{:.2} ms", ns / 1_000_000.0)
+    } else if ns >= 1_000.0 {
+        format!("{:.2} µs", ns / 1_000.0)
+    } else {
+        format!("{:.1} ns", ns)
+    }
+}
+
+/// atom × バックエンドの比較表（1 呼び出しあたりの時間）を作る。
+/// results に含まれないバックエンドは列を出さない。
+pub fn comparison_table(atoms: &[BenchAtom], results: &[(Backend, HashMap<String, f64>)]) -> String {
+    let mut header = vec!["atom".to_string(), "inputs".to_string()];
+    header.extend(results.iter().map(|(backend, _)| format!("{} (per call)", backend.label())));
+    let mut rows = vec![header];
+    for atom in atoms {
+        let mut row = vec![atom.name.clone(), atom.inputs.len().to_string()];
+        for (backend, times) in results {
+            // ハーネスに含めなかった atom は、同名のベンチマーク結果があっても unsupported とする
+            let cell = match times.get(&atom.name) {
+                _ if !atom.supports(*backend) => "unsupported".to_string(),
+                Some(ns) => format_nanos(ns / atom.inputs.len().max(1) as f64),
+                None => "-".to_string(),
+            };
+            row.push(cell);
+        }
+        rows.push(row);
+    }
+    let columns = rows[0].len();
+    let widths: Vec<usize> = (0..columns).map(|c| rows.iter().map(|r| r[c].chars().count()).max().unwrap_or(0)).collect();
+    let mut out = String::new();
+    for row in &rows {
+        let cells: Vec<String> = row.iter().zip(&widths).map(|(cell, w)| format!("{:<w$}", cell, w = w)).collect();
+        out.push_str(&format!("  {}\n", cells.join("  ").trim_end()));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_module;
+    use crate::test_support::{assert_golden, TempDir};
+
+    const SOURCE: &str = r#"
+atom halve(n: i64, d: i64)
+requires: d > 0 && n >= 0;
+ensures: result >= 0;
+body: n / d;
+
+atom magic(x: i64)
+requires: x == 123456;
+ensures: true;
+body: x;
+"#;
+
+    fn sample_atoms() -> Vec<BenchAtom> {
+        vec![
+            BenchAtom {
+                name: "halve".to_string(),
+                params: vec!["n".to_string(), "d".to_string()],
+                inputs: vec![vec![Value::Int(10), Value::Int(3)], vec![Value::Int(0), Value::Int(1)]],
+                backends: Backend::ALL.to_vec(),
+                notes: Vec::new(),
+            },
+            BenchAtom {
+                name: "scale".to_string(),
+                params: vec!["a".to_string()],
+                inputs: vec![vec![Value::Float(-1.5)]],
+                backends: vec![Backend::Rust, Backend::Node],
+                notes: vec![(Backend::Go, "float not supported".to_string())],
+            },
+        ]
+    }
+
+    #[test]
+    fn test_harness_golden_files() {
+        let atoms = sample_atoms();
+        assert_golden("bench", "Cargo.toml.golden", &criterion_manifest());
+        assert_golden("bench", "mumei.rs.golden", &criterion_bench(&atoms));
+        assert_golden("bench", "go.mod.golden", &go_mod());
+        assert_golden("bench", "mumei_bench_test.go.golden", &go_bench(&atoms));
+        assert_golden("bench", "bench.ts.golden", &node_harness(&atoms));
+    }
+
+    #[test]
+    fn test_plan_samples_requires_and_skips_unsatisfiable() {
+        let items = parse_module(SOURCE).unwrap();
+        let mut module_env = ModuleEnv::new();
+        for item in &items {
+            if let Item::Atom(a) = item {
+                module_env.register_atom(a);
+            }
+        }
+        let mut rng = Rng::new(11);
+        let halve = plan_atom(module_env.get_atom("halve").unwrap(), &module_env, 8, &mut rng).unwrap();
+        assert_eq!(halve.params, vec!["n", "d"]);
+        assert_eq!(halve.inputs.len(), 8);
+        assert!(halve.inputs.iter().all(|i| matches!(i.as_slice(), [Value::Int(n), Value::Int(d)] if *n >= 0 && *d > 0)));
+        assert_eq!(halve.backends, Backend::ALL.to_vec());
+
+        let err = plan_atom(module_env.get_atom("magic").unwrap(), &module_env, 8, &mut rng).unwrap_err();
+        assert!(err.contains("no input satisfying requires"), "{}", err);
+
+        // 生成したハーネスは全バックエンド分のファイルを持つ
+        let dir = TempDir::new("bench");
+        let written = write_harnesses(&items, &module_env, std::slice::from_ref(&halve), &dir).unwrap();
+        assert_eq!(written.iter().map(|(b, _)| *b).collect::<Vec<_>>(), Backend::ALL.to_vec());
+        let lib = fs::read_to_string(dir.join("rust").join("src").join("lib.rs")).unwrap();
+        assert!(lib.contains("pub fn halve("), "{}", lib);
+        let go = fs::read_to_string(dir.join("go").join("mumei.go")).unwrap();
+        assert!(go.starts_with("package mumeibench"), "{}", go);
+        let ts = fs::read_to_string(dir.join("node").join("bench.ts")).unwrap();
+        assert!(ts.contains("function halve(") && ts.contains("bench(\"halve\""), "{}", ts);
+    }
+
+    #[test]
+    fn test_parse_toolchain_output() {
+        let criterion = "halve                   time:   [10.120 ns 10.250 ns 10.400 ns]\n                        change: [-1.0% +0.2% +1.3%]\na_rather_long_benchmark_name\n                        time:   [1.5000 µs 1.5200 µs 1.5400 µs]\n";
+        let rust = parse_criterion(criterion);
+        assert_eq!(rust.get("halve"), Some(&10.25));
+        assert_eq!(rust.get("a_rather_long_benchmark_name"), Some(&1520.0));
+
+        let go = parse_go_bench("goos: linux\nBenchmark_halve-8   \t50000000\t        24.60 ns/op\nBenchmark_scale\t1000\t3.0 ns/op\nPASS\n");
+        assert_eq!(go.get("halve"), Some(&24.6));
+        assert_eq!(go.get("scale"), Some(&3.0));
+
+        let node = parse_node("BENCH halve 40.5\nwarning: something\n");
+        assert_eq!(node.len(), 1);
+        assert_eq!(node.get("halve"), Some(&40.5));
+
+        // 表は 1 呼び出しあたり（1 反復 / 入力数）で、ハーネスに含めなかった atom は unsupported
+        let atoms = sample_atoms();
+        let table = comparison_table(&atoms, &[(Backend::Rust, rust), (Backend::Go, go)]);
+        let lines: Vec<&str> = table.lines().collect();
+        assert!(lines[0].contains("rust (per call)") && lines[0].contains("go (per call)"), "{}", table);
+        assert!(lines[1].contains("halve") && lines[1].contains("5.1 ns") && lines[1].contains("12.3 ns"), "{}", table);
+        assert!(lines[2].contains("scale") && lines[2].contains("unsupported"), "{}", table);
+    }
+}
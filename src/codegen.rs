@@ -1,11 +1,13 @@
 use inkwell::context::Context;
 use inkwell::values::{AnyValue, BasicValueEnum, FunctionValue, PhiValue};
 use inkwell::builder::Builder;
+use inkwell::types::{BasicType, BasicTypeEnum};
 use inkwell::module::Module;
 use inkwell::IntPredicate;
 use inkwell::FloatPredicate;
 use inkwell::AddressSpace;
-use crate::parser::{Atom, Expr, Op, Pattern, parse_expression};
+use crate::ast::ResultKind;
+use crate::parser::{Atom, EnumDef, Expr, Op, Pattern, StructDef, parse_expression};
 use crate::verification::{ModuleEnv, MumeiError, MumeiResult};
 use std::collections::HashMap;
 use std::path::Path;
@@ -22,15 +24,184 @@ fn array_struct_type<'a>(context: &'a Context) -> inkwell::types::StructType<'a>
     context.struct_type(&[i64_type.into(), ptr_type.into()], false)
 }
 
+/// 配列パラメータ（`xs: [T]`）の要素の LLVM 型。f64 以外の要素は i64 として扱う
+fn array_elem_type<'a>(context: &'a Context, type_name: Option<&str>, module_env: &ModuleEnv) -> inkwell::types::BasicTypeEnum<'a> {
+    let elem = type_name.and_then(crate::ast::slice_elem_type).map(|t| module_env.resolve_base_type(&t));
+    match elem.as_deref() {
+        Some("f64") => context.f64_type().into(),
+        _ => context.i64_type().into(),
+    }
+}
+
+/// nullable パラメータ（`x: T?` / `Option<T>`）の構造体型 { i64 tag, T value }。
+/// tag は Option の variant index（None=0, Some=1）で、None のとき value は未規定。
+/// i64 / u64 / f64 のいずれも番兵値を使わずこの (tag, value) ペアで渡す。
+fn option_struct_type<'a>(context: &'a Context, inner: &str, module_env: &ModuleEnv) -> inkwell::types::StructType<'a> {
+    let value_type: inkwell::types::BasicTypeEnum = match module_env.resolve_base_type(inner).as_str() {
+        "f64" => context.f64_type().into(),
+        _ => context.i64_type().into(),
+    };
+    context.struct_type(&[context.i64_type().into(), value_type], false)
+}
+
+/// nullable パラメータの値を保持する変数名
+fn nullable_value_name(param_name: &str) -> String {
+    format!("__some_{}", param_name)
+}
+
+/// 再帰的 Enum のバリアント（`Cons(x, xs)` / `Nil`）を値として構築しようとしている場合のエラー。
+/// 再帰フィールドはヒープ上のノードを指す必要があるが、codegen は std/alloc の確保関数にまだ接続していないため、
+/// tag だけの誤った IR を出す代わりにここで拒否する。
+fn recursive_variant_error(name: &str, module_env: &ModuleEnv) -> Option<MumeiError> {
+    module_env.find_enum_by_variant(name)
+        .filter(|enum_def| enum_def.is_recursive)
+        .map(|enum_def| MumeiError::CodegenError(format!(
+            "recursive enum construction requires the runtime allocator (std/alloc): '{}' builds a value of recursive enum '{}'",
+            name, enum_def.name
+        )))
+}
+
+// =============================================================================
+// Enum の tagged union 表現
+// =============================================================================
+//
+// payload を持つ Enum の値は { i64 tag, [N x i64] payload }（N は最大のバリアントのフィールド数）。
+// tag は Variant の定義順の番号で、payload の i 番目のスロットが Variant の i 番目のフィールド。
+// f64 のフィールドはビット列のまま i64 のスロットに格納する。
+// payload を持たない Enum（全 Variant が unit）は従来どおり tag の i64 だけで表す。
+
+/// payload を持つ Enum の LLVM 型。payload のない Enum は None（tag の i64 で表す）
+fn enum_llvm_type<'a>(context: &'a Context, enum_def: &EnumDef) -> Option<inkwell::types::StructType<'a>> {
+    let slots = enum_def.variants.iter().map(|v| v.fields.len()).max().unwrap_or(0);
+    if slots == 0 {
+        return None;
+    }
+    Some(context.struct_type(&[context.i64_type().into(), context.i64_type().array_type(slots as u32).into()], false))
+}
+
+/// 型名が payload を持つ Enum なら、その LLVM 型
+fn enum_type_for<'a>(context: &'a Context, type_name: Option<&str>, module_env: &ModuleEnv) -> Option<inkwell::types::StructType<'a>> {
+    type_name.and_then(|name| module_env.get_enum(name)).and_then(|enum_def| enum_llvm_type(context, enum_def))
+}
+
+/// Variant の tag（Enum 内の定義順）
+fn variant_tag(enum_def: &EnumDef, variant_name: &str) -> u64 {
+    enum_def.variants.iter().position(|v| v.name == variant_name).unwrap_or(0) as u64
+}
+
+/// Variant の値を構築する（`Some(v)` / `Empty`）。payload のない Enum なら tag の i64
+fn build_variant<'a>(
+    context: &'a Context,
+    builder: &Builder<'a>,
+    enum_def: &EnumDef,
+    variant_name: &str,
+    args: &[BasicValueEnum<'a>],
+) -> MumeiResult<BasicValueEnum<'a>> {
+    let field_count = enum_def.variants.iter().find(|v| v.name == variant_name).map_or(0, |v| v.fields.len());
+    if args.len() != field_count {
+        return Err(MumeiError::CodegenError(format!(
+            "variant '{}' of enum '{}' takes {} field(s), but {} were given",
+            variant_name, enum_def.name, field_count, args.len()
+        )));
+    }
+    let tag = context.i64_type().const_int(variant_tag(enum_def, variant_name), false);
+    let enum_type = match enum_llvm_type(context, enum_def) {
+        Some(enum_type) => enum_type,
+        None => return Ok(tag.into()),
+    };
+    let slots = enum_def.variants.iter().map(|v| v.fields.len()).max().unwrap_or(0) as u32;
+    let mut payload = context.i64_type().array_type(slots).const_zero();
+    for (i, arg) in args.iter().enumerate() {
+        let slot = to_payload_slot(context, builder, *arg, variant_name)?;
+        payload = llvm!(builder.build_insert_value(payload, slot, i as u32, &format!("{}_field_{}", variant_name, i))).into_array_value();
+    }
+    let value = llvm!(builder.build_insert_value(enum_type.const_zero(), tag, 0, &format!("{}_tag", variant_name))).into_struct_value();
+    let value = llvm!(builder.build_insert_value(value, payload, 1, variant_name)).into_struct_value();
+    Ok(value.into())
+}
+
+/// payload のスロットに格納する i64（f64 はビット列のまま、i1 はゼロ拡張）
+fn to_payload_slot<'a>(context: &'a Context, builder: &Builder<'a>, value: BasicValueEnum<'a>, variant_name: &str) -> MumeiResult<inkwell::values::IntValue<'a>> {
+    match value {
+        BasicValueEnum::IntValue(v) if v.get_type().get_bit_width() == 64 => Ok(v),
+        BasicValueEnum::IntValue(v) => Ok(llvm!(builder.build_int_z_extend(v, context.i64_type(), "payload_ext"))),
+        BasicValueEnum::FloatValue(f) => Ok(llvm!(builder.build_bit_cast(f, context.i64_type(), "payload_bits")).into_int_value()),
+        _ => Err(MumeiError::CodegenError(format!(
+            "a field of variant '{}' is not an i64 or f64 value; enum payloads hold scalar fields only in LLVM codegen",
+            variant_name
+        ))),
+    }
+}
+
+/// Enum の値の tag（tagged union なら 0 番目のフィールド、tag だけの表現なら値そのもの）
+fn enum_tag<'a>(builder: &Builder<'a>, target: BasicValueEnum<'a>) -> MumeiResult<inkwell::values::IntValue<'a>> {
+    if target.is_struct_value() {
+        return Ok(llvm!(builder.build_extract_value(target.into_struct_value(), 0, "tag")).into_int_value());
+    }
+    Ok(target.into_int_value())
+}
+
+/// Enum の値の payload の index 番目のフィールドを、フィールドの型（f64 / それ以外は i64）の値で取り出す。
+/// tag だけの表現（payload のない Enum・nullable パラメータの tag）なら None
+fn enum_payload_field<'a>(
+    context: &'a Context,
+    builder: &Builder<'a>,
+    target: BasicValueEnum<'a>,
+    index: usize,
+    field_type: &str,
+    module_env: &ModuleEnv,
+) -> MumeiResult<Option<BasicValueEnum<'a>>> {
+    if !target.is_struct_value() {
+        return Ok(None);
+    }
+    let payload = llvm!(builder.build_extract_value(target.into_struct_value(), 1, "payload")).into_array_value();
+    let slot = llvm!(builder.build_extract_value(payload, index as u32, &format!("payload_{}", index))).into_int_value();
+    if module_env.resolve_base_type(field_type) == "f64" {
+        return Ok(Some(llvm!(builder.build_bit_cast(slot, context.f64_type(), &format!("payload_{}_f64", index)))));
+    }
+    Ok(Some(slot.into()))
+}
+
+/// パターンの Variant の i 番目のフィールドの型名（Enum 定義がなければ i64）
+fn variant_field_type<'m>(variant_name: &str, index: usize, module_env: &'m ModuleEnv) -> &'m str {
+    module_env.find_enum_by_variant(variant_name)
+        .and_then(|e| e.variants.iter().find(|v| v.name == variant_name))
+        .and_then(|v| v.fields.get(index))
+        .map_or("i64", String::as_str)
+}
+
+/// 構造体の LLVM 型（フィールドは定義順、f64 以外は i64）
+fn struct_llvm_type<'a>(context: &'a Context, sdef: &StructDef, module_env: &ModuleEnv) -> inkwell::types::StructType<'a> {
+    let field_types: Vec<BasicTypeEnum> = sdef.fields.iter().map(|f| match module_env.resolve_base_type(&f.type_name).as_str() {
+        "f64" => context.f64_type().into(),
+        _ => context.i64_type().into(),
+    }).collect();
+    context.struct_type(&field_types, false)
+}
+
 /// パラメータの LLVM 型を解決する
 fn resolve_param_type<'a>(context: &'a Context, type_name: Option<&str>, module_env: &ModuleEnv) -> inkwell::types::BasicTypeEnum<'a> {
+    if let Some(inner) = type_name.and_then(crate::ast::option_inner_type) {
+        return option_struct_type(context, &inner, module_env).into();
+    }
+    // 配列（`[i64]` / `[f64]`）は要素型によらず同じ Fat Pointer { len, data_ptr } で渡す
+    if type_name.and_then(crate::ast::slice_elem_type).is_some() {
+        return array_struct_type(context).into();
+    }
+    // payload を持つ Enum は tagged union の構造体を値で渡す
+    if let Some(enum_type) = enum_type_for(context, type_name, module_env) {
+        return enum_type.into();
+    }
     match type_name {
         Some(name) => {
             let base = module_env.resolve_base_type(name);
             match base.as_str() {
                 "f64" => context.f64_type().into(),
                 "u64" => context.i64_type().into(),
-                "[i64]" => array_struct_type(context).into(),
+                // bool は i1 で受け取り、本体では他の真偽値と同じ i64 の 0 / 1 に拡張する
+                "bool" => context.bool_type().into(),
+                // Str は配列と同じ Fat Pointer { len（バイト数）, data_ptr（i8*）}
+                "Str" => array_struct_type(context).into(),
                 _ => context.i64_type().into(),
             }
         },
@@ -38,48 +209,197 @@ fn resolve_param_type<'a>(context: &'a Context, type_name: Option<&str>, module_
     }
 }
 
-pub fn compile(atom: &Atom, output_path: &Path, module_env: &ModuleEnv) -> MumeiResult<()> {
+/// atom を LLVM IR (.ll) に変換して output_path に書き出す（atom ごとに 1 モジュール）。
+/// 他の atom の呼び出しは外部宣言になるため、単体でリンクできるのは呼び出しのない atom だけ。
+/// header: IR の先頭に置くコメント（検証状態の provenance / verify=false の透かし）
+pub fn compile(atom: &Atom, output_path: &Path, module_env: &ModuleEnv, header: &str) -> MumeiResult<()> {
     let context = Context::create();
     let module = context.create_module(&atom.name);
     let builder = context.create_builder();
 
-    let i64_type = context.i64_type();
+    let function = declare_atom(&context, &module, atom, module_env);
+    define_atom(&context, &builder, &module, function, atom, module_env)?;
+
+    write_ir(output_path, &format!("{}{}", header, module.print_to_string().to_string()))
+}
 
-    // パラメータ型を精緻型から解決
+/// 複数の atom を 1 つの LLVM モジュールにまとめて output_path に書き出す。
+/// 先に全 atom の関数を宣言してから本体を生成するので、後ろで定義される atom の呼び出しも
+/// 外部宣言ではなくモジュール内の定義に解決される。
+/// atoms: (atom, その関数定義の直前に置くコメント)。header: IR の先頭に置くコメント
+pub fn compile_module(module_name: &str, atoms: &[(&Atom, String)], output_path: &Path, module_env: &ModuleEnv, header: &str) -> MumeiResult<()> {
+    let context = Context::create();
+    let module = context.create_module(module_name);
+    let builder = context.create_builder();
+
+    let functions: Vec<FunctionValue> = atoms.iter()
+        .map(|(atom, _)| declare_atom(&context, &module, atom, module_env))
+        .collect();
+    for ((atom, _), function) in atoms.iter().zip(functions) {
+        define_atom(&context, &builder, &module, function, atom, module_env)?;
+    }
+
+    let comments: HashMap<&str, &str> = atoms.iter().map(|(atom, comment)| (symbol_name(&atom.name), comment.as_str())).collect();
+    let ir = insert_function_comments(&module.print_to_string().to_string(), &comments);
+    write_ir(output_path, &format!("{}{}", header, ir))
+}
+
+fn write_ir(output_path: &Path, ir: &str) -> MumeiResult<()> {
+    let path_with_ext = output_path.with_extension("ll");
+    std::fs::write(&path_with_ext, ir).map_err(|e| MumeiError::CodegenError(e.to_string()))
+}
+
+/// 印字した IR の各 `define` 行の直前に、その関数のコメントを差し込む
+fn insert_function_comments(ir: &str, comments: &HashMap<&str, &str>) -> String {
+    let mut out = String::with_capacity(ir.len());
+    for line in ir.split_inclusive('\n') {
+        if let Some(comment) = defined_function_name(line).and_then(|name| comments.get(name)) {
+            out.push_str(comment);
+        }
+        out.push_str(line);
+    }
+    out
+}
+
+/// `define i64 @name(...)` / `define i64 @"math::add"(...)` から関数名を取り出す
+fn defined_function_name(line: &str) -> Option<&str> {
+    if !line.starts_with("define ") {
+        return None;
+    }
+    let after_at = &line[line.find('@')? + 1..];
+    match after_at.strip_prefix('"') {
+        Some(quoted) => quoted.split('"').next(),
+        None => after_at.split('(').next(),
+    }
+}
+
+/// 実行ファイルの入口 `atom main()` の LLVM シンボル名。C の main（crate::native のスタブ）と衝突しないように付け替える
+pub const ENTRY_SYMBOL: &str = "mumei_main";
+
+/// atom の LLVM シンボル名（`main` だけは ENTRY_SYMBOL）
+fn symbol_name(atom_name: &str) -> &str {
+    if atom_name == "main" { ENTRY_SYMBOL } else { atom_name }
+}
+
+/// atom の関数をモジュールに宣言する（パラメータ型は精緻型から解決し、戻り値は i64）
+fn declare_atom<'a>(context: &'a Context, module: &Module<'a>, atom: &Atom, module_env: &ModuleEnv) -> FunctionValue<'a> {
     let param_types: Vec<inkwell::types::BasicMetadataTypeEnum> = atom.params.iter()
-        .map(|p| resolve_param_type(&context, p.type_name.as_deref(), module_env).into())
+        .map(|p| resolve_param_type(context, p.type_name.as_deref(), module_env).into())
         .collect();
-    let fn_type = i64_type.fn_type(&param_types, false);
-    let function = module.add_function(&atom.name, fn_type, None);
+    let fn_type = return_type(context, atom, module_env).fn_type(&param_types, false);
+    module.add_function(symbol_name(&atom.name), fn_type, None)
+}
+
+/// atom の戻り値型（crate::ast::result_kind）。真偽値は i1 で返し、呼び出し側で i64 に拡張する。
+/// Str は define_atom がエラーにするので、宣言上は i64 のままにする。`-> Point` は構造体を値で返し、
+/// payload を持つ Enum（`-> Shape`）は tagged union の構造体を値で返す
+fn return_type<'a>(context: &'a Context, atom: &Atom, module_env: &ModuleEnv) -> BasicTypeEnum<'a> {
+    if let Some(sdef) = crate::ast::struct_return(atom, module_env) {
+        return struct_llvm_type(context, sdef, module_env).into();
+    }
+    if let Some(enum_type) = enum_type_for(context, atom.return_type.as_deref(), module_env) {
+        return enum_type.into();
+    }
+    match crate::ast::result_kind(atom, module_env) {
+        ResultKind::Float => context.f64_type().into(),
+        ResultKind::Bool => context.bool_type().into(),
+        ResultKind::Int | ResultKind::Str => context.i64_type().into(),
+    }
+}
 
+/// 宣言済みの関数に atom の本体を生成する
+fn define_atom<'a>(
+    context: &'a Context,
+    builder: &Builder<'a>,
+    module: &Module<'a>,
+    function: FunctionValue<'a>,
+    atom: &Atom,
+    module_env: &ModuleEnv,
+) -> MumeiResult<()> {
+    // 関数は i64 / f64 / i1 しか返さないため、Str の戻り値は扱えない
+    let kind = crate::ast::result_kind(atom, module_env);
+    if kind == ResultKind::Str {
+        return Err(MumeiError::CodegenError(format!(
+            "atom '{}' returns Str, which LLVM codegen does not support yet (Str is supported as a parameter and literal argument)",
+            atom.name
+        )));
+    }
     let entry_block = context.append_basic_block(function, "entry");
     builder.position_at_end(entry_block);
 
     let mut variables = HashMap::new();
-    let mut array_ptrs: HashMap<String, (BasicValueEnum, BasicValueEnum)> = HashMap::new(); // name -> (len, data_ptr)
+    let mut array_ptrs: HashMap<String, (BasicValueEnum, BasicValueEnum, BasicTypeEnum)> = HashMap::new(); // name -> (len, data_ptr, 要素型)
 
     for (i, param) in atom.params.iter().enumerate() {
         let val = function.get_nth_param(i as u32).unwrap();
-        // Fat Pointer 配列パラメータの場合、len と data_ptr を分解して保持
-        if val.is_struct_value() {
+        let is_nullable = param.type_name.as_deref().and_then(crate::ast::option_inner_type).is_some();
+        if is_nullable {
+            // nullable パラメータ: 変数本体は tag（match / is_some 用）、値は __some_{name}
+            let struct_val = val.into_struct_value();
+            let tag = llvm!(builder.build_extract_value(struct_val, 0, &format!("{}_tag", param.name)));
+            let value = llvm!(builder.build_extract_value(struct_val, 1, &format!("{}_value", param.name)));
+            variables.insert(param.name.clone(), tag);
+            variables.insert(nullable_value_name(&param.name), value);
+        } else if enum_type_for(context, param.type_name.as_deref(), module_env).is_some() {
+            // payload を持つ Enum のパラメータは tagged union の構造体のまま保持する（match で tag と payload を取り出す）
+            variables.insert(param.name.clone(), val);
+        } else if val.is_struct_value() {
+            // Fat Pointer 配列パラメータの場合、len と data_ptr を分解して保持
             let struct_val = val.into_struct_value();
             let len_val = llvm!(builder.build_extract_value(struct_val, 0, &format!("{}_len", param.name)));
             let data_ptr = llvm!(builder.build_extract_value(struct_val, 1, &format!("{}_data", param.name)));
-            array_ptrs.insert(param.name.clone(), (len_val, data_ptr));
+            let elem_type = array_elem_type(context, param.type_name.as_deref(), module_env);
+            array_ptrs.insert(param.name.clone(), (len_val, data_ptr, elem_type));
             variables.insert(param.name.clone(), len_val); // デフォルトでは len を返す
+        } else if val.is_int_value() && val.into_int_value().get_type().get_bit_width() == 1 {
+            // bool パラメータ（i1）は式の真偽値の表現（i64 の 0 / 1）に揃える
+            let widened = llvm!(builder.build_int_z_extend(val.into_int_value(), context.i64_type(), &format!("{}_bool", param.name)));
+            variables.insert(param.name.clone(), widened.into());
         } else {
             variables.insert(param.name.clone(), val);
         }
     }
 
-    let body_ast = parse_expression(&atom.body_expr);
-    let result_val = compile_expr(&context, &builder, &module, &function, &body_ast, &mut variables, &array_ptrs, module_env)?;
+    let body_ast = parse_expression(&atom.body_expr)?;
+    let result_val = compile_expr(context, builder, module, &function, &body_ast, &mut variables, &array_ptrs, module_env)?;
 
-    llvm!(builder.build_return(Some(&result_val)));
-
-    let path_with_ext = output_path.with_extension("ll");
-    module.print_to_file(&path_with_ext).map_err(|e| MumeiError::CodegenError(e.to_string()))?;
+    // 構造体を返す atom は本体の構造体の値をそのまま返す（検証で本体の型は確認済み）
+    if let Some(sdef) = crate::ast::struct_return(atom, module_env) {
+        if !result_val.is_struct_value() {
+            return Err(MumeiError::CodegenError(format!(
+                "atom '{}' declares return type {} but its body does not yield a struct value",
+                atom.name, sdef.name
+            )));
+        }
+        llvm!(builder.build_return(Some(&result_val)));
+        return Ok(());
+    }
+    if let Some(enum_name) = atom.return_type.as_deref().filter(|_| enum_type_for(context, atom.return_type.as_deref(), module_env).is_some()) {
+        if !result_val.is_struct_value() {
+            return Err(MumeiError::CodegenError(format!(
+                "atom '{}' declares return type {} but its body does not yield a value of that enum",
+                atom.name, enum_name
+            )));
+        }
+        llvm!(builder.build_return(Some(&result_val)));
+        return Ok(());
+    }
 
+    // 式は真偽値を i64 の 0 / 1 で持つので、宣言した戻り値型に合わせる
+    let result_val: BasicValueEnum = match (kind, result_val) {
+        (ResultKind::Bool, BasicValueEnum::IntValue(v)) if v.get_type().get_bit_width() != 1 =>
+            llvm!(builder.build_int_compare(IntPredicate::NE, v, context.i64_type().const_int(0, false), "ret_bool")).into(),
+        (ResultKind::Float, BasicValueEnum::IntValue(v)) =>
+            llvm!(builder.build_signed_int_to_float(v, context.f64_type(), "ret_float")).into(),
+        (ResultKind::Int, BasicValueEnum::FloatValue(_)) => {
+            return Err(MumeiError::CodegenError(format!(
+                "atom '{}' body yields f64 but its result was inferred as i64",
+                atom.name
+            )));
+        }
+        (_, value) => value,
+    };
+    llvm!(builder.build_return(Some(&result_val)));
     Ok(())
 }
 
@@ -90,17 +410,35 @@ fn compile_expr<'a>(
     function: &FunctionValue<'a>,
     expr: &Expr,
     variables: &mut HashMap<String, BasicValueEnum<'a>>,
-    array_ptrs: &HashMap<String, (BasicValueEnum<'a>, BasicValueEnum<'a>)>,
+    array_ptrs: &HashMap<String, (BasicValueEnum<'a>, BasicValueEnum<'a>, BasicTypeEnum<'a>)>,
     module_env: &ModuleEnv,
 ) -> MumeiResult<BasicValueEnum<'a>> {
     match expr {
         Expr::Number(n) => Ok(context.i64_type().const_int(*n as u64, true).into()),
 
         Expr::Float(f) => Ok(context.f64_type().const_float(*f).into()),
+        // 真偽値は比較演算の結果と同じ i64 の 0 / 1
+        Expr::Bool(b) => Ok(context.i64_type().const_int(*b as u64, false).into()),
+        Expr::StringLit(s) => {
+            // 文字列リテラル: 定数のグローバル文字列を指す Fat Pointer { len, data_ptr }
+            let data = llvm!(builder.build_global_string_ptr(s, "str"));
+            let len = context.i64_type().const_int(s.len() as u64, false);
+            Ok(array_struct_type(context).const_named_struct(&[len.into(), data.as_pointer_value().into()]).into())
+        },
 
-        Expr::Variable(name) => variables.get(name)
-            .cloned()
-            .ok_or_else(|| MumeiError::CodegenError(format!("Undefined variable: {}", name))),
+        Expr::Variable(name) => {
+            if let Some(value) = variables.get(name) {
+                return Ok(*value);
+            }
+            if let Some(err) = recursive_variant_error(name, module_env) {
+                return Err(err);
+            }
+            // unit variant（`None` / `Empty`）の値
+            match module_env.find_enum_by_variant(name) {
+                Some(enum_def) => build_variant(context, builder, enum_def, name, &[]),
+                None => Err(MumeiError::CodegenError(format!("Undefined variable: {}", name))),
+            }
+        },
 
         Expr::Call(name, args) => {
             match name.as_str() {
@@ -115,11 +453,39 @@ fn compile_expr<'a>(
                     let result = call.as_any_value_enum();
                     Ok(result.into_float_value().into())
                 },
+                "approx_eq" => {
+                    // |a - b| <= eps（fabs + 順序付き比較。NaN なら偽）
+                    if args.len() != 3 {
+                        return Err(MumeiError::CodegenError("approx_eq() expects 3 arguments".into()));
+                    }
+                    let mut operands = Vec::with_capacity(3);
+                    for arg in args {
+                        let value = compile_expr(context, builder, module, function, arg, variables, array_ptrs, module_env)?;
+                        operands.push(if value.is_float_value() {
+                            value.into_float_value()
+                        } else {
+                            llvm!(builder.build_signed_int_to_float(value.into_int_value(), context.f64_type(), "approx_operand"))
+                        });
+                    }
+                    let fabs_func = module.get_function("llvm.fabs.f64").unwrap_or_else(|| {
+                        let type_f64 = context.f64_type();
+                        let fn_type = type_f64.fn_type(&[type_f64.into()], false);
+                        module.add_function("llvm.fabs.f64", fn_type, None)
+                    });
+                    let diff = llvm!(builder.build_float_sub(operands[0], operands[1], "approx_diff"));
+                    let call = llvm!(builder.build_call(fabs_func, &[diff.into()], "approx_abs"));
+                    let abs = call.as_any_value_enum().into_float_value();
+                    let cmp = llvm!(builder.build_float_compare(FloatPredicate::OLE, abs, operands[2], "approx_cmp"));
+                    Ok(llvm!(builder.build_int_z_extend(cmp, context.i64_type(), "approx_eq")).into())
+                },
                 "len" => {
-                    // Fat Pointer: 配列名から長さフィールドを取得
+                    if let Some(Expr::StringLit(s)) = args.first() {
+                        return Ok(context.i64_type().const_int(s.len() as u64, false).into());
+                    }
+                    // Fat Pointer: 配列名（Str の変数名）から長さフィールドを取得
                     if !args.is_empty() {
                         if let Expr::Variable(arr_name) = &args[0] {
-                            if let Some((len_val, _)) = array_ptrs.get(arr_name) {
+                            if let Some((len_val, _, _)) = array_ptrs.get(arr_name) {
                                 return Ok(*len_val);
                             }
                         }
@@ -127,6 +493,36 @@ fn compile_expr<'a>(
                     // フォールバック: 配列が見つからない場合はダミー定数
                     Ok(context.i64_type().const_int(0, false).into())
                 },
+                // nullable パラメータ: is_some / is_none は tag 比較、value（`x!`）は
+                // tag を検査して None なら llvm.trap で停止する（検証済みなら到達しない）
+                "is_some" | "is_none" | "value" if nullable_arg(args, variables).is_some() => {
+                    let param = nullable_arg(args, variables).unwrap_or_default().to_string();
+                    let tag = variables[&param].into_int_value();
+                    let some_tag = context.i64_type().const_int(option_some_tag(module_env), false);
+                    let present = llvm!(builder.build_int_compare(IntPredicate::EQ, tag, some_tag, "is_some_cmp"));
+                    match name.as_str() {
+                        "is_some" => Ok(llvm!(builder.build_int_z_extend(present, context.i64_type(), "is_some")).into()),
+                        "is_none" => {
+                            let absent = llvm!(builder.build_not(present, "is_none_cmp"));
+                            Ok(llvm!(builder.build_int_z_extend(absent, context.i64_type(), "is_none")).into())
+                        },
+                        _ => {
+                            let some_block = context.append_basic_block(*function, "nullable.some");
+                            let none_block = context.append_basic_block(*function, "nullable.none");
+                            llvm!(builder.build_conditional_branch(present, some_block, none_block));
+
+                            builder.position_at_end(none_block);
+                            let trap_fn = module.get_function("llvm.trap").unwrap_or_else(|| {
+                                module.add_function("llvm.trap", context.void_type().fn_type(&[], false), None)
+                            });
+                            llvm!(builder.build_call(trap_fn, &[], "trap_none"));
+                            llvm!(builder.build_unreachable());
+
+                            builder.position_at_end(some_block);
+                            Ok(variables[&nullable_value_name(&param)])
+                        }
+                    }
+                },
                 "alloc_raw" => {
                     // alloc_raw(size) → malloc(size * 8) → i64 としてポインタを返す
                     let size_val = compile_expr(context, builder, module, function, &args[0], variables, array_ptrs, module_env)?;
@@ -176,38 +572,77 @@ fn compile_expr<'a>(
                             .map(|p| resolve_param_type(context, p.type_name.as_deref(), module_env).into())
                             .collect();
 
-                        // 戻り値型の推定: f64 パラメータがあれば f64、なければ i64
-                        let has_float = callee.params.iter().any(|p| {
-                            p.type_name.as_deref()
-                                .map(|t| module_env.resolve_base_type(t) == "f64")
-                                .unwrap_or(false)
+                        // 戻り値型は定義側と同じ推論（crate::ast::result_kind）で決める
+                        let callee_kind = crate::ast::result_kind(callee, module_env);
+                        let fn_type = return_type(context, callee, module_env).fn_type(&callee_param_types, false);
+                        let callee_fn = module.get_function(symbol_name(name)).unwrap_or_else(|| {
+                            module.add_function(symbol_name(name), fn_type, Some(inkwell::module::Linkage::External))
                         });
-                        let callee_fn = if has_float {
-                            let fn_type = context.f64_type().fn_type(&callee_param_types, false);
-                            module.get_function(name).unwrap_or_else(|| {
-                                module.add_function(name, fn_type, Some(inkwell::module::Linkage::External))
-                            })
-                        } else {
-                            let fn_type = context.i64_type().fn_type(&callee_param_types, false);
-                            module.get_function(name).unwrap_or_else(|| {
-                                module.add_function(name, fn_type, Some(inkwell::module::Linkage::External))
-                            })
-                        };
 
                         // 引数を評価
                         let mut arg_vals: Vec<inkwell::values::BasicMetadataValueEnum> = Vec::new();
-                        for arg in args {
+                        for (i, arg) in args.iter().enumerate() {
+                            let nullable_inner = callee.params.get(i)
+                                .and_then(|p| p.type_name.as_deref())
+                                .and_then(crate::ast::option_inner_type);
+                            if let Some(inner) = nullable_inner {
+                                // nullable 仮引数には実引数を評価した結果を (tag, value) の構造体に詰めて渡す
+                                let packed = compile_nullable_arg(context, builder, module, function, arg, &inner, variables, array_ptrs, module_env)?;
+                                arg_vals.push(packed.into());
+                                continue;
+                            }
+                            // 配列・Str の仮引数には、変数の（len ではなく）Fat Pointer を組み直して渡す
+                            let fat_param = callee.params.get(i)
+                                .and_then(|p| p.type_name.as_deref())
+                                .map_or(false, |t| crate::ast::slice_elem_type(t).is_some() || module_env.resolve_base_type(t) == "Str");
+                            if let Some((len_val, data_ptr)) = fat_pointer_arg(arg.without_ref_marker(), array_ptrs).filter(|_| fat_param) {
+                                let packed = llvm!(builder.build_insert_value(array_struct_type(context).get_undef(), len_val, 0, "fat_len"))
+                                    .into_struct_value();
+                                let packed = llvm!(builder.build_insert_value(packed, data_ptr, 1, "fat_data"))
+                                    .into_struct_value();
+                                arg_vals.push(packed.into());
+                                continue;
+                            }
                             let val = compile_expr(context, builder, module, function, arg, variables, array_ptrs, module_env)?;
+                            // bool の仮引数（i1）には i64 の 0 / 1 を比較で変換して渡す
+                            let bool_param = callee_param_types.get(i)
+                                .map_or(false, |t| matches!(t, inkwell::types::BasicMetadataTypeEnum::IntType(int) if int.get_bit_width() == 1));
+                            let val: BasicValueEnum = match val {
+                                BasicValueEnum::IntValue(v) if bool_param && v.get_type().get_bit_width() != 1 =>
+                                    llvm!(builder.build_int_compare(IntPredicate::NE, v, context.i64_type().const_int(0, false), "arg_bool")).into(),
+                                other => other,
+                            };
                             arg_vals.push(val.into());
                         }
 
                         let call_result = llvm!(builder.build_call(callee_fn, &arg_vals, &format!("call_{}", name)));
                         let result = call_result.as_any_value_enum();
-                        if has_float {
-                            Ok(result.into_float_value().into())
-                        } else {
-                            Ok(result.into_int_value().into())
+                        if crate::ast::struct_return(callee, module_env).is_some() || enum_type_for(context, callee.return_type.as_deref(), module_env).is_some() {
+                            return Ok(result.into_struct_value().into());
+                        }
+                        match callee_kind {
+                            ResultKind::Float => Ok(result.into_float_value().into()),
+                            ResultKind::Bool => Ok(llvm!(builder.build_int_z_extend(result.into_int_value(), context.i64_type(), "call_bool")).into()),
+                            ResultKind::Int | ResultKind::Str => Ok(result.into_int_value().into()),
+                        }
+                    } else if let Some(extension) = crate::theory::find_intrinsic(name) {
+                        // 理論拡張の組み込み関数はコアの式に展開してからコンパイルする
+                        let lowered = extension.lower_call(name, args)?;
+                        compile_expr(context, builder, module, function, &lowered, variables, array_ptrs, module_env)
+                    } else if let Some(err) = recursive_variant_error(name, module_env) {
+                        Err(err)
+                    } else if let Some(enum_def) = module_env.find_enum_by_variant(name) {
+                        // Variant の構築（`Some(v)` / `Rect(w, h)`）: tag と payload の tagged union
+                        let mut fields = Vec::with_capacity(args.len());
+                        for arg in args {
+                            fields.push(compile_expr(context, builder, module, function, arg, variables, array_ptrs, module_env)?);
                         }
+                        build_variant(context, builder, enum_def, name, &fields)
+                    } else if name == "forall" || name == "exists" {
+                        Err(MumeiError::CodegenError(format!(
+                            "{}(...) is a verification-only quantifier and cannot be compiled; use it in requires, ensures or a loop invariant",
+                            name
+                        )))
                     } else {
                         Err(MumeiError::CodegenError(format!("Unknown function {}", name)))
                     }
@@ -219,7 +654,7 @@ fn compile_expr<'a>(
             // Fat Pointer: data_ptr から GEP + load
             let idx = compile_expr(context, builder, module, function, index_expr, variables, array_ptrs, module_env)?
                 .into_int_value();
-            if let Some((len_val, data_ptr_val)) = array_ptrs.get(name) {
+            if let Some((len_val, data_ptr_val, elem_type)) = array_ptrs.get(name) {
                 let data_ptr = data_ptr_val.into_pointer_value();
                 // ランタイム境界チェック: idx < len を検証し、違反時は 0 を返す（安全なフォールバック）
                 let len_int = len_val.into_int_value();
@@ -236,21 +671,25 @@ fn compile_expr<'a>(
                 // Safe path: GEP + load
                 builder.position_at_end(safe_block);
                 let elem_ptr = unsafe {
-                    llvm!(builder.build_gep(context.i64_type(), data_ptr, &[idx], "elem_ptr"))
+                    llvm!(builder.build_gep(*elem_type, data_ptr, &[idx], "elem_ptr"))
                 };
-                let loaded = llvm!(builder.build_load(context.i64_type(), elem_ptr, "elem_val"));
+                let loaded = llvm!(builder.build_load(*elem_type, elem_ptr, "elem_val"));
                 let safe_end = builder.get_insert_block().unwrap();
                 llvm!(builder.build_unconditional_branch(merge_block));
 
-                // OOB path: return 0 (safe default)
+                // OOB path: return 0 / 0.0 (safe default)
                 builder.position_at_end(oob_block);
-                let zero_val = context.i64_type().const_int(0, false);
+                let zero_val: BasicValueEnum = if elem_type.is_float_type() {
+                    context.f64_type().const_float(0.0).into()
+                } else {
+                    context.i64_type().const_int(0, false).into()
+                };
                 let oob_end = builder.get_insert_block().unwrap();
                 llvm!(builder.build_unconditional_branch(merge_block));
 
                 // Merge
                 builder.position_at_end(merge_block);
-                let phi = llvm!(builder.build_phi(context.i64_type(), "arr_result"));
+                let phi = llvm!(builder.build_phi(*elem_type, "arr_result"));
                 phi.add_incoming(&[(&loaded, safe_end), (&zero_val, oob_end)]);
                 Ok(phi.as_basic_value())
             } else {
@@ -259,53 +698,17 @@ fn compile_expr<'a>(
             }
         },
 
-        Expr::BinaryOp(left, op, right) => {
-            let lhs = compile_expr(context, builder, module, function, left, variables, array_ptrs, module_env)?;
-            let rhs = compile_expr(context, builder, module, function, right, variables, array_ptrs, module_env)?;
-
-            if lhs.is_float_value() || rhs.is_float_value() {
-                let l = if lhs.is_float_value() {
-                    lhs.into_float_value()
-                } else {
-                    llvm!(builder.build_signed_int_to_float(lhs.into_int_value(), context.f64_type(), "int_to_float_l"))
-                };
-                let r = if rhs.is_float_value() {
-                    rhs.into_float_value()
-                } else {
-                    llvm!(builder.build_signed_int_to_float(rhs.into_int_value(), context.f64_type(), "int_to_float_r"))
-                };
-                match op {
-                    Op::Add => Ok(llvm!(builder.build_float_add(l, r, "fadd_tmp")).into()),
-                    Op::Sub => Ok(llvm!(builder.build_float_sub(l, r, "fsub_tmp")).into()),
-                    Op::Mul => Ok(llvm!(builder.build_float_mul(l, r, "fmul_tmp")).into()),
-                    Op::Div => Ok(llvm!(builder.build_float_div(l, r, "fdiv_tmp")).into()),
-                    Op::Eq  => {
-                        let cmp = llvm!(builder.build_float_compare(FloatPredicate::OEQ, l, r, "fcmp_tmp"));
-                        Ok(llvm!(builder.build_int_z_extend(cmp, context.i64_type(), "fbool_tmp")).into())
-                    },
-                    _ => Err(MumeiError::CodegenError(format!("Unsupported float operator {:?}", op))),
-                }
-            } else {
-                let l = lhs.into_int_value();
-                let r = rhs.into_int_value();
-                match op {
-                    Op::Add => Ok(llvm!(builder.build_int_add(l, r, "add_tmp")).into()),
-                    Op::Sub => Ok(llvm!(builder.build_int_sub(l, r, "sub_tmp")).into()),
-                    Op::Mul => Ok(llvm!(builder.build_int_mul(l, r, "mul_tmp")).into()),
-                    Op::Div => Ok(llvm!(builder.build_int_signed_div(l, r, "div_tmp")).into()),
-                    Op::Eq | Op::Neq | Op::Lt | Op::Gt | Op::Ge | Op::Le => {
-                        let pred = match op {
-                            Op::Eq => IntPredicate::EQ, Op::Neq => IntPredicate::NE,
-                            Op::Lt => IntPredicate::SLT, Op::Gt => IntPredicate::SGT,
-                            Op::Ge => IntPredicate::SGE, Op::Le => IntPredicate::SLE,
-                            _ => unreachable!(),
-                        };
-                        let cmp = llvm!(builder.build_int_compare(pred, l, r, "cmp_tmp"));
-                        Ok(llvm!(builder.build_int_z_extend(cmp, context.i64_type(), "bool_tmp")).into())
-                    },
-                    _ => Err(MumeiError::CodegenError(format!("Unsupported int operator {:?}", op))),
+        Expr::BinaryOp(..) => {
+            // 左に連なる連鎖（`a + b + c + ...`）は左端から順に畳み込み、連鎖の長さぶん再帰しない
+            let (leaf, nodes) = expr.left_spine();
+            let mut acc = compile_expr(context, builder, module, function, leaf, variables, array_ptrs, module_env)?;
+            for node in nodes {
+                if let Expr::BinaryOp(_, op, right) = node {
+                    let rhs = compile_expr(context, builder, module, function, right, variables, array_ptrs, module_env)?;
+                    acc = compile_binary_op(context, builder, op, acc, rhs)?;
                 }
             }
+            Ok(acc)
         },
 
         Expr::IfThenElse { cond, then_branch, else_branch } => {
@@ -401,22 +804,12 @@ fn compile_expr<'a>(
                     let qualified = format!("__struct_{}_{}", type_name, field_name);
                     variables.insert(qualified, val);
                 }
-                // 構造体定義のフィールド順で LLVM StructType を構築
-                let field_types: Vec<inkwell::types::BasicTypeEnum> = sdef.fields.iter().map(|f| {
-                    let base = module_env.resolve_base_type(&f.type_name);
-                    match base.as_str() {
-                        "f64" => context.f64_type().into(),
-                        _ => context.i64_type().into(),
-                    }
-                }).collect();
-                let struct_type = context.struct_type(
-                    &field_types.iter().map(|t| (*t).into()).collect::<Vec<_>>(), false
-                );
-                let mut struct_val = struct_type.get_undef();
-                for (i, (field_name, _)) in fields.iter().enumerate() {
-                    let qualified = format!("__struct_{}_{}", type_name, field_name);
+                // 構造体定義のフィールド順で LLVM StructType を構築（リテラルの記述順によらず定義順に格納する）
+                let mut struct_val = struct_llvm_type(context, sdef, module_env).get_undef();
+                for (i, field) in sdef.fields.iter().enumerate() {
+                    let qualified = format!("__struct_{}_{}", type_name, field.name);
                     if let Some(val) = variables.get(&qualified) {
-                        struct_val = llvm!(builder.build_insert_value(struct_val, *val, i as u32, &format!("struct_{}", field_name)))
+                        struct_val = llvm!(builder.build_insert_value(struct_val, *val, i as u32, &format!("struct_{}", field.name)))
                             .into_struct_value();
                     }
                 }
@@ -482,14 +875,15 @@ fn compile_expr<'a>(
 
                 // --- Step 1: パターン条件の生成（再帰的） ---
                 let pattern_matches = compile_pattern_test(
-                    context, builder, &arm.pattern, target_val, variables, module_env,
+                    context, builder, &arm.pattern, target_val, module_env,
                 )?;
 
                 // --- Step 2: ガード条件 ---
                 let full_cond = if let Some(guard) = &arm.guard {
                     // ガード評価のためにパターン変数を一時バインド
                     let mut guard_vars = variables.clone();
-                    bind_pattern_variables(&arm.pattern, target_val, &mut guard_vars);
+                    bind_pattern_variables(context, builder, &arm.pattern, target_val, &mut guard_vars, module_env)?;
+                    bind_nullable_payload(&arm.pattern, target, &mut guard_vars);
                     let guard_val = compile_expr(context, builder, module, function, guard, &mut guard_vars, array_ptrs, module_env)?.into_int_value();
                     let guard_bool = llvm!(builder.build_int_compare(
                         IntPredicate::NE, guard_val,
@@ -507,7 +901,8 @@ fn compile_expr<'a>(
                 // --- Step 3 & 4: パターン変数バインド + body コンパイル ---
                 builder.position_at_end(body_block);
                 let mut arm_vars = variables.clone();
-                bind_pattern_variables(&arm.pattern, target_val, &mut arm_vars);
+                bind_pattern_variables(context, builder, &arm.pattern, target_val, &mut arm_vars, module_env)?;
+                bind_nullable_payload(&arm.pattern, target, &mut arm_vars);
 
                 let body_val = compile_expr(context, builder, module, function, &arm.body, &mut arm_vars, array_ptrs, module_env)?;
                 let body_end = builder.get_insert_block().unwrap();
@@ -516,14 +911,20 @@ fn compile_expr<'a>(
             }
 
             // unreachable ブロック: 網羅性は verification で保証済みなので到達しない
+            // 結果の型はアームの値に合わせる（Enum を返す match は tagged union の構造体）
+            let result_type = incoming.first().map_or(context.i64_type().into(), |(val, _)| val.get_type());
             builder.position_at_end(unreachable_block);
-            let unreachable_val: BasicValueEnum = context.i64_type().const_int(0, false).into();
+            let unreachable_val: BasicValueEnum = match result_type {
+                BasicTypeEnum::FloatType(t) => t.const_float(0.0).into(),
+                BasicTypeEnum::StructType(t) => t.get_undef().into(),
+                _ => context.i64_type().const_int(0, false).into(),
+            };
             llvm!(builder.build_unconditional_branch(merge_block));
             incoming.push((unreachable_val, unreachable_block));
 
             // merge ブロックで phi ノードを構築
             builder.position_at_end(merge_block);
-            let phi = llvm!(builder.build_phi(context.i64_type(), "match_result"));
+            let phi = llvm!(builder.build_phi(result_type, "match_result"));
             for (val, block) in &incoming {
                 phi.add_incoming(&[(val, *block)]);
             }
@@ -567,7 +968,8 @@ fn compile_expr<'a>(
 
             // グローバル mutex 変数: @__mumei_resource_{name}
             // リンク時にランタイムライブラリまたはユーザーコードが提供する。
-            let global_name = format!("__mumei_resource_{}", resource);
+            // 修飾名 `alias::name` は C からリンク可能な `alias__name` に変換する。
+            let global_name = format!("__mumei_resource_{}", resource.replace("::", "__"));
             let mutex_global = module.get_global(&global_name).unwrap_or_else(|| {
                 // i8 型のグローバル変数として宣言（実際の型はランタイム依存）
                 // pthread_mutex_t のサイズはプラットフォーム依存のため、
@@ -611,6 +1013,14 @@ fn compile_expr<'a>(
             //   switch i8 %suspend, label %suspend.end [i8 0, label %resume; i8 1, label %cleanup]
             compile_expr(context, builder, module, function, expr, variables, array_ptrs, module_env)
         },
+        Expr::Assume { .. } => {
+            // assume は検証時の仮定なので IR には何も出さない
+            Ok(context.i64_type().const_int(0, false).into())
+        },
+        // 参照渡しの実引数: ref / ref mut パラメータも IR では値渡し
+        Expr::RefArg { expr, .. } => {
+            compile_expr(context, builder, module, function, expr, variables, array_ptrs, module_env)
+        },
 
         Expr::FieldAccess(inner_expr, field_name) => {
             // ネスト構造体のフィールドアクセスを再帰的に解決する。
@@ -662,6 +1072,59 @@ fn compile_expr<'a>(
     }
 }
 
+/// 評価済みのオペランドに二項演算を適用する（片方が f64 なら浮動小数点の演算にする）
+fn compile_binary_op<'a>(
+    context: &'a Context,
+    builder: &Builder<'a>,
+    op: &Op,
+    lhs: BasicValueEnum<'a>,
+    rhs: BasicValueEnum<'a>,
+) -> MumeiResult<BasicValueEnum<'a>> {
+    if lhs.is_float_value() || rhs.is_float_value() {
+        let l = if lhs.is_float_value() {
+            lhs.into_float_value()
+        } else {
+            llvm!(builder.build_signed_int_to_float(lhs.into_int_value(), context.f64_type(), "int_to_float_l"))
+        };
+        let r = if rhs.is_float_value() {
+            rhs.into_float_value()
+        } else {
+            llvm!(builder.build_signed_int_to_float(rhs.into_int_value(), context.f64_type(), "int_to_float_r"))
+        };
+        match op {
+            Op::Add => Ok(llvm!(builder.build_float_add(l, r, "fadd_tmp")).into()),
+            Op::Sub => Ok(llvm!(builder.build_float_sub(l, r, "fsub_tmp")).into()),
+            Op::Mul => Ok(llvm!(builder.build_float_mul(l, r, "fmul_tmp")).into()),
+            Op::Div => Ok(llvm!(builder.build_float_div(l, r, "fdiv_tmp")).into()),
+            Op::Eq  => {
+                let cmp = llvm!(builder.build_float_compare(FloatPredicate::OEQ, l, r, "fcmp_tmp"));
+                Ok(llvm!(builder.build_int_z_extend(cmp, context.i64_type(), "fbool_tmp")).into())
+            },
+            _ => Err(MumeiError::CodegenError(format!("Unsupported float operator {:?}", op))),
+        }
+    } else {
+        let l = lhs.into_int_value();
+        let r = rhs.into_int_value();
+        match op {
+            Op::Add => Ok(llvm!(builder.build_int_add(l, r, "add_tmp")).into()),
+            Op::Sub => Ok(llvm!(builder.build_int_sub(l, r, "sub_tmp")).into()),
+            Op::Mul => Ok(llvm!(builder.build_int_mul(l, r, "mul_tmp")).into()),
+            Op::Div => Ok(llvm!(builder.build_int_signed_div(l, r, "div_tmp")).into()),
+            Op::Eq | Op::Neq | Op::Lt | Op::Gt | Op::Ge | Op::Le => {
+                let pred = match op {
+                    Op::Eq => IntPredicate::EQ, Op::Neq => IntPredicate::NE,
+                    Op::Lt => IntPredicate::SLT, Op::Gt => IntPredicate::SGT,
+                    Op::Ge => IntPredicate::SGE, Op::Le => IntPredicate::SLE,
+                    _ => unreachable!(),
+                };
+                let cmp = llvm!(builder.build_int_compare(pred, l, r, "cmp_tmp"));
+                Ok(llvm!(builder.build_int_z_extend(cmp, context.i64_type(), "bool_tmp")).into())
+            },
+            _ => Err(MumeiError::CodegenError(format!("Unsupported int operator {:?}", op))),
+        }
+    }
+}
+
 // =============================================================================
 // Pattern Matrix: パターン条件生成 + 変数バインド
 // =============================================================================
@@ -672,13 +1135,12 @@ fn compile_expr<'a>(
 ///
 /// - Wildcard / Variable → true (const 1)
 /// - Literal(n) → target == n
-/// - Variant { name, fields } → (target == tag) ∧ (fields の再帰条件)
+/// - Variant { name, fields } → (tag == Variant の番号) ∧ (payload の各フィールドに対する再帰条件)
 fn compile_pattern_test<'a>(
     context: &'a Context,
     builder: &Builder<'a>,
     pattern: &Pattern,
     target: BasicValueEnum<'a>,
-    _variables: &HashMap<String, BasicValueEnum<'a>>,
     module_env: &ModuleEnv,
 ) -> MumeiResult<inkwell::values::IntValue<'a>> {
     match pattern {
@@ -694,35 +1156,31 @@ fn compile_pattern_test<'a>(
         },
         Pattern::Variant { variant_name, fields } => {
             // Enum variant: tag 値で判定
-            let target_int = target.into_int_value();
+            let target_tag = enum_tag(builder, target)?;
             let tag_val = if let Some(enum_def) = module_env.find_enum_by_variant(variant_name) {
-                enum_def.variants.iter()
-                    .position(|v| v.name == *variant_name)
-                    .unwrap_or(0) as u64
+                variant_tag(enum_def, variant_name)
             } else {
                 // Enum 定義が見つからない場合はハッシュベースのフォールバック
                 variant_name.bytes().fold(0u64, |acc, b| acc.wrapping_mul(31).wrapping_add(b as u64))
             };
             let tag_const = context.i64_type().const_int(tag_val, false);
-            let tag_match = llvm!(builder.build_int_compare(IntPredicate::EQ, target_int, tag_const, "pat_tag_eq"));
+            let tag_match = llvm!(builder.build_int_compare(IntPredicate::EQ, target_tag, tag_const, "pat_tag_eq"));
 
-            // ネストパターンの再帰処理: 各フィールドの条件を AND 結合
-            // 現在は tag のみで payload を持たないため、フィールドパターンが
-            // Wildcard/Variable 以外の場合のみ再帰（将来の payload 対応の準備）
+            // ネストパターンの再帰処理: payload の各フィールドを取り出して条件を AND 結合する
             let mut result = tag_match;
-            for (_i, field_pat) in fields.iter().enumerate() {
-                match field_pat {
-                    Pattern::Wildcard | Pattern::Variable(_) => {
-                        // 常にマッチ → AND しても変わらない
-                    },
-                    _ => {
-                        // 将来: payload からフィールド値を取得して再帰
-                        // 現在は payload がないため、ダミー値 0 で再帰テスト
-                        let dummy_field: BasicValueEnum = context.i64_type().const_int(0, false).into();
-                        let field_test = compile_pattern_test(context, builder, field_pat, dummy_field, _variables, module_env)?;
-                        result = llvm!(builder.build_and(result, field_test, "pat_nested_and"));
-                    },
+            for (i, field_pat) in fields.iter().enumerate() {
+                if matches!(field_pat, Pattern::Wildcard | Pattern::Variable(_)) {
+                    // 常にマッチ → AND しても変わらない
+                    continue;
                 }
+                let field_type = variant_field_type(variant_name, i, module_env);
+                let field = enum_payload_field(context, builder, target, i, field_type, module_env)?
+                    .ok_or_else(|| MumeiError::CodegenError(format!(
+                        "pattern on the payload of '{}' needs an enum value that carries a payload, but the match target only holds the tag",
+                        variant_name
+                    )))?;
+                let field_test = compile_pattern_test(context, builder, field_pat, field, module_env)?;
+                result = llvm!(builder.build_and(result, field_test, "pat_nested_and"));
             }
             Ok(result)
         },
@@ -731,37 +1189,28 @@ fn compile_pattern_test<'a>(
 
 /// パターンから変数バインドを variables に登録する（再帰的）。
 /// - Variable(name) → target の値を name にバインド
-/// - Variant の fields 内の Variable → 将来は payload から取得、現在はダミー
+/// - Variant の fields → payload の対応するスロットから取り出した値に再帰的にバインド。
+///   tag だけの値（nullable パラメータ）の payload は bind_nullable_payload が束縛する
 fn bind_pattern_variables<'a>(
+    context: &'a Context,
+    builder: &Builder<'a>,
     pattern: &Pattern,
     target: BasicValueEnum<'a>,
     variables: &mut HashMap<String, BasicValueEnum<'a>>,
-) {
+    module_env: &ModuleEnv,
+) -> MumeiResult<()> {
     match pattern {
         Pattern::Variable(name) => {
             variables.insert(name.clone(), target);
         },
-        Pattern::Variant { variant_name: _, fields } => {
-            for (_i, field_pat) in fields.iter().enumerate() {
-                match field_pat {
-                    Pattern::Variable(fname) => {
-                        // 将来: payload からフィールド値を GEP + load で取得
-                        // 現在は tag のみなのでダミー値
-                        let dummy: BasicValueEnum = target.get_type()
-                            .into_int_type()
-                            .const_int(0, false)
-                            .into();
-                        variables.insert(fname.clone(), dummy);
-                    },
-                    Pattern::Variant { .. } => {
-                        // ネストした Variant: 再帰的にバインド（将来の payload 対応）
-                        let dummy: BasicValueEnum = target.get_type()
-                            .into_int_type()
-                            .const_int(0, false)
-                            .into();
-                        bind_pattern_variables(field_pat, dummy, variables);
-                    },
-                    _ => {}
+        Pattern::Variant { variant_name, fields } => {
+            for (i, field_pat) in fields.iter().enumerate() {
+                if !matches!(field_pat, Pattern::Variable(_) | Pattern::Variant { .. }) {
+                    continue;
+                }
+                let field_type = variant_field_type(variant_name, i, module_env);
+                if let Some(field) = enum_payload_field(context, builder, target, i, field_type, module_env)? {
+                    bind_pattern_variables(context, builder, field_pat, field, variables, module_env)?;
                 }
             }
         },
@@ -769,6 +1218,112 @@ fn bind_pattern_variables<'a>(
             // バインドなし
         },
     }
+    Ok(())
+}
+
+/// nullable パラメータへの match では、`Some(v)` の v を (tag, value) の value にバインドする
+fn bind_nullable_payload<'a>(
+    pattern: &Pattern,
+    target: &Expr,
+    variables: &mut HashMap<String, BasicValueEnum<'a>>,
+) {
+    if let (Pattern::Variant { variant_name, fields }, Expr::Variable(name)) = (pattern, target) {
+        if variant_name != "Some" {
+            return;
+        }
+        if let ([Pattern::Variable(bound)], Some(value)) = (fields.as_slice(), variables.get(&nullable_value_name(name)).cloned()) {
+            variables.insert(bound.clone(), value);
+        }
+    }
+}
+
+/// 実引数が配列・Str のパラメータなら、その (len, data_ptr)
+fn fat_pointer_arg<'a>(arg: &Expr, array_ptrs: &HashMap<String, (BasicValueEnum<'a>, BasicValueEnum<'a>, BasicTypeEnum<'a>)>) -> Option<(BasicValueEnum<'a>, BasicValueEnum<'a>)> {
+    match arg {
+        Expr::Variable(name) => array_ptrs.get(name).map(|(len, data, _)| (*len, *data)),
+        _ => None,
+    }
+}
+
+/// `is_some(x)` / `is_none(x)` / `value(x)` の引数が nullable パラメータなら、その名前を返す
+fn nullable_arg<'e>(args: &'e [Expr], variables: &HashMap<String, BasicValueEnum>) -> Option<&'e str> {
+    match args {
+        [Expr::Variable(name)] if variables.contains_key(&nullable_value_name(name)) => Some(name.as_str()),
+        _ => None,
+    }
+}
+
+/// nullable 仮引数（`T?`）に渡す実引数を評価し、(tag, value) の構造体に詰める。
+/// - nullable パラメータの変数: その tag と値（`__some_{name}`）
+/// - if 式: 分岐ごとに詰めてから phi で合流する（分岐が nullable パラメータでも値を失わない）
+/// - Option の値（`Some(v)` / `None` / Option を返す呼び出し）: tagged union の tag と payload
+/// - それ以外（T の値）: Some として詰める
+#[allow(clippy::too_many_arguments)]
+fn compile_nullable_arg<'a>(
+    context: &'a Context,
+    builder: &Builder<'a>,
+    module: &Module<'a>,
+    function: &FunctionValue<'a>,
+    arg: &Expr,
+    inner: &str,
+    variables: &mut HashMap<String, BasicValueEnum<'a>>,
+    array_ptrs: &HashMap<String, (BasicValueEnum<'a>, BasicValueEnum<'a>, BasicTypeEnum<'a>)>,
+    module_env: &ModuleEnv,
+) -> MumeiResult<inkwell::values::StructValue<'a>> {
+    let arg = arg.without_ref_marker();
+    let (tag, value) = match arg {
+        Expr::Variable(name) if variables.contains_key(&nullable_value_name(name)) => {
+            (variables[name], variables[&nullable_value_name(name)])
+        },
+        Expr::IfThenElse { cond, then_branch, else_branch } => {
+            let cond_val = compile_expr(context, builder, module, function, cond, variables, array_ptrs, module_env)?.into_int_value();
+            let cond_bool = llvm!(builder.build_int_compare(IntPredicate::NE, cond_val, context.i64_type().const_int(0, false), "opt_cond"));
+
+            let then_block = context.append_basic_block(*function, "opt.then");
+            let else_block = context.append_basic_block(*function, "opt.else");
+            let merge_block = context.append_basic_block(*function, "opt.merge");
+            llvm!(builder.build_conditional_branch(cond_bool, then_block, else_block));
+
+            builder.position_at_end(then_block);
+            let then_val = compile_nullable_arg(context, builder, module, function, then_branch, inner, variables, array_ptrs, module_env)?;
+            let then_end_block = builder.get_insert_block().unwrap();
+            llvm!(builder.build_unconditional_branch(merge_block));
+
+            builder.position_at_end(else_block);
+            let else_val = compile_nullable_arg(context, builder, module, function, else_branch, inner, variables, array_ptrs, module_env)?;
+            let else_end_block = builder.get_insert_block().unwrap();
+            llvm!(builder.build_unconditional_branch(merge_block));
+
+            builder.position_at_end(merge_block);
+            let phi = llvm!(builder.build_phi(then_val.get_type(), "opt_arg"));
+            phi.add_incoming(&[(&then_val, then_end_block), (&else_val, else_end_block)]);
+            return Ok(phi.as_basic_value().into_struct_value());
+        },
+        _ => {
+            let val = compile_expr(context, builder, module, function, arg, variables, array_ptrs, module_env)?;
+            if val.is_struct_value() {
+                let tag = enum_tag(builder, val)?;
+                let zero: BasicValueEnum = match module_env.resolve_base_type(inner).as_str() {
+                    "f64" => context.f64_type().const_zero().into(),
+                    _ => context.i64_type().const_zero().into(),
+                };
+                let value = enum_payload_field(context, builder, val, 0, inner, module_env)?.unwrap_or(zero);
+                (tag.into(), value)
+            } else {
+                (context.i64_type().const_int(option_some_tag(module_env), false).into(), val)
+            }
+        },
+    };
+    let struct_type = option_struct_type(context, inner, module_env);
+    let packed = llvm!(builder.build_insert_value(struct_type.get_undef(), tag, 0, "opt_tag")).into_struct_value();
+    Ok(llvm!(builder.build_insert_value(packed, value, 1, "opt_value")).into_struct_value())
+}
+
+/// Option の Some の tag 値（prelude 未ロード時は None=0, Some=1）
+fn option_some_tag(module_env: &ModuleEnv) -> u64 {
+    module_env.get_enum("Option")
+        .and_then(|e| e.variants.iter().position(|v| v.name == "Some"))
+        .unwrap_or(1) as u64
 }
 
 /// フィールド名のみから全構造体定義を走査してインデックスを検索（ネスト構造体用）
@@ -796,3 +1351,165 @@ fn find_field_index(type_or_var_name: &str, field_name: &str, module_env: &Modul
     }
     None
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{parse_module, Item};
+    use crate::test_support::{env_from_source, TempDir};
+
+    /// source の atom を定義順に 1 つのモジュールへコンパイルし、生成した IR を返す。
+    /// atom_comments なら各 atom の定義の前に `; mumei: atom <name>` を置き、header はファイルの先頭に置く
+    fn compile_source(module_name: &str, source: &str, atom_comments: bool, header: &str) -> String {
+        let (items, module_env) = env_from_source(source);
+        let atoms: Vec<(&Atom, String)> = items.iter().filter_map(|item| match item {
+            Item::Atom(atom) => Some((atom, if atom_comments { format!("; mumei: atom {}\n", atom.name) } else { String::new() })),
+            _ => None,
+        }).collect();
+        compile_atoms(module_name, &atoms, &module_env, header)
+    }
+
+    /// atoms を 1 つのモジュールへコンパイルし、一時ディレクトリに書いた `<module_name>.ll` を読み返す
+    fn compile_atoms(module_name: &str, atoms: &[(&Atom, String)], module_env: &ModuleEnv, header: &str) -> String {
+        let dir = TempDir::new(&format!("codegen_{}", module_name));
+        compile_module(module_name, atoms, &dir.join(module_name), module_env, header).unwrap();
+        std::fs::read_to_string(dir.join(format!("{}.ll", module_name))).unwrap()
+    }
+
+    #[test]
+    fn test_compile_module_resolves_forward_calls() {
+        let ir = compile_source("katana", r#"
+atom twice_inc(x: i64) requires: true; ensures: true; body: inc(inc(x));
+atom inc(x: i64) requires: true; ensures: true; body: x + 1;
+"#, true, "; header\n");
+
+        // 後ろで定義される inc の呼び出しも外部宣言ではなくモジュール内の定義に解決される
+        assert!(ir.starts_with("; header\n"), "{}", ir);
+        assert!(ir.contains("; mumei: atom twice_inc\ndefine i64 @twice_inc("), "{}", ir);
+        assert!(ir.contains("; mumei: atom inc\ndefine i64 @inc("), "{}", ir);
+        assert!(!ir.contains("declare"), "{}", ir);
+    }
+
+    #[test]
+    fn test_entry_atom_symbol() {
+        let ir = compile_source("entry", r#"
+atom main() requires: true; ensures: true; body: answer();
+atom answer() requires: true; ensures: true; body: 42;
+"#, true, "");
+
+        // atom main は C のスタブの main と衝突しないよう mumei_main として定義される
+        assert!(ir.contains("; mumei: atom main\ndefine i64 @mumei_main("), "{}", ir);
+        assert!(!ir.contains("@main("), "{}", ir);
+    }
+
+    #[test]
+    fn test_defined_function_name() {
+        assert_eq!(defined_function_name("define i64 @inc(i64 %0) {\n"), Some("inc"));
+        assert_eq!(defined_function_name("define i64 @\"math::add\"(i64 %0) {\n"), Some("math::add"));
+        assert_eq!(defined_function_name("declare i64 @inc(i64)\n"), None);
+        assert_eq!(defined_function_name("  %call_inc = call i64 @inc(i64 %x)\n"), None);
+    }
+
+    #[test]
+    fn test_llvm_return_types_follow_result_kind() {
+        let ir = compile_source("kinds", r#"
+atom is_pos(x: i64) requires: true; ensures: true; body: x > 0;
+atom half(x: f64) requires: true; ensures: true; body: x / 2.0;
+atom sign(x: i64) requires: true; ensures: true; body: if is_pos(x) { 1 } else { 0 };
+"#, false, "");
+
+        // 真偽値は i1、f64 は double で返し、呼び出し側は i1 を i64 に拡張して使う
+        assert!(ir.contains("define i1 @is_pos("), "{}", ir);
+        assert!(ir.contains("define double @half("), "{}", ir);
+        assert!(ir.contains("define i64 @sign("), "{}", ir);
+        assert!(ir.contains("call i1 @is_pos(") && ir.contains("zext i1"), "{}", ir);
+    }
+
+    #[test]
+    fn test_struct_return_type() {
+        let ir = compile_source("points", r#"
+struct Point { x: i64 where v >= 0, y: f64 }
+atom make_point(a: i64) -> Point requires: a >= 0; ensures: true; body: Point { y: 1.5, x: a };
+atom point_x(a: i64) requires: a >= 0; ensures: true; body: make_point(a).x;
+"#, false, "");
+
+        // 構造体は定義順のフィールドで値として返し、呼び出し側は extractvalue でフィールドを取り出す
+        assert!(ir.contains("define { i64, double } @make_point("), "{}", ir);
+        assert!(ir.contains("insertvalue { i64, double } undef, i64 %"), "{}", ir);
+        assert!(ir.contains("call { i64, double } @make_point(") && ir.contains("extractvalue { i64, double }"), "{}", ir);
+    }
+
+    #[test]
+    fn test_enum_payload_tagged_union() {
+        let ir = compile_source("maybe", r#"
+enum MaybeInt { Nothing, Just(i64) }
+enum Shape { Circle(f64), Rect(f64, f64), Empty }
+atom wrap(x: i64) -> MaybeInt requires: true; ensures: true; body: if x > 0 { Just(x) } else { Nothing };
+atom unwrap_or_zero(m: MaybeInt) requires: true; ensures: true; body: match m { Just(v) => v + 1, Nothing => 0 };
+atom round_trip(x: i64) requires: true; ensures: true; body: unwrap_or_zero(wrap(x));
+atom is_unit(s: Shape) requires: true; ensures: true; body: match s { Rect(w, _) => 0, Circle(r) => 1, Empty => 2 };
+"#, false, "");
+
+        // payload を持つ Enum は { i64 tag, [N x i64] payload } を値で受け渡す
+        assert!(ir.contains("define { i64, [1 x i64] } @wrap("), "{}", ir);
+        assert!(ir.contains("@unwrap_or_zero({ i64, [1 x i64] }"), "{}", ir);
+        assert!(ir.contains("call { i64, [1 x i64] } @wrap("), "{}", ir);
+        // 構築は tag と payload の insertvalue、match は tag を比較して payload のスロットを取り出す
+        assert!(ir.contains("insertvalue [1 x i64]") && ir.contains("insertvalue { i64, [1 x i64] }"), "{}", ir);
+        assert!(ir.contains("extractvalue { i64, [1 x i64] }") && ir.contains("extractvalue [1 x i64]"), "{}", ir);
+        assert!(ir.contains("phi { i64, [1 x i64] }"), "{}", ir);
+        // f64 のフィールドはビット列のまま i64 のスロットに入っている
+        assert!(ir.contains("@is_unit({ i64, [2 x i64] }") && ir.contains("extractvalue { i64, [2 x i64] }"), "{}", ir);
+    }
+
+    #[test]
+    fn test_llvm_bool_params() {
+        let ir = compile_source("flags", r#"
+type Flag = bool where v == true;
+atom pick(flag: bool, a: i64, b: i64) requires: true; ensures: true; body: if flag { a } else { b };
+atom pick_flag(flag: Flag, a: i64) requires: true; ensures: true; body: if flag { a } else { 0 };
+atom pick_first(a: i64, b: i64) requires: true; ensures: true; body: pick(true, a, b);
+atom pick_pos(a: i64, b: i64) requires: true; ensures: true; body: pick(a > 0, a, b);
+"#, false, "");
+
+        // bool（とベース型が bool の精緻型）は i1 で受け取り、本体では i64 に拡張して条件に使う
+        assert!(ir.contains("define i64 @pick(i1 ") && ir.contains("define i64 @pick_flag(i1 "), "{}", ir);
+        assert!(ir.contains("zext i1"), "{}", ir);
+        // 呼び出し側は i64 の真偽値を i1 にして渡す（リテラルは定数に畳み込まれる）
+        assert!(ir.contains("call i64 @pick(i1 true,"), "{}", ir);
+        assert!(ir.contains("arg_bool"), "{}", ir);
+    }
+
+    #[test]
+    fn test_nullable_args_pack_evaluated_expressions() {
+        let ir = compile_source("nullable", r#"
+atom get_or_zero(x: i64?) requires: true; ensures: true; body: if is_some(x) { x! } else { 0 };
+atom double(n: i64) requires: true; ensures: true; body: n * 2;
+atom from_call(n: i64) requires: true; ensures: true; body: get_or_zero(double(n));
+atom from_branch(a: i64?, b: i64?, c: bool) requires: true; ensures: true; body: get_or_zero(if c { a } else { b });
+"#, false, "");
+
+        // 呼び出し結果（T の値）は Some として (tag, value) に詰めてから渡す
+        assert!(ir.contains("call i64 @double("), "{}", ir);
+        assert!(ir.contains("call i64 @get_or_zero({ i64, i64 }"), "{}", ir);
+        // if 式の実引数は分岐ごとに nullable パラメータの tag と値を詰め、構造体の phi で合流する
+        assert!(ir.contains("phi { i64, i64 }"), "{}", ir);
+    }
+
+    #[test]
+    fn test_deep_left_chain_body() {
+        // 10 万項の `x + x + ... + x` の本体を、連鎖の長さぶん再帰せずに生成する。
+        let items = parse_module("atom deep(x: i64) requires: true; ensures: true; body: x;\n").unwrap();
+        let mut atom = match &items[0] {
+            Item::Atom(atom) => atom.clone(),
+            _ => unreachable!(),
+        };
+        atom.body_expr = vec!["x"; 100_000].join(" + ");
+        let mut module_env = ModuleEnv::new();
+        module_env.register_atom(&atom);
+
+        let ir = compile_atoms("deep", &[(&atom, String::new())], &module_env, "");
+        assert!(ir.contains("define i64 @deep(i64"), "{}", ir.lines().take(5).collect::<Vec<_>>().join("\n"));
+        assert_eq!(ir.matches(" = add i64 ").count(), 99_999);
+    }
+}
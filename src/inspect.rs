@@ -0,0 +1,631 @@
+//! # Inspect モジュール
+//!
+//! `mumei inspect`（別名 `mumei doctor`）の実装。開発環境（Z3・LLVM・各言語のツールチェーン・std・
+//! mumei.toml・~/.mumei）を調べて項目ごとの [`Check`] にまとめ、テキストまたは `--json` で出力する。
+//!
+//! `--fix` は修復手段（[`Fix`]）を持つ項目を自動で直す:
+//! - Z3 / LLVM が見つからない → `mumei setup` を子プロセスで実行する
+//! - std が MUMEI_STD_PATH でしか見つからない → 実行ファイルの隣に std/ のシンボリックリンクを作る
+//! - .mm ファイルがあるのに mumei.toml がない → 最小の mumei.toml を作る（確認のうえ。`--yes` なら確認しない）
+//!
+//! 修復の結果は1件ずつ報告し、その後で検査をやり直す。終了コードはやり直した検査に残ったエラーで決まる。
+use std::fs;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::process::{Command as Cmd, Stdio};
+use serde_json::{json, Value};
+use crate::{manifest, setup, theory, verification};
+
+/// std の検査で確認するモジュール
+const STD_MODULES: &[&str] = &["prelude.mm", "option.mm", "result.mm", "list.mm",
+                                "stack.mm", "alloc.mm", "container/bounded_array.mm"];
+
+// =============================================================================
+// 検査結果
+// =============================================================================
+
+/// 検査項目の重大度
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Ok,
+    /// 情報のみ（集計には数えない）
+    Info,
+    /// 任意のツールがない等。Mumei はそのまま使える
+    Warning,
+    /// Mumei を使うには直す必要がある
+    Error,
+}
+
+impl Severity {
+    /// `--json` の severity
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Severity::Ok => "ok",
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        }
+    }
+
+    fn icon(self) -> &'static str {
+        match self {
+            Severity::Ok => "✅",
+            Severity::Info => "ℹ️ ",
+            Severity::Warning => "⚠️ ",
+            Severity::Error => "❌",
+        }
+    }
+}
+
+/// `--fix` で自動的に行える修復
+#[derive(Debug, Clone, PartialEq)]
+pub enum Fix {
+    /// `mumei setup` で Z3 / LLVM のツールチェーンを ~/.mumei/toolchains に導入する
+    Setup,
+    /// 実行ファイルの隣に std/ のシンボリックリンク（link → target）を作る
+    LinkStd { target: PathBuf, link: PathBuf },
+    /// dir に最小の mumei.toml を作る
+    Manifest { dir: PathBuf, name: String },
+}
+
+impl Fix {
+    /// 修復の内容（「run `mumei inspect --fix` to ...」に続く形）
+    pub fn describe(&self) -> String {
+        match self {
+            Fix::Setup => "run `mumei setup` (installs Z3 and LLVM into ~/.mumei/toolchains)".to_string(),
+            Fix::LinkStd { target, link } => format!("link {} -> {}", link.display(), target.display()),
+            Fix::Manifest { dir, name } => format!("create {} for package '{}'", dir.join("mumei.toml").display(), name),
+        }
+    }
+}
+
+/// 1つの検査項目の結果
+#[derive(Debug, Clone)]
+pub struct Check {
+    /// 項目名（`--json` の tool。例: "z3", "llvm", "std"）
+    pub tool: String,
+    pub found: bool,
+    pub version: Option<String>,
+    pub path: Option<String>,
+    pub severity: Severity,
+    /// テキスト出力の1行目（アイコンの後ろ）
+    pub message: String,
+    /// 続く補足行（インストール方法など）
+    pub notes: Vec<String>,
+    pub fix: Option<Fix>,
+}
+
+impl Check {
+    fn new(tool: &str, severity: Severity, message: String) -> Self {
+        Check { tool: tool.to_string(), found: true, version: None, path: None, severity, message, notes: Vec::new(), fix: None }
+    }
+
+    fn missing(mut self) -> Self {
+        self.found = false;
+        self
+    }
+
+    fn with_version(mut self, version: &str) -> Self {
+        self.version = Some(version.to_string());
+        self
+    }
+
+    fn with_path(mut self, path: impl std::fmt::Display) -> Self {
+        self.path = Some(path.to_string());
+        self
+    }
+
+    fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    fn with_fix(mut self, fix: Fix) -> Self {
+        self.fix = Some(fix);
+        self
+    }
+}
+
+/// 重大度ごとの件数（Info は数えない）
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Summary {
+    pub ok: usize,
+    pub warnings: usize,
+    pub errors: usize,
+}
+
+impl Summary {
+    pub fn of(checks: &[Check]) -> Self {
+        let count = |severity| checks.iter().filter(|c| c.severity == severity).count();
+        Summary { ok: count(Severity::Ok), warnings: count(Severity::Warning), errors: count(Severity::Error) }
+    }
+}
+
+// =============================================================================
+// 検査
+// =============================================================================
+
+/// 全項目を検査する（カレントディレクトリと実行ファイルの場所を基準にする）
+pub fn run_checks(z3_override: Option<&str>) -> Vec<Check> {
+    let version = env!("CARGO_PKG_VERSION");
+    let mut checks = vec![Check::new("mumei", Severity::Ok, format!("Mumei compiler: v{}", version)).with_version(version)];
+    checks.extend(check_z3(z3_override));
+    checks.push(check_llvm());
+    checks.push(check_tool("rustc", "Rust", &["--version"], "optional, for generated .rs syntax check"));
+    checks.push(check_tool("go", "Go", &["version"], "optional, for generated .go compilation"));
+    checks.push(check_tool("node", "Node.js", &["--version"], "optional, for generated .ts execution"));
+
+    let cwd = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+    let exe_dir = std::env::current_exe().ok().and_then(|exe| exe.parent().map(Path::to_path_buf));
+    let std_env = std::env::var("MUMEI_STD_PATH").ok().filter(|p| !p.is_empty());
+    checks.push(check_std(&cwd, exe_dir.as_deref(), std_env.as_deref()));
+    checks.push(check_manifest(&cwd));
+    checks.push(check_toolchains(&manifest::mumei_home().join("toolchains")));
+
+    // 理論拡張（cargo feature で組み込んだもの）
+    let theories = theory::registered();
+    if !theories.is_empty() {
+        let names: Vec<&str> = theories.iter().map(|t| t.name()).collect();
+        checks.push(Check::new("theories", Severity::Info, format!("theory extensions: {}", names.join(", "))));
+    }
+    checks
+}
+
+/// リンク済みの libz3 と CLI の z3。
+/// 検証に使われるのはリンク済みの libz3 で、CLI の z3 とはバージョンが異なりうるため両方を報告する
+fn check_z3(z3_override: Option<&str>) -> Vec<Check> {
+    let linked_z3 = verification::linked_z3_version();
+    let mut checks = vec![
+        Check::new("z3-library", Severity::Ok, format!("Z3 (linked library, used for verification): {}", linked_z3)).with_version(&linked_z3),
+    ];
+
+    let toolchain_z3 = manifest::mumei_home().join("toolchains").join(format!("z3-{}", setup::Z3_VERSION));
+    let z3_bin = verification::z3_binary_path(z3_override);
+    let binary = if Cmd::new(&z3_bin).arg("--version").output().is_err() {
+        let toolchain_bin = toolchain_z3.join("bin").join("z3");
+        if toolchain_bin.exists() {
+            // `mumei setup` 済みだが ~/.mumei/env を読み込んでいない
+            Check::new("z3", Severity::Warning, format!("Z3 ({}): not on PATH, but {} is installed", z3_bin, toolchain_bin.display()))
+                .with_path(toolchain_bin.display())
+                .with_note("Run: source ~/.mumei/env")
+        } else {
+            Check::new("z3", Severity::Error, format!("Z3 ({}): not found", z3_bin))
+                .missing()
+                .with_note("Install: brew install z3")
+                .with_fix(Fix::Setup)
+        }
+    } else {
+        match verification::query_z3_binary_version(&z3_bin) {
+            None => Check::new("z3", Severity::Warning, format!("Z3 ({}): installed but version unknown", z3_bin)).with_path(&z3_bin),
+            Some(version) => match verification::z3_versions_compatible(&linked_z3, &version) {
+                Some(false) => Check::new("z3", Severity::Warning, format!("Z3 ({}): {} — differs from the linked library ({})", z3_bin, version, linked_z3))
+                    .with_version(&version)
+                    .with_path(&z3_bin)
+                    .with_note(format!("Verification uses the linked library, so results may differ from standalone `{}` runs.", z3_bin)),
+                _ => Check::new("z3", Severity::Ok, format!("Z3 ({}): {}", z3_bin, version)).with_version(&version).with_path(&z3_bin),
+            },
+        }
+    };
+    checks.push(binary);
+
+    // ~/.mumei の Z3 ツールチェーンが導入済みなのに、別の libz3 にリンクされているケース
+    if toolchain_z3.exists() && verification::z3_versions_compatible(&linked_z3, setup::Z3_VERSION) == Some(false) {
+        checks.push(Check::new("z3-toolchain", Severity::Warning, format!(
+            "Z3 toolchain: {} provides Z3 {}, but mumei is linked against {}", toolchain_z3.display(), setup::Z3_VERSION, linked_z3
+        ))
+            .with_version(setup::Z3_VERSION)
+            .with_path(toolchain_z3.display())
+            .with_note("Rebuild with the toolchain's libz3: source ~/.mumei/env && cargo install --path . --force")
+            .with_note("(or point Z3_SYS_Z3_LIB_DIR / Z3_SYS_Z3_HEADER at the intended Z3 before building)"));
+    }
+    checks
+}
+
+/// llc。mumei build --emit obj|exe と同じ探索（MUMEI_LLC → ~/.mumei のツールチェーン → PATH）
+fn check_llvm() -> Check {
+    match setup::locate_llc() {
+        Ok(llc) => {
+            let version = Cmd::new(&llc).arg("--version").output()
+                .map(|output| String::from_utf8_lossy(&output.stdout).lines().next().unwrap_or("unknown").trim().to_string())
+                .unwrap_or_else(|_| "installed".to_string());
+            Check::new("llvm", Severity::Ok, format!("LLVM ({}): {}", llc.display(), version)).with_version(&version).with_path(llc.display())
+        }
+        Err(e) => Check::new("llvm", Severity::Error, format!("LLVM: {}", e))
+            .missing()
+            .with_note("Install: mumei setup (or brew install llvm@18)")
+            .with_fix(Fix::Setup),
+    }
+}
+
+/// 生成コードの確認に使う任意のツール（見つからなければ警告）
+fn check_tool(command: &str, label: &str, version_args: &[&str], purpose: &str) -> Check {
+    match Cmd::new(command).args(version_args).output() {
+        Ok(output) => {
+            let version = String::from_utf8_lossy(&output.stdout).trim().to_string();
+            Check::new(command, Severity::Ok, format!("{}: {}", label, version)).with_version(&version)
+        }
+        Err(_) => Check::new(command, Severity::Warning, format!("{}: not found ({})", label, purpose)).missing(),
+    }
+}
+
+/// std ライブラリ。resolver と同じ探索順序: cwd → 実行ファイルの隣 → MUMEI_STD_PATH。
+/// MUMEI_STD_PATH でしか見つからないときは、実行ファイルの隣へのリンクを修復として提案する
+/// （環境変数を引き継がないエディタや CI からも std が見えるようにする）
+pub(crate) fn check_std(cwd: &Path, exe_dir: Option<&Path>, std_env: Option<&str>) -> Check {
+    let env_dir = std_env.map(PathBuf::from).filter(|dir| dir.join("prelude.mm").exists());
+    let base_dir = Some(cwd.join("std"))
+        .filter(|dir| dir.join("prelude.mm").exists())
+        .or_else(|| exe_dir.map(|dir| dir.join("std")).filter(|dir| dir.join("prelude.mm").exists()))
+        .or_else(|| env_dir.clone());
+
+    let missing: Vec<&str> = match &base_dir {
+        Some(base) => STD_MODULES.iter().copied().filter(|module| !base.join(module).exists()).collect(),
+        None => STD_MODULES.to_vec(),
+    };
+    let found = STD_MODULES.len() - missing.len();
+    let check = match &base_dir {
+        Some(base) if missing.is_empty() => {
+            Check::new("std", Severity::Ok, format!("std library: {}/{} modules found ({})", found, STD_MODULES.len(), base.display()))
+                .with_path(base.display())
+        }
+        _ => {
+            let hint = if base_dir.is_none() { " (set MUMEI_STD_PATH or place std/ next to mumei binary)" } else { "" };
+            let check = Check::new("std", Severity::Warning, format!(
+                "std library: {}/{} modules found (missing: {}){}", found, STD_MODULES.len(), missing.join(", "), hint
+            ));
+            match &base_dir {
+                Some(base) => check.with_path(base.display()),
+                None => check.missing(),
+            }
+        }
+    };
+
+    match (&base_dir, &env_dir, exe_dir) {
+        (Some(base), Some(env_dir), Some(exe_dir)) if base == env_dir => {
+            let link = exe_dir.join("std");
+            // 壊れたリンクも既存のファイルとして扱い、上書きしない
+            if fs::symlink_metadata(&link).is_ok() {
+                return check;
+            }
+            let target = env_dir.canonicalize().unwrap_or_else(|_| env_dir.clone());
+            check.with_note("found only through MUMEI_STD_PATH").with_fix(Fix::LinkStd { target, link })
+        }
+        _ => check,
+    }
+}
+
+/// mumei.toml。dir に .mm ファイルがあるのに（dir と祖先に）mumei.toml がなければ、最小の mumei.toml を提案する
+pub(crate) fn check_manifest(dir: &Path) -> Check {
+    let manifest_path = dir.join("mumei.toml");
+    if !manifest_path.exists() {
+        let project = manifest::find_from(dir).map(|found| match found {
+            Ok((project_dir, _)) => project_dir.join("mumei.toml").display().to_string(),
+            Err(e) => e,
+        });
+        return match project {
+            Some(project) => Check::new("mumei.toml", Severity::Info, format!("mumei.toml: not in this directory (project: {})", project)).missing(),
+            None if has_sources(dir) => {
+                let name = dir.canonicalize().ok()
+                    .and_then(|d| d.file_name().map(|n| n.to_string_lossy().into_owned()))
+                    .unwrap_or_else(|| "my_project".to_string());
+                Check::new("mumei.toml", Severity::Info, "mumei.toml: not found, but this directory has .mm files".to_string())
+                    .missing()
+                    .with_fix(Fix::Manifest { dir: dir.to_path_buf(), name })
+            }
+            None => Check::new("mumei.toml", Severity::Info, "mumei.toml: not found (not in a Mumei project directory)".to_string()).missing(),
+        };
+    }
+    // mumei.toml が見つかったらパースして内容を表示
+    match manifest::load(&manifest_path) {
+        Ok(m) => {
+            let mut check = Check::new("mumei.toml", Severity::Ok, format!("mumei.toml: {} v{}", m.package.name, m.package.version))
+                .with_version(&m.package.version)
+                .with_path(manifest_path.display());
+            if !m.dependencies.is_empty() {
+                let mut names: Vec<&str> = m.dependencies.keys().map(|k| k.as_str()).collect();
+                names.sort();
+                check = check.with_note(format!("dependencies: {}", names.join(", ")));
+            }
+            if !m.build.targets.is_empty() {
+                check = check.with_note(format!("targets: {}", m.build.targets.join(", ")));
+            }
+            if !m.build.verify {
+                check = check.with_note("verification is disabled ([build] verify = false): build outputs are watermarked as NOT verified");
+            }
+            check
+        }
+        Err(e) => Check::new("mumei.toml", Severity::Warning, format!("mumei.toml: found but parse error: {}", e)).with_path(manifest_path.display()),
+    }
+}
+
+/// dir または dir/src に .mm ファイルがあるか
+fn has_sources(dir: &Path) -> bool {
+    [dir.to_path_buf(), dir.join("src")].iter().any(|d| {
+        fs::read_dir(d).map_or(false, |entries| {
+            entries.flatten().any(|entry| entry.path().extension().map_or(false, |ext| ext == "mm"))
+        })
+    })
+}
+
+/// ~/.mumei/toolchains に導入済みのツールチェーン
+fn check_toolchains(toolchains_dir: &Path) -> Check {
+    let mut names: Vec<String> = fs::read_dir(toolchains_dir).map(|entries| {
+        entries.flatten()
+            .filter(|entry| entry.path().is_dir())
+            .filter_map(|entry| entry.file_name().to_str().map(str::to_string))
+            .collect()
+    }).unwrap_or_default();
+    if !toolchains_dir.exists() {
+        return Check::new("toolchains", Severity::Info, "~/.mumei/toolchains: not found (run `mumei setup`)".to_string()).missing();
+    }
+    if names.is_empty() {
+        return Check::new("toolchains", Severity::Info, "~/.mumei/toolchains: empty (run `mumei setup`)".to_string())
+            .missing()
+            .with_path(toolchains_dir.display());
+    }
+    names.sort();
+    Check::new("toolchains", Severity::Ok, format!("~/.mumei/toolchains: {}", names.join(", "))).with_path(toolchains_dir.display())
+}
+
+// =============================================================================
+// 修復（--fix）
+// =============================================================================
+
+/// 修復の結果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FixStatus {
+    Applied,
+    Failed,
+    /// 確認で断られた、または確認できなかった（対話端末でなく --yes もない）
+    Skipped,
+}
+
+impl FixStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            FixStatus::Applied => "applied",
+            FixStatus::Failed => "failed",
+            FixStatus::Skipped => "skipped",
+        }
+    }
+}
+
+/// 1件の修復の報告
+#[derive(Debug, Clone)]
+pub struct FixOutcome {
+    pub fix: Fix,
+    pub status: FixStatus,
+    pub message: String,
+}
+
+/// 検査結果の修復を順に行う。同じ修復（Z3 と LLVM の両方が求める `mumei setup` など）は1回だけ行う。
+/// assume_yes なら mumei.toml の作成を確認しない
+pub fn apply_fixes(checks: &[Check], assume_yes: bool) -> Vec<FixOutcome> {
+    let mut fixes: Vec<&Fix> = Vec::new();
+    for fix in checks.iter().filter_map(|c| c.fix.as_ref()) {
+        if !fixes.contains(&fix) {
+            fixes.push(fix);
+        }
+    }
+    fixes.into_iter().map(|fix| {
+        let (status, message) = match apply_fix(fix, assume_yes) {
+            Ok(Some(message)) => (FixStatus::Applied, message),
+            Ok(None) => (FixStatus::Skipped, "not confirmed (pass --yes to apply without asking)".to_string()),
+            Err(message) => (FixStatus::Failed, message),
+        };
+        FixOutcome { fix: fix.clone(), status, message }
+    }).collect()
+}
+
+/// 1件の修復。Ok(None) は確認で見送ったとき
+pub(crate) fn apply_fix(fix: &Fix, assume_yes: bool) -> Result<Option<String>, String> {
+    match fix {
+        Fix::Setup => run_setup().map(Some),
+        Fix::LinkStd { target, link } => link_std(target, link).map(|_| Some(format!("linked {} -> {}", link.display(), target.display()))),
+        Fix::Manifest { dir, name } => {
+            let path = dir.join("mumei.toml");
+            if !assume_yes && !confirm(&format!("Create {} for package '{}'?", path.display(), name)) {
+                return Ok(None);
+            }
+            if path.exists() {
+                return Err(format!("{} already exists", path.display()));
+            }
+            fs::write(&path, minimal_manifest(name))
+                .map_err(|e| format!("failed to write {}: {}", path.display(), e))?;
+            Ok(Some(format!("created {}", path.display())))
+        }
+    }
+}
+
+/// `mumei setup` を子プロセスで実行する。進捗は stderr に流し、stdout（`--json` の出力）を汚さない
+fn run_setup() -> Result<String, String> {
+    let exe = std::env::current_exe().map_err(|e| format!("could not locate the mumei executable: {}", e))?;
+    let status = Cmd::new(&exe).arg("setup")
+        .stdout(Stdio::from(std::io::stderr()))
+        .status()
+        .map_err(|e| format!("failed to run mumei setup: {}", e))?;
+    if !status.success() {
+        return Err(format!("mumei setup exited with {}", status.code().map_or_else(|| "a signal".to_string(), |code| format!("status {}", code))));
+    }
+    if !setup::toolchains_installed() {
+        return Err("mumei setup finished, but the Z3 / LLVM toolchains are still missing (see its output above)".to_string());
+    }
+    Ok("installed the Z3 / LLVM toolchains into ~/.mumei/toolchains (run `source ~/.mumei/env`)".to_string())
+}
+
+/// std/ のシンボリックリンクを作る
+fn link_std(target: &Path, link: &Path) -> Result<(), String> {
+    #[cfg(unix)]
+    {
+        std::os::unix::fs::symlink(target, link).map_err(|e| format!("failed to link {} -> {}: {}", link.display(), target.display(), e))
+    }
+    #[cfg(not(unix))]
+    {
+        Err(format!("symlinks are only created on Unix; copy {} to {}", target.display(), link.display()))
+    }
+}
+
+/// 対話端末で y/N を尋ねる。端末でなければ（CI など）尋ねずに見送る
+fn confirm(question: &str) -> bool {
+    if !std::io::stdin().is_terminal() {
+        return false;
+    }
+    eprint!("  ❓ {} [y/N] ", question);
+    let mut answer = String::new();
+    if std::io::stdin().read_line(&mut answer).is_err() {
+        return false;
+    }
+    matches!(answer.trim().to_ascii_lowercase().as_str(), "y" | "yes")
+}
+
+/// `--fix` が作る最小の mumei.toml（残りの設定は既定値。全項目の雛形は `mumei init` が作る）
+pub(crate) fn minimal_manifest(name: &str) -> String {
+    format!(r#"[package]
+name = "{}"
+version = "0.1.0"
+edition = "{}"
+[dependencies]
+[build]
+targets = ["rust", "go", "typescript"]
+"#, name, crate::migrate::latest_edition())
+}
+
+// =============================================================================
+// 出力
+// =============================================================================
+
+/// テキスト出力（修復できる項目には `--fix` の案内を付ける）
+pub fn print_text(checks: &[Check]) {
+    for check in checks {
+        println!("  {} {}", check.severity.icon(), check.message);
+        for note in &check.notes {
+            println!("     {}", note);
+        }
+        if let Some(fix) = &check.fix {
+            println!("     Fix: run `mumei inspect --fix` to {}", fix.describe());
+        }
+    }
+}
+
+/// 修復の報告（テキスト出力）
+pub fn print_fixes(outcomes: &[FixOutcome]) {
+    for outcome in outcomes {
+        let icon = match outcome.status {
+            FixStatus::Applied => "✅",
+            FixStatus::Failed => "❌",
+            FixStatus::Skipped => "⏭️ ",
+        };
+        println!("  🔧 {}", outcome.fix.describe());
+        println!("     {} {}: {}", icon, outcome.status.as_str(), outcome.message);
+    }
+}
+
+/// `--json` の出力
+pub fn to_json(checks: &[Check], fixes: &[FixOutcome]) -> Value {
+    let summary = Summary::of(checks);
+    json!({
+        "checks": checks.iter().map(|check| json!({
+            "tool": check.tool,
+            "found": check.found,
+            "version": check.version,
+            "path": check.path,
+            "severity": check.severity.as_str(),
+            "message": check.message,
+            "notes": check.notes,
+            "fixable": check.fix.is_some(),
+        })).collect::<Vec<_>>(),
+        "fixes": fixes.iter().map(|outcome| json!({
+            "fix": outcome.fix.describe(),
+            "status": outcome.status.as_str(),
+            "message": outcome.message,
+        })).collect::<Vec<_>>(),
+        "summary": {
+            "ok": summary.ok,
+            "warnings": summary.warnings,
+            "errors": summary.errors,
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("mumei_inspect_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir.canonicalize().unwrap()
+    }
+
+    #[test]
+    fn test_std_only_through_env_offers_link() {
+        let root = temp_dir("std");
+        let (cwd, exe_dir, std_dir) = (root.join("work"), root.join("bin"), root.join("share").join("std"));
+        for module in STD_MODULES {
+            let path = std_dir.join(module);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+        fs::create_dir_all(&cwd).unwrap();
+        fs::create_dir_all(&exe_dir).unwrap();
+
+        let check = check_std(&cwd, Some(&exe_dir), std_dir.to_str());
+        assert_eq!(check.severity, Severity::Ok, "{:?}", check);
+        let fix = Fix::LinkStd { target: std_dir.clone(), link: exe_dir.join("std") };
+        assert_eq!(check.fix, Some(fix.clone()));
+
+        // リンクの後は実行ファイルの隣で見つかり、修復は提案されない
+        assert!(apply_fix(&fix, false).unwrap().is_some());
+        let check = check_std(&cwd, Some(&exe_dir), std_dir.to_str());
+        assert_eq!(check.path, Some(exe_dir.join("std").display().to_string()));
+        assert_eq!(check.fix, None);
+
+        // std がどこにもなければ警告だけ（リンク先がない）
+        let check = check_std(&cwd, None, None);
+        assert!(check.severity == Severity::Warning && !check.found && check.fix.is_none(), "{:?}", check);
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn test_manifest_scaffold_for_source_directory() {
+        let dir = temp_dir("manifest");
+        let check = check_manifest(&dir);
+        assert!(check.fix.is_none(), "{:?}", check);
+
+        fs::write(dir.join("main.mm"), "atom one() requires: true; ensures: true; body: 1;\n").unwrap();
+        let check = check_manifest(&dir);
+        let name = dir.file_name().unwrap().to_string_lossy().into_owned();
+        assert_eq!(check.fix, Some(Fix::Manifest { dir: dir.clone(), name: name.clone() }));
+
+        let outcomes = apply_fixes(&[check], true);
+        assert_eq!(outcomes[0].status, FixStatus::Applied, "{:?}", outcomes);
+        let manifest = manifest::load(&dir.join("mumei.toml")).unwrap();
+        assert_eq!(manifest.package.name, name);
+        assert_eq!(manifest.build.targets, ["rust", "go", "typescript"]);
+        let check = check_manifest(&dir);
+        assert!(check.severity == Severity::Ok && check.fix.is_none(), "{:?}", check);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_json_output() {
+        let checks = vec![
+            Check::new("z3", Severity::Error, "Z3 (z3): not found".to_string()).missing().with_fix(Fix::Setup),
+            Check::new("llvm", Severity::Error, "LLVM: llc not found".to_string()).missing().with_fix(Fix::Setup),
+            Check::new("go", Severity::Warning, "Go: not found".to_string()).missing(),
+            Check::new("rustc", Severity::Ok, "Rust: rustc 1.82.0".to_string()).with_version("rustc 1.82.0"),
+        ];
+        let outcomes = vec![FixOutcome { fix: Fix::Setup, status: FixStatus::Failed, message: "mumei setup exited with status 1".to_string() }];
+        let value = to_json(&checks, &outcomes);
+        assert_eq!(value["summary"], json!({ "ok": 1, "warnings": 1, "errors": 2 }));
+        assert_eq!(value["checks"][0]["tool"], "z3");
+        assert_eq!(value["checks"][0]["found"], false);
+        assert_eq!(value["checks"][0]["severity"], "error");
+        assert_eq!(value["checks"][0]["fixable"], true);
+        assert_eq!(value["checks"][3]["version"], "rustc 1.82.0");
+        assert_eq!(value["checks"][3]["path"], Value::Null);
+        assert_eq!(value["fixes"][0]["status"], "failed");
+        assert!(value["fixes"][0]["fix"].as_str().unwrap().contains("mumei setup"));
+    }
+}
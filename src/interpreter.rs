@@ -0,0 +1,485 @@
+// =============================================================================
+// Tree-walking Interpreter: atom 本体・契約の具体値評価
+// =============================================================================
+//
+// 検証（Z3 の記号モデル）とも生成コード（LLVM / トランスパイラ）とも独立に、
+// atom を具体的な入力で実行するための参照評価器。`mumei selftest` が
+// requires を満たす入力のサンプリング、本体の実行、ensures の具体値チェックに使い、
+// `mumei test` が test ブロックの assert の評価に使う。
+//
+// 意味論は生成コード（Rust バックエンド）に合わせる:
+// - 整数は i64。オーバーフローは実行時エラー（Rust の debug ビルドの panic に相当）
+// - 整数除算は 0 方向への切り捨て（Rust の `/`）、0 除算は実行時エラー
+// - 比較の結果は Bool。算術・条件式では 0/1 の整数としても扱う（LLVM codegen と同じ）
+// - match は上のアームから順に試行する
+// 配列・構造体・量化子など具体値で評価できない構文は Unsupported として報告する。
+
+use crate::parser::{Atom, Expr, Op, Pattern, TestDef, parse_expression};
+use crate::verification::ModuleEnv;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+/// 呼び出しのネスト上限（再帰 atom の暴走防止）
+const MAX_CALL_DEPTH: usize = 256;
+/// 1回の評価で実行できるステップ数の上限（停止しないループの防止）
+const MAX_STEPS: u64 = 1_000_000;
+
+/// 具体値
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Value {
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+}
+
+impl Value {
+    /// 条件式としての真偽（整数は非 0 を真とする）
+    pub fn truthy(&self) -> bool {
+        match self {
+            Value::Bool(b) => *b,
+            Value::Int(n) => *n != 0,
+            Value::Float(f) => *f != 0.0,
+        }
+    }
+
+    /// 整数として扱う（Bool は 0/1）
+    fn as_int(&self) -> Option<i64> {
+        match self {
+            Value::Int(n) => Some(*n),
+            Value::Bool(b) => Some(*b as i64),
+            Value::Float(_) => None,
+        }
+    }
+
+    /// 数値として同じ値か（生成コードの出力との比較用。浮動小数点は相対誤差 1e-9 まで許容）
+    pub fn same_as(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Float(a), Value::Float(b)) => {
+                (a.is_nan() && b.is_nan()) || a == b || (a - b).abs() <= 1e-9 * a.abs().max(b.abs())
+            }
+            (Value::Float(_), _) | (_, Value::Float(_)) => false,
+            _ => self.as_int() == other.as_int(),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Int(n) => write!(f, "{}", n),
+            Value::Float(x) => write!(f, "{:?}", x),
+            Value::Bool(b) => write!(f, "{}", b),
+        }
+    }
+}
+
+/// 評価エラー
+#[derive(Debug, Clone, PartialEq)]
+pub enum EvalError {
+    /// 具体値では評価できない構文（配列・構造体・量化子など）
+    Unsupported(String),
+    /// 実行時エラー（0 除算・オーバーフロー・ステップ上限など）
+    Runtime(String),
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EvalError::Unsupported(msg) => write!(f, "unsupported: {}", msg),
+            EvalError::Runtime(msg) => write!(f, "runtime error: {}", msg),
+        }
+    }
+}
+
+pub type EvalResult = Result<Value, EvalError>;
+
+pub struct Interpreter<'a> {
+    module_env: &'a ModuleEnv,
+    depth: usize,
+    steps: u64,
+}
+
+/// 式をパースする。構文エラーは評価できない式として返す
+fn parse_source(source: &str) -> Result<Expr, EvalError> {
+    parse_expression(source).map_err(|e| EvalError::Unsupported(format!("syntax error at {}", e)))
+}
+
+impl<'a> Interpreter<'a> {
+    pub fn new(module_env: &'a ModuleEnv) -> Self {
+        Self { module_env, depth: 0, steps: 0 }
+    }
+
+    /// atom を引数で実行して結果を返す
+    pub fn call_atom(&mut self, atom: &Atom, args: &[Value]) -> EvalResult {
+        if args.len() != atom.params.len() {
+            return Err(EvalError::Runtime(format!(
+                "atom '{}' expects {} argument(s), got {}", atom.name, atom.params.len(), args.len()
+            )));
+        }
+        if self.depth >= MAX_CALL_DEPTH {
+            return Err(EvalError::Runtime(format!("call depth limit ({}) exceeded in '{}'", MAX_CALL_DEPTH, atom.name)));
+        }
+        let mut env: HashMap<String, Value> = atom.params.iter()
+            .map(|p| p.name.clone())
+            .zip(args.iter().copied())
+            .collect();
+        let body = parse_source(&atom.body_expr)?;
+        self.depth += 1;
+        let result = self.eval(&body, &mut env);
+        self.depth -= 1;
+        result
+    }
+
+    /// 契約（requires / ensures / 精緻型の述語）を束縛の下で評価する
+    pub fn eval_contract(&mut self, source: &str, bindings: &HashMap<String, Value>) -> Result<bool, EvalError> {
+        let mut env = bindings.clone();
+        Ok(self.eval(&parse_source(source)?, &mut env)?.truthy())
+    }
+
+    fn tick(&mut self) -> Result<(), EvalError> {
+        self.steps += 1;
+        if self.steps > MAX_STEPS {
+            return Err(EvalError::Runtime(format!("step limit ({}) exceeded", MAX_STEPS)));
+        }
+        Ok(())
+    }
+
+    pub fn eval(&mut self, expr: &Expr, env: &mut HashMap<String, Value>) -> EvalResult {
+        self.tick()?;
+        match expr {
+            Expr::Number(n) => Ok(Value::Int(*n)),
+            Expr::Float(f) => Ok(Value::Float(*f)),
+            Expr::Bool(b) => Ok(Value::Bool(*b)),
+            Expr::Variable(name) => env.get(name).copied()
+                .ok_or_else(|| EvalError::Unsupported(format!("unbound variable '{}'", name))),
+            Expr::BinaryOp(l, op, r) => self.eval_binary(l, op, r, env),
+            Expr::IfThenElse { cond, then_branch, else_branch } => {
+                if self.eval(cond, env)?.truthy() {
+                    self.eval(then_branch, env)
+                } else {
+                    self.eval(else_branch, env)
+                }
+            }
+            Expr::Let { var, value } | Expr::Assign { var, value } => {
+                let v = self.eval(value, env)?;
+                env.insert(var.clone(), v);
+                Ok(v)
+            }
+            Expr::Block(stmts) => {
+                let mut last = Value::Int(0);
+                for stmt in stmts {
+                    last = self.eval(stmt, env)?;
+                }
+                Ok(last)
+            }
+            Expr::While { cond, body, .. } => {
+                while self.eval(cond, env)?.truthy() {
+                    self.eval(body, env)?;
+                }
+                Ok(Value::Int(0))
+            }
+            Expr::Call(name, args) => self.eval_call(name, args, env),
+            Expr::Match { target, arms } => {
+                let target_val = self.eval(target, env)?;
+                for arm in arms {
+                    let mut arm_env = env.clone();
+                    if !self.match_pattern(&arm.pattern, target_val, &mut arm_env)? {
+                        continue;
+                    }
+                    if let Some(guard) = &arm.guard {
+                        if !self.eval(guard, &mut arm_env)?.truthy() {
+                            continue;
+                        }
+                    }
+                    return self.eval(&arm.body, &mut arm_env);
+                }
+                Err(EvalError::Runtime(format!("no match arm covers {}", target_val)))
+            }
+            // 単一スレッドの参照評価では、リソース取得・async は本体をその場で実行するのと同じ
+            Expr::Acquire { body, .. } | Expr::Async { body } => self.eval(body, env),
+            Expr::Await { expr } | Expr::RefArg { expr, .. } => self.eval(expr, env),
+            // 生成コードの assume はデバッグアサーション（Rust の debug_assert!）なので、成り立たなければ実行時エラー
+            Expr::Assume { cond, text, justification } => {
+                if !self.eval(cond, env)?.truthy() {
+                    return Err(EvalError::Runtime(format!("assumption violated: {} ({})", text, justification)));
+                }
+                Ok(Value::Int(0))
+            }
+            Expr::ArrayAccess(name, _) => Err(EvalError::Unsupported(format!("array access '{}[..]'", name))),
+            Expr::StructInit { type_name, .. } => Err(EvalError::Unsupported(format!("struct literal '{}'", type_name))),
+            Expr::FieldAccess(_, field) => Err(EvalError::Unsupported(format!("field access '.{}'", field))),
+            Expr::StringLit(_) => Err(EvalError::Unsupported("string literal".to_string())),
+        }
+    }
+
+    fn eval_binary(&mut self, l: &Expr, op: &Op, r: &Expr, env: &mut HashMap<String, Value>) -> EvalResult {
+        // 論理演算子は短絡評価
+        match op {
+            Op::And => return Ok(Value::Bool(self.eval(l, env)?.truthy() && self.eval(r, env)?.truthy())),
+            Op::Or => return Ok(Value::Bool(self.eval(l, env)?.truthy() || self.eval(r, env)?.truthy())),
+            Op::Implies => return Ok(Value::Bool(!self.eval(l, env)?.truthy() || self.eval(r, env)?.truthy())),
+            _ => {}
+        }
+        let lv = self.eval(l, env)?;
+        let rv = self.eval(r, env)?;
+        if let (Value::Float(a), Value::Float(b)) = (lv, rv) {
+            return Ok(match op {
+                Op::Add => Value::Float(a + b),
+                Op::Sub => Value::Float(a - b),
+                Op::Mul => Value::Float(a * b),
+                Op::Div => Value::Float(a / b),
+                Op::Eq => Value::Bool(a == b),
+                Op::Neq => Value::Bool(a != b),
+                Op::Gt => Value::Bool(a > b),
+                Op::Lt => Value::Bool(a < b),
+                Op::Ge => Value::Bool(a >= b),
+                Op::Le => Value::Bool(a <= b),
+                Op::And | Op::Or | Op::Implies => unreachable!(),
+            });
+        }
+        let (a, b) = match (lv.as_int(), rv.as_int()) {
+            (Some(a), Some(b)) => (a, b),
+            _ => return Err(EvalError::Unsupported(format!("mixed int/float operands {} {:?} {}", lv, op, rv))),
+        };
+        let overflow = || EvalError::Runtime(format!("integer overflow in {} {:?} {}", a, op, b));
+        Ok(match op {
+            Op::Add => Value::Int(a.checked_add(b).ok_or_else(overflow)?),
+            Op::Sub => Value::Int(a.checked_sub(b).ok_or_else(overflow)?),
+            Op::Mul => Value::Int(a.checked_mul(b).ok_or_else(overflow)?),
+            Op::Div => {
+                if b == 0 {
+                    return Err(EvalError::Runtime(format!("division by zero in {} / 0", a)));
+                }
+                Value::Int(a.checked_div(b).ok_or_else(overflow)?)
+            }
+            Op::Eq => Value::Bool(a == b),
+            Op::Neq => Value::Bool(a != b),
+            Op::Gt => Value::Bool(a > b),
+            Op::Lt => Value::Bool(a < b),
+            Op::Ge => Value::Bool(a >= b),
+            Op::Le => Value::Bool(a <= b),
+            Op::And | Op::Or | Op::Implies => unreachable!(),
+        })
+    }
+
+    fn eval_call(&mut self, name: &str, args: &[Expr], env: &mut HashMap<String, Value>) -> EvalResult {
+        let mut values = Vec::with_capacity(args.len());
+        for arg in args {
+            values.push(self.eval(arg, env)?);
+        }
+        match name {
+            "sqrt" => match values.first() {
+                Some(Value::Float(f)) => Ok(Value::Float(f.sqrt())),
+                Some(v) => Ok(Value::Float((v.as_int().unwrap_or(0) as f64).sqrt())),
+                None => Err(EvalError::Runtime("sqrt expects 1 argument".into())),
+            },
+            "approx_eq" => match values.as_slice() {
+                [a, b, eps] => {
+                    let float = |v: &Value| match v {
+                        Value::Float(f) => *f,
+                        other => other.as_int().unwrap_or(0) as f64,
+                    };
+                    Ok(Value::Bool((float(a) - float(b)).abs() <= float(eps)))
+                }
+                _ => Err(EvalError::Runtime("approx_eq expects 3 arguments".into())),
+            },
+            "cast_to_int" => match values.first() {
+                Some(Value::Float(f)) => Ok(Value::Int(*f as i64)),
+                Some(v) => Ok(Value::Int(v.as_int().unwrap_or(0))),
+                None => Err(EvalError::Runtime("cast_to_int expects 1 argument".into())),
+            },
+            // 契約は呼び出し時点の束縛で評価するので、old(x) は x の値そのもの
+            "old" => match values.as_slice() {
+                [value] => Ok(*value),
+                _ => Err(EvalError::Runtime("old expects 1 argument".into())),
+            },
+            _ => {
+                // FQN dot-notation: "math.add" → "math::add"
+                let fqn_name = name.replace('.', "::");
+                let callee = self.module_env.get_atom(name)
+                    .or_else(|| self.module_env.get_atom(&fqn_name))
+                    .cloned();
+                match callee {
+                    Some(callee) => self.call_atom(&callee, &values),
+                    // 理論拡張の組み込み関数は codegen と同じ展開を評価する
+                    None => match crate::theory::find_intrinsic(name) {
+                        Some(extension) => {
+                            let lowered = extension.lower_call(name, args)
+                                .map_err(|e| EvalError::Unsupported(e.to_string()))?;
+                            self.eval(&lowered, env)
+                        }
+                        None => Err(EvalError::Unsupported(format!("call to '{}'", name))),
+                    },
+                }
+            }
+        }
+    }
+
+    /// パターンが値にマッチするか判定し、マッチすれば変数を束縛する
+    fn match_pattern(&mut self, pattern: &Pattern, value: Value, env: &mut HashMap<String, Value>) -> Result<bool, EvalError> {
+        match pattern {
+            Pattern::Wildcard => Ok(true),
+            Pattern::Variable(name) => {
+                env.insert(name.clone(), value);
+                Ok(true)
+            }
+            Pattern::Literal(n) => Ok(value.as_int() == Some(*n)),
+            Pattern::Variant { variant_name, fields } => {
+                if !fields.is_empty() {
+                    return Err(EvalError::Unsupported(format!("variant payload pattern '{}(..)'", variant_name)));
+                }
+                let tag = self.module_env.find_enum_by_variant(variant_name)
+                    .and_then(|e| e.variants.iter().position(|v| v.name == *variant_name))
+                    .ok_or_else(|| EvalError::Unsupported(format!("unknown variant '{}'", variant_name)))?;
+                Ok(value.as_int() == Some(tag as i64))
+            }
+        }
+    }
+}
+
+// =============================================================================
+// 具体値の例によるテスト (mumei test)
+// =============================================================================
+//
+// test ブロックの `assert <式>` を上の評価器で実行する。Z3 / LLVM を使わないので、
+// 契約の証明より手前の素早い確認に使える。
+
+/// atom 以外に呼び出しとして書ける組み込みの名前（評価できるかは評価器が判定する）
+const BUILTIN_CALLS: &[&str] = &["sqrt", "approx_eq", "cast_to_int", "old", "len", "is_some", "is_none", "forall", "exists"];
+
+/// assert 1 つの結果
+#[derive(Debug, Clone, PartialEq)]
+pub enum AssertionOutcome {
+    Passed,
+    /// 偽に評価された。比較式なら両辺の値を添える
+    Failed(String),
+    /// 評価できなかった（未知の atom・`assert` のない文・実行時エラーなど）
+    Error(String),
+}
+
+/// test ブロックの文を順に評価し、(文, 結果) を返す。`assert` で始まらない文はエラーとして先に並べる
+pub fn run_test(test: &TestDef, module_env: &ModuleEnv) -> Vec<(String, AssertionOutcome)> {
+    let mut outcomes: Vec<(String, AssertionOutcome)> = test.malformed.iter()
+        .map(|statement| (statement.clone(), AssertionOutcome::Error("expected `assert <expression>`".to_string())))
+        .collect();
+    for source in &test.assertions {
+        outcomes.push((source.clone(), run_assertion(source, module_env)));
+    }
+    outcomes
+}
+
+fn run_assertion(source: &str, module_env: &ModuleEnv) -> AssertionOutcome {
+    let expr = match parse_source(source) {
+        Ok(expr) => expr,
+        Err(e) => return AssertionOutcome::Error(e.to_string()),
+    };
+    let calls: HashSet<String> = crate::verification::collect_callees(&expr).into_iter().collect();
+    let mut unknown: Vec<&String> = calls.iter()
+        .filter(|name| {
+            !BUILTIN_CALLS.contains(&name.as_str())
+                && module_env.get_atom(name).or_else(|| module_env.get_atom(&name.replace('.', "::"))).is_none()
+                && crate::theory::find_intrinsic(name).is_none()
+        })
+        .collect();
+    unknown.sort();
+    if let Some(name) = unknown.first() {
+        return AssertionOutcome::Error(format!("unknown atom '{}'", name));
+    }
+
+    match Interpreter::new(module_env).eval(&expr, &mut HashMap::new()) {
+        Ok(value) if value.truthy() => AssertionOutcome::Passed,
+        Ok(value) => match &expr {
+            // 比較なら両辺を評価し直して値を示す（失敗したときだけなので二重評価の負担は問題にならない）
+            Expr::BinaryOp(l, Op::Eq | Op::Neq | Op::Lt | Op::Gt | Op::Le | Op::Ge, r) => {
+                let side = |e: &Expr| Interpreter::new(module_env).eval(e, &mut HashMap::new())
+                    .map_or_else(|err| err.to_string(), |v| v.to_string());
+                AssertionOutcome::Failed(format!("left: {}, right: {}", side(l), side(r)))
+            }
+            _ => AssertionOutcome::Failed(format!("evaluated to {}", value)),
+        },
+        Err(e) => AssertionOutcome::Error(e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{parse_module, Item};
+    use crate::test_support::env_from_source;
+
+    fn run(module_env: &ModuleEnv, name: &str, args: &[Value]) -> EvalResult {
+        let atom = module_env.get_atom(name).cloned().unwrap();
+        Interpreter::new(module_env).call_atom(&atom, args)
+    }
+
+    #[test]
+    fn test_integer_semantics_follow_generated_code() {
+        let (_, module_env) = env_from_source(r#"
+atom quot(a: i64, b: i64) requires: b != 0; ensures: true; body: a / b;
+atom inc(a: i64) requires: true; ensures: true; body: a + 1;
+"#);
+        // 0 方向への切り捨て（Z3 の Int 除算とは負数で異なる）
+        assert_eq!(run(&module_env, "quot", &[Value::Int(-7), Value::Int(2)]), Ok(Value::Int(-3)));
+        assert!(matches!(run(&module_env, "quot", &[Value::Int(1), Value::Int(0)]), Err(EvalError::Runtime(_))));
+        assert!(matches!(run(&module_env, "inc", &[Value::Int(i64::MAX)]), Err(EvalError::Runtime(_))));
+    }
+
+    #[test]
+    fn test_loops_calls_and_match() {
+        let (_, module_env) = env_from_source(r#"
+atom sum_to(n: i64)
+requires: n >= 0;
+ensures: result >= 0;
+body: {
+    let s = 0;
+    let i = 0;
+    while i < n invariant: s >= 0 {
+        i = i + 1;
+        s = s + i;
+    };
+    s
+};
+
+atom classify(x: i64)
+requires: true;
+ensures: result >= 0;
+body: match x { n if n > 0 => 0, 0 => 1, _ => sum_to(2) };
+"#);
+        assert_eq!(run(&module_env, "sum_to", &[Value::Int(4)]), Ok(Value::Int(10)));
+        assert_eq!(run(&module_env, "classify", &[Value::Int(5)]), Ok(Value::Int(0)));
+        assert_eq!(run(&module_env, "classify", &[Value::Int(0)]), Ok(Value::Int(1)));
+        assert_eq!(run(&module_env, "classify", &[Value::Int(-5)]), Ok(Value::Int(3)));
+
+        let mut interp = Interpreter::new(&module_env);
+        let bindings: HashMap<String, Value> = [("result".to_string(), Value::Int(3))].into_iter().collect();
+        assert_eq!(interp.eval_contract("result >= 0 && result < 4", &bindings), Ok(true));
+    }
+
+    #[test]
+    fn test_run_test_assertions() {
+        let items = parse_module(r#"
+atom increment(x: i64) requires: x >= 0; ensures: result == x + 1; body: x + 1;
+
+test examples {
+    assert increment(1) == 2;
+    assert increment(1) == 3;
+    assert decrement(1) == 0;
+    assert increment(9223372036854775807) > 0;
+    increment(2);
+}
+"#).unwrap();
+        let mut module_env = ModuleEnv::new();
+        for item in &items {
+            if let Item::Atom(a) = item { module_env.register_atom(a); }
+        }
+        let test = items.iter().find_map(|i| if let Item::TestDef(t) = i { Some(t) } else { None }).unwrap();
+        let outcomes: Vec<AssertionOutcome> = run_test(test, &module_env).into_iter().map(|(_, o)| o).collect();
+        assert_eq!(outcomes[..4], [
+            AssertionOutcome::Error("expected `assert <expression>`".to_string()),
+            AssertionOutcome::Passed,
+            AssertionOutcome::Failed("left: 2, right: 3".to_string()),
+            AssertionOutcome::Error("unknown atom 'decrement'".to_string()),
+        ]);
+        assert!(matches!(&outcomes[4], AssertionOutcome::Error(msg) if msg.contains("integer overflow")), "{:?}", outcomes[4]);
+    }
+}
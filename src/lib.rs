@@ -0,0 +1,82 @@
+//! # Mumei
+//!
+//! 形式検証付き言語 Mumei のコンパイラ本体。`mumei` コマンド（main.rs）はこの crate の上の CLI 層で、
+//! ビルドスクリプトやテストハーネスからは crate のルートの API で同じ処理を呼べる（crate::api）。
+//!
+//! - [`parse`]: ソース文字列 → item
+//! - [`verify_items`] / [`verify_items_with`]: Z3 による検証。進捗は [`VerifyProgress`] に通知する
+//! - [`transpile_items`]: Rust / Go / TypeScript のバンドルへの変換
+//!
+//! どの関数もプロセスを終了させず、stdout / stderr にも出力しない。
+//!
+//! ```
+//! let source = "
+//! type Nat = i64 where v >= 0;
+//! atom inc(x: Nat) requires: true; ensures: result > x; body: x + 1;
+//! atom broken(x: i64) requires: true; ensures: result > x; body: x - 1;
+//! ";
+//! let items = mumei::parse(source).expect("the source parses");
+//!
+//! let verification = mumei::verify_items(&items, &mumei::VerifyOptions::default());
+//! assert!(!verification.passed());
+//! assert_eq!((verification.summary.verified, verification.summary.failed), (1, 1));
+//! let broken = verification.report.atoms.iter().find(|atom| atom.name == "broken").unwrap();
+//! assert_eq!(broken.status, mumei::report::AtomStatus::Failed);
+//!
+//! let rust = mumei::transpile_items(&items, mumei::TargetLanguage::Rust).expect("no import errors");
+//! assert!(rust.contains("pub fn inc("));
+//! ```
+
+// ライブラリ API が直接扱う型のモジュール
+pub mod parser;
+pub mod pipeline;
+pub mod report;
+pub mod theory;
+pub mod transpiler;
+pub mod verification;
+
+// CLI（main.rs）が使う内部モジュール。API としての互換性は保証しない
+#[doc(hidden)]
+pub mod ast;
+#[doc(hidden)]
+pub mod bench;
+#[doc(hidden)]
+pub mod codegen;
+#[doc(hidden)]
+pub mod interpreter;
+#[doc(hidden)]
+pub mod lockfile;
+#[doc(hidden)]
+pub mod lsp;
+#[doc(hidden)]
+#[allow(dead_code)]
+pub mod manifest;
+#[doc(hidden)]
+pub mod migrate;
+#[doc(hidden)]
+pub mod native;
+#[doc(hidden)]
+pub mod printer;
+#[doc(hidden)]
+pub mod query;
+#[doc(hidden)]
+pub mod registry;
+#[doc(hidden)]
+pub mod resolver;
+#[doc(hidden)]
+pub mod sarif;
+#[doc(hidden)]
+pub mod selftest;
+#[doc(hidden)]
+pub mod setup;
+#[doc(hidden)]
+pub mod tailrec;
+
+mod api;
+#[cfg(test)]
+mod test_support;
+
+pub use api::{parse, transpile_items, verify_items, verify_items_with, VerificationReport, VerifyOptions, VerifyProgress};
+pub use parser::{Item, ParseError};
+pub use pipeline::{Diagnostic, ManifestLookup, Stage, VerifyEvent, VerifySummary};
+pub use transpiler::TargetLanguage;
@@ -0,0 +1,142 @@
+//! # Lockfile モジュール
+//!
+//! `mumei.lock` の読み書き。git 依存ごとに解決したコミットハッシュを記録する。
+//! `mumei build` はマニフェストの tag / branch よりロックされたリビジョンを優先するので、
+//! 同じロックファイルからは同じソースでビルドされる（キャッシュ済みならネットワーク不要）。
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// ロックファイル名（mumei.toml と同じディレクトリに置く）
+pub const LOCKFILE_NAME: &str = "mumei.lock";
+
+const HEADER: &str = "# This file is generated by mumei. Do not edit it by hand.\n\n";
+
+/// mumei.lock 全体
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Lockfile {
+    /// ロックファイルの形式のバージョン
+    pub version: u32,
+    /// 依存ごとの解決結果（`[[package]]`）。名前順に保つ
+    #[serde(default, rename = "package")]
+    pub packages: Vec<LockedPackage>,
+}
+
+/// 1 つの git 依存の解決結果
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LockedPackage {
+    /// [dependencies] での依存名
+    pub name: String,
+    /// 取得元の git URL
+    pub git: String,
+    /// マニフェストで指定した参照（"tag:v1.0.0" / "branch:main" / "rev:abc123" / "HEAD"）
+    pub reference: String,
+    /// 解決したコミットハッシュ
+    pub rev: String,
+}
+
+impl Default for Lockfile {
+    fn default() -> Self {
+        Lockfile { version: 1, packages: Vec::new() }
+    }
+}
+
+/// プロジェクトのロックファイルのパス
+pub fn lockfile_path(project_dir: &Path) -> PathBuf {
+    project_dir.join(LOCKFILE_NAME)
+}
+
+/// マニフェストの git 依存の参照指定をロックファイルの reference の表記にする
+pub fn reference_of(tag: Option<&str>, rev: Option<&str>, branch: Option<&str>) -> String {
+    match (tag, rev, branch) {
+        (Some(tag), _, _) => format!("tag:{}", tag),
+        (None, Some(rev), _) => format!("rev:{}", rev),
+        (None, None, Some(branch)) => format!("branch:{}", branch),
+        (None, None, None) => "HEAD".to_string(),
+    }
+}
+
+impl Lockfile {
+    /// プロジェクトのロックファイルを読み込む。存在しなければ空のロックファイルを返す
+    pub fn load(project_dir: &Path) -> Result<Lockfile, String> {
+        let path = lockfile_path(project_dir);
+        match fs::read_to_string(&path) {
+            Ok(content) => toml::from_str(&content).map_err(|e| format!("Failed to parse {}: {}", path.display(), e)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Lockfile::default()),
+            Err(e) => Err(format!("Failed to read {}: {}", path.display(), e)),
+        }
+    }
+
+    /// プロジェクトにロックファイルを書き込む（一時ファイル + rename で原子的に置き換える）
+    pub fn save(&self, project_dir: &Path) -> Result<(), String> {
+        let path = lockfile_path(project_dir);
+        let body = toml::to_string(self).map_err(|e| format!("Failed to serialize {}: {}", LOCKFILE_NAME, e))?;
+        let tmp = path.with_extension(format!("lock.tmp.{}", std::process::id()));
+        fs::write(&tmp, format!("{}{}", HEADER, body)).map_err(|e| format!("Failed to write {}: {}", tmp.display(), e))?;
+        fs::rename(&tmp, &path).map_err(|e| {
+            let _ = fs::remove_file(&tmp);
+            format!("Failed to write {}: {}", path.display(), e)
+        })
+    }
+
+    /// 依存 name のロック済みリビジョン。URL か参照指定がマニフェストと食い違うエントリは使わない
+    pub fn locked_rev(&self, name: &str, git: &str, reference: &str) -> Option<&str> {
+        self.packages.iter()
+            .find(|p| p.name == name && p.git == git && p.reference == reference)
+            .map(|p| p.rev.as_str())
+    }
+
+    /// 依存 name の解決結果を記録する（既存のエントリは置き換える）
+    pub fn set(&mut self, name: &str, git: &str, reference: &str, rev: &str) {
+        let entry = LockedPackage { name: name.to_string(), git: git.to_string(), reference: reference.to_string(), rev: rev.to_string() };
+        match self.packages.iter_mut().find(|p| p.name == name) {
+            Some(existing) => *existing = entry,
+            None => {
+                self.packages.push(entry);
+                self.packages.sort_by(|a, b| a.name.cmp(&b.name));
+            }
+        }
+    }
+
+    /// 依存 name のエントリを削除する。削除したら true
+    pub fn remove(&mut self, name: &str) -> bool {
+        let before = self.packages.len();
+        self.packages.retain(|p| p.name != name);
+        self.packages.len() != before
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    #[test]
+    fn test_lockfile_round_trip() {
+        let dir = TempDir::new("lockfile");
+
+        // ロックファイルがなければ空
+        assert_eq!(Lockfile::load(&dir).unwrap(), Lockfile::default());
+
+        let mut lock = Lockfile::default();
+        lock.set("utils", "https://example.com/utils-mm", "tag:v1.0.0", "1111111111111111111111111111111111111111");
+        lock.set("math", "https://example.com/math-mm", "HEAD", "2222222222222222222222222222222222222222");
+        lock.save(&dir).unwrap();
+        let content = fs::read_to_string(lockfile_path(&dir)).unwrap();
+        assert!(content.starts_with("# This file is generated by mumei."), "{}", content);
+        assert!(content.contains("[[package]]\nname = \"math\""), "{}", content);
+
+        let loaded = Lockfile::load(&dir).unwrap();
+        assert_eq!(loaded, lock);
+        assert_eq!(loaded.locked_rev("utils", "https://example.com/utils-mm", "tag:v1.0.0"), Some("1111111111111111111111111111111111111111"));
+        // tag を変えた・URL を変えた依存はロックを使わずに解決し直す
+        assert_eq!(loaded.locked_rev("utils", "https://example.com/utils-mm", "tag:v2.0.0"), None);
+        assert_eq!(loaded.locked_rev("utils", "https://example.com/fork-mm", "tag:v1.0.0"), None);
+
+        let mut lock = loaded;
+        assert!(lock.remove("utils"));
+        assert!(!lock.remove("utils"));
+        assert_eq!(lock.packages.len(), 1);
+
+    }
+}
@@ -4,56 +4,289 @@
 //! JSON-RPC over stdio で Language Server Protocol を提供する。
 //!
 //! ## 対応機能（Phase 1: 最小実装）
-//! - `initialize` / `initialized` ハンドシェイク
-//! - `textDocument/didOpen` / `textDocument/didChange` → パースして diagnostics 送信
+//! - `initialize` / `initialized` ハンドシェイク（`initializationOptions` で設定を受け取る）
+//! - `textDocument/didOpen` / `didChange` / `didSave` → パース・Z3 検証して diagnostics 送信
+//!   （Z3 検証は 1 本のワーカースレッドで順に走らせ、URI ごとに最新の版だけを検証する。古い版の結果は捨てる）
+//! - `workspace/didChangeConfiguration` → 設定をその場で更新
+//! - `textDocument/rename` / `prepareRename` → atom の定義と全ファイルの呼び出しを書き換える WorkspaceEdit
+//! - `textDocument/references` / `workspace/symbol` → ワークスペースの索引から atom を探す
+//! - `textDocument/hover` → atom のシグネチャと契約、精緻型の基底型と述語
 //! - `shutdown` / `exit`
 //!
 //! ## 将来の拡張（Phase 2+）
-//! - `textDocument/hover` — atom の requires/ensures 表示
 //! - `textDocument/completion` — キーワード・atom 名補完
-//! - `textDocument/publishDiagnostics` — Z3 検証エラーのリアルタイム表示
 //! - `textDocument/definition` — 定義ジャンプ
 use std::collections::HashMap;
+use std::fs;
 use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, OnceLock};
+use regex::Regex;
+use serde_json::Value;
 use crate::parser;
+use crate::report::Severity;
+// =============================================================================
+// サーバー設定（initializationOptions / workspace/didChangeConfiguration）
+// =============================================================================
+//
+// JSON の形（キーは camelCase。snake_case も受け付ける。未指定・不正な値は既定値のまま）:
+//   { "stdPath": "/opt/mumei/std", "verifyMode": "off" | "onSave" | "onChange",
+//     "timeoutMs": 5000, "maxDiagnostics": 100, "maxDocumentBytes": 5242880 }
+// didChangeConfiguration では settings 直下、または settings.mumei に同じ形で渡す。
+// initialize の応答では capabilities.experimental.mumei に現在の設定を返す。
+
+/// Z3 検証を実行するタイミング
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VerifyMode {
+    /// 検証しない（パースエラーのみ）
+    Off,
+    /// textDocument/didSave でのみ検証する
+    OnSave,
+    /// didOpen / didChange のたびに検証する
+    OnChange,
+}
+
+impl VerifyMode {
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "off" => Some(VerifyMode::Off),
+            "onSave" | "on_save" => Some(VerifyMode::OnSave),
+            "onChange" | "on_change" => Some(VerifyMode::OnChange),
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            VerifyMode::Off => "off",
+            VerifyMode::OnSave => "onSave",
+            VerifyMode::OnChange => "onChange",
+        }
+    }
+}
+
+/// `mumei lsp` の設定
+#[derive(Debug, Clone, PartialEq)]
+pub struct LspConfig {
+    /// 標準ライブラリのディレクトリ（MUMEI_STD_PATH と同じ扱い）。None なら通常の探索順序
+    pub std_path: Option<PathBuf>,
+    pub verify_mode: VerifyMode,
+    /// atom 1 件あたりの Z3 タイムアウト（ミリ秒）
+    pub timeout_ms: u64,
+    /// 1 ファイルあたりに送る diagnostics の上限
+    pub max_diagnostics: usize,
+    /// これより大きいドキュメントはパース・検証せず、区切り記号の対応だけを検査する（バイト数）
+    pub max_document_bytes: usize,
+}
+
+/// max_document_bytes の既定値（5 MiB）
+pub const DEFAULT_MAX_DOCUMENT_BYTES: usize = 5 * 1024 * 1024;
+
+impl Default for LspConfig {
+    fn default() -> Self {
+        Self {
+            std_path: None,
+            verify_mode: VerifyMode::OnChange,
+            timeout_ms: 5000,
+            max_diagnostics: 100,
+            max_document_bytes: DEFAULT_MAX_DOCUMENT_BYTES,
+        }
+    }
+}
+
+impl LspConfig {
+    /// 設定 JSON を上書き適用する（指定されたキーだけを変更する）
+    pub fn merge(&mut self, options: &Value) {
+        let get = |camel: &str, snake: &str| options.get(camel).or_else(|| options.get(snake)).cloned();
+        if let Some(path) = get("stdPath", "std_path") {
+            self.std_path = path.as_str().filter(|p| !p.is_empty()).map(PathBuf::from);
+        }
+        if let Some(mode) = get("verifyMode", "verify_mode").as_ref().and_then(Value::as_str).and_then(VerifyMode::parse) {
+            self.verify_mode = mode;
+        }
+        if let Some(timeout) = get("timeoutMs", "timeout_ms").as_ref().and_then(Value::as_u64).filter(|t| *t > 0) {
+            self.timeout_ms = timeout;
+        }
+        if let Some(max) = get("maxDiagnostics", "max_diagnostics").as_ref().and_then(Value::as_u64) {
+            self.max_diagnostics = max as usize;
+        }
+        if let Some(max) = get("maxDocumentBytes", "max_document_bytes").as_ref().and_then(Value::as_u64).filter(|m| *m > 0) {
+            self.max_document_bytes = max as usize;
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        serde_json::json!({
+            "stdPath": self.std_path.as_ref().map(|p| p.display().to_string()),
+            "verifyMode": self.verify_mode.as_str(),
+            "timeoutMs": self.timeout_ms,
+            "maxDiagnostics": self.max_diagnostics,
+            "maxDocumentBytes": self.max_document_bytes,
+        })
+    }
+}
+
 // =============================================================================
 // メイン処理
 // =============================================================================
 /// `mumei lsp` のエントリポイント — stdio で JSON-RPC メッセージを処理
 pub fn run() {
     eprintln!("mumei-lsp: starting (stdio mode)...");
-    let stdin = io::stdin();
+    // stdin の読み取りとワーカースレッドの検証結果を 1 本のキューで受け、stdout への書き込みはこのスレッドだけが行う
+    let (events, inbox) = mpsc::channel();
+    let reader_events = events.clone();
+    std::thread::spawn(move || {
+        let stdin = io::stdin();
+        let mut reader = stdin.lock();
+        loop {
+            // LSP メッセージを読み取り
+            let message = match read_message(&mut reader) {
+                Ok(msg) => msg,
+                Err(e) => {
+                    eprintln!("mumei-lsp: read error: {}", e);
+                    let _ = reader_events.send(Event::Closed);
+                    break;
+                }
+            };
+            // JSON パース
+            match serde_json::from_str(&message) {
+                Ok(json) => {
+                    if reader_events.send(Event::Message(json)).is_err() {
+                        break;
+                    }
+                }
+                Err(e) => eprintln!("mumei-lsp: JSON parse error: {}", e),
+            }
+        }
+    });
     let stdout = io::stdout();
-    let mut reader = stdin.lock();
     let mut writer = stdout.lock();
-    // ファイル URI → ソースコード のキャッシュ
-    let mut documents: HashMap<String, String> = HashMap::new();
-    loop {
-        // LSP メッセージを読み取り
-        let message = match read_message(&mut reader) {
-            Ok(msg) => msg,
-            Err(e) => {
-                eprintln!("mumei-lsp: read error: {}", e);
+    let mut server = Server { worker: Some(spawn_verifier(events)), ..Server::default() };
+    for event in inbox {
+        if !server.handle_event(event, &mut writer) {
+            break;
+        }
+    }
+}
+
+/// run のイベントループが受け取るもの
+enum Event {
+    /// クライアントからのメッセージ
+    Message(Value),
+    /// stdin の終わり（読み取りエラーを含む）
+    Closed,
+    /// ワーカースレッドの Z3 検証の結果。revision は検証したドキュメントの版
+    Verified { uri: String, revision: u64, diagnostics: Vec<Value> },
+}
+
+/// ワーカースレッドへの Z3 検証の依頼
+struct VerifyRequest {
+    uri: String,
+    revision: u64,
+    source: String,
+    workspace_roots: Vec<PathBuf>,
+    std_path: Option<PathBuf>,
+    timeout_ms: u64,
+}
+
+/// Z3 検証のワーカースレッドを起動し、依頼を送るキューを返す。
+/// 検証は 1 本のスレッドで順に行うため、スレッドのソルバプール（Context / Solver）を使い回す。
+/// 検証中に溜まった依頼は URI ごとに最新の版だけを残し、途中の版は検証しない。
+/// 結果は events に Event::Verified で送る。キューが閉じられたら終了する
+fn spawn_verifier(events: mpsc::Sender<Event>) -> mpsc::Sender<VerifyRequest> {
+    let (requests, queue) = mpsc::channel::<VerifyRequest>();
+    std::thread::spawn(move || {
+        let mut pending: Vec<VerifyRequest> = Vec::new();
+        loop {
+            if pending.is_empty() {
+                match queue.recv() {
+                    Ok(request) => pending.push(request),
+                    Err(_) => break,
+                }
+            }
+            while let Ok(request) = queue.try_recv() {
+                supersede(&mut pending, request);
+            }
+            let request = pending.remove(0);
+            let diagnostics = verify_document(&request.uri, &request.source, &request.workspace_roots, request.std_path.as_deref(), request.timeout_ms);
+            if events.send(Event::Verified { uri: request.uri, revision: request.revision, diagnostics }).is_err() {
                 break;
             }
-        };
-        // JSON パース
-        let json: serde_json::Value = match serde_json::from_str(&message) {
-            Ok(v) => v,
-            Err(e) => {
-                eprintln!("mumei-lsp: JSON parse error: {}", e);
-                continue;
+        }
+    });
+    requests
+}
+
+/// 未処理の依頼に request を加える。同じ URI の古い依頼は取り除く
+fn supersede(pending: &mut Vec<VerifyRequest>, request: VerifyRequest) {
+    pending.retain(|p| p.uri != request.uri);
+    pending.push(request);
+}
+
+/// サーバーの状態
+#[derive(Default)]
+struct Server {
+    config: LspConfig,
+    /// Z3 検証のワーカースレッドへの依頼キュー。None（テスト）ならメッセージの処理中にその場で検証する
+    worker: Option<mpsc::Sender<VerifyRequest>>,
+    /// ファイル URI → ドキュメントの版（didOpen / didChange / didSave のたびに進める）。
+    /// ワーカーの結果はこの版が変わっていなければ採用する
+    revisions: HashMap<String, u64>,
+    /// workspaceFolders（なければ rootUri）のディレクトリ。mumei.toml の探索に使う
+    workspace_roots: Vec<PathBuf>,
+    /// ファイル URI → ソースコード のキャッシュ
+    documents: HashMap<String, String>,
+    /// ファイル URI → 最後に実行した Z3 検証の diagnostics（onSave では編集中も保存時の結果を出し続ける）
+    verification: HashMap<String, Vec<Value>>,
+    /// ファイル URI → atom の定義・呼び出し・import の索引（開いているドキュメントとワークスペースの .mm）
+    index: HashMap<String, FileSymbols>,
+    /// ファイル URI → 最後にパースできた item（hover で契約・型定義を引く）
+    items: HashMap<String, Vec<parser::Item>>,
+}
+
+impl Server {
+    /// イベント 1 件を処理する。終了するなら false を返す
+    fn handle_event(&mut self, event: Event, writer: &mut impl Write) -> bool {
+        match event {
+            Event::Message(json) => self.handle(&json, writer),
+            Event::Closed => false,
+            Event::Verified { uri, revision, diagnostics } => {
+                self.finish_verification(writer, &uri, revision, diagnostics);
+                true
             }
-        };
+        }
+    }
+
+    /// メッセージ 1 件を処理する。exit を受け取ったら false を返す
+    fn handle(&mut self, json: &Value, writer: &mut impl Write) -> bool {
         let method = json.get("method").and_then(|m| m.as_str()).unwrap_or("");
         let id = json.get("id").cloned();
+        let params = json.get("params");
+        let uri_of = |params: Option<&Value>| {
+            params.and_then(|p| p.get("textDocument")).and_then(|td| td.get("uri")).and_then(|u| u.as_str()).map(str::to_string)
+        };
+        let position_of = |params: Option<&Value>| {
+            let position = params?.get("position")?;
+            Some((uri_of(params)?, position.get("line")?.as_u64()? as usize, position.get("character")?.as_u64()? as usize))
+        };
         match method {
             "initialize" => {
+                if let Some(params) = params {
+                    self.initialize(params);
+                }
                 let result = serde_json::json!({
                     "capabilities": {
-                        "textDocumentSync": 1,
+                        "textDocumentSync": {
+                            "openClose": true,
+                            "change": 1,
+                            "save": { "includeText": true }
+                        },
                         "hoverProvider": true,
-                        "completionProvider": null
+                        "renameProvider": { "prepareProvider": true },
+                        "referencesProvider": true,
+                        "workspaceSymbolProvider": true,
+                        "completionProvider": null,
+                        "experimental": { "mumei": self.config.to_json() }
                     },
                     "serverInfo": {
                         "name": "mumei-lsp",
@@ -61,63 +294,67 @@ pub fn run() {
                     }
                 });
                 if let Some(id) = id {
-                    send_response(&mut writer, id, result);
+                    send_response(writer, id, result);
                 }
             }
             "initialized" => {
                 eprintln!("mumei-lsp: initialized");
             }
+            "workspace/didChangeConfiguration" => {
+                if let Some(settings) = params.and_then(|p| p.get("settings")) {
+                    self.set_config(settings.get("mumei").unwrap_or(settings));
+                    eprintln!("mumei-lsp: configuration updated ({})", self.config.to_json());
+                }
+            }
             "textDocument/didOpen" => {
-                if let Some(params) = json.get("params") {
-                    if let Some(td) = params.get("textDocument") {
-                        let uri = td.get("uri").and_then(|u| u.as_str()).unwrap_or("");
-                        let text = td.get("text").and_then(|t| t.as_str()).unwrap_or("");
-                        documents.insert(uri.to_string(), text.to_string());
-                        let diagnostics = diagnose(uri, text);
-                        send_diagnostics(&mut writer, uri, &diagnostics);
-                    }
+                if let Some(td) = params.and_then(|p| p.get("textDocument")) {
+                    let uri = td.get("uri").and_then(|u| u.as_str()).unwrap_or("");
+                    let text = parser::normalize_source(td.get("text").and_then(|t| t.as_str()).unwrap_or(""));
+                    self.publish(writer, uri, text, false);
                 }
             }
             "textDocument/didChange" => {
-                if let Some(params) = json.get("params") {
-                    if let Some(td) = params.get("textDocument") {
-                        let uri = td.get("uri").and_then(|u| u.as_str()).unwrap_or("");
-                        // contentChanges[0].text (full sync mode)
-                        if let Some(changes) = params.get("contentChanges").and_then(|c| c.as_array()) {
-                            if let Some(change) = changes.first() {
-                                if let Some(text) = change.get("text").and_then(|t| t.as_str()) {
-                                    documents.insert(uri.to_string(), text.to_string());
-                                    let diagnostics = diagnose(uri, text);
-                                    send_diagnostics(&mut writer, uri, &diagnostics);
-                                }
-                            }
-                        }
+                if let Some(uri) = uri_of(params) {
+                    // contentChanges[0].text (full sync mode)
+                    let text = params.and_then(|p| p.get("contentChanges")).and_then(|c| c.as_array())
+                        .and_then(|changes| changes.first())
+                        .and_then(|change| change.get("text")).and_then(|t| t.as_str());
+                    if let Some(text) = text {
+                        self.publish(writer, &uri, parser::normalize_source(text), false);
                     }
                 }
             }
-            "textDocument/didClose" => {
-                if let Some(params) = json.get("params") {
-                    if let Some(td) = params.get("textDocument") {
-                        let uri = td.get("uri").and_then(|u| u.as_str()).unwrap_or("");
-                        documents.remove(uri);
-                        // diagnostics をクリア
-                        send_diagnostics(&mut writer, uri, &[]);
+            "textDocument/didSave" => {
+                if let Some(uri) = uri_of(params) {
+                    // includeText が有効なら保存内容、なければ didChange で受け取った内容を使う
+                    let text = params.and_then(|p| p.get("text")).and_then(|t| t.as_str())
+                        .map(parser::normalize_source)
+                        .or_else(|| self.documents.get(&uri).cloned());
+                    if let Some(text) = text {
+                        self.publish(writer, &uri, text, true);
                     }
                 }
             }
-            "textDocument/hover" => {
-                // 簡易 hover: カーソル行付近の `atom <name>(...)` を探索し、契約を表示
-                let hover_result = if let Some(params) = json.get("params") {
-                    let uri = params.get("textDocument").and_then(|td| td.get("uri")).and_then(|u| u.as_str()).unwrap_or("");
-                    let line = params.get("position").and_then(|p| p.get("line")).and_then(|l| l.as_u64()).unwrap_or(0) as usize;
-                    if let Some(text) = documents.get(uri) {
-                        build_hover(text, line)
+            "textDocument/didClose" => {
+                if let Some(uri) = uri_of(params) {
+                    self.documents.remove(&uri);
+                    self.revisions.remove(&uri);
+                    self.verification.remove(&uri);
+                    self.items.remove(&uri);
+                    // ワークスペース内のファイルは索引をディスク上の内容に戻す
+                    let in_workspace = uri_to_path(&uri).map_or(false, |path| self.workspace_roots.iter().any(|root| path.starts_with(root)));
+                    if in_workspace {
+                        self.index_from_disk(&uri);
                     } else {
-                        None
+                        self.index.remove(&uri);
                     }
-                } else {
-                    None
-                };
+                    // diagnostics をクリア
+                    send_diagnostics(writer, &uri, &[]);
+                }
+            }
+            "textDocument/hover" => {
+                // カーソル下の atom 名（定義・呼び出し）なら契約、精緻型名なら基底型と述語。それ以外は null
+                let hover_result = position_of(params).and_then(|(uri, line, character)| self.hover(&uri, line, character));
 
                 let result = if let Some(contents) = hover_result {
                     serde_json::json!({
@@ -127,39 +364,195 @@ pub fn run() {
                         }
                     })
                 } else {
-                    serde_json::Value::Null
+                    Value::Null
                 };
 
                 if let Some(id) = id {
-                    send_response(&mut writer, id, result);
+                    send_response(writer, id, result);
+                }
+            }
+            "textDocument/prepareRename" => {
+                // 名前部分の範囲と現在の名前。atom でなければ null（エディタが rename できない旨を表示する）
+                let result = position_of(params)
+                    .and_then(|(uri, line, character)| self.symbol_at(&uri, line, character))
+                    .map_or(Value::Null, |(_, name, span)| serde_json::json!({ "range": span.to_json(), "placeholder": name }));
+                if let Some(id) = id {
+                    send_response(writer, id, result);
+                }
+            }
+            "textDocument/rename" => {
+                let new_name = params.and_then(|p| p.get("newName")).and_then(|n| n.as_str()).unwrap_or("");
+                let result = position_of(params)
+                    .ok_or_else(|| "rename requires textDocument and position".to_string())
+                    .and_then(|(uri, line, character)| self.rename(&uri, line, character, new_name));
+                if let Some(id) = id {
+                    match result {
+                        Ok(edit) => send_response(writer, id, edit),
+                        Err(message) => send_error(writer, id, -32602, &message),
+                    }
+                }
+            }
+            "textDocument/references" => {
+                let include_declaration = params.and_then(|p| p.get("context")).and_then(|c| c.get("includeDeclaration"))
+                    .and_then(|i| i.as_bool()).unwrap_or(true);
+                let locations: Vec<Value> = position_of(params)
+                    .and_then(|(uri, line, character)| self.symbol_at(&uri, line, character))
+                    .map(|(def_uri, name, _)| self.occurrences(&def_uri, &name, include_declaration))
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|(uri, span)| serde_json::json!({ "uri": uri, "range": span.to_json() }))
+                    .collect();
+                if let Some(id) = id {
+                    send_response(writer, id, Value::Array(locations));
+                }
+            }
+            "workspace/symbol" => {
+                let query = params.and_then(|p| p.get("query")).and_then(|q| q.as_str()).unwrap_or("");
+                if let Some(id) = id {
+                    send_response(writer, id, Value::Array(self.workspace_symbols(query)));
                 }
             }
             "shutdown" => {
                 eprintln!("mumei-lsp: shutdown requested");
                 if let Some(id) = id {
-                    send_response(&mut writer, id, serde_json::Value::Null);
+                    send_response(writer, id, Value::Null);
                 }
             }
             "exit" => {
                 eprintln!("mumei-lsp: exit");
-                break;
+                return false;
             }
             _ => {
                 // 未対応メソッド — リクエストなら MethodNotFound を返す
                 if let Some(id) = id {
-                    send_error(&mut writer, id, -32601, &format!("Method not found: {}", method));
+                    send_error(writer, id, -32601, &format!("Method not found: {}", method));
+                }
+            }
+        }
+        true
+    }
+
+    /// initialize の params から設定とワークスペースを読み取る
+    fn initialize(&mut self, params: &Value) {
+        if let Some(options) = params.get("initializationOptions") {
+            self.set_config(options);
+        }
+        let folders: Vec<&str> = params.get("workspaceFolders").and_then(|f| f.as_array())
+            .map(|folders| folders.iter().filter_map(|f| f.get("uri").and_then(|u| u.as_str())).collect())
+            .unwrap_or_default();
+        let roots: Vec<&str> = if folders.is_empty() {
+            params.get("rootUri").and_then(|u| u.as_str()).into_iter().collect()
+        } else {
+            folders
+        };
+        self.workspace_roots = roots.into_iter().filter_map(uri_to_path).collect();
+        self.index_workspace();
+    }
+
+    fn set_config(&mut self, options: &Value) {
+        self.config.merge(options);
+    }
+
+    /// ドキュメントを更新して diagnostics を送信する。
+    /// Z3 検証は verify_mode と契機（保存かどうか）が一致した場合のみ実行し、
+    /// それ以外は前回の検証結果をそのまま添える。ワーカーがあれば検証はワーカースレッドに任せ、
+    /// 結果が届くまでは前回の結果を出す
+    fn publish(&mut self, writer: &mut impl Write, uri: &str, text: String, saved: bool) {
+        let revision = self.revisions.get(uri).map_or(1, |r| r + 1);
+        self.revisions.insert(uri.to_string(), revision);
+        // 上限を超えるドキュメントはパース・Z3 検証をせず、軽量な構文チェックだけを行う
+        if text.len() > self.config.max_document_bytes {
+            let mut diagnostics = oversized_diagnostics(&text, self.config.max_document_bytes);
+            diagnostics.truncate(self.config.max_diagnostics);
+            self.verification.remove(uri);
+            self.index.remove(uri);
+            self.items.remove(uri);
+            self.documents.insert(uri.to_string(), text);
+            send_diagnostics(writer, uri, &diagnostics);
+            return;
+        }
+        let run_verification = match self.config.verify_mode {
+            VerifyMode::Off => false,
+            VerifyMode::OnSave => saved,
+            VerifyMode::OnChange => !saved,
+        };
+        let parsed = parser::parse_module(&text);
+        let mut diagnostics = parse_diagnostics(&text, &parsed);
+        if diagnostics.is_empty() && self.config.verify_mode != VerifyMode::Off {
+            if run_verification {
+                match &self.worker {
+                    Some(worker) => {
+                        let _ = worker.send(VerifyRequest {
+                            uri: uri.to_string(),
+                            revision,
+                            source: text.clone(),
+                            workspace_roots: self.workspace_roots.clone(),
+                            std_path: self.config.std_path.clone(),
+                            timeout_ms: self.config.timeout_ms,
+                        });
+                    }
+                    None => {
+                        let verified = verify_document(uri, &text, &self.workspace_roots, self.config.std_path.as_deref(), self.config.timeout_ms);
+                        self.verification.insert(uri.to_string(), verified);
+                    }
                 }
             }
+            diagnostics.extend(self.verification.get(uri).cloned().unwrap_or_default());
         }
+        diagnostics.truncate(self.config.max_diagnostics);
+        self.index.insert(uri.to_string(), index_source(uri, &text));
+        // パースできなかった編集中は、直前にパースできた item を hover に使い続ける
+        if let Ok(items) = parsed {
+            self.items.insert(uri.to_string(), items);
+        }
+        self.documents.insert(uri.to_string(), text);
+        send_diagnostics(writer, uri, &diagnostics);
+    }
+
+    /// ワーカースレッドの検証結果を受け取る。検証した後にドキュメントが変わっていれば
+    /// （新しい didChange / didSave が届いた・閉じられた・検証を切った）結果を捨てる
+    fn finish_verification(&mut self, writer: &mut impl Write, uri: &str, revision: u64, diagnostics: Vec<Value>) {
+        if self.revisions.get(uri) != Some(&revision) || self.config.verify_mode == VerifyMode::Off {
+            eprintln!("mumei-lsp: discarding stale verification result for {} (revision {})", uri, revision);
+            return;
+        }
+        // 検証したのはパースできた版なので、この版の diagnostics は検証結果だけになる
+        let mut published = diagnostics.clone();
+        published.truncate(self.config.max_diagnostics);
+        self.verification.insert(uri.to_string(), diagnostics);
+        send_diagnostics(writer, uri, &published);
+    }
+}
+
+/// Z3 検証 diagnostics（file:// URI の場合のみ実行）
+fn verify_document(uri: &str, source: &str, workspace_roots: &[PathBuf], std_path: Option<&Path>, timeout_ms: u64) -> Vec<Value> {
+    match uri_to_path(uri) {
+        Some(path) => verification_diagnostics(source, &verify_source_for_lsp(&path, source, workspace_roots, std_path, timeout_ms)),
+        None => Vec::new(),
     }
 }
 // =============================================================================
 // 診断（パースエラー検出）
 // =============================================================================
-/// ソースコードをパースして diagnostics を生成
-fn diagnose(uri: &str, source: &str) -> Vec<serde_json::Value> {
-    // Phase 1: パースできるか
-    let items = parser::parse_module(source);
+/// パース結果から diagnostics を生成（Z3 検証は Server::verify_diagnostics）
+fn parse_diagnostics(source: &str, parsed: &Result<Vec<parser::Item>, parser::ParseError>) -> Vec<Value> {
+    let items = match parsed {
+        Ok(items) => items,
+        Err(e) => {
+            // 構文エラーの箇所（トークン）を range にする。入力の末尾では 1 文字分
+            let (line, character) = (e.line - 1, e.column - 1);
+            let width = e.width(source);
+            return vec![serde_json::json!({
+                "range": {
+                    "start": { "line": line, "character": character },
+                    "end": { "line": line, "character": character + width }
+                },
+                "severity": 1,
+                "source": "mumei",
+                "message": format!("Parse error: {}", e.message)
+            })];
+        }
+    };
     let mut diagnostics = Vec::new();
 
     // ソースが空でない場合にアイテムが0個 → パースエラーの可能性
@@ -174,120 +567,725 @@ fn diagnose(uri: &str, source: &str) -> Vec<serde_json::Value> {
             "source": "mumei",
             "message": "Parse error: no valid items found. Check syntax."
         }));
-        return diagnostics;
     }
+    diagnostics
+}
 
-    // Phase 2: Z3 検証 diagnostics（file:// URI の場合のみ実行）
-    if let Some(path) = uri_to_path(uri) {
-        if let Err(msg) = verify_source_for_lsp(&path, source) {
-            diagnostics.push(serde_json::json!({
-                "range": {
-                    "start": { "line": 0, "character": 0 },
-                    "end": { "line": 0, "character": 1 }
-                },
-                "severity": 1,
-                "source": "mumei-z3",
-                "message": msg
-            }));
+/// 上限を超えるドキュメントの diagnostics: 検証を無効にした旨と、区切り記号 `()[]{}` の対応の誤り。
+/// パーサを通さず 1 回の走査で済ませる（文字列リテラルと行コメントの中は数えない）
+fn oversized_diagnostics(source: &str, limit: usize) -> Vec<Value> {
+    let diagnostic = |line: usize, character: usize, severity: u64, message: String| serde_json::json!({
+        "range": {
+            "start": { "line": line, "character": character },
+            "end": { "line": line, "character": character + 1 }
+        },
+        "severity": severity,
+        "source": "mumei",
+        "message": message
+    });
+    let mut diagnostics = vec![diagnostic(0, 0, 2, format!(
+        "Document is {} bytes, over the {}-byte limit (maxDocumentBytes): full parsing and verification are disabled for this file; only delimiter balance is checked",
+        source.len(), limit
+    ))];
+
+    let mut open: Vec<(char, usize, usize)> = Vec::new();
+    let (mut line, mut character) = (0, 0);
+    let (mut in_string, mut in_comment, mut escaped) = (false, false, false);
+    let mut chars = source.chars().peekable();
+    while let Some(c) = chars.next() {
+        let (at_line, at_char) = (line, character);
+        if c == '\n' {
+            line += 1;
+            character = 0;
+            in_comment = false;
+            in_string = false;
+            continue;
+        }
+        character += 1;
+        if in_comment {
+            continue;
+        }
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' => in_string = true,
+            '/' if chars.peek() == Some(&'/') => in_comment = true,
+            '(' | '[' | '{' => open.push((c, at_line, at_char)),
+            ')' | ']' | '}' => {
+                let expected = match c { ')' => '(', ']' => '[', _ => '{' };
+                match open.pop() {
+                    Some((o, _, _)) if o == expected => {}
+                    Some((o, l, ch)) => {
+                        diagnostics.push(diagnostic(at_line, at_char, 1, format!("Syntax error: '{}' does not match '{}' opened at line {}:{}", c, o, l + 1, ch + 1)));
+                        return diagnostics;
+                    }
+                    None => {
+                        diagnostics.push(diagnostic(at_line, at_char, 1, format!("Syntax error: unmatched '{}'", c)));
+                        return diagnostics;
+                    }
+                }
+            }
+            _ => {}
         }
     }
-
+    if let Some((o, l, ch)) = open.pop() {
+        diagnostics.push(diagnostic(l, ch, 1, format!("Syntax error: '{}' is never closed", o)));
+    }
     diagnostics
 }
 
-fn uri_to_path(uri: &str) -> Option<std::path::PathBuf> {
+fn uri_to_path(uri: &str) -> Option<PathBuf> {
     if let Some(rest) = uri.strip_prefix("file://") {
-        Some(std::path::PathBuf::from(rest))
+        Some(PathBuf::from(percent_decode(rest)))
     } else {
         None
     }
 }
 
-/// ソースコードを in-process でパース → Z3 検証し、最初のエラーを返す。
-/// mumei.toml を上方探索してプロジェクトルートを決定し、依存パッケージも解決する。
-fn verify_source_for_lsp(path: &std::path::Path, source: &str) -> Result<(), String> {
-    use crate::verification;
-
-    let items = crate::parser::parse_module(source);
-    if items.is_empty() {
-        return Ok(());
+/// URI のパス部分の `%XX` をバイトに戻す（不正な並びはそのまま残す）
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = (bytes[i] == b'%').then(|| text.get(i + 1..i + 3)).flatten().and_then(|h| u8::from_str_radix(h, 16).ok());
+        match hex {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
     }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
 
-    let mut module_env = verification::ModuleEnv::new();
-    verification::register_builtin_traits(&mut module_env);
+/// Z3 検証の段階で見つかった問題 1 件
+#[derive(Debug, Clone, PartialEq)]
+struct VerifyIssue {
+    /// Error（検証の失敗・モジュールを準備できない）か Warning（prelude・依存の読み込み・atom の検証の警告など）
+    severity: Severity,
+    /// 検証に失敗した atom（モジュールの準備段階の問題なら None）
+    atom: Option<String>,
+    message: String,
+}
 
-    // mumei.toml を探してプロジェクトルートを決定
-    let base_dir = path.parent().unwrap_or(std::path::Path::new("."));
-    let _ = crate::resolver::resolve_prelude(base_dir, &mut module_env);
+/// ソースコードを in-process でパース → Z3 検証し、失敗したすべての atom と準備段階の警告を返す。
+/// 読み込み・登録は CLI と同じ pipeline::prepare_module を使う（出力も終了もしない）。
+/// mumei.toml をファイルの位置から上方探索し（見つからなければワークスペースのルートから）、
+/// プロジェクトルートを決定して依存パッケージも解決する。
+fn verify_source_for_lsp(path: &Path, source: &str, workspace_roots: &[PathBuf], std_path: Option<&Path>, timeout_ms: u64) -> Vec<VerifyIssue> {
+    use crate::pipeline::{self, ManifestLookup, PrepareOptions};
+    use crate::verification;
 
-    // mumei.toml があれば依存パッケージも解決（ジャンプ先の定義が利用可能になる）
-    if let Some((proj_dir, manifest)) = crate::manifest::find_and_load() {
-        let _ = crate::resolver::resolve_manifest_dependencies(&manifest, &proj_dir, &mut module_env);
+    if crate::parser::parse_module(source).map_or(true, |items| items.is_empty()) {
+        return Vec::new();
     }
 
-    let _ = crate::resolver::resolve_imports(&items, base_dir, &mut module_env);
-
-    for item in &items {
-        match item {
-            crate::parser::Item::TypeDef(t) => module_env.register_type(t),
-            crate::parser::Item::StructDef(s) => module_env.register_struct(s),
-            crate::parser::Item::EnumDef(e) => module_env.register_enum(e),
-            crate::parser::Item::Atom(a) => module_env.register_atom(a),
-            crate::parser::Item::TraitDef(t) => module_env.register_trait(t),
-            crate::parser::Item::ImplDef(i) => module_env.register_impl(i),
-            crate::parser::Item::ResourceDef(r) => module_env.register_resource(r),
-            crate::parser::Item::Import(_) => {}
-        }
-    }
+    let base_dir = path.parent().unwrap_or(Path::new("."));
+    let mut manifest_dirs = vec![base_dir.to_path_buf()];
+    manifest_dirs.extend(workspace_roots.iter().cloned());
+    let options = PrepareOptions {
+        source: Some(source.to_string()),
+        manifest: ManifestLookup::From(manifest_dirs),
+        std_path: std_path.map(Path::to_path_buf),
+    };
+    // エラーと警告だけをエディタに出す（キャッシュの刈り込みなどの Info は出さない）
+    let module_issues = |diagnostics: &[pipeline::Diagnostic]| -> Vec<VerifyIssue> {
+        diagnostics.iter()
+            .filter(|d| matches!(d.severity, Severity::Error | Severity::Warning))
+            .map(|d| VerifyIssue { severity: d.severity, atom: None, message: d.message.clone() })
+            .collect()
+    };
+    let pipeline::PreparedModule { items, mut module_env, diagnostics, .. } = match pipeline::prepare_module(path, &options) {
+        Ok(prepared) => prepared,
+        Err(diagnostics) => return module_issues(&diagnostics),
+    };
+    let mut issues = module_issues(&diagnostics);
 
-    let output_dir = std::path::Path::new(".");
+    // 検証レポートはエディタのカレントディレクトリではなく一時ディレクトリに書く。
+    // 失敗した atom があっても残りの atom の検証を続ける
+    let output_dir = std::env::temp_dir().join("mumei-lsp");
     for item in &items {
         if let crate::parser::Item::Atom(atom) = item {
             if module_env.is_verified(&atom.name) {
                 continue;
             }
-            if let Err(e) = verification::verify_with_config(atom, output_dir, &module_env, 5000, 3) {
-                return Err(format!("atom '{}': {}", atom.name, e));
+            match verification::verify_with_config(atom, &output_dir, &module_env, timeout_ms, 3) {
+                Ok(warnings) => {
+                    module_env.mark_verified(&atom.name);
+                    issues.extend(warnings.into_iter().map(|warning| VerifyIssue {
+                        severity: Severity::Warning,
+                        atom: Some(atom.name.clone()),
+                        message: format!("atom '{}': {}", atom.name, warning),
+                    }));
+                }
+                Err(e) => issues.push(VerifyIssue {
+                    severity: Severity::Error,
+                    atom: Some(atom.name.clone()),
+                    message: format!("atom '{}': {}", atom.name, e),
+                }),
             }
-            module_env.mark_verified(&atom.name);
         }
     }
+    issues
+}
+
+/// 検証の問題を diagnostics にする。atom の失敗は ensures 節（呼び出し先の requires 違反なら
+/// その呼び出し式）に、準備段階の問題は先頭行に付ける
+fn verification_diagnostics(source: &str, issues: &[VerifyIssue]) -> Vec<Value> {
+    issues.iter().map(|issue| {
+        let range = issue.atom.as_deref()
+            .and_then(|atom| failure_range(source, atom, &issue.message))
+            .unwrap_or_else(|| serde_json::json!({
+                "start": { "line": 0, "character": 0 },
+                "end": { "line": 0, "character": 1 }
+            }));
+        serde_json::json!({
+            "range": range,
+            "severity": if issue.severity == Severity::Error { 1 } else { 2 },
+            "source": "mumei-z3",
+            "message": issue.message
+        })
+    }).collect()
+}
+
+/// 検証に失敗した atom の diagnostic の範囲。message が呼び出し先の requires 違反
+/// （`Call to 'f': precondition ...`）なら body 内の最初の `f(...)` の呼び出し式、
+/// それ以外は `ensures:` 節（`;` の手前まで）。節がなければ atom 名。atom が見つからなければ None
+fn failure_range(source: &str, atom: &str, message: &str) -> Option<Value> {
+    static ATOM: OnceLock<Regex> = OnceLock::new();
+    static BODY: OnceLock<Regex> = OnceLock::new();
+    static ENSURES: OnceLock<Regex> = OnceLock::new();
+    let atom_re = ATOM.get_or_init(|| Regex::new(r"\batom\s+([A-Za-z_]\w*)").unwrap());
+    let code = mask_comments_and_strings(source);
+    let definition = atom_re.captures_iter(&code).filter_map(|c| c.get(1)).find(|m| m.as_str() == atom)?;
+    // atom の範囲は次の atom 定義の手前まで
+    let end = atom_re.find_at(&code, definition.end()).map_or(code.len(), |m| m.start());
+    let base = definition.end();
+    let text = &code[base..end];
+    // 範囲（ソース全体のバイト位置）
+    let range = |start: usize, end: usize| {
+        let (start_line, start_character) = offset_position(source, start);
+        let (end_line, end_character) = offset_position(source, end);
+        serde_json::json!({
+            "start": { "line": start_line, "character": start_character },
+            "end": { "line": end_line, "character": end_character }
+        })
+    };
+
+    let callee = message.split_once("Call to '").and_then(|(_, rest)| rest.split_once('\'')).map(|(name, _)| name);
+    if let Some(callee) = callee {
+        let body = BODY.get_or_init(|| Regex::new(r"\bbody\s*:").unwrap()).find(text).map_or(0, |m| m.end());
+        if let Some(call) = find_call(text, body, callee) {
+            // 対応する `)` まで（閉じていなければ名前だけ）
+            let mut depth = 0;
+            let close = text[call.end - 1..].char_indices().find_map(|(i, c)| {
+                match c {
+                    '(' => depth += 1,
+                    ')' => depth -= 1,
+                    _ => {}
+                }
+                (depth == 0).then_some(call.end + i)
+            });
+            return Some(range(base + call.start, base + close.unwrap_or(call.start + callee.len())));
+        }
+    }
+    match ENSURES.get_or_init(|| Regex::new(r"\bensures\s*:").unwrap()).find(text) {
+        Some(clause) => {
+            let clause_end = text[clause.end()..].find(';').map_or(text.len(), |i| clause.end() + i);
+            Some(range(base + clause.start(), base + clause.start() + text[clause.start()..clause_end].trim_end().len()))
+        }
+        None => Some(range(definition.start(), definition.end())),
+    }
+}
+
+/// text の from 以降で最初の `callee(` 呼び出し（名前の先頭から `(` の直後まで）。
+/// 前後が識別子の一部なら呼び出しとみなさない
+fn find_call(text: &str, from: usize, callee: &str) -> Option<std::ops::Range<usize>> {
+    let is_word = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    text[from..].match_indices(callee).map(|(i, _)| from + i).find_map(|start| {
+        let after = &text[start + callee.len()..];
+        let open = after.len() - after.trim_start().len();
+        let bounded = !text[..start].ends_with(is_word) && !after.starts_with(is_word) && after[open..].starts_with('(');
+        bounded.then(|| start..start + callee.len() + open + 1)
+    })
+}
 
-    Ok(())
+/// カーソル位置の識別子（line / character は LSP の位置。識別子の直後にカーソルがある場合も含む）
+fn word_at(source: &str, line: usize, character: usize) -> Option<String> {
+    let chars: Vec<char> = source.lines().nth(line)?.chars().collect();
+    let is_word = |c: &char| c.is_ascii_alphanumeric() || *c == '_';
+    // character は UTF-16 のコード単位で数えた位置。その位置までに収まる文字数に直す
+    let at = chars.iter()
+        .scan(0, |units, c| {
+            *units += c.len_utf16();
+            Some(*units)
+        })
+        .take_while(|&units| units <= character)
+        .count();
+    let start = at - chars[..at].iter().rev().take_while(|c| is_word(c)).count();
+    let end = at + chars[at..].iter().take_while(|c| is_word(c)).count();
+    let word: String = chars[start..end].iter().collect();
+    if word.is_empty() || word.starts_with(|c: char| c.is_ascii_digit()) {
+        return None;
+    }
+    Some(word)
 }
 
-/// Hover 用: 指定行付近の atom を探し、requires/ensures を markdown で返す
-fn build_hover(source: &str, line: usize) -> Option<String> {
-    let items = crate::parser::parse_module(source);
-    let lines: Vec<&str> = source.lines().collect();
-    let target_line = lines.get(line).copied().unwrap_or("");
+/// atom の hover: シグネチャ（async / trusted / total 等の修飾子付き）、where・requires・ensures、リソース
+fn atom_hover(atom: &parser::Atom) -> String {
+    let trust = match atom.trust_level {
+        parser::TrustLevel::Trusted => Some("trusted"),
+        parser::TrustLevel::Unverified => Some("unverified"),
+        parser::TrustLevel::Verified => None,
+    };
+    let modifiers: Vec<&str> = [atom.is_async.then_some("async"), trust, atom.is_total.then_some("total")]
+        .into_iter().flatten().collect();
+    let params: Vec<String> = atom.params.iter().map(|p| {
+        let borrow = if p.is_ref_mut { "ref mut " } else if p.is_ref { "ref " } else { "" };
+        match &p.type_name {
+            Some(type_name) => format!("{}{}: {}", borrow, p.name, type_name),
+            None => format!("{}{}", borrow, p.name),
+        }
+    }).collect();
+    let type_params = if atom.type_params.is_empty() { String::new() } else { format!("<{}>", atom.type_params.join(", ")) };
+    let signature = format!(
+        "{}atom {}{}({})",
+        modifiers.iter().map(|m| format!("{} ", m)).collect::<String>(), atom.name, type_params, params.join(", ")
+    );
 
-    // 1) その行に atom 名が書かれているケース: `atom name(`
-    let atom_name = if let Some(idx) = target_line.find("atom ") {
-        let rest = &target_line[idx + 5..];
-        rest.split(|c: char| c == '(' || c.is_whitespace()).next().map(|s| s.trim().to_string())
+    // where 節はパラメータ間の関係としてシグネチャの直後に requires と分けて示す
+    let where_md = atom.where_clause.as_ref()
+        .map(|clause| format!("**where**:\n```\n{}\n```\n\n", clause))
+        .unwrap_or_default();
+    let resources_md = if atom.resources.is_empty() {
+        String::new()
     } else {
-        None
+        format!("\n\n**resources**: {}", atom.resources.iter().map(|r| format!("`{}`", r)).collect::<Vec<_>>().join(", "))
     };
+    format!(
+        "### atom {}\n\n```mumei\n{}\n```\n\n{}**requires**:\n```\n{}\n```\n\n**ensures**:\n```\n{}\n```{}",
+        atom.name, signature, where_md, atom.requires_raw, atom.ensures_raw, resources_md
+    )
+}
 
-    // 2) パース済み items から契約を拾う
-    if let Some(name) = atom_name {
-        for it in &items {
-            if let crate::parser::Item::Atom(a) = it {
-                if a.name == name {
-                    let md = format!(
-                        "### atom {}\n\n**requires**:\n```\n{}\n```\n\n**ensures**:\n```\n{}\n```",
-                        a.name,
-                        a.requires.trim(),
-                        a.ensures.trim()
-                    );
-                    return Some(md);
+/// 精緻型の hover: 定義、基底型と述語
+fn type_hover(refined: &parser::RefinedType) -> String {
+    format!(
+        "### type {}\n\n```mumei\ntype {} = {} where {};\n```\n\n**base type**: `{}`\n\n**predicate**: `{}`",
+        refined.name, refined.name, refined._base_type, refined.predicate_raw, refined._base_type, refined.predicate_raw
+    )
+}
+
+impl Server {
+    /// hover の内容。atom は索引で呼び出しを定義（import 先を含む）に解決し、その契約を示す。
+    /// 精緻型は自身と import したファイルの type 定義から探す。上限を超えるドキュメントでは出さない
+    fn hover(&self, uri: &str, line: usize, character: usize) -> Option<String> {
+        let text = self.documents.get(uri).filter(|t| t.len() <= self.config.max_document_bytes)?;
+        let word = word_at(text, line, character)?;
+        if let Some((def_uri, name, _)) = self.symbol_at(uri, line, character) {
+            let atom = self.items_of(&def_uri).into_iter().find_map(|item| match item {
+                parser::Item::Atom(atom) if atom.name == name => Some(atom),
+                _ => None,
+            });
+            if let Some(atom) = atom {
+                return Some(atom_hover(&atom));
+            }
+        }
+        self.visible_files(uri, &word).iter()
+            .flat_map(|file| self.items_of(file))
+            .find_map(|item| match item {
+                parser::Item::TypeDef(refined) if refined.name == word => Some(type_hover(&refined)),
+                _ => None,
+            })
+    }
+
+    /// ファイルの item。開いているドキュメントは最後にパースできた結果、それ以外はディスクから読む
+    fn items_of(&self, uri: &str) -> Vec<parser::Item> {
+        if let Some(items) = self.items.get(uri) {
+            return items.clone();
+        }
+        uri_to_path(uri).and_then(|path| fs::read_to_string(path).ok())
+            .filter(|source| source.len() <= self.config.max_document_bytes)
+            .and_then(|source| parser::parse_module(&parser::normalize_source(&source)).ok())
+            .unwrap_or_default()
+    }
+}
+
+// =============================================================================
+// ワークスペースのシンボル索引（rename / references / workspace/symbol）
+// =============================================================================
+//
+// 索引はパーサを通さずテキストから作る（編集途中でパースできないドキュメントでも使えるように）。
+// コメントと文字列リテラルの中身を空白で塗りつぶしたうえで、
+//   - 定義:   `atom <name>`
+//   - 参照:   `<name>(` / `<alias>.<name>(` / `<alias>::<name>(`
+//   - import: `import "<path>" [as <alias>] [using { <name>, ... }];`
+//   - export: `export { <name>, ... };` の名前（ファイル自身の atom を指す）
+// を拾う。名前の解決は resolver と同じく、ファイル自身と（推移的に）import したファイルの atom が
+// 修飾なしで見え（`using { ... }` 付きの import からは挙げた名前だけ）、
+// import の alias を付けた `alias.name` / `alias::name` でも見えるものとする。
+// ワークスペースのルートが複数あっても manifest は仮定せず、import の相対パスだけでファイルを結ぶ。
+
+/// 1 行内の範囲（LSP の line / character。0 始まり）
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Span {
+    line: usize,
+    start: usize,
+    end: usize,
+}
+
+impl Span {
+    fn contains(&self, line: usize, character: usize) -> bool {
+        self.line == line && self.start <= character && character <= self.end
+    }
+
+    fn to_json(self) -> Value {
+        serde_json::json!({
+            "start": { "line": self.line, "character": self.start },
+            "end": { "line": self.line, "character": self.end }
+        })
+    }
+}
+
+/// atom の呼び出し箇所
+#[derive(Debug, Clone, PartialEq)]
+struct SymbolRef {
+    /// `alias.name` / `alias::name` の alias（修飾なしなら None）
+    qualifier: Option<String>,
+    name: String,
+    /// 名前部分の範囲（alias は含まない）
+    span: Span,
+}
+
+/// 1 ファイル分の索引
+#[derive(Debug, Clone, Default, PartialEq)]
+struct FileSymbols {
+    /// 定義された atom と名前の範囲
+    definitions: Vec<(String, Span)>,
+    references: Vec<SymbolRef>,
+    imports: Vec<ImportLink>,
+    /// export リストに並んだ名前と範囲
+    exports: Vec<(String, Span)>,
+}
+
+/// import 宣言 1 件
+#[derive(Debug, Clone, PartialEq)]
+struct ImportLink {
+    /// import 先の URI
+    uri: String,
+    alias: Option<String>,
+    /// `using { ... }` に挙げた名前と範囲（None なら import 先の item がすべて修飾なしで見える）
+    using: Option<Vec<(String, Span)>>,
+}
+
+impl ImportLink {
+    /// import 先の name が修飾なしで見えるか
+    fn exposes(&self, name: &str) -> bool {
+        self.using.as_ref().is_none_or(|using| using.iter().any(|(n, _)| n == name))
+    }
+}
+
+impl FileSymbols {
+    fn defines(&self, name: &str) -> bool {
+        self.definitions.iter().any(|(n, _)| n == name)
+    }
+}
+
+/// atom 名にできないキーワードと組み込みの呼び出し
+const KEYWORDS: &[&str] = &[
+    "atom", "requires", "ensures", "body", "where", "let", "if", "else", "while", "invariant", "decreases",
+    "match", "forall", "exists", "import", "as", "export", "type", "struct", "enum", "trait", "impl", "for",
+    "resource", "acquire", "assume", "async", "await", "trusted", "unverified", "total", "test", "true", "false",
+];
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().map_or(false, |c| c.is_ascii_alphabetic() || c == '_') && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// file:// URI。パスの区切り `/` と非予約文字以外（空白・非 ASCII など）は UTF-8 のバイトごとに `%XX` にする
+fn path_to_uri(path: &Path) -> String {
+    let mut uri = String::from("file://");
+    for byte in path.to_string_lossy().bytes() {
+        if byte.is_ascii_alphanumeric() || b"/-._~".contains(&byte) {
+            uri.push(byte as char);
+        } else {
+            uri.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    uri
+}
+
+/// import のパスを import 元の URI 基準で解決した file:// URI
+fn resolve_import_uri(from_uri: &str, path: &str) -> Option<String> {
+    let base = uri_to_path(from_uri)?;
+    Some(path_to_uri(&crate::manifest::normalize_path(&base.parent()?.join(path))))
+}
+
+/// コメントと文字列リテラルの中身を同じバイト数の空白にしたソース（バイト位置を保つ）
+fn mask_comments_and_strings(source: &str) -> String {
+    static LITERALS: OnceLock<Regex> = OnceLock::new();
+    let mut code = String::with_capacity(source.len());
+    let mut last = 0;
+    let literals = LITERALS.get_or_init(|| Regex::new(r#""(?:[^"\\\n]|\\.)*"|//[^\n]*"#).unwrap());
+    for m in literals.find_iter(source) {
+        code.push_str(&source[last..m.start()]);
+        if m.as_str().starts_with('"') {
+            code.push('"');
+            code.extend(std::iter::repeat(' ').take(m.end() - m.start() - 2));
+            code.push('"');
+        } else {
+            code.extend(std::iter::repeat(' ').take(m.end() - m.start()));
+        }
+        last = m.end();
+    }
+    code.push_str(&source[last..]);
+    code
+}
+
+/// バイト位置の LSP の位置（line, character）。character は LSP の既定どおり UTF-16 のコード単位で数える
+fn offset_position(source: &str, offset: usize) -> (usize, usize) {
+    let line_start = source[..offset].rfind('\n').map_or(0, |i| i + 1);
+    (source[..line_start].matches('\n').count(), source[line_start..offset].encode_utf16().count())
+}
+
+/// ソースを索引する。uri は import の相対パスを解決する基準。
+/// 索引はキー入力のたびに作り直すので、正規表現は一度だけコンパイルする
+fn index_source(uri: &str, source: &str) -> FileSymbols {
+    static DEFINITION: OnceLock<Regex> = OnceLock::new();
+    static REFERENCE: OnceLock<Regex> = OnceLock::new();
+    static IMPORT: OnceLock<Regex> = OnceLock::new();
+    static EXPORT: OnceLock<Regex> = OnceLock::new();
+    static NAME: OnceLock<Regex> = OnceLock::new();
+    let code = mask_comments_and_strings(source);
+    let line_starts: Vec<usize> = std::iter::once(0).chain(source.match_indices('\n').map(|(i, _)| i + 1)).collect();
+    let span = |start: usize, end: usize| {
+        let line = line_starts.partition_point(|&s| s <= start) - 1;
+        let character = |offset: usize| source[line_starts[line]..offset].encode_utf16().count();
+        Span { line, start: character(start), end: character(end) }
+    };
+
+    let definitions: Vec<(String, Span)> = DEFINITION.get_or_init(|| Regex::new(r"\batom\s+([A-Za-z_]\w*)").unwrap()).captures_iter(&code)
+        .filter_map(|c| c.get(1))
+        .map(|m| (m.as_str().to_string(), span(m.start(), m.end())))
+        .collect();
+    let references = REFERENCE.get_or_init(|| Regex::new(r"\b(?:([A-Za-z_]\w*)\s*(?:\.|::)\s*)?([A-Za-z_]\w*)\s*\(").unwrap()).captures_iter(&code)
+        .filter_map(|c| {
+            let name = c.get(2)?;
+            let name_span = span(name.start(), name.end());
+            // `atom name(` は定義、`if (` などはキーワード
+            if KEYWORDS.contains(&name.as_str()) || definitions.iter().any(|(_, s)| *s == name_span) {
+                return None;
+            }
+            Some(SymbolRef { qualifier: c.get(1).map(|q| q.as_str().to_string()), name: name.as_str().to_string(), span: name_span })
+        })
+        .collect();
+    // export / using の名前リストは複数行にまたがりうるので、名前ごとにソース全体のバイト位置から範囲を求める
+    let name_re = NAME.get_or_init(|| Regex::new(r"[A-Za-z_]\w*").unwrap());
+    let names = |list: regex::Match| -> Vec<(String, Span)> {
+        name_re.find_iter(list.as_str())
+            .map(|m| (m.as_str().to_string(), span(list.start() + m.start(), list.start() + m.end())))
+            .collect()
+    };
+    let import_re = IMPORT.get_or_init(|| {
+        Regex::new(r#"(?m)^import\s+"([^"]*)"(?:\s+as\s+(\w+))?(?:\s+using\s*\{([^}]*)\})?\s*;"#).unwrap()
+    });
+    let imports = import_re.captures_iter(&code)
+        .filter_map(|c| {
+            // パスは塗りつぶす前のソースから読む
+            let path = &source[c.get(1)?.range()];
+            Some(ImportLink {
+                uri: resolve_import_uri(uri, path)?,
+                alias: c.get(2).map(|a| a.as_str().to_string()),
+                using: c.get(3).map(names),
+            })
+        })
+        .collect();
+    let exports = EXPORT.get_or_init(|| Regex::new(r"(?m)^export\s*\{([^}]*)\}").unwrap()).captures_iter(&code)
+        .filter_map(|c| c.get(1))
+        .flat_map(names)
+        .collect();
+    FileSymbols { definitions, references, imports, exports }
+}
+
+impl Server {
+    /// ワークスペースのルート以下の .mm をディスクから索引する（開いているドキュメントはそちらを優先する）
+    fn index_workspace(&mut self) {
+        for root in self.workspace_roots.clone() {
+            let mut files = Vec::new();
+            crate::manifest::collect_mm_files(&root, Path::new(""), &mut files);
+            for path in files {
+                let uri = path_to_uri(&path);
+                if !self.documents.contains_key(&uri) {
+                    self.index_from_disk(&uri);
+                }
+            }
+        }
+    }
+
+    /// ディスク上の内容で索引を更新する（読めない・上限を超える場合は索引から外す）
+    fn index_from_disk(&mut self, uri: &str) {
+        let source = uri_to_path(uri).and_then(|path| fs::read_to_string(path).ok())
+            .filter(|source| source.len() <= self.config.max_document_bytes);
+        match source {
+            Some(source) => {
+                self.index.insert(uri.to_string(), index_source(uri, &parser::normalize_source(&source)));
+            }
+            None => {
+                self.index.remove(uri);
+            }
+        }
+    }
+
+    /// uri から name が修飾なしで見えるファイル（自身が先頭で、推移的に import したファイルが続く）。
+    /// `using { ... }` 付きの import は name を挙げている場合だけたどる
+    fn visible_files(&self, uri: &str, name: &str) -> Vec<String> {
+        let mut files = vec![uri.to_string()];
+        let mut i = 0;
+        while i < files.len() {
+            if let Some(symbols) = self.index.get(&files[i]) {
+                for link in symbols.imports.iter().filter(|link| link.exposes(name)) {
+                    if !files.contains(&link.uri) {
+                        files.push(link.uri.clone());
+                    }
                 }
             }
+            i += 1;
+        }
+        files
+    }
+
+    /// uri 内の呼び出しが指す atom の定義ファイル
+    fn resolve_reference(&self, uri: &str, reference: &SymbolRef) -> Option<String> {
+        let defines = |file: &String| self.index.get(file).map_or(false, |s| s.defines(&reference.name));
+        let visible = self.visible_files(uri, &reference.name);
+        match &reference.qualifier {
+            // 修飾なし: 自身の定義を優先し、なければ import したファイルから探す
+            None => visible.into_iter().find(defines),
+            // alias 付き: その alias で import したファイル（alias でなければ値に対するメソッド呼び出し等）
+            Some(alias) => visible.iter()
+                .filter_map(|file| self.index.get(file))
+                .flat_map(|symbols| symbols.imports.iter())
+                .filter(|link| link.alias.as_deref() == Some(alias.as_str()))
+                .map(|link| link.uri.clone())
+                .find(defines),
+        }
+    }
+
+    /// カーソル位置の atom（定義・export / using に挙げた名前・呼び出し）を (定義ファイル, 名前, カーソル下の名前の範囲) で返す
+    fn symbol_at(&self, uri: &str, line: usize, character: usize) -> Option<(String, String, Span)> {
+        let symbols = self.index.get(uri)?;
+        if let Some((name, span)) = symbols.definitions.iter().find(|(_, span)| span.contains(line, character)) {
+            return Some((uri.to_string(), name.clone(), *span));
+        }
+        // export / using のリストには型の名前も並ぶので、atom を定義しているものだけを対象にする
+        let defines = |file: &str, name: &str| self.index.get(file).is_some_and(|s| s.defines(name));
+        if let Some((name, span)) = symbols.exports.iter().find(|(name, span)| span.contains(line, character) && defines(uri, name)) {
+            return Some((uri.to_string(), name.clone(), *span));
+        }
+        for link in &symbols.imports {
+            let listed = link.using.iter().flatten().find(|(name, span)| span.contains(line, character) && defines(&link.uri, name));
+            if let Some((name, span)) = listed {
+                return Some((link.uri.clone(), name.clone(), *span));
+            }
+        }
+        let reference = symbols.references.iter().find(|r| r.span.contains(line, character))?;
+        Some((self.resolve_reference(uri, reference)?, reference.name.clone(), reference.span))
+    }
+
+    /// def_uri で定義された atom の（定義と）全呼び出し箇所。URI 順
+    fn occurrences(&self, def_uri: &str, name: &str, include_declaration: bool) -> Vec<(String, Span)> {
+        let mut uris: Vec<&String> = self.index.keys().collect();
+        uris.sort();
+        let mut found = Vec::new();
+        for uri in uris {
+            let symbols = &self.index[uri];
+            if include_declaration && uri == def_uri {
+                found.extend(symbols.definitions.iter().filter(|(n, _)| n == name).map(|(_, span)| (uri.clone(), *span)));
+            }
+            found.extend(symbols.references.iter()
+                .filter(|r| r.name == name && self.resolve_reference(uri, r).as_deref() == Some(def_uri))
+                .map(|r| (uri.clone(), r.span)));
+            // export リストの名前は定義ファイル自身の atom を、using リストの名前は import 先の atom を指す
+            if uri == def_uri {
+                found.extend(symbols.exports.iter().filter(|(n, _)| n == name).map(|(_, span)| (uri.clone(), *span)));
+            }
+            found.extend(symbols.imports.iter()
+                .filter(|link| link.uri == def_uri)
+                .flat_map(|link| link.using.iter().flatten())
+                .filter(|(n, _)| n == name)
+                .map(|(_, span)| (uri.clone(), *span)));
+        }
+        found
+    }
+
+    /// rename の WorkspaceEdit（changes: URI → TextEdit[]）。
+    /// 新しい名前が識別子でない・予約されている・影響するファイルから見える atom と衝突する場合はエラー文を返す
+    fn rename(&self, uri: &str, line: usize, character: usize, new_name: &str) -> Result<Value, String> {
+        let (def_uri, name, _) = self.symbol_at(uri, line, character)
+            .ok_or_else(|| "No atom to rename at this position".to_string())?;
+        if !is_identifier(new_name) || KEYWORDS.contains(&new_name) {
+            return Err(format!("'{}' is not a valid atom name", new_name));
+        }
+        if let Some(reason) = parser::reserved_name(new_name) {
+            return Err(format!("'{}' cannot be used as an atom name ({})", new_name, reason));
+        }
+        if new_name == name {
+            return Ok(serde_json::json!({ "changes": {} }));
+        }
+        let occurrences = self.occurrences(&def_uri, &name, true);
+
+        // 定義ファイルと、それを（推移的に）import する各ファイルから修飾なしで見える範囲に、同名の atom があってはならない。
+        // 呼び出しのないファイルでも、改名後の名前は import で見えるようになる
+        let mut affected: Vec<&String> = self.index.keys()
+            .filter(|file| self.visible_files(file, &name).contains(&def_uri))
+            .collect();
+        affected.sort();
+        for file in affected {
+            let conflict = self.visible_files(file, new_name).into_iter()
+                .find(|visible| self.index.get(visible).map_or(false, |s| s.defines(new_name)));
+            if let Some(conflict) = conflict {
+                return Err(format!("Cannot rename '{}' to '{}': an atom named '{}' is already visible from {} (defined in {})",
+                    name, new_name, new_name, file, conflict));
+            }
         }
+
+        let mut changes = serde_json::Map::new();
+        for (file, span) in occurrences {
+            if let Value::Array(edits) = changes.entry(file).or_insert_with(|| Value::Array(Vec::new())) {
+                edits.push(serde_json::json!({ "range": span.to_json(), "newText": new_name }));
+            }
+        }
+        Ok(serde_json::json!({ "changes": changes }))
     }
 
-    None
+    /// workspace/symbol: 名前に query を含む（大文字小文字を区別しない）atom の定義
+    fn workspace_symbols(&self, query: &str) -> Vec<Value> {
+        let query = query.to_lowercase();
+        let mut uris: Vec<&String> = self.index.keys().collect();
+        uris.sort();
+        uris.into_iter()
+            .flat_map(|uri| self.index[uri].definitions.iter().map(move |(name, span)| (uri, name, span)))
+            .filter(|(_, name, _)| name.to_lowercase().contains(&query))
+            .map(|(uri, name, span)| serde_json::json!({
+                "name": name,
+                "kind": 12,
+                "location": { "uri": uri, "range": span.to_json() }
+            }))
+            .collect()
+    }
 }
 
 // =============================================================================
@@ -322,7 +1320,7 @@ fn read_message(reader: &mut impl BufRead) -> Result<String, String> {
         .map_err(|e| format!("Invalid UTF-8 in body: {}", e))
 }
 /// JSON-RPC レスポンスを送信
-fn send_response(writer: &mut impl Write, id: serde_json::Value, result: serde_json::Value) {
+fn send_response(writer: &mut impl Write, id: Value, result: Value) {
     let response = serde_json::json!({
         "jsonrpc": "2.0",
         "id": id,
@@ -331,7 +1329,7 @@ fn send_response(writer: &mut impl Write, id: serde_json::Value, result: serde_j
     send_message(writer, &response);
 }
 /// JSON-RPC エラーレスポンスを送信
-fn send_error(writer: &mut impl Write, id: serde_json::Value, code: i32, message: &str) {
+fn send_error(writer: &mut impl Write, id: Value, code: i32, message: &str) {
     let response = serde_json::json!({
         "jsonrpc": "2.0",
         "id": id,
@@ -343,7 +1341,7 @@ fn send_error(writer: &mut impl Write, id: serde_json::Value, code: i32, message
     send_message(writer, &response);
 }
 /// textDocument/publishDiagnostics 通知を送信
-fn send_diagnostics(writer: &mut impl Write, uri: &str, diagnostics: &[serde_json::Value]) {
+fn send_diagnostics(writer: &mut impl Write, uri: &str, diagnostics: &[Value]) {
     let notification = serde_json::json!({
         "jsonrpc": "2.0",
         "method": "textDocument/publishDiagnostics",
@@ -355,10 +1353,514 @@ fn send_diagnostics(writer: &mut impl Write, uri: &str, diagnostics: &[serde_jso
     send_message(writer, &notification);
 }
 /// LSP メッセージを stdout に送信（Content-Length ヘッダ付き）
-fn send_message(writer: &mut impl Write, message: &serde_json::Value) {
+fn send_message(writer: &mut impl Write, message: &Value) {
     let body = serde_json::to_string(message).unwrap_or_default();
     let header = format!("Content-Length: {}\r\n\r\n", body.len());
     let _ = writer.write_all(header.as_bytes());
     let _ = writer.write_all(body.as_bytes());
     let _ = writer.flush();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    /// source の最初の atom の hover
+    fn first_atom_hover(source: &str) -> String {
+        parser::parse_module(source).unwrap().into_iter()
+            .find_map(|item| if let parser::Item::Atom(atom) = item { Some(atom_hover(&atom)) } else { None })
+            .expect("hover for atom")
+    }
+
+    #[test]
+    fn test_hover_preserves_multiline_requires() {
+        let source = "atom clamp(x: i64, lo: i64, hi: i64)\nrequires:\n    lo <= hi &&\n    x >= lo &&\n    x <= hi;\nensures: result >= lo;\nbody: x;\n";
+        let hover = first_atom_hover(source);
+        assert!(hover.contains("**requires**:\n```\nlo <= hi &&\nx >= lo &&\nx <= hi\n```"), "{}", hover);
+        assert!(hover.contains("result >= lo"), "{}", hover);
+    }
+
+    #[test]
+    fn test_hover_shows_where_clause_separately() {
+        let source = "atom span(lo: i64, hi: i64)\nwhere: lo <= hi;\nrequires: lo >= 0;\nensures: result >= 0;\nbody: hi - lo;\n";
+        let hover = first_atom_hover(source);
+        assert!(hover.contains("**where**:\n```\nlo <= hi\n```\n\n**requires**:\n```\nlo >= 0\n```"), "{}", hover);
+    }
+
+    #[test]
+    fn test_parse_error_diagnostic_points_at_token() {
+        let source = "atom f(x: i64)\nrequires: true;\nensures: true;\nbody: if x > 0 { 1 };\n";
+        let diagnostics = parse_diagnostics(source, &parser::parse_module(source));
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0]["range"], serde_json::json!({
+            "start": { "line": 3, "character": 6 },
+            "end": { "line": 3, "character": 8 }
+        }));
+        assert_eq!(diagnostics[0]["message"], "Parse error: Mumei requires an 'else' branch");
+    }
+
+    /// 出力バッファから送信されたメッセージを取り出す
+    fn sent(out: &[u8]) -> Vec<Value> {
+        let mut reader = io::Cursor::new(out);
+        let mut messages = Vec::new();
+        while let Ok(body) = read_message(&mut reader) {
+            messages.push(serde_json::from_str(&body).unwrap());
+        }
+        messages
+    }
+
+    /// 送信された publishDiagnostics の diagnostics の source 一覧
+    fn diagnostic_sources(out: &[u8]) -> Vec<Vec<String>> {
+        sent(out).iter()
+            .filter(|m| m["method"] == "textDocument/publishDiagnostics")
+            .map(|m| m["params"]["diagnostics"].as_array().unwrap().iter()
+                .map(|d| d["source"].as_str().unwrap().to_string())
+                .collect())
+            .collect()
+    }
+
+    fn initialize(server: &mut Server, options: Value) -> Value {
+        let mut out = Vec::new();
+        server.handle(&serde_json::json!({
+            "jsonrpc": "2.0", "id": 1, "method": "initialize",
+            "params": {
+                "rootUri": "file:///tmp/root-uri",
+                "workspaceFolders": [{ "uri": "file:///tmp/ws-a", "name": "a" }, { "uri": "file:///tmp/ws-b", "name": "b" }],
+                "initializationOptions": options
+            }
+        }), &mut out);
+        sent(&out).remove(0)
+    }
+
+    #[test]
+    fn test_initialization_options() {
+        let mut server = Server::default();
+        let response = initialize(&mut server, serde_json::json!({
+            "stdPath": env!("CARGO_MANIFEST_DIR").to_string() + "/std",
+            "verifyMode": "onSave",
+            "timeoutMs": 1234,
+            "maxDiagnostics": 7
+        }));
+        assert_eq!(server.config.verify_mode, VerifyMode::OnSave);
+        assert_eq!(server.config.timeout_ms, 1234);
+        assert_eq!(server.config.max_diagnostics, 7);
+        assert_eq!(server.config.std_path, Some(PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/std"))));
+        // workspaceFolders があれば rootUri より優先する
+        assert_eq!(server.workspace_roots, vec![PathBuf::from("/tmp/ws-a"), PathBuf::from("/tmp/ws-b")]);
+
+        // 応答は保存通知を要求し、現在の設定を experimental.mumei で返す
+        let capabilities = &response["result"]["capabilities"];
+        assert_eq!(capabilities["textDocumentSync"]["save"]["includeText"], true);
+        assert_eq!(capabilities["experimental"]["mumei"]["verifyMode"], "onSave");
+        assert_eq!(capabilities["experimental"]["mumei"]["timeoutMs"], 1234);
+
+        // 不正な値は無視し、指定されていないキーは変えない
+        let mut config = LspConfig::default();
+        config.merge(&serde_json::json!({ "verify_mode": "off", "timeoutMs": "fast", "maxDiagnostics": 3 }));
+        assert_eq!(config, LspConfig { verify_mode: VerifyMode::Off, max_diagnostics: 3, ..LspConfig::default() });
+    }
+
+    #[test]
+    fn test_verify_uses_shared_prepare_pipeline() {
+        let dir = TempDir::new("lsp_prepare");
+        let path = dir.join("main.mm");
+
+        // import の解決失敗は（以前のように無視せず）サーバーを止めずに diagnostic になる
+        let issues = verify_source_for_lsp(&path, "import \"./missing.mm\";\natom f(x: i64) requires: true; ensures: true; body: x;\n", &[], None, 1000);
+        let error = issues.iter().find(|issue| issue.severity == Severity::Error).expect("import error");
+        assert!(error.message.starts_with("Import Resolution Failed: ") && error.atom.is_none(), "{:?}", issues);
+
+        // 未保存のバッファの内容で検証する（ディスク上の main.mm は存在しない）
+        let issues = verify_source_for_lsp(&path, "atom f(x: i64) requires: x >= 0; ensures: result >= 0; body: x;\n", &[], None, 5000);
+        assert!(issues.iter().all(|issue| issue.severity != Severity::Error), "{:?}", issues);
+    }
+
+    #[test]
+    fn test_verification_diagnostics_point_at_failing_contract() {
+        let dir = TempDir::new("lsp_ranges");
+        let uri = format!("file://{}", dir.join("main.mm").display());
+        let source = "atom pos(x: i64)\nrequires: x > 0;\nensures: result > 0;\nbody: x;\n\n\
+                      atom bad(x: i64)\nrequires: true;\nensures: result > 0;\nbody: x;\n\n\
+                      atom caller(y: i64)\nrequires: true;\nensures: true;\nbody: {\n    let z = y + 1;\n    pos(z)\n};\n";
+
+        // 失敗した atom ごとに 1 件。最初の失敗で打ち切らない
+        let diagnostics = verify_document(&uri, source, &[], None, 5000);
+        let failures: Vec<&Value> = diagnostics.iter().filter(|d| d["severity"] == 1).collect();
+        assert_eq!(failures.len(), 2, "{:?}", diagnostics);
+        // ensures の失敗は ensures 節に付け、反例を含める
+        assert_eq!(failures[0]["range"], serde_json::json!({
+            "start": { "line": 7, "character": 0 },
+            "end": { "line": 7, "character": 19 }
+        }));
+        let message = failures[0]["message"].as_str().unwrap();
+        assert!(message.starts_with("atom 'bad': ") && message.contains("Counter-example"), "{}", message);
+        // 呼び出し先の requires 違反は呼び出し式に付ける
+        assert_eq!(failures[1]["range"], serde_json::json!({
+            "start": { "line": 15, "character": 4 },
+            "end": { "line": 15, "character": 10 }
+        }));
+        let message = failures[1]["message"].as_str().unwrap();
+        assert!(message.contains("Call to 'pos': precondition") && message.contains("Counter-example"), "{}", message);
+
+        // 準備段階の問題は先頭行に付け、エラーと警告を severity で分ける
+        let issues = [
+            VerifyIssue { severity: Severity::Warning, atom: None, message: "Prelude load warning: missing".to_string() },
+            VerifyIssue { severity: Severity::Error, atom: Some("gone".to_string()), message: "atom 'gone': failed".to_string() },
+        ];
+        let diagnostics = verification_diagnostics(source, &issues);
+        assert_eq!(diagnostics[0]["severity"], 2);
+        assert_eq!(diagnostics[1]["severity"], 1);
+        assert_eq!(diagnostics[1]["range"]["start"], serde_json::json!({ "line": 0, "character": 0 }));
+
+        // character は UTF-16 のコード単位（é は 1、😀 はサロゲートペアで 2）
+        let source = "// é😀\natom bad";
+        assert_eq!(offset_position(source, source.find('\n').unwrap()), (0, 6));
+    }
+
+    #[test]
+    fn test_background_verification_discards_stale_results() {
+        let dir = TempDir::new("lsp_worker");
+        let uri = format!("file://{}", dir.join("bad.mm").display());
+        let source = "atom bad(x: i64)\nrequires: true;\nensures: result > 0;\nbody: x;\n";
+        let save = serde_json::json!({ "method": "textDocument/didSave", "params": { "textDocument": { "uri": uri }, "text": source } });
+        let change = serde_json::json!({
+            "method": "textDocument/didChange",
+            "params": { "textDocument": { "uri": uri }, "contentChanges": [{ "text": format!("{}// edit\n", source) }] }
+        });
+        let (events, inbox) = mpsc::channel();
+        let mut server = Server { worker: Some(spawn_verifier(events)), ..Server::default() };
+        initialize(&mut server, serde_json::json!({ "verifyMode": "onSave" }));
+        let mut out = Vec::new();
+        let next = || inbox.recv_timeout(std::time::Duration::from_secs(60)).expect("verification result");
+
+        // 保存すると検証はワーカーに任せ、その場では前回の結果（なし）を出す
+        server.handle(&save, &mut out);
+        // 結果が届く前に編集されたら、古い版の結果は捨てる
+        server.handle(&change, &mut out);
+        assert!(server.handle_event(next(), &mut out));
+        assert_eq!(diagnostic_sources(&out), vec![Vec::<String>::new(), vec![]]);
+        assert!(server.verification.get(&uri).is_none());
+
+        // 最新の版の結果は届いた時点で送る
+        server.handle(&save, &mut out);
+        assert!(server.handle_event(next(), &mut out));
+        let z3 = vec!["mumei-z3".to_string()];
+        assert_eq!(diagnostic_sources(&out), vec![vec![], vec![], vec![], z3]);
+        assert!(!server.handle_event(Event::Closed, &mut out));
+
+        // 検証待ちの依頼は URI ごとに最新の版だけを残す
+        let request = |uri: &str, revision| VerifyRequest {
+            uri: uri.to_string(),
+            revision,
+            source: String::new(),
+            workspace_roots: Vec::new(),
+            std_path: None,
+            timeout_ms: 0,
+        };
+        let mut pending = Vec::new();
+        for (uri, revision) in [("a", 1), ("b", 1), ("a", 2), ("a", 3)] {
+            supersede(&mut pending, request(uri, revision));
+        }
+        let pending: Vec<(&str, u64)> = pending.iter().map(|r| (r.uri.as_str(), r.revision)).collect();
+        assert_eq!(pending, vec![("b", 1), ("a", 3)]);
+    }
+
+    #[test]
+    fn test_verify_mode_controls_z3_diagnostics() {
+        let dir = TempDir::new("lsp");
+        let uri = format!("file://{}", dir.join("bad.mm").display());
+        let source = "atom bad(x: i64)\nrequires: true;\nensures: result > 0;\nbody: x;\n";
+        let open = serde_json::json!({ "method": "textDocument/didOpen", "params": { "textDocument": { "uri": uri, "text": source } } });
+        let save = serde_json::json!({ "method": "textDocument/didSave", "params": { "textDocument": { "uri": uri } } });
+        let change = |text: &str| serde_json::json!({
+            "method": "textDocument/didChange",
+            "params": { "textDocument": { "uri": uri }, "contentChanges": [{ "text": text }] }
+        });
+        let z3 = vec!["mumei-z3".to_string()];
+
+        // off: 開いても保存しても Z3 は走らない
+        let mut server = Server::default();
+        initialize(&mut server, serde_json::json!({ "verifyMode": "off" }));
+        let mut out = Vec::new();
+        server.handle(&open, &mut out);
+        server.handle(&save, &mut out);
+        assert_eq!(diagnostic_sources(&out), vec![Vec::<String>::new(), vec![]]);
+
+        // onSave: 保存時のみ検証し、その後の編集中も保存時の結果を出し続ける
+        let mut server = Server::default();
+        initialize(&mut server, serde_json::json!({ "verifyMode": "onSave" }));
+        let mut out = Vec::new();
+        server.handle(&open, &mut out);
+        server.handle(&save, &mut out);
+        server.handle(&change(&format!("{}// edit\n", source)), &mut out);
+        assert_eq!(diagnostic_sources(&out), vec![vec![], z3.clone(), z3.clone()]);
+
+        // onChange: 開いた時点で検証する。didChangeConfiguration で off に切り替えると消える
+        let mut server = Server::default();
+        initialize(&mut server, serde_json::json!({ "verifyMode": "onChange", "maxDiagnostics": 5 }));
+        let mut out = Vec::new();
+        server.handle(&open, &mut out);
+        server.handle(&serde_json::json!({
+            "method": "workspace/didChangeConfiguration",
+            "params": { "settings": { "mumei": { "verifyMode": "off" } } }
+        }), &mut out);
+        server.handle(&change(source), &mut out);
+        assert_eq!(diagnostic_sources(&out), vec![z3.clone(), vec![]]);
+        assert_eq!(server.config.max_diagnostics, 5);
+
+        // maxDiagnostics: 0 なら何も送らない
+        let mut server = Server::default();
+        initialize(&mut server, serde_json::json!({ "maxDiagnostics": 0 }));
+        let mut out = Vec::new();
+        server.handle(&open, &mut out);
+        assert_eq!(diagnostic_sources(&out), vec![Vec::<String>::new()]);
+
+    }
+
+    #[test]
+    fn test_oversized_document_gets_lightweight_diagnostics() {
+        let atom = "atom f(x: i64) requires: x >= 0; ensures: result >= 0; body: { x }; // (unbalanced in comment\n";
+        let big = atom.repeat(DEFAULT_MAX_DOCUMENT_BYTES / atom.len() + 1);
+        assert!(big.len() > DEFAULT_MAX_DOCUMENT_BYTES);
+        let open = |uri: &str, text: &str| serde_json::json!({
+            "method": "textDocument/didOpen", "params": { "textDocument": { "uri": uri, "text": text } }
+        });
+        let messages = |out: &[u8]| -> Vec<String> {
+            sent(out).iter()
+                .filter(|m| m["method"] == "textDocument/publishDiagnostics")
+                .flat_map(|m| m["params"]["diagnostics"].as_array().unwrap().iter()
+                    .map(|d| d["message"].as_str().unwrap().to_string()).collect::<Vec<_>>())
+                .collect()
+        };
+
+        // 既定の上限（5 MiB）を超えると Z3 検証もパースもせず、無効化した旨だけを伝える
+        let mut server = Server::default();
+        let mut out = Vec::new();
+        server.handle(&open("file:///tmp/mumei_lsp_big.mm", &big), &mut out);
+        let sent_messages = messages(&out);
+        assert_eq!(sent_messages.len(), 1, "{:?}", sent_messages);
+        assert!(sent_messages[0].contains("over the 5242880-byte limit (maxDocumentBytes)"), "{}", sent_messages[0]);
+        assert!(sent_messages[0].contains("full parsing and verification are disabled"), "{}", sent_messages[0]);
+
+        // 上限は設定で変えられ、区切り記号の対応の誤りは位置付きで報告する
+        let mut server = Server::default();
+        initialize(&mut server, serde_json::json!({ "maxDocumentBytes": 64 }));
+        assert_eq!(server.config.max_document_bytes, 64);
+        let unclosed = format!("{}atom g(x: i64) requires: true; ensures: true; body: {{ x;\n", atom);
+        let mut out = Vec::new();
+        server.handle(&open("file:///tmp/mumei_lsp_unclosed.mm", &unclosed), &mut out);
+        let sent_messages = messages(&out);
+        assert_eq!(sent_messages.len(), 2, "{:?}", sent_messages);
+        assert_eq!(sent_messages[1], "Syntax error: '{' is never closed");
+        let diagnostics = oversized_diagnostics(&unclosed, 64);
+        assert_eq!(diagnostics[1]["range"]["start"]["line"], 1);
+
+        // 上限以下なら従来どおりパースする
+        let mut server = Server::default();
+        initialize(&mut server, serde_json::json!({ "verifyMode": "off" }));
+        let mut out = Vec::new();
+        server.handle(&open("file:///tmp/mumei_lsp_small.mm", atom), &mut out);
+        assert!(messages(&out).is_empty());
+    }
+
+    #[test]
+    fn test_rename_atom_across_workspace_files() {
+        // 2 つのワークスペースルートにまたがる 2 ファイル（ディスクには書かない）
+        let lib = "file:///tmp/ws-a/lib/math.mm";
+        let main = "file:///tmp/ws-b/main.mm";
+        let other = "file:///tmp/ws-b/other.mm";
+        let lib_source = "// add(a, b) は非負数の和\natom add(a: i64, b: i64)\nrequires: a >= 0 && b >= 0;\nensures: result >= 0;\nbody: a + b;\n\natom twice(n: i64)\nrequires: n >= 0;\nensures: result >= 0;\nbody: add(n, n);\nexport {\n    add,\n    twice\n};\n";
+        // sub の本体は文字列に UTF-16 で 2 単位の文字を含む（列は UTF-16 のコード単位で数える）
+        let main_source = "import \"../ws-a/lib/math.mm\" as math;\natom sum3(x: i64, y: i64, z: i64)\nrequires: x >= 0 && y >= 0 && z >= 0;\nensures: result >= 0;\nbody: math.add(x, math::add(y, z));\n\natom sub(x: i64)\nrequires: x >= 0;\nensures: result >= 0;\nbody: { assume(x >= 0, \"add(x) は不要 😀\"); add(x, 0) };\n";
+        // add を呼ばないが math.mm を import するファイル
+        let other_source = "import \"../ws-a/lib/math.mm\";\natom minus(x: i64)\nrequires: true;\nensures: true;\nbody: x;\n";
+        let mut server = Server::default();
+        initialize(&mut server, serde_json::json!({ "verifyMode": "off" }));
+        let mut out = Vec::new();
+        for (uri, text) in [(lib, lib_source), (main, main_source), (other, other_source)] {
+            server.handle(&serde_json::json!({
+                "method": "textDocument/didOpen", "params": { "textDocument": { "uri": uri, "text": text } }
+            }), &mut out);
+        }
+        let mut request = |method: &str, uri: &str, line: u64, character: u64, extra: Value| {
+            let mut params = serde_json::json!({ "textDocument": { "uri": uri }, "position": { "line": line, "character": character } });
+            params.as_object_mut().unwrap().extend(extra.as_object().cloned().unwrap_or_default());
+            let mut out = Vec::new();
+            server.handle(&serde_json::json!({ "jsonrpc": "2.0", "id": 7, "method": method, "params": params }), &mut out);
+            sent(&out).remove(0)
+        };
+        let edits = |response: &Value, uri: &str| -> Vec<(u64, u64, u64)> {
+            response["result"]["changes"][uri].as_array().unwrap().iter()
+                .map(|e| (e["range"]["start"]["line"].as_u64().unwrap(), e["range"]["start"]["character"].as_u64().unwrap(), e["range"]["end"]["character"].as_u64().unwrap()))
+                .collect()
+        };
+
+        // alias 付きの呼び出しから prepareRename すると名前部分の範囲を返す
+        let prepared = request("textDocument/prepareRename", main, 4, 12, Value::Null);
+        assert_eq!(prepared["result"]["placeholder"], "add");
+        assert_eq!(prepared["result"]["range"], serde_json::json!({ "start": { "line": 4, "character": 11 }, "end": { "line": 4, "character": 14 } }));
+        assert_eq!(request("textDocument/prepareRename", main, 0, 2, Value::Null)["result"], Value::Null);
+
+        // 定義・export リストと、両ファイルの修飾なし・`alias.name`・`alias::name` の呼び出しを書き換える（コメント・文字列は除く）
+        let renamed = request("textDocument/rename", main, 4, 25, serde_json::json!({ "newName": "plus" }));
+        assert_eq!(edits(&renamed, lib), vec![(1, 5, 8), (9, 6, 9), (11, 4, 7)]);
+        assert_eq!(edits(&renamed, main), vec![(4, 11, 14), (4, 24, 27), (9, 41, 44)]);
+        assert!(renamed["result"]["changes"][other].is_null());
+        assert_eq!(renamed["result"]["changes"][main][0]["newText"], "plus");
+
+        // 不正な名前や、影響するファイルから見える atom と衝突する名前はエラー
+        for (new_name, expected) in [
+            ("1plus", "'1plus' is not a valid atom name"),
+            ("while", "'while' is not a valid atom name"),
+            ("len_a", "reserved for internal verifier symbols"),
+            ("twice", "an atom named 'twice' is already visible from file:///tmp/ws-a/lib/math.mm"),
+            ("sub", "an atom named 'sub' is already visible from file:///tmp/ws-b/main.mm"),
+            // 呼び出しのないファイルでも、改名後の名前は import で見えるようになる
+            ("minus", "an atom named 'minus' is already visible from file:///tmp/ws-b/other.mm"),
+        ] {
+            let response = request("textDocument/rename", lib, 1, 6, serde_json::json!({ "newName": new_name }));
+            assert_eq!(response["error"]["code"], -32602, "{}", new_name);
+            assert!(response["error"]["message"].as_str().unwrap().contains(expected), "{}", response["error"]["message"]);
+        }
+
+        // 索引は didChange で更新される
+        let edited = main_source.replace("math.add(x, math::add(y, z))", "x + math::add(y, z)");
+        server.handle(&serde_json::json!({
+            "method": "textDocument/didChange",
+            "params": { "textDocument": { "uri": main }, "contentChanges": [{ "text": edited }] }
+        }), &mut out);
+        let mut request = |method: &str, params: Value| {
+            let mut out = Vec::new();
+            server.handle(&serde_json::json!({ "jsonrpc": "2.0", "id": 8, "method": method, "params": params }), &mut out);
+            sent(&out).remove(0)["result"].clone()
+        };
+        let references = request("textDocument/references", serde_json::json!({
+            "textDocument": { "uri": lib }, "position": { "line": 1, "character": 6 }, "context": { "includeDeclaration": false }
+        }));
+        let locations: Vec<(String, u64)> = references.as_array().unwrap().iter()
+            .map(|l| (l["uri"].as_str().unwrap().to_string(), l["range"]["start"]["character"].as_u64().unwrap()))
+            .collect();
+        assert_eq!(locations, vec![(lib.to_string(), 6), (lib.to_string(), 4), (main.to_string(), 16), (main.to_string(), 41)]);
+
+        let symbols = request("workspace/symbol", serde_json::json!({ "query": "U" }));
+        let names: Vec<&str> = symbols.as_array().unwrap().iter().map(|s| s["name"].as_str().unwrap()).collect();
+        assert_eq!(names, vec!["sum3", "sub", "minus"]);
+    }
+
+    #[test]
+    fn test_rename_through_selective_imports() {
+        let lib = "file:///tmp/ws-a/lib/math.mm";
+        let main = "file:///tmp/ws-b/main.mm";
+        let other = "file:///tmp/ws-b/other.mm";
+        let lib_source = "atom add(a: i64, b: i64)\nrequires: true;\nensures: true;\nbody: a + b;\n\natom mul(a: i64, b: i64)\nrequires: true;\nensures: true;\nbody: a * b;\n";
+        let main_source = "import \"../ws-a/lib/math.mm\" using {\n    add,\n};\natom f(x: i64)\nrequires: x >= 0;\nensures: result >= 0;\nbody: add(x, x);\n";
+        // using に add を挙げていないので、other の `add(` は math.mm の add を指さない
+        let other_source = "import \"../ws-a/lib/math.mm\" using {mul};\natom plus(x: i64)\nrequires: true;\nensures: true;\nbody: add(x, mul(x, x));\n";
+        let mut server = Server::default();
+        initialize(&mut server, serde_json::json!({ "verifyMode": "off" }));
+        let mut out = Vec::new();
+        for (uri, text) in [(lib, lib_source), (main, main_source), (other, other_source)] {
+            server.handle(&serde_json::json!({
+                "method": "textDocument/didOpen", "params": { "textDocument": { "uri": uri, "text": text } }
+            }), &mut out);
+        }
+        let mut request = |method: &str, uri: &str, line: u64, character: u64, extra: Value| {
+            let mut params = serde_json::json!({ "textDocument": { "uri": uri }, "position": { "line": line, "character": character } });
+            params.as_object_mut().unwrap().extend(extra.as_object().cloned().unwrap_or_default());
+            let mut out = Vec::new();
+            server.handle(&serde_json::json!({ "jsonrpc": "2.0", "id": 9, "method": method, "params": params }), &mut out);
+            sent(&out).remove(0)
+        };
+        let edits = |response: &Value, uri: &str| -> Vec<(u64, u64, u64)> {
+            response["result"]["changes"][uri].as_array().unwrap().iter()
+                .map(|e| (e["range"]["start"]["line"].as_u64().unwrap(), e["range"]["start"]["character"].as_u64().unwrap(), e["range"]["end"]["character"].as_u64().unwrap()))
+                .collect()
+        };
+
+        // using に挙げた名前からも rename でき、定義・using の名前・呼び出しを書き換える
+        let prepared = request("textDocument/prepareRename", main, 1, 5, Value::Null);
+        assert_eq!(prepared["result"]["placeholder"], "add");
+        let renamed = request("textDocument/rename", main, 1, 5, serde_json::json!({ "newName": "plus" }));
+        assert_eq!(edits(&renamed, lib), vec![(0, 5, 8)]);
+        assert_eq!(edits(&renamed, main), vec![(6, 6, 9), (1, 4, 7)]);
+        assert!(renamed["result"]["changes"][other].is_null());
+
+        // other は using {mul} で mul を取り込むので、mul の参照に含まれ、other の plus と衝突する
+        let references = request("textDocument/references", other, 0, 37, serde_json::json!({ "context": { "includeDeclaration": false } }));
+        let locations: Vec<(String, u64, u64)> = references["result"].as_array().unwrap().iter()
+            .map(|l| (l["uri"].as_str().unwrap().to_string(), l["range"]["start"]["line"].as_u64().unwrap(), l["range"]["start"]["character"].as_u64().unwrap()))
+            .collect();
+        assert_eq!(locations, vec![(other.to_string(), 4, 13), (other.to_string(), 0, 36)]);
+        let response = request("textDocument/rename", lib, 5, 6, serde_json::json!({ "newName": "plus" }));
+        assert!(response["error"]["message"].as_str().unwrap().contains("already visible from file:///tmp/ws-b/other.mm"), "{}", response);
+    }
+
+    #[test]
+    fn test_positions_and_uris_follow_lsp_encoding() {
+        // character は UTF-16 のコード単位: `"😀" ab` の直後（7）は ab の末尾
+        assert_eq!(word_at("\"😀\" ab cd", 0, 7).as_deref(), Some("ab"));
+        assert_eq!(word_at("\"😀\" ab cd", 0, 8).as_deref(), Some("cd"));
+
+        // 空白や非 ASCII を含むパスは percent-encoding した URI にし、元のパスに戻せる
+        let path = Path::new("/tmp/my ws/数学.mm");
+        let uri = path_to_uri(path);
+        assert_eq!(uri, "file:///tmp/my%20ws/%E6%95%B0%E5%AD%A6.mm");
+        assert_eq!(uri_to_path(&uri).as_deref(), Some(path));
+        assert_eq!(resolve_import_uri(&uri, "./lib/a b.mm").as_deref(), Some("file:///tmp/my%20ws/lib/a%20b.mm"));
+    }
+
+    #[test]
+    fn test_hover_over_atom_calls_and_refined_types() {
+        let lib = "file:///tmp/ws-a/lib/net.mm";
+        let main = "file:///tmp/ws-b/main.mm";
+        let lib_source = "type Nat = i64 where v >= 0;\nresource db priority: 1 mode: exclusive;\nasync trusted atom fetch(ref mut buf: Nat, n: Nat)\nrequires: n >= 0;\nensures: result >= 0;\nresources: [db];\nbody: n;\n";
+        let main_source = "import \"../ws-a/lib/net.mm\" as net;\natom load(x: Nat)\nrequires: x >= 0;\nensures: result >= 0;\nbody: fetch(x, x) + len_of(x);\n";
+        let mut server = Server::default();
+        initialize(&mut server, serde_json::json!({ "verifyMode": "off" }));
+        let mut out = Vec::new();
+        for (uri, text) in [(lib, lib_source), (main, main_source)] {
+            server.handle(&serde_json::json!({
+                "method": "textDocument/didOpen", "params": { "textDocument": { "uri": uri, "text": text } }
+            }), &mut out);
+        }
+        let hover = |server: &mut Server, line: u64, character: u64| -> Value {
+            let mut out = Vec::new();
+            server.handle(&serde_json::json!({
+                "jsonrpc": "2.0", "id": 3, "method": "textDocument/hover",
+                "params": { "textDocument": { "uri": main }, "position": { "line": line, "character": character } }
+            }), &mut out);
+            let response = sent(&out).remove(0);
+            assert!(response.get("error").is_none(), "{}", response);
+            if response["result"].is_null() {
+                return Value::Null;
+            }
+            assert_eq!(response["result"]["contents"]["kind"], "markdown");
+            response["result"]["contents"]["value"].clone()
+        };
+
+        // 別ファイルの atom の呼び出し: シグネチャ（修飾子・ref mut）、契約、リソース
+        let fetch = hover(&mut server, 4, 8);
+        let fetch = fetch.as_str().unwrap();
+        assert!(fetch.contains("```mumei\nasync trusted atom fetch(ref mut buf: Nat, n: Nat)\n```"), "{}", fetch);
+        assert!(fetch.contains("**requires**:\n```\nn >= 0\n```\n\n**ensures**:\n```\nresult >= 0\n```"), "{}", fetch);
+        assert!(fetch.ends_with("**resources**: `db`"), "{}", fetch);
+
+        // import 先の精緻型: 基底型と述語
+        let nat = hover(&mut server, 1, 14);
+        let nat = nat.as_str().unwrap();
+        assert!(nat.contains("**base type**: `i64`\n\n**predicate**: `v >= 0`"), "{}", nat);
+
+        // 変数や未知の呼び出しはエラーではなく null
+        assert_eq!(hover(&mut server, 4, 12), Value::Null);
+        assert_eq!(hover(&mut server, 4, 21), Value::Null);
+
+        // パースできない編集中も、直前にパースできた item で定義の hover を返す
+        server.handle(&serde_json::json!({
+            "method": "textDocument/didChange",
+            "params": { "textDocument": { "uri": main }, "contentChanges": [{ "text": main_source.replace("fetch(x, x)", "if x > 0 { 1 }") }] }
+        }), &mut out);
+        let load = hover(&mut server, 1, 6);
+        assert!(load.as_str().unwrap().contains("```mumei\natom load(x: Nat)\n```"), "{}", load);
+    }
+}
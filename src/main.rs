@@ -1,19 +1,15 @@
-mod ast;
-mod parser;
-mod verification;
-mod codegen;
-mod transpiler;
-mod resolver;
-#[allow(dead_code)]
-mod manifest;
-mod setup;
-mod lsp;
-mod registry;
+// コンパイラ本体はライブラリ（lib.rs）。この crate はその上の CLI 層で、
+// `--watch` と `mumei inspect` だけがバイナリ固有のモジュール
+mod watch;
+mod inspect;
+
+use mumei::{bench, interpreter, lockfile, lsp, manifest, migrate, native, parser, pipeline, query, registry, report, resolver, sarif, selftest, setup, tailrec, theory, transpiler, verification};
 
 use clap::{Parser, Subcommand};
 use std::fs;
-use std::path::Path;
-use crate::transpiler::{TargetLanguage, transpile, transpile_enum, transpile_struct, transpile_trait, transpile_impl, transpile_module_header};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use crate::transpiler::{TargetLanguage, UnsupportedPolicy, transpile_bundle_header};
 use crate::parser::{Item, ImportDecl};
 
 // =============================================================================
@@ -22,17 +18,25 @@ use crate::parser::{Item, ImportDecl};
 //
 // Usage:
 //   mumei build input.mm -o dist/katana   # verify + codegen + transpile (default)
+//   mumei build input.mm --allow-partial  # emit TODO stubs for backend-unsupported constructs
 //   mumei verify input.mm                 # Z3 verification only
 //   mumei check input.mm                  # parse + resolve + monomorphize (no Z3)
+//   mumei verify input.mm --watch         # re-run on changes to the input, its imports, std/ and mumei.toml
 //   mumei init my_project                 # generate project template
 //   mumei setup                           # download & configure Z3 + LLVM toolchain
 //   mumei add <dep>                       # add dependency to mumei.toml
+//   mumei remove <dep>                    # remove dependency from mumei.toml
+//   mumei report report.json              # render a saved report (--filter failed, --diff old.json)
+//   mumei selftest input.mm               # differential test: interpreter vs ensures vs transpiled Rust
+//   mumei bench input.mm --atom f         # generate + run Criterion / testing.B / Node benchmarks
+//   mumei query input.mm --imports        # imports / atoms / types as JSON for build systems (no Z3)
+//   mumei migrate src --to 2026 --dry-run # rewrite sources for a newer edition (unified diff only)
 //   mumei input.mm -o dist/katana         # backward compat → same as build
 
 #[derive(Parser)]
 #[command(
     name = "mumei",
-    version = env!("CARGO_PKG_VERSION"),
+    version = cli_version(),
     about = "🗡️ Mumei — Mathematical Proof-Driven Programming Language",
     long_about = "Formally verified language: parse → resolve → monomorphize → verify (Z3) → codegen (LLVM IR) → transpile (Rust/Go/TypeScript)"
 )]
@@ -47,6 +51,10 @@ struct Cli {
     /// Output base name (for .ll, .rs, .go, .ts)
     #[arg(short, long, default_value = "katana")]
     output: String,
+
+    /// Print detailed logs (e.g. stale cache entries pruned on save)
+    #[arg(long, global = true)]
+    verbose: bool,
 }
 
 #[derive(Subcommand)]
@@ -58,16 +66,67 @@ enum Command {
         /// Output base name
         #[arg(short, long, default_value = "katana")]
         output: String,
+        /// Emit TODO stubs (instead of compile-error markers) for constructs a backend does not support yet
+        #[arg(long)]
+        allow_partial: bool,
+        /// Also write the verification results as a SARIF 2.1.0 log to this path
+        #[arg(long)]
+        sarif: Option<String>,
+        /// Fail if verification is disabled (verify=false) or any atom is trusted/unverified (for release pipelines)
+        #[arg(long)]
+        require_verified: bool,
+        /// Fail if any trusted atom is defined outside std/ and the mumei.toml dependencies (audits the trust surface)
+        #[arg(long)]
+        deny_trusted: bool,
+        /// Number of atoms to verify in parallel (default: number of CPU cores)
+        #[arg(short, long)]
+        jobs: Option<usize>,
+        /// Write one LLVM IR file per atom (`<output>_<atom>.ll`) instead of a single `<output>.ll` module
+        #[arg(long)]
+        split_ir: bool,
+        /// Print the AST size of each impl law after method expansion
+        #[arg(long)]
+        profile: bool,
+        /// Native output: llvm-ir (default), obj (also run llc to write .o files) or exe (also link a binary with cc; needs `atom main()`)
+        #[arg(long, default_value = "llvm-ir", value_parser = ["llvm-ir", "obj", "exe"])]
+        emit: String,
+        /// Re-run whenever the input, its imports, std/ or mumei.toml change
+        #[arg(long)]
+        watch: bool,
     },
     /// Z3 formal verification only (no codegen, no transpile)
     Verify {
         /// Input .mm file
         input: String,
+        /// Counterexample output format: inline (default), table, or json
+        #[arg(long, default_value = "inline", value_parser = ["inline", "table", "json"])]
+        counterexample_format: String,
+        /// Also write the verification results as a SARIF 2.1.0 log to this path
+        #[arg(long)]
+        sarif: Option<String>,
+        /// Print an equivalent `while` loop (with an invariant derived from ensures) for each tail-recursive atom
+        #[arg(long)]
+        suggest_invariant: bool,
+        /// Print the slowest Z3 obligations (up to 5) of each verified atom and the expanded size of each impl law
+        #[arg(long)]
+        profile: bool,
+        /// Number of atoms to verify in parallel (default: number of CPU cores)
+        #[arg(short, long)]
+        jobs: Option<usize>,
+        /// Re-run whenever the input, its imports, std/ or mumei.toml change
+        #[arg(long)]
+        watch: bool,
     },
     /// Parse + resolve + monomorphize only (no Z3, fast syntax check)
     Check {
         /// Input .mm file
         input: String,
+        /// Show which line ranges each item was extracted from, and list source text no item consumed
+        #[arg(long)]
+        explain_parse: bool,
+        /// Re-run whenever the input, its imports, std/ or mumei.toml change
+        #[arg(long)]
+        watch: bool,
     },
     /// Generate a new Mumei project template
     Init {
@@ -75,7 +134,21 @@ enum Command {
         name: String,
     },
     /// Inspect development environment (Z3, LLVM, std library)
-    Inspect,
+    #[command(visible_alias = "doctor")]
+    Inspect {
+        /// Z3 binary to compare against the linked library (default: $MUMEI_Z3_BINARY or `z3` on PATH)
+        #[arg(long)]
+        z3: Option<String>,
+        /// Print the check results as JSON (tool, found, version, path, severity)
+        #[arg(long)]
+        json: bool,
+        /// Fix what can be fixed automatically (mumei setup, std/ link, mumei.toml), then check again
+        #[arg(long)]
+        fix: bool,
+        /// With --fix, create mumei.toml without asking
+        #[arg(long, requires = "fix")]
+        yes: bool,
+    },
     /// Download and configure Z3 + LLVM toolchain into ~/.mumei/
     Setup {
         /// Force re-download even if already installed
@@ -84,7 +157,12 @@ enum Command {
     },
     /// Add a dependency to mumei.toml
     Add {
-        /// Dependency specifier: local path (./path/to/lib) or package name
+        /// Dependency specifier: local path (./path/to/lib), git URL, or package name in the local registry
+        dep: String,
+    },
+    /// Remove a dependency from mumei.toml
+    Remove {
+        /// Dependency name as declared in [dependencies]
         dep: String,
     },
     /// Publish package to local registry (~/.mumei/packages/)
@@ -93,28 +171,134 @@ enum Command {
         #[arg(long)]
         proof_only: bool,
     },
+    /// Render a saved report.json as a human-readable summary
+    Report {
+        /// Path to report.json written by `mumei verify` / `mumei build`
+        path: String,
+        /// Show all results (default) or only failed ones
+        #[arg(long, default_value = "all", value_parser = ["all", "failed"])]
+        filter: String,
+        /// Baseline report to compare against (lists newly failing / newly passing atoms)
+        #[arg(long)]
+        diff: Option<String>,
+    },
+    /// Run `test` blocks: evaluate each `assert` with the concrete interpreter (no Z3 / LLVM needed)
+    Test {
+        /// Input .mm file
+        input: String,
+    },
+    /// Differential test: run verified scalar atoms on random inputs (interpreter vs ensures vs transpiled Rust)
+    Selftest {
+        /// Input .mm file
+        input: String,
+        /// Number of random inputs per atom
+        #[arg(long, default_value_t = 100)]
+        samples: usize,
+        /// Random seed (printed on every run so failures can be reproduced)
+        #[arg(long)]
+        seed: Option<u64>,
+        /// Skip compiling the transpiled Rust harness even if rustc is available
+        #[arg(long)]
+        no_rust: bool,
+    },
+    /// Generate benchmark harnesses (Criterion / Go testing.B / Node) for verified atoms and compare backends
+    Bench {
+        /// Input .mm file
+        input: String,
+        /// Atom to benchmark (repeatable; default: every verified atom)
+        #[arg(long = "atom")]
+        atoms: Vec<String>,
+        /// Directory for the generated harnesses (default: a temporary directory, removed afterwards)
+        #[arg(long)]
+        out_dir: Option<String>,
+        /// Number of inputs sampled from requires per atom
+        #[arg(long, default_value_t = 16)]
+        samples: usize,
+        /// Random seed for input sampling
+        #[arg(long)]
+        seed: Option<u64>,
+        /// Only generate the harnesses; do not run any toolchain
+        #[arg(long)]
+        no_run: bool,
+    },
+    /// Print imports, atoms and types of a .mm file as JSON for build-system integration (no verification)
+    Query {
+        /// Input .mm file
+        input: String,
+        /// Output format
+        #[arg(long, default_value = "json", value_parser = ["json"])]
+        format: String,
+        /// Resolved absolute paths of the direct imports (imported files are not loaded)
+        #[arg(long)]
+        imports: bool,
+        /// Atoms with params, contracts, hash, async and resources
+        #[arg(long)]
+        atoms: bool,
+        /// Refined types, structs and enums
+        #[arg(long)]
+        types: bool,
+        /// Transitive import closure (parse only)
+        #[arg(long)]
+        deps_closure: bool,
+    },
+    /// Apply mechanical rewrites for a newer language edition and record it in mumei.toml
+    Migrate {
+        /// .mm file, or directory searched recursively
+        path: String,
+        /// Target edition (e.g. 2026)
+        #[arg(long)]
+        to: String,
+        /// Print a unified diff instead of rewriting files (mumei.toml is not updated)
+        #[arg(long)]
+        dry_run: bool,
+    },
     /// Start Language Server Protocol server (stdio mode)
     Lsp,
 }
 
+/// `mumei --version` の出力: コンパイラのバージョンとリンク済み Z3 のバージョン
+fn cli_version() -> &'static str {
+    static VERSION: std::sync::OnceLock<String> = std::sync::OnceLock::new();
+    VERSION.get_or_init(|| format!("{} (linked {})", env!("CARGO_PKG_VERSION"), verification::linked_z3_version()))
+}
+
 fn main() {
     let cli = Cli::parse();
+    VERBOSE.store(cli.verbose, Ordering::Relaxed);
+    // cargo feature で有効にした理論拡張（crate::theory）を登録
+    theory::register_builtin();
 
     match cli.command {
-        Some(Command::Build { input, output }) => {
-            cmd_build(&input, &output);
+        Some(Command::Build { input, output, allow_partial, sarif, require_verified, deny_trusted, jobs, split_ir, profile, emit, watch }) => {
+            if watch {
+                watch_or_exit("build", &input);
+            }
+            // value_parser で値は限定済み
+            let emit = emit.parse().unwrap_or_default();
+            check_z3_available();
+            if let Err(failure) = cmd_build(&input, &output, allow_partial, sarif.as_deref(), require_verified, deny_trusted, resolve_jobs(jobs), split_ir, profile, emit) {
+                exit_build_failure(failure);
+            }
         }
-        Some(Command::Verify { input }) => {
-            cmd_verify(&input);
+        Some(Command::Verify { input, counterexample_format, sarif, suggest_invariant, profile, jobs, watch }) => {
+            if watch {
+                watch_or_exit("verify", &input);
+            }
+            // value_parser で値は限定済み
+            let ce_format = counterexample_format.parse().unwrap_or_default();
+            cmd_verify(&input, ce_format, sarif.as_deref(), suggest_invariant, profile, resolve_jobs(jobs));
         }
-        Some(Command::Check { input }) => {
-            cmd_check(&input);
+        Some(Command::Check { input, explain_parse, watch }) => {
+            if watch {
+                watch_or_exit("check", &input);
+            }
+            cmd_check(&input, explain_parse);
         }
         Some(Command::Init { name }) => {
             cmd_init(&name);
         }
-        Some(Command::Inspect) => {
-            cmd_inspect();
+        Some(Command::Inspect { z3, json, fix, yes }) => {
+            cmd_inspect(z3.as_deref(), json, fix, yes);
         }
         Some(Command::Setup { force }) => {
             setup::run(force);
@@ -122,16 +306,43 @@ fn main() {
         Some(Command::Add { dep }) => {
             cmd_add(&dep);
         }
+        Some(Command::Remove { dep }) => {
+            cmd_remove(&dep);
+        }
         Some(Command::Publish { proof_only }) => {
             cmd_publish(proof_only);
         }
+        Some(Command::Report { path, filter, diff }) => {
+            // value_parser で値は限定済み
+            let filter = filter.parse().unwrap_or_default();
+            cmd_report(&path, filter, diff.as_deref());
+        }
+        Some(Command::Test { input }) => {
+            cmd_test(&input);
+        }
+        Some(Command::Selftest { input, samples, seed, no_rust }) => {
+            cmd_selftest(&input, samples, seed, no_rust);
+        }
+        Some(Command::Bench { input, atoms, out_dir, samples, seed, no_run }) => {
+            cmd_bench(&input, &atoms, out_dir.as_deref(), samples, seed, no_run);
+        }
+        Some(Command::Query { input, format: _, imports, atoms, types, deps_closure }) => {
+            // value_parser で format は json に限定済み
+            cmd_query(&input, query::Subqueries { imports, atoms, types, deps_closure });
+        }
+        Some(Command::Migrate { path, to, dry_run }) => {
+            cmd_migrate(&path, &to, dry_run);
+        }
         Some(Command::Lsp) => {
             lsp::run();
         }
         None => {
             // 後方互換: `mumei input.mm -o dist/katana` → build として実行
             if let Some(ref input) = cli.input {
-                cmd_build(input, &cli.output);
+                check_z3_available();
+                if let Err(failure) = cmd_build(input, &cli.output, false, None, false, false, resolve_jobs(None), false, false, native::Emit::LlvmIr) {
+                    exit_build_failure(failure);
+                }
             } else {
                 eprintln!("Usage: mumei <COMMAND> or mumei <input.mm>");
                 eprintln!("  build   Verify + compile + transpile (default)");
@@ -140,8 +351,15 @@ fn main() {
                 eprintln!("  init    Generate a new project template");
                 eprintln!("  setup   Download & configure Z3 + LLVM toolchain");
                 eprintln!("  add     Add a dependency to mumei.toml");
+                eprintln!("  remove  Remove a dependency from mumei.toml");
                 eprintln!("  lsp     Start Language Server Protocol server");
                 eprintln!("  inspect Inspect development environment");
+                eprintln!("  report  Render a saved report.json");
+                eprintln!("  test    Run test blocks (assert) with the concrete interpreter");
+                eprintln!("  selftest Differential test of interpreter / ensures / transpiled Rust");
+                eprintln!("  bench   Benchmark generated Rust / Go / TypeScript code");
+                eprintln!("  query   Print imports / atoms / types as JSON (no verification)");
+                eprintln!("  migrate Rewrite sources for a newer language edition");
                 eprintln!("Run `mumei --help` for full usage.");
                 std::process::exit(1);
             }
@@ -153,14 +371,51 @@ fn main() {
 // Shared pipeline helpers
 // =============================================================================
 
-/// ソースファイルを読み込む
+/// `--verbose` が指定されたか（main で設定する）
+static VERBOSE: AtomicBool = AtomicBool::new(false);
+
+/// キャッシュの保存時に刈り込んだ古いエントリを --verbose のときだけ表示する
+fn log_pruned(cache_file: &str, pruned: &[String]) {
+    if VERBOSE.load(Ordering::Relaxed) {
+        for name in pruned {
+            println!("{}", pruned_line(cache_file, name));
+        }
+    }
+}
+
+fn pruned_line(cache_file: &str, name: &str) -> String {
+    format!("  🧹 {}: pruned stale entry '{}'", cache_file, name)
+}
+
+/// impl の各 law の展開後のサイズを --profile / --verbose のときだけ表示する
+fn log_law_sizes(impl_def: &parser::ImplDef, module_env: &verification::ModuleEnv, profile: bool) {
+    if profile || VERBOSE.load(Ordering::Relaxed) {
+        if let Ok(sizes) = verification::law_expansion_sizes(impl_def, module_env) {
+            for (law_name, nodes) in sizes {
+                println!("    📏 law '{}': {} AST node(s) after expansion", law_name, nodes);
+            }
+        }
+    }
+}
+
+/// ソースファイルを読み込む（改行と BOM は resolver::read_source で正規化される）
 fn load_source(input: &str) -> String {
-    fs::read_to_string(input).unwrap_or_else(|_| {
+    resolver::read_source(Path::new(input)).unwrap_or_else(|_| {
         eprintln!("❌ Error: Could not read Mumei source file '{}'", input);
         std::process::exit(1);
     })
 }
 
+/// `--sarif <path>` が指定されていれば report を SARIF ログとしても書き出す
+fn save_sarif(report: &report::Report, sarif_path: Option<&str>) {
+    if let Some(path) = sarif_path {
+        match sarif::save(report, Path::new(path)) {
+            Ok(()) => println!("  📄 SARIF log: {}", path),
+            Err(e) => eprintln!("  ⚠️  Failed to write SARIF log '{}': {}", path, e),
+        }
+    }
+}
+
 /// Z3 が利用可能かチェックし、なければ親切なメッセージで終了する
 fn check_z3_available() {
     use std::process::Command as Cmd;
@@ -178,69 +433,74 @@ fn check_z3_available() {
     }
 }
 
-/// parse → resolve → monomorphize → ModuleEnv に全定義を登録
-fn load_and_prepare(input: &str) -> (Vec<Item>, verification::ModuleEnv, Vec<ImportDecl>) {
-    let source = load_source(input);
-    let items = parser::parse_module(&source);
-
-    let mut module_env = verification::ModuleEnv::new();
-    verification::register_builtin_traits(&mut module_env);
-    let input_path = Path::new(input);
-    let base_dir = input_path.parent().unwrap_or(Path::new("."));
-
-    // std/prelude.mm の自動ロード（Eq, Ord, Numeric, Option<T>, Result<T, E> 等）
-    // prelude が見つからない場合は組み込みトレイトがフォールバックとして機能する
-    if let Err(e) = resolver::resolve_prelude(base_dir, &mut module_env) {
-        eprintln!("  ⚠️  Prelude load warning: {}", e);
-        // prelude のロード失敗は致命的ではない（組み込みトレイトが代替）
-    }
+/// `--watch`: 変更のたびに同じコマンドを `--watch` なしの子プロセスで再実行し続ける（crate::watch）。
+/// 子プロセスを起動できなくなったときだけ終了する
+fn watch_or_exit(command: &str, input: &str) -> ! {
+    let args = watch::child_args(std::env::args_os().skip(1));
+    let Err(e) = watch::run(command, Path::new(input), &args);
+    eprintln!("❌ Error: {}", e);
+    std::process::exit(1);
+}
 
-    // mumei.toml の [dependencies] から依存パッケージを解決
-    if let Some((proj_dir, m)) = manifest::find_and_load() {
-        if let Err(e) = resolver::resolve_manifest_dependencies(&m, &proj_dir, &mut module_env) {
-            eprintln!("  ⚠️  Dependency resolution warning: {}", e);
+/// parse → resolve → monomorphize → ModuleEnv に全定義を登録（pipeline::prepare_module の CLI 層）。
+/// diagnostic を表示し、致命的なエラーがあれば終了する
+fn load_and_prepare(input: &str) -> (Vec<Item>, verification::ModuleEnv, Vec<ImportDecl>) {
+    match pipeline::prepare_module(Path::new(input), &pipeline::PrepareOptions::default()) {
+        Ok(prepared) => {
+            print_diagnostics(&prepared.diagnostics);
+            (prepared.items, prepared.module_env, prepared.imports)
+        }
+        Err(diagnostics) => {
+            print_diagnostics(&diagnostics);
+            std::process::exit(1);
         }
     }
+}
 
-    if let Err(e) = resolver::resolve_imports(&items, base_dir, &mut module_env) {
-        eprintln!("  ❌ Import Resolution Failed: {}", e);
-        std::process::exit(1);
-    }
+/// CLI の出力先
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Stream {
+    Stdout,
+    Stderr,
+}
 
-    let mut mono = ast::Monomorphizer::new();
-    mono.collect(&items);
-    let items = if mono.has_generics() {
-        let mono_items = mono.monomorphize(&items);
-        println!("  🔬 Monomorphization: {} generic instance(s) expanded.", mono.instances().len());
-        mono_items
-    } else {
-        items
-    };
+/// pipeline の diagnostic を表示する行に変換する（表示しないものは None）
+fn render_diagnostic(d: &pipeline::Diagnostic, verbose: bool) -> Option<(Stream, String)> {
+    use pipeline::Stage;
+    match (d.severity, d.stage) {
+        (report::Severity::Error, Stage::Source) => Some((Stream::Stderr, format!("❌ Error: {}", d.message))),
+        (report::Severity::Error, Stage::Parse) => Some((Stream::Stderr, format!("❌ {}", d.message))),
+        (report::Severity::Error, _) => Some((Stream::Stderr, format!("  ❌ {}", d.message))),
+        (report::Severity::Warning, _) => Some((Stream::Stderr, format!("  ⚠️  {}", d.message))),
+        (_, Stage::Cache) => verbose.then(|| (Stream::Stdout, pruned_line(".mumei_cache", &d.message))),
+        (_, Stage::Dependencies) => Some((Stream::Stdout, format!("  📦 {}", d.message))),
+        (_, Stage::Monomorphization) => Some((Stream::Stdout, format!("  🔬 {}", d.message))),
+        _ => Some((Stream::Stdout, format!("  {}", d.message))),
+    }
+}
 
-    let mut imports: Vec<ImportDecl> = Vec::new();
-    for item in &items {
-        match item {
-            Item::Import(decl) => imports.push(decl.clone()),
-            Item::TypeDef(refined_type) => module_env.register_type(refined_type),
-            Item::StructDef(struct_def) => module_env.register_struct(struct_def),
-            Item::EnumDef(enum_def) => module_env.register_enum(enum_def),
-            Item::Atom(atom) => module_env.register_atom(atom),
-            Item::TraitDef(trait_def) => module_env.register_trait(trait_def),
-            Item::ImplDef(impl_def) => module_env.register_impl(impl_def),
-            Item::ResourceDef(resource_def) => module_env.register_resource(resource_def),
+fn print_diagnostics(diagnostics: &[pipeline::Diagnostic]) {
+    let verbose = VERBOSE.load(Ordering::Relaxed);
+    for d in diagnostics {
+        match render_diagnostic(d, verbose) {
+            Some((Stream::Stdout, line)) => println!("{}", line),
+            Some((Stream::Stderr, line)) => eprintln!("{}", line),
+            None => {}
         }
     }
-
-    (items, module_env, imports)
 }
 
 // =============================================================================
 // mumei check — parse + resolve + monomorphize only
 // =============================================================================
 
-fn cmd_check(input: &str) {
+fn cmd_check(input: &str, explain_parse: bool) {
     println!("🗡️  Mumei check: parsing and resolving '{}'...", input);
-    let (items, _module_env, _imports) = load_and_prepare(input);
+    if explain_parse {
+        // resolve の前に表示する（未知の名前で止まる場合も、無視された箇所を先に確認できる）
+        print_parse_explanation(&load_source(input));
+    }
+    let (items, module_env, _imports) = load_and_prepare(input);
 
     let mut type_count = 0;
     let mut struct_count = 0;
@@ -251,20 +511,66 @@ fn cmd_check(input: &str) {
         match item {
             Item::Import(decl) => {
                 let alias_str = decl.alias.as_deref().unwrap_or("(none)");
-                println!("  📦 Import: '{}' as '{}'", decl.path, alias_str);
+                let using_str = decl.using.as_ref().map(|names| format!(" using {{ {} }}", names.join(", "))).unwrap_or_default();
+                println!("  📦 Import: '{}' as '{}'{}", decl.path, alias_str, using_str);
             }
             Item::TypeDef(t) => { type_count += 1; println!("  ✨ Type: '{}' ({})", t.name, t._base_type); }
-            Item::StructDef(s) => { struct_count += 1; println!("  🏗️  Struct: '{}'", s.name); }
+            Item::StructDef(s) => {
+                struct_count += 1;
+                println!("  🏗️  Struct: '{}'", s.name);
+                if let Some(invariant) = &s.invariant {
+                    println!("     invariant: {}", invariant);
+                }
+            }
             Item::EnumDef(e) => { enum_count += 1; println!("  🔷 Enum: '{}'", e.name); }
             Item::TraitDef(t) => { trait_count += 1; println!("  📜 Trait: '{}'", t.name); }
             Item::ImplDef(i) => { println!("  🔧 Impl: {} for {}", i.trait_name, i.target_type); }
             Item::Atom(a) => {
                 atom_count += 1;
-                let async_marker = if a.is_async { " (async)" } else { "" };
+                for source in [&a.requires, &a.ensures, &a.body_expr] {
+                    if let Err(e) = parser::validate_literals(source) {
+                        eprintln!("  ❌ Atom '{}': {}", a.name, e);
+                        std::process::exit(1);
+                    }
+                }
+                if let Err(e) = parser::validate_names(a).and_then(|_| parser::validate_quantifiers(a)) {
+                    eprintln!("  ❌ {}", e);
+                    std::process::exit(1);
+                }
+                if let Err(e) = parser::atom_assumptions(a) {
+                    eprintln!("  ❌ {}", e);
+                    std::process::exit(1);
+                }
+                for warning in parser::quantifier_shadowing(a) {
+                    eprintln!("  ⚠️  Warning (atom '{}'): {}", a.name, warning);
+                }
+                // 充足不能な requires（空虚な証明）と、どこにも使われない変数だけの requires の連言を、
+                // body を検証せずに短いタイムアウトの Z3 で検査する
+                match verification::check_requires(a, &module_env) {
+                    Ok(warnings) => {
+                        for warning in warnings {
+                            eprintln!("  ⚠️  Warning (atom '{}'): {}", a.name, warning);
+                        }
+                    }
+                    Err(e) => {
+                        eprintln!("  ❌ {}", e);
+                        std::process::exit(1);
+                    }
+                }
+                let async_marker = match (a.is_async, a.is_total) {
+                    (true, true) => " (async, total)",
+                    (true, false) => " (async)",
+                    (false, true) => " (total)",
+                    (false, false) => "",
+                };
                 let res_marker = if !a.resources.is_empty() {
                     format!(" [resources: {}]", a.resources.join(", "))
                 } else { String::new() };
                 println!("  ✨ Atom: '{}'{}{}", a.name, async_marker, res_marker);
+                if let Some(clause) = &a.where_clause {
+                    println!("     where: {}", parser::normalize_contract(clause));
+                }
+                println!("     targets: {}", a.targets.effective().join(", "));
             }
             Item::ResourceDef(r) => {
                 let mode_str = match r.mode {
@@ -273,95 +579,495 @@ fn cmd_check(input: &str) {
                 };
                 println!("  🔒 Resource: '{}' (priority={}, mode={})", r.name, r.priority, mode_str);
             }
+            Item::Export(decl) => println!("  📤 Export: {}", decl.names.join(", ")),
+            Item::TestDef(test) => println!("  🧪 Test: '{}' ({} assertion(s))", test.name, test.assertions.len()),
         }
     }
     println!("✅ Check passed: {} types, {} structs, {} enums, {} traits, {} atoms",
         type_count, struct_count, enum_count, trait_count, atom_count);
 }
 
+/// `mumei check --explain-parse`: item ごとの抽出範囲と、どの item にも消費されなかった領域
+fn print_parse_explanation(source: &str) {
+    let explanation = parser::explain_parse(source);
+    let lines = |r: &parser::SourceRegion| if r.start_line == r.end_line {
+        format!("L{}", r.start_line)
+    } else {
+        format!("L{}-L{}", r.start_line, r.end_line)
+    };
+    println!("  🔎 Parse explanation:");
+    for r in &explanation.claimed {
+        println!("    📍 {:<10} {} '{}'", lines(r), r.kind, r.name);
+    }
+    if explanation.unclaimed.is_empty() {
+        println!("  ✅ All non-whitespace source text was consumed by an item.");
+        return;
+    }
+    println!("  ⚠️  {} region(s) were not consumed by any item (silently ignored by the parser):", explanation.unclaimed.len());
+    for r in &explanation.unclaimed {
+        println!("    ❓ {:<10} {}", lines(r), r.snippet);
+    }
+}
+
 // =============================================================================
 // mumei verify — Z3 verification only (no codegen, no transpile)
 // =============================================================================
 
-fn cmd_verify(input: &str) {
+/// `--jobs` の値（省略時は CPU コア数、0 は 1 とみなす）
+fn resolve_jobs(jobs: Option<usize>) -> usize {
+    jobs.unwrap_or_else(pipeline::default_jobs).max(1)
+}
+
+/// `mumei verify` の進捗表示（ライブラリ API の VerifyProgress）
+struct VerifyPrinter {
+    profile: bool,
+}
+
+impl mumei::VerifyProgress for VerifyPrinter {
+    fn diagnostic(&mut self, diagnostic: &pipeline::Diagnostic) {
+        print_diagnostics(std::slice::from_ref(diagnostic));
+    }
+
+    fn event(&mut self, event: pipeline::VerifyEvent<'_>, module_env: &verification::ModuleEnv) {
+        print_verify_event(event, module_env, self.profile);
+    }
+}
+
+/// mumei::verify_items_with の CLI 層: 表示・report.json / SARIF の保存・終了コードだけを受け持つ
+fn cmd_verify(input: &str, ce_format: verification::CounterexampleFormat, sarif_path: Option<&str>, suggest_invariant: bool, profile: bool, jobs: usize) {
     check_z3_available();
     println!("🗡️  Mumei verify: verifying '{}'...", input);
-    let (items, mut module_env, _imports) = load_and_prepare(input);
+    let source = load_source(input);
+    let items = mumei::parse(&source).unwrap_or_else(|e| {
+        eprintln!("❌ {}", e.render(&source, input));
+        std::process::exit(1);
+    });
 
+    // 検証ループ（Incremental Build: ビルドキャッシュの読み込み・刈り込み・保存を含む）
     let output_dir = Path::new(".");
-    let input_path = Path::new(input);
-    let base_dir = input_path.parent().unwrap_or(Path::new("."));
-    let mut verified = 0;
-    let mut failed = 0;
-    let mut skipped = 0;
+    let options = mumei::VerifyOptions {
+        path: PathBuf::from(input),
+        manifest: pipeline::ManifestLookup::CurrentDir,
+        jobs,
+        counterexample_format: ce_format,
+        build_cache: true,
+        output_dir: output_dir.to_path_buf(),
+        ..mumei::VerifyOptions::default()
+    };
+    let verification = mumei::verify_items_with(&items, &options, &mut VerifyPrinter { profile });
+    if verification.prepare_failed() {
+        std::process::exit(1);
+    }
+    let (summary, report) = (verification.summary, verification.report);
+    log_pruned(".mumei_build_cache", &summary.pruned);
+    let (verified, failed, skipped) = (summary.verified, summary.failed, summary.skipped);
+    // trusted atom（body を検証せず契約を仮定したもの）は監査できるように別に数える
+    let trusted = if summary.trusted > 0 { format!(", {} trusted (assumed)", summary.trusted) } else { String::new() };
+    if suggest_invariant {
+        print_loop_suggestions(&items);
+    }
 
-    // Incremental Build: ビルドキャッシュをロード
-    let build_cache = resolver::load_build_cache(base_dir);
-    let mut new_cache = std::collections::HashMap::new();
+    // モジュール全体のレポート（report.json: crate::report のスキーマ）で atom 単位の report.json を上書きする
+    if let Err(e) = report.save(output_dir) {
+        eprintln!("  ⚠️  Failed to write {}: {}", report::REPORT_FILE, e);
+    }
+    save_sarif(&report, sarif_path);
 
-    for item in &items {
-        match item {
-            Item::ImplDef(impl_def) => {
-                println!("  🔧 Verifying impl {} for {}...", impl_def.trait_name, impl_def.target_type);
-                match verification::verify_impl(impl_def, &module_env) {
-                    Ok(_) => {
-                        println!("    ✅ Laws verified");
-                        verified += 1;
-                    }
-                    Err(e) => {
-                        eprintln!("    ❌ Law verification failed: {}", e);
-                        failed += 1;
+    println!("");
+    if failed > 0 {
+        eprintln!("❌ Verification: {} passed, {} failed, {} skipped (cached){}", verified, failed, skipped, trusted);
+        std::process::exit(1);
+    }
+    if skipped > 0 {
+        println!("✅ Verification passed: {} verified{}, {} skipped (unchanged) ⚡", verified, trusted, skipped);
+    } else {
+        println!("✅ Verification passed: {} item(s) verified{}", verified, trusted);
+    }
+}
+
+/// `mumei verify --suggest-invariant`: 再帰する atom ごとに等価なループ形を提案する（ソースは変更しない）
+fn print_loop_suggestions(items: &[Item]) {
+    for item in items {
+        if let Item::Atom(atom) = item {
+            if tailrec::self_calls(atom).total == 0 {
+                continue;
+            }
+            match tailrec::suggest_loop(atom) {
+                Ok(suggestion) => {
+                    println!("  💡 Loop formulation for tail-recursive atom '{}':", atom.name);
+                    for line in suggestion.lines() {
+                        println!("      {}", line);
                     }
                 }
+                Err(reason) => println!("  💡 No loop suggestion: {}", reason),
             }
-            Item::Atom(atom) => {
-                if module_env.is_verified(&atom.name) {
-                    println!("  ⚖️  '{}': skipped (imported, contract-trusted)", atom.name);
-                } else {
-                    // Incremental Build: atom のハッシュを計算してキャッシュと比較
-                    let atom_hash = resolver::compute_atom_hash(atom);
-                    new_cache.insert(atom.name.clone(), atom_hash.clone());
-
-                    if let Some(cached_hash) = build_cache.get(&atom.name) {
-                        if *cached_hash == atom_hash {
-                            println!("  ⚖️  '{}': skipped (unchanged, cached) ⏩", atom.name);
-                            module_env.mark_verified(&atom.name);
-                            skipped += 1;
-                            continue;
-                        }
-                    }
+        }
+    }
+}
 
-                    match verification::verify(atom, output_dir, &module_env) {
-                        Ok(_) => {
-                            println!("  ⚖️  '{}': verified ✅", atom.name);
-                            module_env.mark_verified(&atom.name);
-                            verified += 1;
-                        }
-                        Err(e) => {
-                            eprintln!("  ❌ '{}': verification failed: {}", atom.name, e);
-                            // 検証失敗した atom はキャッシュから除外
-                            new_cache.remove(&atom.name);
-                            failed += 1;
-                        }
-                    }
+/// `mumei verify` の進捗表示
+fn print_verify_event(event: pipeline::VerifyEvent<'_>, module_env: &verification::ModuleEnv, profile: bool) {
+    for (stream, line) in render_verify_event(&event, profile) {
+        match stream {
+            Stream::Stdout => println!("{}", line),
+            Stream::Stderr => eprintln!("{}", line),
+        }
+    }
+    if let pipeline::VerifyEvent::ImplStarted(impl_def) = event {
+        log_law_sizes(impl_def, module_env, profile);
+    }
+}
+
+/// verification::verify が返した警告を `mumei verify` と同じ形式で表示する
+fn print_verify_warnings(name: &str, warnings: &[String]) {
+    for warning in warnings {
+        for (_, line) in render_verify_event(&pipeline::VerifyEvent::Warning(name, warning), false) {
+            eprintln!("{}", line);
+        }
+    }
+}
+
+/// profile なら（`--profile`）atom ごとに時間のかかった義務を並べる
+fn render_verify_event(event: &pipeline::VerifyEvent<'_>, profile: bool) -> Vec<(Stream, String)> {
+    use pipeline::{Outcome, VerifyEvent};
+    let mut lines = Vec::new();
+    match event {
+        VerifyEvent::ImplStarted(impl_def) => {
+            lines.push((Stream::Stdout, format!("  🔧 Verifying impl {} for {}...", impl_def.trait_name, impl_def.target_type)));
+        }
+        VerifyEvent::Finished(Item::ImplDef(_), verification) => match &verification.outcome {
+            Outcome::Failed(e) => lines.push((Stream::Stderr, format!("    ❌ Law verification failed: {}", e))),
+            Outcome::Cached => lines.push((Stream::Stdout, "    ✅ Laws verified (cached) ⏩".to_string())),
+            _ => lines.push((Stream::Stdout, "    ✅ Laws verified".to_string())),
+        },
+        VerifyEvent::Finished(item, verification) => {
+            let name = match item {
+                Item::Atom(atom) => atom.name.as_str(),
+                _ => return lines,
+            };
+            let cache = &verification.result.cache;
+            if cache.obligations_reused > 0 {
+                lines.push((Stream::Stdout, format!("  ♻️  '{}': {} obligation(s) reused, {} re-solved", name, cache.obligations_reused, cache.obligations_solved)));
+            }
+            lines.push(match &verification.outcome {
+                Outcome::Imported => (Stream::Stdout, format!("  ⚖️  '{}': skipped (imported, contract-trusted)", name)),
+                Outcome::Cached => (Stream::Stdout, format!("  ⚖️  '{}': skipped (unchanged, cached) ⏩", name)),
+                Outcome::Verified if !verification.result.assumptions.is_empty() => (Stream::Stdout, format!(
+                    "  ⚖️  '{}': verified-with-assumptions ({}) ✅", name, verification.result.assumptions.len()
+                )),
+                Outcome::Verified => (Stream::Stdout, format!("  ⚖️  '{}': verified ✅", name)),
+                Outcome::Failed(e) => (Stream::Stderr, format!("  ❌ '{}': verification failed: {}", name, e)),
+            });
+            if !matches!(verification.outcome, Outcome::Failed(_)) {
+                for assumption in &verification.result.assumptions {
+                    lines.push((Stream::Stdout, format!("     📌 assume {}: {}", assumption.condition, assumption.justification)));
+                }
+            }
+            if profile && !verification.result.slowest_obligations.is_empty() {
+                lines.push((Stream::Stdout, "     ⏱️  slowest obligations:".to_string()));
+                for timing in &verification.result.slowest_obligations {
+                    lines.push((Stream::Stdout, format!("       {:>8.1} ms  {}: {}", timing.duration_ms, timing.kind, timing.label)));
+                }
+            }
+        }
+        VerifyEvent::Warning(name, message) => {
+            lines.push((Stream::Stderr, format!("  ⚠️  '{}': {}", name, message)));
+        }
+    }
+    lines
+}
+
+// =============================================================================
+// mumei test — concrete example assertions
+// =============================================================================
+
+fn cmd_test(input: &str) {
+    // Z3 / LLVM を使わないので check_z3_available は呼ばない
+    println!("🧪 Mumei test: '{}'", input);
+    let (items, module_env, _imports) = load_and_prepare(input);
+    let tests: Vec<&parser::TestDef> = items.iter()
+        .filter_map(|item| match item {
+            Item::TestDef(test) => Some(test),
+            _ => None,
+        })
+        .collect();
+    if tests.is_empty() {
+        println!("⚠️  No test blocks found in '{}'", input);
+        return;
+    }
+
+    let (mut passed, mut failed, mut errors, mut failed_tests) = (0, 0, 0, 0);
+    for test in &tests {
+        let outcomes = interpreter::run_test(test, &module_env);
+        let ok = outcomes.iter().all(|(_, outcome)| *outcome == interpreter::AssertionOutcome::Passed);
+        if ok {
+            println!("  ✅ {}: {} assertion(s) passed", test.name, outcomes.len());
+        } else {
+            failed_tests += 1;
+            eprintln!("  ❌ {}", test.name);
+        }
+        for (source, outcome) in &outcomes {
+            match outcome {
+                interpreter::AssertionOutcome::Passed => passed += 1,
+                interpreter::AssertionOutcome::Failed(detail) => {
+                    failed += 1;
+                    eprintln!("      ❌ assert {} ({})", source, detail);
+                }
+                interpreter::AssertionOutcome::Error(message) => {
+                    errors += 1;
+                    eprintln!("      💥 {}: {}", source, message);
+                }
+            }
+        }
+    }
+
+    println!("");
+    if failed_tests > 0 {
+        eprintln!("❌ Tests: {} of {} test(s) failed ({} assertion(s) passed, {} failed, {} error(s))",
+            failed_tests, tests.len(), passed, failed, errors);
+        std::process::exit(1);
+    }
+    println!("✅ Tests passed: {} test(s), {} assertion(s)", tests.len(), passed);
+}
+
+// =============================================================================
+// mumei selftest — randomized differential testing
+// =============================================================================
+
+fn cmd_selftest(input: &str, samples: usize, seed: Option<u64>, no_rust: bool) {
+    check_z3_available();
+    let seed = seed.unwrap_or_else(|| {
+        std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map(|d| d.as_nanos() as u64).unwrap_or(0)
+    });
+    println!("🧪 Mumei selftest: '{}' ({} samples/atom, seed {})", input, samples, seed);
+    let (items, module_env, _imports) = load_and_prepare(input);
+
+    // 検証の副作用（report.json 等）はカレントディレクトリではなく作業ディレクトリに書く
+    let work_dir = std::env::temp_dir().join(format!("mumei_selftest_{}", std::process::id()));
+    let _ = fs::create_dir_all(&work_dir);
+    let cfg = selftest::SelftestConfig { samples };
+    let mut rng = selftest::Rng::new(seed);
+    let mut runs: Vec<selftest::AtomRun> = Vec::new();
+    let mut skipped = 0;
+
+    for item in &items {
+        let atom = match item {
+            Item::Atom(atom) => atom,
+            _ => continue,
+        };
+        if atom.trust_level != parser::TrustLevel::Verified {
+            println!("  ⏭️  '{}': skipped (body not verified)", atom.name);
+            skipped += 1;
+            continue;
+        }
+        match verification::verify(atom, &work_dir, &module_env) {
+            Ok(warnings) => print_verify_warnings(&atom.name, &warnings),
+            Err(e) => {
+                println!("  ⏭️  '{}': skipped (verification failed: {})", atom.name, e);
+                skipped += 1;
+                continue;
+            }
+        }
+        match selftest::run_atom(atom, &module_env, &cfg, &mut rng) {
+            selftest::AtomSelftest::Skipped { reason } => {
+                println!("  ⏭️  '{}': skipped ({})", atom.name, reason);
+                skipped += 1;
+            }
+            selftest::AtomSelftest::Ran(run) => runs.push(run),
+        }
+    }
+
+    // Rust ハーネス: トランスパイル結果を同じ入力で実行して Interpreter と比較する
+    let mut rust_error: Option<String> = None;
+    let rust_enabled = !no_rust && runs.iter().any(|r| r.rust_note.is_none());
+    if rust_enabled && !selftest::rustc_available() {
+        println!("  ⚠️  rustc not found: skipping comparison against transpiled Rust");
+    } else if rust_enabled {
+        let harness = selftest::rust_harness(&selftest::rust_bundle(&items, &module_env), &runs);
+        match selftest::run_rust_harness(&harness, &work_dir) {
+            Ok(stdout) => selftest::compare_rust_output(&mut runs, &stdout),
+            Err(e) => rust_error = Some(e),
+        }
+    }
+    let _ = fs::remove_dir_all(&work_dir);
+
+    let mut failed = 0;
+    for run in &runs {
+        let rust = if no_rust || rust_error.is_some() {
+            String::new()
+        } else {
+            match &run.rust_note {
+                Some(note) => format!(", rust skipped ({})", note),
+                None => ", rust compared".to_string(),
+            }
+        };
+        let ensures = match &run.ensures_note {
+            Some(note) => format!(", ensures not checked ({})", note),
+            None => String::new(),
+        };
+        if run.findings.is_empty() {
+            println!("  ✅ '{}': {} input(s){}{}", run.atom.name, run.cases.len(), ensures, rust);
+        } else {
+            failed += 1;
+            eprintln!("  ❌ '{}': {} finding(s) over {} input(s){}{}", run.atom.name, run.findings.len(), run.cases.len(), ensures, rust);
+            for finding in &run.findings {
+                eprintln!("      - {}", finding);
+            }
+        }
+    }
+
+    println!("");
+    if let Some(e) = &rust_error {
+        eprintln!("❌ {}", e);
+    }
+    if failed > 0 || rust_error.is_some() {
+        eprintln!("❌ Selftest: {} atom(s) diverged, {} passed, {} skipped (seed {})", failed, runs.len() - failed, skipped, seed);
+        std::process::exit(1);
+    }
+    println!("✅ Selftest passed: {} atom(s) consistent, {} skipped", runs.len(), skipped);
+}
+
+// =============================================================================
+// mumei query — module information for build systems
+// =============================================================================
+
+fn cmd_query(input: &str, subqueries: query::Subqueries) {
+    match query::run(Path::new(input), subqueries) {
+        Ok(value) => println!("{}", serde_json::to_string_pretty(&value).unwrap_or_default()),
+        Err(e) => {
+            eprintln!("❌ Query failed: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+// =============================================================================
+// mumei bench — benchmark harnesses for the generated code
+// =============================================================================
+
+fn cmd_bench(input: &str, requested: &[String], out_dir: Option<&str>, samples: usize, seed: Option<u64>, no_run: bool) {
+    check_z3_available();
+    let seed = seed.unwrap_or_else(|| {
+        std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map(|d| d.as_nanos() as u64).unwrap_or(0)
+    });
+    println!("⏱️  Mumei bench: '{}' ({} inputs/atom, seed {})", input, samples, seed);
+    let (items, module_env, _imports) = load_and_prepare(input);
+
+    for name in requested {
+        if module_env.get_atom(name).is_none() {
+            eprintln!("❌ Error: atom '{}' not found in '{}'", name, input);
+            std::process::exit(1);
+        }
+    }
+
+    // 検証の副作用（report.json 等）はハーネスとは別の作業ディレクトリに書く
+    let work_dir = std::env::temp_dir().join(format!("mumei_bench_{}", std::process::id()));
+    let _ = fs::create_dir_all(&work_dir);
+    let mut rng = selftest::Rng::new(seed);
+    let mut plans: Vec<bench::BenchAtom> = Vec::new();
+    for item in &items {
+        let atom = match item {
+            Item::Atom(atom) if requested.is_empty() || requested.contains(&atom.name) => atom,
+            _ => continue,
+        };
+        if atom.trust_level != parser::TrustLevel::Verified {
+            println!("  ⏭️  '{}': skipped (body not verified)", atom.name);
+            continue;
+        }
+        match verification::verify(atom, &work_dir, &module_env) {
+            Ok(warnings) => print_verify_warnings(&atom.name, &warnings),
+            Err(e) => {
+                println!("  ⏭️  '{}': skipped (verification failed: {})", atom.name, e);
+                continue;
+            }
+        }
+        match bench::plan_atom(atom, &module_env, samples, &mut rng) {
+            Ok(plan) => {
+                println!("  ⚖️  '{}({})': {} input(s) sampled from requires", plan.name, plan.params.join(", "), plan.inputs.len());
+                for (backend, note) in &plan.notes {
+                    println!("      ⏭️  {}: skipped ({})", backend.label(), note);
                 }
+                plans.push(plan);
             }
-            _ => {}
+            Err(reason) => println!("  ⏭️  '{}': skipped ({})", atom.name, reason),
         }
     }
+    let _ = fs::remove_dir_all(&work_dir);
+    if plans.is_empty() {
+        eprintln!("❌ Bench: no atom could be benchmarked");
+        std::process::exit(1);
+    }
+
+    let harness_dir = match out_dir {
+        Some(dir) => std::path::PathBuf::from(dir),
+        None => std::env::temp_dir().join(format!("mumei_bench_harness_{}", std::process::id())),
+    };
+    let written = bench::write_harnesses(&items, &module_env, &plans, &harness_dir).unwrap_or_else(|e| {
+        eprintln!("❌ Error: could not write benchmark harnesses to '{}': {}", harness_dir.display(), e);
+        std::process::exit(1);
+    });
+    for (backend, dir) in &written {
+        println!("  📄 {} harness: {}", backend.label(), dir.display());
+    }
+    if no_run {
+        println!("✅ Bench harnesses generated (not run)");
+        return;
+    }
+
+    // ツールチェーンがあるバックエンドだけ実行する
+    let mut results = Vec::new();
+    let mut failed = false;
+    for (backend, dir) in &written {
+        if !backend.available() {
+            println!("  ⚠️  {}: toolchain not found, skipping (see `mumei inspect`)", backend.label());
+            continue;
+        }
+        println!("  🏃 {}: running...", backend.label());
+        match bench::run_backend(*backend, dir) {
+            Ok(times) => results.push((*backend, times)),
+            Err(e) => {
+                eprintln!("  ❌ {}", e);
+                failed = true;
+            }
+        }
+    }
+    if out_dir.is_none() {
+        let _ = fs::remove_dir_all(&harness_dir);
+    }
+
+    println!("");
+    if results.is_empty() {
+        eprintln!("❌ Bench: no backend could be run");
+        std::process::exit(1);
+    }
+    print!("{}", bench::comparison_table(&plans, &results));
+    if failed {
+        std::process::exit(1);
+    }
+}
 
-    // Incremental Build: キャッシュを保存
-    resolver::save_build_cache(base_dir, &new_cache);
+// =============================================================================
+// mumei report — render a saved report.json
+// =============================================================================
 
-    println!("");
-    if failed > 0 {
-        eprintln!("❌ Verification: {} passed, {} failed, {} skipped (cached)", verified, failed, skipped);
+fn cmd_report(path: &str, filter: report::ReportFilter, baseline: Option<&str>) {
+    let load = |p: &str| report::Report::load(Path::new(p)).unwrap_or_else(|e| {
+        eprintln!("❌ Error: {}", e);
         std::process::exit(1);
-    }
-    if skipped > 0 {
-        println!("✅ Verification passed: {} verified, {} skipped (unchanged) ⚡", verified, skipped);
-    } else {
-        println!("✅ Verification passed: {} item(s) verified", verified);
+    });
+    let current = load(path);
+    print!("{}", current.render(filter));
+
+    if let Some(baseline_path) = baseline {
+        let baseline = load(baseline_path);
+        let diff = report::diff(&baseline, &current);
+        println!("");
+        println!("🔀 Diff: '{}' → '{}'", baseline_path, path);
+        print!("{}", diff.render());
+        // CI 向け: 新たに失敗した atom があれば非ゼロで終了する
+        if !diff.newly_failing.is_empty() {
+            std::process::exit(1);
+        }
     }
 }
 
@@ -387,6 +1093,7 @@ fn cmd_init(name: &str) {
     let toml_content = format!(r#"[package]
 name = "{}"
 version = "0.1.0"
+edition = "{}"
 # authors = ["Your Name"]
 # description = "A formally verified Mumei project"
 # repository = "https://github.com/mumei-lang/your-project"
@@ -398,16 +1105,31 @@ version = "0.1.0"
 targets = ["rust", "go", "typescript"]
 verify = true
 max_unroll = 3
+max_mono_depth = 16
+# require_explicit_exports = false
+# strict_ref_args = false
+# runtime_checks = false
+[build.rust]
+# derive_serde = false
+[build.ts]
+# declarations = false
+# emit_validators = false
 [proof]
 cache = true
 timeout_ms = 10000
-"#, name);
+timeout_grace_ms = 5000
+# max_memory_mb = 8192
+# max_law_expansion_nodes = 10000
+# deny_assume = false
+# strict_resources = false
+"#, name, migrate::latest_edition());
     fs::write(project_dir.join("mumei.toml"), toml_content).unwrap();
 
     // .gitignore
     let gitignore_content = r#"# Mumei build artifacts
 dist/
 *.ll
+*.o
 
 # Verification cache (regenerated automatically)
 .mumei_build_cache
@@ -519,397 +1241,387 @@ body: {{
 // mumei inspect — environment check
 // =============================================================================
 
-fn cmd_inspect() {
-    use std::process::Command as Cmd;
-
-    println!("🔍 Mumei Inspect: checking development environment...");
-    println!();
+fn cmd_inspect(z3_override: Option<&str>, json: bool, fix: bool, assume_yes: bool) {
+    if !json {
+        println!("🔍 Mumei Inspect: checking development environment...");
+        println!();
+    }
 
-    let mut ok_count = 0;
-    let mut warn_count = 0;
-    let mut fail_count = 0;
-
-    // --- 1. Mumei compiler version ---
-    println!("  Mumei compiler: v{}", env!("CARGO_PKG_VERSION"));
-    ok_count += 1;
-
-    // --- 2. Z3 solver ---
-    match Cmd::new("z3").arg("--version").output() {
-        Ok(output) => {
-            let version = String::from_utf8_lossy(&output.stdout);
-            let version = version.trim();
-            if version.is_empty() {
-                println!("  ⚠️  Z3: installed but version unknown");
-                warn_count += 1;
+    let mut checks = inspect::run_checks(z3_override);
+    let mut fixes = Vec::new();
+    if fix {
+        fixes = inspect::apply_fixes(&checks, assume_yes);
+        if !json {
+            if fixes.is_empty() {
+                println!("  🔧 Nothing to fix automatically");
             } else {
-                println!("  ✅ Z3: {}", version);
-                ok_count += 1;
+                inspect::print_fixes(&fixes);
             }
+            println!();
         }
-        Err(_) => {
-            println!("  ❌ Z3: not found");
-            println!("     Install: brew install z3");
-            fail_count += 1;
+        // 終了コードは修復後に残ったエラーで決める
+        if !fixes.is_empty() {
+            checks = inspect::run_checks(z3_override);
         }
     }
+    let summary = inspect::Summary::of(&checks);
 
-    // --- 3. LLVM ---
-    let llvm_found = ["llc-17", "llc"].iter().any(|cmd| {
-        Cmd::new(cmd).arg("--version").output().is_ok()
-    });
-    if llvm_found {
-        // Try to get version
-        let version_output = Cmd::new("llc-17").arg("--version").output()
-            .or_else(|_| Cmd::new("llc").arg("--version").output());
-        if let Ok(output) = version_output {
-            let version = String::from_utf8_lossy(&output.stdout);
-            let first_line = version.lines().next().unwrap_or("unknown");
-            println!("  ✅ LLVM: {}", first_line.trim());
-        } else {
-            println!("  ✅ LLVM: installed");
+    if json {
+        println!("{}", serde_json::to_string_pretty(&inspect::to_json(&checks, &fixes)).unwrap_or_default());
+        if summary.errors > 0 {
+            std::process::exit(1);
         }
-        ok_count += 1;
+        return;
+    }
+
+    inspect::print_text(&checks);
+
+    // --- Summary ---
+    println!();
+    if summary.errors > 0 {
+        println!("❌ Inspect: {} ok, {} warnings, {} errors", summary.ok, summary.warnings, summary.errors);
+        println!("   Fix the errors above to use Mumei.");
+        std::process::exit(1);
+    } else if summary.warnings > 0 {
+        println!("✅ Inspect: {} ok, {} warnings — Mumei is ready (optional tools missing)", summary.ok, summary.warnings);
     } else {
-        println!("  ❌ LLVM: not found");
-        println!("     Install: brew install llvm@17");
-        fail_count += 1;
+        println!("✅ Inspect: {} ok — all tools available", summary.ok);
     }
+}
 
-    // --- 4. Rust toolchain ---
-    match Cmd::new("rustc").arg("--version").output() {
-        Ok(output) => {
-            let version = String::from_utf8_lossy(&output.stdout);
-            println!("  ✅ Rust: {}", version.trim());
-            ok_count += 1;
-        }
-        Err(_) => {
-            println!("  ⚠️  Rust: not found (optional, for generated .rs syntax check)");
-            warn_count += 1;
-        }
+// =============================================================================
+// mumei build — full pipeline (verify + codegen + transpile)
+// =============================================================================
+
+/// `-o` の出力先を検証し、親ディレクトリがなければ作成する。
+/// - 最後の要素（stem）は .ll のファイル名にもそのまま使うため、パス区切りや `..` を含むものは拒否する
+/// - 入力の .mm と同じファイルを指す出力先は拒否する
+fn prepare_build_output(input: &str, output: &str) -> Result<pipeline::BuildOutput, String> {
+    let file_name = output.rsplit(|c: char| c == '/' || c == std::path::MAIN_SEPARATOR).next().unwrap_or("");
+    if file_name.is_empty() || file_name == "." {
+        return Err(format!("output path '{}' does not end in a file name (e.g. 'dist/katana')", output));
+    }
+    if file_name == ".." || file_name.contains('\\') {
+        return Err(format!("output file stem '{}' must not contain path separators or '..'", file_name));
     }
 
-    // --- 5. Go toolchain ---
-    match Cmd::new("go").arg("version").output() {
-        Ok(output) => {
-            let version = String::from_utf8_lossy(&output.stdout);
-            println!("  ✅ Go: {}", version.trim());
-            ok_count += 1;
-        }
-        Err(_) => {
-            println!("  ⚠️  Go: not found (optional, for generated .go compilation)");
-            warn_count += 1;
-        }
+    let output_path = Path::new(output);
+    let stem = output_path.file_stem().and_then(|s| s.to_str()).unwrap_or(file_name).to_string();
+    let dir = match output_path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    let output = pipeline::BuildOutput { dir, stem, created_dir: false };
+
+    // 入力ファイルを上書きする出力先（`-o src/main.mm` など）
+    let same_file = |path: &Path| match (path.canonicalize(), Path::new(input).canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    };
+    let candidates = [output_path.to_path_buf(), output.bundle_path("rs"), output.bundle_path("go"), output.bundle_path("ts"), output.bundle_path("d.ts")];
+    if let Some(path) = candidates.iter().find(|path| same_file(path)) {
+        return Err(format!("output path '{}' collides with the input file '{}'", display_path(path), input));
     }
 
-    // --- 6. Node.js / TypeScript ---
-    match Cmd::new("node").arg("--version").output() {
-        Ok(output) => {
-            let version = String::from_utf8_lossy(&output.stdout);
-            println!("  ✅ Node.js: {}", version.trim());
-            ok_count += 1;
-        }
-        Err(_) => {
-            println!("  ⚠️  Node.js: not found (optional, for generated .ts execution)");
-            warn_count += 1;
-        }
+    if output.dir.exists() {
+        return Ok(output);
     }
+    fs::create_dir_all(&output.dir)
+        .map_err(|e| format!("could not create output directory '{}': {}", display_path(&output.dir), e))?;
+    Ok(pipeline::BuildOutput { created_dir: true, ..output })
+}
 
-    // --- 7. std library ---
-    // resolver と同じ探索順序: cwd → exe隣 → MUMEI_STD_PATH
-    let std_modules = ["prelude.mm", "option.mm", "result.mm", "list.mm",
-                       "stack.mm", "alloc.mm", "container/bounded_array.mm"];
-    let mut std_base_dir: Option<std::path::PathBuf> = None;
+/// 表示用のパス。カレントディレクトリ配下ならそこからの相対パスにする
+fn display_path(path: &Path) -> String {
+    let path = path.strip_prefix(".").unwrap_or(path);
+    let cwd = std::env::current_dir().ok();
+    let relative = cwd.as_deref().and_then(|cwd| path.strip_prefix(cwd).ok());
+    relative.unwrap_or(path).display().to_string()
+}
 
-    if Path::new("std/prelude.mm").exists() {
-        std_base_dir = Some(std::path::PathBuf::from("std"));
+/// mumei.toml の edition と入力ソースが食い違っていれば警告する
+/// （未知の edition、またはその edition の migration で書き換わる古い書き方が残っている）
+fn warn_edition_mismatch(input: &str, edition: Option<&str>) {
+    let edition = edition.unwrap_or(migrate::EDITIONS[0]);
+    if migrate::edition_index(edition).is_none() {
+        println!("  ⚠️  Unknown edition '{}' in mumei.toml (known: {})", edition, migrate::EDITIONS.join(", "));
+        return;
     }
-    if std_base_dir.is_none() {
-        if let Ok(exe_path) = std::env::current_exe() {
-            if let Some(exe_dir) = exe_path.parent() {
-                let candidate = exe_dir.join("std/prelude.mm");
-                if candidate.exists() {
-                    std_base_dir = Some(exe_dir.join("std"));
-                }
-            }
-        }
+    let source = match fs::read_to_string(input) {
+        Ok(source) => source,
+        Err(_) => return,
+    };
+    let outdated = migrate::outdated_migrations(&source, edition);
+    if !outdated.is_empty() {
+        let summaries: Vec<&str> = outdated.iter().map(|m| m.summary).collect();
+        println!("  ⚠️  {} predates edition {} ({}); run `mumei migrate {} --to {}`", input, edition, summaries.join("; "), input, edition);
     }
-    if std_base_dir.is_none() {
-        if let Ok(std_path) = std::env::var("MUMEI_STD_PATH") {
-            let candidate = Path::new(&std_path).join("prelude.mm");
-            if candidate.exists() {
-                std_base_dir = Some(std::path::PathBuf::from(&std_path));
-            }
-        }
+}
+
+/// cmd_build の失敗。main が表示して終了コード 1 で終了する
+#[derive(Debug)]
+enum BuildFailure {
+    /// stderr に `  ❌ ` を付けて表示するメッセージ
+    Message(String),
+    /// diagnostic として表示済み
+    Reported,
+}
+
+impl From<String> for BuildFailure {
+    fn from(message: String) -> Self {
+        BuildFailure::Message(message)
     }
+}
 
-    let mut std_found = 0;
-    let mut std_missing = Vec::new();
-    if let Some(ref base) = std_base_dir {
-        for module in &std_modules {
-            if base.join(module).exists() {
-                std_found += 1;
-            } else {
-                std_missing.push(*module);
-            }
-        }
-    } else {
-        std_missing = std_modules.to_vec();
+/// cmd_build の失敗を表示して終了する
+fn exit_build_failure(failure: BuildFailure) -> ! {
+    if let BuildFailure::Message(message) = failure {
+        eprintln!("  ❌ {}", message);
     }
+    std::process::exit(1);
+}
 
-    if std_missing.is_empty() {
-        let location = std_base_dir.as_ref().map(|p| p.display().to_string()).unwrap_or_else(|| "?".to_string());
-        println!("  ✅ std library: {}/{} modules found ({})", std_found, std_modules.len(), location);
-        ok_count += 1;
-    } else {
-        let hint = if std_base_dir.is_none() {
-            " (set MUMEI_STD_PATH or place std/ next to mumei binary)"
-        } else { "" };
-        println!("  ⚠️  std library: {}/{} modules found (missing: {}){}",
-            std_found, std_modules.len(), std_missing.join(", "), hint);
-        warn_count += 1;
-    }
-
-    // --- 8. mumei.toml (if in project directory) ---
-    if Path::new("mumei.toml").exists() {
-        // mumei.toml が見つかったらパースして内容を表示
-        match manifest::load(Path::new("mumei.toml")) {
-            Ok(m) => {
-                println!("  ✅ mumei.toml: {} v{}", m.package.name, m.package.version);
-                if !m.dependencies.is_empty() {
-                    println!("     dependencies: {}", m.dependencies.keys()
-                        .map(|k| k.as_str()).collect::<Vec<_>>().join(", "));
-                }
-                if !m.build.targets.is_empty() {
-                    println!("     targets: {}", m.build.targets.join(", "));
-                }
-                ok_count += 1;
-            }
-            Err(e) => {
-                println!("  ⚠️  mumei.toml: found but parse error: {}", e);
-                warn_count += 1;
+/// build_items の進捗を表示する（検証の失敗は cmd_build が BuildError から表示する）
+fn print_build_event(event: pipeline::BuildEvent<'_>, module_env: &verification::ModuleEnv, jobs: usize, profile: bool) {
+    use pipeline::{BuildEvent, Tempering};
+    match event {
+        BuildEvent::VerificationStarted(count) => println!("  ⚖️  Verifying {} atom(s) with {} job(s)...", count, jobs),
+        // --- import 宣言（resolver で処理済み） ---
+        BuildEvent::Registered(Item::Import(import_decl)) => {
+            let alias_str = import_decl.alias.as_deref().unwrap_or("(none)");
+            println!("  📦 Import: '{}' as '{}'", import_decl.path, alias_str);
+        }
+        BuildEvent::Registered(Item::TypeDef(refined_type)) => {
+            println!("  ✨ Registered Refined Type: '{}' ({})", refined_type.name, refined_type._base_type);
+        }
+        BuildEvent::Registered(Item::StructDef(struct_def)) => {
+            let field_names: Vec<&str> = struct_def.fields.iter().map(|f| f.name.as_str()).collect();
+            println!("  🏗️  Registered Struct: '{}' (fields: {})", struct_def.name, field_names.join(", "));
+        }
+        BuildEvent::Registered(Item::EnumDef(enum_def)) => {
+            let variant_names: Vec<&str> = enum_def.variants.iter().map(|v| v.name.as_str()).collect();
+            println!("  🔷 Registered Enum: '{}' (variants: {})", enum_def.name, variant_names.join(", "));
+        }
+        BuildEvent::Registered(Item::TraitDef(trait_def)) => {
+            let method_names: Vec<&str> = trait_def.methods.iter().map(|m| m.name.as_str()).collect();
+            let law_names: Vec<&str> = trait_def.laws.iter().map(|(n, _)| n.as_str()).collect();
+            println!("  📜 Registered Trait: '{}' (methods: {}, laws: {})",
+                trait_def.name, method_names.join(", "), law_names.join(", "));
+        }
+        BuildEvent::Registered(Item::ImplDef(impl_def)) => {
+            println!("  🔧 Registered Impl: {} for {}", impl_def.trait_name, impl_def.target_type);
+        }
+        BuildEvent::Registered(Item::ResourceDef(resource_def)) => {
+            let mode_str = match resource_def.mode {
+                parser::ResourceMode::Exclusive => "exclusive",
+                parser::ResourceMode::Shared => "shared",
+            };
+            println!("  🔒 Registered Resource: '{}' (priority={}, mode={})",
+                resource_def.name, resource_def.priority, mode_str);
+        }
+        BuildEvent::Registered(Item::Atom(atom)) => {
+            let async_marker = match (atom.is_async, atom.is_total) {
+                (true, true) => " (async, total)",
+                (true, false) => " (async)",
+                (false, true) => " (total)",
+                (false, false) => "",
+            };
+            let res_marker = if !atom.resources.is_empty() {
+                format!(" [resources: {}]", atom.resources.join(", "))
+            } else { String::new() };
+            println!("  ✨ [1/4] Polishing Syntax: Atom '{}'{}{} identified.", atom.name, async_marker, res_marker);
+            if atom.targets.effective().len() < parser::ATOM_TARGETS.len() {
+                println!("  🎯 Targets: {}", atom.targets.effective().join(", "));
             }
         }
-    } else {
-        println!("  ℹ️  mumei.toml: not found (not in a Mumei project directory)");
-    }
-
-    // --- 9. ~/.mumei/ toolchain ---
-    let mumei_home = manifest::mumei_home();
-    let toolchains_dir = mumei_home.join("toolchains");
-    if toolchains_dir.exists() {
-        let mut tc_list = Vec::new();
-        if let Ok(entries) = fs::read_dir(&toolchains_dir) {
-            for entry in entries.flatten() {
-                if entry.path().is_dir() {
-                    if let Some(name) = entry.file_name().to_str() {
-                        tc_list.push(name.to_string());
-                    }
-                }
+        // export 宣言・test ブロック（mumei test だけが実行する）は表示しない
+        BuildEvent::Registered(_) => {}
+        BuildEvent::ImplStarted(impl_def) => log_law_sizes(impl_def, module_env, profile),
+        BuildEvent::VerificationSkipped(Item::ImplDef(_)) => println!("    ⚖️  Laws verification skipped (verify=false in mumei.toml)"),
+        BuildEvent::VerificationSkipped(_) => println!("  ⚖️  [2/4] Verification: Skipped (verify=false in mumei.toml)."),
+        BuildEvent::Verified(Item::ImplDef(impl_def), verification) => match &verification.outcome {
+            pipeline::Outcome::Failed(_) => {}
+            pipeline::Outcome::Cached => println!("    ✅ Laws verified (cached) for impl {} ⏩", pipeline::impl_name(impl_def)),
+            _ => println!("    ✅ Laws verified for impl {}", pipeline::impl_name(impl_def)),
+        },
+        BuildEvent::Verified(_, verification) => {
+            let cache = &verification.result.cache;
+            if cache.obligations_reused > 0 {
+                // 義務単位キャッシュ: 変更のない義務（例: ensures だけ編集した場合の境界チェック）は再証明しない
+                println!("  ♻️  [2/4] Verification: {} obligation(s) reused from cache, {} re-solved.", cache.obligations_reused, cache.obligations_solved);
+            }
+            for warning in &verification.warnings {
+                eprintln!("  ⚠️  Warning: {}", warning);
+            }
+            match &verification.outcome {
+                // インポートされた atom は検証済み（契約のみ信頼）なのでスキップ
+                pipeline::Outcome::Imported => println!("  ⚖️  [2/4] Verification: Skipped (imported, contract-trusted)."),
+                pipeline::Outcome::Cached => println!("  ⚖️  [2/4] Verification: Skipped (unchanged, cached) ⏩"),
+                pipeline::Outcome::Verified if !verification.result.assumptions.is_empty() => println!(
+                    "  ⚖️  [2/4] Verification: Passed with {} assumption(s) (verified-with-assumptions).",
+                    verification.result.assumptions.len()
+                ),
+                pipeline::Outcome::Verified => println!("  ⚖️  [2/4] Verification: Passed. Logic verified with Z3."),
+                pipeline::Outcome::Failed(_) => return,
+            }
+            for assumption in &verification.result.assumptions {
+                println!("     📌 assume {}: {}", assumption.condition, assumption.justification);
             }
         }
-        if tc_list.is_empty() {
-            println!("  ℹ️  ~/.mumei/toolchains: empty (run `mumei setup`)");
-        } else {
-            tc_list.sort();
-            println!("  ✅ ~/.mumei/toolchains: {}", tc_list.join(", "));
-            ok_count += 1;
+        BuildEvent::Tempering(_, Tempering::Excluded) => println!("  ⚙️  [3/4] Tempering: Skipped (excluded from native targets by @only/@exclude)."),
+        BuildEvent::Tempering(_, Tempering::Queued(path)) => println!("  ⚙️  [3/4] Tempering: Queued for the module {}.", display_path(path)),
+        BuildEvent::Tempering(atom, Tempering::Compiled(path)) => {
+            println!("  ⚙️  [3/4] Tempering: Done. Compiled '{}' to LLVM IR ({}).", atom.name, display_path(path));
+        }
+        BuildEvent::ModuleCompiled(count, path) => {
+            println!("  ⚙️  [3/4] Tempering: Done. Compiled {} atom(s) into one LLVM module ({}).", count, display_path(path));
         }
-    } else {
-        println!("  ℹ️  ~/.mumei/toolchains: not found (run `mumei setup`)");
-    }
-
-    // --- Summary ---
-    println!();
-    if fail_count > 0 {
-        println!("❌ Inspect: {} ok, {} warnings, {} errors", ok_count, warn_count, fail_count);
-        println!("   Fix the errors above to use Mumei.");
-        std::process::exit(1);
-    } else if warn_count > 0 {
-        println!("✅ Inspect: {} ok, {} warnings — Mumei is ready (optional tools missing)", ok_count, warn_count);
-    } else {
-        println!("✅ Inspect: {} ok — all tools available", ok_count);
     }
 }
 
-// =============================================================================
-// mumei build — full pipeline (verify + codegen + transpile)
-// =============================================================================
-
-fn cmd_build(input: &str, output: &str) {
-    check_z3_available();
+#[allow(clippy::too_many_arguments)]
+fn cmd_build(input: &str, output: &str, allow_partial: bool, sarif_path: Option<&str>, require_verified: bool, deny_trusted: bool, jobs: usize, split_ir: bool, profile: bool, emit: native::Emit) -> Result<(), BuildFailure> {
     println!("🗡️  Mumei: Forging the blade (Type System 2.0 + Generics enabled)...");
 
     // mumei.toml の自動検出と設定適用
     let manifest_config = manifest::find_and_load();
     let (build_cfg, proof_cfg) = if let Some((ref _proj_dir, ref m)) = manifest_config {
         println!("  📄 Using mumei.toml: {} v{}", m.package.name, m.package.version);
+        warn_edition_mismatch(input, m.package.edition.as_deref());
         (m.build.clone(), m.proof.clone())
     } else {
         (manifest::BuildConfig::default(), manifest::ProofConfig::default())
     };
 
-    let (items, mut module_env, imports) = load_and_prepare(input);
+    // --require-verified: 検証をスキップした成果物・未検証の atom を含む成果物を作らない
+    if require_verified && !build_cfg.verify {
+        return Err("--require-verified: verification is disabled (verify=false in mumei.toml).".to_string().into());
+    }
 
-    let output_path = Path::new(output);
-    let output_dir = output_path.parent().unwrap_or(Path::new("."));
-    let file_stem = output_path.file_stem().and_then(|s| s.to_str()).unwrap_or(output);
-    let input_path = Path::new(input);
-    let build_base_dir = input_path.parent().unwrap_or(Path::new("."));
+    // 出力先の検証とディレクトリの作成（検証に時間をかける前に失敗させる）
+    let build_output = prepare_build_output(input, output).map_err(|e| format!("Invalid output path: {}", e))?;
+    if build_output.created_dir {
+        println!("  📁 Created output directory: {}", display_path(&build_output.dir));
+    }
 
-    // Incremental Build: ビルドキャッシュをロード（proof.cache が false ならスキップ）
-    let build_cache = if proof_cfg.cache {
-        resolver::load_build_cache(build_base_dir)
+    // --emit obj|exe: llc が見つからなければ検証の前に失敗させる（MUMEI_LLC で上書きできる）
+    let llc = if emit.needs_llc() {
+        Some(setup::locate_llc().map_err(|e| format!("--emit {}: {}", emit.as_str(), e))?)
     } else {
-        std::collections::HashMap::new()
+        None
     };
-    let mut build_cache_new = std::collections::HashMap::new();
-
-    // [build] targets から有効なトランスパイル言語を決定
-    let enable_rust = build_cfg.targets.iter().any(|t| t == "rust");
-    let enable_go = build_cfg.targets.iter().any(|t| t == "go");
-    let enable_ts = build_cfg.targets.iter().any(|t| t == "typescript" || t == "ts");
-    let skip_verify = !build_cfg.verify;
-
-    let mut atom_count = 0;
-
-    // Transpiler バンドル初期化（有効な言語のみ）
-    let mut rust_bundle = if enable_rust { transpile_module_header(&imports, file_stem, TargetLanguage::Rust) } else { String::new() };
-    let mut go_bundle = if enable_go { transpile_module_header(&imports, file_stem, TargetLanguage::Go) } else { String::new() };
-    let mut ts_bundle = if enable_ts { transpile_module_header(&imports, file_stem, TargetLanguage::TypeScript) } else { String::new() };
-
-    for item in &items {
-        match item {
-            // --- import 宣言（resolver で処理済み） ---
-            Item::Import(import_decl) => {
-                let alias_str = import_decl.alias.as_deref().unwrap_or("(none)");
-                println!("  📦 Import: '{}' as '{}'", import_decl.path, alias_str);
-            }
 
-            // --- 精緻型の登録 ---
-            Item::TypeDef(refined_type) => {
-                println!("  ✨ Registered Refined Type: '{}' ({})", refined_type.name, refined_type._base_type);
-            }
-
-            // --- 構造体定義の登録 + トランスパイル ---
-            Item::StructDef(struct_def) => {
-                let field_names: Vec<&str> = struct_def.fields.iter().map(|f| f.name.as_str()).collect();
-                println!("  🏗️  Registered Struct: '{}' (fields: {})", struct_def.name, field_names.join(", "));
-                // 構造体定義をトランスパイル出力に含める（有効な言語のみ）
-                if enable_rust { rust_bundle.push_str(&transpile_struct(struct_def, TargetLanguage::Rust)); rust_bundle.push_str("\n\n"); }
-                if enable_go { go_bundle.push_str(&transpile_struct(struct_def, TargetLanguage::Go)); go_bundle.push_str("\n\n"); }
-                if enable_ts { ts_bundle.push_str(&transpile_struct(struct_def, TargetLanguage::TypeScript)); ts_bundle.push_str("\n\n"); }
-            }
-
-            // --- Enum 定義の登録 + トランスパイル ---
-            Item::EnumDef(enum_def) => {
-                let variant_names: Vec<&str> = enum_def.variants.iter().map(|v| v.name.as_str()).collect();
-                println!("  🔷 Registered Enum: '{}' (variants: {})", enum_def.name, variant_names.join(", "));
-                if enable_rust { rust_bundle.push_str(&transpile_enum(enum_def, TargetLanguage::Rust)); rust_bundle.push_str("\n\n"); }
-                if enable_go { go_bundle.push_str(&transpile_enum(enum_def, TargetLanguage::Go)); go_bundle.push_str("\n\n"); }
-                if enable_ts { ts_bundle.push_str(&transpile_enum(enum_def, TargetLanguage::TypeScript)); ts_bundle.push_str("\n\n"); }
-            }
+    let prepared = match pipeline::prepare_module(Path::new(input), &pipeline::PrepareOptions::default()) {
+        Ok(prepared) => prepared,
+        Err(diagnostics) => {
+            print_diagnostics(&diagnostics);
+            return Err(BuildFailure::Reported);
+        }
+    };
+    print_diagnostics(&prepared.diagnostics);
+    let pipeline::PreparedModule { items, mut module_env, imports, .. } = prepared;
+
+    if emit == native::Emit::Exe {
+        let entry = items.iter().find_map(|item| match item {
+            Item::Atom(atom) if atom.name == "main" => Some(atom),
+            _ => None,
+        });
+        native::check_entry(entry, &module_env).map_err(|e| format!("--emit exe: {}", e))?;
+    }
 
-            // --- トレイト定義 + トランスパイル ---
-            Item::TraitDef(trait_def) => {
-                let method_names: Vec<&str> = trait_def.methods.iter().map(|m| m.name.as_str()).collect();
-                let law_names: Vec<&str> = trait_def.laws.iter().map(|(n, _)| n.as_str()).collect();
-                println!("  📜 Registered Trait: '{}' (methods: {}, laws: {})",
-                    trait_def.name, method_names.join(", "), law_names.join(", "));
-                if enable_rust { rust_bundle.push_str(&transpile_trait(trait_def, TargetLanguage::Rust)); rust_bundle.push_str("\n\n"); }
-                if enable_go { go_bundle.push_str(&transpile_trait(trait_def, TargetLanguage::Go)); go_bundle.push_str("\n\n"); }
-                if enable_ts { ts_bundle.push_str(&transpile_trait(trait_def, TargetLanguage::TypeScript)); ts_bundle.push_str("\n\n"); }
+    if require_verified {
+        let not_verified: Vec<String> = items.iter().filter_map(|item| match item {
+            Item::Atom(atom) if atom.trust_level != parser::TrustLevel::Verified => {
+                Some(format!("\n      - '{}' ({})", atom.name, pipeline::report_status(atom).as_str()))
             }
+            _ => None,
+        }).collect();
+        if !not_verified.is_empty() {
+            return Err(format!("--require-verified: {} atom(s) are not fully verified:{}", not_verified.len(), not_verified.concat()).into());
+        }
+    }
 
-            // --- トレイト実装の登録 + 法則検証 + トランスパイル ---
-            Item::ImplDef(impl_def) => {
-                println!("  🔧 Registered Impl: {} for {}", impl_def.trait_name, impl_def.target_type);
-                // impl が trait の全 law を満たしているか Z3 で検証
-                if skip_verify {
-                    println!("    ⚖️  Laws verification skipped (verify=false in mumei.toml)");
-                } else {
-                    match verification::verify_impl(impl_def, &module_env) {
-                        Ok(_) => println!("    ✅ Laws verified for impl {} for {}", impl_def.trait_name, impl_def.target_type),
-                        Err(e) => {
-                            eprintln!("    ❌ Law verification failed: {}", e);
-                            std::process::exit(1);
-                        }
-                    }
-                }
-                // impl 定義をトランスパイル出力に含める（有効な言語のみ）
-                if enable_rust { rust_bundle.push_str(&transpile_impl(impl_def, TargetLanguage::Rust)); rust_bundle.push_str("\n\n"); }
-                if enable_go { go_bundle.push_str(&transpile_impl(impl_def, TargetLanguage::Go)); go_bundle.push_str("\n\n"); }
-                if enable_ts { ts_bundle.push_str(&transpile_impl(impl_def, TargetLanguage::TypeScript)); ts_bundle.push_str("\n\n"); }
-            }
+    // --deny-trusted: std/ と mumei.toml の依存以外に trusted atom（検証していない body）を置かない
+    if deny_trusted {
+        let dependency_dirs = manifest_config.as_ref()
+            .map(|(project_dir, m)| resolver::dependency_dirs(m, project_dir))
+            .unwrap_or_default();
+        let trusted = resolver::trusted_atoms(Path::new(input), &dependency_dirs).map_err(|e| format!("--deny-trusted: {}", e))?;
+        if !trusted.is_empty() {
+            let names: Vec<String> = trusted.iter().map(|(path, name)| format!("\n      - '{}' ({})", name, display_path(path))).collect();
+            return Err(format!("--deny-trusted: {} trusted atom(s) outside std/ and the declared dependencies:{}", trusted.len(), names.concat()).into());
+        }
+    }
 
-            // --- リソース定義の登録 ---
-            Item::ResourceDef(resource_def) => {
-                let mode_str = match resource_def.mode {
-                    parser::ResourceMode::Exclusive => "exclusive",
-                    parser::ResourceMode::Shared => "shared",
-                };
-                println!("  🔒 Registered Resource: '{}' (priority={}, mode={})",
-                    resource_def.name, resource_def.priority, mode_str);
-            }
+    let output_dir = build_output.dir.as_path();
+    let file_stem = build_output.stem.as_str();
+    let input_path = Path::new(input);
+    let build_base_dir = input_path.parent().unwrap_or(Path::new("."));
+    let skip_verify = !build_cfg.verify;
 
-            // --- Atom の処理 ---
-            Item::Atom(atom) => {
-                atom_count += 1;
-                let async_marker = if atom.is_async { " (async)" } else { "" };
-                let res_marker = if !atom.resources.is_empty() {
-                    format!(" [resources: {}]", atom.resources.join(", "))
-                } else { String::new() };
-                println!("  ✨ [1/4] Polishing Syntax: Atom '{}'{}{} identified.", atom.name, async_marker, res_marker);
+    // Incremental Build: ビルドキャッシュをロード（proof.cache が false ならスキップ）
+    let mut build_cache = pipeline::CacheState::new(if proof_cfg.cache {
+        resolver::load_build_cache(build_base_dir)
+    } else {
+        resolver::BuildCache::default()
+    });
 
-                // --- 2. Verification (形式検証: Z3 + StdLib) ---
-                if skip_verify {
-                    println!("  ⚖️  [2/4] Verification: Skipped (verify=false in mumei.toml).");
-                    module_env.mark_verified(&atom.name);
-                } else if module_env.is_verified(&atom.name) {
-                    // インポートされた atom は検証済み（契約のみ信頼）なのでスキップ
-                    println!("  ⚖️  [2/4] Verification: Skipped (imported, contract-trusted).");
-                } else {
-                    // Incremental Build: atom ハッシュでキャッシュ比較
-                    let atom_hash = resolver::compute_atom_hash(atom);
-                    build_cache_new.insert(atom.name.clone(), atom_hash.clone());
-
-                    let cache_hit = build_cache.get(&atom.name)
-                        .map_or(false, |cached| *cached == atom_hash);
-
-                    if cache_hit {
-                        println!("  ⚖️  [2/4] Verification: Skipped (unchanged, cached) ⏩");
-                        module_env.mark_verified(&atom.name);
-                    } else {
-                        match verification::verify_with_config(atom, output_dir, &module_env, proof_cfg.timeout_ms, build_cfg.max_unroll) {
-                            Ok(_) => {
-                                println!("  ⚖️  [2/4] Verification: Passed. Logic verified with Z3.");
-                                module_env.mark_verified(&atom.name);
-                            },
-                            Err(e) => {
-                                eprintln!("  ❌ [2/4] Verification: Failed! Flaw detected: {}", e);
-                                build_cache_new.remove(&atom.name);
-                                std::process::exit(1);
-                            }
-                        }
-                    }
-                }
+    // report.json（crate::report のスキーマ）。検証失敗で終了する場合も書き出す
+    let started = std::time::Instant::now();
+    let mut report = report::Report::new("build", input);
+    if skip_verify {
+        report.verification = report::VerificationMode::Skipped;
+        println!("  ⚠️  Verification is disabled (verify=false in mumei.toml): outputs will be watermarked as NOT verified.");
+    }
+    let save_report = |report: &mut report::Report| {
+        report.finish(started.elapsed().as_millis() as u64);
+        if let Err(e) = report.save(output_dir) {
+            eprintln!("  ⚠️  Failed to write {}: {}", report::REPORT_FILE, e);
+        }
+        save_sarif(report, sarif_path);
+    };
 
-                // --- 3. Codegen (LLVM 18 + Floating Point) ---
-                // 各 Atom ごとに .ll ファイルを生成（またはモジュールを統合する拡張も可能）
-                let atom_output_path = output_dir.join(format!("{}_{}", file_stem, atom.name));
-                match codegen::compile(atom, &atom_output_path, &module_env) {
-                    Ok(_) => println!("  ⚙️  [3/4] Tempering: Done. Compiled '{}' to LLVM IR.", atom.name),
-                    Err(e) => {
-                        eprintln!("  ❌ [3/4] Tempering: Failed! Codegen error: {}", e);
-                        std::process::exit(1);
-                    }
-                }
+    // 未対応構文ポリシー: 既定はコンパイルエラーマーカー、--allow-partial で TODO スタブ
+    let unsupported_policy = if allow_partial { UnsupportedPolicy::AllowPartial } else { UnsupportedPolicy::CompileError };
+    let options = pipeline::BuildOptions {
+        output: &build_output,
+        build: &build_cfg,
+        timeout_ms: proof_cfg.timeout_ms,
+        jobs,
+        split_ir,
+        unsupported_policy,
+    };
+    let built = pipeline::build_items(&items, &mut module_env, &mut build_cache, &options, &mut report, &mut |event, env| print_build_event(event, env, jobs, profile));
+    let pipeline::BuildArtifacts { atom_count, ir_files, mut bundles, unsupported: unsupported_summary } = match built {
+        Ok(artifacts) => artifacts,
+        Err(pipeline::BuildError::Law(e)) => {
+            eprintln!("    ❌ Law verification failed: {}", e);
+            save_report(&mut report);
+            return Err(BuildFailure::Reported);
+        }
+        Err(pipeline::BuildError::Verification(e)) => {
+            eprintln!("  ❌ [2/4] Verification: Failed! Flaw detected: {}", e);
+            save_report(&mut report);
+            return Err(BuildFailure::Reported);
+        }
+        Err(pipeline::BuildError::Codegen(e)) => return Err(format!("[3/4] Tempering: Failed! Codegen error: {}", e).into()),
+    };
 
-                // --- 4. Transpile (多言語エクスポート) ---
-                // バンドル用に各言語のコードを生成（有効な言語のみ）
-                if enable_rust { rust_bundle.push_str(&transpile(atom, TargetLanguage::Rust)); rust_bundle.push_str("\n\n"); }
-                if enable_go { go_bundle.push_str(&transpile(atom, TargetLanguage::Go)); go_bundle.push_str("\n\n"); }
-                if enable_ts { ts_bundle.push_str(&transpile(atom, TargetLanguage::TypeScript)); ts_bundle.push_str("\n\n"); }
-            }
+    // --emit obj|exe: 書き出した .ll を llc でオブジェクトファイルにし、exe ならエントリスタブとリンクする
+    if let Some(llc) = &llc {
+        let objects = ir_files.iter()
+            .map(|ir_path| native::compile_object(llc, ir_path))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("[3/4] Tempering: Failed! {}", e))?;
+        let object_names: Vec<String> = objects.iter().map(|path| display_path(path)).collect();
+        println!("  ⚙️  [3/4] Tempering: Done. Wrote object file(s) with {}: {}", llc.display(), object_names.join(", "));
+
+        if emit == native::Emit::Exe {
+            let stub_path = build_output.entry_stub_path();
+            fs::write(&stub_path, native::entry_stub()).map_err(|e| format!("Failed to write {}: {}", display_path(&stub_path), e))?;
+            let exe_path = build_output.exe_path();
+            native::link_executable(&objects, &stub_path, &exe_path).map_err(|e| format!("[3/4] Tempering: Failed! {}", e))?;
+            println!("  ⚙️  [3/4] Tempering: Done. Linked executable {}.", display_path(&exe_path));
         }
     }
 
@@ -917,31 +1629,70 @@ fn cmd_build(input: &str, output: &str) {
     if atom_count > 0 {
         println!("  🌍 [4/4] Sharpening: Exporting verified sources...");
 
-        let mut created_files = Vec::new();
-        let files: Vec<(&str, &str, bool)> = vec![
-            (&rust_bundle, "rs", enable_rust),
-            (&go_bundle, "go", enable_go),
-            (&ts_bundle, "ts", enable_ts),
-        ];
-
-        for (code, ext, enabled) in files {
-            if !enabled { continue; }
-            let out_filename = format!("{}.{}", file_stem, ext);
-            let out_full_path = output_dir.join(&out_filename);
-            if let Err(e) = fs::write(&out_full_path, code) {
-                eprintln!("  ❌ Failed to write {}: {}", out_filename, e);
-                std::process::exit(1);
+        // ヘッダーには各言語のバンドルが実際に参照する import だけを出す（Go は未使用の import がコンパイルエラー）
+        // async atom を含む場合、Go ヘッダーに "context" の import が必要
+        // verify=false のときは先頭に透かし（transpiler::UNVERIFIED_WATERMARK）を入れる
+        let has_async = items.iter().any(|item| matches!(item, Item::Atom(atom) if atom.is_async));
+        let import_definitions: Vec<_> = imports.iter()
+            .map(|import| resolver::import_definitions(import, build_base_dir).ok())
+            .collect();
+        let used_imports = |lang| transpiler::referenced_imports(&imports, &import_definitions, &transpiler::bundle_references(&items, lang));
+        for (lang, bundle) in &mut bundles {
+            bundle.insert_str(0, &transpile_bundle_header(&used_imports(*lang), file_stem, *lang, has_async, !skip_verify));
+            // Go: コンパイルエラーマーカーを含むバンドルは通常ビルドから除外する
+            if *lang == TargetLanguage::Go && unsupported_policy == UnsupportedPolicy::CompileError && unsupported_summary.has_backend(TargetLanguage::Go) {
+                bundle.insert_str(0, transpiler::golang::UNSUPPORTED_BUILD_TAG);
             }
-            created_files.push(out_filename);
+        }
+
+        // [build.ts] declarations = true: バンドルと同じ型情報から .d.ts を生成する
+        let emit_declarations = build_cfg.ts.declarations && bundles.iter().any(|(lang, _)| *lang == TargetLanguage::TypeScript);
+        let mut files: Vec<(String, &str)> = bundles.into_iter().map(|(lang, bundle)| {
+            let ext = match lang {
+                TargetLanguage::Rust => "rs",
+                TargetLanguage::Go => "go",
+                TargetLanguage::TypeScript => "ts",
+            };
+            (bundle, ext)
+        }).collect();
+        if emit_declarations {
+            let declarations = transpiler::typescript::transpile_declarations_ts(&used_imports(TargetLanguage::TypeScript), &items, &module_env, build_cfg.ts.emit_validators);
+            files.push((format!("{}{}", transpiler::watermark("//", !skip_verify), declarations), "d.ts"));
+        }
+
+        let mut created_files = Vec::new();
+        for (code, ext) in files {
+            let out_full_path = build_output.bundle_path(ext);
+            fs::write(&out_full_path, code).map_err(|e| format!("Failed to write {}: {}", display_path(&out_full_path), e))?;
+            created_files.push(display_path(&out_full_path));
         }
         println!("  ✅ Done. Created: {}", created_files.join(", "));
+
+        // 未対応構文のサマリー（atom × バックエンド）
+        if !unsupported_summary.is_empty() {
+            let policy_desc = match unsupported_policy {
+                UnsupportedPolicy::CompileError => "emitted as compile-error markers; use --allow-partial for TODO stubs",
+                UnsupportedPolicy::AllowPartial => "emitted as TODO stubs (--allow-partial)",
+            };
+            println!("  ⚠️  Unsupported constructs: {} atom/backend pair(s) {}:", unsupported_summary.count(), policy_desc);
+            for line in unsupported_summary.lines() {
+                println!("      - {}", line);
+            }
+            for (atom_name, lang, u) in &unsupported_summary.entries {
+                report.diagnostic(report::Severity::Warning, Some(atom_name), &u.message(*lang));
+            }
+        }
         println!("🎉 Blade forged successfully with {} atoms.", atom_count);
     } else {
         println!("⚠️  Warning: No atoms found in the source file.");
     }
 
-    // Incremental Build: ビルドキャッシュを保存
-    resolver::save_build_cache(build_base_dir, &build_cache_new);
+    // Incremental Build: ビルドキャッシュを保存（ソースから消えた atom のエントリは刈り込む）
+    let pruned = resolver::save_build_cache(build_base_dir, &build_cache.current, &pipeline::live_cache_names(&items));
+    log_pruned(".mumei_build_cache", &pruned);
+
+    save_report(&mut report);
+    Ok(())
 }
 
 // =============================================================================
@@ -957,18 +1708,12 @@ fn cmd_add(dep: &str) {
         std::process::exit(1);
     }
 
-    // 現在の mumei.toml を読み込み
-    let content = fs::read_to_string(manifest_path).unwrap_or_else(|e| {
-        eprintln!("❌ Error: Cannot read mumei.toml: {}", e);
+    // 編集ロックを取得して現在の mumei.toml を読み込み（パース確認を含む）
+    let edit = manifest::ManifestEdit::open(manifest_path).unwrap_or_else(|e| {
+        eprintln!("❌ Error: {}", e);
         std::process::exit(1);
     });
 
-    // パース確認
-    if let Err(e) = manifest::load(manifest_path) {
-        eprintln!("❌ Error: mumei.toml parse error: {}", e);
-        std::process::exit(1);
-    }
-
     // 依存の種類を判定
     let dep_entry = if dep.starts_with("./") || dep.starts_with("../") || dep.starts_with('/') {
         // ローカルパス依存
@@ -985,41 +1730,212 @@ fn cmd_add(dep: &str) {
         let toml_line = format!("{} = {{ path = \"{}\" }}", pkg_name, dep);
         println!("📦 Adding local dependency: {} → {}", pkg_name, dep);
         (pkg_name, toml_line)
-    } else if dep.contains("github.com") || dep.contains("gitlab.com") {
-        // Git URL 依存
+    } else if dep.contains("github.com") || dep.contains("gitlab.com") || dep.starts_with("https://") || dep.starts_with("git@") || dep.ends_with(".git") {
+        // Git URL 依存: 最新のタグを固定する（タグがなければ既定ブランチを追い、mumei.lock でコミットを固定する）
         let pkg_name = dep.split('/')
             .last()
             .unwrap_or("unknown")
             .trim_end_matches(".git")
             .replace('-', "_");
-        let toml_line = format!("{} = {{ git = \"{}\" }}", pkg_name, dep);
-        println!("📦 Adding git dependency: {} → {}", pkg_name, dep);
+        let tag = resolver::latest_git_tag(dep).unwrap_or_else(|e| {
+            eprintln!("⚠️  Warning: could not list tags of {}: {}", dep, e);
+            None
+        });
+        let toml_line = match &tag {
+            Some(tag) => format!("{} = {{ git = \"{}\", tag = \"{}\" }}", pkg_name, dep, tag),
+            None => format!("{} = {{ git = \"{}\" }}", pkg_name, dep),
+        };
+        println!("📦 Adding git dependency: {} → {}{}", pkg_name, dep, tag.map(|t| format!(" ({})", t)).unwrap_or_default());
         (pkg_name, toml_line)
     } else {
-        // パッケージ名のみ（レジストリ依存 — 将来対応）
-        let toml_line = format!("{} = \"*\"", dep);
-        println!("📦 Adding dependency: {} (registry lookup not yet implemented)", dep);
+        // パッケージ名のみ（レジストリ依存）: ローカルレジストリの最新バージョンを書き込む
+        let latest = registry::load().packages.get(dep).map(|entry| entry.latest.clone());
+        let version = latest.unwrap_or_else(|| {
+            eprintln!("❌ Error: package '{}' not found in the local registry ({}).", dep, registry::registry_path().display());
+            eprintln!("   Run `mumei publish` in that project first, or add it by path or git URL.");
+            std::process::exit(1);
+        });
+        let toml_line = format!("{} = \"{}\"", dep, version);
+        println!("📦 Adding registry dependency: {} = {}", dep, version);
         (dep.to_string(), toml_line)
     };
 
-    // mumei.toml に追記
-    let new_content = if content.contains("[dependencies]") {
-        // [dependencies] セクションが既にある場合、その直後に追記
-        content.replace(
-            "[dependencies]",
-            &format!("[dependencies]\n{}", dep_entry.1),
-        )
+    // mumei.toml の [dependencies] に追記（なければセクションを作成）し、原子的に書き込む
+    let new_content = manifest::add_dependency_line(&edit.content, &dep_entry.1);
+    edit.commit(&new_content).unwrap_or_else(|e| {
+        eprintln!("❌ Error: {}", e);
+        std::process::exit(1);
+    });
+
+    println!("✅ Added '{}' to mumei.toml", dep_entry.0);
+}
+
+// =============================================================================
+// mumei remove — remove dependency from mumei.toml
+// =============================================================================
+
+fn cmd_remove(dep: &str) {
+    let manifest_path = Path::new("mumei.toml");
+    if !manifest_path.exists() {
+        eprintln!("❌ Error: mumei.toml not found in current directory.");
+        eprintln!("   Run `mumei init <project>` first, or cd into a Mumei project.");
+        std::process::exit(1);
+    }
+
+    let edit = manifest::ManifestEdit::open(manifest_path).unwrap_or_else(|e| {
+        eprintln!("❌ Error: {}", e);
+        std::process::exit(1);
+    });
+    let manifest = edit.manifest().unwrap_or_else(|e| {
+        eprintln!("❌ Error: {}", e);
+        std::process::exit(1);
+    });
+    if !manifest.dependencies.contains_key(dep) {
+        let mut declared: Vec<&String> = manifest.dependencies.keys().collect();
+        declared.sort();
+        eprintln!("❌ Error: '{}' is not a dependency in mumei.toml.", dep);
+        if !declared.is_empty() {
+            eprintln!("   Declared: {}", declared.iter().map(|d| d.as_str()).collect::<Vec<_>>().join(", "));
+        }
+        std::process::exit(1);
+    }
+
+    // 他の依存やソースからの参照が残っている場合は削除しない
+    let references = manifest::find_dependency_references(Path::new("."), &manifest, dep);
+    if !references.is_empty() {
+        eprintln!("❌ Error: '{}' is still referenced ({} place(s)):", dep, references.len());
+        for r in &references {
+            eprintln!("     - {}", r);
+        }
+        eprintln!("   Remove these references first.");
+        std::process::exit(1);
+    }
+
+    let (new_content, removed) = manifest::remove_dependency_entry(&edit.content, dep).unwrap_or_else(|| {
+        eprintln!("❌ Error: could not locate the entry for '{}' in mumei.toml.", dep);
+        std::process::exit(1);
+    });
+    edit.commit(&new_content).unwrap_or_else(|e| {
+        eprintln!("❌ Error: {}", e);
+        std::process::exit(1);
+    });
+
+    // mumei.lock のエントリも削除する（次回のビルドを待たずにロックファイルを宣言と揃える）
+    let project_dir = Path::new(".");
+    match lockfile::Lockfile::load(project_dir) {
+        Ok(mut lock) => {
+            if lock.remove(dep) {
+                if let Err(e) = lock.save(project_dir) {
+                    eprintln!("⚠️  Warning: {}", e);
+                }
+            }
+        }
+        Err(e) => eprintln!("⚠️  Warning: {}", e),
+    }
+    println!("🗑️  Removed from mumei.toml:");
+    for line in &removed {
+        println!("     - {}", line);
+    }
+    println!("✅ Removed '{}' from mumei.toml", dep);
+}
+
+// =============================================================================
+// mumei migrate — mechanical rewrites to a newer language edition
+// =============================================================================
+
+fn cmd_migrate(path: &str, to: &str, dry_run: bool) {
+    if migrate::edition_index(to).is_none() {
+        eprintln!("❌ Error: unknown edition '{}' (known: {})", to, migrate::EDITIONS.join(", "));
+        std::process::exit(1);
+    }
+    let root = Path::new(path);
+    let files = if root.is_dir() {
+        let mut files = Vec::new();
+        manifest::collect_mm_files(root, Path::new(""), &mut files);
+        files
+    } else if root.is_file() {
+        vec![root.to_path_buf()]
     } else {
-        // [dependencies] セクションがない場合、末尾に追加
-        format!("{}\n[dependencies]\n{}\n", content.trim_end(), dep_entry.1)
+        eprintln!("❌ Error: '{}' does not exist.", path);
+        std::process::exit(1);
+    };
+
+    // mumei.toml は対象の位置から上方向に探す。宣言済みの edition より古い edition へは戻さない
+    let start = if root.is_dir() { root } else { root.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new(".")) };
+    let start = fs::canonicalize(start).unwrap_or_else(|_| start.to_path_buf());
+    let project = match manifest::find_from(&start) {
+        Some(Ok((dir, m))) => Some((dir, m)),
+        Some(Err(msg)) => {
+            eprintln!("❌ Error: {}", msg);
+            std::process::exit(1);
+        }
+        None => None,
     };
+    if let Some(current) = project.as_ref().and_then(|(_, m)| m.package.edition.as_deref()) {
+        if migrate::edition_index(current) > migrate::edition_index(to) {
+            eprintln!("❌ Error: mumei.toml already declares edition {}; migrating back to {} is not supported.", current, to);
+            std::process::exit(1);
+        }
+    }
 
-    fs::write(manifest_path, new_content).unwrap_or_else(|e| {
-        eprintln!("❌ Error: Cannot write mumei.toml: {}", e);
+    // 構文エラーのあるファイルが 1 つでもあれば、どのファイルも書き換えない
+    println!("🔁 Mumei migrate: {} file(s) → edition {}{}", files.len(), to, if dry_run { " (dry run)" } else { "" });
+    let mut migrated = Vec::new();
+    let mut failed = 0;
+    for file in &files {
+        let shown = display_path(file);
+        let source = fs::read_to_string(file).unwrap_or_else(|e| {
+            eprintln!("❌ Error: could not read '{}': {}", shown, e);
+            std::process::exit(1);
+        });
+        match migrate::migrate_source(&source, &shown, to) {
+            Ok(result) if result.applied.is_empty() => {}
+            Ok(result) => migrated.push((file, shown, source, result)),
+            Err(e) => {
+                eprintln!("❌ {}: refusing to migrate a file that does not parse\n{}", shown, e);
+                failed += 1;
+            }
+        }
+    }
+    if failed > 0 {
+        eprintln!("❌ {} file(s) have parse errors; nothing was changed.", failed);
         std::process::exit(1);
-    });
+    }
 
-    println!("✅ Added '{}' to mumei.toml", dep_entry.0);
+    for (file, shown, source, result) in &migrated {
+        if dry_run {
+            print!("{}", migrate::unified_diff(shown, source, &result.source));
+            continue;
+        }
+        fs::write(file, &result.source).unwrap_or_else(|e| {
+            eprintln!("❌ Error: could not write '{}': {}", shown, e);
+            std::process::exit(1);
+        });
+        println!("  ✏️  {} ({})", shown, result.applied.join(", "));
+    }
+    if dry_run {
+        println!("🔍 {} file(s) would change; run without --dry-run to apply.", migrated.len());
+        return;
+    }
+
+    match project {
+        Some((dir, _)) => {
+            let manifest_path = dir.join("mumei.toml");
+            let edit = manifest::ManifestEdit::open(&manifest_path).unwrap_or_else(|e| {
+                eprintln!("❌ Error: {}", e);
+                std::process::exit(1);
+            });
+            edit.commit(&manifest::set_package_edition(&edit.content, to)).unwrap_or_else(|e| {
+                eprintln!("❌ Error: {}", e);
+                std::process::exit(1);
+            });
+            println!("✅ Migrated {} file(s); recorded edition = \"{}\" in {}", migrated.len(), to, display_path(&manifest_path));
+        }
+        None => {
+            println!("✅ Migrated {} file(s)", migrated.len());
+            println!("  ℹ️  No mumei.toml found; the edition was not recorded.");
+        }
+    }
 }
 
 // =============================================================================
@@ -1073,8 +1989,9 @@ fn cmd_publish(proof_only: bool) {
                 continue;
             }
             match verification::verify(atom, output_dir, &module_env) {
-                Ok(_) => {
+                Ok(warnings) => {
                     println!("  ⚖️  '{}': verified ✅", atom.name);
+                    print_verify_warnings(&atom.name, &warnings);
                     module_env.mark_verified(&atom.name);
                     atom_count += 1;
                 }
@@ -1161,4 +2078,130 @@ fn copy_dir_recursive(src: &Path, dst: &Path) {
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pipeline::{Diagnostic, ItemVerification, Outcome, Stage, VerifyEvent};
+
+    // pipeline に移した処理の表示は、移す前の CLI の出力と 1 バイトも変わらないこと
+
+    #[test]
+    fn test_prepare_build_output() {
+        let dir = std::env::temp_dir().join(format!("mumei_build_output_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("main.mm");
+        fs::write(&input, "").unwrap();
+        let input = input.to_str().unwrap();
+
+        // 存在しない深いディレクトリは作成し、2 回目は既存のものを使う
+        let nested = dir.join("dist/a/b/katana");
+        let output = prepare_build_output(input, nested.to_str().unwrap()).unwrap();
+        assert!(output.created_dir);
+        assert!(dir.join("dist/a/b").is_dir());
+        assert_eq!(output.stem, "katana");
+        assert_eq!(output.bundle_path("d.ts"), dir.join("dist/a/b/katana.d.ts"));
+        assert_eq!(output.module_ir_path(), dir.join("dist/a/b/katana.ll"));
+        assert_eq!(output.ir_path("f"), dir.join("dist/a/b/katana_f.ll"));
+        assert_eq!(output.entry_stub_path(), dir.join("dist/a/b/katana_main.c"));
+        assert_eq!(output.exe_path(), dir.join(format!("dist/a/b/katana{}", std::env::consts::EXE_SUFFIX)));
+        assert!(!prepare_build_output(input, nested.to_str().unwrap()).unwrap().created_dir);
+
+        // stem に拡張子以外のドットがあっても .ll の名前は崩れない
+        let dotted = prepare_build_output(input, dir.join("katana.v2.rs").to_str().unwrap()).unwrap();
+        assert_eq!(dotted.module_ir_path(), dir.join("katana.v2.ll"));
+        assert_eq!(dotted.ir_path("f"), dir.join("katana.v2_f.ll"));
+
+        // `..` やパス区切りを含む stem は拒否し、ディレクトリも作らない
+        let err = prepare_build_output(input, dir.join("up/..").to_str().unwrap()).unwrap_err();
+        assert_eq!(err, "output file stem '..' must not contain path separators or '..'");
+        assert!(!dir.join("up").exists());
+        if std::path::MAIN_SEPARATOR == '/' {
+            let err = prepare_build_output(input, "out\\..\\katana").unwrap_err();
+            assert!(err.contains("must not contain path separators"), "{}", err);
+        }
+        let err = prepare_build_output(input, &format!("{}/", dir.display())).unwrap_err();
+        assert!(err.contains("does not end in a file name"), "{}", err);
+
+        // 入力ファイルと衝突する出力先
+        let err = prepare_build_output(input, input).unwrap_err();
+        assert!(err.contains("collides with the input file"), "{}", err);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_prepare_diagnostics_render_like_before() {
+        let render = |d: Diagnostic| render_diagnostic(&d, false);
+        assert_eq!(render(Diagnostic::error(Stage::Source, "Could not read Mumei source file 'a.mm'")),
+            Some((Stream::Stderr, "❌ Error: Could not read Mumei source file 'a.mm'".to_string())));
+        assert_eq!(render(Diagnostic::error(Stage::Parse, "Parse error: x\n --> a.mm:1:1")),
+            Some((Stream::Stderr, "❌ Parse error: x\n --> a.mm:1:1".to_string())));
+        assert_eq!(render(Diagnostic::error(Stage::Imports, "Import Resolution Failed: x")),
+            Some((Stream::Stderr, "  ❌ Import Resolution Failed: x".to_string())));
+        assert_eq!(render(Diagnostic::warning(Stage::Prelude, "Prelude load warning: x")),
+            Some((Stream::Stderr, "  ⚠️  Prelude load warning: x".to_string())));
+        assert_eq!(render(Diagnostic::info(Stage::Dependencies, "Dependency 'm': using cached clone")),
+            Some((Stream::Stdout, "  📦 Dependency 'm': using cached clone".to_string())));
+        assert_eq!(render(Diagnostic::info(Stage::Monomorphization, "Monomorphization: 2 generic instance(s) expanded.")),
+            Some((Stream::Stdout, "  🔬 Monomorphization: 2 generic instance(s) expanded.".to_string())));
+
+        // キャッシュの刈り込みは --verbose のときだけ
+        let pruned = Diagnostic::info(Stage::Cache, "lib.mm: old");
+        assert_eq!(render_diagnostic(&pruned, false), None);
+        assert_eq!(render_diagnostic(&pruned, true),
+            Some((Stream::Stdout, "  🧹 .mumei_cache: pruned stale entry 'lib.mm: old'".to_string())));
+    }
+
+    #[test]
+    fn test_verify_events_render_like_before() {
+        let items = parser::parse_module("atom f(x: i64) requires: true; ensures: true; body: x;\n\nimpl Eq for i64 {\n    fn eq(a: i64, b: i64) -> bool { a == b }\n}\n").unwrap();
+        let atom = items.iter().find(|i| matches!(i, Item::Atom(_))).unwrap();
+        let impl_item = items.iter().find(|i| matches!(i, Item::ImplDef(_))).unwrap();
+        let finished = |item: &Item, outcome: Outcome, reused: usize| {
+            let mut result = report::AtomResult::new("f", report::ItemKind::Atom, report::AtomStatus::Verified);
+            result.cache.obligations_reused = reused;
+            result.cache.obligations_solved = 1;
+            render_verify_event(&VerifyEvent::Finished(item, &ItemVerification { outcome, result, warnings: Vec::new() }), false)
+        };
+        let out = |line: &str| (Stream::Stdout, line.to_string());
+        let err = |line: &str| (Stream::Stderr, line.to_string());
+
+        assert_eq!(finished(atom, Outcome::Verified, 0), vec![out("  ⚖️  'f': verified ✅")]);
+        assert_eq!(finished(atom, Outcome::Verified, 2), vec![
+            out("  ♻️  'f': 2 obligation(s) reused, 1 re-solved"),
+            out("  ⚖️  'f': verified ✅"),
+        ]);
+        assert_eq!(finished(atom, Outcome::Cached, 0), vec![out("  ⚖️  'f': skipped (unchanged, cached) ⏩")]);
+        let mut result = report::AtomResult::new("f", report::ItemKind::Atom, report::AtomStatus::VerifiedWithAssumptions);
+        result.assumptions.push(report::Assumption { condition: "x > 0".to_string(), justification: "OS guarantee".to_string() });
+        assert_eq!(render_verify_event(&VerifyEvent::Finished(atom, &ItemVerification { outcome: Outcome::Verified, result, warnings: Vec::new() }), false), vec![
+            out("  ⚖️  'f': verified-with-assumptions (1) ✅"),
+            out("     📌 assume x > 0: OS guarantee"),
+        ]);
+        assert_eq!(finished(atom, Outcome::Imported, 0), vec![out("  ⚖️  'f': skipped (imported, contract-trusted)")]);
+        assert_eq!(finished(atom, Outcome::Failed(verification::MumeiError::VerificationError("boom".into())), 0),
+            vec![err("  ❌ 'f': verification failed: Verification Error: boom")]);
+
+        let impl_def = match impl_item { Item::ImplDef(d) => d, _ => unreachable!() };
+        assert_eq!(render_verify_event(&VerifyEvent::ImplStarted(impl_def), false), vec![out("  🔧 Verifying impl Eq for i64...")]);
+        assert_eq!(finished(impl_item, Outcome::Verified, 0), vec![out("    ✅ Laws verified")]);
+        assert_eq!(finished(impl_item, Outcome::Failed(verification::MumeiError::VerificationError("law".into())), 0),
+            vec![err("    ❌ Law verification failed: Verification Error: law")]);
+
+        // --profile では時間のかかった義務を並べ、遅い義務の警告は stderr に出す
+        let mut result = report::AtomResult::new("f", report::ItemKind::Atom, report::AtomStatus::Verified);
+        result.slowest_obligations.push(report::ObligationTiming { kind: "ensures".to_string(), label: "ensures x * x * x <= y * y * y".to_string(), duration_ms: 812.5 });
+        result.slowest_obligations.push(report::ObligationTiming { kind: "bounds".to_string(), label: "array bounds for arr[i + 1]".to_string(), duration_ms: 3.0 });
+        let verification = ItemVerification { outcome: Outcome::Verified, result, warnings: Vec::new() };
+        assert_eq!(render_verify_event(&VerifyEvent::Finished(atom, &verification), false), vec![out("  ⚖️  'f': verified ✅")]);
+        assert_eq!(render_verify_event(&VerifyEvent::Finished(atom, &verification), true), vec![
+            out("  ⚖️  'f': verified ✅"),
+            out("     ⏱️  slowest obligations:"),
+            out("          812.5 ms  ensures: ensures x * x * x <= y * y * y"),
+            out("            3.0 ms  bounds: array bounds for arr[i + 1]"),
+        ]);
+        assert_eq!(render_verify_event(&VerifyEvent::Warning("f", "slow"), false), vec![err("  ⚠️  'f': slow")]);
+    }
+}
+
 // end of src/main.rs
@@ -3,11 +3,17 @@
 //! `mumei.toml` の解析と設定値の提供を行う。
 //!
 //! ## 対応セクション
-//! - `[package]`: プロジェクトメタデータ（name, version, authors, description）
+//! - `[package]`: プロジェクトメタデータ（name, version, edition, authors, description）
 //! - `[dependencies]`: パッケージ依存（path / git / version）
-//! - `[build]`: ビルド設定（targets, verify, max_unroll）
-//! - `[proof]`: 検証設定（cache, timeout_ms）
+//! - `[build]`: ビルド設定（targets, verify, max_unroll, max_mono_depth, require_explicit_exports, strict_ref_args, runtime_checks）
+//! - `[build.rust]`: Rust バックエンドの設定（derive_serde）
+//! - `[build.ts]`: TypeScript バックエンドの設定（declarations, emit_validators）
+//! - `[proof]`: 検証設定（cache, timeout_ms, timeout_grace_ms, max_memory_mb, max_law_expansion_nodes, deny_assume, strict_resources）
+//!
+//! `mumei add` / `mumei remove` による編集は [`ManifestEdit`] を通して行い、
+//! 一時ファイル + rename による原子的な書き込みと、読み込み後の外部変更検知を行う。
 use serde::Deserialize;
+use sha2::{Digest, Sha256};
 use std::collections::HashMap;
 use std::fs;
 use std::path::{Path, PathBuf};
@@ -30,6 +36,9 @@ pub struct Manifest {
 pub struct Package {
     pub name: String,
     pub version: String,
+    /// ソースが準拠する言語の edition（`mumei migrate --to` が記録する）。未指定なら最初の edition
+    #[serde(default)]
+    pub edition: Option<String>,
     #[serde(default)]
     pub authors: Vec<String>,
     #[serde(default)]
@@ -69,6 +78,42 @@ pub struct BuildConfig {
     /// BMC 展開深度（デフォルト: 3）
     #[serde(default = "default_max_unroll")]
     pub max_unroll: usize,
+    /// 単相化のインスタンス化チェーンの長さの上限（デフォルト: 16）
+    #[serde(default = "default_max_mono_depth")]
+    pub max_mono_depth: usize,
+    /// import するモジュールに `export { ... };` を必須にするか（デフォルト: false = 宣言なしは全公開）
+    #[serde(default)]
+    pub require_explicit_exports: bool,
+    /// ref / ref mut パラメータへの実引数に `ref` / `ref mut` の明示を必須にするか（デフォルト: false = 警告のみ）
+    #[serde(default)]
+    pub strict_ref_args: bool,
+    /// 生成コードに requires / ensures の実行時検査を出力するか（デフォルト: false）。
+    /// 検証済みの契約を、生成先の言語から呼ぶ側にも守らせる
+    #[serde(default)]
+    pub runtime_checks: bool,
+    /// [build.rust] Rust バックエンドの設定
+    #[serde(default)]
+    pub rust: RustBuildConfig,
+    /// [build.ts] TypeScript バックエンドの設定
+    #[serde(default)]
+    pub ts: TsBuildConfig,
+}
+/// [build.rust] セクション
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct RustBuildConfig {
+    /// struct / enum に serde の Serialize / Deserialize を導出するか（デフォルト: false）
+    #[serde(default)]
+    pub derive_serde: bool,
+}
+/// [build.ts] セクション
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct TsBuildConfig {
+    /// バンドルと同じ型情報から <stem>.d.ts も書き出すか（デフォルト: false）
+    #[serde(default)]
+    pub declarations: bool,
+    /// struct / enum ごとに JSON を検査する parseX 関数を出力するか（デフォルト: false）
+    #[serde(default)]
+    pub emit_validators: bool,
 }
 impl Default for BuildConfig {
     fn default() -> Self {
@@ -76,6 +121,12 @@ impl Default for BuildConfig {
             targets: default_targets(),
             verify: true,
             max_unroll: 3,
+            max_mono_depth: 16,
+            require_explicit_exports: false,
+            strict_ref_args: false,
+            runtime_checks: false,
+            rust: RustBuildConfig::default(),
+            ts: TsBuildConfig::default(),
         }
     }
 }
@@ -88,12 +139,32 @@ pub struct ProofConfig {
     /// Z3 ソルバのタイムアウト（ミリ秒、デフォルト: 10000）
     #[serde(default = "default_timeout")]
     pub timeout_ms: u64,
+    /// timeout_ms 超過後、watchdog が Z3 を interrupt するまでの猶予（ミリ秒、デフォルト: 5000）
+    #[serde(default = "default_timeout_grace")]
+    pub timeout_grace_ms: u64,
+    /// Z3 のメモリ上限（MB、memory_max_size）。未指定なら無制限
+    #[serde(default)]
+    pub max_memory_mb: Option<u64>,
+    /// impl の law をメソッド body で展開した結果の AST ノード数の上限（デフォルト: 10000）
+    #[serde(default = "default_max_law_expansion_nodes")]
+    pub max_law_expansion_nodes: usize,
+    /// atom の body の `assume(...)` を禁止するか（デフォルト: false）
+    #[serde(default)]
+    pub deny_assume: bool,
+    /// exclusive リソースの下で計算した値の持ち出しをスカラーも含めてエラーにするか（デフォルト: false）
+    #[serde(default)]
+    pub strict_resources: bool,
 }
 impl Default for ProofConfig {
     fn default() -> Self {
         Self {
             cache: true,
-            timeout_ms: 10000,
+            timeout_ms: default_timeout(),
+            timeout_grace_ms: 5000,
+            max_memory_mb: None,
+            max_law_expansion_nodes: default_max_law_expansion_nodes(),
+            deny_assume: false,
+            strict_resources: false,
         }
     }
 }
@@ -109,8 +180,17 @@ fn default_true() -> bool {
 fn default_max_unroll() -> usize {
     3
 }
+fn default_max_mono_depth() -> usize {
+    16
+}
 fn default_timeout() -> u64 {
-    10000
+    crate::verification::DEFAULT_TIMEOUT_MS
+}
+fn default_timeout_grace() -> u64 {
+    5000
+}
+fn default_max_law_expansion_nodes() -> usize {
+    crate::verification::DEFAULT_MAX_LAW_EXPANSION_NODES
 }
 // =============================================================================
 // マニフェスト読み込み
@@ -126,17 +206,29 @@ pub fn load(path: &Path) -> Result<Manifest, ManifestError> {
 /// カレントディレクトリから上方向に mumei.toml を探索して読み込む
 /// 見つかった場合は (mumei.toml のあるディレクトリ, Manifest) を返す
 pub fn find_and_load() -> Option<(PathBuf, Manifest)> {
-    let mut dir = std::env::current_dir().ok()?;
+    find_and_load_from(&std::env::current_dir().ok()?)
+}
+/// start から上方向に mumei.toml を探索して読み込む（LSP ではワークスペース / 編集中ファイルの位置から探す）
+pub fn find_and_load_from(start: &Path) -> Option<(PathBuf, Manifest)> {
+    match find_from(start)? {
+        Ok(found) => Some(found),
+        Err(msg) => {
+            eprintln!("  ⚠️  {}", msg);
+            None
+        }
+    }
+}
+/// find_and_load_from の出力しない版。見つからなければ None、
+/// 最初に見つかった mumei.toml がパースできなければ Some(Err(メッセージ)) を返す
+pub fn find_from(start: &Path) -> Option<Result<(PathBuf, Manifest), String>> {
+    let mut dir = start.to_path_buf();
     loop {
         let manifest_path = dir.join("mumei.toml");
         if manifest_path.exists() {
-            match load(&manifest_path) {
-                Ok(manifest) => return Some((dir, manifest)),
-                Err(e) => {
-                    eprintln!("  ⚠️  Failed to parse {}: {}", manifest_path.display(), e);
-                    return None;
-                }
-            }
+            return Some(match load(&manifest_path) {
+                Ok(manifest) => Ok((dir, manifest)),
+                Err(e) => Err(format!("Failed to parse {}: {}", manifest_path.display(), e)),
+            });
         }
         if !dir.pop() {
             break;
@@ -183,6 +275,309 @@ impl Dependency {
         }
     }
 }
+// =============================================================================
+// マニフェストの編集（mumei add / mumei remove）
+// =============================================================================
+//
+// 並行実行への対策:
+// - 編集ロック: mumei.toml と同じディレクトリに `.mumei.toml.lock` を排他作成し、
+//   mumei 同士の編集を直列化する（読み込み → 書き込みの間に他の mumei が割り込まない）。
+// - 外部変更検知: 読み込み時の内容ハッシュを保持し、書き込み直前に再計算して
+//   一致しなければ中断する（エディタ等による変更を上書きしない）。
+// - 原子的な書き込み: 一時ファイルに書いてから rename する。
+
+/// 編集ロックを待つ最大時間
+const EDIT_LOCK_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+/// これより古いロックファイルは異常終了した mumei の残骸とみなして削除する
+const EDIT_LOCK_STALE: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// 読み込み済みの mumei.toml と、その時点の内容ハッシュ
+#[derive(Debug)]
+pub struct ManifestEdit {
+    path: PathBuf,
+    /// 読み込み時点の内容
+    pub content: String,
+    fingerprint: String,
+    _lock: EditLock,
+}
+
+/// 編集ロック。Drop でロックファイルを削除する。
+#[derive(Debug)]
+struct EditLock(PathBuf);
+
+impl Drop for EditLock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+fn content_fingerprint(content: &str) -> String {
+    format!("{:x}", Sha256::digest(content.as_bytes()))
+}
+
+fn acquire_edit_lock(lock_path: &Path) -> Result<EditLock, ManifestError> {
+    let start = std::time::Instant::now();
+    loop {
+        match fs::OpenOptions::new().write(true).create_new(true).open(lock_path) {
+            Ok(_) => return Ok(EditLock(lock_path.to_path_buf())),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                let stale = fs::metadata(lock_path)
+                    .and_then(|m| m.modified())
+                    .ok()
+                    .and_then(|t| t.elapsed().ok())
+                    .map_or(false, |age| age > EDIT_LOCK_STALE);
+                if stale {
+                    let _ = fs::remove_file(lock_path);
+                    continue;
+                }
+                if start.elapsed() > EDIT_LOCK_TIMEOUT {
+                    return Err(ManifestError::Locked(lock_path.to_path_buf()));
+                }
+                std::thread::sleep(std::time::Duration::from_millis(50));
+            }
+            Err(e) => return Err(ManifestError::Io(lock_path.to_path_buf(), e)),
+        }
+    }
+}
+
+impl ManifestEdit {
+    /// 編集ロックを取得して mumei.toml を読み込む。パースできない場合はエラー。
+    pub fn open(path: &Path) -> Result<Self, ManifestError> {
+        // 以降のエラーでは EditLock の Drop がロックを解放する
+        let lock = acquire_edit_lock(&path.with_file_name(".mumei.toml.lock"))?;
+        let content = fs::read_to_string(path).map_err(|e| ManifestError::Io(path.to_path_buf(), e))?;
+        toml::from_str::<Manifest>(&content).map_err(|e| ManifestError::Parse(path.to_path_buf(), e.to_string()))?;
+        Ok(ManifestEdit {
+            path: path.to_path_buf(),
+            fingerprint: content_fingerprint(&content),
+            content,
+            _lock: lock,
+        })
+    }
+
+    /// 読み込み時点の内容をパースした Manifest
+    pub fn manifest(&self) -> Result<Manifest, ManifestError> {
+        toml::from_str(&self.content).map_err(|e| ManifestError::Parse(self.path.clone(), e.to_string()))
+    }
+
+    /// 新しい内容を原子的に書き込む。読み込み後にファイルが変更されていれば中断する。
+    pub fn commit(&self, new_content: &str) -> Result<(), ManifestError> {
+        let on_disk = fs::read_to_string(&self.path).map_err(|e| ManifestError::Io(self.path.clone(), e))?;
+        if content_fingerprint(&on_disk) != self.fingerprint {
+            return Err(ManifestError::Conflict(self.path.clone()));
+        }
+        let tmp_path = self.path.with_file_name(format!(".mumei.toml.tmp-{}", std::process::id()));
+        fs::write(&tmp_path, new_content).map_err(|e| ManifestError::Io(tmp_path.clone(), e))?;
+        fs::rename(&tmp_path, &self.path).map_err(|e| {
+            let _ = fs::remove_file(&tmp_path);
+            ManifestError::Io(self.path.clone(), e)
+        })
+    }
+}
+
+/// 行が `[section]` ヘッダならセクション名を返す
+fn section_header(line: &str) -> Option<&str> {
+    let t = line.trim();
+    if t.starts_with('[') && !t.starts_with("[[") && t.ends_with(']') {
+        Some(t[1..t.len() - 1].trim())
+    } else {
+        None
+    }
+}
+
+/// `name = ...` / `"name" = ...` 形式のキーを返す
+fn line_key(line: &str) -> Option<&str> {
+    let (key, _) = line.split_once('=')?;
+    Some(key.trim().trim_matches('"'))
+}
+
+/// [dependencies] セクションに 1 行追加した内容を返す（セクションがなければ末尾に作る）
+pub fn add_dependency_line(content: &str, toml_line: &str) -> String {
+    let mut out = String::new();
+    let mut inserted = false;
+    for line in content.lines() {
+        out.push_str(line);
+        out.push('\n');
+        if !inserted && section_header(line) == Some("dependencies") {
+            out.push_str(toml_line);
+            out.push('\n');
+            inserted = true;
+        }
+    }
+    if !inserted {
+        out = format!("{}\n\n[dependencies]\n{}\n", content.trim_end(), toml_line);
+    }
+    out
+}
+
+/// 依存 `name` のエントリ（`[dependencies]` 内の行、または `[dependencies.name]` テーブル）を
+/// 削除した内容と、削除した行を返す。見つからなければ None。
+pub fn remove_dependency_entry(content: &str, name: &str) -> Option<(String, Vec<String>)> {
+    let table_header = format!("dependencies.{}", name);
+    let mut out = String::new();
+    let mut removed = Vec::new();
+    let mut section = String::new();
+    let mut in_removed_table = false;
+    for line in content.lines() {
+        if let Some(header) = section_header(line) {
+            section = header.to_string();
+            in_removed_table = section == table_header;
+        }
+        let drop_line = in_removed_table
+            || (section == "dependencies" && section_header(line).is_none() && line_key(line) == Some(name));
+        if drop_line {
+            if !line.trim().is_empty() {
+                removed.push(line.to_string());
+            }
+        } else {
+            out.push_str(line);
+            out.push('\n');
+        }
+    }
+    if removed.is_empty() {
+        return None;
+    }
+    if !content.ends_with('\n') {
+        out.pop();
+    }
+    Some((out, removed))
+}
+
+/// [package] の `edition = "..."` を設定した内容を返す。既存の行は置き換え、
+/// なければ [package] セクションの末尾（後続の空行・コメントより前）に追加する
+pub fn set_package_edition(content: &str, edition: &str) -> String {
+    let edition_line = format!("edition = \"{}\"", edition);
+    let mut lines: Vec<String> = content.lines().map(str::to_string).collect();
+    let mut section = String::new();
+    let mut insert_at = None;
+    for (i, line) in lines.iter_mut().enumerate() {
+        if let Some(header) = section_header(line) {
+            section = header.to_string();
+            if section == "package" {
+                insert_at = Some(i + 1);
+            }
+            continue;
+        }
+        if section != "package" {
+            continue;
+        }
+        if line_key(line) == Some("edition") && !line.trim_start().starts_with('#') {
+            *line = edition_line;
+            return join_lines(&lines, content);
+        }
+        if !line.trim().is_empty() && !line.trim_start().starts_with('#') {
+            insert_at = Some(i + 1);
+        }
+    }
+    match insert_at {
+        Some(i) => lines.insert(i, edition_line),
+        None => lines.insert(0, format!("[package]\n{}", edition_line)),
+    }
+    join_lines(&lines, content)
+}
+
+/// 行を連結する。元の内容の末尾改行の有無を保つ
+fn join_lines(lines: &[String], original: &str) -> String {
+    let mut out = lines.join("\n");
+    if original.ends_with('\n') {
+        out.push('\n');
+    }
+    out
+}
+
+/// パスを字句的に正規化する（`.` / `..` を畳み込む。ファイルシステムは参照しない）
+pub(crate) fn normalize_path(path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+    for comp in path.components() {
+        match comp {
+            std::path::Component::CurDir => {}
+            std::path::Component::ParentDir => {
+                if !out.pop() {
+                    out.push("..");
+                }
+            }
+            other => out.push(other.as_os_str()),
+        }
+    }
+    out
+}
+
+/// dir 以下の .mm を集める（隠しディレクトリ・ビルド出力と skip 配下は除く）
+pub fn collect_mm_files(dir: &Path, skip: &Path, out: &mut Vec<PathBuf>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(e) => e,
+        Err(_) => return,
+    };
+    let mut paths: Vec<PathBuf> = entries.filter_map(|e| e.ok().map(|e| e.path())).collect();
+    paths.sort();
+    for path in paths {
+        let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+        if path.is_dir() {
+            if name.starts_with('.') || matches!(name, "target" | "dist" | "node_modules") || normalize_path(&path) == skip {
+                continue;
+            }
+            collect_mm_files(&path, skip, out);
+        } else if name.ends_with(".mm") {
+            out.push(path);
+        }
+    }
+}
+
+/// 依存 `name` を参照している箇所を探す（ベストエフォート）。
+/// - 他の path 依存の mumei.toml が `name` を依存として宣言している
+/// - プロジェクト内の .mm が `import "name..."`、依存のパス配下を import、または `name::` を使っている
+///
+/// 返り値は人間向けの参照箇所（"src/main.mm:3: import ..." など）。
+pub fn find_dependency_references(project_dir: &Path, manifest: &Manifest, name: &str) -> Vec<String> {
+    let mut refs = Vec::new();
+
+    let mut others: Vec<(&String, &Dependency)> = manifest.dependencies.iter().filter(|(n, _)| n.as_str() != name).collect();
+    others.sort_by(|a, b| a.0.cmp(b.0));
+    for (other, dep) in others {
+        if let Some(p) = dep.as_path() {
+            let other_manifest = project_dir.join(p).join("mumei.toml");
+            if let Ok(m) = load(&other_manifest) {
+                if m.dependencies.contains_key(name) {
+                    refs.push(format!("dependency '{}' ({})", other, other_manifest.display()));
+                }
+            }
+        }
+    }
+
+    let dep_dir = manifest.dependencies.get(name)
+        .and_then(|d| d.as_path())
+        .map(|p| normalize_path(&project_dir.join(p)));
+    let mut files = Vec::new();
+    collect_mm_files(project_dir, dep_dir.as_deref().unwrap_or(Path::new("")), &mut files);
+
+    let qualified = regex::Regex::new(&format!(r"\b{}::", regex::escape(name))).unwrap();
+    let import_re = regex::Regex::new(r#"^\s*import\s+"([^"]+)""#).unwrap();
+    for file in files {
+        let source = match crate::resolver::read_source(&file) {
+            Ok(s) => s,
+            Err(_) => continue,
+        };
+        let file_dir = file.parent().unwrap_or(project_dir);
+        let display = file.strip_prefix(project_dir).unwrap_or(&file).display().to_string();
+        for (i, line) in source.lines().enumerate() {
+            let code = line.split("//").next().unwrap_or("");
+            let hit = if let Some(cap) = import_re.captures(code) {
+                let target = &cap[1];
+                let by_name = target == name || target.starts_with(&format!("{}/", name));
+                let by_path = (target.starts_with("./") || target.starts_with("../"))
+                    && dep_dir.as_ref().map_or(false, |d| normalize_path(&file_dir.join(target)).starts_with(d));
+                by_name || by_path
+            } else {
+                qualified.is_match(code)
+            };
+            if hit {
+                refs.push(format!("{}:{}: {}", display, i + 1, line.trim()));
+            }
+        }
+    }
+    refs
+}
+
 // =============================================================================
 // エラー型
 // =============================================================================
@@ -190,13 +585,119 @@ impl Dependency {
 pub enum ManifestError {
     Io(PathBuf, std::io::Error),
     Parse(PathBuf, String),
+    /// 読み込み後に mumei.toml が外部で変更された
+    Conflict(PathBuf),
+    /// 他の mumei が編集中（ロックを取得できない）
+    Locked(PathBuf),
 }
 impl std::fmt::Display for ManifestError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             ManifestError::Io(path, e) => write!(f, "Cannot read '{}': {}", path.display(), e),
             ManifestError::Parse(path, e) => write!(f, "Parse error in '{}': {}", path.display(), e),
+            ManifestError::Conflict(path) => write!(
+                f,
+                "'{}' changed on disk since it was read; not overwriting (re-run the command)",
+                path.display()
+            ),
+            ManifestError::Locked(lock) => write!(
+                f,
+                "another mumei process is editing the manifest (lock file '{}'); try again",
+                lock.display()
+            ),
         }
     }
 }
-impl std::error::Error for ManifestError {}
\ No newline at end of file
+impl std::error::Error for ManifestError {}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    const MANIFEST: &str = "[package]\nname = \"app\"\nversion = \"0.1.0\"\n\n[dependencies]\nmath = { path = \"./libs/math\" }\nutils = { path = \"./libs/utils\" }\n\n[build]\nverify = true\n";
+
+    fn project(name: &str) -> TempDir {
+        let dir = TempDir::new(&format!("manifest_{}", name));
+        fs::create_dir_all(dir.join("libs/math")).unwrap();
+        fs::create_dir_all(dir.join("libs/utils")).unwrap();
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::write(dir.join("mumei.toml"), MANIFEST).unwrap();
+        // 依存自身のソースは参照としてカウントしない
+        fs::write(dir.join("libs/math/main.mm"), "atom sq(x: i64) requires: true; ensures: true; body: x * x;\n").unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_remove_blocked_by_references() {
+        let dir = project("blocked");
+        fs::write(dir.join("src/main.mm"), "import \"./../libs/math/main.mm\" as m;\natom f(x: i64) requires: true; ensures: true; body: x;\n").unwrap();
+        fs::write(dir.join("libs/utils/mumei.toml"), "[package]\nname = \"utils\"\nversion = \"0.1.0\"\n\n[dependencies]\nmath = \"0.1.0\"\n").unwrap();
+
+        let manifest = load(&dir.join("mumei.toml")).unwrap();
+        let refs = find_dependency_references(&dir, &manifest, "math");
+        assert_eq!(refs.len(), 2, "{:?}", refs);
+        assert!(refs[0].starts_with("dependency 'utils'"), "{:?}", refs);
+        assert!(refs[1].starts_with("src/main.mm:1:"), "{:?}", refs);
+        assert!(find_dependency_references(&dir, &manifest, "utils").is_empty());
+    }
+
+    #[test]
+    fn test_remove_clean() {
+        let dir = project("clean");
+        fs::write(dir.join("src/main.mm"), "atom f(x: i64) requires: true; ensures: true; body: x;\n").unwrap();
+        let manifest_path = dir.join("mumei.toml");
+
+        let edit = ManifestEdit::open(&manifest_path).unwrap();
+        let manifest = edit.manifest().unwrap();
+        assert!(find_dependency_references(&dir, &manifest, "math").is_empty());
+        let (new_content, removed) = remove_dependency_entry(&edit.content, "math").unwrap();
+        assert_eq!(removed, vec!["math = { path = \"./libs/math\" }"]);
+        edit.commit(&new_content).unwrap();
+        drop(edit);
+
+        let reloaded = load(&manifest_path).unwrap();
+        assert!(!reloaded.dependencies.contains_key("math"));
+        assert!(reloaded.dependencies.contains_key("utils"));
+        assert!(reloaded.build.verify);
+        assert!(!dir.join(".mumei.toml.lock").exists());
+
+        // [dependencies.name] テーブル形式も削除できる
+        let table = "[dependencies.math]\npath = \"./libs/math\"\n\n[build]\nverify = false\n";
+        let (out, removed) = remove_dependency_entry(table, "math").unwrap();
+        assert_eq!(removed.len(), 2);
+        assert_eq!(out, "[build]\nverify = false\n");
+        assert!(remove_dependency_entry(table, "missing").is_none());
+    }
+
+    #[test]
+    fn test_concurrent_modification_refused() {
+        let dir = project("conflict");
+        let manifest_path = dir.join("mumei.toml");
+
+        let edit = ManifestEdit::open(&manifest_path).unwrap();
+        let new_content = add_dependency_line(&edit.content, "extra = \"*\"");
+        assert!(new_content.contains("[dependencies]\nextra = \"*\"\nmath"), "{}", new_content);
+        // 読み込み後にエディタが保存した
+        let edited = format!("{}# edited elsewhere\n", MANIFEST);
+        fs::write(&manifest_path, &edited).unwrap();
+
+        let err = edit.commit(&new_content).unwrap_err();
+        assert!(matches!(err, ManifestError::Conflict(_)), "{}", err);
+        assert_eq!(fs::read_to_string(&manifest_path).unwrap(), edited);
+        drop(edit);
+    }
+
+    #[test]
+    fn test_set_package_edition() {
+        let updated = set_package_edition(MANIFEST, "2026");
+        assert!(updated.starts_with("[package]\nname = \"app\"\nversion = \"0.1.0\"\nedition = \"2026\"\n\n[dependencies]"), "{}", updated);
+        let manifest: Manifest = toml::from_str(&updated).unwrap();
+        assert_eq!(manifest.package.edition.as_deref(), Some("2026"));
+
+        // 既存の edition は置き換え、コメントアウトされた行には触れない
+        let again = set_package_edition(&updated.replace("2026", "2025"), "2026");
+        assert_eq!(again, updated);
+        let commented = "[package]\nname = \"app\"\nversion = \"0.1.0\"\n# edition = \"2025\"\n";
+        assert_eq!(set_package_edition(commented, "2026"), "[package]\nname = \"app\"\nversion = \"0.1.0\"\nedition = \"2026\"\n# edition = \"2025\"\n");
+    }
+}
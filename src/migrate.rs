@@ -0,0 +1,359 @@
+// =============================================================================
+// mumei migrate: 言語の edition 間の機械的な書き換え
+// =============================================================================
+//
+// 構文の変更は edition（mumei.toml の `[package] edition`）単位で導入し、古い書き方から
+// 新しい書き方への書き換えを migration として登録する。`mumei migrate <path> --to <edition>`
+// は指定 edition までの migration を登録順に適用し、mumei.toml に edition を記録する。
+//
+// migration は次のどちらかで実装する:
+// - 字句的な変更: トークン位置に基づくテキスト編集（コメント・空白・書式をそのまま保つ）
+// - 構文的な変更: パース → AST の変換 → printer::show による印字
+//
+// どの migration も冪等であること（適用済みのソースに再適用しても変化しない）。
+// 構文エラーのあるソースは書き換えない。書き換え後のソースがパースできない場合も
+// migration の不具合としてファイルを変更しない。
+
+use crate::parser::{self, Expr};
+
+/// 既知の edition（古い順）。mumei.toml に edition がなければ最初の edition とみなす
+pub const EDITIONS: &[&str] = &["2025", "2026"];
+
+/// 1 つの機械的な書き換え
+pub struct Migration {
+    /// 表示・ログ用の名前
+    pub name: &'static str,
+    /// この書き換えを導入した edition
+    pub edition: &'static str,
+    /// 書き換えの要約（`mumei migrate` と build の警告に表示する）
+    pub summary: &'static str,
+    /// ソース全体を受け取り、書き換えたソースを返す
+    pub apply: fn(&str) -> String,
+}
+
+/// 登録済みの migration（適用順）。
+/// else なしの if が入る edition では `else { 0 }` の省略をここに追加する
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        name: "cast-to-as",
+        edition: "2026",
+        summary: "cast_to_int(x) → x as i64",
+        apply: cast_to_as,
+    },
+];
+
+/// 最新の edition（`mumei init` が記録する）
+pub fn latest_edition() -> &'static str {
+    EDITIONS[EDITIONS.len() - 1]
+}
+
+/// edition の古い順の位置。未知の edition なら None
+pub fn edition_index(edition: &str) -> Option<usize> {
+    EDITIONS.iter().position(|e| *e == edition)
+}
+
+/// edition `to` までに導入された migration（登録順）
+pub fn migrations_up_to(to: &str) -> Vec<&'static Migration> {
+    let limit = edition_index(to);
+    MIGRATIONS.iter().filter(|m| edition_index(m.edition) <= limit).collect()
+}
+
+/// 1 ファイル分の移行結果
+pub struct Migrated {
+    pub source: String,
+    /// 実際にソースを変更した migration の名前（適用順）
+    pub applied: Vec<&'static str>,
+}
+
+/// source に edition `to` までの migration を順に適用する。
+/// 構文エラーのあるソースは書き換えず、エラーをキャレット付きの表示で返す
+pub fn migrate_source(source: &str, path: &str, to: &str) -> Result<Migrated, String> {
+    parser::parse_module(source).map_err(|e| e.render(&parser::normalize_source(source), path))?;
+    let mut current = source.to_string();
+    let mut applied = Vec::new();
+    for migration in migrations_up_to(to) {
+        let next = (migration.apply)(&current);
+        if next == current {
+            continue;
+        }
+        if let Err(e) = parser::parse_module(&next) {
+            return Err(format!(
+                "migration '{}' produced source that does not parse (please report this):\n{}",
+                migration.name, e.render(&parser::normalize_source(&next), path)
+            ));
+        }
+        applied.push(migration.name);
+        current = next;
+    }
+    Ok(Migrated { source: current, applied })
+}
+
+/// edition `edition` を宣言したプロジェクトのソースに、その edition までの migration で
+/// 書き換わる箇所が残っていれば、該当する migration を返す（build の警告用）
+pub fn outdated_migrations(source: &str, edition: &str) -> Vec<&'static Migration> {
+    migrations_up_to(edition).into_iter().filter(|m| (m.apply)(source) != source).collect()
+}
+
+// =============================================================================
+// cast-to-as: cast_to_int(x) → x as i64
+// =============================================================================
+
+/// `cast_to_int(arg)` の呼び出しを `arg as i64` に置き換える（字句的な書き換え）。
+/// `as` は二項演算子より強く結合するため、オペランドでない実引数は括弧で囲む。
+/// 修飾された呼び出し（`m::cast_to_int(...)`、`x.cast_to_int(...)`）と実引数が 1 つでない
+/// 呼び出しはユーザー定義の関数なので触れない。コメントと文字列リテラルの中も書き換えない
+fn cast_to_as(source: &str) -> String {
+    let mut current = source.to_string();
+    // 後ろの呼び出しから 1 つずつ置き換える。入れ子の呼び出しは内側が後ろにあるので先に置き換わり、
+    // 外側の呼び出しは置き換え後のテキストで探し直す
+    let mut limit = current.len();
+    while let Some((start, end, replacement)) = last_cast_call(&current, limit) {
+        current.replace_range(start..end, &replacement);
+        limit = start;
+    }
+    current
+}
+
+/// limit より前から始まる最後の書き換え対象の呼び出し（開始位置, 終了位置, 置き換え後のテキスト）
+fn last_cast_call(source: &str, limit: usize) -> Option<(usize, usize, String)> {
+    let masked = parser::strip_comments(source, true);
+    let tokens = parser::tokenize(&masked);
+    // トークンはコメントを空白にしたテキストを指すスライス。バイト位置は source と同じ
+    let offset = |i: usize| tokens[i].as_ptr() as usize - masked.as_ptr() as usize;
+    for i in (0..tokens.len()).rev() {
+        if tokens[i] != "cast_to_int" || offset(i) >= limit || tokens.get(i + 1) != Some(&"(") {
+            continue;
+        }
+        // `::` は `:` 2 トークンになる。`body: cast_to_int(...)` の `:` 1 つは修飾ではない
+        let qualified = i > 0 && (tokens[i - 1] == "." || (tokens[i - 1] == ":" && i > 1 && tokens[i - 2] == ":"));
+        if qualified {
+            continue;
+        }
+        let close = match matching_paren(&tokens, i + 1) {
+            Some(close) => close,
+            None => continue,
+        };
+        let arg = source[offset(i + 1) + 1..offset(close)].trim();
+        if arg.is_empty() || has_top_level_comma(&tokens[i + 2..close]) {
+            continue;
+        }
+        let operand = if needs_parens(arg) { format!("({})", arg) } else { arg.to_string() };
+        let cast = format!("{} as i64", operand);
+        // `-cast_to_int(x)` の単項マイナスは `as` の外側に残す
+        let negated = i > 0 && tokens[i - 1] == "-" && parser::is_unary_position(if i >= 2 { tokens.get(i - 2) } else { None });
+        let replacement = if negated { format!("({})", cast) } else { cast };
+        return Some((offset(i), offset(close) + 1, replacement));
+    }
+    None
+}
+
+/// tokens[open] の `(` に対応する `)` の位置
+fn matching_paren(tokens: &[&str], open: usize) -> Option<usize> {
+    let mut depth = 0usize;
+    for (i, token) in tokens.iter().enumerate().skip(open) {
+        match *token {
+            "(" | "[" | "{" => depth += 1,
+            ")" | "]" | "}" => {
+                depth -= 1;
+                if depth == 0 {
+                    return if *token == ")" { Some(i) } else { None };
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// 括弧の外側に `,` があるか（実引数が複数）
+fn has_top_level_comma(tokens: &[&str]) -> bool {
+    let mut depth = 0i32;
+    tokens.iter().any(|token| {
+        match *token {
+            "(" | "[" | "{" => depth += 1,
+            ")" | "]" | "}" => depth -= 1,
+            _ => {}
+        }
+        depth == 0 && *token == ","
+    })
+}
+
+/// `arg as i64` の arg を括弧で囲む必要があるか（オペランド以外の式、または全体が括弧でない）
+fn needs_parens(arg: &str) -> bool {
+    let tokens = parser::tokenize(arg);
+    if tokens.first() == Some(&"(") && matching_paren(&tokens, 0) == Some(tokens.len() - 1) {
+        return false;
+    }
+    !matches!(
+        parser::parse_expression(arg),
+        Ok(Expr::Number(_) | Expr::Float(_) | Expr::Variable(_) | Expr::Call(..) | Expr::FieldAccess(..) | Expr::ArrayAccess(..))
+    )
+}
+
+// =============================================================================
+// --dry-run: unified diff
+// =============================================================================
+
+/// hunk の前後に付ける文脈行の数
+const CONTEXT: usize = 3;
+
+enum Line<'a> {
+    Same(&'a str),
+    Removed(&'a str),
+    Added(&'a str),
+}
+
+/// 行単位の unified diff（`--- a/<path>` / `+++ b/<path>` と文脈 3 行の hunk）。差分がなければ空文字列
+pub fn unified_diff(path: &str, old: &str, new: &str) -> String {
+    let a: Vec<&str> = old.lines().collect();
+    let b: Vec<&str> = new.lines().collect();
+    let lines = diff_lines(&a, &b);
+    let changed: Vec<usize> = lines.iter().enumerate()
+        .filter(|(_, l)| !matches!(l, Line::Same(_)))
+        .map(|(i, _)| i)
+        .collect();
+    if changed.is_empty() {
+        return String::new();
+    }
+
+    // 文脈が重なる変更は 1 つの hunk にまとめる
+    let mut hunks: Vec<(usize, usize)> = Vec::new();
+    for &i in &changed {
+        let (start, end) = (i.saturating_sub(CONTEXT), (i + CONTEXT + 1).min(lines.len()));
+        match hunks.last_mut() {
+            Some(last) if start <= last.1 => last.1 = end,
+            _ => hunks.push((start, end)),
+        }
+    }
+
+    let mut out = format!("--- a/{}\n+++ b/{}\n", path, path);
+    for (start, end) in hunks {
+        let old_before = lines[..start].iter().filter(|l| !matches!(l, Line::Added(_))).count();
+        let new_before = lines[..start].iter().filter(|l| !matches!(l, Line::Removed(_))).count();
+        let old_len = lines[start..end].iter().filter(|l| !matches!(l, Line::Added(_))).count();
+        let new_len = lines[start..end].iter().filter(|l| !matches!(l, Line::Removed(_))).count();
+        // 空の範囲は直前の行番号で表す（diff(1) と同じ）
+        let range = |before: usize, len: usize| format!("{},{}", if len == 0 { before } else { before + 1 }, len);
+        out.push_str(&format!("@@ -{} +{} @@\n", range(old_before, old_len), range(new_before, new_len)));
+        for line in &lines[start..end] {
+            match line {
+                Line::Same(l) => out.push_str(&format!(" {}\n", l)),
+                Line::Removed(l) => out.push_str(&format!("-{}\n", l)),
+                Line::Added(l) => out.push_str(&format!("+{}\n", l)),
+            }
+        }
+    }
+    out
+}
+
+/// 共通の先頭・末尾を除いた中間部分の LCS から行の対応を求める
+fn diff_lines<'a>(a: &[&'a str], b: &[&'a str]) -> Vec<Line<'a>> {
+    let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..].iter().rev().zip(b[prefix..].iter().rev()).take_while(|(x, y)| x == y).count();
+    let (ma, mb) = (&a[prefix..a.len() - suffix], &b[prefix..b.len() - suffix]);
+    // lcs[i][j]: ma[i..] と mb[j..] の最長共通部分列の長さ
+    let mut lcs = vec![vec![0u32; mb.len() + 1]; ma.len() + 1];
+    for i in (0..ma.len()).rev() {
+        for j in (0..mb.len()).rev() {
+            lcs[i][j] = if ma[i] == mb[j] { lcs[i + 1][j + 1] + 1 } else { lcs[i + 1][j].max(lcs[i][j + 1]) };
+        }
+    }
+
+    let mut lines: Vec<Line> = a[..prefix].iter().copied().map(Line::Same).collect();
+    let (mut i, mut j) = (0, 0);
+    while i < ma.len() || j < mb.len() {
+        if i < ma.len() && j < mb.len() && ma[i] == mb[j] {
+            lines.push(Line::Same(ma[i]));
+            i += 1;
+            j += 1;
+        } else if i < ma.len() && (j == mb.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            lines.push(Line::Removed(ma[i]));
+            i += 1;
+        } else {
+            lines.push(Line::Added(mb[j]));
+            j += 1;
+        }
+    }
+    lines.extend(a[a.len() - suffix..].iter().copied().map(Line::Same));
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{parse_expression, parse_module, Item};
+    use crate::test_support::assert_golden;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("golden").join("migrate").join(name)
+    }
+
+    /// atom ごとの (名前, 契約と本体の式)
+    fn atom_exprs(source: &str) -> Vec<(String, Vec<Expr>)> {
+        parse_module(source).unwrap().into_iter().filter_map(|item| match item {
+            Item::Atom(a) => {
+                let exprs = [&a.requires, &a.ensures, &a.body_expr].iter().map(|e| parse_expression(e).unwrap()).collect();
+                Some((a.name, exprs))
+            }
+            _ => None,
+        }).collect()
+    }
+
+    #[test]
+    fn test_migrations_match_fixtures() {
+        for migration in MIGRATIONS {
+            let input_path = fixture(&format!("{}.mm", migration.name));
+            let input = fs::read_to_string(&input_path).unwrap_or_else(|e| panic!("{}: {}", input_path.display(), e));
+            let migrated = (migration.apply)(&input);
+            assert_golden("migrate", &format!("{}.golden", migration.name), &migrated);
+            // 冪等で、書き換え前と同じ AST にパースされる
+            assert_eq!((migration.apply)(&migrated), migrated, "migration '{}' is not idempotent", migration.name);
+            assert_eq!(atom_exprs(&migrated), atom_exprs(&input), "migration '{}' changed the meaning", migration.name);
+        }
+    }
+
+    #[test]
+    fn test_migrate_source_refuses_parse_errors() {
+        let broken = "atom f(x: f64)\nrequires: true;\nensures: true;\nbody: if x > 0.0 { cast_to_int(x) };\n";
+        let err = migrate_source(broken, "src/broken.mm", "2026").err().unwrap();
+        assert!(err.contains("src/broken.mm:4:"), "{}", err);
+
+        let source = "atom f(x: f64)\nrequires: true;\nensures: true;\nbody: cast_to_int(x);\n";
+        let migrated = migrate_source(source, "src/f.mm", "2026").unwrap();
+        assert_eq!(migrated.applied, vec!["cast-to-as"]);
+        assert!(migrated.source.ends_with("body: x as i64;\n"), "{}", migrated.source);
+        // 古い edition までなら何もしない。適用済みのソースにも何もしない
+        assert!(migrate_source(source, "src/f.mm", "2025").unwrap().applied.is_empty());
+        assert!(migrate_source(&migrated.source, "src/f.mm", "2026").unwrap().applied.is_empty());
+
+        assert_eq!(outdated_migrations(source, "2026").len(), 1);
+        assert!(outdated_migrations(source, "2025").is_empty());
+        assert!(outdated_migrations(&migrated.source, "2026").is_empty());
+    }
+
+    #[test]
+    fn test_unified_diff() {
+        let old = "a\nb\nc\nd\ne\nf\ng\nh\ni\nj\n";
+        let new = "a\nB\nc\nd\ne\nf\ng\nh\ni\nj\nk\n";
+        assert_eq!(unified_diff("src/m.mm", old, new), "\
+--- a/src/m.mm
++++ b/src/m.mm
+@@ -1,5 +1,5 @@
+ a
+-b
++B
+ c
+ d
+ e
+@@ -8,3 +8,4 @@
+ h
+ i
+ j
++k
+");
+        assert_eq!(unified_diff("src/m.mm", old, old), "");
+        assert_eq!(unified_diff("new.mm", "", "x\n"), "--- a/new.mm\n+++ b/new.mm\n@@ -0,0 +1,1 @@\n+x\n");
+    }
+}
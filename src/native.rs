@@ -0,0 +1,194 @@
+//! # Native モジュール
+//!
+//! `mumei build --emit obj|exe` の実装。codegen が書き出した LLVM IR（.ll）を llc で
+//! オブジェクトファイル（.o）にし、exe ではさらに C のエントリスタブと一緒に
+//! システムの C コンパイラ（既定は `cc`、環境変数 CC で上書き）でリンクする。
+//!
+//! 実行ファイルの入口は引数なしの `atom main()`。codegen はこの atom を
+//! `mumei_main`（crate::codegen::ENTRY_SYMBOL）として出力し、スタブの C の main が
+//! その戻り値をプロセスの終了コードとして返す。
+//!
+//! llc は crate::setup::locate_llc で探す（MUMEI_LLC → ~/.mumei のツールチェーン → PATH）。
+use std::path::{Path, PathBuf};
+use std::process::{Command as Cmd, Output};
+use std::str::FromStr;
+use crate::ast::ResultKind;
+use crate::codegen::ENTRY_SYMBOL;
+use crate::parser::Atom;
+use crate::verification::{ModuleEnv, MumeiError, MumeiResult};
+
+/// `mumei build --emit` の出力形式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Emit {
+    /// LLVM IR（.ll）だけを出力する（従来どおり）
+    #[default]
+    LlvmIr,
+    /// .ll に加えて llc でオブジェクトファイル（.o）を出力する
+    Obj,
+    /// .o をエントリスタブとリンクして実行ファイルを出力する
+    Exe,
+}
+
+impl FromStr for Emit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "llvm-ir" => Ok(Emit::LlvmIr),
+            "obj" => Ok(Emit::Obj),
+            "exe" => Ok(Emit::Exe),
+            other => Err(format!("unknown emit kind '{}' (expected llvm-ir, obj or exe)", other)),
+        }
+    }
+}
+
+impl Emit {
+    /// `--emit` に書く名前
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Emit::LlvmIr => "llvm-ir",
+            Emit::Obj => "obj",
+            Emit::Exe => "exe",
+        }
+    }
+
+    /// llc が必要か（obj / exe）
+    pub fn needs_llc(self) -> bool {
+        self != Emit::LlvmIr
+    }
+}
+
+/// 実行ファイルの入口になる atom（`main`）を検査する。
+/// 引数なしで整数を返し、ネイティブターゲットから除外されていないこと
+pub fn check_entry(entry: Option<&Atom>, module_env: &ModuleEnv) -> MumeiResult<()> {
+    let atom = match entry {
+        Some(atom) => atom,
+        None => return Err(MumeiError::CodegenError(
+            "--emit exe needs an entry point: define `atom main()` returning i64 (its result becomes the exit code)".into()
+        )),
+    };
+    if !atom.params.is_empty() {
+        return Err(MumeiError::CodegenError(format!(
+            "entry atom 'main' must take no parameters, but it takes {}", atom.params.len()
+        )));
+    }
+    if crate::ast::struct_return(atom, module_env).is_some() || crate::ast::result_kind(atom, module_env) != ResultKind::Int {
+        return Err(MumeiError::CodegenError("entry atom 'main' must return an integer (its result becomes the exit code)".into()));
+    }
+    if !atom.targets.emits_ir() {
+        return Err(MumeiError::CodegenError("entry atom 'main' is excluded from native targets by @only/@exclude".into()));
+    }
+    Ok(())
+}
+
+/// 入口 atom を呼び出して終了コードにする C の main
+pub fn entry_stub() -> String {
+    format!(
+        "/* Generated by mumei build --emit exe: calls atom main() and returns its result as the exit code. */\n\
+         #include <stdint.h>\n\
+         \n\
+         int64_t {entry}(void);\n\
+         \n\
+         int main(void) {{\n    return (int){entry}();\n}}\n",
+        entry = ENTRY_SYMBOL
+    )
+}
+
+/// llc で IR をオブジェクトファイル（IR と同じ名前の .o）にする
+pub fn compile_object(llc: &Path, ir_path: &Path) -> MumeiResult<PathBuf> {
+    let obj_path = ir_path.with_extension("o");
+    let output = Cmd::new(llc)
+        .arg("-filetype=obj")
+        .arg("-relocation-model=pic")
+        .arg("-o").arg(&obj_path)
+        .arg(ir_path)
+        .output();
+    check_tool(&llc.display().to_string(), output)?;
+    Ok(obj_path)
+}
+
+/// オブジェクトファイルとエントリスタブを C コンパイラでリンクする
+pub fn link_executable(objects: &[PathBuf], stub_path: &Path, exe_path: &Path) -> MumeiResult<()> {
+    let cc = std::env::var("CC").ok().filter(|cc| !cc.is_empty()).unwrap_or_else(|| "cc".to_string());
+    // llvm.sqrt 等は libm に、acquire の pthread_mutex_* は libpthread に解決される
+    let output = Cmd::new(&cc)
+        .args(objects)
+        .arg(stub_path)
+        .arg("-o").arg(exe_path)
+        .arg("-lm")
+        .arg("-lpthread")
+        .output();
+    check_tool(&cc, output)
+}
+
+/// 外部ツールの実行結果を検査し、失敗ならツールの stderr を含むエラーにする
+fn check_tool(tool: &str, output: std::io::Result<Output>) -> MumeiResult<()> {
+    let output = output.map_err(|e| MumeiError::CodegenError(format!("failed to run {}: {}", tool, e)))?;
+    if output.status.success() {
+        return Ok(());
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let status = output.status.code().map_or_else(|| "a signal".to_string(), |code| format!("status {}", code));
+    Err(MumeiError::CodegenError(format!("{} exited with {}:\n{}", tool, status, stderr.trim_end())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{parse_module, Item};
+
+    #[test]
+    fn test_emit_kinds() {
+        assert_eq!("llvm-ir".parse::<Emit>(), Ok(Emit::LlvmIr));
+        assert_eq!("obj".parse::<Emit>(), Ok(Emit::Obj));
+        assert_eq!("exe".parse::<Emit>(), Ok(Emit::Exe));
+        assert!("asm".parse::<Emit>().unwrap_err().contains("expected llvm-ir, obj or exe"));
+        assert!(!Emit::default().needs_llc());
+        assert!(Emit::Obj.needs_llc() && Emit::Exe.needs_llc());
+        for emit in [Emit::LlvmIr, Emit::Obj, Emit::Exe] {
+            assert_eq!(emit.as_str().parse::<Emit>(), Ok(emit));
+        }
+    }
+
+    #[test]
+    fn test_entry_atom_checks() {
+        let items = parse_module(r#"
+atom main() requires: true; ensures: true; body: 0;
+atom flag() requires: true; ensures: true; body: 1 > 0;
+atom add(x: i64) requires: true; ensures: true; body: x + 1;
+"#).unwrap();
+        let mut module_env = ModuleEnv::new();
+        let atoms: Vec<&Atom> = items.iter().filter_map(|item| match item {
+            Item::Atom(atom) => Some(atom),
+            _ => None,
+        }).collect();
+        for atom in &atoms {
+            module_env.register_atom(atom);
+        }
+
+        assert!(check_entry(Some(atoms[0]), &module_env).is_ok());
+        let err = check_entry(None, &module_env).unwrap_err().to_string();
+        assert!(err.contains("define `atom main()`"), "{}", err);
+        let err = check_entry(Some(atoms[1]), &module_env).unwrap_err().to_string();
+        assert!(err.contains("must return an integer"), "{}", err);
+        let err = check_entry(Some(atoms[2]), &module_env).unwrap_err().to_string();
+        assert!(err.contains("must take no parameters"), "{}", err);
+
+        let stub = entry_stub();
+        assert!(stub.contains("int64_t mumei_main(void);") && stub.contains("return (int)mumei_main();"), "{}", stub);
+    }
+
+    #[test]
+    fn test_tool_failures_carry_stderr() {
+        let output = Cmd::new("sh").arg("-c").arg("echo 'llc: error: bad.ll:1:1: expected top-level entity' >&2; exit 1").output();
+        let err = check_tool("llc-18", output).unwrap_err().to_string();
+        assert!(err.contains("llc-18 exited with status 1:\nllc: error: bad.ll:1:1: expected top-level entity"), "{}", err);
+
+        let err = compile_object(Path::new("/nonexistent/mumei-llc"), Path::new("katana.ll")).unwrap_err().to_string();
+        assert!(err.contains("failed to run /nonexistent/mumei-llc"), "{}", err);
+
+        // MUMEI_LLC が実行できなければほかの候補に進まずエラーにする
+        let err = crate::setup::locate_llc_with(Some("/nonexistent/mumei-llc".into())).unwrap_err();
+        assert!(err.contains("MUMEI_LLC=/nonexistent/mumei-llc could not be run"), "{}", err);
+    }
+}
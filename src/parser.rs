@@ -1,4 +1,6 @@
 use regex::Regex;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
 use crate::ast::TypeRef;
 
 // --- 1. 数式の構造定義 (AST: Abstract Syntax Tree) ---
@@ -37,10 +39,14 @@ pub enum ResourceMode {
     Shared,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Expr {
     Number(i64),
     Float(f64),
+    /// 文字列リテラル `"..."`（エスケープ解除済み）。Str 型の値で、検証では長さだけを扱う
+    StringLit(String),
+    /// 真偽値リテラル `true` / `false`
+    Bool(bool),
     Variable(String),
     ArrayAccess(String, Box<Expr>),
     BinaryOp(Box<Expr>, Op, Box<Expr>),
@@ -95,10 +101,202 @@ pub enum Expr {
     Await {
         expr: Box<Expr>,
     },
+    /// 仮定文: assume(cond, "justification")
+    /// モデルの外で保証される事実（ハードウェア仕様・OS の保証など）を証明なしで仮定する。
+    /// 検証では cond をその時点の事実として solver に追加し、codegen / transpiler では消去する。
+    Assume {
+        cond: Box<Expr>,
+        /// 表示用の条件式テキスト（検証サマリ・report.json）
+        text: String,
+        /// 仮定の根拠。空ならパースエラー（atom_assumptions が報告する）
+        justification: String,
+    },
+    /// 参照渡しの実引数: f(ref x) / f(ref mut x)。呼び出しの引数にだけ現れる。
+    /// 値としては x と同じ。検証で x が変数であること・呼び出し先パラメータの ref / ref mut と
+    /// 一致することを確かめ、トランスパイラは &x / &mut x を出力する。
+    RefArg {
+        expr: Box<Expr>,
+        mutable: bool,
+    },
+}
+
+impl Expr {
+    /// 参照渡しのマーカー（`ref x` / `ref mut x`）を外した実引数
+    pub fn without_ref_marker(&self) -> &Expr {
+        match self {
+            Expr::RefArg { expr, .. } => expr,
+            other => other,
+        }
+    }
+
+    /// 左に連なる二項演算（`a + b + c` は `(a + b) + c`）の左端のオペランドと、その上の BinaryOp を
+    /// 内側から順に返す。生成コードなどの長い連鎖を、連鎖の長さぶん再帰せずに辿るために使う
+    pub fn left_spine(&self) -> (&Expr, Vec<&Expr>) {
+        let mut nodes = Vec::new();
+        let mut leaf = self;
+        while let Expr::BinaryOp(left, _, _) = leaf {
+            nodes.push(leaf);
+            leaf = left;
+        }
+        nodes.reverse();
+        (leaf, nodes)
+    }
+
+    /// 直下の部分式をソース上の順に f へ渡す（match アームはガード → 本体の順）
+    pub fn for_each_child<'a>(&'a self, mut f: impl FnMut(&'a Expr)) {
+        match self {
+            Expr::Number(_) | Expr::Float(_) | Expr::StringLit(_) | Expr::Bool(_) | Expr::Variable(_) => {}
+            Expr::ArrayAccess(_, e)
+            | Expr::Let { value: e, .. }
+            | Expr::Assign { value: e, .. }
+            | Expr::FieldAccess(e, _)
+            | Expr::Acquire { body: e, .. }
+            | Expr::Async { body: e }
+            | Expr::Await { expr: e }
+            | Expr::Assume { cond: e, .. }
+            | Expr::RefArg { expr: e, .. } => f(e),
+            Expr::BinaryOp(l, _, r) => {
+                f(l);
+                f(r);
+            }
+            Expr::IfThenElse { cond, then_branch, else_branch } => {
+                f(cond);
+                f(then_branch);
+                f(else_branch);
+            }
+            Expr::While { cond, invariant, decreases, body } => {
+                f(cond);
+                f(invariant);
+                if let Some(d) = decreases {
+                    f(d);
+                }
+                f(body);
+            }
+            Expr::Block(exprs) | Expr::Call(_, exprs) => {
+                for e in exprs {
+                    f(e);
+                }
+            }
+            Expr::StructInit { fields, .. } => {
+                for (_, e) in fields {
+                    f(e);
+                }
+            }
+            Expr::Match { target, arms } => {
+                f(target);
+                for arm in arms {
+                    if let Some(guard) = &arm.guard {
+                        f(guard);
+                    }
+                    f(&arm.body);
+                }
+            }
+        }
+    }
+
+    /// for_each_child の可変版
+    pub fn for_each_child_mut<'a>(&'a mut self, mut f: impl FnMut(&'a mut Expr)) {
+        match self {
+            Expr::Number(_) | Expr::Float(_) | Expr::StringLit(_) | Expr::Bool(_) | Expr::Variable(_) => {}
+            Expr::ArrayAccess(_, e)
+            | Expr::Let { value: e, .. }
+            | Expr::Assign { value: e, .. }
+            | Expr::FieldAccess(e, _)
+            | Expr::Acquire { body: e, .. }
+            | Expr::Async { body: e }
+            | Expr::Await { expr: e }
+            | Expr::Assume { cond: e, .. }
+            | Expr::RefArg { expr: e, .. } => f(e),
+            Expr::BinaryOp(l, _, r) => {
+                f(l);
+                f(r);
+            }
+            Expr::IfThenElse { cond, then_branch, else_branch } => {
+                f(cond);
+                f(then_branch);
+                f(else_branch);
+            }
+            Expr::While { cond, invariant, decreases, body } => {
+                f(cond);
+                f(invariant);
+                if let Some(d) = decreases {
+                    f(d);
+                }
+                f(body);
+            }
+            Expr::Block(exprs) | Expr::Call(_, exprs) => {
+                for e in exprs {
+                    f(e);
+                }
+            }
+            Expr::StructInit { fields, .. } => {
+                for (_, e) in fields {
+                    f(e);
+                }
+            }
+            Expr::Match { target, arms } => {
+                f(target);
+                for arm in arms {
+                    if let Some(guard) = &mut arm.guard {
+                        f(guard);
+                    }
+                    f(&mut arm.body);
+                }
+            }
+        }
+    }
+
+    /// 自身とすべての部分式を前順（ソース上の順）に visit へ渡す。visit が false を返した式の
+    /// 部分式には降りない。作業リストで辿るので、長い演算の連鎖でもスタックを消費しない
+    pub fn walk<'a>(&'a self, mut visit: impl FnMut(&'a Expr) -> bool) {
+        let mut pending = vec![self];
+        while let Some(expr) = pending.pop() {
+            if visit(expr) {
+                let start = pending.len();
+                expr.for_each_child(|child| pending.push(child));
+                pending[start..].reverse();
+            }
+        }
+    }
+
+    /// walk の可変版。visit は式を書き換えてよく、false を返せばその中には降りない
+    pub fn walk_mut(&mut self, mut visit: impl FnMut(&mut Expr) -> bool) {
+        let mut pending = vec![self];
+        while let Some(expr) = pending.pop() {
+            if visit(expr) {
+                let start = pending.len();
+                expr.for_each_child_mut(|child| pending.push(child));
+                pending[start..].reverse();
+            }
+        }
+    }
+
+    /// pred を満たす部分式（自身を含む）があるか
+    pub fn any(&self, mut pred: impl FnMut(&Expr) -> bool) -> bool {
+        let mut found = false;
+        self.walk(|e| {
+            found = found || pred(e);
+            !found
+        });
+        found
+    }
+}
+
+/// 式の木を作業リストで破棄する。derive の Drop は子を再帰的に破棄するため、
+/// 生成コードの長い演算の連鎖（10 万項の `a + b + ...`）では既定のスタックを使い切る
+impl Drop for Expr {
+    fn drop(&mut self) {
+        // 子を葉（`0`）と入れ替えて取り出す。取り出した子は自身の子を取り出してから破棄されるので再帰しない
+        let mut pending = Vec::new();
+        self.for_each_child_mut(|child| pending.push(std::mem::replace(child, Expr::Number(0))));
+        while let Some(mut expr) = pending.pop() {
+            expr.for_each_child_mut(|child| pending.push(std::mem::replace(child, Expr::Number(0))));
+        }
+    }
 }
 
 /// Match 式のアーム（パターン → 式）
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct MatchArm {
     pub pattern: Pattern,
     /// オプションのガード条件: match x { Pattern if cond => ... }
@@ -107,7 +305,7 @@ pub struct MatchArm {
 }
 
 /// パターン
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Pattern {
     /// ワイルドカード: _
     Wildcard,
@@ -146,8 +344,8 @@ pub struct EnumDef {
     /// Generics: 型パラメータリスト（例: ["T", "U"]）。非ジェネリックなら空。
     pub type_params: Vec<String>,
     pub variants: Vec<EnumVariant>,
-    /// この Enum が再帰的データ型か（いずれかの Variant が自身を参照するか）
-    #[allow(dead_code)]
+    /// この Enum が再帰的データ型か（いずれかの Variant が自身を参照するか）。
+    /// transpiler は再帰フィールドを間接参照で出力し、codegen は構築を拒否する。
     pub is_recursive: bool,
 }
 
@@ -205,9 +403,23 @@ pub struct Atom {
     #[allow(dead_code)]
     pub where_bounds: Vec<TypeParamBound>,
     pub params: Vec<Param>,
+    /// 宣言した戻り値型（`atom make_point(a: i64) -> Point`）。なければ本体の値から推論する
+    /// （crate::ast::result_kind）。構造体型なら検証・コード生成・transpiler がその構造体を返す
+    pub return_type: Option<String>,
+    /// 正規化済みの事前条件（空白・改行を1スペースに畳んだ1行）。パース・検証に使う。
     pub requires: String,
+    /// 記述どおりの事前条件（改行・相対インデントを保持、コメント除去済み）。
+    /// hover・エラーメッセージ・生成コードのドキュメントコメント等の表示に使う。
+    pub requires_raw: String,
+    /// `where:` 節（パラメータ間の関係を表す型的な不変量。例: "lo <= hi"）の記述どおりのテキスト。
+    /// 検証と呼び出し側のチェックでは requires の追加の連言として扱い（requires に結合済み）、
+    /// 表示（check / hover / 生成コードのドキュメント / report.json）では requires と分けて示す。
+    pub where_clause: Option<String>,
     pub forall_constraints: Vec<Quantifier>,
+    /// 正規化済みの事後条件。`ensures:` が複数ある場合は各節を && で結合したもの。
     pub ensures: String,
+    /// 記述どおりの事後条件。`ensures:` が複数ある場合は "- 節" の箇条書き。
+    pub ensures_raw: String,
     pub body_expr: String,
     /// 所有権の消費対象パラメータ名リスト（Linear Types）
     /// `atom take(x: T) consume x;` の場合: consumed_params = ["x"]
@@ -221,6 +433,9 @@ pub struct Atom {
     /// この atom が非同期（async）かどうか
     /// `async atom fetch(url: Str)` の場合: is_async = true
     pub is_async: bool,
+    /// `total atom` かどうか。total atom はゼロ除算・範囲外アクセス・網羅性・停止性などの
+    /// 暗黙の義務をすべて requires のみから証明しなければならない（Unknown も失敗扱い）。
+    pub is_total: bool,
     /// 信頼レベル（外部ライブラリとの境界）
     /// - Verified: 完全に検証される（デフォルト）
     /// - Trusted: requires/ensures の契約のみ信頼し、body は検証しない
@@ -246,6 +461,66 @@ pub struct Atom {
     /// 2. 維持 (Preservation): invariant が成立する状態で body を実行した後も invariant が維持されることを証明
     /// 3. 再帰呼び出し時: 呼び出し先の invariant を仮定として使用（帰納法の仮定）
     pub invariant: Option<String>,
+    /// atom レベルの停止性測度（`decreases: n;`）。ヘッダ（`body:` より前）にだけ書ける。
+    /// 自己再帰呼び出しのたびに「測度が 0 以上」かつ「呼び出し元より真に小さい」ことを証明し、
+    /// そのうえで呼び出し先の ensures を仮定する（整礎帰納法）。
+    pub decreases: Option<String>,
+    /// 出力先ターゲットの注釈（`@only(rust, go)` / `@exclude(typescript)`）。注釈がなければ全ターゲット
+    pub targets: AtomTargets,
+}
+
+// =============================================================================
+// 出力ターゲット注釈 (@only / @exclude)
+// =============================================================================
+
+/// atom ごとの出力先ターゲット名（`[build] targets` の言語名と、LLVM IR の `llvm`）
+pub const ATOM_TARGETS: [&str; 4] = ["llvm", "rust", "go", "typescript"];
+
+/// ネイティブ系のターゲット。どちらかに含まれる atom だけが .ll を出力する
+const NATIVE_TARGETS: [&str; 2] = ["llvm", "rust"];
+
+/// ターゲット名の別名を正規化する（`ts` → `typescript`）
+fn canonical_target(name: &str) -> String {
+    match name {
+        "ts" => "typescript".to_string(),
+        other => other.to_string(),
+    }
+}
+
+/// atom の出力先ターゲット注釈。
+/// `@only(...)` は出力先を列挙したものに限定し、`@exclude(...)` はそこから取り除く。
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AtomTargets {
+    /// `@only(...)` の対象（None なら全ターゲット）
+    pub only: Option<Vec<String>>,
+    /// `@exclude(...)` の対象
+    pub exclude: Vec<String>,
+}
+
+impl AtomTargets {
+    /// target（`rust` / `go` / `typescript` / `llvm`）向けに出力するか
+    pub fn includes(&self, target: &str) -> bool {
+        self.only.as_ref().map_or(true, |only| only.iter().any(|t| t == target))
+            && !self.exclude.iter().any(|t| t == target)
+    }
+
+    /// 出力するターゲット（ATOM_TARGETS の順）
+    pub fn effective(&self) -> Vec<String> {
+        ATOM_TARGETS.iter().filter(|t| self.includes(t)).map(|t| t.to_string()).collect()
+    }
+
+    /// LLVM IR（.ll）を出力するか。ネイティブ系ターゲットのすべてから除外された atom だけ出力しない
+    pub fn emits_ir(&self) -> bool {
+        NATIVE_TARGETS.iter().any(|t| self.includes(t))
+    }
+
+    /// 注釈に現れる未知のターゲット名
+    pub fn unknown(&self) -> Vec<String> {
+        self.only.iter().flatten().chain(&self.exclude)
+            .filter(|t| !ATOM_TARGETS.contains(&t.as_str()))
+            .cloned()
+            .collect()
+    }
 }
 
 // =============================================================================
@@ -291,6 +566,9 @@ pub struct StructDef {
     /// 実際の Atom 定義は ModuleEnv.atoms に "Stack::push" のような FQN で登録される。
     #[allow(dead_code)]
     pub method_names: Vec<String>,
+    /// 複数のフィールドにまたがる不変条件（`invariant: balance >= reserved`）。フィールド名で書く。
+    /// 構造体パラメータと構造体を返す呼び出しの結果では仮定し、構造体リテラルと構造体を返す atom では証明する
+    pub invariant: Option<String>,
 }
 
 /// インポート宣言
@@ -300,6 +578,17 @@ pub struct ImportDecl {
     pub path: String,
     /// エイリアス（例: as math → Some("math")）
     pub alias: Option<String>,
+    /// 素の名前で取り込む item（例: `using {add, Vec2}` → Some(["add", "Vec2"])）。
+    /// None なら従来通り公開 item をすべて素の名前で取り込む。エイリアスによる FQN 参照は常にすべて可能
+    pub using: Option<Vec<String>>,
+}
+
+/// エクスポート宣言: `export { push, pop, Stack };`
+/// モジュールに 1 つでもあれば、列挙された item だけがインポート側から参照できる。
+/// 1 つもなければ後方互換のため全 item を公開する。
+#[derive(Debug, Clone)]
+pub struct ExportDecl {
+    pub names: Vec<String>,
 }
 
 /// トレイト境界: 型パラメータに課す制約（例: "T: Comparable"）
@@ -323,7 +612,7 @@ pub struct TraitMethod {
     /// パラメータごとの精緻型制約（例: "v != 0"）。制約がないパラメータは None。
     /// `fn div(a: Self, b: Self where v != 0) -> Self;` の場合:
     /// param_constraints = [None, Some("v != 0")]
-    #[allow(dead_code)]
+    /// impl の law 検証では前提として仮定され、atom からの直接呼び出しでは証明義務になる。
     pub param_constraints: Vec<Option<String>>,
 }
 
@@ -334,6 +623,11 @@ pub struct TraitMethod {
 ///     law reflexive: leq(x, x) == true;
 ///     law transitive: leq(a, b) && leq(b, c) => leq(a, c);
 /// }
+///
+/// trait Monoid extends Semigroup with add = combine {
+///     fn unit() -> Self;
+///     law left_identity: combine(unit(), a) == a;
+/// }
 /// ```
 #[derive(Debug, Clone)]
 pub struct TraitDef {
@@ -344,6 +638,9 @@ pub struct TraitDef {
     /// 法則（Laws）: トレイトが満たすべき論理的性質。
     /// 各要素は (法則名, 論理式の文字列) のペア。
     pub laws: Vec<(String, String)>,
+    /// 継承する親トレイト: (親トレイト名, 親メソッド名 → 子メソッド名 のリネーム表)。
+    /// 親のメソッドと law は継承され、impl は親の law も満たす必要がある。
+    pub extends: Vec<(String, HashMap<String, String>)>,
 }
 
 /// トレイト実装定義
@@ -362,6 +659,22 @@ pub struct ImplDef {
     pub method_bodies: Vec<(String, String)>,
 }
 
+/// 具体値の例によるテスト（`mumei test` が実行する）
+/// ```mumei
+/// test increment_basic {
+///     assert increment(1) == 2;
+///     assert increment(0) >= 1;
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct TestDef {
+    pub name: String,
+    /// `assert` に続く式の文字列（検証の契約と同じく使う側で parse_expression する）
+    pub assertions: Vec<String>,
+    /// `assert` で始まらない文。`mumei test` がエラーとして報告する
+    pub malformed: Vec<String>,
+}
+
 #[derive(Debug, Clone)]
 pub enum Item {
     Atom(Atom),
@@ -373,6 +686,10 @@ pub enum Item {
     ImplDef(ImplDef),
     /// リソース定義: resource name priority mode;
     ResourceDef(ResourceDef),
+    /// エクスポート宣言: export { name, ... };
+    Export(ExportDecl),
+    /// テスト: test name { assert expr; ... }
+    TestDef(TestDef),
 }
 
 // --- 3. Generics パースヘルパー ---
@@ -410,8 +727,16 @@ fn parse_type_params_from_str(input: &str) -> (Vec<String>, usize) {
 }
 
 /// 型参照文字列（例: "Stack<i64>", "i64", "Map<String, List<i64>>"）を TypeRef にパースする。
+/// nullable 糖衣 `T?` は `Option<T>` に脱糖する。
 pub fn parse_type_ref(input: &str) -> TypeRef {
     let input = input.trim();
+    if let Some(inner) = input.strip_suffix('?') {
+        return TypeRef::generic("Option", vec![parse_type_ref(inner)]);
+    }
+    if let Some(elem) = input.strip_prefix('[').and_then(|rest| rest.strip_suffix(']')) {
+        // 配列型: "[f64]" → TypeRef("slice", [f64])
+        return TypeRef::slice(parse_type_ref(elem));
+    }
     if let Some(angle_pos) = input.find('<') {
         // ジェネリック型: "Stack<i64>" → name="Stack", type_args=[TypeRef("i64")]
         let name = input[..angle_pos].trim().to_string();
@@ -430,15 +755,15 @@ pub fn parse_type_ref(input: &str) -> TypeRef {
     }
 }
 
-/// ネストした `<>` を考慮してカンマで型引数を分割する
+/// ネストした `<>` / `()` を考慮してカンマで型引数（や enum の variant 列）を分割する
 fn split_type_args(input: &str) -> Vec<String> {
     let mut result = Vec::new();
     let mut depth = 0;
     let mut current = String::new();
     for c in input.chars() {
         match c {
-            '<' => { depth += 1; current.push(c); }
-            '>' => { depth -= 1; current.push(c); }
+            '<' | '(' => { depth += 1; current.push(c); }
+            '>' | ')' => { depth -= 1; current.push(c); }
             ',' if depth == 0 => {
                 let trimmed = current.trim().to_string();
                 if !trimmed.is_empty() {
@@ -481,33 +806,320 @@ fn parse_type_params_with_bounds(input: &str) -> (Vec<String>, Vec<TypeParamBoun
 
 // --- 4. メインパーサーロジック ---
 
-pub fn parse_module(source: &str) -> Vec<Item> {
+/// trait の extends 句をパースする。
+/// "Semigroup with add = combine + Eq" → [("Semigroup", {add: combine}), ("Eq", {})]
+fn parse_trait_extends(clause: &str) -> Vec<(String, HashMap<String, String>)> {
+    clause.split('+')
+        .filter_map(|parent| {
+            let parent = parent.trim();
+            // `with` の前後は改行でもよい（" with " 固定だと行末の `with` を取りこぼす）
+            let with_re = Regex::new(r"\s+with\s+").unwrap();
+            let (parent_name, renames_str) = match with_re.find(parent) {
+                Some(m) => (parent[..m.start()].trim(), Some(&parent[m.end()..])),
+                None => (parent, None),
+            };
+            if parent_name.is_empty() {
+                return None;
+            }
+            let mut renames = HashMap::new();
+            if let Some(renames_str) = renames_str {
+                for pair in renames_str.split(',') {
+                    if let Some((from, to)) = pair.split_once('=') {
+                        renames.insert(from.trim().to_string(), to.trim().to_string());
+                    }
+                }
+            }
+            Some((parent_name.to_string(), renames))
+        })
+        .collect()
+}
+
+/// ソーステキストの改行と BOM を正規化する（CRLF / CR → LF、先頭の BOM を除去）。
+/// ファイルや LSP から読み込んだソースは必ずここを通してからパース・ハッシュ化する。
+/// OS ごとのチェックアウト差（core.autocrlf 等）で検証キャッシュのハッシュが変わらないようにするため。
+pub fn normalize_source(source: &str) -> String {
+    normalized_source(source).into_owned()
+}
+
+/// normalize_source の借用版。正規化が不要なら（LF のみのソースでは常に）コピーしない
+fn normalized_source(source: &str) -> Cow<'_, str> {
+    let source = source.strip_prefix('\u{feff}').unwrap_or(source);
+    if !source.contains('\r') {
+        return Cow::Borrowed(source);
+    }
+    Cow::Owned(source.replace("\r\n", "\n").replace('\r', "\n"))
+}
+
+// トップレベル item の抽出パターン（parse_module と explain_parse で共有する）
+const IMPORT_PATTERN: &str = r#"(?m)^import\s+"([^"]+)"(?:\s+as\s+(\w+))?(?:\s+using\s*\{([^}]*)\})?\s*;"#;
+const TYPE_PATTERN: &str = r"(?m)^type\s+(\w+)\s*=\s*(\w+)\s+where\s+([^;]+);";
+const ATOM_PATTERN: &str = r"\batom\s+\w+";
+const MODIFIED_ATOM_PATTERN: &str = r"(?:@(?:only|exclude)\s*\([^)]*\)\s*)+(?:(?:async|trusted|unverified|total)\s+)*atom\s+\w+|\b(?:(?:async|trusted|unverified|total)\s+)+atom\s+\w+";
+const TARGET_ANNOTATION_PATTERN: &str = r"@(only|exclude)\s*\(([^)]*)\)";
+const STRUCT_PATTERN: &str = r"(?m)^struct\s+(\w+)\s*(<[^>]*>)?\s*\{([^}]*)\}";
+const ENUM_PATTERN: &str = r"(?m)^enum\s+(\w+)\s*(<[^>]*>)?\s*\{([^}]*)\}";
+const TRAIT_PATTERN: &str = r"(?m)^trait\s+(\w+)(?:\s+extends\s+([^{]+?))?\s*\{([^}]*)\}";
+const IMPL_HEADER_PATTERN: &str = r"(?m)^impl\s+(\w+)\s+for\s+(\w+)\s*\{";
+const RESOURCE_PATTERN: &str = r"(?m)^resource\s+(\w+)\s+priority:\s*(-?\d+)\s+mode:\s*(exclusive|shared)\s*;";
+const EXPORT_PATTERN: &str = r"(?m)^export\s*\{([^}]*)\}\s*;";
+const TEST_HEADER_PATTERN: &str = r"(?m)^test\s+(\w+)\s*\{";
+/// 行コメント。文字列リテラル（import のパス・assume の正当化文）内の `//` をコメントとみなさないよう、
+/// 文字列リテラルも同じ選択肢でマッチさせ、置換時にそのまま残す
+const COMMENT_PATTERN: &str = r#""(?:[^"\\\n]|\\.)*"|//[^\n]*"#;
+
+/// 行コメントの区間（バイト位置）。文字列リテラル内の `//` は含まない
+fn comment_spans(source: &str) -> Vec<(usize, usize)> {
+    Regex::new(COMMENT_PATTERN).unwrap().find_iter(source)
+        .filter(|m| !m.as_str().starts_with('"'))
+        .map(|m| (m.start(), m.end()))
+        .collect()
+}
+
+/// 行コメントを除去したソース。コメントがなければ入力を借用したまま返し、あれば 1 回の確保で組み立てる。
+/// keep_offsets ならコメントを同じバイト数の空白で置き換え、以降のバイト位置を保つ
+pub(crate) fn strip_comments(source: &str, keep_offsets: bool) -> Cow<'_, str> {
+    let spans = comment_spans(source);
+    if spans.is_empty() {
+        return Cow::Borrowed(source);
+    }
+    let removed: usize = spans.iter().map(|(start, end)| end - start).sum();
+    let mut out = String::with_capacity(if keep_offsets { source.len() } else { source.len() - removed });
+    let mut last = 0;
+    for (start, end) in spans {
+        out.push_str(&source[last..start]);
+        if keep_offsets {
+            out.extend(std::iter::repeat(' ').take(end - start));
+        }
+        last = end;
+    }
+    out.push_str(&source[last..]);
+    Cow::Owned(out)
+}
+
+/// トップレベル item 1 件が占める区間（コメント除去後のソース上のバイト位置）
+struct ItemSpan {
+    start: usize,
+    end: usize,
+    /// item の種類（"atom", "async trusted atom", "struct" ...）
+    kind: String,
+    name: String,
+    /// atom の場合、修飾子を除いた `atom` キーワードの位置
+    keyword: Option<usize>,
+}
+
+/// ソースを位置順に走査し、各バイトを高々 1 つの item に割り当てる。
+/// まずブロック型の item（import / type / struct / enum / trait / impl / resource / export）を確定し、
+/// 重なるマッチは先に始まる方だけを残す。atom はどのブロックにも、先行する atom の本体にも
+/// 含まれない位置のものだけを拾う（impl のメソッド本体や atom の `{}` 本体に現れる
+/// `atom push` のような文字列を、トップレベルの atom として二重にパースしないため）。
+fn scan_items(source: &str) -> Vec<ItemSpan> {
+    let mut blocks: Vec<ItemSpan> = Vec::new();
+    let simple_items = [
+        (IMPORT_PATTERN, "import"),
+        (TYPE_PATTERN, "type"),
+        (STRUCT_PATTERN, "struct"),
+        (ENUM_PATTERN, "enum"),
+        (TRAIT_PATTERN, "trait"),
+        (RESOURCE_PATTERN, "resource"),
+        (EXPORT_PATTERN, "export"),
+    ];
+    for (pattern, kind) in simple_items {
+        for cap in Regex::new(pattern).unwrap().captures_iter(source) {
+            let m = cap.get(0).unwrap();
+            // export の名前リストは改行を含みうるので 1 行に畳む
+            let name = cap[1].split_whitespace().collect::<Vec<_>>().join(" ");
+            blocks.push(ItemSpan { start: m.start(), end: m.end(), kind: kind.to_string(), name, keyword: None });
+        }
+    }
+    // impl はヘッダーから対応する '}' まで
+    for cap in Regex::new(IMPL_HEADER_PATTERN).unwrap().captures_iter(source) {
+        let m = cap.get(0).unwrap();
+        let end = matching_brace_end(source, m.end() - 1);
+        blocks.push(ItemSpan { start: m.start(), end, kind: "impl".to_string(), name: format!("{} for {}", &cap[1], &cap[2]), keyword: None });
+    }
+    // test もヘッダーから対応する '}' まで（assert の式が {} を含みうる）
+    for cap in Regex::new(TEST_HEADER_PATTERN).unwrap().captures_iter(source) {
+        let m = cap.get(0).unwrap();
+        let end = matching_brace_end(source, m.end() - 1);
+        blocks.push(ItemSpan { start: m.start(), end, kind: "test".to_string(), name: cap[1].to_string(), keyword: None });
+    }
+    blocks.sort_by_key(|b| (b.start, std::cmp::Reverse(b.end)));
+    let mut spans: Vec<ItemSpan> = Vec::new();
+    for block in blocks {
+        if spans.last().map_or(true, |prev| block.start >= prev.end) {
+            spans.push(block);
+        }
+    }
+    let in_block = |pos: usize| spans.iter().any(|b| b.start <= pos && pos < b.end);
+
+    // atom の候補: ブロック外の `atom name`。修飾子があればその先頭から始まる
+    let modified: Vec<_> = Regex::new(MODIFIED_ATOM_PATTERN).unwrap().find_iter(source).collect();
+    let candidates: Vec<(usize, regex::Match)> = Regex::new(ATOM_PATTERN).unwrap()
+        .find_iter(source)
+        .filter(|m| !in_block(m.start()))
+        .map(|m| {
+            let start = modified.iter()
+                .find(|mm| mm.start() <= m.start() && m.end() <= mm.end())
+                .map_or(m.start(), |mm| mm.start());
+            (start, m)
+        })
+        .collect();
+
+    let mut atoms: Vec<ItemSpan> = Vec::new();
+    for (i, (start, m)) in candidates.iter().enumerate() {
+        // 先行する atom の本体に含まれる候補は atom ではない
+        if atoms.last().map_or(false, |prev| m.start() < prev.end) {
+            continue;
+        }
+        let next_block = spans.iter().map(|b| b.start).find(|&b| b > m.start()).unwrap_or(source.len());
+        let next_atom = candidates.get(i + 1).map_or(source.len(), |(next, _)| *next).min(next_block);
+        let end = atom_span_end(source, m.start(), m.end(), next_atom, next_block);
+        // kind には修飾子だけを含める（`@only(...)` などのターゲット注釈は除く）
+        let prefix = Regex::new(TARGET_ANNOTATION_PATTERN).unwrap().replace_all(&source[*start..m.start()], "").to_string();
+        let modifiers: Vec<&str> = prefix.split_whitespace().collect();
+        let kind = if modifiers.is_empty() { "atom".to_string() } else { format!("{} atom", modifiers.join(" ")) };
+        let name = m.as_str()["atom".len()..].trim().to_string();
+        atoms.push(ItemSpan { start: *start, end, kind, name, keyword: Some(m.start()) });
+    }
+    spans.extend(atoms);
+    spans.sort_by_key(|s| (s.start, s.end));
+    spans
+}
+
+// =============================================================================
+// パースエラー
+// =============================================================================
+//
+// parse_module / parse_atom / parse_expression は、不正なソースに対して panic せず
+// ParseError を返す。offset は渡されたソース先頭からのバイト位置で、line / column は
+// 同じソース上の 1 始まりの行・列（列は文字数）。CLI は render でキャレット付きの診断を表示し、
+// LSP は line / column / len から diagnostic の range を作る。
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub message: String,
+    /// 問題の箇所のバイト位置
+    pub offset: usize,
+    /// 問題の箇所のバイト長（入力の末尾では 0）
+    pub len: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl ParseError {
+    fn new(source: &str, offset: usize, len: usize, message: impl Into<String>) -> Self {
+        let mut error = ParseError { message: message.into(), offset, len, line: 1, column: 1 };
+        error.locate(source);
+        error
+    }
+
+    /// offset から line / column を計算し直す
+    fn locate(&mut self, source: &str) {
+        let offset = self.offset.min(source.len());
+        let before = source.get(..offset).unwrap_or(source);
+        self.line = before.matches('\n').count() + 1;
+        self.column = before.rsplit('\n').next().map_or(0, |l| l.chars().count()) + 1;
+    }
+
+    /// 部分文字列（base の位置から始まる）に対するエラーを、外側の source 上の位置に移す
+    fn shifted(mut self, source: &str, base: usize) -> Self {
+        self.offset += base;
+        self.locate(source);
+        self
+    }
+
+    /// 問題の箇所の表示幅（先頭行の文字数。入力の末尾でも 1）
+    pub fn width(&self, source: &str) -> usize {
+        source.get(self.offset..self.offset + self.len)
+            .map_or(1, |text| text.lines().next().unwrap_or("").chars().count().max(1))
+    }
+
+    /// rustc 風のキャレット付き診断。source はエラーを返したパース関数に渡したもの
+    pub fn render(&self, source: &str, path: &str) -> String {
+        let line_text = source.lines().nth(self.line - 1).unwrap_or("");
+        let gutter = " ".repeat(self.line.to_string().len());
+        let indent: String = line_text.chars().take(self.column - 1)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        let width = self.width(source);
+        format!(
+            "Parse error: {}\n{}--> {}:{}:{}\n{} |\n{} | {}\n{} | {}{}",
+            self.message, gutter, path, self.line, self.column,
+            gutter, self.line, line_text, gutter, indent, "^".repeat(width)
+        )
+    }
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// コメントを除去したソース上のバイト位置を、除去前のソース上の位置に戻す
+fn unstripped_offset(comments: &[(usize, usize)], offset: usize) -> usize {
+    let mut offset = offset;
+    for (start, end) in comments {
+        if *start <= offset {
+            offset += end - start;
+        } else {
+            break;
+        }
+    }
+    offset
+}
+
+/// ソース全体をパースする。エラーの位置は正規化（改行・BOM）後の source 上のもの
+pub fn parse_module(source: &str) -> Result<Vec<Item>, ParseError> {
     let mut items = Vec::new();
 
-    // コメント除去: // から行末までを削除（文字列リテラル内は考慮しない簡易実装）
-    let comment_re = Regex::new(r"//[^\n]*").unwrap();
-    let source = comment_re.replace_all(source, "").to_string();
-    let source = source.as_str();
+    // 読み込み側で正規化済みのはずだが、ライブラリ API に生の文字列が渡された場合に備えて再度正規化する
+    // （先頭の BOM が残ると `(?m)^import` 等が1行目にマッチしない）
+    // 大きなソースでもコピーは正規化・コメント除去が必要な場合の高々 1 回ずつにする
+    let normalized = normalized_source(source);
+
+    // コメント除去: // から行末までを削除（文字列リテラル内の // は残す）
+    let stripped = strip_comments(&normalized, false);
+    let source: &str = &stripped;
+    // エラー位置をコメント除去前の位置に戻すため、除去した区間を覚えておく
+    let comments = if matches!(stripped, Cow::Owned(_)) { comment_spans(&normalized) } else { Vec::new() };
 
     // import 定義: import "path" as alias; または import "path";
-    let import_re = Regex::new(r#"(?m)^import\s+"([^"]+)"(?:\s+as\s+(\w+))?\s*;"#).unwrap();
+    let import_re = Regex::new(IMPORT_PATTERN).unwrap();
     // type 定義: i64 | u64 | f64 を許容するように変更
-    let type_re = Regex::new(r"(?m)^type\s+(\w+)\s*=\s*(\w+)\s+where\s+([^;]+);").unwrap();
-    let atom_re = Regex::new(r"atom\s+\w+").unwrap();
+    let type_re = Regex::new(TYPE_PATTERN).unwrap();
     // struct 定義: struct Name { field: Type, ... } または struct Name<T> { field: T, ... }
-    let struct_re = Regex::new(r"(?m)^struct\s+(\w+)\s*(<[^>]*>)?\s*\{([^}]*)\}").unwrap();
+    let struct_re = Regex::new(STRUCT_PATTERN).unwrap();
+    let invariant_re = Regex::new(r"(?:^|,)\s*invariant\s*:").unwrap();
+
+    // 各バイトを高々 1 つの item に割り当てた区間。各パターンのマッチは、
+    // 走査で確定した区間の先頭に一致するものだけを採用する
+    let spans = scan_items(source);
+    let claimed: HashSet<(usize, &str)> = spans.iter().map(|s| (s.start, s.kind.as_str())).collect();
+    let owns = |m: regex::Match, kind: &str| claimed.contains(&(m.start(), kind));
 
     // import 宣言のパース
     for cap in import_re.captures_iter(source) {
+        if !owns(cap.get(0).unwrap(), "import") { continue; }
         let path = cap[1].to_string();
         let alias = cap.get(2).map(|m| m.as_str().to_string());
-        items.push(Item::Import(ImportDecl { path, alias }));
+        let using = cap.get(3).map(|m| {
+            m.as_str().split(',').map(str::trim).filter(|name| !name.is_empty()).map(str::to_string).collect()
+        });
+        items.push(Item::Import(ImportDecl { path, alias, using }));
     }
 
     for cap in type_re.captures_iter(source) {
+        if !owns(cap.get(0).unwrap(), "type") { continue; }
         let full_predicate = cap[3].trim().to_string();
         let tokens = tokenize(&full_predicate);
-        let operand = tokens.first().cloned().unwrap_or_else(|| "v".to_string());
+        // Str の述語は `len(v) > 0` のように長さから始まるので、len の引数を述語変数とする
+        let operand = match tokens.as_slice() {
+            ["len", "(", name, ..] => name.to_string(),
+            _ => tokens.first().map_or_else(|| "v".to_string(), |t| t.to_string()),
+        };
         items.push(Item::TypeDef(RefinedType {
             name: cap[1].to_string(),
             _base_type: cap[2].to_string(),
@@ -517,6 +1129,7 @@ pub fn parse_module(source: &str) -> Vec<Item> {
     }
 
     for cap in struct_re.captures_iter(source) {
+        if !owns(cap.get(0).unwrap(), "struct") { continue; }
         let name = cap[1].to_string();
         // Generics: 型パラメータ <T, U> のパース
         let type_params = cap.get(2)
@@ -525,7 +1138,11 @@ pub fn parse_module(source: &str) -> Vec<Item> {
                 params
             })
             .unwrap_or_default();
-        let fields_raw = &cap[3];
+        // `invariant: <式>` は最後の項目として書く（式中のカンマも含めて `}` まで）
+        let (fields_raw, invariant) = match invariant_re.find(&cap[3]) {
+            Some(m) => (&cap[3][..m.start()], Some(normalize_contract(cap[3][m.end()..].trim().trim_end_matches(',')))),
+            None => (&cap[3], None),
+        };
         let fields: Vec<StructField> = fields_raw
             .split(',')
             .map(|s| s.trim())
@@ -548,13 +1165,14 @@ pub fn parse_module(source: &str) -> Vec<Item> {
                 }
             })
             .collect();
-        items.push(Item::StructDef(StructDef { name, type_params, fields, method_names: vec![] }));
+        items.push(Item::StructDef(StructDef { name, type_params, fields, method_names: vec![], invariant }));
     }
 
     // enum 定義: enum Name { ... } または enum Name<T> { ... }
     // 再帰的 ADT: フィールド型に "Self" または Enum 自身の名前を記述可能
-    let enum_re = Regex::new(r"(?m)^enum\s+(\w+)\s*(<[^>]*>)?\s*\{([^}]*)\}").unwrap();
+    let enum_re = Regex::new(ENUM_PATTERN).unwrap();
     for cap in enum_re.captures_iter(source) {
+        if !owns(cap.get(0).unwrap(), "enum") { continue; }
         let name = cap[1].to_string();
         // Generics: 型パラメータ <T, U> のパース
         let type_params = cap.get(2)
@@ -565,19 +1183,17 @@ pub fn parse_module(source: &str) -> Vec<Item> {
             .unwrap_or_default();
         let variants_raw = &cap[3];
         let mut any_recursive = false;
-        let variants: Vec<EnumVariant> = variants_raw
-            .split(',')
-            .map(|s| s.trim())
-            .filter(|s| !s.is_empty())
+        // `Cons(i64, Self)` の括弧内のカンマで variant を分割しないよう、ネストを考慮して分割する
+        let variants: Vec<EnumVariant> = split_type_args(variants_raw)
+            .iter()
             .map(|s| {
                 // "Circle(f64)" or "None" or "Cons(i64, Self)" or "Cons(i64, List)"
                 if let Some(paren_start) = s.find('(') {
                     let variant_name = s[..paren_start].trim().to_string();
                     let fields_str = &s[paren_start + 1..s.rfind(')').unwrap_or(s.len())];
-                    let fields: Vec<String> = fields_str
-                        .split(',')
+                    let fields: Vec<String> = split_type_args(fields_str)
+                        .into_iter()
                         .map(|f| {
-                            let f = f.trim().to_string();
                             // "Self" を Enum 自身の名前に展開
                             if f == "Self" { name.clone() } else { f }
                         })
@@ -600,10 +1216,13 @@ pub fn parse_module(source: &str) -> Vec<Item> {
     }
 
     // trait 定義: trait Name { fn method(a: Type) -> Type; law name: expr; }
-    let trait_re = Regex::new(r"(?m)^trait\s+(\w+)\s*\{([^}]*)\}").unwrap();
+    // 継承: trait Name extends Parent [with parent_m = child_m, ...] [+ Parent2 ...] { ... }
+    let trait_re = Regex::new(TRAIT_PATTERN).unwrap();
     for cap in trait_re.captures_iter(source) {
+        if !owns(cap.get(0).unwrap(), "trait") { continue; }
         let name = cap[1].to_string();
-        let body = &cap[2];
+        let extends = cap.get(2).map(|m| parse_trait_extends(m.as_str())).unwrap_or_default();
+        let body = &cap[3];
         let mut methods = Vec::new();
         let mut laws = Vec::new();
 
@@ -653,13 +1272,14 @@ pub fn parse_module(source: &str) -> Vec<Item> {
                 }
             }
         }
-        items.push(Item::TraitDef(TraitDef { name, methods, laws }));
+        items.push(Item::TraitDef(TraitDef { name, methods, laws, extends }));
     }
 
     // impl 定義: impl TraitName for TypeName { fn method(params) -> Type { body } }
     // ネストした {} を正しく処理するためにカスタムパーサーを使用
-    let impl_header_re = Regex::new(r"(?m)^impl\s+(\w+)\s+for\s+(\w+)\s*\{").unwrap();
+    let impl_header_re = Regex::new(IMPL_HEADER_PATTERN).unwrap();
     for cap in impl_header_re.captures_iter(source) {
+        if !owns(cap.get(0).unwrap(), "impl") { continue; }
         let trait_name = cap[1].to_string();
         let target_type = cap[2].to_string();
         // impl ブロックの開始位置から、ネストした {} を考慮して終了位置を探す
@@ -709,8 +1329,9 @@ pub fn parse_module(source: &str) -> Vec<Item> {
     }
 
     // resource 定義: resource name priority:<N> mode:exclusive|shared;
-    let resource_re = Regex::new(r"(?m)^resource\s+(\w+)\s+priority:\s*(-?\d+)\s+mode:\s*(exclusive|shared)\s*;").unwrap();
+    let resource_re = Regex::new(RESOURCE_PATTERN).unwrap();
     for cap in resource_re.captures_iter(source) {
+        if !owns(cap.get(0).unwrap(), "resource") { continue; }
         let name = cap[1].to_string();
         let priority = cap[2].parse::<i64>().unwrap_or(0);
         let mode = match &cap[3] {
@@ -720,72 +1341,67 @@ pub fn parse_module(source: &str) -> Vec<Item> {
         items.push(Item::ResourceDef(ResourceDef { name, priority, mode }));
     }
 
-    // 修飾子付き atom のパース: "async atom", "trusted atom", "unverified atom",
-    // "async trusted atom" 等の組み合わせを先に検出
-    let modified_atom_re = Regex::new(r"(?:(?:async|trusted|unverified)\s+)+atom\s+\w+").unwrap();
-    let modified_atom_indices: Vec<_> = modified_atom_re.find_iter(source).collect();
-    let mut modified_atom_starts: std::collections::HashSet<usize> = std::collections::HashSet::new();
-    for mat in &modified_atom_indices {
-        let start = mat.start();
-        modified_atom_starts.insert(start);
-        let atom_source = &source[start..];
-        // 修飾子を解析
+    // export 宣言のパース: export { push, pop, Stack };
+    for cap in Regex::new(EXPORT_PATTERN).unwrap().captures_iter(source) {
+        if !owns(cap.get(0).unwrap(), "export") { continue; }
+        let names = cap[1].split(',').map(|n| n.trim().to_string()).filter(|n| !n.is_empty()).collect();
+        items.push(Item::Export(ExportDecl { names }));
+    }
+
+    // test ブロック: 本体を文に分け、`assert <式>` の式を取り出す
+    for cap in Regex::new(TEST_HEADER_PATTERN).unwrap().captures_iter(source) {
+        let m = cap.get(0).unwrap();
+        if !owns(m, "test") { continue; }
+        let close = matching_brace_end(source, m.end() - 1);
+        let body_end = if source[..close].ends_with('}') { close - 1 } else { close };
+        let mut assertions = Vec::new();
+        let mut malformed = Vec::new();
+        for statement in split_statements(&source[m.end()..body_end]) {
+            match statement.strip_prefix("assert").filter(|rest| rest.starts_with(char::is_whitespace) || rest.starts_with('(')) {
+                Some(expr) => assertions.push(expr.trim().to_string()),
+                None => malformed.push(statement),
+            }
+        }
+        items.push(Item::TestDef(TestDef { name: cap[1].to_string(), assertions, malformed }));
+    }
+
+    // atom のパース: 修飾子（async / trusted / unverified / total）とターゲット注釈は走査で atom の区間に含めてある
+    let target_annotation_re = Regex::new(TARGET_ANNOTATION_PATTERN).unwrap();
+    for span in &spans {
+        let keyword = match span.keyword {
+            Some(keyword) => keyword,
+            None => continue,
+        };
         let mut is_async = false;
+        let mut is_total = false;
         let mut trust_level = TrustLevel::Verified;
-        let mut remaining = atom_source;
-        loop {
-            remaining = remaining.trim_start();
-            if remaining.starts_with("async") && remaining[5..].starts_with(|c: char| c.is_whitespace()) {
-                is_async = true;
-                remaining = &remaining[5..];
-            } else if remaining.starts_with("trusted") && remaining[7..].starts_with(|c: char| c.is_whitespace()) {
-                trust_level = TrustLevel::Trusted;
-                remaining = &remaining[7..];
-            } else if remaining.starts_with("unverified") && remaining[10..].starts_with(|c: char| c.is_whitespace()) {
-                trust_level = TrustLevel::Unverified;
-                remaining = &remaining[10..];
-            } else {
-                break;
+        let prefix = &source[span.start..keyword];
+        for modifier in target_annotation_re.replace_all(prefix, "").split_whitespace() {
+            match modifier {
+                "async" => is_async = true,
+                "total" => is_total = true,
+                "trusted" => trust_level = TrustLevel::Trusted,
+                "unverified" => trust_level = TrustLevel::Unverified,
+                _ => {}
             }
         }
-        // "atom" から始まる部分を切り出して parse_atom に渡す
-        let atom_start_in_remaining = remaining.find("atom").unwrap_or(0);
-        let atom_text = &remaining[atom_start_in_remaining..];
-        // 次の atom の開始位置を探す
-        let next_atom_pos = atom_re.find(atom_text.get(5..).unwrap_or(""))
-            .map(|m| m.start() + 5)
-            .unwrap_or(atom_text.len());
-        let atom_slice = &atom_text[..next_atom_pos];
-        let mut atom = parse_atom(atom_slice);
+        let mut atom = parse_atom(&source[keyword..span.end]).map_err(|e| {
+            let offset = unstripped_offset(&comments, keyword + e.offset);
+            let mut error = ParseError { offset, ..e };
+            error.locate(&normalized);
+            error
+        })?;
         atom.is_async = is_async;
+        atom.is_total = is_total;
         atom.trust_level = trust_level;
+        atom.targets = parse_target_annotations(prefix);
         items.push(Item::Atom(atom));
     }
 
-    let atom_indices: Vec<_> = atom_re.find_iter(source).map(|m| m.start()).collect();
-    for i in 0..atom_indices.len() {
-        let start = atom_indices[i];
-        // 修飾子付き atom の一部として既にパース済みならスキップ
-        let skip = modified_atom_starts.iter().any(|&ms| {
-            start > ms && start < ms + 30 // 修飾子 + "atom" の最大長以内
-        });
-        if skip {
-            continue;
-        }
-        // 直前に修飾子キーワードがある場合もスキップ
-        let prefix = &source[start.saturating_sub(12)..start];
-        if prefix.contains("async") || prefix.contains("trusted") || prefix.contains("unverified") {
-            continue;
-        }
-        let end = if i + 1 < atom_indices.len() { atom_indices[i+1] } else { source.len() };
-        let atom_source = &source[start..end];
-        items.push(Item::Atom(parse_atom(atom_source)));
-    }
-
-    items
+    Ok(items)
 }
 
-pub fn parse_atom(source: &str) -> Atom {
+pub fn parse_atom(source: &str) -> Result<Atom, ParseError> {
     // Generics 対応: atom name<T, U>(params) の形式もパース
     let name_re = Regex::new(r"atom\s+(\w+)\s*(<[^>]*>)?\s*\(([^)]*)\)").unwrap();
     let req_re = Regex::new(r"requires:\s*([^;]+);").unwrap();
@@ -794,7 +1410,21 @@ pub fn parse_atom(source: &str) -> Atom {
     let forall_re = Regex::new(r"forall\(\s*(\w+)\s*,\s*([^,]+)\s*,\s*([^,]+)\s*,\s*([^)]+)\)").unwrap();
     let exists_re = Regex::new(r"exists\(\s*(\w+)\s*,\s*([^,]+)\s*,\s*([^,]+)\s*,\s*([^)]+)\)").unwrap();
 
-    let name_caps = name_re.captures(source).expect("Failed to parse atom name");
+    // body: のない atom は走査がヘッダーまでで区切るので、ヘッダーの形より先に確かめる
+    let body_marker = "body:";
+    if !source.contains(body_marker) {
+        let (start, len, name) = Regex::new(r"atom\s+(\w+)").unwrap().captures(source)
+            .and_then(|c| c.get(1))
+            .map_or((0, 0, ""), |m| (m.start(), m.len(), m.as_str()));
+        return Err(ParseError::new(source, start, len, format!("atom '{}' has no `body:`", name)));
+    }
+    let name_caps = match name_re.captures(source) {
+        Some(caps) => caps,
+        None => {
+            let header_len = source.find(|c: char| c == ';' || c == '\n').unwrap_or(source.len());
+            return Err(ParseError::new(source, 0, header_len, "expected `atom <name>(<params>)`"));
+        }
+    };
     let name = name_caps[1].to_string();
     // Generics: 型パラメータ <T: Trait, U> のパース（トレイト境界対応）
     let (type_params, where_bounds) = name_caps.get(2)
@@ -816,8 +1446,13 @@ pub fn parse_atom(source: &str) -> Atom {
                 (false, false, s)
             };
             if let Some((param_name, type_name)) = s_stripped.split_once(':') {
-                let type_name_str = type_name.trim().to_string();
-                let type_ref = parse_type_ref(&type_name_str);
+                let type_ref = parse_type_ref(type_name);
+                // `x: Nat?` は Option<Nat> と同一視する（type_name も脱糖後の名前で保持）
+                let type_name_str = if type_name.trim().ends_with('?') {
+                    type_ref.display_name()
+                } else {
+                    type_name.trim().to_string()
+                };
                 Param {
                     name: param_name.trim().to_string(),
                     type_name: Some(type_name_str),
@@ -831,11 +1466,36 @@ pub fn parse_atom(source: &str) -> Atom {
         })
         .collect();
 
-    let requires_raw = req_re.captures(source).map_or("true".to_string(), |c| c[1].trim().to_string());
-    let ensures = ens_re.captures(source).map_or("true".to_string(), |c| c[1].trim().to_string());
+    // where: 節はパラメータリストの直後（requires / ensures / body より前）にだけ書ける。
+    // 型パラメータの境界は `<T: Trait>` で書くため、ここでの `where:` は常に値レベルの節になる
+    let header_start = name_caps.get(0).unwrap().end();
+    let header_end = ["requires:", "ensures:", "body:"].iter()
+        .filter_map(|marker| source[header_start..].find(marker))
+        .min()
+        .map_or(source.len(), |offset| header_start + offset);
+    let where_clause = Regex::new(r"(?:^|\s)where:\s*([^;]+);").unwrap()
+        .captures(&source[header_start..header_end])
+        .map(|c| dedent_contract(&c[1]));
+    // 戻り値型の注釈 `-> T` はパラメータリストの直後の同じ行に書く（`where:` 節が続いてもよい）
+    let return_type = source[header_start..header_end].trim_start().strip_prefix("->")
+        .and_then(|rest| rest.lines().next())
+        .map(|line| line.split("where:").next().unwrap_or(line).trim().to_string())
+        .filter(|t| !t.is_empty());
+
+    let requires_display = req_re.captures(source).map_or("true".to_string(), |c| dedent_contract(&c[1]));
+    let requires_normalized = normalize_contract(&requires_display);
+    // 複数の `ensures:` 節は && で結合して検証し、表示は箇条書きにする
+    let ensures_clauses: Vec<String> = ens_re.captures_iter(source).map(|c| dedent_contract(&c[1])).collect();
+    let (ensures, ensures_display) = match ensures_clauses.len() {
+        0 => ("true".to_string(), "true".to_string()),
+        1 => (normalize_contract(&ensures_clauses[0]), ensures_clauses[0].clone()),
+        _ => (
+            ensures_clauses.iter().map(|c| format!("({})", normalize_contract(c))).collect::<Vec<_>>().join(" && "),
+            ensures_clauses.iter().map(|c| format!("- {}", c.replace('\n', "\n  "))).collect::<Vec<_>>().join("\n"),
+        ),
+    };
 
-    let body_marker = "body:";
-    let body_start_pos = source.find(body_marker).expect("Failed to find body:") + body_marker.len();
+    let body_start_pos = source.find(body_marker).map_or(source.len(), |p| p + body_marker.len());
     let body_snippet = source[body_start_pos..].trim();
 
     let mut body_raw = String::new();
@@ -850,14 +1510,23 @@ pub fn parse_atom(source: &str) -> Atom {
             }
         }
     } else {
-        body_raw = body_snippet.split(';').next().unwrap_or("").to_string();
+        body_raw = body_snippet.split(';').next().unwrap_or("").trim().to_string();
+    }
+
+    // 契約と本体の式をここでパースしておき、構文エラーを atom 内の位置付きで返す
+    // （後段の検証・コード生成は式の文字列を再パースする）
+    let body_offset = source.len() - source[body_start_pos..].trim_start().len();
+    parse_expression(&body_raw).map_err(|e| e.shifted(source, body_offset))?;
+    for cap in req_re.captures_iter(source).chain(ens_re.captures_iter(source)) {
+        let clause = cap.get(1).unwrap();
+        parse_expression(clause.as_str()).map_err(|e| e.shifted(source, clause.start()))?;
     }
 
     let mut forall_constraints = Vec::new();
-    for cap in forall_re.captures_iter(&requires_raw) {
+    for cap in forall_re.captures_iter(&requires_normalized) {
         forall_constraints.push(Quantifier { q_type: QuantifierType::ForAll, var: cap[1].to_string(), start: cap[2].trim().to_string(), end: cap[3].trim().to_string(), condition: cap[4].trim().to_string() });
     }
-    for cap in exists_re.captures_iter(&requires_raw) {
+    for cap in exists_re.captures_iter(&requires_normalized) {
         forall_constraints.push(Quantifier { q_type: QuantifierType::Exists, var: cap[1].to_string(), start: cap[2].trim().to_string(), end: cap[3].trim().to_string(), condition: cap[4].trim().to_string() });
     }
 
@@ -895,246 +1564,884 @@ pub fn parse_atom(source: &str) -> Atom {
     let invariant = invariant_re.captures(source)
         .map(|cap| cap[1].trim().to_string());
 
-    Atom {
+    // decreases 句のパース: "decreases: <expr>;"
+    // body 内のループの `decreases:` と区別するため、body: より前のヘッダだけを見る
+    let decreases = Regex::new(r"(?:^|\s)decreases:\s*([^;]+);").unwrap()
+        .captures(&source[..body_start_pos - body_marker.len()])
+        .map(|cap| normalize_contract(&cap[1]));
+
+    // where: 節は requires の連言として検証・呼び出し側チェックに使う
+    let requires = forall_re.replace_all(&exists_re.replace_all(&requires_normalized, "true"), "true").to_string();
+    let requires = match &where_clause {
+        Some(clause) if requires.trim() == "true" => normalize_contract(clause),
+        Some(clause) => format!("({}) && ({})", normalize_contract(clause), requires),
+        None => requires,
+    };
+
+    Ok(Atom {
         name,
         type_params,
         where_bounds,
         params,
-        requires: forall_re.replace_all(&exists_re.replace_all(&requires_raw, "true"), "true").to_string(),
+        return_type,
+        requires,
+        requires_raw: requires_display,
+        where_clause,
         forall_constraints,
         ensures,
+        ensures_raw: ensures_display,
         body_expr: body_raw,
         consumed_params,
         resources,
         is_async: false,
+        is_total: false,
         trust_level: TrustLevel::Verified,
         max_unroll,
         invariant,
+        decreases,
+        targets: AtomTargets::default(),
+    })
+}
+
+/// atom の前に置かれたターゲット注釈（`@only(rust, go)` / `@exclude(typescript)`）をパースする。
+/// 同じ種類の注釈が複数あれば結合する。名前の検証は check_atom_targets で行う
+fn parse_target_annotations(prefix: &str) -> AtomTargets {
+    let mut targets = AtomTargets::default();
+    for cap in Regex::new(TARGET_ANNOTATION_PATTERN).unwrap().captures_iter(prefix) {
+        let names = cap[2].split(',')
+            .map(|t| canonical_target(t.trim()))
+            .filter(|t| !t.is_empty());
+        match &cap[1] {
+            "only" => targets.only.get_or_insert_with(Vec::new).extend(names),
+            _ => targets.exclude.extend(names),
+        }
     }
+    targets
 }
 
-pub fn tokenize(input: &str) -> Vec<String> {
-    // 小数点(.)を含む数値リテラルを先にマッチし、残りの `.` はフィールドアクセス演算子として扱う
-    let re = Regex::new(r"(\d+\.\d+|\d+|[a-zA-Z_]\w*|==|!=|>=|<=|=>|&&|\|\||[+\-*/><()\[\]{};=,:.])").unwrap();
-    re.find_iter(input).map(|m| m.as_str().to_string()).collect()
+/// 契約式を1行に正規化する（連続する空白・改行を1スペースに畳む）
+pub fn normalize_contract(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
 }
 
-pub fn parse_expression(input: &str) -> Expr {
-    let tokens = tokenize(input);
-    let mut pos = 0;
-    parse_block_or_expr(&tokens, &mut pos)
+/// 契約式の表示用テキストを作る: 前後の空白を除き、2行目以降の共通インデントを取り除く。
+/// 1行目は `requires:` の直後から始まるためインデントを持たない。
+fn dedent_contract(text: &str) -> String {
+    let trimmed = text.trim();
+    let mut lines = trimmed.lines();
+    let first = lines.next().unwrap_or("").trim_end().to_string();
+    let rest: Vec<&str> = lines.map(|l| l.trim_end()).collect();
+    let indent = rest.iter()
+        .filter(|l| !l.trim().is_empty())
+        .map(|l| l.len() - l.trim_start().len())
+        .min()
+        .unwrap_or(0);
+    std::iter::once(first)
+        .chain(rest.iter().map(|l| l.get(indent..).unwrap_or("").to_string()))
+        .collect::<Vec<_>>()
+        .join("\n")
 }
 
-fn parse_block_or_expr(tokens: &[String], pos: &mut usize) -> Expr {
-    if *pos < tokens.len() && tokens[*pos] == "{" {
-        *pos += 1;
-        let mut stmts = Vec::new();
-        while *pos < tokens.len() && tokens[*pos] != "}" {
-            stmts.push(parse_statement(tokens, pos));
-            if *pos < tokens.len() && tokens[*pos] == ";" { *pos += 1; }
-        }
-        if *pos < tokens.len() && tokens[*pos] == "}" { *pos += 1; }
-        Expr::Block(stmts)
-    } else {
-        parse_implies(tokens, pos)
-    }
+/// 複数行の契約をメッセージ中に引用する際、2行目以降を indent で字下げする
+pub fn indent_contract(raw: &str, indent: &str) -> String {
+    raw.lines().collect::<Vec<_>>().join(&format!("\n{}", indent))
 }
 
-/// match アームの body 専用パーサー。
-/// `{...}` ブロックの場合は通常通りパース。
-/// それ以外の場合は `parse_logical_or` を使い、`=>` を含意演算子として消費しない。
-/// これにより `0 => match x { 0 => 1, _ => 2 }, 1 => ...` のネストが正しく動作する。
-fn parse_match_arm_body(tokens: &[String], pos: &mut usize) -> Expr {
-    if *pos < tokens.len() && tokens[*pos] == "{" {
-        // ブロック式: 通常通りパース（内部の `=>` は match パーサーが処理する）
-        parse_block_or_expr(tokens, pos)
-    } else if *pos < tokens.len() && tokens[*pos] == "match" {
-        // ネストした match 式: match パーサーに委譲（parse_primary 経由）
-        parse_implies(tokens, pos)
-    } else if *pos < tokens.len() && tokens[*pos] == "if" {
-        // if-then-else 式
-        parse_implies(tokens, pos)
-    } else {
-        // それ以外: `=>` を消費しないレベルでパース
-        parse_logical_or(tokens, pos)
+/// 式をトークンに分割する。トークンは入力を指すスライスで、トークンごとの String は確保しない
+pub fn tokenize(input: &str) -> Vec<&str> {
+    // 16進 (0x..) / 2進 (0b..) リテラルを先にマッチし、次に小数点(.)を含む数値リテラル、
+    // 最後に 10 進整数をマッチする。いずれも `_` による桁区切りを許容する（例: 1_000_000）。
+    // 残りの `.` はフィールドアクセス演算子として扱う
+    // 文字列リテラル（assume の正当化文）は 1 トークンとして扱う
+    let re = Regex::new(r#"("(?:[^"\\]|\\.)*"|0[xX][0-9a-fA-F_]+|0[bB][01_]+|\d[\d_]*\.\d[\d_]*|\d[\d_]*|[a-zA-Z_]\w*|==|!=|>=|<=|=>|~=|&&|\|\||[+\-*/><()\[\]{};=,:.!?])"#).unwrap();
+    let mut tokens: Vec<&str> = Vec::new();
+    let mut prev_end = usize::MAX;
+    for m in re.find_iter(input) {
+        // 単独の `!` は識別子に空白なしで続くとき（nullable アクセス `x!`）だけ残し、
+        // それ以外（`if !flag` など）は従来どおり読み飛ばす
+        if m.as_str() == "!" {
+            let after_ident = m.start() == prev_end && tokens.last()
+                .map_or(false, |t| t.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_'));
+            if !after_ident {
+                continue;
+            }
+        }
+        prev_end = m.end();
+        tokens.push(m.as_str());
     }
+    tokens
 }
 
-fn parse_statement(tokens: &[String], pos: &mut usize) -> Expr {
-    if *pos < tokens.len() && tokens[*pos] == "let" {
-        *pos += 1;
-        let var = tokens[*pos].clone();
-        *pos += 1;
-        if *pos < tokens.len() && tokens[*pos] == "=" { *pos += 1; }
-        let value = parse_implies(tokens, pos);
-        Expr::Let { var, value: Box::new(value) }
-    } else if *pos + 1 < tokens.len()
-        && tokens[*pos].chars().next().map_or(false, |c| c.is_alphabetic() || c == '_')
-        && tokens[*pos + 1] == "="
-    {
-        let var = tokens[*pos].clone();
-        *pos += 1;
-        *pos += 1;
-        let value = parse_implies(tokens, pos);
-        Expr::Assign { var, value: Box::new(value) }
-    } else {
-        parse_implies(tokens, pos)
-    }
+// =============================================================================
+// パース結果の説明（mumei check --explain-parse）
+// =============================================================================
+//
+// item の抽出は正規表現ベースのため、わずかな構文の揺れ（resource の末尾 `;` 抜け、
+// `{` のない struct、ジェネリクス付き impl など）があると item が黙って無視され、
+// 後段で「未知の名前」のエラーになって初めて気付く。
+// explain_parse は parse_module と同じ走査で各 item の区間を記録し、
+// どの item にも消費されなかった非空白テキスト（unclaimed）を行番号付きで返す。
+
+/// ソース上の 1 区間（行番号は 1 始まり）
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceRegion {
+    /// item の種類（"atom", "trusted atom", "struct" ...）。unclaimed では "unclaimed"
+    pub kind: String,
+    /// item 名（unclaimed では空）
+    pub name: String,
+    pub start_line: usize,
+    pub end_line: usize,
+    /// 先頭行の抜粋（unclaimed では消費されなかった部分）
+    pub snippet: String,
 }
 
-fn parse_implies(tokens: &[String], pos: &mut usize) -> Expr {
-    let mut node = parse_logical_or(tokens, pos);
-    while *pos < tokens.len() && tokens[*pos] == "=>" {
-        *pos += 1;
-        let right = parse_logical_or(tokens, pos);
-        node = Expr::BinaryOp(Box::new(node), Op::Implies, Box::new(right));
-    }
-    node
+#[derive(Debug, Clone, Default)]
+pub struct ParseExplanation {
+    /// item として抽出された区間（ソース順）
+    pub claimed: Vec<SourceRegion>,
+    /// どの item にも消費されなかった非空白テキストを含む行の範囲
+    pub unclaimed: Vec<SourceRegion>,
 }
 
-fn parse_logical_or(tokens: &[String], pos: &mut usize) -> Expr {
-    let mut node = parse_logical_and(tokens, pos);
-    while *pos < tokens.len() && tokens[*pos] == "||" {
-        *pos += 1;
-        let right = parse_logical_and(tokens, pos);
-        node = Expr::BinaryOp(Box::new(node), Op::Or, Box::new(right));
+/// 抜粋の最大文字数
+const SNIPPET_CHARS: usize = 60;
+
+fn snippet_of(text: &str) -> String {
+    let text = text.trim();
+    if text.chars().count() <= SNIPPET_CHARS {
+        return text.to_string();
     }
-    node
+    format!("{}…", text.chars().take(SNIPPET_CHARS).collect::<String>())
 }
 
-fn parse_logical_and(tokens: &[String], pos: &mut usize) -> Expr {
-    let mut node = parse_comparison(tokens, pos);
-    while *pos < tokens.len() && tokens[*pos] == "&&" {
-        *pos += 1;
-        let right = parse_comparison(tokens, pos);
-        node = Expr::BinaryOp(Box::new(node), Op::And, Box::new(right));
+/// open の位置にある '{' に対応する '}' の直後のバイト位置（閉じていなければ source 末尾）
+/// ブロック本体をトップレベルの `;` で文に分ける（括弧・波括弧・文字列リテラルの中の `;` では分けない）
+fn split_statements(body: &str) -> Vec<String> {
+    let mut statements = Vec::new();
+    let mut depth = 0i32;
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut start = 0;
+    for (i, c) in body.char_indices() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' => in_string = true,
+            '(' | '{' | '[' => depth += 1,
+            ')' | '}' | ']' => depth -= 1,
+            ';' if depth == 0 => {
+                statements.push(body[start..i].trim().to_string());
+                start = i + 1;
+            }
+            _ => {}
+        }
     }
-    node
+    statements.push(body[start..].trim().to_string());
+    statements.retain(|s| !s.is_empty());
+    statements
 }
 
-fn parse_comparison(tokens: &[String], pos: &mut usize) -> Expr {
-    let mut node = parse_add_sub(tokens, pos);
-    if *pos < tokens.len() {
-        let op = match tokens[*pos].as_str() {
-            ">" => Some(Op::Gt), "<" => Some(Op::Lt), "==" => Some(Op::Eq),
+fn matching_brace_end(source: &str, open: usize) -> usize {
+    let mut depth = 0;
+    for (i, c) in source[open..].char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return open + i + 1;
+                }
+            }
+            _ => {}
+        }
+    }
+    source.len()
+}
+
+/// atom が実際に消費する範囲の終端（body の終わり: `;` または対応する `}` とそれに続く `;`）。
+/// body: は次の atom 候補（next_atom）より前で探し、見つからなければヘッダー（header_end）までとする
+/// （parse_atom 自体が失敗する）。`{}` 本体は中に `atom` という語を含みうるため、
+/// 対応する `}` を次のブロック item（next_block）の手前まで探す。
+fn atom_span_end(source: &str, start: usize, header_end: usize, next_atom: usize, next_block: usize) -> usize {
+    let body_pos = match source[start..next_atom].find("body:") {
+        Some(p) => start + p + "body:".len(),
+        None => return header_end,
+    };
+    let rest = &source[body_pos..next_block];
+    let body_start = body_pos + (rest.len() - rest.trim_start().len());
+    if source[body_start..].starts_with('{') {
+        let source = &source[..next_block];
+        let end = matching_brace_end(source, body_start);
+        let after = &source[end..];
+        let trimmed = after.trim_start();
+        if trimmed.starts_with(';') {
+            end + (after.len() - trimmed.len()) + 1
+        } else {
+            end
+        }
+    } else {
+        source[body_start..next_atom].find(';').map_or(next_atom, |p| body_start + p + 1)
+    }
+}
+
+/// モジュールの公開 item 名。export 宣言がなければ None（全 item を公開）
+pub fn module_exports(items: &[Item]) -> Option<HashSet<String>> {
+    let mut exports: Option<HashSet<String>> = None;
+    for item in items {
+        if let Item::Export(decl) = item {
+            exports.get_or_insert_with(HashSet::new).extend(decl.names.iter().cloned());
+        }
+    }
+    exports
+}
+
+/// parse_module と同じ走査（scan_items）で各 item の区間を求め、消費されなかった領域を列挙する
+pub fn explain_parse(source: &str) -> ParseExplanation {
+    let normalized = normalized_source(source);
+    // コメントは parse_module では削除されるが、ここではバイト位置を保つため空白で置き換える
+    let stripped = strip_comments(&normalized, true);
+    let source: &str = &stripped;
+
+    let spans = scan_items(source);
+
+    let line_starts: Vec<usize> = std::iter::once(0)
+        .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+        .collect();
+    let line_of = |offset: usize| line_starts.partition_point(|&s| s <= offset);
+
+    let mut claimed_mask = vec![false; source.len()];
+    let mut explanation = ParseExplanation::default();
+    for span in &spans {
+        for b in &mut claimed_mask[span.start..span.end] {
+            *b = true;
+        }
+        let first_line = source[span.start..span.end].lines().next().unwrap_or("");
+        explanation.claimed.push(SourceRegion {
+            kind: span.kind.clone(),
+            name: span.name.clone(),
+            start_line: line_of(span.start),
+            end_line: line_of(span.end.saturating_sub(1).max(span.start)),
+            snippet: snippet_of(first_line),
+        });
+    }
+
+    // 行ごとに未消費の非空白文字があるかを調べ、連続する行をひとまとめにする
+    let mut current: Option<SourceRegion> = None;
+    for (idx, &line_start) in line_starts.iter().enumerate() {
+        let line_end = line_starts.get(idx + 1).map_or(source.len(), |next| next - 1);
+        let unclaimed: String = source[line_start..line_end]
+            .char_indices()
+            .map(|(i, c)| if claimed_mask[line_start + i] { ' ' } else { c })
+            .collect();
+        let line_no = idx + 1;
+        if unclaimed.trim().is_empty() {
+            explanation.unclaimed.extend(current.take());
+            continue;
+        }
+        match current.as_mut() {
+            Some(region) => region.end_line = line_no,
+            None => {
+                current = Some(SourceRegion {
+                    kind: "unclaimed".to_string(),
+                    name: String::new(),
+                    start_line: line_no,
+                    end_line: line_no,
+                    snippet: snippet_of(&unclaimed),
+                });
+            }
+        }
+    }
+    explanation.unclaimed.extend(current);
+    explanation
+}
+
+// =============================================================================
+// 数値リテラル
+// =============================================================================
+
+/// i64 の範囲を表すエラーメッセージ用の文字列
+const I64_RANGE: &str = "-9223372036854775808..=9223372036854775807";
+
+/// 整数リテラルトークン（10進 / 0x / 0b、`_` 区切り可）の絶対値を返す。
+/// 整数リテラルでなければ None。桁数が u128 にも収まらない場合は u128::MAX に飽和させる
+/// （いずれにせよ i64 の範囲外として報告される）。
+pub fn parse_int_literal(token: &str) -> Option<u128> {
+    let (digits, radix) = if let Some(rest) = token.strip_prefix("0x").or_else(|| token.strip_prefix("0X")) {
+        (rest, 16)
+    } else if let Some(rest) = token.strip_prefix("0b").or_else(|| token.strip_prefix("0B")) {
+        (rest, 2)
+    } else {
+        (token, 10)
+    };
+    if !token.starts_with(|c: char| c.is_ascii_digit()) {
+        return None;
+    }
+    let cleaned: String = digits.chars().filter(|c| *c != '_').collect();
+    if cleaned.is_empty() || !cleaned.chars().all(|c| c.is_digit(radix)) {
+        return None;
+    }
+    Some(u128::from_str_radix(&cleaned, radix).unwrap_or(u128::MAX))
+}
+
+/// 浮動小数点リテラルトークン（`_` 区切り可）を f64 に変換する
+fn parse_float_literal(token: &str) -> Option<f64> {
+    if !token.contains('.') || !token.starts_with(|c: char| c.is_ascii_digit()) {
+        return None;
+    }
+    token.replace('_', "").parse::<f64>().ok()
+}
+
+/// 整数リテラルの絶対値を符号付きで i64 に収める。範囲外ならエラーメッセージを返す。
+/// `-9223372036854775808` (i64::MIN) は単項マイナスと絶対値 2^63 の組としてのみ表現できる。
+fn int_literal_to_i64(token: &str, magnitude: u128, negative: bool) -> Result<i64, String> {
+    let signed = if negative { -(magnitude.min(i128::MAX as u128) as i128) } else { magnitude.min(i128::MAX as u128) as i128 };
+    i64::try_from(signed).map_err(|_| {
+        let shown = if negative { format!("-{}", token) } else { token.to_string() };
+        format!("Integer literal '{}' is out of range for i64 ({})", shown, I64_RANGE)
+    })
+}
+
+/// 単項マイナスとして解釈される位置か（直前が演算子・区切り・式の先頭）
+pub(crate) fn is_unary_position(prev: Option<&&str>) -> bool {
+    match prev {
+        None => true,
+        Some(p) => matches!(*p,
+            "(" | "[" | "{" | "," | ";" | ":" | "?" | "=" | "==" | "!=" | ">=" | "<=" | "=>" | "~=" | "&&" | "||"
+            | "+" | "-" | "*" | "/" | ">" | "<"
+            | "return" | "if" | "else" | "match" | "while" | "await" | "invariant" | "decreases"),
+    }
+}
+
+/// 式中の整数リテラルがすべて i64 に収まることを検査する。
+/// パーサ本体は範囲外リテラルで panic しない代わりに値を飽和させるため、
+/// 検証・チェックの入口でこの関数を呼び、リテラルと i64 の範囲を含むエラーを報告する。
+pub fn validate_literals(input: &str) -> Result<(), String> {
+    let tokens = tokenize(input);
+    for (i, token) in tokens.iter().enumerate() {
+        if let Some(magnitude) = parse_int_literal(token) {
+            let negative = i > 0 && tokens[i - 1] == "-" && is_unary_position(if i >= 2 { tokens.get(i - 2) } else { None });
+            int_literal_to_i64(token, magnitude, negative)?;
+        }
+    }
+    Ok(())
+}
+
+/// 検証器が env に置く内部シンボルの接頭辞（配列長 / 生存・借用フラグ / enum 射影 / 構造体フィールド / 呼び出し結果）。
+/// env は名前 → Z3 値のフラットなマップなので、利用者がこれらの名前に代入すると内部状態を上書きできてしまう。
+pub const RESERVED_PREFIXES: &[&str] = &["len_", "__alive_", "__borrowed_", "__proj_", "__struct_", "__q_", "call_"];
+
+/// 利用者が束縛・代入・宣言できない名前なら、その理由を返す
+pub fn reserved_name(name: &str) -> Option<String> {
+    if name == "result" {
+        return Some("'result' is reserved for the return value checked by ensures".to_string());
+    }
+    RESERVED_PREFIXES.iter().find(|prefix| name.starts_with(**prefix)).map(|prefix| {
+        format!("names starting with '{}' are reserved for internal verifier symbols", prefix)
+    })
+}
+
+/// atom 名とパラメータ名が予約名でないことを検査する（validate_literals と同じく検証・チェックの入口で呼ぶ）
+pub fn validate_names(atom: &Atom) -> Result<(), String> {
+    if let Some(reason) = reserved_name(&atom.name) {
+        return Err(format!("Reserved name: atom '{}' cannot be declared ({})", atom.name, reason));
+    }
+    for param in &atom.params {
+        if let Some(reason) = reserved_name(&param.name) {
+            return Err(format!("Reserved name: parameter '{}' of atom '{}' cannot be declared ({})", param.name, atom.name, reason));
+        }
+    }
+    Ok(())
+}
+
+/// 量化子 forall / exists が書ける位置に書かれているかを検査する（validate_names と同じく検証・チェックの入口で呼ぶ）。
+/// - requires: && で結ばれた最上位の項だけ（量化子は requires から取り出して別途仮定するため）
+/// - ensures: どこでも（事後状態で Z3 の量化式として検査する）
+/// - body: ループ不変量と assume の条件の中だけ（量化子は実行時に評価できない）
+pub fn validate_quantifiers(atom: &Atom) -> Result<(), String> {
+    if let Ok(requires) = parse_expression(&atom.requires_raw) {
+        if let Some(q) = nested_requires_quantifier(&requires) {
+            return Err(format!(
+                "Unsupported quantifier position: atom '{}' nests {}(...) inside another expression in requires; \
+                 quantifiers in requires must be top-level conjuncts joined with && (e.g. `requires: n >= 0 && forall(i, 0, n, xs[i] >= 0);`)",
+                atom.name, q
+            ));
+        }
+    }
+    if let Ok(body) = parse_expression(&atom.body_expr) {
+        if let Some(q) = find_quantifier(&body, true) {
+            return Err(format!(
+                "Unsupported quantifier position: atom '{}' uses {}(...) in its body; \
+                 quantifiers cannot be executed and are only allowed in requires, ensures, loop invariants and assume conditions",
+                atom.name, q
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// requires の && の連言の項のうち、最上位でない位置にある量化子の名前
+fn nested_requires_quantifier(expr: &Expr) -> Option<&'static str> {
+    match expr {
+        Expr::BinaryOp(l, Op::And, r) => nested_requires_quantifier(l).or_else(|| nested_requires_quantifier(r)),
+        Expr::Call(name, _) if name == "forall" || name == "exists" => None,
+        other => find_quantifier(other, false),
+    }
+}
+
+/// 式に含まれる最初の量化子の名前。skip_ghost なら検証専用の位置（ループ不変量・assume の条件）は見ない
+fn find_quantifier(expr: &Expr, skip_ghost: bool) -> Option<&'static str> {
+    let mut found = None;
+    expr.walk(|e| match e {
+        _ if found.is_some() => false,
+        Expr::Call(name, _) if name == "forall" => {
+            found = Some("forall");
+            false
+        }
+        Expr::Call(name, _) if name == "exists" => {
+            found = Some("exists");
+            false
+        }
+        Expr::Assume { .. } if skip_ghost => false,
+        Expr::While { cond, decreases, body, .. } if skip_ghost => {
+            // ループ不変量だけを飛ばす
+            let find = |e: &Expr| find_quantifier(e, skip_ghost);
+            found = find(cond).or_else(|| decreases.as_deref().and_then(find)).or_else(|| find(body));
+            false
+        }
+        _ => true,
+    });
+    found
+}
+
+/// パラメータと同じ名前を束縛する量化子（`forall(i, ...)` で `i` がパラメータ）ごとに警告文を返す。
+/// 検証では束縛変数を一意な内部シンボルに置き換えるので捕獲は起きないが、
+/// 量化子の内側の `i` がパラメータを指さないことは利用者にとって紛らわしい
+pub fn quantifier_shadowing(atom: &Atom) -> Vec<String> {
+    let quantifier_re = Regex::new(r"\b(forall|exists)\(\s*(\w+)\s*,").unwrap();
+    let mut warnings: Vec<String> = Vec::new();
+    for source in [&atom.requires_raw, &atom.ensures, &atom.body_expr] {
+        for cap in quantifier_re.captures_iter(source) {
+            let var = &cap[2];
+            if atom.params.iter().any(|p| p.name == var) {
+                let warning = format!(
+                    "quantifier variable '{}' in {}({}, ...) shadows parameter '{}'; inside the quantifier '{}' is the bound variable, not the parameter",
+                    var, &cap[1], var, var, var
+                );
+                if !warnings.contains(&warning) {
+                    warnings.push(warning);
+                }
+            }
+        }
+    }
+    warnings
+}
+
+/// 文字列リテラルの中身のエスケープ（`\"` `\\` `\n` `\t`）を解除する。未知のエスケープはそのまま残す
+fn unescape_string(literal: &str) -> String {
+    let mut out = String::with_capacity(literal.len());
+    let mut chars = literal.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some(escaped @ ('"' | '\\')) => out.push(escaped),
+            Some(other) => {
+                out.push('\\');
+                out.push(other);
+            }
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+/// トークン列を表示用の式テキストに戻す（`f ( x )` ではなく `f(x)` のように括弧・区切りの前後の空白を省く）
+fn join_tokens(tokens: &[&str]) -> String {
+    let mut out = String::new();
+    for (i, token) in tokens.iter().enumerate() {
+        let prev = if i == 0 { None } else { Some(tokens[i - 1]) };
+        let tight = match prev {
+            None => true,
+            Some("(") | Some("[") | Some(".") => true,
+            Some(p) => matches!(*token, ")" | "]" | "," | "." | "!")
+                || (matches!(*token, "(" | "[") && p.starts_with(|c: char| c.is_alphanumeric() || c == '_')),
+        };
+        if !tight { out.push(' '); }
+        out.push_str(token);
+    }
+    out
+}
+
+// =============================================================================
+// 仮定 (assume)
+// =============================================================================
+//
+// `assume(cond, "justification");` はモデルの外で保証される事実を証明なしで仮定する。
+// 正当化文は必須で、仮定は検証サマリと report.json に一覧される。仮定を含む atom は
+// total にできず、`[proof] deny_assume = true` のプロジェクトでは assume 自体がエラーになる。
+
+/// assume で仮定した事実
+#[derive(Debug, Clone, PartialEq)]
+pub struct Assumption {
+    /// 仮定した条件式（表示用テキスト）
+    pub condition: String,
+    pub justification: String,
+}
+
+/// 式に含まれる assume を出現順に集める
+pub fn collect_assumptions(expr: &Expr, out: &mut Vec<Assumption>) {
+    expr.walk(|e| {
+        if let Expr::Assume { text, justification, .. } = e {
+            out.push(Assumption { condition: text.clone(), justification: justification.clone() });
+        }
+        true
+    });
+}
+
+/// atom の body に含まれる assume を返す。正当化文のない assume はパースエラーにする
+/// （validate_literals / validate_names と同じく検証・チェックの入口で呼ぶ）
+pub fn atom_assumptions(atom: &Atom) -> Result<Vec<Assumption>, String> {
+    let mut assumptions = Vec::new();
+    let body = parse_expression(&atom.body_expr).map_err(|e| format!("Parse error in atom '{}': {}", atom.name, e))?;
+    collect_assumptions(&body, &mut assumptions);
+    if let Some(missing) = assumptions.iter().find(|a| a.justification.is_empty()) {
+        return Err(format!(
+            "Parse error in atom '{}': assume({}) requires a justification string, e.g. assume({}, \"guaranteed by the device datasheet\")",
+            atom.name, missing.condition, missing.condition
+        ));
+    }
+    Ok(assumptions)
+}
+
+/// 式パーサー内部のエラー。位置はトークンの添字で持ち、parse_expression がバイト位置に直す
+struct SyntaxError {
+    token: usize,
+    message: &'static str,
+}
+
+type Parsed<T> = Result<T, SyntaxError>;
+
+fn syntax_error<T>(token: usize, message: &'static str) -> Parsed<T> {
+    Err(SyntaxError { token, message })
+}
+
+/// 閉じ括弧 close を読み飛ばす。なければ（入力の終わりを含む）その位置を指すエラー
+fn expect_closing(tokens: &[&str], pos: &mut usize, close: &str, message: &'static str) -> Parsed<()> {
+    if tokens.get(*pos) != Some(&close) {
+        return syntax_error(*pos, message);
+    }
+    *pos += 1;
+    Ok(())
+}
+
+pub fn parse_expression(input: &str) -> Result<Expr, ParseError> {
+    let tokens = tokenize(input);
+    let mut pos = 0;
+    parse_block_or_expr(&tokens, &mut pos).map_err(|e| {
+        // トークンは input を指すスライスなので、ポインタの差がバイト位置になる
+        let (offset, len) = match tokens.get(e.token) {
+            Some(token) => (token.as_ptr() as usize - input.as_ptr() as usize, token.len()),
+            None => (input.trim_end().len(), 0),
+        };
+        ParseError::new(input, offset, len, e.message)
+    })
+}
+
+fn parse_block_or_expr(tokens: &[&str], pos: &mut usize) -> Parsed<Expr> {
+    if *pos < tokens.len() && tokens[*pos] == "{" {
+        *pos += 1;
+        let mut stmts = Vec::new();
+        while *pos < tokens.len() && tokens[*pos] != "}" {
+            stmts.push(parse_statement(tokens, pos)?);
+            if *pos < tokens.len() && tokens[*pos] == ";" { *pos += 1; }
+        }
+        expect_closing(tokens, pos, "}", "expected '}' to close the block")?;
+        Ok(Expr::Block(stmts))
+    } else {
+        parse_implies(tokens, pos)
+    }
+}
+
+/// match アームの body 専用パーサー。
+/// `{...}` ブロックの場合は通常通りパース。
+/// それ以外の場合は `parse_logical_or` を使い、`=>` を含意演算子として消費しない。
+/// これにより `0 => match x { 0 => 1, _ => 2 }, 1 => ...` のネストが正しく動作する。
+fn parse_match_arm_body(tokens: &[&str], pos: &mut usize) -> Parsed<Expr> {
+    if *pos < tokens.len() && tokens[*pos] == "{" {
+        // ブロック式: 通常通りパース（内部の `=>` は match パーサーが処理する）
+        parse_block_or_expr(tokens, pos)
+    } else if *pos < tokens.len() && tokens[*pos] == "match" {
+        // ネストした match 式: match パーサーに委譲（parse_primary 経由）
+        parse_implies(tokens, pos)
+    } else if *pos < tokens.len() && tokens[*pos] == "if" {
+        // if-then-else 式
+        parse_implies(tokens, pos)
+    } else {
+        // それ以外: `=>` を消費しないレベルでパース
+        parse_conditional(tokens, pos)
+    }
+}
+
+fn parse_statement(tokens: &[&str], pos: &mut usize) -> Parsed<Expr> {
+    if *pos < tokens.len() && tokens[*pos] == "let" {
+        *pos += 1;
+        let var = match tokens.get(*pos) {
+            Some(var) if var.starts_with(|c: char| c.is_alphabetic() || c == '_') => var.to_string(),
+            _ => return syntax_error(*pos, "expected a variable name after 'let'"),
+        };
+        *pos += 1;
+        if *pos < tokens.len() && tokens[*pos] == "=" { *pos += 1; }
+        let value = parse_implies(tokens, pos)?;
+        Ok(Expr::Let { var, value: Box::new(value) })
+    } else if *pos + 1 < tokens.len()
+        && tokens[*pos].chars().next().map_or(false, |c| c.is_alphabetic() || c == '_')
+        && tokens[*pos + 1] == "="
+    {
+        let var = tokens[*pos].to_string();
+        *pos += 1;
+        *pos += 1;
+        let value = parse_implies(tokens, pos)?;
+        Ok(Expr::Assign { var, value: Box::new(value) })
+    } else {
+        parse_implies(tokens, pos)
+    }
+}
+
+fn parse_implies(tokens: &[&str], pos: &mut usize) -> Parsed<Expr> {
+    let mut node = parse_conditional(tokens, pos)?;
+    while *pos < tokens.len() && tokens[*pos] == "=>" {
+        *pos += 1;
+        let right = parse_conditional(tokens, pos)?;
+        node = Expr::BinaryOp(Box::new(node), Op::Implies, Box::new(right));
+    }
+    Ok(node)
+}
+
+/// 条件演算子 `cond ? a : b`（比較・論理演算より低い優先順位で右結合）。
+/// Expr::IfThenElse に脱糖する。`if` 式と違って分岐は Block にならないため、
+/// トランスパイラは分岐の形で式としての再出力（三項演算子など）を選べる。
+/// 構造体リテラルの `field: value` の `:` はフィールド名の直後で消費済みなので衝突しない。
+fn parse_conditional(tokens: &[&str], pos: &mut usize) -> Parsed<Expr> {
+    let cond = parse_logical_or(tokens, pos)?;
+    if *pos < tokens.len() && tokens[*pos] == "?" {
+        *pos += 1;
+        let then_branch = parse_conditional(tokens, pos)?;
+        if tokens.get(*pos) != Some(&":") {
+            return syntax_error(*pos, "expected ':' in conditional expression");
+        }
+        *pos += 1;
+        let else_branch = parse_conditional(tokens, pos)?;
+        return Ok(Expr::IfThenElse { cond: Box::new(cond), then_branch: Box::new(then_branch), else_branch: Box::new(else_branch) });
+    }
+    Ok(cond)
+}
+
+fn parse_logical_or(tokens: &[&str], pos: &mut usize) -> Parsed<Expr> {
+    let mut node = parse_logical_and(tokens, pos)?;
+    while *pos < tokens.len() && tokens[*pos] == "||" {
+        *pos += 1;
+        let right = parse_logical_and(tokens, pos)?;
+        node = Expr::BinaryOp(Box::new(node), Op::Or, Box::new(right));
+    }
+    Ok(node)
+}
+
+fn parse_logical_and(tokens: &[&str], pos: &mut usize) -> Parsed<Expr> {
+    let mut node = parse_comparison(tokens, pos)?;
+    while *pos < tokens.len() && tokens[*pos] == "&&" {
+        *pos += 1;
+        let right = parse_comparison(tokens, pos)?;
+        node = Expr::BinaryOp(Box::new(node), Op::And, Box::new(right));
+    }
+    Ok(node)
+}
+
+fn parse_comparison(tokens: &[&str], pos: &mut usize) -> Parsed<Expr> {
+    let mut node = parse_add_sub(tokens, pos)?;
+    if *pos < tokens.len() {
+        let op = match tokens[*pos] {
+            ">" => Some(Op::Gt), "<" => Some(Op::Lt), "==" => Some(Op::Eq),
             "!=" => Some(Op::Neq), ">=" => Some(Op::Ge), "<=" => Some(Op::Le),
             _ => None,
         };
         if let Some(operator) = op {
             *pos += 1;
-            let right = parse_add_sub(tokens, pos);
+            let right = parse_add_sub(tokens, pos)?;
             node = Expr::BinaryOp(Box::new(node), operator, Box::new(right));
+        } else if tokens[*pos] == "~=" {
+            // `a ~= b within eps` は approx_eq(a, b, eps) の糖衣構文
+            *pos += 1;
+            let right = parse_add_sub(tokens, pos)?;
+            let eps = if *pos < tokens.len() && tokens[*pos] == "within" {
+                *pos += 1;
+                parse_add_sub(tokens, pos)?
+            } else {
+                // within 節がなければ許容誤差なし（approx_eq の検査でエラーになる）
+                Expr::Number(0)
+            };
+            node = Expr::Call("approx_eq".into(), vec![node, right, eps]);
         }
     }
-    node
+    Ok(node)
 }
 
-fn parse_add_sub(tokens: &[String], pos: &mut usize) -> Expr {
-    let mut node = parse_mul_div(tokens, pos);
+fn parse_add_sub(tokens: &[&str], pos: &mut usize) -> Parsed<Expr> {
+    let mut node = parse_mul_div(tokens, pos)?;
     while *pos < tokens.len() && (tokens[*pos] == "+" || tokens[*pos] == "-") {
         let op = if tokens[*pos] == "+" { Op::Add } else { Op::Sub };
         *pos += 1;
-        let right = parse_mul_div(tokens, pos);
+        let right = parse_mul_div(tokens, pos)?;
         node = Expr::BinaryOp(Box::new(node), op, Box::new(right));
     }
-    node
+    Ok(node)
 }
 
-fn parse_mul_div(tokens: &[String], pos: &mut usize) -> Expr {
-    let mut node = parse_primary(tokens, pos);
+fn parse_mul_div(tokens: &[&str], pos: &mut usize) -> Parsed<Expr> {
+    let mut node = parse_cast(tokens, pos)?;
     while *pos < tokens.len() && (tokens[*pos] == "*" || tokens[*pos] == "/") {
         let op = if tokens[*pos] == "*" { Op::Mul } else { Op::Div };
         *pos += 1;
-        let right = parse_primary(tokens, pos);
+        let right = parse_cast(tokens, pos)?;
         node = Expr::BinaryOp(Box::new(node), op, Box::new(right));
     }
-    node
+    Ok(node)
 }
 
-fn parse_primary(tokens: &[String], pos: &mut usize) -> Expr {
-    if *pos >= tokens.len() { return Expr::Number(0); }
-    let token = &tokens[*pos];
+/// 後置キャスト `expr as i64`。`cast_to_int(expr)` に脱糖し、二項演算子より強く結合する
+fn parse_cast(tokens: &[&str], pos: &mut usize) -> Parsed<Expr> {
+    let mut node = parse_primary(tokens, pos)?;
+    while *pos < tokens.len() && tokens[*pos] == "as" {
+        if tokens.get(*pos + 1) != Some(&"i64") {
+            return syntax_error(*pos + 1, "only `as i64` casts are supported");
+        }
+        *pos += 2;
+        node = Expr::Call("cast_to_int".to_string(), vec![node]);
+    }
+    Ok(node)
+}
+
+fn parse_primary(tokens: &[&str], pos: &mut usize) -> Parsed<Expr> {
+    // 入力の終わりや閉じ括弧・区切りの位置に式はない（`x +;` を `x + 0` と読まない）
+    let token = match tokens.get(*pos) {
+        Some(token) if !matches!(*token, ")" | "}" | "]" | "," | ";") => *token,
+        _ => return syntax_error(*pos, "expected an expression"),
+    };
 
     // acquire 式: acquire resource_name { body }
     if token == "acquire" {
         *pos += 1;
         let resource = if *pos < tokens.len() {
-            let r = tokens[*pos].clone();
+            let mut r = tokens[*pos].to_string();
             *pos += 1;
+            // 修飾名 `alias::name`（トークン列では `alias` `:` `:` `name`）
+            while *pos + 2 < tokens.len() && tokens[*pos] == ":" && tokens[*pos + 1] == ":" {
+                r = format!("{}::{}", r, tokens[*pos + 2]);
+                *pos += 3;
+            }
             r
         } else {
             "unknown".to_string()
         };
-        let body = parse_block_or_expr(tokens, pos);
-        return Expr::Acquire { resource, body: Box::new(body) };
+        let body = parse_block_or_expr(tokens, pos)?;
+        return Ok(Expr::Acquire { resource, body: Box::new(body) });
+    }
+
+    // assume 文: assume(cond, "justification")
+    if token == "assume" && tokens.get(*pos + 1).map_or(false, |t| *t == "(") {
+        *pos += 2;
+        let start = *pos;
+        let cond = parse_implies(tokens, pos)?;
+        let text = join_tokens(&tokens[start..*pos]);
+        let mut justification = String::new();
+        if *pos < tokens.len() && tokens[*pos] == "," {
+            *pos += 1;
+            if let Some(literal) = tokens.get(*pos).and_then(|t| t.strip_prefix('"')).and_then(|t| t.strip_suffix('"')) {
+                justification = literal.replace("\\\"", "\"").trim().to_string();
+            }
+        }
+        // 正当化文が文字列でない場合も閉じ括弧までを assume の引数として読み飛ばす
+        while *pos < tokens.len() && tokens[*pos] != ")" { *pos += 1; }
+        expect_closing(tokens, pos, ")", "expected ')' to close assume(...)")?;
+        return Ok(Expr::Assume { cond: Box::new(cond), text, justification });
     }
 
     // async 式: async { body }
     if token == "async" {
         *pos += 1;
-        let body = parse_block_or_expr(tokens, pos);
-        return Expr::Async { body: Box::new(body) };
+        let body = parse_block_or_expr(tokens, pos)?;
+        return Ok(Expr::Async { body: Box::new(body) });
     }
 
     // await 式: await expr
     if token == "await" {
         *pos += 1;
-        let expr = parse_primary(tokens, pos);
-        return Expr::Await { expr: Box::new(expr) };
+        let expr = parse_primary(tokens, pos)?;
+        return Ok(Expr::Await { expr: Box::new(expr) });
     }
 
     // while, if 処理 (既存通り)
     if token == "while" {
+        let keyword = *pos;
         *pos += 1;
-        let cond = parse_implies(tokens, pos);
+        let cond = parse_implies(tokens, pos)?;
         if *pos < tokens.len() && tokens[*pos] == "invariant" {
             *pos += 1;
             // `invariant:` の `:` をスキップ（tokenizer が `:` を独立トークンとして分離するため）
             if *pos < tokens.len() && tokens[*pos] == ":" { *pos += 1; }
-            let inv = parse_implies(tokens, pos);
+            let inv = parse_implies(tokens, pos)?;
             // オプション: decreases 句（停止性証明用の減少式）
             let decreases = if *pos < tokens.len() && tokens[*pos] == "decreases" {
                 *pos += 1;
                 // `decreases:` の `:` もスキップ
                 if *pos < tokens.len() && tokens[*pos] == ":" { *pos += 1; }
-                Some(Box::new(parse_implies(tokens, pos)))
+                Some(Box::new(parse_implies(tokens, pos)?))
             } else {
                 None
             };
-            let body = parse_block_or_expr(tokens, pos);
-            return Expr::While { cond: Box::new(cond), invariant: Box::new(inv), decreases, body: Box::new(body) };
+            let body = parse_block_or_expr(tokens, pos)?;
+            return Ok(Expr::While { cond: Box::new(cond), invariant: Box::new(inv), decreases, body: Box::new(body) });
         }
-        panic!("Mumei loops require an 'invariant'.");
+        return syntax_error(keyword, "Mumei loops require an 'invariant'");
     }
 
     if token == "if" {
+        let keyword = *pos;
         *pos += 1;
-        let cond = parse_implies(tokens, pos);
-        let then_branch = parse_block_or_expr(tokens, pos);
+        let cond = parse_implies(tokens, pos)?;
+        let then_branch = parse_block_or_expr(tokens, pos)?;
         if *pos < tokens.len() && tokens[*pos] == "else" {
             *pos += 1;
-            let else_branch = parse_block_or_expr(tokens, pos);
-            return Expr::IfThenElse { cond: Box::new(cond), then_branch: Box::new(then_branch), else_branch: Box::new(else_branch) };
+            let else_branch = parse_block_or_expr(tokens, pos)?;
+            return Ok(Expr::IfThenElse { cond: Box::new(cond), then_branch: Box::new(then_branch), else_branch: Box::new(else_branch) });
         }
-        panic!("Mumei requires an 'else' branch.");
+        return syntax_error(keyword, "Mumei requires an 'else' branch");
     }
 
     // match 式: match expr { Pattern => expr, ... }
     if token == "match" {
         *pos += 1;
-        let target = parse_implies(tokens, pos);
-        if *pos < tokens.len() && tokens[*pos] == "{" {
-            *pos += 1; // skip {
-        }
+        let target = parse_implies(tokens, pos)?;
+        expect_closing(tokens, pos, "{", "expected '{' after the match target")?;
         let mut arms = Vec::new();
         while *pos < tokens.len() && tokens[*pos] != "}" {
-            let pattern = parse_pattern(tokens, pos);
+            let pattern = parse_pattern(tokens, pos)?;
             // オプション: ガード条件 "if cond"
             // parse_logical_or を使い、`=>` を含意演算子として消費しない。
             // これにより `Pattern if cond => body` の `=>` がアーム区切りとして正しく処理される。
             let guard = if *pos < tokens.len() && tokens[*pos] == "if" {
                 *pos += 1;
-                Some(Box::new(parse_logical_or(tokens, pos)))
+                Some(Box::new(parse_logical_or(tokens, pos)?))
             } else {
                 None
             };
@@ -1150,24 +2457,43 @@ fn parse_primary(tokens: &[String], pos: &mut usize) -> Expr {
             // アーム body のパース:
             // `=>` を含意演算子として消費しないよう parse_match_arm_body を使用。
             // これにより `0 => match x { ... }, 1 => ...` のネストが正しく解析される。
-            let body = parse_match_arm_body(tokens, pos);
+            let body = parse_match_arm_body(tokens, pos)?;
             arms.push(MatchArm { pattern, guard, body: Box::new(body) });
             // アーム間の "," をスキップ
             if *pos < tokens.len() && tokens[*pos] == "," { *pos += 1; }
         }
-        if *pos < tokens.len() && tokens[*pos] == "}" { *pos += 1; }
-        return Expr::Match { target: Box::new(target), arms };
+        expect_closing(tokens, pos, "}", "expected '}' to close the match")?;
+        return Ok(Expr::Match { target: Box::new(target), arms });
     }
 
     *pos += 1;
     let mut node = if token == "(" {
-        let node = parse_implies(tokens, pos);
-        if *pos < tokens.len() && tokens[*pos] == ")" { *pos += 1; }
+        let node = parse_implies(tokens, pos)?;
+        expect_closing(tokens, pos, ")", "expected ')'")?;
         node
-    } else if let Ok(n) = token.parse::<i64>() {
-        Expr::Number(n)
-    } else if let Ok(f) = token.parse::<f64>() {
-        if token.contains('.') { Expr::Float(f) } else { Expr::Number(token.parse().unwrap()) }
+    } else if token == "-" {
+        // 単項マイナス: 整数リテラルには符号ごと畳み込み（i64::MIN を表現可能にする）、
+        // それ以外は `0 - expr` に脱糖する
+        if let Some(magnitude) = tokens.get(*pos).and_then(|t| parse_int_literal(t)) {
+            let literal = &tokens[*pos];
+            *pos += 1;
+            Expr::Number(int_literal_to_i64(literal, magnitude, true).unwrap_or(i64::MIN))
+        } else if let Some(f) = tokens.get(*pos).and_then(|t| parse_float_literal(t)) {
+            *pos += 1;
+            Expr::Float(-f)
+        } else {
+            let operand = parse_primary(tokens, pos)?;
+            Expr::BinaryOp(Box::new(Expr::Number(0)), Op::Sub, Box::new(operand))
+        }
+    } else if let Some(magnitude) = parse_int_literal(token) {
+        // 範囲外リテラルは validate_literals で報告されるため、ここでは飽和させて続行する
+        Expr::Number(int_literal_to_i64(token, magnitude, false).unwrap_or(i64::MAX))
+    } else if let Some(f) = parse_float_literal(token) {
+        Expr::Float(f)
+    } else if let Some(literal) = token.strip_prefix('"').and_then(|t| t.strip_suffix('"')) {
+        Expr::StringLit(unescape_string(literal))
+    } else if token == "true" || token == "false" {
+        Expr::Bool(token == "true")
     } else if *pos < tokens.len() && tokens[*pos] == "{" {
         // 構造体初期化: TypeName { field: expr, ... }
         // 大文字始まりの識別子の後に { が来たら構造体と判定
@@ -1175,48 +2501,66 @@ fn parse_primary(tokens: &[String], pos: &mut usize) -> Expr {
             *pos += 1; // skip {
             let mut fields = Vec::new();
             while *pos < tokens.len() && tokens[*pos] != "}" {
-                let field_name = tokens[*pos].clone();
+                let field_name = tokens[*pos].to_string();
                 *pos += 1;
                 if *pos < tokens.len() && tokens[*pos] == ":" { *pos += 1; }
-                let value = parse_implies(tokens, pos);
+                let value = parse_implies(tokens, pos)?;
                 fields.push((field_name, value));
                 if *pos < tokens.len() && tokens[*pos] == "," { *pos += 1; }
             }
-            if *pos < tokens.len() && tokens[*pos] == "}" { *pos += 1; }
-            Expr::StructInit { type_name: token.clone(), fields }
+            expect_closing(tokens, pos, "}", "expected '}' to close the struct literal")?;
+            Expr::StructInit { type_name: token.to_string(), fields }
         } else {
-            Expr::Variable(token.clone())
+            Expr::Variable(token.to_string())
         }
     } else if *pos < tokens.len() && tokens[*pos] == "(" {
         // 関数呼び出し: name(args)
         *pos += 1; // (
         let mut args = Vec::new();
         while *pos < tokens.len() && tokens[*pos] != ")" {
-            args.push(parse_implies(tokens, pos));
+            args.push(parse_call_arg(tokens, pos)?);
             if *pos < tokens.len() && tokens[*pos] == "," { *pos += 1; }
         }
-        if *pos < tokens.len() && tokens[*pos] == ")" { *pos += 1; }
-        Expr::Call(token.clone(), args)
+        expect_closing(tokens, pos, ")", "expected ')' to close the argument list")?;
+        Expr::Call(token.to_string(), args)
     } else if *pos < tokens.len() && tokens[*pos] == "[" {
         // 配列アクセス
         *pos += 1; // [
-        let index = parse_implies(tokens, pos);
-        if *pos < tokens.len() && tokens[*pos] == "]" { *pos += 1; }
-        Expr::ArrayAccess(token.clone(), Box::new(index))
+        let index = parse_implies(tokens, pos)?;
+        expect_closing(tokens, pos, "]", "expected ']'")?;
+        Expr::ArrayAccess(token.to_string(), Box::new(index))
     } else {
-        Expr::Variable(token.clone())
+        Expr::Variable(token.to_string())
     };
 
+    // nullable アクセス: `x!` は `value(x)` の糖衣（存在性の証明義務を伴う）
+    if *pos < tokens.len() && tokens[*pos] == "!" {
+        *pos += 1;
+        node = Expr::Call("value".to_string(), vec![node]);
+    }
+
     // フィールドアクセスチェーン: expr.field1.field2 ...
     while *pos < tokens.len() && tokens[*pos] == "." {
         *pos += 1; // skip .
         if *pos < tokens.len() {
-            let field = tokens[*pos].clone();
+            let field = tokens[*pos].to_string();
             *pos += 1;
             node = Expr::FieldAccess(Box::new(node), field);
         }
     }
-    node
+    Ok(node)
+}
+
+/// 呼び出しの実引数。`ref x` / `ref mut x` は参照渡しのマーカー付きで読む
+fn parse_call_arg(tokens: &[&str], pos: &mut usize) -> Parsed<Expr> {
+    if *pos < tokens.len() && tokens[*pos] == "ref" {
+        *pos += 1;
+        let mutable = *pos < tokens.len() && tokens[*pos] == "mut";
+        if mutable { *pos += 1; }
+        let expr = parse_implies(tokens, pos)?;
+        return Ok(Expr::RefArg { expr: Box::new(expr), mutable });
+    }
+    parse_implies(tokens, pos)
 }
 
 /// パターンをパースする
@@ -1225,33 +2569,34 @@ fn parse_primary(tokens: &[String], pos: &mut usize) -> Expr {
 /// - 大文字始まり識別子 + "(" ... ")" → Variant パターン
 /// - 大文字始まり識別子（括弧なし） → Unit Variant パターン
 /// - 小文字始まり識別子 → 変数バインド
-fn parse_pattern(tokens: &[String], pos: &mut usize) -> Pattern {
-    if *pos >= tokens.len() { return Pattern::Wildcard; }
+fn parse_pattern(tokens: &[&str], pos: &mut usize) -> Parsed<Pattern> {
+    if *pos >= tokens.len() { return Ok(Pattern::Wildcard); }
 
-    let token = &tokens[*pos];
+    let token = tokens[*pos];
 
     if token == "_" {
         *pos += 1;
-        return Pattern::Wildcard;
+        return Ok(Pattern::Wildcard);
     }
 
-    // 負の数値リテラル: "-" + 数字
+    // 負の数値リテラル: "-" + 数字（i64::MIN も可）
     if token == "-" && *pos + 1 < tokens.len() {
-        if let Ok(n) = tokens[*pos + 1].parse::<i64>() {
+        if let Some(magnitude) = parse_int_literal(&tokens[*pos + 1]) {
+            let n = int_literal_to_i64(&tokens[*pos + 1], magnitude, true).unwrap_or(i64::MIN);
             *pos += 2;
-            return Pattern::Literal(-n);
+            return Ok(Pattern::Literal(n));
         }
     }
 
-    // 数値リテラル
-    if let Ok(n) = token.parse::<i64>() {
+    // 数値リテラル（10進 / 0x / 0b、`_` 区切り可）
+    if let Some(magnitude) = parse_int_literal(token) {
         *pos += 1;
-        return Pattern::Literal(n);
+        return Ok(Pattern::Literal(int_literal_to_i64(token, magnitude, false).unwrap_or(i64::MAX)));
     }
 
     // 識別子
     if token.chars().next().map_or(false, |c| c.is_alphabetic() || c == '_') {
-        let name = token.clone();
+        let name = token.to_string();
         *pos += 1;
 
         // 大文字始まり → Variant パターン
@@ -1260,22 +2605,22 @@ fn parse_pattern(tokens: &[String], pos: &mut usize) -> Pattern {
                 *pos += 1; // skip (
                 let mut fields = Vec::new();
                 while *pos < tokens.len() && tokens[*pos] != ")" {
-                    fields.push(parse_pattern(tokens, pos));
+                    fields.push(parse_pattern(tokens, pos)?);
                     if *pos < tokens.len() && tokens[*pos] == "," { *pos += 1; }
                 }
                 if *pos < tokens.len() && tokens[*pos] == ")" { *pos += 1; }
-                return Pattern::Variant { variant_name: name, fields };
+                return Ok(Pattern::Variant { variant_name: name, fields });
             }
             // Unit variant（括弧なし）
-            return Pattern::Variant { variant_name: name, fields: vec![] };
+            return Ok(Pattern::Variant { variant_name: name, fields: vec![] });
         }
 
         // 小文字始まり → 変数バインド
-        return Pattern::Variable(name);
+        return Ok(Pattern::Variable(name));
     }
 
     *pos += 1;
-    Pattern::Wildcard
+    Ok(Pattern::Wildcard)
 }
 
 // =============================================================================
@@ -1286,6 +2631,7 @@ fn parse_pattern(tokens: &[String], pos: &mut usize) -> Pattern {
 mod tests {
     use super::*;
     use crate::ast::TypeRef;
+    use crate::test_support::find_atom;
 
     #[test]
     fn test_parse_type_ref_simple() {
@@ -1312,6 +2658,23 @@ mod tests {
         assert_eq!(tr.type_args[1].type_args[0].name, "i64");
     }
 
+    #[test]
+    fn test_parse_type_ref_nullable() {
+        let tr = parse_type_ref("Nat?");
+        assert_eq!(tr.display_name(), "Option<Nat>");
+        assert_eq!(tr.option_inner().map(|t| t.name.as_str()), Some("Nat"));
+
+        let atom = parse_atom("atom f(x: Nat?, y: i64) requires: is_some(x); ensures: result >= 0; body: x! + y;").unwrap();
+        assert_eq!(atom.params[0].type_name.as_deref(), Some("Option<Nat>"));
+        assert!(matches!(
+            parse_expression(&atom.body_expr).unwrap(),
+            Expr::BinaryOp(ref l, Op::Add, _) if matches!(l.as_ref(), Expr::Call(name, _) if name == "value")
+        ));
+        // 空白を挟んだ `!` は従来どおり無視され、`!=` は比較演算子のまま
+        assert_eq!(tokenize("x != y"), vec!["x", "!=", "y"]);
+        assert_eq!(tokenize("if !x"), vec!["if", "x"]);
+    }
+
     #[test]
     fn test_parse_type_ref_display() {
         let tr = parse_type_ref("Stack<i64>");
@@ -1344,7 +2707,7 @@ struct Pair<T, U> {
     second: U
 }
 "#;
-        let items = parse_module(source);
+        let items = parse_module(source).unwrap();
         let struct_items: Vec<_> = items.iter().filter_map(|i| {
             if let Item::StructDef(s) = i { Some(s) } else { None }
         }).collect();
@@ -1358,6 +2721,28 @@ struct Pair<T, U> {
         assert_eq!(s.fields[0].type_ref.name, "T");
         assert_eq!(s.fields[1].name, "second");
         assert_eq!(s.fields[1].type_ref.name, "U");
+        assert_eq!(s.invariant, None);
+    }
+
+    #[test]
+    fn test_parse_struct_invariant() {
+        // invariant は最後の項目で、式中のカンマを含めて `}` までを取る
+        let source = r#"
+struct Account {
+    balance: i64,
+    reserved: i64 where v >= 0,
+    invariant: balance >= reserved
+        && clamp(balance, 0, 100) >= 0,
+}
+"#;
+        let items = parse_module(source).unwrap();
+        let s = match &items[0] {
+            Item::StructDef(s) => s,
+            other => panic!("expected a struct, got {:?}", other),
+        };
+        let fields: Vec<(&str, Option<&str>)> = s.fields.iter().map(|f| (f.name.as_str(), f.constraint.as_deref())).collect();
+        assert_eq!(fields, [("balance", None), ("reserved", Some("v >= 0"))]);
+        assert_eq!(s.invariant.as_deref(), Some("balance >= reserved && clamp(balance, 0, 100) >= 0"));
     }
 
     #[test]
@@ -1368,7 +2753,7 @@ enum Option<T> {
     None
 }
 "#;
-        let items = parse_module(source);
+        let items = parse_module(source).unwrap();
         let enum_items: Vec<_> = items.iter().filter_map(|i| {
             if let Item::EnumDef(e) = i { Some(e) } else { None }
         }).collect();
@@ -1384,6 +2769,27 @@ enum Option<T> {
         assert!(e.variants[1].fields.is_empty());
     }
 
+    #[test]
+    fn test_parse_recursive_enum() {
+        let items = parse_module("enum List { Nil, Cons(i64, Self) }\nenum Pair { Both(i64, Option<i64>), Neither }").unwrap();
+        let enum_items: Vec<_> = items.iter().filter_map(|i| {
+            if let Item::EnumDef(e) = i { Some(e) } else { None }
+        }).collect();
+
+        // 括弧内のカンマで variant を分割せず、Self は Enum 名に展開する
+        let list = &enum_items[0];
+        assert_eq!(list.variants.len(), 2);
+        assert_eq!(list.variants[1].name, "Cons");
+        assert_eq!(list.variants[1].fields, vec!["i64", "List"]);
+        assert!(list.variants[1].is_recursive && !list.variants[0].is_recursive);
+        assert!(list.is_recursive);
+
+        let pair = &enum_items[1];
+        assert_eq!(pair.variants.len(), 2);
+        assert_eq!(pair.variants[0].fields, vec!["i64", "Option<i64>"]);
+        assert!(!pair.is_recursive);
+    }
+
     #[test]
     fn test_parse_generic_atom() {
         let source = r#"
@@ -1392,7 +2798,7 @@ requires: true;
 ensures: true;
 body: x;
 "#;
-        let items = parse_module(source);
+        let items = parse_module(source).unwrap();
         let atom_items: Vec<_> = items.iter().filter_map(|i| {
             if let Item::Atom(a) = i { Some(a) } else { None }
         }).collect();
@@ -1415,7 +2821,7 @@ trait Comparable {
     law transitive: leq(a, b) && leq(b, c) => leq(a, c);
 }
 "#;
-        let items = parse_module(source);
+        let items = parse_module(source).unwrap();
         let traits: Vec<_> = items.iter().filter_map(|i| {
             if let Item::TraitDef(t) = i { Some(t) } else { None }
         }).collect();
@@ -1432,6 +2838,31 @@ trait Comparable {
         assert_eq!(t.laws[1].0, "transitive");
     }
 
+    #[test]
+    fn test_parse_trait_extends() {
+        let source = r#"
+trait Monoid extends Semigroup with add = combine + Eq {
+    fn unit() -> Self;
+    law left_identity: combine(unit(), a) == a;
+}
+"#;
+        let items = parse_module(source).unwrap();
+        let traits: Vec<_> = items.iter().filter_map(|i| {
+            if let Item::TraitDef(t) = i { Some(t) } else { None }
+        }).collect();
+
+        assert_eq!(traits.len(), 1);
+        let t = &traits[0];
+        assert_eq!(t.name, "Monoid");
+        assert_eq!(t.extends.len(), 2);
+        assert_eq!(t.extends[0].0, "Semigroup");
+        assert_eq!(t.extends[0].1.get("add").map(String::as_str), Some("combine"));
+        assert_eq!(t.extends[1].0, "Eq");
+        assert!(t.extends[1].1.is_empty());
+        assert_eq!(t.methods.len(), 1);
+        assert_eq!(t.laws.len(), 1);
+    }
+
     #[test]
     fn test_parse_impl_def() {
         let source = r#"
@@ -1439,7 +2870,7 @@ impl Comparable for i64 {
     fn leq(a: i64, b: i64) -> bool { a <= b }
 }
 "#;
-        let items = parse_module(source);
+        let items = parse_module(source).unwrap();
         let impls: Vec<_> = items.iter().filter_map(|i| {
             if let Item::ImplDef(im) = i { Some(im) } else { None }
         }).collect();
@@ -1460,7 +2891,7 @@ requires: true;
 ensures: true;
 body: a;
 "#;
-        let items = parse_module(source);
+        let items = parse_module(source).unwrap();
         let atoms: Vec<_> = items.iter().filter_map(|i| {
             if let Item::Atom(a) = i { Some(a) } else { None }
         }).collect();
@@ -1474,6 +2905,72 @@ body: a;
         assert_eq!(a.where_bounds[0].bounds, vec!["Comparable"]);
     }
 
+    #[test]
+    fn test_parse_where_clause() {
+        let source = r#"
+atom span<T: Comparable>(lo: i64, hi: i64, tag: T)
+where: lo <= hi;
+requires: lo >= 0;
+ensures: result >= 0;
+body: hi - lo;
+
+atom plain(lo: i64, hi: i64)
+where: lo <= hi;
+ensures: true;
+body: lo;
+
+atom no_where(x: i64)
+requires: x >= 0;
+ensures: true;
+body: x;
+"#;
+        let items = parse_module(source).unwrap();
+        let atoms: Vec<_> = items.iter().filter_map(|i| {
+            if let Item::Atom(a) = i { Some(a) } else { None }
+        }).collect();
+        assert_eq!(atoms.len(), 3);
+
+        // 型パラメータの境界は <...> から、値レベルの where はパラメータ直後の節から取る
+        let span = atoms[0];
+        assert_eq!(span.where_bounds[0].bounds, vec!["Comparable"]);
+        assert_eq!(span.where_clause.as_deref(), Some("lo <= hi"));
+        assert_eq!(span.requires_raw, "lo >= 0");
+        assert_eq!(span.requires, "(lo <= hi) && (lo >= 0)");
+
+        // requires がなければ where 節だけが事前条件になる
+        assert_eq!(atoms[1].requires, "lo <= hi");
+        assert_eq!(atoms[1].requires_raw, "true");
+        assert!(atoms[2].where_clause.is_none());
+        assert_eq!(atoms[2].requires, "x >= 0");
+    }
+
+    #[test]
+    fn test_parse_atom_return_type() {
+        let source = r#"
+atom make_point(a: i64) -> Point
+requires: a >= 0;
+ensures: result.x == a;
+body: Point { x: a, y: 0 };
+
+atom bounded(lo: i64, hi: i64) -> i64 where: lo <= hi;
+ensures: true;
+body: lo;
+
+atom inferred(x: i64)
+ensures: true;
+body: x;
+"#;
+        let items = parse_module(source).unwrap();
+        let atoms: Vec<_> = items.iter().filter_map(|i| {
+            if let Item::Atom(a) = i { Some(a) } else { None }
+        }).collect();
+        assert_eq!(atoms[0].return_type.as_deref(), Some("Point"));
+        // where 節が同じ行に続いても戻り値型はその手前まで
+        assert_eq!(atoms[1].return_type.as_deref(), Some("i64"));
+        assert_eq!(atoms[1].where_clause.as_deref(), Some("lo <= hi"));
+        assert!(atoms[2].return_type.is_none());
+    }
+
     #[test]
     fn test_parse_atom_with_multiple_bounds() {
         let source = r#"
@@ -1482,7 +2979,7 @@ requires: true;
 ensures: true;
 body: a;
 "#;
-        let items = parse_module(source);
+        let items = parse_module(source).unwrap();
         let atoms: Vec<_> = items.iter().filter_map(|i| {
             if let Item::Atom(a) = i { Some(a) } else { None }
         }).collect();
@@ -1514,7 +3011,7 @@ requires: true;
 ensures: true;
 body: a + b;
 "#;
-        let items = parse_module(source);
+        let items = parse_module(source).unwrap();
 
         let structs: Vec<_> = items.iter().filter_map(|i| {
             if let Item::StructDef(s) = i { Some(s) } else { None }
@@ -1548,7 +3045,7 @@ body: a + b;
 resource db_conn priority: 1 mode: exclusive;
 resource cache priority: 2 mode: shared;
 "#;
-        let items = parse_module(source);
+        let items = parse_module(source).unwrap();
         let resources: Vec<_> = items.iter().filter_map(|i| {
             if let Item::ResourceDef(r) = i { Some(r) } else { None }
         }).collect();
@@ -1571,7 +3068,7 @@ requires: amount >= 0;
 ensures: true;
 body: amount;
 "#;
-        let items = parse_module(source);
+        let items = parse_module(source).unwrap();
         let atoms: Vec<_> = items.iter().filter_map(|i| {
             if let Item::Atom(a) = i { Some(a) } else { None }
         }).collect();
@@ -1585,12 +3082,12 @@ body: amount;
 
     #[test]
     fn test_parse_acquire_expression() {
-        let expr = parse_expression("acquire mutex_a { x + 1 }");
-        match expr {
+        let expr = parse_expression("acquire mutex_a { x + 1 }").unwrap();
+        match &expr {
             Expr::Acquire { resource, body } => {
                 assert_eq!(resource, "mutex_a");
                 // body should be a Block containing x + 1
-                match *body {
+                match **body {
                     Expr::Block(_) => {} // OK
                     _ => panic!("Expected Block in acquire body"),
                 }
@@ -1599,12 +3096,21 @@ body: amount;
         }
     }
 
+    #[test]
+    fn test_parse_acquire_qualified_resource() {
+        let expr = parse_expression("acquire lib_a::cache { x }").unwrap();
+        match &expr {
+            Expr::Acquire { resource, .. } => assert_eq!(resource, "lib_a::cache"),
+            _ => panic!("Expected Acquire expression, got {:?}", expr),
+        }
+    }
+
     #[test]
     fn test_parse_async_expression() {
-        let expr = parse_expression("async { x + 1 }");
-        match expr {
+        let expr = parse_expression("async { x + 1 }").unwrap();
+        match &expr {
             Expr::Async { body } => {
-                match *body {
+                match **body {
                     Expr::Block(_) => {} // OK
                     _ => panic!("Expected Block in async body"),
                 }
@@ -1613,6 +3119,19 @@ body: amount;
         }
     }
 
+    #[test]
+    fn test_parse_as_i64_cast() {
+        // `as i64` は cast_to_int の呼び出しに脱糖し、乗算より強く結合する
+        let cast = |e: Expr| Expr::Call("cast_to_int".to_string(), vec![e]);
+        assert_eq!(parse_expression("x as i64").unwrap(), parse_expression("cast_to_int(x)").unwrap());
+        assert_eq!(parse_expression("a * x as i64").unwrap(), Expr::BinaryOp(
+            Box::new(Expr::Variable("a".to_string())), Op::Mul, Box::new(cast(Expr::Variable("x".to_string()))),
+        ));
+        assert_eq!(parse_expression("(a + b) as i64").unwrap(), parse_expression("cast_to_int(a + b)").unwrap());
+        let err = parse_expression("x as f64").unwrap_err();
+        assert_eq!((err.message.as_str(), err.column), ("only `as i64` casts are supported", 6));
+    }
+
     #[test]
     fn test_parse_trusted_atom() {
         let source = r#"
@@ -1621,7 +3140,7 @@ requires: fd >= 0;
 ensures: result >= 0;
 body: fd;
 "#;
-        let items = parse_module(source);
+        let items = parse_module(source).unwrap();
         let atoms: Vec<_> = items.iter().filter_map(|i| {
             if let Item::Atom(a) = i { Some(a) } else { None }
         }).collect();
@@ -1640,7 +3159,7 @@ requires: true;
 ensures: true;
 body: x;
 "#;
-        let items = parse_module(source);
+        let items = parse_module(source).unwrap();
         let atoms: Vec<_> = items.iter().filter_map(|i| {
             if let Item::Atom(a) = i { Some(a) } else { None }
         }).collect();
@@ -1658,7 +3177,7 @@ requires: url >= 0;
 ensures: result >= 0;
 body: url;
 "#;
-        let items = parse_module(source);
+        let items = parse_module(source).unwrap();
         let atoms: Vec<_> = items.iter().filter_map(|i| {
             if let Item::Atom(a) = i { Some(a) } else { None }
         }).collect();
@@ -1669,6 +3188,133 @@ body: url;
         assert_eq!(atoms[0].trust_level, TrustLevel::Trusted);
     }
 
+    #[test]
+    fn test_parse_total_modifier() {
+        let source = r#"
+total atom safe_div(x: i64, y: i64)
+requires: y != 0;
+ensures: true;
+body: x / y;
+
+atom plain(x: i64)
+requires: true;
+ensures: true;
+body: x;
+"#;
+        let items = parse_module(source).unwrap();
+        let atoms: Vec<_> = items.iter().filter_map(|i| {
+            if let Item::Atom(a) = i { Some(a) } else { None }
+        }).collect();
+
+        assert_eq!(atoms.len(), 2);
+        assert_eq!(atoms[0].name, "safe_div");
+        assert!(atoms[0].is_total);
+        assert_eq!(atoms[0].trust_level, TrustLevel::Verified);
+        assert!(!atoms[1].is_total);
+    }
+
+    #[test]
+    fn test_reserved_atom_and_param_names() {
+        let atom = |source: &str| match parse_module(source).unwrap().remove(0) {
+            Item::Atom(a) => a,
+            other => panic!("Expected atom, got {:?}", other),
+        };
+        let err = validate_names(&atom("atom len_of(xs: [i64]) requires: true; ensures: true; body: 0;")).unwrap_err();
+        assert!(err.contains("atom 'len_of'") && err.contains("'len_'"), "{}", err);
+        let err = validate_names(&atom("atom call_twice(x: i64) requires: true; ensures: true; body: x;")).unwrap_err();
+        assert!(err.contains("atom 'call_twice'") && err.contains("'call_'"), "{}", err);
+        let err = validate_names(&atom("atom f(result: i64) requires: true; ensures: true; body: result;")).unwrap_err();
+        assert!(err.contains("parameter 'result'") && err.contains("return value"), "{}", err);
+        let err = validate_names(&atom("atom f(x: i64, __alive_x: bool) requires: true; ensures: true; body: x;")).unwrap_err();
+        assert!(err.contains("parameter '__alive_x'"), "{}", err);
+
+        // 接頭辞に一致しない名前は通常の識別子
+        assert!(validate_names(&atom("atom length(results: i64, caller: i64) requires: true; ensures: true; body: caller;")).is_ok());
+        assert!(reserved_name("__structure").is_none());
+        assert!(reserved_name("__q_i_0").is_some());
+    }
+
+    #[test]
+    fn test_quantifier_positions() {
+        let atom = |source: &str| match parse_module(source).unwrap().remove(0) {
+            Item::Atom(a) => a,
+            other => panic!("Expected atom, got {:?}", other),
+        };
+        // requires の最上位の連言・ensures のどこでも・ループ不変量と assume の中は書ける
+        assert!(validate_quantifiers(&atom("atom f(xs: [i64], n: i64)
+requires: n >= 0 && forall(i, 0, n, xs[i] >= 0);
+ensures: result >= 0 || exists(i, 0, n, xs[i] == result);
+body: {
+    let k = 0;
+    while k < n
+    invariant: k >= 0 && forall(j, 0, k, xs[j] >= 0)
+    decreases: n - k
+    {
+        k = k + 1;
+    };
+    k
+};")).is_ok());
+
+        let err = validate_quantifiers(&atom("atom f(xs: [i64], n: i64)
+requires: n == 0 || forall(i, 0, n, xs[i] >= 0);
+ensures: true;
+body: n;")).unwrap_err();
+        assert!(err.contains("atom 'f' nests forall(...) inside another expression in requires"), "{}", err);
+        assert!(err.contains("top-level conjuncts"), "{}", err);
+
+        let err = validate_quantifiers(&atom("atom g(xs: [i64], n: i64)
+requires: n >= 0;
+ensures: true;
+body: if exists(i, 0, n, xs[i] == 0) { 1 } else { 0 };")).unwrap_err();
+        assert!(err.contains("atom 'g' uses exists(...) in its body"), "{}", err);
+    }
+
+    #[test]
+    fn test_quantifier_shadowing_warnings() {
+        let atom = |source: &str| match parse_module(source).unwrap().remove(0) {
+            Item::Atom(a) => a,
+            other => panic!("Expected atom, got {:?}", other),
+        };
+        let warnings = quantifier_shadowing(&atom(
+            "atom f(i: i64, n: i64) requires: forall(i, 0, n, arr[i] >= 0); ensures: exists(i, 0, n, arr[i] >= 0) && exists(k, 0, n, k >= 0); body: i;",
+        ));
+        assert_eq!(warnings.len(), 2, "{:?}", warnings);
+        assert!(warnings[0].contains("forall(i, ...) shadows parameter 'i'"), "{:?}", warnings);
+        assert!(warnings[1].contains("exists(i, ...)"), "{:?}", warnings);
+
+        // パラメータと異なる名前の束縛変数は警告しない
+        assert!(quantifier_shadowing(&atom("atom g(n: i64) requires: forall(i, 0, n, arr[i] >= 0); ensures: true; body: n;")).is_empty());
+    }
+
+    #[test]
+    fn test_parse_export_list() {
+        let source = r#"
+export {
+    clamp,
+    Nat
+};
+
+type Nat = i64 where v >= 0;
+
+atom helper(x: i64) requires: true; ensures: result >= 0; body: x * x;
+
+atom clamp(x: i64) requires: true; ensures: result >= 0; body: helper(x);
+"#;
+        let items = parse_module(source).unwrap();
+        let atoms: Vec<_> = items.iter().filter_map(|i| {
+            if let Item::Atom(a) = i { Some(a.name.as_str()) } else { None }
+        }).collect();
+        assert_eq!(atoms, vec!["helper", "clamp"]);
+
+        let exports = module_exports(&items).expect("export list");
+        assert_eq!(exports.len(), 2);
+        assert!(exports.contains("clamp") && exports.contains("Nat"));
+        assert!(!exports.contains("helper"));
+
+        // export 宣言がなければ None（全 item 公開）
+        assert!(module_exports(&parse_module("atom f(x: i64) requires: true; ensures: true; body: x;").unwrap()).is_none());
+    }
+
     #[test]
     fn test_parse_max_unroll() {
         let source = r#"
@@ -1678,7 +3324,7 @@ requires: n >= 0;
 ensures: true;
 body: n;
 "#;
-        let items = parse_module(source);
+        let items = parse_module(source).unwrap();
         let atoms: Vec<_> = items.iter().filter_map(|i| {
             if let Item::Atom(a) = i { Some(a) } else { None }
         }).collect();
@@ -1696,7 +3342,7 @@ requires: state >= 0;
 ensures: result >= 0;
 body: state + 1;
 "#;
-        let items = parse_module(source);
+        let items = parse_module(source).unwrap();
         let atoms: Vec<_> = items.iter().filter_map(|i| {
             if let Item::Atom(a) = i { Some(a) } else { None }
         }).collect();
@@ -1708,6 +3354,94 @@ body: state + 1;
         assert_eq!(a.invariant, Some("state >= 0".to_string()));
     }
 
+    #[test]
+    fn test_parse_atom_decreases() {
+        let source = r#"
+atom go(n: i64, acc: i64)
+requires: n >= 0;
+ensures: result >= acc;
+decreases: n;
+body: {
+    let i = n;
+    while i > 0
+    invariant: i >= 0
+    decreases: i
+    {
+        i = i - 1;
+    };
+    if n == 0 { acc } else { go(n - 1, acc + n) }
+};
+
+atom plain(x: i64)
+requires: true;
+ensures: true;
+body: {
+    let i = x;
+    while i > 0 invariant: true decreases: i { i = i - 1; };
+    i
+};
+"#;
+        let items = parse_module(source).unwrap();
+        let decreases = |name: &str| items.iter().find_map(|i| match i {
+            Item::Atom(a) if a.name == name => Some(a.decreases.clone()),
+            _ => None,
+        }).unwrap();
+        assert_eq!(decreases("go"), Some("n".to_string()));
+        // body 内のループの decreases は atom の測度ではない
+        assert_eq!(decreases("plain"), None);
+    }
+
+    #[test]
+    fn test_parse_target_annotations() {
+        let source = r#"
+@only(rust, go)
+atom native_only(x: i64) requires: true; ensures: true; body: x;
+
+@exclude(ts) total atom no_ts(x: i64) requires: true; ensures: true; body: x;
+
+@only(llvm) @exclude(llvm)
+atom nowhere(x: i64) requires: true; ensures: true; body: x;
+
+@only(wasm)
+atom unknown(x: i64) requires: true; ensures: true; body: x;
+
+atom everywhere(x: i64) requires: true; ensures: true; body: x;
+"#;
+        let items = parse_module(source).unwrap();
+        let atom = |name: &str| find_atom(&items, name);
+        assert_eq!(items.len(), 5);
+
+        let native_only = atom("native_only");
+        assert_eq!(native_only.targets.effective(), vec!["rust", "go"]);
+        assert!(native_only.targets.emits_ir());
+
+        // 注釈の後ろの修飾子も従来どおり解釈される（ts は typescript の別名）
+        let no_ts = atom("no_ts");
+        assert!(no_ts.is_total);
+        assert_eq!(no_ts.targets.effective(), vec!["llvm", "rust", "go"]);
+
+        let nowhere = atom("nowhere");
+        assert!(nowhere.targets.effective().is_empty());
+        assert!(!nowhere.targets.emits_ir());
+
+        assert_eq!(atom("unknown").targets.unknown(), vec!["wasm"]);
+        assert_eq!(atom("everywhere").targets, AtomTargets::default());
+        assert_eq!(atom("everywhere").targets.effective(), ATOM_TARGETS.to_vec());
+    }
+
+    #[test]
+    fn test_parse_slice_type_ref() {
+        let t = parse_type_ref("[f64]");
+        assert_eq!(t, TypeRef::slice(TypeRef::simple("f64")));
+        assert_eq!(t.display_name(), "[f64]");
+        assert_eq!(crate::ast::slice_elem_type("[Nat]"), Some("Nat".to_string()));
+        assert_eq!(crate::ast::slice_elem_type("i64"), None);
+
+        // `[T]` は単相化で要素型が置換される
+        let map: HashMap<String, TypeRef> = [("T".to_string(), TypeRef::simple("i64"))].into_iter().collect();
+        assert_eq!(parse_type_ref("[T]").substitute(&map).display_name(), "[i64]");
+    }
+
     #[test]
     fn test_parse_ref_mut_param() {
         let source = r#"
@@ -1716,7 +3450,7 @@ requires: v >= 0;
 ensures: result >= 0;
 body: v + r;
 "#;
-        let items = parse_module(source);
+        let items = parse_module(source).unwrap();
         let atoms: Vec<_> = items.iter().filter_map(|i| {
             if let Item::Atom(a) = i { Some(a) } else { None }
         }).collect();
@@ -1734,12 +3468,32 @@ body: v + r;
         assert!(!a.params[1].is_ref_mut);
     }
 
+    #[test]
+    fn test_parse_ref_call_args() {
+        let expr = parse_expression("push(ref stack, item, ref mut log, a + 1)").unwrap();
+        let var = |n: &str| Box::new(Expr::Variable(n.to_string()));
+        assert_eq!(expr, Expr::Call("push".to_string(), vec![
+            Expr::RefArg { expr: var("stack"), mutable: false },
+            Expr::Variable("item".to_string()),
+            Expr::RefArg { expr: var("log"), mutable: true },
+            Expr::BinaryOp(var("a"), Op::Add, Box::new(Expr::Number(1))),
+        ]));
+        // マーカーは任意の式に付けられる（変数でなければ検証でエラーになる）
+        match &parse_expression("f(ref a + 1)").unwrap() {
+            Expr::Call(_, args) => {
+                assert!(matches!(&args[0], Expr::RefArg { mutable: false, .. }));
+                assert!(matches!(args[0].without_ref_marker(), Expr::BinaryOp(_, Op::Add, _)));
+            }
+            other => panic!("Expected Call, got {:?}", other),
+        }
+    }
+
     #[test]
     fn test_parse_await_expression() {
-        let expr = parse_expression("await x");
-        match expr {
+        let expr = parse_expression("await x").unwrap();
+        match &expr {
             Expr::Await { expr } => {
-                match *expr {
+                match **expr {
                     Expr::Variable(ref name) => assert_eq!(name, "x"),
                     _ => panic!("Expected Variable in await expr"),
                 }
@@ -1747,4 +3501,463 @@ body: v + r;
             _ => panic!("Expected Await expression, got {:?}", expr),
         }
     }
+
+    #[test]
+    fn test_parse_conditional_operator() {
+        // 比較より低く、== の右辺でも括弧内なら条件演算子になる
+        let expr = parse_expression("result == (a > b ? a : b)").unwrap();
+        match &expr {
+            Expr::BinaryOp(_, Op::Eq, rhs) => match &**rhs {
+                Expr::IfThenElse { cond, then_branch, else_branch } => {
+                    assert!(matches!(**cond, Expr::BinaryOp(_, Op::Gt, _)));
+                    assert!(matches!(**then_branch, Expr::Variable(ref v) if v == "a"));
+                    assert!(matches!(**else_branch, Expr::Variable(ref v) if v == "b"));
+                }
+                other => panic!("Expected IfThenElse, got {:?}", other),
+            },
+            other => panic!("Expected ==, got {:?}", other),
+        }
+
+        // 右結合: a ? b : c ? d : e == a ? b : (c ? d : e)。&& / || より低い優先順位
+        match &parse_expression("x > 0 && y > 0 ? 1 : y > 0 ? 2 : 3").unwrap() {
+            Expr::IfThenElse { cond, else_branch, .. } => {
+                assert!(matches!(**cond, Expr::BinaryOp(_, Op::And, _)));
+                assert!(matches!(**else_branch, Expr::IfThenElse { .. }));
+            }
+            other => panic!("Expected IfThenElse, got {:?}", other),
+        }
+
+        // 構造体リテラルのフィールド値にも書ける
+        match &parse_expression("Point { x: a < 0 ? 0 : a, y: 1 }").unwrap() {
+            Expr::StructInit { fields, .. } => {
+                assert_eq!(fields.len(), 2);
+                assert!(matches!(fields[0].1, Expr::IfThenElse { .. }));
+                assert!(matches!(fields[1].1, Expr::Number(1)));
+            }
+            other => panic!("Expected StructInit, got {:?}", other),
+        }
+
+        // `:` のない条件演算子はパースエラー（else 側を黙って補わない）
+        let err = parse_expression("c ? a b").unwrap_err();
+        assert!(err.to_string().contains("expected ':' in conditional expression"), "{}", err);
+        assert_eq!(err.offset, 6);
+    }
+
+    // =========================================================================
+    // 数値リテラル
+    // =========================================================================
+
+    #[test]
+    fn test_tokenize_numeric_literal_forms() {
+        let tokens = tokenize("0xFF + 0b1010 + 1_000_000 + 1_000.5");
+        assert_eq!(tokens, vec!["0xFF", "+", "0b1010", "+", "1_000_000", "+", "1_000.5"]);
+    }
+
+    #[test]
+    fn test_parse_string_literals() {
+        assert_eq!(parse_expression(r#""a \"b\"\n""#).unwrap(), Expr::StringLit("a \"b\"\n".to_string()));
+        assert_eq!(
+            parse_expression(r#"len("héllo")"#).unwrap(),
+            Expr::Call("len".to_string(), vec![Expr::StringLit("héllo".to_string())])
+        );
+    }
+
+    #[test]
+    fn test_parse_bool_literals() {
+        assert_eq!(parse_expression("false").unwrap(), Expr::Bool(false));
+        assert_eq!(
+            parse_expression("flag == true").unwrap(),
+            Expr::BinaryOp(Box::new(Expr::Variable("flag".to_string())), Op::Eq, Box::new(Expr::Bool(true)))
+        );
+        match &parse_expression("if true { 1 } else { 0 }").unwrap() {
+            Expr::IfThenElse { cond, .. } => assert_eq!(**cond, Expr::Bool(true)),
+            other => panic!("expected if, got {:?}", other),
+        }
+        // true / false で始まるだけの識別子は変数のまま
+        assert_eq!(parse_expression("trueish").unwrap(), Expr::Variable("trueish".to_string()));
+    }
+
+    #[test]
+    fn test_parse_test_blocks() {
+        let items = parse_module(r#"
+atom increment(x: i64) requires: x >= 0; ensures: result == x + 1; body: x + 1;
+
+test increment_basic {
+    assert increment(1) == 2;
+    assert (if increment(0) > 0 { 1 } else { 0 }) == 1;
+    increment(2);
+}
+"#).unwrap();
+        let test = items.iter().find_map(|i| if let Item::TestDef(t) = i { Some(t) } else { None }).unwrap();
+        assert_eq!(test.name, "increment_basic");
+        assert_eq!(test.assertions, vec!["increment(1) == 2", "(if increment(0) > 0 { 1 } else { 0 }) == 1"]);
+        assert_eq!(test.malformed, vec!["increment(2)"]);
+        // test ブロックの中身は atom として拾わない
+        assert_eq!(items.iter().filter(|i| matches!(i, Item::Atom(_))).count(), 1);
+    }
+
+    #[test]
+    fn test_parse_errors_carry_positions() {
+        let err = parse_expression("while i < n { i = i + 1 }").unwrap_err();
+        assert_eq!((err.message.as_str(), err.offset, err.len), ("Mumei loops require an 'invariant'", 0, 5));
+        let err = parse_expression("{ let }").unwrap_err();
+        assert_eq!((err.message.as_str(), err.line, err.column), ("expected a variable name after 'let'", 1, 7));
+
+        let err = parse_atom("atom g(x: i64) requires: true; ensures: true;").unwrap_err();
+        assert_eq!((err.message.as_str(), err.column), ("atom 'g' has no `body:`", 6));
+
+        // 契約の式の位置はモジュール上の行・列で返す
+        let err = parse_module("atom h(x: i64)\nrequires: if x > 0 { true };\nensures: true;\nbody: x;\n").unwrap_err();
+        assert_eq!((err.line, err.column), (2, 11));
+
+        // 本体のエラー。位置は除去したコメントの分を戻して数える
+        let source = "// header\natom f(x: i64)\nrequires: x >= 0;\nensures: result >= 0;\nbody: {\n    if x > 0 { x }\n};\n";
+        let err = parse_module(source).unwrap_err();
+        assert_eq!((err.message.as_str(), err.line, err.column), ("Mumei requires an 'else' branch", 6, 5));
+        assert_eq!(
+            err.render(source, "f.mm"),
+            "Parse error: Mumei requires an 'else' branch\n --> f.mm:6:5\n  |\n6 |     if x > 0 { x }\n  |     ^^"
+        );
+    }
+
+    #[test]
+    fn test_missing_operands_and_delimiters_are_errors() {
+        let error = |source: &str| parse_expression(source).map(|e| format!("{:?}", e)).unwrap_err();
+        // 式の途中で入力が終わっても 0 を補わない
+        let err = error("x +");
+        assert_eq!((err.message.as_str(), err.offset), ("expected an expression", 3));
+        assert_eq!(error("f(, x)").message, "expected an expression");
+        // 閉じ括弧の欠落は、欠けている位置（入力の終わり）を指す
+        let err = error("(x + 1");
+        assert_eq!((err.message.as_str(), err.offset), ("expected ')'", 6));
+        assert_eq!(error("f(x, y").message, "expected ')' to close the argument list");
+        assert_eq!(error("{ let y = 1; y").message, "expected '}' to close the block");
+        assert_eq!(error("arr[i").message, "expected ']'");
+        assert_eq!(error("Point { x: 1, y: 2").message, "expected '}' to close the struct literal");
+        assert_eq!(error("match x { 0 => 1, _ => 2").message, "expected '}' to close the match");
+
+        // atom の本体でも同じく位置付きのエラーになる
+        let err = parse_module("atom f(x: i64)\nrequires: true;\nensures: true;\nbody: x +;\n").unwrap_err();
+        assert_eq!((err.message.as_str(), err.line), ("expected an expression", 4));
+    }
+
+    #[test]
+    fn test_parse_hex_binary_underscore_literals() {
+        assert!(matches!(parse_expression("0xFF").unwrap(), Expr::Number(255)));
+        assert!(matches!(parse_expression("0x7fff_ffff").unwrap(), Expr::Number(2147483647)));
+        assert!(matches!(parse_expression("0b1010").unwrap(), Expr::Number(10)));
+        assert!(matches!(parse_expression("1_000_000").unwrap(), Expr::Number(1000000)));
+        assert!(matches!(parse_expression("007").unwrap(), Expr::Number(7)));
+        match parse_expression("1_000.5").unwrap() {
+            Expr::Float(f) => assert_eq!(f, 1000.5),
+            other => panic!("Expected Float, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_i64_min_via_unary_minus() {
+        assert!(matches!(parse_expression("-9223372036854775808").unwrap(), Expr::Number(i64::MIN)));
+        assert!(matches!(parse_expression("9223372036854775807").unwrap(), Expr::Number(i64::MAX)));
+        assert!(validate_literals("x >= -9223372036854775808 && x <= 9223372036854775807").is_ok());
+        // 単項マイナスはリテラル以外では `0 - expr` に脱糖される
+        match &parse_expression("-x").unwrap() {
+            Expr::BinaryOp(lhs, Op::Sub, rhs) => {
+                assert!(matches!(**lhs, Expr::Number(0)));
+                assert!(matches!(**rhs, Expr::Variable(ref v) if v == "x"));
+            }
+            other => panic!("Expected 0 - x, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_literal_forms_in_contracts_and_bodies() {
+        let source = r#"
+atom mask(x: i64)
+    requires: x >= 0x0 && x <= 1_000;
+    ensures: result >= 0 && result <= 0b1111;
+    body: {
+        let m = 0x0F;
+        x - x + m
+    };
+"#;
+        let items = parse_module(source).unwrap();
+        let atom = items.iter().find_map(|i| match i { Item::Atom(a) if a.name == "mask" => Some(a), _ => None }).unwrap();
+        assert!(validate_literals(&atom.requires).is_ok());
+        assert!(validate_literals(&atom.ensures).is_ok());
+        assert!(validate_literals(&atom.body_expr).is_ok());
+        match &parse_expression(&atom.ensures).unwrap() {
+            Expr::BinaryOp(_, Op::And, rhs) => assert!(matches!(**rhs, Expr::BinaryOp(_, Op::Le, ref r) if matches!(**r, Expr::Number(15)))),
+            other => panic!("Expected conjunction, got {:?}", other),
+        }
+        match &parse_expression("match x { -9223372036854775808 => 0, 0x10 => 1, _ => 2 }").unwrap() {
+            Expr::Match { arms, .. } => {
+                assert!(matches!(arms[0].pattern, Pattern::Literal(i64::MIN)));
+                assert!(matches!(arms[1].pattern, Pattern::Literal(16)));
+            }
+            other => panic!("Expected Match, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_integer_literal_overflow_is_reported() {
+        let err = validate_literals("x + 9223372036854775808").unwrap_err();
+        assert!(err.contains("'9223372036854775808'"), "{}", err);
+        assert!(err.contains("-9223372036854775808..=9223372036854775807"), "{}", err);
+        // 二項演算の右辺は単項マイナスではないため、絶対値 2^63 は範囲外
+        assert!(validate_literals("x - 9223372036854775808").is_err());
+        assert!(validate_literals("-0x8000_0000_0000_0001").is_err());
+        assert!(validate_literals("0xFFFF_FFFF_FFFF_FFFF_FFFF_FFFF_FFFF_FFFF_FFFF").is_err());
+        // パーサ自体は panic しない
+        let _ = parse_expression("99999999999999999999999").unwrap();
+    }
+
+    // =========================================================================
+    // 契約の表示用テキスト
+    // =========================================================================
+
+    #[test]
+    fn test_multiline_requires_keeps_raw_and_normalized() {
+        let source = "atom clamp(x: i64, lo: i64, hi: i64)\nrequires:\n    lo <= hi &&\n    x >= lo - 100 &&   // 余裕を持たせる\n    x <= hi + 100;\nensures: result >= lo;\nbody: lo;\n";
+        let items = parse_module(source).unwrap();
+        let atom = match &items[0] { Item::Atom(a) => a, other => panic!("Expected atom, got {:?}", other) };
+        assert_eq!(atom.requires_raw, "lo <= hi &&\nx >= lo - 100 &&\nx <= hi + 100");
+        assert_eq!(atom.requires, "lo <= hi && x >= lo - 100 && x <= hi + 100");
+        assert_eq!(atom.ensures_raw, "result >= lo");
+    }
+
+    #[test]
+    fn test_multiple_ensures_clauses() {
+        let source = "atom inc(n: i64)\nrequires: n >= 0;\nensures: result > n;\nensures: result == n + 1;\nbody: n + 1;\n";
+        let items = parse_module(source).unwrap();
+        let atom = match &items[0] { Item::Atom(a) => a, other => panic!("Expected atom, got {:?}", other) };
+        assert_eq!(atom.ensures, "(result > n) && (result == n + 1)");
+        assert_eq!(atom.ensures_raw, "- result > n\n- result == n + 1");
+        assert!(matches!(parse_expression(&atom.ensures).unwrap(), Expr::BinaryOp(_, Op::And, _)));
+    }
+
+    const LF_MODULE: &str = "import \"./lib/math.mm\" as math;\ntype Nat = i64 where v >= 0;\nstruct Point { x: i64 where v >= 0, y: Nat }\ntrait Monoid extends Semigroup with\n    add = combine {\n    fn unit() -> Self;\n    fn div(a: Self, b: Self where v != 0) -> Self;\n    law left_identity: combine(unit(), a) == a;\n}\nimpl Monoid for i64 {\n    fn unit() -> i64 { 0 }\n}\natom clamp(x: Nat, lo: i64, hi: i64)\nrequires:\n    lo <= hi &&   // comment\n    x >= lo;\nensures: result >= lo;\nbody: {\n    let y = x;\n    y\n};\n";
+
+    #[test]
+    fn test_normalize_source() {
+        assert_eq!(normalize_source("\u{feff}type A = i64 where v > 0;\r\n"), "type A = i64 where v > 0;\n");
+        assert_eq!(normalize_source("a\rb\r\nc\n"), "a\nb\nc\n");
+        // CRLF / BOM 付きでも正規化後のテキスト（＝キャッシュハッシュの入力）は LF 版と一致する
+        let crlf = format!("\u{feff}{}", LF_MODULE.replace('\n', "\r\n"));
+        assert_eq!(normalize_source(&crlf), LF_MODULE);
+        assert_eq!(normalize_source(LF_MODULE), LF_MODULE);
+    }
+
+    #[test]
+    fn test_parse_crlf_module_matches_lf() {
+        // 正規化を経ずに parse_module に渡された CRLF / BOM 付きソースも LF 版と同じ結果になる
+        let crlf = format!("\u{feff}{}", LF_MODULE.replace('\n', "\r\n"));
+        let lf_items = parse_module(LF_MODULE).unwrap();
+        let crlf_items = parse_module(&crlf).unwrap();
+        assert_eq!(format!("{:?}", crlf_items), format!("{:?}", lf_items));
+
+        let mut saw = (false, false, false, false);
+        for item in &crlf_items {
+            match item {
+                Item::Import(i) => {
+                    assert_eq!(i.path, "./lib/math.mm");
+                    assert_eq!(i.alias.as_deref(), Some("math"));
+                    saw.0 = true;
+                }
+                Item::TypeDef(t) => {
+                    assert_eq!(t.name, "Nat");
+                    assert_eq!(t._base_type, "i64");
+                    saw.1 = true;
+                }
+                Item::StructDef(s) => assert_eq!(s.fields[1].type_name, "Nat"),
+                Item::TraitDef(t) => {
+                    assert_eq!(t.extends[0].0, "Semigroup");
+                    assert_eq!(t.extends[0].1.get("add").map(String::as_str), Some("combine"));
+                    assert_eq!(t.methods[1].param_types, vec!["Self", "Self"]);
+                    assert_eq!(t.methods[1].param_constraints[1].as_deref(), Some("v != 0"));
+                    assert_eq!(t.laws[0], ("left_identity".to_string(), "combine(unit(), a) == a".to_string()));
+                    saw.2 = true;
+                }
+                Item::Atom(a) => {
+                    assert_eq!(a.params[0].type_name.as_deref(), Some("Nat"));
+                    assert_eq!(a.requires, "lo <= hi && x >= lo");
+                    assert_eq!(a.requires_raw, "lo <= hi &&\nx >= lo");
+                    assert_eq!(a.ensures, "result >= lo");
+                    assert!(!a.body_expr.contains('\r'));
+                    saw.3 = true;
+                }
+                _ => {}
+            }
+        }
+        assert_eq!(saw, (true, true, true, true));
+    }
+
+    #[test]
+    fn test_parse_import_using() {
+        let items = parse_module("import \"math\" using {add, Vec2};\nimport \"./geo.mm\" as geo using {\n    area,\n};\nimport \"std/list\";\n").unwrap();
+        let imports: Vec<&ImportDecl> = items.iter().filter_map(|i| if let Item::Import(d) = i { Some(d) } else { None }).collect();
+        assert_eq!(imports.len(), 3);
+        assert_eq!((imports[0].path.as_str(), imports[0].alias.as_deref()), ("math", None));
+        assert_eq!(imports[0].using, Some(vec!["add".to_string(), "Vec2".to_string()]));
+        assert_eq!(imports[1].alias.as_deref(), Some("geo"));
+        assert_eq!(imports[1].using, Some(vec!["area".to_string()]));
+        assert_eq!(imports[2].using, None);
+    }
+
+    #[test]
+    fn test_parse_assume() {
+        // 正当化文は文字列リテラルで、中の `//` や `,` はコメント・区切りとして扱わない
+        let expr = parse_expression("{ assume(len(buf) >= 4, \"see https://example.com/spec, 4.2\"); 0 }").unwrap();
+        let mut assumptions = Vec::new();
+        collect_assumptions(&expr, &mut assumptions);
+        assert_eq!(assumptions, vec![Assumption {
+            condition: "len(buf) >= 4".to_string(),
+            justification: "see https://example.com/spec, 4.2".to_string(),
+        }]);
+
+        let items = parse_module("atom f(x: i64)\nrequires: true;\nensures: true;\nbody: {\n    assume(x > 0);\n    x\n};\n").unwrap();
+        let atom = match &items[0] { Item::Atom(a) => a, _ => panic!("expected atom") };
+        let err = atom_assumptions(atom).unwrap_err();
+        assert!(err.starts_with("Parse error in atom 'f': assume(x > 0) requires a justification string"), "{}", err);
+    }
+
+    #[test]
+    fn test_atom_text_inside_impl_is_not_an_item() {
+        // impl のメソッド本体に現れる `atom push ...` はトップレベルの atom として拾わない
+        let source = "trait Stack {\n    fn push(a: Self, b: Self) -> Self;\n}\nimpl Stack for i64 {\n    fn push(a: i64, b: i64) -> i64 { atom push(a) requires: true; ensures: true; body: a; }\n}\natom use_stack(x: i64)\nrequires: true;\nensures: true;\nbody: x;\n";
+        let items = parse_module(source).unwrap();
+        let names: Vec<String> = items.iter().map(|i| match i {
+            Item::TraitDef(t) => format!("trait {}", t.name),
+            Item::ImplDef(i) => format!("impl {} for {}", i.trait_name, i.target_type),
+            Item::Atom(a) => format!("atom {}", a.name),
+            _ => "other".to_string(),
+        }).collect();
+        assert_eq!(names, vec!["trait Stack", "impl Stack for i64", "atom use_stack"]);
+
+        let atom = items.iter().find_map(|i| match i {
+            Item::Atom(a) => Some(a),
+            _ => None,
+        }).unwrap();
+        assert_eq!(atom.body_expr, "x");
+        // explain_parse も同じ区間割り当てを使うため、impl 内の文字列は impl の一部になる
+        let kinds: Vec<String> = explain_parse(source).claimed.iter().map(|r| r.kind.clone()).collect();
+        assert_eq!(kinds, vec!["trait", "impl", "atom"]);
+    }
+
+    #[test]
+    fn test_explain_parse_reports_unclaimed_struct() {
+        // `{` を書き忘れた struct は struct パターンにマッチせず、黙って無視される
+        let source = "type Nat = i64 where v >= 0;\n\nstruct Point {\n    x: i64,\n    y: i64\n}\n\nstruct Pair\n    a: i64,\n    b: i64\n}\n// helpers\natom sum(p: i64)\nrequires: p >= 0;\nensures: result >= 0;\nbody: p;\n";
+        assert!(!parse_module(source).unwrap().iter().any(|i| matches!(i, Item::StructDef(s) if s.name == "Pair")));
+
+        let explanation = explain_parse(source);
+        let claimed: Vec<(&str, &str, usize, usize)> = explanation.claimed.iter()
+            .map(|r| (r.kind.as_str(), r.name.as_str(), r.start_line, r.end_line))
+            .collect();
+        assert_eq!(claimed, vec![
+            ("type", "Nat", 1, 1),
+            ("struct", "Point", 3, 6),
+            ("atom", "sum", 13, 16),
+        ]);
+
+        // コメント行は unclaimed に含めない
+        assert_eq!(explanation.unclaimed.len(), 1, "{:?}", explanation.unclaimed);
+        let region = &explanation.unclaimed[0];
+        assert_eq!((region.start_line, region.end_line), (8, 11));
+        assert_eq!(region.snippet, "struct Pair");
+    }
+
+    #[test]
+    fn test_explain_parse_atom_span_stops_at_body() {
+        // atom は次の atom の手前までではなく body の終わりまでを消費する
+        let source = "trusted atom f(x: i64)\nrequires: true;\nensures: true;\nbody: {\n    x\n};\nresource db priority: 1 mode: exclusive\natom g(x: i64) requires: true; ensures: true; body: x;\n";
+        let explanation = explain_parse(source);
+        assert_eq!(explanation.claimed[0].kind, "trusted atom");
+        assert_eq!((explanation.claimed[0].start_line, explanation.claimed[0].end_line), (1, 6));
+        // 末尾の `;` がない resource は unclaimed として残る
+        assert_eq!(explanation.unclaimed.len(), 1, "{:?}", explanation.unclaimed);
+        assert_eq!(explanation.unclaimed[0].start_line, 7);
+        assert!(explanation.unclaimed[0].snippet.starts_with("resource db"));
+    }
+
+    #[test]
+    fn test_strip_comments_borrows_when_possible() {
+        // コメントも CR もないソースはコピーしない
+        let plain = "atom f(x: i64) requires: true; ensures: true; body: x;\n";
+        assert!(matches!(normalized_source(plain), Cow::Borrowed(_)));
+        assert!(matches!(strip_comments(plain, false), Cow::Borrowed(_)));
+
+        // 文字列リテラル内の `//` は残し、keep_offsets ではバイト位置を保つ
+        let commented = "import \"http://x\"; // note\nbody: x; // 説明\n";
+        assert_eq!(strip_comments(commented, false), "import \"http://x\"; \nbody: x; \n");
+        let kept = strip_comments(commented, true);
+        assert_eq!(kept.len(), commented.len());
+        assert_eq!(kept.find("body"), commented.find("body"));
+    }
+
+    #[test]
+    fn test_deep_left_chain() {
+        // 10 万項の `x + x + ... + x` は左に連なる BinaryOp の連鎖になる。
+        let source = vec!["x"; 100_000].join(" + ");
+        let expr = parse_expression(&source).unwrap();
+        let (leaf, nodes) = expr.left_spine();
+        assert_eq!(*leaf, Expr::Variable("x".into()));
+        assert_eq!(nodes.len(), 99_999);
+        assert!(std::ptr::eq(nodes[nodes.len() - 1], &expr));
+        assert!(nodes.iter().all(|node| matches!(node, Expr::BinaryOp(_, Op::Add, r) if **r == Expr::Variable("x".into()))));
+    }
+
+    // =========================================================================
+    // メモリベンチマーク（通常のテストでは実行しない）
+    //   cargo test --release bench_parse_module_peak_memory -- --ignored --nocapture --test-threads=1
+    // =========================================================================
+
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// 確保中のバイト数とその最大値を数えるアロケータ（テストバイナリでのみ使う）
+    struct PeakAlloc;
+    static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+    static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+    unsafe impl GlobalAlloc for PeakAlloc {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            let ptr = System.alloc(layout);
+            if !ptr.is_null() {
+                let now = ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+                PEAK.fetch_max(now, Ordering::Relaxed);
+            }
+            ptr
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            System.dealloc(ptr, layout);
+            ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+        }
+    }
+
+    #[global_allocator]
+    static GLOBAL: PeakAlloc = PeakAlloc;
+
+    #[test]
+    #[ignore]
+    fn bench_parse_module_peak_memory() {
+        // 機械生成の契約を模した大きなモジュール（約 8 MB、行コメント付き）
+        let atom = "// generated contract\natom f{i}(x: i64, y: i64)\nrequires: x >= 0 && y >= 0;\nensures: result >= x && result >= y;\nbody: {\n    let s = x + y; // sum\n    s\n};\n\n";
+        let source: String = (0..60_000).map(|i| atom.replace("{i}", &i.to_string())).collect();
+
+        let baseline = ALLOCATED.load(Ordering::Relaxed);
+        PEAK.store(baseline, Ordering::Relaxed);
+        let started = std::time::Instant::now();
+        let items = parse_module(&source).unwrap();
+        let elapsed = started.elapsed();
+        let peak = PEAK.load(Ordering::Relaxed) - baseline;
+        let retained = ALLOCATED.load(Ordering::Relaxed) - baseline;
+
+        assert_eq!(items.len(), 60_000);
+        println!(
+            "parse_module: source {:.1} MB, peak {:.1} MB ({:.2}x source), retained {:.1} MB, {} ms",
+            source.len() as f64 / 1e6, peak as f64 / 1e6, peak as f64 / source.len() as f64,
+            retained as f64 / 1e6, elapsed.as_millis()
+        );
+    }
 }
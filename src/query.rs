@@ -0,0 +1,283 @@
+// =============================================================================
+// mumei query: ビルドシステム連携用のモジュール情報（検証なし）
+// =============================================================================
+//
+// Bazel 等のルールが .mm ファイルごとの依存関係と atom の契約を知るためのコマンド。
+// パースと import パスの解決だけを行い、Z3 検証・ModuleEnv への登録・キャッシュの
+// 書き込みはしない（推移閉包は resolver の読み取り専用モードで走査する）。
+//
+// 出力はキャッシュキーに使えるよう安定させる:
+// - パスは解決済みの絶対パス、リストは名前（パス）順にソート
+// - オブジェクトのキーは serde_json::Map（BTreeMap）によりソート済み
+
+use crate::parser::{self, Atom, Item};
+use crate::resolver;
+use crate::verification::MumeiResult;
+use serde_json::{json, Value};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+/// 出力するサブクエリ（どれも指定されなければ全部）
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Subqueries {
+    pub imports: bool,
+    pub atoms: bool,
+    pub types: bool,
+    pub deps_closure: bool,
+}
+
+impl Subqueries {
+    pub fn all() -> Self {
+        Self { imports: true, atoms: true, types: true, deps_closure: true }
+    }
+
+    fn or_all(self) -> Self {
+        if self == Self::default() { Self::all() } else { self }
+    }
+}
+
+/// 直接の import を解決済みの絶対パスで返す（import 先は読み込まない）
+pub fn direct_imports(items: &[Item], base_dir: &Path) -> MumeiResult<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+    for item in items {
+        if let Item::Import(import) = item {
+            paths.push(resolver::resolve_path(&import.path, base_dir)?);
+        }
+    }
+    paths.sort();
+    paths.dedup();
+    Ok(paths)
+}
+
+fn path_list(paths: &[PathBuf]) -> Value {
+    json!(paths.iter().map(|p| p.display().to_string()).collect::<Vec<_>>())
+}
+
+fn atom_json(atom: &Atom, exported: bool) -> Value {
+    let params: Vec<Value> = atom.params.iter()
+        .map(|p| json!({ "name": p.name, "type": p.type_name }))
+        .collect();
+    json!({
+        "name": atom.name,
+        "params": params,
+        "where": atom.where_clause,
+        "requires": atom.requires_raw,
+        "ensures": atom.ensures_raw,
+        "hash": resolver::compute_atom_hash(atom),
+        "async": atom.is_async,
+        "resources": atom.resources,
+        "exported": exported,
+    })
+}
+
+/// モジュールが定義する atom（名前順）
+pub fn atoms(items: &[Item]) -> Value {
+    let exports = parser::module_exports(items);
+    let is_exported = |name: &str| exports.as_ref().map_or(true, |e: &HashSet<String>| e.contains(name));
+    let mut atoms: Vec<&Atom> = items.iter().filter_map(|item| match item {
+        Item::Atom(atom) => Some(atom),
+        _ => None,
+    }).collect();
+    atoms.sort_by(|a, b| a.name.cmp(&b.name));
+    json!(atoms.into_iter().map(|a| atom_json(a, is_exported(&a.name))).collect::<Vec<_>>())
+}
+
+/// モジュールが定義する型（精緻型・struct・enum、名前順）
+pub fn types(items: &[Item]) -> Value {
+    let exports = parser::module_exports(items);
+    let is_exported = |name: &str| exports.as_ref().map_or(true, |e: &HashSet<String>| e.contains(name));
+    let mut types: Vec<(String, Value)> = Vec::new();
+    for item in items {
+        let entry = match item {
+            Item::TypeDef(t) => json!({
+                "kind": "refined",
+                "name": t.name,
+                "base": t._base_type,
+                "predicate": t.predicate_raw,
+                "exported": is_exported(&t.name),
+            }),
+            Item::StructDef(s) => json!({
+                "kind": "struct",
+                "name": s.name,
+                "type_params": s.type_params,
+                "fields": s.fields.iter().map(|f| json!({ "name": f.name, "type": f.type_name, "constraint": f.constraint })).collect::<Vec<_>>(),
+                "invariant": s.invariant,
+                "exported": is_exported(&s.name),
+            }),
+            Item::EnumDef(e) => json!({
+                "kind": "enum",
+                "name": e.name,
+                "type_params": e.type_params,
+                "variants": e.variants.iter().map(|v| json!({ "name": v.name, "fields": v.fields })).collect::<Vec<_>>(),
+                "exported": is_exported(&e.name),
+            }),
+            _ => continue,
+        };
+        let name = entry["name"].as_str().unwrap_or_default().to_string();
+        types.push((name, entry));
+    }
+    types.sort_by(|a, b| a.0.cmp(&b.0));
+    json!(types.into_iter().map(|(_, v)| v).collect::<Vec<_>>())
+}
+
+/// path の .mm ファイルについて、指定されたサブクエリの結果を 1 つの JSON オブジェクトにまとめる
+pub fn run(path: &Path, subqueries: Subqueries) -> MumeiResult<Value> {
+    let io_error = |e: std::io::Error| crate::verification::MumeiError::VerificationError(format!("Could not read '{}': {}", path.display(), e));
+    let path = path.canonicalize().map_err(io_error)?;
+    let source = resolver::read_source(&path).map_err(io_error)?;
+    let items = resolver::parse_source(&source, &path)?;
+    let base_dir = path.parent().unwrap_or(Path::new("."));
+    let subqueries = subqueries.or_all();
+
+    let mut out = json!({ "file": path.display().to_string() });
+    if subqueries.imports {
+        out["imports"] = path_list(&direct_imports(&items, base_dir)?);
+    }
+    if subqueries.atoms {
+        out["atoms"] = atoms(&items);
+    }
+    if subqueries.types {
+        out["types"] = types(&items);
+    }
+    if subqueries.deps_closure {
+        out["deps_closure"] = path_list(&resolver::import_closure(&items, base_dir)?);
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+    use std::fs;
+
+    const MAIN: &str = r#"import "./lib/math.mm" as math;
+import "./lib/shapes.mm";
+
+export { area, Size };
+
+type Size = i64 where v >= 0;
+
+atom area(w: Size, h: Size)
+requires: w >= 0 && h >= 0;
+ensures: result >= 0;
+body: math::mul(w, h);
+
+async atom helper(x: i64)
+where: x >= 0;
+resources: [db];
+requires: true;
+ensures: result == x;
+body: x;
+"#;
+
+    const MATH: &str = "import \"./shapes.mm\";\natom mul(a: i64, b: i64) requires: a >= 0 && b >= 0; ensures: result >= 0; body: a * b;\n";
+    const SHAPES: &str = "struct Point { x: i64, y: i64 where v >= 0 }\nenum Shape { Dot, Box(i64, i64) }\n";
+
+    /// main.mm と lib/ を書いた一時ディレクトリと、その正規化したパス
+    fn fixture(name: &str) -> (TempDir, PathBuf) {
+        let temp = TempDir::new(&format!("query_{}", name));
+        fs::create_dir_all(temp.join("lib")).unwrap();
+        fs::write(temp.join("main.mm"), MAIN).unwrap();
+        fs::write(temp.join("lib").join("math.mm"), MATH).unwrap();
+        fs::write(temp.join("lib").join("shapes.mm"), SHAPES).unwrap();
+        let dir = temp.canonicalize().unwrap();
+        (temp, dir)
+    }
+
+    #[test]
+    fn test_query_json_contents() {
+        let (_temp, dir) = fixture("all");
+        let out = run(&dir.join("main.mm"), Subqueries::default()).unwrap();
+
+        let items = parser::parse_module(MAIN).unwrap();
+        let hash = |name: &str| items.iter().find_map(|i| match i {
+            Item::Atom(a) if a.name == name => Some(resolver::compute_atom_hash(a)),
+            _ => None,
+        }).unwrap();
+        let lib = |file: &str| dir.join("lib").join(file).display().to_string();
+
+        let expected = json!({
+            "file": dir.join("main.mm").display().to_string(),
+            "imports": [lib("math.mm"), lib("shapes.mm")],
+            "atoms": [
+                {
+                    "name": "area",
+                    "params": [{ "name": "w", "type": "Size" }, { "name": "h", "type": "Size" }],
+                    "where": null,
+                    "requires": "w >= 0 && h >= 0",
+                    "ensures": "result >= 0",
+                    "hash": hash("area"),
+                    "async": false,
+                    "resources": [],
+                    "exported": true,
+                },
+                {
+                    "name": "helper",
+                    "params": [{ "name": "x", "type": "i64" }],
+                    "where": "x >= 0",
+                    "requires": "true",
+                    "ensures": "result == x",
+                    "hash": hash("helper"),
+                    "async": true,
+                    "resources": ["db"],
+                    "exported": false,
+                },
+            ],
+            "types": [
+                { "kind": "refined", "name": "Size", "base": "i64", "predicate": "v >= 0", "exported": true },
+            ],
+            "deps_closure": [lib("math.mm"), lib("shapes.mm")],
+        });
+        assert_eq!(out, expected);
+
+        // 同じ入力なら出力は同一（キャッシュキーに使える）
+        let again = run(&dir.join("main.mm"), Subqueries::all()).unwrap();
+        assert_eq!(serde_json::to_string(&out).unwrap(), serde_json::to_string(&again).unwrap());
+
+        // 読み取り専用: 検証キャッシュを書き込まない
+        assert!(!dir.join(".mumei_cache").exists());
+        assert!(!dir.join("lib").join(".mumei_cache").exists());
+    }
+
+    #[test]
+    fn test_query_subqueries_and_types() {
+        let (_temp, dir) = fixture("types");
+        let out = run(&dir.join("lib").join("shapes.mm"), Subqueries { types: true, ..Subqueries::default() }).unwrap();
+        assert_eq!(out, json!({
+            "file": dir.join("lib").join("shapes.mm").display().to_string(),
+            "types": [
+                {
+                    "kind": "struct",
+                    "name": "Point",
+                    "type_params": [],
+                    "fields": [
+                        { "name": "x", "type": "i64", "constraint": null },
+                        { "name": "y", "type": "i64", "constraint": "v >= 0" },
+                    ],
+                    "invariant": null,
+                    "exported": true,
+                },
+                {
+                    "kind": "enum",
+                    "name": "Shape",
+                    "type_params": [],
+                    "variants": [{ "name": "Dot", "fields": [] }, { "name": "Box", "fields": ["i64", "i64"] }],
+                    "exported": true,
+                },
+            ],
+        }));
+
+        // 直接の import は読み込まずに解決し、推移閉包は import 先の import も含む
+        let out = run(&dir.join("lib").join("math.mm"), Subqueries { imports: true, deps_closure: true, ..Subqueries::default() }).unwrap();
+        let shapes = dir.join("lib").join("shapes.mm").display().to_string();
+        assert_eq!(out["imports"], json!([shapes]));
+        assert_eq!(out["deps_closure"], json!([shapes]));
+        assert!(out.get("atoms").is_none());
+
+        // 解決できない import はエラー
+        fs::write(dir.join("broken.mm"), "import \"./missing.mm\";\n").unwrap();
+        let err = run(&dir.join("broken.mm"), Subqueries::all()).unwrap_err();
+        assert!(err.to_string().contains("Cannot resolve import path './missing.mm'"), "{}", err);
+    }
+}
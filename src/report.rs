@@ -0,0 +1,767 @@
+// =============================================================================
+// 検証レポート (report.json) の公開スキーマ
+// =============================================================================
+//
+// cmd_verify / cmd_build と、atom 単位の検証（LSP を含む
+// verification::save_visualizer_report 経由）が書き出す report.json の形を
+// serde 構造体として定義する。ダッシュボード等の外部ツールが依存するため、
+// 以下の互換性ルールを守ること:
+//
+// - schema_version は "MAJOR.MINOR"。同一 MAJOR 内では追加のみ行う
+//   （フィールドの削除・改名・意味の変更は MAJOR を上げる）。
+// - 追加するフィールドには #[serde(default)] を付け、古いレポートも読めるようにする。
+// - 未知のフィールドは無視する（deny_unknown_fields は使わない）。
+// - 列挙値の追加も MINOR で行える。古いリーダーは `unknown` として読む。
+//
+// `mumei report` はこのスキーマを読み込み、人間向けのサマリーや
+// 2 つの実行結果の差分（新たに失敗 / 新たに成功した atom）を表示する。
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+/// 現在のスキーマバージョン（MAJOR.MINOR）
+pub const SCHEMA_VERSION: &str = "1.7";
+
+/// レポートのファイル名（出力ディレクトリ直下）
+pub const REPORT_FILE: &str = "report.json";
+
+/// 検証対象 1 件分の結果
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum AtomStatus {
+    Verified,
+    Failed,
+    /// 変更なし（キャッシュ）・インポート済み・verify=false などで検証しなかった
+    #[default]
+    Skipped,
+    Trusted,
+    Unverified,
+    /// 検証済みだが body の `assume(...)` を仮定している（1.5 で追加。仮定は AtomResult::assumptions）
+    #[serde(rename = "verified-with-assumptions")]
+    VerifiedWithAssumptions,
+    #[serde(other)]
+    Unknown,
+}
+
+impl AtomStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AtomStatus::Verified => "verified",
+            AtomStatus::Failed => "failed",
+            AtomStatus::Skipped => "skipped",
+            AtomStatus::Trusted => "trusted",
+            AtomStatus::Unverified => "unverified",
+            AtomStatus::VerifiedWithAssumptions => "verified-with-assumptions",
+            AtomStatus::Unknown => "unknown",
+        }
+    }
+}
+
+/// 結果の種別（atom か trait impl の law 検証か）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ItemKind {
+    #[default]
+    Atom,
+    Impl,
+    #[serde(other)]
+    Unknown,
+}
+
+/// 検証失敗のカテゴリ（1.7 で追加）。検証器が失敗した箇所で決め、SARIF の rule id にもなる
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum FailureKind {
+    Postcondition,
+    Precondition,
+    Law,
+    Exhaustiveness,
+    Linearity,
+    Termination,
+    Totality,
+    LoopInvariant,
+    Overflow,
+    DivisionByZero,
+    Bounds,
+    Presence,
+    Refinement,
+    Resource,
+    SolverLimit,
+    /// 上のどれにも当たらない失敗（型エラー・未対応の構文など）
+    #[default]
+    #[serde(other)]
+    Verification,
+}
+
+impl FailureKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FailureKind::Postcondition => "postcondition",
+            FailureKind::Precondition => "precondition",
+            FailureKind::Law => "law",
+            FailureKind::Exhaustiveness => "exhaustiveness",
+            FailureKind::Linearity => "linearity",
+            FailureKind::Termination => "termination",
+            FailureKind::Totality => "totality",
+            FailureKind::LoopInvariant => "loop-invariant",
+            FailureKind::Overflow => "overflow",
+            FailureKind::DivisionByZero => "division-by-zero",
+            FailureKind::Bounds => "bounds",
+            FailureKind::Presence => "presence",
+            FailureKind::Refinement => "refinement",
+            FailureKind::Resource => "resource",
+            FailureKind::SolverLimit => "solver-limit",
+            FailureKind::Verification => "verification",
+        }
+    }
+}
+
+/// 検証結果の出所
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum CacheSource {
+    /// 今回 Z3 で検証した（義務単位で再利用した分は obligations_reused に計上）
+    #[default]
+    Fresh,
+    /// atom ハッシュが一致し、ビルドキャッシュの結果を使った
+    BuildCache,
+    /// インポートされた atom（契約のみ信頼）
+    Imported,
+    #[serde(other)]
+    Unknown,
+}
+
+/// モジュール全体で検証を実行したか（1.1 で追加）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum VerificationMode {
+    /// 通常どおり検証した（atom ごとの結果は atoms[].status を見る）
+    #[default]
+    Performed,
+    /// mumei.toml の `[build] verify = false` により検証自体を行わなかった
+    Skipped,
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    Error,
+    #[default]
+    Warning,
+    Info,
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+pub struct ToolInfo {
+    #[serde(default)]
+    pub mumei_version: String,
+    #[serde(default)]
+    pub z3_version: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+pub struct ModuleInfo {
+    /// 入力ファイルのパス（atom 単位のレポートでは空）
+    #[serde(default)]
+    pub path: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+pub struct CacheInfo {
+    #[serde(default)]
+    pub source: CacheSource,
+    #[serde(default)]
+    pub obligations_reused: usize,
+    #[serde(default)]
+    pub obligations_solved: usize,
+}
+
+/// 証明済みの義務（キーは "種別:hash"）
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+pub struct Obligation {
+    pub kind: String,
+    pub key: String,
+}
+
+impl Obligation {
+    pub fn from_key(key: &str) -> Self {
+        let kind = key.split(':').next().unwrap_or("").to_string();
+        Obligation { kind, key: key.to_string() }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+pub struct AtomResult {
+    pub name: String,
+    #[serde(default)]
+    pub kind: ItemKind,
+    #[serde(default)]
+    pub status: AtomStatus,
+    #[serde(default)]
+    pub duration_ms: u64,
+    #[serde(default)]
+    pub cache: CacheInfo,
+    #[serde(default)]
+    pub obligations: Vec<Obligation>,
+    /// `total atom` として宣言されている（1.2 で追加。status が verified なら totality も証明済み）
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub total: bool,
+    /// atom の `where:` 節（1.3 で追加。検証では requires の一部として扱われる）
+    #[serde(rename = "where", default, skip_serializing_if = "Option::is_none")]
+    pub where_clause: Option<String>,
+    /// atom を出力するターゲット（1.4 で追加。`@only` / `@exclude` 注釈を反映した llvm / rust / go / typescript の部分集合）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub targets: Option<Vec<String>>,
+    /// body の `assume(cond, "justification")` で仮定した事実（1.5 で追加。出現順）
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub assumptions: Vec<Assumption>,
+    /// Z3 の時間を最も消費した義務（1.6 で追加。所要時間の降順で最大 PROFILE_TOP 件）
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub slowest_obligations: Vec<ObligationTiming>,
+    /// 失敗のカテゴリ（1.7 で追加。failed のときだけ）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub failure: Option<FailureKind>,
+    /// 失敗理由などのメッセージ
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    /// `--counterexample-format json` の反例オブジェクト
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub counterexample: Option<serde_json::Value>,
+}
+
+impl AtomResult {
+    pub fn new(name: &str, kind: ItemKind, status: AtomStatus) -> Self {
+        AtomResult { name: name.to_string(), kind, status, ..Default::default() }
+    }
+
+    /// 検証失敗のカテゴリとメッセージを記録する
+    pub fn with_failure(mut self, kind: FailureKind, message: &str) -> Self {
+        self.failure = Some(kind);
+        self.with_error(message)
+    }
+
+    /// 検証エラーのメッセージを記録し、JSON 形式の反例が含まれていれば取り出す
+    pub fn with_error(mut self, message: &str) -> Self {
+        self.counterexample = counterexample_from_message(message);
+        self.message = Some(message.to_string());
+        self
+    }
+}
+
+/// `slowest_obligations` に残す義務の数
+pub const PROFILE_TOP: usize = 5;
+
+/// 義務 1 件分の Z3 クエリの所要時間
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+pub struct ObligationTiming {
+    /// 義務の種類（"bounds" / "call_requires" / "ensures" など。Obligation::kind と同じ）
+    pub kind: String,
+    /// 義務を生んだ構文（例: "array bounds for arr[i + 1]", "requires of callee push at call #2"）
+    pub label: String,
+    #[serde(default)]
+    pub duration_ms: f64,
+}
+
+/// 所要時間の降順で上位 PROFILE_TOP 件を返す（同じ時間なら解いた順）
+pub fn slowest_obligations(mut timings: Vec<ObligationTiming>) -> Vec<ObligationTiming> {
+    timings.sort_by(|a, b| b.duration_ms.total_cmp(&a.duration_ms));
+    timings.truncate(PROFILE_TOP);
+    timings
+}
+
+/// 証明なしで仮定した事実とその根拠
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+pub struct Assumption {
+    pub condition: String,
+    pub justification: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+pub struct Diagnostic {
+    #[serde(default)]
+    pub severity: Severity,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub atom: Option<String>,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+pub struct Timings {
+    #[serde(default)]
+    pub total_ms: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+pub struct Summary {
+    #[serde(default)]
+    pub verified: usize,
+    #[serde(default)]
+    pub failed: usize,
+    #[serde(default)]
+    pub skipped: usize,
+    #[serde(default)]
+    pub trusted: usize,
+    #[serde(default)]
+    pub unverified: usize,
+    /// 仮定付きで検証した atom の数（1.5 で追加。verified には含めない）
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub verified_with_assumptions: usize,
+}
+
+fn is_zero(n: &usize) -> bool {
+    *n == 0
+}
+
+/// report.json のトップレベル
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+pub struct Report {
+    pub schema_version: String,
+    /// 生成したコマンド: "verify" / "build" / "atom"（atom 単位の検証）
+    #[serde(default)]
+    pub command: String,
+    /// "skipped" のとき、このビルドの成果物は契約が検証されていない
+    #[serde(default)]
+    pub verification: VerificationMode,
+    #[serde(default)]
+    pub tool: ToolInfo,
+    #[serde(default)]
+    pub module: ModuleInfo,
+    #[serde(default)]
+    pub summary: Summary,
+    #[serde(default)]
+    pub atoms: Vec<AtomResult>,
+    #[serde(default)]
+    pub diagnostics: Vec<Diagnostic>,
+    #[serde(default)]
+    pub timings: Timings,
+}
+
+impl Report {
+    pub fn new(command: &str, module_path: &str) -> Self {
+        Report {
+            schema_version: SCHEMA_VERSION.to_string(),
+            command: command.to_string(),
+            tool: ToolInfo {
+                mumei_version: env!("CARGO_PKG_VERSION").to_string(),
+                z3_version: crate::verification::linked_z3_version(),
+            },
+            module: ModuleInfo { path: module_path.to_string() },
+            ..Default::default()
+        }
+    }
+
+    pub fn push(&mut self, result: AtomResult) {
+        self.atoms.push(result);
+    }
+
+    pub fn diagnostic(&mut self, severity: Severity, atom: Option<&str>, message: &str) {
+        self.diagnostics.push(Diagnostic { severity, atom: atom.map(|a| a.to_string()), message: message.to_string() });
+    }
+
+    /// 集計と総所要時間を確定する
+    pub fn finish(&mut self, total_ms: u64) {
+        let mut summary = Summary::default();
+        for a in &self.atoms {
+            match a.status {
+                AtomStatus::Verified => summary.verified += 1,
+                AtomStatus::Failed => summary.failed += 1,
+                AtomStatus::Skipped => summary.skipped += 1,
+                AtomStatus::Trusted => summary.trusted += 1,
+                AtomStatus::Unverified => summary.unverified += 1,
+                AtomStatus::VerifiedWithAssumptions => summary.verified_with_assumptions += 1,
+                AtomStatus::Unknown => {}
+            }
+        }
+        self.summary = summary;
+        self.timings.total_ms = total_ms;
+    }
+
+    /// `output_dir/report.json` に書き出す
+    pub fn save(&self, output_dir: &Path) -> std::io::Result<()> {
+        fs::create_dir_all(output_dir)?;
+        let json = serde_json::to_string_pretty(self).map_err(std::io::Error::other)?;
+        fs::write(output_dir.join(REPORT_FILE), json)
+    }
+
+    /// JSON 文字列からレポートを読み込む。MAJOR が異なるレポートは拒否する。
+    pub fn from_json(text: &str) -> Result<Self, String> {
+        let report: Report = serde_json::from_str(text).map_err(|e| format!("invalid report: {}", e))?;
+        let major = |v: &str| v.split('.').next().unwrap_or("").to_string();
+        if major(&report.schema_version) != major(SCHEMA_VERSION) {
+            return Err(format!(
+                "unsupported report schema_version '{}' (this mumei reads {}.x)",
+                report.schema_version, major(SCHEMA_VERSION)
+            ));
+        }
+        Ok(report)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("could not read '{}': {}", path.display(), e))?;
+        Self::from_json(&text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// 人間向けのサマリー（cmd_verify の出力と同じ体裁）
+    pub fn render(&self, filter: ReportFilter) -> String {
+        let mut out = String::new();
+        let module = if self.module.path.is_empty() { "(single atom)" } else { self.module.path.as_str() };
+        out.push_str(&format!(
+            "🗡️  Mumei report: '{}' ({}, schema {}, {})\n",
+            module, self.command, self.schema_version, self.tool.z3_version
+        ));
+        if self.verification == VerificationMode::Skipped {
+            out.push_str("  ⚠️  Verification was skipped for this build (verify=false): contracts NOT verified\n");
+        }
+        for a in &self.atoms {
+            if filter == ReportFilter::Failed && a.status != AtomStatus::Failed {
+                continue;
+            }
+            let label = match a.kind {
+                ItemKind::Impl => format!("impl {}", a.name),
+                _ => format!("'{}'", a.name),
+            };
+            let line = match a.status {
+                AtomStatus::Verified if a.total => format!("  ⚖️  {}: verified ✅, total ({} ms)", label, a.duration_ms),
+                AtomStatus::Verified => format!("  ⚖️  {}: verified ✅ ({} ms)", label, a.duration_ms),
+                AtomStatus::Failed => format!(
+                    "  ❌ {}: verification failed: {}",
+                    label,
+                    a.message.as_deref().unwrap_or("(no message)")
+                ),
+                AtomStatus::Skipped => {
+                    let why = match a.cache.source {
+                        CacheSource::BuildCache => "unchanged, cached",
+                        CacheSource::Imported => "imported, contract-trusted",
+                        _ => "not verified",
+                    };
+                    format!("  ⚖️  {}: skipped ({})", label, why)
+                }
+                AtomStatus::Trusted => format!("  ⚖️  {}: trusted (body not verified)", label),
+                AtomStatus::Unverified => format!("  ⚠️  {}: unverified", label),
+                AtomStatus::VerifiedWithAssumptions => format!(
+                    "  ⚖️  {}: verified-with-assumptions ({}) ✅ ({} ms)",
+                    label, a.assumptions.len(), a.duration_ms
+                ),
+                AtomStatus::Unknown => format!("  ❔ {}: unknown status", label),
+            };
+            out.push_str(&line);
+            out.push('\n');
+            for assumption in &a.assumptions {
+                out.push_str(&format!("     📌 assume {}: {}\n", assumption.condition, assumption.justification));
+            }
+            if a.cache.obligations_reused > 0 {
+                out.push_str(&format!(
+                    "     ♻️  {} obligation(s) reused, {} re-solved\n",
+                    a.cache.obligations_reused, a.cache.obligations_solved
+                ));
+            }
+        }
+        for d in &self.diagnostics {
+            let icon = match d.severity {
+                Severity::Error => "❌",
+                Severity::Warning => "⚠️ ",
+                _ => "ℹ️ ",
+            };
+            match &d.atom {
+                Some(atom) => out.push_str(&format!("  {} '{}': {}\n", icon, atom, d.message)),
+                None => out.push_str(&format!("  {} {}\n", icon, d.message)),
+            }
+        }
+        let s = &self.summary;
+        out.push('\n');
+        if s.failed > 0 {
+            out.push_str(&format!(
+                "❌ Verification: {} passed, {} failed, {} skipped ({} ms)\n",
+                s.verified, s.failed, s.skipped, self.timings.total_ms
+            ));
+        } else if s.verified_with_assumptions > 0 {
+            out.push_str(&format!(
+                "✅ Verification passed: {} verified, {} verified with assumptions, {} skipped ({} ms)\n",
+                s.verified, s.verified_with_assumptions, s.skipped, self.timings.total_ms
+            ));
+        } else {
+            out.push_str(&format!(
+                "✅ Verification passed: {} verified, {} skipped ({} ms)\n",
+                s.verified, s.skipped, self.timings.total_ms
+            ));
+        }
+        out
+    }
+}
+
+/// `mumei report --filter`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReportFilter {
+    #[default]
+    All,
+    Failed,
+}
+
+impl std::str::FromStr for ReportFilter {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "all" => Ok(ReportFilter::All),
+            "failed" => Ok(ReportFilter::Failed),
+            other => Err(format!("unknown report filter '{}' (expected all or failed)", other)),
+        }
+    }
+}
+
+/// 2 つのレポートの差分（baseline → current）
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ReportDiff {
+    /// current で失敗し、baseline では失敗していなかった（新規 atom を含む）
+    pub newly_failing: Vec<String>,
+    /// baseline で失敗し、current では失敗していない
+    pub newly_passing: Vec<String>,
+    /// current にのみ存在する
+    pub added: Vec<String>,
+    /// baseline にのみ存在する
+    pub removed: Vec<String>,
+}
+
+/// atom と impl を区別するためのキー
+fn result_key(a: &AtomResult) -> String {
+    match a.kind {
+        ItemKind::Impl => format!("impl {}", a.name),
+        _ => a.name.clone(),
+    }
+}
+
+pub fn diff(baseline: &Report, current: &Report) -> ReportDiff {
+    let base: std::collections::HashMap<String, AtomStatus> =
+        baseline.atoms.iter().map(|a| (result_key(a), a.status)).collect();
+    let cur: std::collections::HashMap<String, AtomStatus> =
+        current.atoms.iter().map(|a| (result_key(a), a.status)).collect();
+
+    let mut d = ReportDiff::default();
+    for a in &current.atoms {
+        let key = result_key(a);
+        let before = base.get(&key).copied();
+        if before.is_none() {
+            d.added.push(key.clone());
+        }
+        let failed_now = a.status == AtomStatus::Failed;
+        let failed_before = before == Some(AtomStatus::Failed);
+        if failed_now && !failed_before {
+            d.newly_failing.push(key);
+        } else if !failed_now && failed_before {
+            d.newly_passing.push(key);
+        }
+    }
+    for a in &baseline.atoms {
+        let key = result_key(a);
+        if !cur.contains_key(&key) {
+            d.removed.push(key);
+        }
+    }
+    d
+}
+
+impl ReportDiff {
+    pub fn is_empty(&self) -> bool {
+        self.newly_failing.is_empty() && self.newly_passing.is_empty() && self.added.is_empty() && self.removed.is_empty()
+    }
+
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let mut section = |title: &str, items: &[String]| {
+            if items.is_empty() {
+                return;
+            }
+            out.push_str(&format!("  {} ({}):\n", title, items.len()));
+            for i in items {
+                out.push_str(&format!("      - {}\n", i));
+            }
+        };
+        section("❌ Newly failing", &self.newly_failing);
+        section("✅ Newly passing", &self.newly_passing);
+        section("➕ Added", &self.added);
+        section("➖ Removed", &self.removed);
+        if out.is_empty() {
+            out.push_str("  ✅ No changes in verification results\n");
+        }
+        out
+    }
+}
+
+/// `--counterexample-format json` で描画された検証エラーから反例オブジェクトを取り出す
+fn counterexample_from_message(message: &str) -> Option<serde_json::Value> {
+    let start = message.find('{')?;
+    let parsed: serde_json::Value = serde_json::from_str(&message[start..]).ok()?;
+    parsed.get("counterexample").cloned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASELINE: &str = r#"{
+  "schema_version": "1.0",
+  "command": "verify",
+  "module": { "path": "src/main.mm" },
+  "atoms": [
+    { "name": "add", "status": "verified", "duration_ms": 12 },
+    { "name": "div", "status": "failed", "message": "Division by zero" },
+    { "name": "old_helper", "status": "verified" },
+    { "name": "Eq for i64", "kind": "impl", "status": "verified" }
+  ]
+}"#;
+
+    const CURRENT: &str = r#"{
+  "schema_version": "1.7",
+  "command": "verify",
+  "module": { "path": "src/main.mm" },
+  "future_field": { "anything": true },
+  "atoms": [
+    { "name": "add", "status": "failed", "message": "Postcondition (ensures) is not satisfied." },
+    { "name": "div", "status": "verified" },
+    { "name": "mul", "status": "failed" },
+    { "name": "sub", "status": "some_future_status" },
+    { "name": "Eq for i64", "kind": "impl", "status": "skipped", "cache": { "source": "build_cache" } }
+  ]
+}"#;
+
+    #[test]
+    fn test_report_round_trip() {
+        let mut report = Report::new("build", "src/main.mm");
+        let mut ok = AtomResult::new("add", ItemKind::Atom, AtomStatus::Verified);
+        ok.duration_ms = 7;
+        ok.cache.obligations_reused = 2;
+        ok.obligations.push(Obligation::from_key("bounds:abc123"));
+        ok.total = true;
+        ok.where_clause = Some("lo <= hi".to_string());
+        ok.targets = Some(vec!["rust".to_string(), "go".to_string()]);
+        report.push(ok);
+        report.push(AtomResult::new("div", ItemKind::Atom, AtomStatus::Failed).with_error(
+            "Division by zero\n{\"failure\": \"Division by zero\", \"counterexample\": {\"values\": [{\"name\": \"b\", \"value\": \"0\"}]}}",
+        ));
+        report.diagnostic(Severity::Warning, Some("div"), "unsupported construct");
+        report.finish(42);
+
+        assert_eq!(report.summary.verified, 1);
+        assert_eq!(report.summary.failed, 1);
+        assert_eq!(report.atoms[0].obligations[0].kind, "bounds");
+        assert_eq!(report.atoms[1].counterexample.as_ref().unwrap()["values"][0]["name"], "b");
+        assert!(report.render(ReportFilter::All).contains("'add': verified ✅, total"));
+
+        let json = serde_json::to_string_pretty(&report).unwrap();
+        assert!(json.contains("\"where\": \"lo <= hi\""), "{}", json);
+        assert_eq!(json.matches("\"targets\"").count(), 1, "{}", json);
+        let parsed = Report::from_json(&json).unwrap();
+        assert_eq!(parsed, report);
+    }
+
+    #[test]
+    fn test_report_assumptions() {
+        let mut report = Report::new("verify", "src/main.mm");
+        let mut assumed = AtomResult::new("read_reg", ItemKind::Atom, AtomStatus::VerifiedWithAssumptions);
+        assumed.assumptions.push(Assumption {
+            condition: "v <= 255".to_string(),
+            justification: "8-bit register (datasheet 4.2)".to_string(),
+        });
+        report.push(assumed);
+        report.push(AtomResult::new("add", ItemKind::Atom, AtomStatus::Verified));
+        report.finish(3);
+
+        // 仮定付きの atom は verified と別に数え、仮定と根拠を一覧する
+        assert_eq!(report.summary.verified, 1);
+        assert_eq!(report.summary.verified_with_assumptions, 1);
+        let rendered = report.render(ReportFilter::All);
+        assert!(rendered.contains("'read_reg': verified-with-assumptions (1)"), "{}", rendered);
+        assert!(rendered.contains("📌 assume v <= 255: 8-bit register (datasheet 4.2)"), "{}", rendered);
+        assert!(rendered.contains("1 verified with assumptions"), "{}", rendered);
+
+        let json: serde_json::Value = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["atoms"][0]["status"], "verified-with-assumptions");
+        assert_eq!(json["atoms"][0]["assumptions"][0]["justification"], "8-bit register (datasheet 4.2)");
+        assert!(json["atoms"][1].get("assumptions").is_none());
+        assert_eq!(Report::from_json(&json.to_string()).unwrap(), report);
+    }
+
+    #[test]
+    fn test_slowest_obligations() {
+        let timing = |label: &str, duration_ms: f64| ObligationTiming { kind: "bounds".to_string(), label: label.to_string(), duration_ms };
+        let timings: Vec<ObligationTiming> = [3.0, 40.0, 1.0, 40.0, 7.5, 0.2, 12.0]
+            .iter().enumerate()
+            .map(|(i, ms)| timing(&format!("q{}", i), *ms))
+            .collect();
+        let top = slowest_obligations(timings);
+        let labels: Vec<&str> = top.iter().map(|t| t.label.as_str()).collect();
+        assert_eq!(labels, vec!["q1", "q3", "q6", "q4", "q0"]);
+
+        let mut result = AtomResult::new("f", ItemKind::Atom, AtomStatus::Verified);
+        result.slowest_obligations = top;
+        let json = serde_json::to_value(&result).unwrap();
+        assert_eq!(json["slowest_obligations"][0]["label"], "q1");
+        assert_eq!(json["slowest_obligations"][0]["duration_ms"], 40.0);
+        assert!(serde_json::to_value(AtomResult::new("g", ItemKind::Atom, AtomStatus::Verified)).unwrap()
+            .get("slowest_obligations").is_none());
+    }
+
+    #[test]
+    fn test_report_additive_compatibility() {
+        // 未知のフィールド・未知の列挙値を含む新しい MINOR のレポートも読める
+        let current = Report::from_json(CURRENT).unwrap();
+        assert_eq!(current.atoms[3].status, AtomStatus::Unknown);
+        assert_eq!(current.atoms[4].cache.source, CacheSource::BuildCache);
+        // 省略されたフィールドは既定値
+        assert_eq!(current.atoms[2].duration_ms, 0);
+
+        let err = Report::from_json(r#"{ "schema_version": "2.0" }"#).unwrap_err();
+        assert!(err.contains("unsupported report schema_version '2.0'"), "{}", err);
+    }
+
+    #[test]
+    fn test_report_verification_mode() {
+        // verify=false のビルドはトップレベルに "verification": "skipped" を持つ
+        let mut report = Report::new("build", "src/main.mm");
+        report.verification = VerificationMode::Skipped;
+        report.push(AtomResult::new("add", ItemKind::Atom, AtomStatus::Skipped));
+        report.finish(1);
+        let json: serde_json::Value = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["verification"], "skipped");
+        assert!(report.render(ReportFilter::All).contains("contracts NOT verified"));
+
+        // 1.0 のレポート（フィールドなし）は通常どおり検証したものとして読む
+        let baseline = Report::from_json(BASELINE).unwrap();
+        assert_eq!(baseline.verification, VerificationMode::Performed);
+        assert!(!baseline.render(ReportFilter::All).contains("contracts NOT verified"));
+        let json = serde_json::to_value(&Report::new("verify", "a.mm")).unwrap();
+        assert_eq!(json["verification"], "performed");
+    }
+
+    #[test]
+    fn test_report_diff() {
+        let baseline = Report::from_json(BASELINE).unwrap();
+        let current = Report::from_json(CURRENT).unwrap();
+        let d = diff(&baseline, &current);
+        assert_eq!(d.newly_failing, vec!["add", "mul"]);
+        assert_eq!(d.newly_passing, vec!["div"]);
+        assert_eq!(d.added, vec!["mul", "sub"]);
+        assert_eq!(d.removed, vec!["old_helper"]);
+
+        let rendered = d.render();
+        assert!(rendered.contains("Newly failing (2)"), "{}", rendered);
+        assert!(diff(&baseline, &baseline).is_empty());
+    }
+
+    #[test]
+    fn test_report_render_filter_failed() {
+        let mut current = Report::from_json(CURRENT).unwrap();
+        current.finish(5);
+        let all = current.render(ReportFilter::All);
+        assert!(all.contains("'div': verified"), "{}", all);
+        assert!(all.contains("impl Eq for i64: skipped (unchanged, cached)"), "{}", all);
+        let failed = current.render(ReportFilter::Failed);
+        assert!(failed.contains("'add': verification failed: Postcondition"), "{}", failed);
+        assert!(!failed.contains("'div'"), "{}", failed);
+        assert!(failed.contains("2 failed"), "{}", failed);
+    }
+}
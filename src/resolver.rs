@@ -14,15 +14,18 @@
 //!
 //! キャッシュファイル (.mumei_cache) にはソースハッシュと検証結果を永続化し、
 //! ソースが変更されていなければ再パース・再検証をスキップする。
+//! 保存時には今回走査しなかったファイル・ソースから消えた atom のエントリを刈り込む
+//! （改名・削除された atom が古い検証済み扱いを引き継がないように）。
 
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::fs;
 use std::path::{Path, PathBuf};
 use sha2::{Sha256, Digest};
 use serde::{Serialize, Deserialize};
 
 use crate::parser::{self, Item};
-use crate::verification::{ModuleEnv, MumeiError, MumeiResult};
+use crate::report::Severity;
+use crate::verification::{ModuleEnv, MumeiError, MumeiResult, PrivateItem};
 
 /// 検証キャッシュのエントリ
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -42,37 +45,172 @@ struct CacheEntry {
     atom_hashes: HashMap<String, String>,
 }
 
+/// .mumei_cache / .mumei_build_cache のスキーマバージョン。
+/// バージョンのない旧形式（1 以前）はそのまま読み込み、次の保存で刈り込み済みの現行形式に移行する。
+/// 現行より新しいバージョンのファイルは読み捨てる
+pub const CACHE_SCHEMA_VERSION: u32 = 2;
+
 /// キャッシュファイル全体
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 struct VerificationCache {
+    /// スキーマバージョン（旧形式では 0）
+    #[serde(default)]
+    version: u32,
     /// ファイルパス → キャッシュエントリ
     entries: HashMap<String, CacheEntry>,
 }
+
+impl VerificationCache {
+    /// 今回走査したモジュール（live: パスキー）にないエントリを捨て、previous からの差分として
+    /// 刈り込んだ名前を返す（ファイルは "path"、消えた atom は "path: atom"。ソート済み）。
+    /// 走査したモジュールのエントリは最新のパース結果で置き換わっているため、消えた atom は残らない
+    fn prune(&mut self, previous: &VerificationCache, live: &HashSet<String>) -> Vec<String> {
+        let mut pruned = Vec::new();
+        for (path, old) in &previous.entries {
+            match self.entries.get(path) {
+                Some(entry) if live.contains(path) => {
+                    pruned.extend(old.verified_atoms.iter()
+                        .filter(|atom| !entry.verified_atoms.contains(atom))
+                        .map(|atom| format!("{}: {}", path, atom)));
+                }
+                _ => pruned.push(path.clone()),
+            }
+        }
+        self.entries.retain(|path, _| live.contains(path));
+        self.version = CACHE_SCHEMA_VERSION;
+        pruned.sort();
+        pruned.dedup();
+        pruned
+    }
+}
 /// ロード済みモジュールのキャッシュ
 struct ResolverContext {
-    /// ロード中のモジュールパス集合（循環参照検出用）
-    loading: HashSet<PathBuf>,
-    /// 完全にロード済みのモジュール（キャッシュ）
-    loaded: HashMap<PathBuf, Vec<Item>>,
+    /// ロード中のモジュール集合（循環参照検出用）
+    loading: HashSet<ModuleId>,
+    /// 完全にロード済みのモジュール（キャッシュ）。値は最初に解決したパスと item
+    loaded: HashMap<ModuleId, (PathBuf, Vec<Item>)>,
+    /// 読み取り専用モード: パースと import の走査のみ行い、ModuleEnv への登録・
+    /// 検証済みマーク・キャッシュの更新をしない（`mumei query`）
+    read_only: bool,
+    /// 呼び出し元が指定した標準ライブラリのディレクトリ（resolve_path_in を参照）
+    std_path: Option<PathBuf>,
 }
 impl ResolverContext {
-    fn new() -> Self {
+    fn new(std_path: Option<&Path>) -> Self {
         Self {
             loading: HashSet::new(),
             loaded: HashMap::new(),
+            read_only: false,
+            std_path: std_path.map(Path::to_path_buf),
         }
     }
+
+    fn read_only() -> Self {
+        Self { read_only: true, ..Self::new(None) }
+    }
+
+    /// ロード済みモジュールのキャッシュキー（パス文字列）
+    fn live_keys(&self) -> HashSet<String> {
+        self.loaded.values().map(|(p, _)| p.to_string_lossy().to_string()).collect()
+    }
+}
+
+/// モジュールの同一性（循環参照検出・ロード済み判定のキー）。
+/// canonicalize はシンボリックリンクを解決するが、大文字小文字を区別しないファイルシステム（macOS）では
+/// 入力された綴りのまま返すため、`./Lib/Math.mm` と `./lib/math.mm` が別の文字列になる。
+/// Unix では (device, inode) で比較し、それ以外では canonicalize 済みのパスで比較する
+/// （Windows の canonicalize はディスク上の綴りを返すため、パスの比較で足りる）。
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum ModuleId {
+    Inode { dev: u64, ino: u64 },
+    Path(PathBuf),
+}
+
+impl ModuleId {
+    fn of(path: &Path) -> Self {
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            if let Ok(meta) = fs::metadata(path) {
+                return ModuleId::Inode { dev: meta.dev(), ino: meta.ino() };
+            }
+        }
+        ModuleId::Path(path.to_path_buf())
+    }
 }
 /// items 内の Import 宣言を処理し、依存モジュールの定義を ModuleEnv に登録する。
 /// base_dir はインポート元ファイルの親ディレクトリ。
 /// キャッシュファイルが存在し、ソースハッシュが一致する場合は再パースをスキップする。
-pub fn resolve_imports(items: &[Item], base_dir: &Path, module_env: &mut ModuleEnv) -> MumeiResult<()> {
+/// std_path は標準ライブラリのディレクトリ（resolve_path_in を参照）。
+/// 返り値は .mumei_cache から刈り込んだ古いエントリ（VerificationCache::prune を参照）。
+pub fn resolve_imports(items: &[Item], base_dir: &Path, std_path: Option<&Path>, module_env: &mut ModuleEnv) -> MumeiResult<Vec<String>> {
     let cache_path = base_dir.join(".mumei_cache");
     let mut cache = load_cache(&cache_path);
-    let mut ctx = ResolverContext::new();
+    let previous = cache.clone();
+    let mut ctx = ResolverContext::new(std_path);
     resolve_imports_recursive(items, base_dir, &mut ctx, &mut cache, module_env)?;
+    let pruned = cache.prune(&previous, &ctx.live_keys());
     save_cache(&cache_path, &cache);
-    Ok(())
+    Ok(pruned)
+}
+
+/// items から推移的に import されるモジュールの解決済み絶対パスを返す（ソート済み）。
+/// 読み取り専用モードで走査するため、ModuleEnv の変更や .mumei_cache の書き込みはしない。
+pub fn import_closure(items: &[Item], base_dir: &Path) -> MumeiResult<Vec<PathBuf>> {
+    Ok(imported_modules(items, base_dir)?.into_iter().map(|(path, _)| path).collect())
+}
+
+/// import_closure と同じ走査で、各モジュールのパースした item も返す（パス順）
+fn imported_modules(items: &[Item], base_dir: &Path) -> MumeiResult<Vec<(PathBuf, Vec<Item>)>> {
+    let mut ctx = ResolverContext::read_only();
+    let mut cache = VerificationCache::default();
+    let mut module_env = ModuleEnv::new();
+    resolve_imports_recursive(items, base_dir, &mut ctx, &mut cache, &mut module_env)?;
+    let mut modules: Vec<(PathBuf, Vec<Item>)> = ctx.loaded.into_values().collect();
+    modules.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(modules)
+}
+
+/// 信頼の境界（trust surface）: 入力ファイルと、そこから推移的に import するモジュールの trusted atom を
+/// （定義したファイル, atom 名）で返す。std/ と exempt_dirs（依存パッケージのディレクトリ）以下のモジュールは除く
+pub fn trusted_atoms(input: &Path, exempt_dirs: &[PathBuf]) -> MumeiResult<Vec<(PathBuf, String)>> {
+    let source = read_source(input).map_err(|e| {
+        MumeiError::VerificationError(format!("Failed to read '{}': {}", input.display(), e))
+    })?;
+    let items = parse_source(&source, input)?;
+    let base_dir = match input.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let mut exempt: Vec<PathBuf> = exempt_dirs.iter().filter_map(|dir| dir.canonicalize().ok()).collect();
+    if let Some(std_dir) = resolve_path("std/prelude", base_dir).ok().and_then(|p| p.parent().map(Path::to_path_buf)) {
+        exempt.push(std_dir);
+    }
+
+    let trusted = |path: &Path, items: &[Item]| -> Vec<(PathBuf, String)> {
+        items.iter().filter_map(|item| match item {
+            Item::Atom(atom) if atom.trust_level == parser::TrustLevel::Trusted => Some((path.to_path_buf(), atom.name.clone())),
+            _ => None,
+        }).collect()
+    };
+    let mut found = trusted(input, &items);
+    for (path, module_items) in imported_modules(&items, base_dir)? {
+        if !exempt.iter().any(|dir| path.starts_with(dir)) {
+            found.extend(trusted(&path, &module_items));
+        }
+    }
+    Ok(found)
+}
+
+/// import 先モジュールが定義する名前（atom / 型 / struct / enum / trait / resource）。
+/// トランスパイラが、バンドルから参照されない import をヘッダーから省くのに使う
+pub fn import_definitions(import_decl: &parser::ImportDecl, base_dir: &Path) -> MumeiResult<HashSet<String>> {
+    let path = resolve_path(&import_decl.path, base_dir)?;
+    let source = read_source(&path).map_err(|e| {
+        MumeiError::VerificationError(format!("Failed to read imported module '{}': {}", import_decl.path, e))
+    })?;
+    let items = parse_source(&source, &path)?;
+    Ok(defined_names(&items).into_iter().map(str::to_string).collect())
 }
 
 /// std/prelude.mm を自動的にロードし、ModuleEnv に登録する。
@@ -81,36 +219,39 @@ pub fn resolve_imports(items: &[Item], base_dir: &Path, module_env: &mut ModuleE
 ///
 /// prelude の定義はトレイト・ADT のみを登録し、atom は検証済みとしてマークする。
 /// prelude が見つからない場合はスキップする（組み込みトレイトがフォールバックとして機能）。
-pub fn resolve_prelude(base_dir: &Path, module_env: &mut ModuleEnv) -> MumeiResult<()> {
+/// 返り値は prelude 側の .mumei_cache から刈り込んだ古いエントリ。
+pub fn resolve_prelude(base_dir: &Path, std_path: Option<&Path>, module_env: &mut ModuleEnv) -> MumeiResult<Vec<String>> {
     // prelude のパスを解決（見つからなければスキップ）
-    let prelude_path = match resolve_path("std/prelude", base_dir) {
+    let prelude_path = match resolve_path_in("std/prelude", base_dir, std_path) {
         Ok(path) => path,
         Err(_) => {
             // prelude が見つからない場合は静かにスキップ
             // （組み込みトレイト register_builtin_traits が代替として機能）
-            return Ok(());
+            return Ok(Vec::new());
         }
     };
 
     // prelude を読み込み・パース
-    let source = match fs::read_to_string(&prelude_path) {
+    let source = match read_source(&prelude_path) {
         Ok(s) => s,
-        Err(_) => return Ok(()), // 読み込み失敗もスキップ
+        Err(_) => return Ok(Vec::new()), // 読み込み失敗もスキップ
     };
 
-    let prelude_items = parser::parse_module(&source);
+    let prelude_items = parse_source(&source, &prelude_path)?;
 
     // prelude 内の import を再帰的に解決（prelude 自身が他モジュールに依存する場合）
     let prelude_base_dir = prelude_path.parent().unwrap_or(Path::new("."));
     let cache_path = prelude_base_dir.join(".mumei_cache");
     let mut cache = load_cache(&cache_path);
-    let mut ctx = ResolverContext::new();
-    ctx.loading.insert(prelude_path.clone());
+    let previous = cache.clone();
+    let mut ctx = ResolverContext::new(std_path);
+    ctx.loading.insert(ModuleId::of(&prelude_path));
     resolve_imports_recursive(&prelude_items, prelude_base_dir, &mut ctx, &mut cache, module_env)?;
+    let pruned = cache.prune(&previous, &ctx.live_keys());
     save_cache(&cache_path, &cache);
 
     // prelude の定義を ModuleEnv に登録（alias なし = グローバルスコープ）
-    register_imported_items(&prelude_items, None, module_env);
+    register_imported_items(&prelude_items, None, None, &prelude_path.display().to_string(), module_env)?;
 
     // prelude の atom を検証済みとしてマーク
     for item in &prelude_items {
@@ -119,7 +260,7 @@ pub fn resolve_prelude(base_dir: &Path, module_env: &mut ModuleEnv) -> MumeiResu
         }
     }
 
-    Ok(())
+    Ok(pruned)
 }
 /// 再帰的にインポートを解決する内部関数
 fn resolve_imports_recursive(
@@ -129,23 +270,27 @@ fn resolve_imports_recursive(
     cache: &mut VerificationCache,
     module_env: &mut ModuleEnv,
 ) -> MumeiResult<()> {
+    let mut imports = Vec::new();
     for item in items {
         if let Item::Import(import_decl) = item {
-            let resolved_path = resolve_path(&import_decl.path, base_dir)?;
+            let resolved_path = resolve_path_in(&import_decl.path, base_dir, ctx.std_path.as_deref())?;
+            // 同一性はパス文字列ではなく ModuleId で判定する（エラー表示は利用者が書いたパス）
+            let module_id = ModuleId::of(&resolved_path);
+            imports.push((import_decl, module_id.clone()));
             // 循環参照チェック
-            if ctx.loading.contains(&resolved_path) {
+            if ctx.loading.contains(&module_id) {
                 return Err(MumeiError::VerificationError(
-                    format!("Circular import detected: '{}'", resolved_path.display())
+                    format!("Circular import detected: '{}' (resolved to '{}')", import_decl.path, resolved_path.display())
                 ));
             }
-            // 既にロード済みならスキップ
-            if ctx.loaded.contains_key(&resolved_path) {
+            // 既にロード済みならスキップ（別の綴り・リンク経由の同じファイルも含む）
+            if ctx.loaded.contains_key(&module_id) {
                 continue;
             }
             // ロード中としてマーク
-            ctx.loading.insert(resolved_path.clone());
+            ctx.loading.insert(module_id.clone());
             // ファイルを読み込みパース
-            let source = fs::read_to_string(&resolved_path).map_err(|e| {
+            let source = read_source(&resolved_path).map_err(|e| {
                 MumeiError::VerificationError(
                     format!("Failed to read imported module '{}': {}", import_decl.path, e)
                 )
@@ -162,13 +307,18 @@ fn resolve_imports_recursive(
                 }
             }
 
-            let imported_items = parser::parse_module(&source);
+            let imported_items = parse_source(&source, &resolved_path)?;
             let import_base_dir = resolved_path.parent().unwrap_or(Path::new("."));
             // 再帰的にインポートを解決（インポートされたモジュール内の import も処理）
             resolve_imports_recursive(&imported_items, import_base_dir, ctx, cache, module_env)?;
+            if ctx.read_only {
+                ctx.loading.remove(&module_id);
+                ctx.loaded.insert(module_id, (resolved_path, imported_items));
+                continue;
+            }
             // インポートされたモジュールの定義を ModuleEnv に登録
             let alias_prefix = import_decl.alias.as_deref();
-            register_imported_items(&imported_items, alias_prefix, module_env);
+            register_imported_items(&imported_items, alias_prefix, import_decl.using.as_deref(), &resolved_path.display().to_string(), module_env)?;
 
             // インポートされた atom を検証済みとしてマーク
             // → main.rs で verify() をスキップし、契約のみ信頼する
@@ -194,6 +344,8 @@ fn resolve_imports_recursive(
                     Item::ImplDef(_) => {},
                     Item::ResourceDef(_) => {},
                     Item::Import(_) => {},
+                    Item::Export(_) => {},
+                    Item::TestDef(_) => {},
                 }
             }
 
@@ -207,36 +359,113 @@ fn resolve_imports_recursive(
             });
 
             // ロード完了
-            ctx.loading.remove(&resolved_path);
-            ctx.loaded.insert(resolved_path, imported_items);
+            ctx.loading.remove(&module_id);
+            ctx.loaded.insert(module_id, (resolved_path, imported_items));
         }
     }
-    Ok(())
+    if ctx.read_only {
+        return Ok(());
+    }
+    // このモジュールが import 先の非公開 item・using で取り込まなかった item に触れていないか検査
+    check_visibility(items, module_env)?;
+    check_using(items, &imports, ctx)
 }
 /// インポートされたモジュールの Item を ModuleEnv に登録する。
 /// alias が指定されている場合、FQN（alias::name）でも登録する。
-fn register_imported_items(items: &[Item], alias: Option<&str>, module_env: &mut ModuleEnv) {
+/// リソースだけは alias 付きなら FQN のみで登録し、別モジュールの同名リソースと
+/// 識別子を共有しない。origin はリソースの宣言元（エラー表示用）。
+///
+/// モジュールが `export { ... };` を持つ場合、リストにない atom / 型は非公開になる。
+/// 非公開 item も公開 atom の契約から参照されうるため素の名前では登録するが、
+/// FQN は登録せず module_env.private_items に記録する（check_visibility で参照を拒否）。
+/// export 宣言がなければ従来通り全 item を公開する。
+///
+/// `using {...}`（using が Some）のときは、挙げた item だけが素の名前の占有者になる。
+/// ほかの item も契約の評価用に素の名前で登録するが、占有済みの名前は上書きしない。
+/// インポート側からの素の名前での参照は check_using が拒否し、FQN（alias::name）では従来通りすべて参照できる。
+/// 素の名前を占有する atom / 型 / struct は、別ファイルの異なる定義がすでに占有していればエラーにする
+/// （claim_bare_name を参照）。
+fn register_imported_items(items: &[Item], alias: Option<&str>, using: Option<&[String]>, origin: &str, module_env: &mut ModuleEnv) -> MumeiResult<()> {
+    let exports = parser::module_exports(items);
+    match &exports {
+        None if module_env.require_explicit_exports => {
+            return Err(MumeiError::VerificationError(format!(
+                "Module '{}' has no `export {{ ... }};` list, but [build] require_explicit_exports = true",
+                origin
+            )));
+        }
+        Some(names) => {
+            let defined = defined_names(items);
+            let mut unknown: Vec<&String> = names.iter().filter(|n| !defined.contains(n.as_str())).collect();
+            unknown.sort();
+            if let Some(name) = unknown.first() {
+                return Err(MumeiError::VerificationError(format!(
+                    "Module '{}' exports '{}', which it does not define", origin, name
+                )));
+            }
+        }
+        None => {}
+    }
+    let mut exported: Vec<String> = exports.iter().flatten().cloned().collect();
+    exported.sort();
+    let is_public = |name: &str| exports.as_ref().map_or(true, |e| e.contains(name));
+    // 素の名前で参照できる item（公開されていて、using があればそこに挙げられている）
+    let is_visible = |name: &str| is_public(name) && using.map_or(true, |names| names.iter().any(|n| n == name));
+    let mark = |module_env: &mut ModuleEnv, kind: &'static str, name: &str| {
+        if is_public(name) {
+            module_env.private_items.remove(name);
+        } else {
+            module_env.private_items.insert(name.to_string(), PrivateItem {
+                kind,
+                module: origin.to_string(),
+                exports: exported.clone(),
+            });
+        }
+        is_public(name)
+    };
+
     for item in items {
         match item {
             Item::TypeDef(refined_type) => {
-                module_env.register_type(refined_type);
-                if let Some(prefix) = alias {
+                let name = &refined_type.name;
+                let same = module_env.get_type(name).map(|existing| same_type(existing, refined_type));
+                let public = if claim_bare_name(module_env, "type", name, is_visible(name), same, origin)? {
+                    module_env.register_type(refined_type);
+                    mark(module_env, "type", name)
+                } else {
+                    is_public(name)
+                };
+                if let (Some(prefix), true) = (alias, public) {
                     let mut fqn_type = refined_type.clone();
                     fqn_type.name = format!("{}::{}", prefix, refined_type.name);
                     module_env.register_type(&fqn_type);
                 }
             }
             Item::StructDef(struct_def) => {
-                module_env.register_struct(struct_def);
-                if let Some(prefix) = alias {
+                let name = &struct_def.name;
+                let same = module_env.get_struct(name).map(|existing| same_struct(existing, struct_def));
+                let public = if claim_bare_name(module_env, "struct", name, is_visible(name), same, origin)? {
+                    module_env.register_struct(struct_def);
+                    mark(module_env, "struct", name)
+                } else {
+                    is_public(name)
+                };
+                if let (Some(prefix), true) = (alias, public) {
                     let mut fqn_struct = struct_def.clone();
                     fqn_struct.name = format!("{}::{}", prefix, struct_def.name);
                     module_env.register_struct(&fqn_struct);
                 }
             }
             Item::Atom(atom) => {
-                module_env.register_atom(atom);
-                if let Some(prefix) = alias {
+                let name = &atom.name;
+                let same = module_env.get_atom(name).map(|existing| same_atom(existing, atom));
+                let public = if claim_bare_name(module_env, "atom", name, is_visible(name), same, origin)? {
+                    module_env.register_atom(atom);
+                    mark(module_env, "atom", name)
+                } else {
+                    is_public(name)
+                };
+                if let (Some(prefix), true) = (alias, public) {
                     let mut fqn_atom = atom.clone();
                     fqn_atom.name = format!("{}::{}", prefix, atom.name);
                     module_env.register_atom(&fqn_atom);
@@ -244,7 +473,8 @@ fn register_imported_items(items: &[Item], alias: Option<&str>, module_env: &mut
             }
             Item::EnumDef(enum_def) => {
                 module_env.register_enum(enum_def);
-                if let Some(prefix) = alias {
+                let public = mark(module_env, "enum", &enum_def.name);
+                if let (Some(prefix), true) = (alias, public) {
                     let mut fqn_enum = enum_def.clone();
                     fqn_enum.name = format!("{}::{}", prefix, enum_def.name);
                     module_env.register_enum(&fqn_enum);
@@ -258,18 +488,219 @@ fn register_imported_items(items: &[Item], alias: Option<&str>, module_env: &mut
                 module_env.register_impl(impl_def);
             }
             Item::ResourceDef(resource_def) => {
-                module_env.register_resource(resource_def);
-                if let Some(prefix) = alias {
-                    let mut fqn_resource = resource_def.clone();
-                    fqn_resource.name = format!("{}::{}", prefix, resource_def.name);
-                    module_env.register_resource(&fqn_resource);
+                match alias {
+                    Some(prefix) => {
+                        let mut fqn_resource = resource_def.clone();
+                        fqn_resource.name = format!("{}::{}", prefix, resource_def.name);
+                        module_env.register_resource(&fqn_resource, origin)?;
+                    }
+                    None => module_env.register_resource(resource_def, origin)?,
                 }
             }
             Item::Import(_) => {
                 // 再帰的に処理済み
             }
+            Item::Export(_) => {
+                // module_exports で処理済み
+            }
+            Item::TestDef(_) => {
+                // インポートしたモジュールのテストは実行しない
+            }
         }
     }
+    Ok(())
+}
+
+/// import 先の item を素の名前で登録してよいか。
+/// visible（素の名前で参照できる）item は名前を占有する。すでに別ファイルが占有していて定義が異なれば
+/// （same == Some(false)）後勝ちにせず、両方のファイルを挙げてエラーにする。
+/// visible でない item（非公開・using に挙げられていない）は契約の評価用に登録するが、占有済みの名前は上書きしない。
+fn claim_bare_name(module_env: &mut ModuleEnv, kind: &'static str, name: &str, visible: bool, same: Option<bool>, origin: &str) -> MumeiResult<bool> {
+    let key = (kind, name.to_string());
+    match module_env.item_origins.get(&key) {
+        Some(existing) if visible && existing != origin && same == Some(false) => Err(MumeiError::VerificationError(format!(
+            "Name collision: {} '{}' is defined differently in '{}' and '{}'. \
+             Import one of the modules with an alias (`import \"...\" as m;`) or list only the items you need (`using {{ ... }}`)",
+            kind, name, existing, origin
+        ))),
+        Some(_) => Ok(visible),
+        None => {
+            if visible {
+                module_env.item_origins.insert(key, origin.to_string());
+            }
+            Ok(true)
+        }
+    }
+}
+
+/// 衝突判定用の定義の比較（表示用の raw テキストや位置は比べない）
+fn same_type(a: &parser::RefinedType, b: &parser::RefinedType) -> bool {
+    a._base_type == b._base_type && a.operand == b.operand && a.predicate_raw == b.predicate_raw
+}
+
+fn same_struct(a: &parser::StructDef, b: &parser::StructDef) -> bool {
+    a.type_params == b.type_params
+        && a.fields.len() == b.fields.len()
+        && a.fields.iter().zip(&b.fields).all(|(x, y)| x.name == y.name && x.type_name == y.type_name && x.constraint == y.constraint)
+        && a.invariant == b.invariant
+}
+
+fn same_atom(a: &parser::Atom, b: &parser::Atom) -> bool {
+    a.type_params == b.type_params
+        && format!("{:?}", a.params) == format!("{:?}", b.params)
+        && a.return_type == b.return_type
+        && a.requires == b.requires
+        && a.ensures == b.ensures
+        && a.body_expr == b.body_expr
+}
+
+/// モジュールが定義する item 名（export リストの検証・可視性チェックのローカル名判定用）
+fn defined_names(items: &[Item]) -> HashSet<&str> {
+    items.iter().filter_map(item_kind).map(|(name, _)| name).collect()
+}
+
+/// 名前を持つ item の名前と種類（"atom" / "type" / "struct" / "enum" / "trait" / "resource"）
+fn item_kind(item: &Item) -> Option<(&str, &'static str)> {
+    match item {
+        Item::Atom(atom) => Some((atom.name.as_str(), "atom")),
+        Item::TypeDef(t) => Some((t.name.as_str(), "type")),
+        Item::StructDef(s) => Some((s.name.as_str(), "struct")),
+        Item::EnumDef(e) => Some((e.name.as_str(), "enum")),
+        Item::TraitDef(t) => Some((t.name.as_str(), "trait")),
+        Item::ResourceDef(r) => Some((r.name.as_str(), "resource")),
+        Item::ImplDef(_) | Item::Import(_) | Item::Export(_) | Item::TestDef(_) => None,
+    }
+}
+
+/// items（インポート側モジュール）が他モジュールの非公開 item を参照していないか検査する。
+/// 同名の item を自モジュールで定義している場合はそちらを指すため対象外。
+fn check_visibility(items: &[Item], module_env: &ModuleEnv) -> MumeiResult<()> {
+    if module_env.private_items.is_empty() {
+        return Ok(());
+    }
+    let local = defined_names(items);
+    let violation = |name: &str, from: &str| {
+        let private = &module_env.private_items[name];
+        let exported = if private.exports.is_empty() {
+            "(none)".to_string()
+        } else {
+            private.exports.join(", ")
+        };
+        MumeiError::VerificationError(format!(
+            "{} '{}' is private to module '{}' (referenced from {}). Exported items: {}",
+            private.kind, name, private.module, from, exported
+        ))
+    };
+    let mut private_names: Vec<(&str, &'static str)> = module_env.private_items.iter()
+        .filter(|(name, _)| !local.contains(name.as_str()))
+        .map(|(name, private)| (name.as_str(), private.kind))
+        .collect();
+    private_names.sort();
+    match find_reference(items, &private_names, false) {
+        Some((name, from)) => Err(violation(name, &from)),
+        None => Ok(()),
+    }
+}
+
+/// `using {...}` 付きの import で取り込まなかった公開 item を、items（インポート側モジュール）が
+/// 素の名前で参照していないか検査する。imports はこのモジュールの import 宣言と読み込んだモジュールの対応。
+/// 自モジュールの定義や、ほかの import（using なし、または using に挙げたもの）で同じ名前を取り込んでいれば対象外。
+/// エイリアスによる FQN 参照（alias::name）は常に許す。
+/// 同じモジュールを別のモジュールが using なしで読み込み済みでも、可視性はインポート側ごとに判定する
+fn check_using(items: &[Item], imports: &[(&parser::ImportDecl, ModuleId)], ctx: &ResolverContext) -> MumeiResult<()> {
+    if imports.iter().all(|(decl, _)| decl.using.is_none()) {
+        return Ok(());
+    }
+    let public_items = |module_items: &[Item]| -> Vec<(String, &'static str)> {
+        let exports = parser::module_exports(module_items);
+        module_items.iter()
+            .filter_map(item_kind)
+            .filter(|(name, _)| exports.as_ref().map_or(true, |e| e.contains(*name)))
+            .map(|(name, kind)| (name.to_string(), kind))
+            .collect()
+    };
+    let mut visible: HashSet<String> = defined_names(items).into_iter().map(str::to_string).collect();
+    let mut hidden: Vec<(String, &'static str, &parser::ImportDecl, String)> = Vec::new();
+    for (decl, module_id) in imports {
+        let (path, module_items) = match ctx.loaded.get(module_id) {
+            Some(loaded) => loaded,
+            None => continue,
+        };
+        let public = public_items(module_items);
+        // using に挙げた名前は import 先の公開 item であること
+        for name in decl.using.iter().flatten() {
+            if public.iter().any(|(n, _)| n == name) {
+                continue;
+            }
+            let reason = if defined_names(module_items).contains(name.as_str()) { "does not export" } else { "does not define" };
+            return Err(MumeiError::VerificationError(format!(
+                "`import \"{}\" using {{ ... }}` lists '{}', which module '{}' {}", decl.path, name, path.display(), reason
+            )));
+        }
+        for (name, kind) in public {
+            match &decl.using {
+                Some(using) if !using.contains(&name) => hidden.push((name, kind, *decl, path.display().to_string())),
+                _ => { visible.insert(name); }
+            }
+        }
+    }
+    hidden.retain(|(name, ..)| !visible.contains(name));
+    hidden.sort_by(|a, b| a.0.cmp(&b.0));
+    let names: Vec<(&str, &'static str)> = hidden.iter().map(|(name, kind, ..)| (name.as_str(), *kind)).collect();
+    match find_reference(items, &names, true) {
+        Some((name, from)) => {
+            let (_, kind, decl, module) = hidden.iter().find(|(n, ..)| n == name).unwrap();
+            let using = decl.using.as_deref().unwrap_or_default().join(", ");
+            Err(MumeiError::VerificationError(format!(
+                "{} '{}' is not imported from module '{}' (referenced from {}): `import \"{}\"` lists `using {{ {} }}`. \
+                 Add it to the list or refer to it through a module alias",
+                kind, name, module, from, decl.path, using
+            )))
+        }
+        None => Ok(()),
+    }
+}
+
+/// items が names のいずれかを参照していれば、その名前と参照元（"atom 'f'" など）を返す。
+/// atom は requires / ensures / body 中の呼び出し、それ以外の item はパラメータ型・フィールド型で判定する。
+/// allow_fqn なら `alias::name` の形の参照は対象外（そうでなければ素の名前と同じ item として扱う）
+fn find_reference<'n>(items: &[Item], names: &[(&'n str, &'static str)], allow_fqn: bool) -> Option<(&'n str, String)> {
+    if names.is_empty() {
+        return None;
+    }
+    for item in items {
+        let mut type_refs: Vec<&str> = Vec::new();
+        let from = match item {
+            Item::Atom(atom) => {
+                type_refs.extend(atom.params.iter().filter_map(|p| p.type_name.as_deref()));
+                for (name, _) in names.iter().filter(|(_, kind)| *kind == "atom") {
+                    let pattern = if allow_fqn { r"(?:^|[^:\w]){}\s*\(" } else { r"\b{}\s*\(" };
+                    let call_re = regex::Regex::new(&pattern.replace("{}", &regex::escape(name))).unwrap();
+                    let texts = [&atom.requires, &atom.ensures, &atom.body_expr];
+                    if texts.iter().any(|text| call_re.is_match(text)) {
+                        return Some((*name, format!("atom '{}'", atom.name)));
+                    }
+                }
+                format!("atom '{}'", atom.name)
+            }
+            Item::StructDef(struct_def) => {
+                type_refs.extend(struct_def.fields.iter().map(|f| f.type_name.as_str()));
+                format!("struct '{}'", struct_def.name)
+            }
+            _ => continue,
+        };
+        for type_name in type_refs {
+            if allow_fqn && type_name.contains("::") {
+                continue;
+            }
+            // `alias::Name` と素の `Name` の両方を同じ item として扱う
+            let base = type_name.rsplit("::").next().unwrap_or(type_name);
+            if let Some((name, _)) = names.iter().find(|(n, kind)| *n == base && *kind != "atom") {
+                return Some((*name, from));
+            }
+        }
+    }
+    None
 }
 /// インポートパスを絶対パスに解決する。
 /// 拡張子 .mm が省略されている場合は自動補完する。
@@ -281,7 +712,13 @@ fn register_imported_items(items: &[Item], alias: Option<&str>, module_env: &mut
 ///
 /// これにより `import "std/option";` のようなインポートが、
 /// プロジェクト内に `std/` ディレクトリがなくても解決できる。
-fn resolve_path(import_path: &str, base_dir: &Path) -> MumeiResult<PathBuf> {
+pub fn resolve_path(import_path: &str, base_dir: &Path) -> MumeiResult<PathBuf> {
+    resolve_path_in(import_path, base_dir, None)
+}
+
+/// resolve_path と同じ順序で解決する。std_path を指定した場合は、3. で MUMEI_STD_PATH の代わりに使う
+/// （LSP のワークスペース設定など。プロセス全体の環境変数を書き換えずに済む）
+pub fn resolve_path_in(import_path: &str, base_dir: &Path, std_path: Option<&Path>) -> MumeiResult<PathBuf> {
     let mut path = PathBuf::from(import_path);
     if path.extension().is_none() {
         path.set_extension("mm");
@@ -330,9 +767,9 @@ fn resolve_path(import_path: &str, base_dir: &Path) -> MumeiResult<PathBuf> {
         }
     }
 
-    // 3. MUMEI_STD_PATH 環境変数からの解決
-    if let Ok(std_path) = std::env::var("MUMEI_STD_PATH") {
-        let std_base = Path::new(&std_path);
+    // 3. 指定された std_path、なければ MUMEI_STD_PATH 環境変数からの解決
+    let std_base = std_path.map(Path::to_path_buf).or_else(|| std::env::var_os("MUMEI_STD_PATH").map(PathBuf::from));
+    if let Some(std_base) = std_base {
         // "std/option" → std_base/option.mm として解決
         let relative = import_str.strip_prefix("std/")
             .or_else(|| import_str.strip_prefix("std\\"))
@@ -350,7 +787,7 @@ fn resolve_path(import_path: &str, base_dir: &Path) -> MumeiResult<PathBuf> {
     // すべて失敗した場合はエラー
     Err(MumeiError::VerificationError(
         format!(
-            "Cannot resolve import path '{}'\n  Searched:\n    - {}\n    - compiler binary directory\n    - current working directory\n    - MUMEI_STD_PATH environment variable",
+            "Cannot resolve import path '{}'\n  Searched:\n    - {}\n    - compiler binary directory\n    - current working directory\n    - std path (stdPath / MUMEI_STD_PATH)",
             import_path,
             base_dir.join(&path).display()
         )
@@ -365,12 +802,21 @@ fn resolve_path(import_path: &str, base_dir: &Path) -> MumeiResult<PathBuf> {
 /// パス依存・Git 依存のモジュールを ModuleEnv に登録する。
 ///
 /// パス依存: `math = { path = "./libs/math" }` → path/src/main.mm を解決
-/// Git 依存: `math = { git = "https://...", tag = "v1.0.0" }` → ~/.mumei/packages/ に clone
+/// Git 依存: `math = { git = "https://...", tag = "v1.0.0" }` → ~/.mumei/registry/<name>/<commit>/ に clone し、
+/// 解決したコミットを mumei.lock に記録する（宣言から消えた依存のエントリも削除する）
+///
+/// 読み込んだ依存（Info）とエントリの見つからない依存（Warning）は出力せず notes に積む
+/// （LSP では stdout が JSON-RPC の通信路のため）。std_path は依存の import の解決に使う（resolve_path_in）。
 pub fn resolve_manifest_dependencies(
     manifest: &crate::manifest::Manifest,
     project_dir: &Path,
+    std_path: Option<&Path>,
     module_env: &mut ModuleEnv,
+    notes: &mut Vec<(Severity, String)>,
 ) -> MumeiResult<()> {
+    let original_lock = crate::lockfile::Lockfile::load(project_dir).map_err(MumeiError::VerificationError)?;
+    let mut lockfile = original_lock.clone();
+    lockfile.packages.retain(|p| manifest.dependencies.get(&p.name).map_or(false, |d| d.as_git().is_some()));
     for (dep_name, dep) in &manifest.dependencies {
         // パス依存
         if let Some(dep_path) = dep.as_path() {
@@ -382,20 +828,20 @@ pub fn resolve_manifest_dependencies(
             ];
             let entry = entry_candidates.iter().find(|p| p.exists());
             if let Some(entry_path) = entry {
-                let source = fs::read_to_string(entry_path).map_err(|e| {
+                let source = read_source(entry_path).map_err(|e| {
                     MumeiError::VerificationError(format!(
                         "Failed to read dependency '{}' at '{}': {}",
                         dep_name, entry_path.display(), e
                     ))
                 })?;
-                let items = parser::parse_module(&source);
+                let items = parse_source(&source, entry_path)?;
                 let dep_base_dir = entry_path.parent().unwrap_or(Path::new("."));
                 let cache_path = dep_base_dir.join(".mumei_cache");
                 let mut cache = load_cache(&cache_path);
-                let mut ctx = ResolverContext::new();
+                let mut ctx = ResolverContext::new(std_path);
                 resolve_imports_recursive(&items, dep_base_dir, &mut ctx, &mut cache, module_env)?;
                 save_cache(&cache_path, &cache);
-                register_imported_items(&items, Some(dep_name), module_env);
+                register_imported_items(&items, Some(dep_name), None, &entry_path.display().to_string(), module_env)?;
                 for item in &items {
                     if let Item::Atom(atom) = item {
                         module_env.mark_verified(&atom.name);
@@ -403,77 +849,44 @@ pub fn resolve_manifest_dependencies(
                         module_env.mark_verified(&fqn);
                     }
                 }
-                println!("  📦 Dependency '{}': loaded from {}", dep_name, entry_path.display());
+                notes.push((Severity::Info, format!("Dependency '{}': loaded from {}", dep_name, entry_path.display())));
             } else {
-                eprintln!("  ⚠️  Dependency '{}': no entry file found in '{}'", dep_name, abs_path.display());
+                notes.push((Severity::Warning, format!("Dependency '{}': no entry file found in '{}'", dep_name, abs_path.display())));
             }
         }
-        // Git 依存（git フィールドがある場合は registry より優先）
+        // Git 依存（git フィールドがある場合は registry より優先）。
+        // mumei.lock のリビジョンを tag / branch より優先し、キャッシュ済みならネットワークに出ない
         else if let Some((url, tag, rev, branch)) = dep.as_git() {
-            let packages_dir = crate::manifest::mumei_home().join("packages");
-            let _ = fs::create_dir_all(&packages_dir);
-            let clone_dir = packages_dir.join(dep_name);
-
-            if !clone_dir.exists() {
-                // git clone
-                let ref_arg = if let Some(t) = tag {
-                    vec!["--branch".to_string(), t.to_string(), "--depth".to_string(), "1".to_string()]
-                } else if let Some(b) = branch {
-                    vec!["--branch".to_string(), b.to_string(), "--depth".to_string(), "1".to_string()]
-                } else {
-                    vec!["--depth".to_string(), "1".to_string()]
-                };
-
-                let mut cmd_args = vec!["clone".to_string()];
-                cmd_args.extend(ref_arg);
-                cmd_args.push(url.to_string());
-                cmd_args.push(clone_dir.to_string_lossy().to_string());
-
-                let status = std::process::Command::new("git")
-                    .args(&cmd_args)
-                    .status()
-                    .map_err(|e| MumeiError::VerificationError(format!("git clone failed for '{}': {}", dep_name, e)))?;
-
-                if !status.success() {
-                    return Err(MumeiError::VerificationError(format!(
-                        "git clone failed for dependency '{}' ({})", dep_name, url
-                    )));
-                }
-
-                // 特定の rev にチェックアウト
-                if let Some(r) = rev {
-                    let _ = std::process::Command::new("git")
-                        .args(["checkout", r])
-                        .current_dir(&clone_dir)
-                        .status();
-                }
-
-                println!("  📦 Dependency '{}': cloned from {}", dep_name, url);
-            } else {
-                println!("  📦 Dependency '{}': using cached clone", dep_name);
-            }
+            let reference = crate::lockfile::reference_of(tag, rev, branch);
+            let locked = lockfile.locked_rev(dep_name, url, &reference).map(str::to_string);
+            let cache_root = crate::manifest::mumei_home().join("registry");
+            let fetched = fetch_git_dependency(&cache_root, dep_name, url, tag, rev, branch, locked.as_deref())?;
+            let short = &fetched.commit[..fetched.commit.len().min(12)];
+            let how = if fetched.cloned { "fetched" } else { "using cached checkout" };
+            notes.push((Severity::Info, format!("Dependency '{}': {} {} at {}", dep_name, how, url, short)));
+            lockfile.set(dep_name, url, &reference, &fetched.commit);
 
             // クローンしたディレクトリからエントリファイルを解決
             let entry_candidates = [
-                clone_dir.join("src/main.mm"),
-                clone_dir.join("main.mm"),
-                clone_dir.join(format!("{}.mm", dep_name)),
+                fetched.dir.join("src/main.mm"),
+                fetched.dir.join("main.mm"),
+                fetched.dir.join(format!("{}.mm", dep_name)),
             ];
             if let Some(entry_path) = entry_candidates.iter().find(|p| p.exists()) {
-                let source = fs::read_to_string(entry_path).map_err(|e| {
+                let source = read_source(entry_path).map_err(|e| {
                     MumeiError::VerificationError(format!(
                         "Failed to read dependency '{}' at '{}': {}",
                         dep_name, entry_path.display(), e
                     ))
                 })?;
-                let items = parser::parse_module(&source);
+                let items = parse_source(&source, entry_path)?;
                 let dep_base_dir = entry_path.parent().unwrap_or(Path::new("."));
                 let cache_path = dep_base_dir.join(".mumei_cache");
                 let mut cache = load_cache(&cache_path);
-                let mut ctx = ResolverContext::new();
+                let mut ctx = ResolverContext::new(std_path);
                 resolve_imports_recursive(&items, dep_base_dir, &mut ctx, &mut cache, module_env)?;
                 save_cache(&cache_path, &cache);
-                register_imported_items(&items, Some(dep_name), module_env);
+                register_imported_items(&items, Some(dep_name), None, &entry_path.display().to_string(), module_env)?;
                 for item in &items {
                     if let Item::Atom(atom) = item {
                         module_env.mark_verified(&atom.name);
@@ -482,7 +895,7 @@ pub fn resolve_manifest_dependencies(
                     }
                 }
             } else {
-                eprintln!("  ⚠️  Dependency '{}': no entry file found in cloned repo", dep_name);
+                notes.push((Severity::Warning, format!("Dependency '{}': no entry file found in '{}'", dep_name, fetched.dir.display())));
             }
         }
         // 名前依存（registry.json から解決 — path でも git でもない場合）
@@ -495,20 +908,20 @@ pub fn resolve_manifest_dependencies(
                     pkg_dir.join(format!("{}.mm", dep_name)),
                 ];
                 if let Some(entry_path) = entry_candidates.iter().find(|p| p.exists()) {
-                    let source = fs::read_to_string(entry_path).map_err(|e| {
+                    let source = read_source(entry_path).map_err(|e| {
                         MumeiError::VerificationError(format!(
                             "Failed to read dependency '{}' at '{}': {}",
                             dep_name, entry_path.display(), e
                         ))
                     })?;
-                    let items = parser::parse_module(&source);
+                    let items = parse_source(&source, entry_path)?;
                     let dep_base_dir = entry_path.parent().unwrap_or(Path::new("."));
                     let cache_path = dep_base_dir.join(".mumei_cache");
                     let mut cache = load_cache(&cache_path);
-                    let mut ctx = ResolverContext::new();
+                    let mut ctx = ResolverContext::new(std_path);
                     resolve_imports_recursive(&items, dep_base_dir, &mut ctx, &mut cache, module_env)?;
                     save_cache(&cache_path, &cache);
-                    register_imported_items(&items, Some(dep_name), module_env);
+                    register_imported_items(&items, Some(dep_name), None, &entry_path.display().to_string(), module_env)?;
                     for item in &items {
                         if let Item::Atom(atom) = item {
                             module_env.mark_verified(&atom.name);
@@ -516,23 +929,183 @@ pub fn resolve_manifest_dependencies(
                             module_env.mark_verified(&fqn);
                         }
                     }
-                    println!("  📦 Dependency '{}': loaded from registry ({})", dep_name, pkg_dir.display());
+                    notes.push((Severity::Info, format!("Dependency '{}': loaded from registry ({})", dep_name, pkg_dir.display())));
                 } else {
-                    eprintln!("  ⚠️  Dependency '{}': found in registry but no entry file in '{}'", dep_name, pkg_dir.display());
+                    notes.push((Severity::Warning, format!("Dependency '{}': found in registry but no entry file in '{}'", dep_name, pkg_dir.display())));
                 }
             } else {
-                eprintln!("  ⚠️  Dependency '{}': not found in local registry. Run `mumei publish` in the dependency project first.", dep_name);
+                notes.push((Severity::Warning, format!("Dependency '{}': not found in local registry. Run `mumei publish` in the dependency project first.", dep_name)));
             }
         }
     }
+    if lockfile != original_lock {
+        lockfile.save(project_dir).map_err(MumeiError::VerificationError)?;
+    }
     Ok(())
 }
 
+/// mumei.toml の [dependencies] の各依存を置くディレクトリ（パス依存・Git 依存のチェックアウト・レジストリのパッケージ）。
+/// 取得していない依存も含めて、resolve_manifest_dependencies が読み込む場所を返す
+pub fn dependency_dirs(manifest: &crate::manifest::Manifest, project_dir: &Path) -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = manifest.dependencies.iter().filter_map(|(dep_name, dep)| {
+        if let Some(dep_path) = dep.as_path() {
+            Some(project_dir.join(dep_path))
+        } else if dep.as_git().is_some() {
+            Some(crate::manifest::mumei_home().join("registry").join(dep_name))
+        } else {
+            crate::registry::resolve(dep_name, dep.version())
+        }
+    }).collect();
+    dirs.sort();
+    dirs
+}
+
+// =============================================================================
+// Git 依存の取得
+// =============================================================================
+//
+// チェックアウトは `<cache_root>/<name>/<commit>/` に置き、コミットごとに不変として扱う。
+// ロック済みのコミットがキャッシュにあれば git を呼ばない（オフラインでもビルドできる）。
+// 取得中は `<name>/.fetch-<pid>` に clone し、コミットが確定してから rename する。
+
+/// 取得した git 依存
+#[derive(Debug)]
+pub struct GitCheckout {
+    /// チェックアウトしたディレクトリ（`<cache_root>/<name>/<commit>`）
+    pub dir: PathBuf,
+    /// 解決したコミットハッシュ
+    pub commit: String,
+    /// 今回 clone したか（false ならキャッシュを使った）
+    pub cloned: bool,
+}
+
+/// git コマンドを実行して標準出力を返す
+fn run_git(args: &[&str], dir: Option<&Path>) -> Result<String, String> {
+    let mut cmd = std::process::Command::new("git");
+    cmd.args(args);
+    if let Some(dir) = dir {
+        cmd.current_dir(dir);
+    }
+    let output = cmd.output().map_err(|e| format!("failed to run git: {}", e))?;
+    if !output.status.success() {
+        return Err(format!("git {} failed: {}", args.join(" "), String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// マニフェストから git に渡す値の検査。`-` で始まる値は git のオプションとして解釈されてしまう
+/// （`git = "--upload-pack=..."` のような推移的な依存からの引数注入）ので拒否する
+fn check_git_arg(what: &str, value: &str) -> Result<(), String> {
+    if value.starts_with('-') {
+        return Err(format!("invalid {} '{}': must not start with '-'", what, value));
+    }
+    Ok(())
+}
+
+/// git 依存を cache_root 以下に取得する。locked（mumei.lock のコミット）があればそれを、
+/// なければ rev / tag / branch（いずれもなければ既定ブランチ）をチェックアウトする。
+/// チェックアウトに mumei.toml がなければ Mumei のパッケージではないのでエラーにする
+pub fn fetch_git_dependency(
+    cache_root: &Path,
+    dep_name: &str,
+    url: &str,
+    tag: Option<&str>,
+    rev: Option<&str>,
+    branch: Option<&str>,
+    locked: Option<&str>,
+) -> MumeiResult<GitCheckout> {
+    let fail = |e: String| MumeiError::VerificationError(format!("Dependency '{}' ({}): {}", dep_name, url, e));
+    // 依存名とコミットはキャッシュのパスになるので、cache_root の外を指せないようにする
+    if dep_name.is_empty() || dep_name.contains(|c: char| c == '/' || c == '\\') || dep_name.contains("..") {
+        return Err(fail(format!("invalid dependency name '{}'", dep_name)));
+    }
+    if let Some(commit) = locked {
+        if !commit.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(fail(format!("invalid locked commit '{}'", commit)));
+        }
+    }
+    check_git_arg("git URL", url)
+        .and_then(|_| tag.map_or(Ok(()), |t| check_git_arg("tag", t)))
+        .and_then(|_| rev.map_or(Ok(()), |r| check_git_arg("rev", r)))
+        .and_then(|_| branch.map_or(Ok(()), |b| check_git_arg("branch", b)))
+        .map_err(fail)?;
+    let package_dir = cache_root.join(dep_name);
+    let pinned = locked.or(rev.filter(|r| r.len() == 40 && r.chars().all(|c| c.is_ascii_hexdigit())));
+
+    let checkout = match pinned.map(|commit| (commit, package_dir.join(commit))) {
+        Some((commit, dir)) if dir.is_dir() => GitCheckout { dir, commit: commit.to_string(), cloned: false },
+        _ => {
+            fs::create_dir_all(&package_dir).map_err(|e| fail(format!("failed to create {}: {}", package_dir.display(), e)))?;
+            let tmp = package_dir.join(format!(".fetch-{}", std::process::id()));
+            let _ = fs::remove_dir_all(&tmp);
+            let tmp_str = tmp.to_string_lossy().to_string();
+            let fetched = (|| {
+                // 特定のコミットが必要なら履歴ごと clone し、そうでなければ浅い clone で十分
+                match (pinned.or(rev), tag.or(branch)) {
+                    (Some(commit), _) => {
+                        run_git(&["clone", "--quiet", "--", url, &tmp_str], None)?;
+                        run_git(&["checkout", "--quiet", "--detach", commit, "--"], Some(&tmp))?;
+                    }
+                    (None, Some(reference)) => run_git(&["clone", "--quiet", "--depth", "1", "--branch", reference, "--", url, &tmp_str], None).map(|_| ())?,
+                    (None, None) => run_git(&["clone", "--quiet", "--depth", "1", "--", url, &tmp_str], None).map(|_| ())?,
+                }
+                run_git(&["rev-parse", "HEAD"], Some(&tmp))
+            })();
+            let commit = match fetched {
+                Ok(commit) => commit,
+                Err(e) => {
+                    let _ = fs::remove_dir_all(&tmp);
+                    return Err(fail(e));
+                }
+            };
+            let dir = package_dir.join(&commit);
+            if dir.is_dir() {
+                // 同じコミットを別の参照から取得済み（または並行して取得された）
+                let _ = fs::remove_dir_all(&tmp);
+            } else if let Err(e) = fs::rename(&tmp, &dir) {
+                let _ = fs::remove_dir_all(&tmp);
+                return Err(fail(format!("failed to move checkout to {}: {}", dir.display(), e)));
+            }
+            GitCheckout { dir, commit, cloned: true }
+        }
+    };
+
+    if !checkout.dir.join("mumei.toml").exists() {
+        return Err(fail(format!("not a Mumei package (no mumei.toml at {})", checkout.dir.display())));
+    }
+    Ok(checkout)
+}
+
+/// リモートの git リポジトリのタグのうち最新のもの（`v` を除いた数値の並びで比較する）。
+/// `mumei add <git URL>` が tag を書き込むのに使う。タグがなければ None
+pub fn latest_git_tag(url: &str) -> Result<Option<String>, String> {
+    check_git_arg("git URL", url)?;
+    let listing = run_git(&["ls-remote", "--tags", "--refs", "--", url], None)?;
+    let version_key = |tag: &str| -> Vec<u64> {
+        tag.trim_start_matches('v').split(|c: char| !c.is_ascii_digit()).filter_map(|n| n.parse().ok()).collect()
+    };
+    Ok(listing.lines()
+        .filter_map(|line| line.split_whitespace().nth(1)?.strip_prefix("refs/tags/"))
+        .max_by(|a, b| version_key(a).cmp(&version_key(b)).then_with(|| a.cmp(b)))
+        .map(str::to_string))
+}
+
 // =============================================================================
 // 検証キャッシュの永続化
 // =============================================================================
 
-/// ソースコードの SHA-256 ハッシュを計算する
+/// Mumei ソースファイルを読み込み、改行と BOM を正規化して返す。
+/// ソースの読み込みはすべてここを通す（パース結果とキャッシュハッシュを改行コードに依存させない）。
+pub fn read_source(path: &Path) -> std::io::Result<String> {
+    fs::read_to_string(path).map(|source| parser::normalize_source(&source))
+}
+
+/// 読み込んだソースをパースする。構文エラーはファイルの位置を指すキャレット付きの表示にする
+pub fn parse_source(source: &str, path: &Path) -> MumeiResult<Vec<Item>> {
+    parser::parse_module(source).map_err(|e| MumeiError::TypeError(e.render(source, &path.display().to_string())))
+}
+
+/// ソースコードの SHA-256 ハッシュを計算する（read_source で正規化済みのテキストを渡すこと）
 fn compute_hash(source: &str) -> String {
     let mut hasher = Sha256::new();
     hasher.update(source.as_bytes());
@@ -540,16 +1113,16 @@ fn compute_hash(source: &str) -> String {
 }
 
 /// Atom の契約+body+メタデータのハッシュを計算する（Incremental Build 用）
-/// 以下のフィールドを結合してハッシュ化する:
+/// 以下のフィールドを結合してハッシュ化する（扱う構造体の定義は compute_struct_hash）:
 /// - name, requires, ensures, body_expr（基本契約）
+/// - パラメータの名前と型、宣言した戻り値型（精緻型・構造体の制約と `result.field` の扱いが決まる）
 /// - consumed_params, ref params（所有権制約）
 /// - resources, async flag（並行性制約）
 /// - invariant（帰納的不変量）
 /// - trust_level, max_unroll（検証設定）
 ///
-/// このハッシュが一致すれば、atom の検証結果は変わらないため再検証をスキップできる。
-/// Call Graph サイクル検知・Taint Analysis の結果も暗黙的にキャッシュされる
-/// （呼び出し先の atom が変更されればハッシュが変わり、呼び出し元も再検証される）。
+/// atom 自身のフィールドしか見ないので、呼び出し先の変更は反映されない。
+/// ビルドキャッシュには pipeline::atom_cache_hashes で呼び出し先のハッシュを合成したものを保存する。
 pub fn compute_atom_hash(atom: &crate::parser::Atom) -> String {
     let mut hasher = Sha256::new();
     hasher.update(atom.name.as_bytes());
@@ -559,6 +1132,22 @@ pub fn compute_atom_hash(atom: &crate::parser::Atom) -> String {
     hasher.update(atom.ensures.as_bytes());
     hasher.update(b"|");
     hasher.update(atom.body_expr.as_bytes());
+    // シグネチャ: パラメータの型と戻り値型（`-> Point`）で仮定・証明するフィールド制約が変わる
+    for p in &atom.params {
+        hasher.update(b"|param:");
+        hasher.update(p.name.as_bytes());
+        hasher.update(b":");
+        hasher.update(p.type_name.as_deref().unwrap_or("").as_bytes());
+    }
+    if let Some(ref return_type) = atom.return_type {
+        hasher.update(b"|returns:");
+        hasher.update(return_type.as_bytes());
+    }
+    // where 節も含める（requires に結合済みだが、節の付け替えも変更として扱う）
+    if let Some(ref clause) = atom.where_clause {
+        hasher.update(b"|where:");
+        hasher.update(clause.as_bytes());
+    }
     // consumed_params も含める（所有権制約の変更を検出）
     for cp in &atom.consumed_params {
         hasher.update(b"|consume:");
@@ -584,11 +1173,20 @@ pub fn compute_atom_hash(atom: &crate::parser::Atom) -> String {
     if atom.is_async {
         hasher.update(b"|async");
     }
+    // total フラグも含める（義務の扱いが厳しくなるため再検証が必要）
+    if atom.is_total {
+        hasher.update(b"|total");
+    }
     // invariant も含める
     if let Some(ref inv) = atom.invariant {
         hasher.update(b"|invariant:");
         hasher.update(inv.as_bytes());
     }
+    // atom レベルの decreases も含める（自己再帰呼び出しの義務が変わる）
+    if let Some(ref measure) = atom.decreases {
+        hasher.update(b"|decreases:");
+        hasher.update(measure.as_bytes());
+    }
     // trust_level も含める（信頼レベルの変更を検出）
     let trust_str = match atom.trust_level {
         crate::parser::TrustLevel::Verified => "verified",
@@ -605,28 +1203,171 @@ pub fn compute_atom_hash(atom: &crate::parser::Atom) -> String {
     format!("{:x}", hasher.finalize())
 }
 
+/// atom が扱う構造体の定義のハッシュ（Incremental Build 用）。対象はパラメータの型・宣言した戻り値型・
+/// body の構造体リテラルに現れる構造体と、そのフィールドの型に現れる構造体。
+/// フィールド・フィールド制約・invariant は構造体パラメータで仮定し、構造体リテラルと戻り値で証明するので、
+/// 定義を変えればそれを扱う atom はキャッシュを外れる
+pub fn compute_struct_hash(atom: &parser::Atom, module_env: &ModuleEnv) -> String {
+    let mut pending: Vec<String> = atom.params.iter().filter_map(|p| p.type_name.clone()).collect();
+    pending.extend(atom.return_type.iter().cloned());
+    if let Ok(body) = parser::parse_expression(&atom.body_expr) {
+        body.walk(|e| {
+            if let parser::Expr::StructInit { type_name, .. } = e {
+                pending.push(type_name.clone());
+            }
+            true
+        });
+    }
+    let mut structs = BTreeMap::new();
+    while let Some(name) = pending.pop() {
+        if structs.contains_key(&name) {
+            continue;
+        }
+        if let Some(sdef) = module_env.get_struct(&name) {
+            pending.extend(sdef.fields.iter().map(|f| f.type_name.clone()));
+            structs.insert(name, sdef);
+        }
+    }
+    let mut hasher = Sha256::new();
+    for (name, sdef) in structs {
+        hasher.update(format!("|struct:{}", name).as_bytes());
+        for field in &sdef.fields {
+            hasher.update(format!("|field:{}:{}:{:?}", field.name, field.type_name, field.constraint).as_bytes());
+        }
+        if let Some(ref invariant) = sdef.invariant {
+            hasher.update(b"|invariant:");
+            hasher.update(invariant.as_bytes());
+        }
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+/// impl の law 検証結果のキャッシュキー。次のいずれかが変われば別のハッシュになる:
+/// - コンパイラのバージョン（組み込みトレイトの定義や law 検証の変更）
+/// - trait と、extends で継承するすべての trait のメソッドシグネチャ・law・リネーム表
+/// - impl のメソッド本体
+/// - 対象型が精緻型なら、その述語（law 検証の前提になる）
+pub fn compute_impl_hash(impl_def: &parser::ImplDef, module_env: &ModuleEnv) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(env!("CARGO_PKG_VERSION").as_bytes());
+    hasher.update(b"|impl:");
+    hasher.update(impl_def.trait_name.as_bytes());
+    hasher.update(b" for ");
+    hasher.update(impl_def.target_type.as_bytes());
+    for (method, body) in &impl_def.method_bodies {
+        hasher.update(format!("|body:{}={}", method, body).as_bytes());
+    }
+    if let Some(refined) = module_env.get_type(&impl_def.target_type) {
+        hasher.update(format!("|refined:{}:{}:{}", refined._base_type, refined.operand, refined.predicate_raw).as_bytes());
+    }
+    hash_trait_closure(&impl_def.trait_name, module_env, &mut hasher, &mut Vec::new());
+    format!("{:x}", hasher.finalize())
+}
+
+/// trait とその親 trait の定義をハッシュに加える（継承の循環は 1 回ずつ）
+fn hash_trait_closure(name: &str, module_env: &ModuleEnv, hasher: &mut Sha256, visited: &mut Vec<String>) {
+    if visited.iter().any(|v| v == name) {
+        return;
+    }
+    visited.push(name.to_string());
+    let trait_def = match module_env.get_trait(name) {
+        Some(t) => t,
+        None => {
+            hasher.update(format!("|missing-trait:{}", name).as_bytes());
+            return;
+        }
+    };
+    hasher.update(format!("|trait:{}", trait_def.name).as_bytes());
+    for method in &trait_def.methods {
+        hasher.update(format!(
+            "|fn:{}({:?}/{:?})->{}", method.name, method.param_types, method.param_constraints, method.return_type
+        ).as_bytes());
+    }
+    for (law, expr) in &trait_def.laws {
+        hasher.update(format!("|law:{}={}", law, expr).as_bytes());
+    }
+    for (parent, renames) in &trait_def.extends {
+        let renames: BTreeMap<&String, &String> = renames.iter().collect();
+        hasher.update(format!("|extends:{}{:?}", parent, renames).as_bytes());
+        hash_trait_closure(parent, module_env, hasher, visited);
+    }
+}
+
+/// Incremental Build 用のビルドキャッシュ（.mumei_build_cache）
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct BuildCache {
+    /// スキーマバージョン（旧形式では 0）
+    #[serde(default)]
+    pub version: u32,
+    /// atom 名 → compute_atom_hash の結果
+    #[serde(default, serialize_with = "serialize_sorted")]
+    pub atoms: HashMap<String, String>,
+    /// atom 名 → 証明済み義務キー（"種別:正規化ハッシュ"）の集合。
+    /// atom が変更された場合でも、ハッシュが一致する義務は再証明をスキップする。
+    #[serde(default, serialize_with = "serialize_sorted_sets")]
+    pub obligations: HashMap<String, HashSet<String>>,
+    /// impl 名（"Trait for Type"）→ compute_impl_hash の結果。law 検証に成功した impl のみ
+    #[serde(default, serialize_with = "serialize_sorted")]
+    pub impls: HashMap<String, String>,
+}
+
+/// キー順に書き出す（検証の順序や並列度に関わらず .mumei_build_cache を同じ内容にする）
+fn serialize_sorted<S: serde::Serializer>(map: &HashMap<String, String>, serializer: S) -> Result<S::Ok, S::Error> {
+    map.iter().collect::<BTreeMap<_, _>>().serialize(serializer)
+}
+
+/// キー順・要素順に書き出す
+fn serialize_sorted_sets<S: serde::Serializer>(map: &HashMap<String, HashSet<String>>, serializer: S) -> Result<S::Ok, S::Error> {
+    map.iter()
+        .map(|(name, keys)| (name, keys.iter().collect::<BTreeSet<_>>()))
+        .collect::<BTreeMap<_, _>>()
+        .serialize(serializer)
+}
+
 /// Incremental Build 用: メインファイルのビルドキャッシュをロードする
-pub fn load_build_cache(base_dir: &Path) -> HashMap<String, String> {
+/// 旧形式（atom 名 → ハッシュのフラットなマップ）と、現行より新しいスキーマのファイルは
+/// 読み捨てて空のキャッシュとして扱う。
+pub fn load_build_cache(base_dir: &Path) -> BuildCache {
     let cache_path = base_dir.join(".mumei_build_cache");
     fs::read_to_string(&cache_path)
         .ok()
-        .and_then(|content| serde_json::from_str(&content).ok())
+        .and_then(|content| serde_json::from_str::<BuildCache>(&content).ok())
+        .filter(|cache| cache.version <= CACHE_SCHEMA_VERSION)
         .unwrap_or_default()
 }
 
-/// Incremental Build 用: メインファイルのビルドキャッシュを保存する
-pub fn save_build_cache(base_dir: &Path, cache: &HashMap<String, String>) {
+/// Incremental Build 用: メインファイルのビルドキャッシュを保存する。
+/// live_items（今回処理したソースに存在する atom 名と impl 名）にないエントリは保存せず、
+/// 前回のキャッシュから刈り込んだ名前を返す（ソート済み）
+pub fn save_build_cache(base_dir: &Path, cache: &BuildCache, live_items: &HashSet<String>) -> Vec<String> {
+    let previous = load_build_cache(base_dir);
+    let mut pruned: Vec<String> = previous.atoms.keys()
+        .chain(previous.obligations.keys())
+        .chain(previous.impls.keys())
+        .filter(|name| !live_items.contains(*name))
+        .cloned()
+        .collect();
+    pruned.sort();
+    pruned.dedup();
+
+    let mut cache = cache.clone();
+    cache.version = CACHE_SCHEMA_VERSION;
+    cache.atoms.retain(|name, _| live_items.contains(name));
+    cache.obligations.retain(|name, _| live_items.contains(name));
+    cache.impls.retain(|name, _| live_items.contains(name));
     let cache_path = base_dir.join(".mumei_build_cache");
-    if let Ok(json) = serde_json::to_string_pretty(cache) {
+    if let Ok(json) = serde_json::to_string_pretty(&cache) {
         let _ = fs::write(cache_path, json);
     }
+    pruned
 }
 
-/// キャッシュファイルを読み込む。存在しない場合は空のキャッシュを返す。
+/// キャッシュファイルを読み込む。存在しない場合・現行より新しいスキーマの場合は空のキャッシュを返す。
 fn load_cache(cache_path: &Path) -> VerificationCache {
     fs::read_to_string(cache_path)
         .ok()
-        .and_then(|content| serde_json::from_str(&content).ok())
+        .and_then(|content| serde_json::from_str::<VerificationCache>(&content).ok())
+        .filter(|cache| cache.version <= CACHE_SCHEMA_VERSION)
         .unwrap_or_default()
 }
 
@@ -636,3 +1377,326 @@ fn save_cache(cache_path: &Path, cache: &VerificationCache) {
         let _ = fs::write(cache_path, json);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    const LIB: &str = "export { clamp };\n\
+        atom helper(x: i64) requires: true; ensures: result >= 0; body: x * x;\n\
+        atom clamp(x: i64) requires: true; ensures: result >= 0; body: helper(x);\n";
+
+    fn project(name: &str, lib: &str) -> TempDir {
+        let dir = TempDir::new(&format!("resolver_{}", name));
+        fs::write(dir.join("lib.mm"), lib).unwrap();
+        dir
+    }
+
+    fn resolve(dir: &Path, main: &str, module_env: &mut ModuleEnv) -> MumeiResult<Vec<String>> {
+        let items = parser::parse_module(main).unwrap();
+        resolve_imports(&items, dir, None, module_env)
+    }
+
+    #[test]
+    fn test_exported_atom_may_call_private_helper() {
+        let dir = project("exported", LIB);
+        let mut module_env = ModuleEnv::new();
+        let main = "import \"./lib.mm\" as lib;\n\
+            atom f(x: i64) requires: true; ensures: result >= 0; body: clamp(x);\n";
+        resolve(&dir, main, &mut module_env).unwrap();
+        // 公開 atom の契約が参照できるよう helper 自体は登録されるが FQN は公開しない
+        assert!(module_env.get_atom("helper").is_some());
+        assert!(module_env.get_atom("lib::clamp").is_some());
+        assert!(module_env.get_atom("lib::helper").is_none());
+        assert_eq!(module_env.private_items["helper"].exports, vec!["clamp"]);
+    }
+
+    #[test]
+    fn test_private_helper_rejected_from_importer() {
+        let dir = project("private", LIB);
+        let mut module_env = ModuleEnv::new();
+        let main = "import \"./lib.mm\" as lib;\n\
+            atom f(x: i64) requires: true; ensures: result >= 0; body: lib::helper(x);\n";
+        let err = resolve(&dir, main, &mut module_env).unwrap_err().to_string();
+        assert!(err.contains("atom 'helper' is private to module"), "{}", err);
+        assert!(err.contains("referenced from atom 'f'"), "{}", err);
+        assert!(err.contains("Exported items: clamp"), "{}", err);
+
+        // 同名の atom を自モジュールで定義していればそちらを指す
+        let mut module_env = ModuleEnv::new();
+        let shadowed = "import \"./lib.mm\" as lib;\n\
+            atom helper(x: i64) requires: true; ensures: true; body: x;\n\
+            atom f(x: i64) requires: true; ensures: true; body: helper(x);\n";
+        resolve(&dir, shadowed, &mut module_env).unwrap();
+    }
+
+    #[test]
+    fn test_module_without_exports_is_public() {
+        let lib = "atom helper(x: i64) requires: true; ensures: result >= 0; body: x * x;\n";
+        let dir = project("compat", lib);
+        let main = "import \"./lib.mm\" as lib;\n\
+            atom f(x: i64) requires: true; ensures: result >= 0; body: helper(x);\n";
+        let mut module_env = ModuleEnv::new();
+        resolve(&dir, main, &mut module_env).unwrap();
+        assert!(module_env.get_atom("lib::helper").is_some());
+        assert!(module_env.private_items.is_empty());
+
+        // [build] require_explicit_exports = true では export 宣言のないモジュールを拒否する
+        let mut module_env = ModuleEnv::new();
+        module_env.require_explicit_exports = true;
+        let err = resolve(&dir, main, &mut module_env).unwrap_err().to_string();
+        assert!(err.contains("has no `export { ... };` list"), "{}", err);
+    }
+
+    const GEO: &str = "struct Vec2 { x: i64, y: i64 }\n\
+        atom add(x: i64, y: i64) requires: true; ensures: result == x + y; body: x + y;\n\
+        atom clamp(x: i64) requires: true; ensures: result >= 0; body: if x < 0 { 0 } else { x };\n";
+
+    #[test]
+    fn test_import_using_limits_bare_names() {
+        let dir = project("using", GEO);
+        let mut module_env = ModuleEnv::new();
+        let main = "import \"./lib.mm\" as geo using {add, Vec2};\n\
+            atom f(x: i64) requires: true; ensures: true; body: add(x, geo::clamp(x));\n";
+        resolve(&dir, main, &mut module_env).unwrap();
+        // FQN ではすべて参照できる
+        assert!(module_env.get_atom("geo::clamp").is_some() && module_env.get_struct("geo::Vec2").is_some());
+
+        let main = "import \"./lib.mm\" as geo using {add, Vec2};\n\
+            atom f(x: i64) requires: true; ensures: true; body: add(x, clamp(x));\n";
+        let err = resolve(&dir, main, &mut ModuleEnv::new()).unwrap_err().to_string();
+        assert!(err.contains("atom 'clamp' is not imported from module"), "{}", err);
+        assert!(err.contains("referenced from atom 'f'") && err.contains("using { add, Vec2 }"), "{}", err);
+
+        let err = resolve(&dir, "import \"./lib.mm\" using {add, mul};\n", &mut ModuleEnv::new()).unwrap_err().to_string();
+        assert!(err.contains("lists 'mul', which module") && err.contains("does not define"), "{}", err);
+    }
+
+    #[test]
+    fn test_conflicting_bare_imports_name_both_files() {
+        let dir = project("collision", GEO);
+        fs::write(dir.join("other.mm"), "atom clamp(x: i64) requires: true; ensures: result <= 100; body: if x > 100 { 100 } else { x };\n\
+            atom lerp(a: i64, b: i64) requires: true; ensures: true; body: (a + b) / 2;\n").unwrap();
+        let err = resolve(&dir, "import \"./lib.mm\";\nimport \"./other.mm\";\n", &mut ModuleEnv::new()).unwrap_err().to_string();
+        assert!(err.contains("Name collision: atom 'clamp' is defined differently in"), "{}", err);
+        assert!(err.contains("lib.mm") && err.contains("other.mm"), "{}", err);
+
+        // using で clamp を取り込まなければ衝突せず、素の clamp は lib.mm の定義のまま
+        let mut module_env = ModuleEnv::new();
+        resolve(&dir, "import \"./lib.mm\";\nimport \"./other.mm\" as other using {lerp};\n", &mut module_env).unwrap();
+        assert_eq!(module_env.get_atom("clamp").unwrap().ensures, "result >= 0");
+        assert_eq!(module_env.get_atom("other::clamp").unwrap().ensures, "result <= 100");
+
+        // 同じ定義なら別ファイルでも衝突にしない
+        fs::write(dir.join("same.mm"), "atom add(x: i64, y: i64) requires: true; ensures: result == x + y; body: x + y;\n").unwrap();
+        resolve(&dir, "import \"./lib.mm\";\nimport \"./same.mm\";\n", &mut ModuleEnv::new()).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlinked_and_hard_linked_imports_load_once() {
+        let dir = project("identity", "atom clamp(x: i64) requires: true; ensures: result >= 0; body: x * x;\n");
+        fs::create_dir_all(dir.join("real")).unwrap();
+        fs::rename(dir.join("lib.mm"), dir.join("real/lib.mm")).unwrap();
+        std::os::unix::fs::symlink(dir.join("real"), dir.join("linked")).unwrap();
+        fs::hard_link(dir.join("real/lib.mm"), dir.join("real/lib_alias.mm")).unwrap();
+        // ダイヤモンド: main と mid がシンボリックリンク経由・ハードリンク経由で同じファイルを import する
+        fs::write(dir.join("mid.mm"), "import \"./linked/lib.mm\";\nimport \"./real/lib_alias.mm\";\n").unwrap();
+        let main = parser::parse_module("import \"./real/lib.mm\";\nimport \"./mid.mm\";\n").unwrap();
+
+        let closure = import_closure(&main, &dir).unwrap();
+        assert_eq!(closure.len(), 2, "{:?}", closure);
+        let mut module_env = ModuleEnv::new();
+        resolve_imports(&main, &dir, None, &mut module_env).unwrap();
+        assert!(module_env.is_verified("clamp"));
+
+        // 本物の循環はリンク経由でも検出し、エラーには利用者が書いたパスを出す
+        fs::write(dir.join("real/lib.mm"), "import \"../linked/lib_alias.mm\";\n").unwrap();
+        let err = import_closure(&main, &dir).unwrap_err().to_string();
+        assert!(err.contains("Circular import detected: '../linked/lib_alias.mm'"), "{}", err);
+    }
+
+    #[test]
+    fn test_case_variant_import_loads_once() {
+        let dir = project("case", "atom clamp(x: i64) requires: true; ensures: result >= 0; body: x * x;\n");
+        // 大文字小文字を区別するファイルシステムでは別ファイルなので検査しない
+        if !dir.join("LIB.mm").exists() {
+            return;
+        }
+        let main = parser::parse_module("import \"./lib.mm\";\nimport \"./LIB.mm\";\n").unwrap();
+        assert_eq!(import_closure(&main, &dir).unwrap().len(), 1);
+    }
+
+    fn read_verification_cache(dir: &Path) -> VerificationCache {
+        serde_json::from_str(&fs::read_to_string(dir.join(".mumei_cache")).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_verification_cache_prunes_renamed_atoms_and_dropped_files() {
+        let dir = project("prune", "atom clamp(x: i64) requires: true; ensures: result >= 0; body: x * x;\n");
+        fs::write(dir.join("other.mm"), "atom other(x: i64) requires: true; ensures: true; body: x;\n").unwrap();
+        let key = |file: &str| resolve_path(file, &dir).unwrap().to_string_lossy().to_string();
+        let (lib_key, other_key) = (key("./lib.mm"), key("./other.mm"));
+
+        // 旧形式（バージョンなし）のキャッシュに、存在しないファイルのエントリを残しておく
+        fs::write(dir.join(".mumei_cache"), r#"{"entries": {"/gone/module.mm": {"source_hash": "x", "verified_atoms": ["ghost"], "type_names": [], "struct_names": []}}}"#).unwrap();
+        let mut module_env = ModuleEnv::new();
+        let pruned = resolve(&dir, "import \"./lib.mm\";\nimport \"./other.mm\";\n", &mut module_env).unwrap();
+        assert_eq!(pruned, vec!["/gone/module.mm".to_string()]);
+        let cache = read_verification_cache(&dir);
+        assert_eq!(cache.version, CACHE_SCHEMA_VERSION);
+        assert_eq!(cache.entries[&lib_key].verified_atoms, vec!["clamp"]);
+        assert!(cache.entries.contains_key(&other_key));
+
+        // clamp を clamp_nonneg に改名し、other.mm の import をやめる
+        fs::write(dir.join("lib.mm"), "atom clamp_nonneg(x: i64) requires: true; ensures: result >= 0; body: x * x;\n").unwrap();
+        let mut module_env = ModuleEnv::new();
+        let pruned = resolve(&dir, "import \"./lib.mm\";\n", &mut module_env).unwrap();
+        assert_eq!(pruned, vec![format!("{}: clamp", lib_key), other_key.clone()]);
+        let cache = read_verification_cache(&dir);
+        assert_eq!(cache.entries[&lib_key].verified_atoms, vec!["clamp_nonneg"]);
+        assert!(!cache.entries.contains_key(&other_key));
+        assert!(module_env.is_verified("clamp_nonneg"));
+        assert!(!module_env.is_verified("clamp"));
+    }
+
+    #[test]
+    fn test_trusted_atoms_outside_std_and_dependencies() {
+        let dir = project("trusted", "trusted atom lib_clock() ensures: result >= 0; body: 0;\n\
+            atom lib_ok(x: i64) requires: true; ensures: true; body: x;\n");
+        fs::create_dir_all(dir.join("vendor/sys")).unwrap();
+        fs::write(dir.join("vendor/sys/main.mm"), "trusted atom syscall() ensures: result >= 0; body: 0;\n").unwrap();
+        let main = dir.join("main.mm");
+        fs::write(&main, "import \"./lib.mm\";\nimport \"./vendor/sys/main.mm\" as sys;\n\
+            trusted atom read_clock() ensures: result >= 0; body: { 0 };\n\
+            atom f(x: i64) requires: true; ensures: true; body: x;\n").unwrap();
+        let names = |found: Vec<(PathBuf, String)>| -> Vec<String> {
+            found.into_iter().map(|(path, name)| format!("{}:{}", path.file_name().unwrap().to_string_lossy(), name)).collect()
+        };
+
+        // 依存パッケージのディレクトリ以下のモジュールは信頼の境界に含めない
+        assert_eq!(names(trusted_atoms(&main, &[dir.join("vendor")]).unwrap()), ["main.mm:read_clock", "lib.mm:lib_clock"]);
+        assert_eq!(names(trusted_atoms(&main, &[]).unwrap()), ["main.mm:read_clock", "lib.mm:lib_clock", "main.mm:syscall"]);
+    }
+
+    #[test]
+    fn test_build_cache_prunes_renamed_atoms() {
+        let dir = project("build_cache", "");
+        let live = |names: &[&str]| names.iter().map(|n| n.to_string()).collect::<HashSet<String>>();
+
+        // 1 回目: clamp を検証済みとして保存（旧形式のファイルは上書きで移行される）
+        fs::write(dir.join(".mumei_build_cache"), r#"{"atoms": {"clamp": "old"}}"#).unwrap();
+        assert_eq!(load_build_cache(&dir).atoms["clamp"], "old");
+        let mut first = BuildCache::default();
+        first.atoms.insert("clamp".into(), "h1".into());
+        first.obligations.insert("clamp".into(), HashSet::from(["ensures:abc".to_string()]));
+        assert!(save_build_cache(&dir, &first, &live(&["clamp"])).is_empty());
+        assert_eq!(load_build_cache(&dir).version, CACHE_SCHEMA_VERSION);
+
+        // 2 回目: clamp を clamp_nonneg に改名。新しい名前はキャッシュにないので一から検証される
+        let previous = load_build_cache(&dir);
+        assert!(!previous.atoms.contains_key("clamp_nonneg"));
+        let mut second = BuildCache::default();
+        second.atoms.insert("clamp_nonneg".into(), "h2".into());
+        // 前回のキャッシュを引き継いだ場合でも、ソースにない atom は保存されない
+        second.obligations = previous.obligations.clone();
+        let pruned = save_build_cache(&dir, &second, &live(&["clamp_nonneg"]));
+        assert_eq!(pruned, vec!["clamp".to_string()]);
+        let saved = load_build_cache(&dir);
+        assert_eq!(saved.atoms.keys().collect::<Vec<_>>(), vec!["clamp_nonneg"]);
+        assert!(saved.obligations.is_empty());
+
+        // 現行より新しいスキーマのファイルは読み捨てる
+        fs::write(dir.join(".mumei_build_cache"), format!(r#"{{"version": {}, "atoms": {{"clamp": "h"}}}}"#, CACHE_SCHEMA_VERSION + 1)).unwrap();
+        assert!(load_build_cache(&dir).atoms.is_empty());
+    }
+
+    #[test]
+    fn test_git_dependency_is_cached_per_commit() {
+        if run_git(&["--version"], None).is_err() {
+            return;
+        }
+        let dir = project("git_dep", "");
+        let upstream = dir.join("upstream");
+        fs::create_dir_all(upstream.join("src")).unwrap();
+        let git = |args: &[&str]| run_git(&[&["-c", "user.name=mumei", "-c", "user.email=mumei@example.com"][..], args].concat(), Some(&upstream)).unwrap();
+        git(&["init", "--quiet"]);
+        fs::write(upstream.join("mumei.toml"), "[package]\nname = \"math_mm\"\nversion = \"1.2.0\"\n").unwrap();
+        fs::write(upstream.join("src/main.mm"), LIB).unwrap();
+        git(&["add", "-A"]);
+        git(&["commit", "--quiet", "-m", "v1.2.0"]);
+        git(&["tag", "v1.2.0"]);
+        let tagged = git(&["rev-parse", "HEAD"]);
+        fs::write(upstream.join("README.md"), "math").unwrap();
+        git(&["add", "-A"]);
+        git(&["commit", "--quiet", "-m", "v1.10.0"]);
+        git(&["tag", "v1.10.0"]);
+        let url = format!("file://{}", upstream.display());
+
+        // タグは数値として比較する（v1.10.0 > v1.2.0）
+        assert_eq!(latest_git_tag(&url).unwrap().as_deref(), Some("v1.10.0"));
+
+        // tag を clone して <name>/<commit> に置く
+        let cache_root = dir.join("registry");
+        let fetched = fetch_git_dependency(&cache_root, "math_mm", &url, Some("v1.2.0"), None, None, None).unwrap();
+        assert!(fetched.cloned);
+        assert_eq!(fetched.commit, tagged);
+        assert_eq!(fetched.dir, cache_root.join("math_mm").join(&tagged));
+        assert!(fetched.dir.join("src/main.mm").exists());
+
+        // ロック済みのコミットがキャッシュにあれば、取得元がなくても（オフラインでも）使える
+        fs::remove_dir_all(&upstream).unwrap();
+        let cached = fetch_git_dependency(&cache_root, "math_mm", &url, Some("v1.10.0"), None, None, Some(&tagged)).unwrap();
+        assert!(!cached.cloned);
+        assert_eq!(cached.dir, fetched.dir);
+        let err = fetch_git_dependency(&cache_root, "math_mm", &url, Some("v1.10.0"), None, None, None).unwrap_err().to_string();
+        assert!(err.contains("Dependency 'math_mm'"), "{}", err);
+
+        // mumei.toml のないリポジトリは Mumei のパッケージとして扱わない
+        fs::create_dir_all(&upstream).unwrap();
+        git(&["init", "--quiet"]);
+        fs::write(upstream.join("main.mm"), LIB).unwrap();
+        git(&["add", "-A"]);
+        git(&["commit", "--quiet", "-m", "no manifest"]);
+        let err = fetch_git_dependency(&cache_root, "plain", &url, None, None, None, None).unwrap_err().to_string();
+        assert!(err.contains("not a Mumei package"), "{}", err);
+    }
+
+    #[test]
+    fn test_git_dependency_rejects_option_like_values() {
+        let cache_root = std::env::temp_dir().join(format!("mumei_git_args_{}", std::process::id()));
+        let fetch = |name: &str, url: &str, rev: Option<&str>, locked: Option<&str>| {
+            fetch_git_dependency(&cache_root, name, url, None, rev, None, locked).unwrap_err().to_string()
+        };
+        // `-` で始まる値は git のオプションになるので、git を呼ぶ前に拒否する
+        let err = fetch("evil", "--upload-pack=touch /tmp/pwned", None, None);
+        assert!(err.contains("invalid git URL") && err.contains("must not start with '-'"), "{}", err);
+        let err = fetch("evil", "https://example.com/x.git", Some("-oops"), None);
+        assert!(err.contains("invalid rev"), "{}", err);
+        // 依存名・ロック済みコミットはキャッシュのパスになるので外に出られない
+        for name in ["../escape", "a/b", ".."] {
+            let err = fetch(name, "https://example.com/x.git", None, None);
+            assert!(err.contains("invalid dependency name"), "{}", err);
+        }
+        let err = fetch("ok", "https://example.com/x.git", None, Some("../../etc"));
+        assert!(err.contains("invalid locked commit"), "{}", err);
+        assert!(latest_git_tag("--upload-pack=touch /tmp/pwned").is_err());
+        assert!(!cache_root.exists());
+    }
+
+    #[test]
+    fn test_std_path_is_passed_explicitly() {
+        let dir = TempDir::new("std_path");
+        let std_dir = dir.join("custom_std");
+        fs::create_dir_all(&std_dir).unwrap();
+        fs::write(std_dir.join("only_here.mm"), "atom f(x: i64) requires: true; ensures: true; body: x;\n").unwrap();
+
+        // 環境変数を書き換えず、呼び出し側が渡した std_path から解決する
+        let resolved = resolve_path_in("std/only_here", &dir, Some(&std_dir)).unwrap();
+        assert_eq!(resolved, std_dir.join("only_here.mm").canonicalize().unwrap());
+        if std::env::var_os("MUMEI_STD_PATH").is_none() {
+            assert!(resolve_path_in("std/only_here", &dir, None).is_err());
+        }
+    }
+}
@@ -0,0 +1,246 @@
+// =============================================================================
+// SARIF 2.1.0 出力 (`mumei verify --sarif <path>` / `mumei build --sarif <path>`)
+// =============================================================================
+//
+// GitHub code scanning 等に検証失敗を取り込むための SARIF ログを書き出す。
+// report.json と内容が乖離しないよう、SARIF は crate::report::Report の
+// シリアライザとしてのみ実装する（検証ループから直接組み立てない）。
+//
+// 対応関係:
+// - 失敗カテゴリ（postcondition / precondition / law / exhaustiveness ...）→ rule
+// - 失敗した atom / impl 1 件 → result（level: error）
+// - Report の diagnostics → result（rule: diagnostic、level は severity に従う）
+// - 位置情報はまだ span がないため、入力ファイル単位の location と
+//   atom 名の logicalLocation で表す
+
+use crate::report::{AtomResult, AtomStatus, FailureKind, ItemKind, Report, Severity};
+use serde_json::{json, Value};
+use std::fs;
+use std::path::Path;
+
+pub const SARIF_VERSION: &str = "2.1.0";
+pub const SARIF_SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+
+/// 失敗カテゴリ（rule）: (id, 短い説明)
+const RULES: &[(&str, &str)] = &[
+    ("postcondition", "Postcondition (ensures) not proven"),
+    ("precondition", "Precondition (requires) not proven at a call site"),
+    ("law", "Trait law not satisfied by an impl"),
+    ("exhaustiveness", "Match is not exhaustive"),
+    ("linearity", "Ownership / linearity violation"),
+    ("termination", "Termination (decreases) not proven"),
+    ("totality", "Total atom has a missing termination measure or an undecided obligation"),
+    ("loop-invariant", "Loop or atom invariant not proven"),
+    ("overflow", "Integer overflow or literal out of range"),
+    ("division-by-zero", "Potential division by zero"),
+    ("bounds", "Potential out-of-bounds access"),
+    ("presence", "Nullable value may be None"),
+    ("refinement", "Refinement or field constraint violated"),
+    ("resource", "Resource hierarchy, data race or await safety violation"),
+    ("solver-limit", "Z3 exceeded the time or memory limit"),
+    ("verification", "Other verification failure"),
+    ("diagnostic", "Non-fatal diagnostic"),
+];
+
+/// 失敗の rule id（impl の失敗は law、atom の失敗は検証器が記録した失敗カテゴリ）
+pub fn failure_rule(result: &AtomResult) -> &'static str {
+    if result.kind == ItemKind::Impl {
+        return FailureKind::Law.as_str();
+    }
+    result.failure.unwrap_or_default().as_str()
+}
+
+fn rule_index(id: &str) -> usize {
+    RULES.iter().position(|(rule_id, _)| *rule_id == id).unwrap_or(RULES.len() - 1)
+}
+
+fn location(report: &Report, logical_name: Option<&str>) -> Value {
+    let mut loc = json!({});
+    if !report.module.path.is_empty() {
+        loc["physicalLocation"] = json!({ "artifactLocation": { "uri": report.module.path } });
+    }
+    if let Some(name) = logical_name {
+        loc["logicalLocations"] = json!([{ "name": name, "kind": "function" }]);
+    }
+    loc
+}
+
+/// 失敗した atom / impl 1 件分の result
+fn failure_result(report: &Report, atom: &AtomResult) -> Value {
+    let rule = failure_rule(atom);
+    let message = atom.message.as_deref().unwrap_or("verification failed");
+    let label = match atom.kind {
+        ItemKind::Impl => format!("impl {}", atom.name),
+        _ => format!("atom '{}'", atom.name),
+    };
+    let summary = message.lines().next().unwrap_or(message);
+    let mut markdown = format!("**{}**: {}\n\n```\n{}\n```", label, summary, message);
+    if let Some(ce) = &atom.counterexample {
+        let pretty = serde_json::to_string_pretty(ce).unwrap_or_default();
+        markdown.push_str(&format!("\n\nCounterexample:\n\n```json\n{}\n```", pretty));
+    }
+    json!({
+        "ruleId": rule,
+        "ruleIndex": rule_index(rule),
+        "level": "error",
+        "message": { "text": format!("{}: {}", label, summary), "markdown": markdown },
+        "locations": [location(report, Some(&atom.name))],
+    })
+}
+
+/// Report を SARIF 2.1.0 ログに変換する
+pub fn to_sarif(report: &Report) -> Value {
+    let rules: Vec<Value> = RULES.iter().map(|(id, description)| json!({
+        "id": id,
+        "name": id,
+        "shortDescription": { "text": description },
+        "defaultConfiguration": { "level": if *id == "diagnostic" { "warning" } else { "error" } },
+    })).collect();
+
+    let mut results: Vec<Value> = report.atoms.iter()
+        .filter(|a| a.status == AtomStatus::Failed)
+        .map(|a| failure_result(report, a))
+        .collect();
+    for d in &report.diagnostics {
+        let level = match d.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            _ => "note",
+        };
+        results.push(json!({
+            "ruleId": "diagnostic",
+            "ruleIndex": rule_index("diagnostic"),
+            "level": level,
+            "message": { "text": d.message },
+            "locations": [location(report, d.atom.as_deref())],
+        }));
+    }
+
+    json!({
+        "$schema": SARIF_SCHEMA,
+        "version": SARIF_VERSION,
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "mumei",
+                    "version": report.tool.mumei_version,
+                    "informationUri": "https://github.com/mumei-lang/mumei",
+                    "rules": rules,
+                    "properties": { "z3Version": report.tool.z3_version },
+                }
+            },
+            "invocations": [{
+                "executionSuccessful": true,
+                "properties": { "command": report.command },
+            }],
+            "results": results,
+        }]
+    })
+}
+
+/// SARIF ログを path に書き出す
+pub fn save(report: &Report, path: &Path) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        if !parent.as_os_str().is_empty() {
+            fs::create_dir_all(parent)?;
+        }
+    }
+    let text = serde_json::to_string_pretty(&to_sarif(report)).map_err(std::io::Error::other)?;
+    fs::write(path, text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_report() -> Report {
+        let mut report = Report::new("verify", "src/main.mm");
+        report.push(AtomResult::new("add", ItemKind::Atom, AtomStatus::Verified));
+        report.push(AtomResult::new("clamp", ItemKind::Atom, AtomStatus::Failed).with_failure(
+            FailureKind::Postcondition,
+            "Verification Error: Postcondition (ensures) is not satisfied.\n{\"failure\": \"Postcondition\", \"counterexample\": {\"values\": [{\"name\": \"x\", \"value\": \"-1\"}]}}",
+        ));
+        report.push(AtomResult::new("Eq for i64", ItemKind::Impl, AtomStatus::Failed)
+            .with_error("impl Eq for i64: law 'reflexive' is not satisfied"));
+        report.push(AtomResult::new("pick", ItemKind::Atom, AtomStatus::Failed)
+            .with_failure(FailureKind::Exhaustiveness, "Verification Error: Match is not exhaustive: there exist values not covered by any arm."));
+        report.diagnostic(Severity::Warning, Some("pick"), "unsupported construct for go");
+        report.finish(5);
+        report
+    }
+
+    #[test]
+    fn test_failure_rules() {
+        use crate::verification::MumeiError;
+        let rule = |error: MumeiError| failure_rule(&AtomResult::new("a", ItemKind::Atom, AtomStatus::Failed)
+            .with_failure(error.failure_kind(), &error.to_string()));
+        // rule は検証器が付けたカテゴリで決まり、メッセージの文言には依存しない
+        assert_eq!(rule(MumeiError::failure(FailureKind::Precondition, "Call to 'f': requires not satisfied")), "precondition");
+        assert_eq!(rule(MumeiError::failure(FailureKind::Termination, "Invariant of the measure is not preserved")), "termination");
+        assert_eq!(rule(MumeiError::failure(FailureKind::SolverLimit, "Postcondition query was interrupted")), "solver-limit");
+        // カテゴリのないエラー（型エラーなど）とカテゴリを持たない古いレポートは verification
+        assert_eq!(rule(MumeiError::VerificationError("Postcondition (ensures) is not satisfied.".into())), "verification");
+        assert_eq!(rule(MumeiError::TypeError("Linearity violations".into())), "verification");
+        assert_eq!(failure_rule(&AtomResult::new("a", ItemKind::Atom, AtomStatus::Failed).with_error("Presence obligation")), "verification");
+        // impl の失敗は law
+        assert_eq!(failure_rule(&AtomResult::new("Eq for i64", ItemKind::Impl, AtomStatus::Failed).with_error("impl Eq")), "law");
+
+        // すべての失敗カテゴリに rule がある
+        for kind in [
+            FailureKind::Postcondition, FailureKind::Precondition, FailureKind::Law, FailureKind::Exhaustiveness,
+            FailureKind::Linearity, FailureKind::Termination, FailureKind::Totality, FailureKind::LoopInvariant,
+            FailureKind::Overflow, FailureKind::DivisionByZero, FailureKind::Bounds, FailureKind::Presence,
+            FailureKind::Refinement, FailureKind::Resource, FailureKind::SolverLimit, FailureKind::Verification,
+        ] {
+            assert!(RULES.iter().any(|(id, _)| *id == kind.as_str()), "{:?}", kind);
+            let json = serde_json::to_value(kind).unwrap();
+            assert_eq!(json, kind.as_str());
+        }
+    }
+
+    #[test]
+    fn test_sarif_structure() {
+        let report = sample_report();
+        let sarif = to_sarif(&report);
+
+        assert_eq!(sarif["version"], SARIF_VERSION);
+        assert_eq!(sarif["$schema"], SARIF_SCHEMA);
+        let runs = sarif["runs"].as_array().unwrap();
+        assert_eq!(runs.len(), 1);
+        let driver = &runs[0]["tool"]["driver"];
+        assert_eq!(driver["name"], "mumei");
+        assert_eq!(driver["version"], report.tool.mumei_version);
+        assert_eq!(driver["properties"]["z3Version"], report.tool.z3_version);
+
+        // rule id は一意で、各 result の ruleId / ruleIndex が rules と一致する
+        let rules = driver["rules"].as_array().unwrap();
+        let ids: Vec<&str> = rules.iter().map(|r| r["id"].as_str().unwrap()).collect();
+        let mut unique = ids.clone();
+        unique.sort();
+        unique.dedup();
+        assert_eq!(unique.len(), ids.len());
+        for r in rules {
+            assert!(r["shortDescription"]["text"].as_str().map_or(false, |t| !t.is_empty()));
+        }
+
+        // 失敗 3 件 + diagnostic 1 件（verified の atom は出さない）
+        let results = runs[0]["results"].as_array().unwrap();
+        assert_eq!(results.len(), 4);
+        for result in results {
+            let index = result["ruleIndex"].as_u64().unwrap() as usize;
+            assert_eq!(result["ruleId"], rules[index]["id"]);
+            assert!(["error", "warning", "note"].contains(&result["level"].as_str().unwrap()));
+            assert!(!result["message"]["text"].as_str().unwrap().is_empty());
+            assert_eq!(result["locations"][0]["physicalLocation"]["artifactLocation"]["uri"], "src/main.mm");
+        }
+        let rule_ids: Vec<&str> = results.iter().map(|r| r["ruleId"].as_str().unwrap()).collect();
+        assert_eq!(rule_ids, vec!["postcondition", "law", "exhaustiveness", "diagnostic"]);
+        assert_eq!(results[0]["locations"][0]["logicalLocations"][0]["name"], "clamp");
+        assert_eq!(results[3]["level"], "warning");
+
+        // 反例は markdown 本文に入る
+        let markdown = results[0]["message"]["markdown"].as_str().unwrap();
+        assert!(markdown.contains("Counterexample:"), "{}", markdown);
+        assert!(markdown.contains("\"-1\""), "{}", markdown);
+    }
+}
@@ -0,0 +1,464 @@
+// =============================================================================
+// mumei selftest: 検証モデルと生成コードの差分テスト（Differential Testing）
+// =============================================================================
+//
+// 検証（Z3 の記号モデル）と生成コードの意味論のずれ（除算の丸め方向、
+// match アームの順序、浮動小数点演算など）を検出するための開発者向け機能。
+// 検証済みでスカラー引数のみの atom について:
+//
+// 1. requires（と引数の精緻型）を満たす入力を棄却サンプリングで N 個生成する
+// 2. Tree-walking Interpreter で本体を実行し、ensures を具体値でチェックする
+// 3. Rust ツールチェーンがあれば、トランスパイルした Rust バンドルに
+//    生成したハーネスを付けてコンパイル・実行し、同じ入力での出力を比較する
+//
+// 検証済み atom で ensures が具体値で破れる、または実行時エラーになる入力は
+// 検証モデル側のずれ、Interpreter と Rust の出力の不一致は生成コード側のずれを示す。
+
+use crate::interpreter::{EvalError, Interpreter, Value};
+use crate::parser::{Atom, Item, RefinedType};
+use crate::transpiler::{TargetLanguage, UnsupportedPolicy, UnsupportedSummary, transpile, transpile_with_policy, transpile_enum, transpile_refined_type, transpile_struct, transpile_trait, transpile_impl};
+use crate::verification::ModuleEnv;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+
+/// 整数・浮動小数点の一様サンプリング範囲 [-SAMPLE_RANGE, SAMPLE_RANGE]
+const SAMPLE_RANGE: i64 = 1000;
+/// 1 サンプルあたりの棄却サンプリングの試行上限
+const MAX_ATTEMPTS_PER_SAMPLE: usize = 50;
+/// 一様分布とは別に優先的に試す境界値
+const INT_EDGES: [i64; 7] = [0, 1, -1, 2, -2, SAMPLE_RANGE, -SAMPLE_RANGE];
+const FLOAT_EDGES: [f64; 5] = [0.0, 1.0, -1.0, 0.5, -0.5];
+
+/// 乱数生成器（xorshift64*）。--seed で再現できるよう外部クレートに依存しない
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // 0 は xorshift の不動点なので避ける
+        Rng((seed ^ 0x9E37_79B9_7F4A_7C15) | 1)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    /// [lo, hi] の一様整数
+    pub(crate) fn range(&mut self, lo: i64, hi: i64) -> i64 {
+        let span = (hi - lo) as u64 + 1;
+        lo + (self.next_u64() % span) as i64
+    }
+
+    fn unit(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// スカラー引数の種類
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ScalarKind {
+    Int,
+    UInt,
+    Float,
+}
+
+/// スカラー引数の定義域（ベース型 + 精緻型の述語）
+struct ParamDomain {
+    name: String,
+    kind: ScalarKind,
+    refinement: Option<RefinedType>,
+}
+
+impl ParamDomain {
+    fn sample(&self, rng: &mut Rng) -> Value {
+        let edge = rng.range(0, 3) == 0;
+        match self.kind {
+            ScalarKind::Float if edge => Value::Float(FLOAT_EDGES[rng.range(0, FLOAT_EDGES.len() as i64 - 1) as usize]),
+            ScalarKind::Float => Value::Float((rng.unit() * 2.0 - 1.0) * SAMPLE_RANGE as f64),
+            _ => {
+                let n = if edge {
+                    INT_EDGES[rng.range(0, INT_EDGES.len() as i64 - 1) as usize]
+                } else {
+                    rng.range(-SAMPLE_RANGE, SAMPLE_RANGE)
+                };
+                Value::Int(if self.kind == ScalarKind::UInt { n.abs() } else { n })
+            }
+        }
+    }
+}
+
+/// atom の引数がすべてスカラー（i64 / u64 / f64 とその精緻型）なら定義域を返す。
+/// そうでなければスキップ理由を返す。
+fn scalar_domains(atom: &Atom, module_env: &ModuleEnv) -> Result<Vec<ParamDomain>, String> {
+    atom.params.iter().map(|p| {
+        if p.is_ref || p.is_ref_mut {
+            return Err(format!("ref parameter '{}'", p.name));
+        }
+        let type_name = p.type_name.as_deref().unwrap_or("i64");
+        let kind = match module_env.resolve_base_type(type_name).as_str() {
+            "i64" => ScalarKind::Int,
+            "u64" => ScalarKind::UInt,
+            "f64" => ScalarKind::Float,
+            _ => return Err(format!("non-scalar parameter '{}: {}'", p.name, type_name)),
+        };
+        Ok(ParamDomain { name: p.name.clone(), kind, refinement: module_env.get_type(type_name).cloned() })
+    }).collect()
+}
+
+/// 入力ベクトルの表示: "(x = 3, y = -2)"
+pub fn format_input(params: &[String], values: &[Value]) -> String {
+    let pairs: Vec<String> = params.iter().zip(values).map(|(p, v)| format!("{} = {}", p, v)).collect();
+    format!("({})", pairs.join(", "))
+}
+
+/// selftest の設定
+pub struct SelftestConfig {
+    /// atom あたりの入力数
+    pub samples: usize,
+}
+
+/// 1 入力分の実行結果
+pub struct Case {
+    pub inputs: Vec<Value>,
+    /// Interpreter での結果
+    pub expected: Result<Value, EvalError>,
+}
+
+/// 検出した不整合
+pub enum Finding {
+    /// 検証済みの ensures が具体値で成り立たない
+    EnsuresViolated { input: String, result: Value },
+    /// requires を満たす入力で実行時エラー（0 除算・オーバーフローなど）
+    RuntimeError { input: String, error: String },
+    /// Interpreter と生成コード（Rust）の出力が異なる
+    Divergence { input: String, interpreter: String, rust: String },
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Finding::EnsuresViolated { input, result } =>
+                write!(f, "ensures violated for input {}: result = {}", input, result),
+            Finding::RuntimeError { input, error } =>
+                write!(f, "{} for input {}", error, input),
+            Finding::Divergence { input, interpreter, rust } =>
+                write!(f, "divergence for input {}: interpreter = {}, rust = {}", input, interpreter, rust),
+        }
+    }
+}
+
+/// 1 atom 分の selftest 結果
+pub struct AtomRun {
+    pub atom: Atom,
+    pub params: Vec<String>,
+    pub cases: Vec<Case>,
+    pub findings: Vec<Finding>,
+    /// ensures を具体値で評価できなかった場合の理由（量化子・配列など）
+    pub ensures_note: Option<String>,
+    /// Rust との比較を行わない理由（None なら比較対象）
+    pub rust_note: Option<String>,
+}
+
+pub enum AtomSelftest {
+    Skipped { reason: String },
+    Ran(AtomRun),
+}
+
+/// 精緻型と requires を満たす入力を棄却サンプリングで最大 count 個生成する。
+/// 戻り値は (引数名, 入力ベクトル)。サンプリングできない atom はスキップ理由を返す。
+/// selftest と `mumei bench` のハーネス生成で共有する。
+pub fn sample_inputs(atom: &Atom, module_env: &ModuleEnv, count: usize, rng: &mut Rng) -> Result<(Vec<String>, Vec<Vec<Value>>), String> {
+    let domains = scalar_domains(atom, module_env)?;
+    let params: Vec<String> = domains.iter().map(|d| d.name.clone()).collect();
+
+    let mut inputs: Vec<Vec<Value>> = Vec::new();
+    let mut attempts = 0;
+    while inputs.len() < count && attempts < count * MAX_ATTEMPTS_PER_SAMPLE {
+        attempts += 1;
+        let values: Vec<Value> = domains.iter().map(|d| d.sample(rng)).collect();
+        match satisfies_requires(atom, &domains, &values, module_env) {
+            Ok(true) => inputs.push(values),
+            Ok(false) => {}
+            Err(EvalError::Unsupported(msg)) => {
+                return Err(format!("requires cannot be evaluated concretely ({})", msg));
+            }
+            // requires 自体の評価が実行時エラーになる入力は棄却する
+            Err(EvalError::Runtime(_)) => {}
+        }
+    }
+    if inputs.is_empty() {
+        return Err(format!("no input satisfying requires found in {} attempts", attempts));
+    }
+    Ok((params, inputs))
+}
+
+/// 1 atom について入力をサンプリングし、Interpreter で実行して ensures をチェックする
+pub fn run_atom(atom: &Atom, module_env: &ModuleEnv, cfg: &SelftestConfig, rng: &mut Rng) -> AtomSelftest {
+    let (params, inputs) = match sample_inputs(atom, module_env, cfg.samples, rng) {
+        Ok(sampled) => sampled,
+        Err(reason) => return AtomSelftest::Skipped { reason },
+    };
+
+    let mut run = AtomRun {
+        atom: atom.clone(),
+        params,
+        cases: Vec::new(),
+        findings: Vec::new(),
+        ensures_note: None,
+        rust_note: None,
+    };
+    for values in inputs {
+        let input = format_input(&run.params, &values);
+        let expected = Interpreter::new(module_env).call_atom(atom, &values);
+        match &expected {
+            Ok(result) if run.ensures_note.is_none() => {
+                let mut bindings: HashMap<String, Value> = run.params.iter().cloned().zip(values.iter().copied()).collect();
+                bindings.insert("result".to_string(), *result);
+                match Interpreter::new(module_env).eval_contract(&atom.ensures, &bindings) {
+                    Ok(true) => {}
+                    Ok(false) => run.findings.push(Finding::EnsuresViolated { input, result: *result }),
+                    Err(e) => run.ensures_note = Some(e.to_string()),
+                }
+            }
+            Ok(_) => {}
+            Err(EvalError::Unsupported(msg)) => {
+                return AtomSelftest::Skipped { reason: format!("body cannot be interpreted ({})", msg) };
+            }
+            Err(EvalError::Runtime(msg)) => run.findings.push(Finding::RuntimeError { input, error: msg.clone() }),
+        }
+        run.cases.push(Case { inputs: values, expected });
+    }
+
+    if atom.is_async {
+        run.rust_note = Some("async atom".to_string());
+    } else if let Err(unsupported) = transpile(atom, TargetLanguage::Rust, module_env) {
+        run.rust_note = Some(format!("{} not supported in Rust backend", unsupported.construct));
+    }
+    AtomSelftest::Ran(run)
+}
+
+fn satisfies_requires(atom: &Atom, domains: &[ParamDomain], values: &[Value], module_env: &ModuleEnv) -> Result<bool, EvalError> {
+    let mut interp = Interpreter::new(module_env);
+    for (domain, value) in domains.iter().zip(values) {
+        if let Some(refined) = &domain.refinement {
+            let bindings: HashMap<String, Value> = [(refined.operand.clone(), *value)].into_iter().collect();
+            if !interp.eval_contract(&refined.predicate_raw, &bindings)? {
+                return Ok(false);
+            }
+        }
+    }
+    let bindings: HashMap<String, Value> = domains.iter().map(|d| d.name.clone()).zip(values.iter().copied()).collect();
+    interp.eval_contract(&atom.requires, &bindings)
+}
+
+// =============================================================================
+// Rust ハーネス: トランスパイル結果と Interpreter の比較
+// =============================================================================
+
+/// `rustc` が利用可能か
+pub fn rustc_available() -> bool {
+    std::process::Command::new("rustc").arg("--version").output().map(|o| o.status.success()).unwrap_or(false)
+}
+
+/// モジュールの Rust バンドル（mumei build の .rs 出力と同じ定義群）を生成する。
+/// import 先のモジュール宣言は単一ファイルのハーネスでは解決できないため含めない。
+/// 未対応構文の atom は呼び出し元がコンパイルできるよう TODO スタブにする。
+pub fn rust_bundle(items: &[Item], module_env: &ModuleEnv) -> String {
+    bundle(items, module_env, TargetLanguage::Rust)
+}
+
+/// rust_bundle の言語指定版（`mumei bench` の Go / TypeScript ハーネスでも使う）。
+/// パッケージ宣言などのファイルヘッダーは含めない。
+pub fn bundle(items: &[Item], module_env: &ModuleEnv, lang: TargetLanguage) -> String {
+    let mut bundle = String::new();
+    let mut summary = UnsupportedSummary::default();
+    for item in items {
+        let code = match item {
+            Item::TypeDef(t) => match transpile_refined_type(t, lang) {
+                Some(code) => code,
+                None => continue,
+            },
+            Item::StructDef(s) => transpile_struct(s, lang, false),
+            Item::EnumDef(e) => transpile_enum(e, lang, false),
+            Item::TraitDef(t) => transpile_trait(t, lang),
+            Item::ImplDef(i) => transpile_impl(i, lang),
+            Item::Atom(a) => transpile_with_policy(a, lang, module_env, UnsupportedPolicy::AllowPartial, false, &mut summary),
+            _ => continue,
+        };
+        bundle.push_str(&code);
+        bundle.push_str("\n\n");
+    }
+    bundle
+}
+
+/// 比較対象の atom を全入力で呼び出し、"<atom番号> <入力番号> ok <値>" / "... panic" を出力する main を付ける
+pub fn rust_harness(bundle: &str, runs: &[AtomRun]) -> String {
+    let mut calls = Vec::new();
+    for (ai, run) in runs.iter().enumerate().filter(|(_, r)| r.rust_note.is_none()) {
+        for (ci, case) in run.cases.iter().enumerate() {
+            let args: Vec<String> = case.inputs.iter().map(|v| match v {
+                // 整数リテラルは接尾辞なしにして、生成コード側の引数型（i64 / u64）に推論させる
+                Value::Float(f) => format!("{:?}", f),
+                other => other.to_string(),
+            }).collect();
+            calls.push(format!("    case({}, {}, || format!(\"{{:?}}\", {}({})));", ai, ci, run.atom.name, args.join(", ")));
+        }
+    }
+    format!(
+        "#![allow(unused)]\n{}\nfn case(atom: usize, idx: usize, f: impl FnOnce() -> String + std::panic::UnwindSafe) {{\n    match std::panic::catch_unwind(f) {{\n        Ok(v) => println!(\"{{}} {{}} ok {{}}\", atom, idx, v),\n        Err(_) => println!(\"{{}} {{}} panic\", atom, idx),\n    }}\n}}\n\nfn main() {{\n    std::panic::set_hook(Box::new(|_| {{}}));\n{}\n}}\n",
+        bundle, calls.join("\n")
+    )
+}
+
+/// ハーネスを rustc でコンパイル（debug ビルド: オーバーフロー検査あり）して実行し、標準出力を返す
+pub fn run_rust_harness(harness: &str, work_dir: &Path) -> Result<String, String> {
+    std::fs::create_dir_all(work_dir).map_err(|e| e.to_string())?;
+    let src = work_dir.join("harness.rs");
+    let bin = work_dir.join(if cfg!(windows) { "harness.exe" } else { "harness" });
+    std::fs::write(&src, harness).map_err(|e| e.to_string())?;
+    let compile = std::process::Command::new("rustc")
+        .args(["--edition", "2021", "--cap-lints", "allow", "-o"])
+        .arg(&bin)
+        .arg(&src)
+        .output()
+        .map_err(|e| e.to_string())?;
+    if !compile.status.success() {
+        let stderr = String::from_utf8_lossy(&compile.stderr);
+        let head: Vec<&str> = stderr.lines().take(20).collect();
+        return Err(format!("transpiled Rust harness failed to compile:\n{}", head.join("\n")));
+    }
+    let output = std::process::Command::new(&bin).output().map_err(|e| e.to_string())?;
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Rust 側の `{:?}` 出力を Value に戻す
+fn parse_rust_value(s: &str) -> Option<Value> {
+    match s {
+        "true" => Some(Value::Bool(true)),
+        "false" => Some(Value::Bool(false)),
+        _ => s.parse::<i64>().map(Value::Int).ok().or_else(|| s.parse::<f64>().map(Value::Float).ok()),
+    }
+}
+
+/// ハーネスの出力を Interpreter の結果と突き合わせ、不一致を Finding として追加する。
+/// 実行時エラー（Interpreter）と panic（Rust）は一致とみなす。
+pub fn compare_rust_output(runs: &mut [AtomRun], stdout: &str) {
+    let mut outputs: HashMap<(usize, usize), Option<Value>> = HashMap::new();
+    for line in stdout.lines() {
+        let parts: Vec<&str> = line.splitn(4, ' ').collect();
+        if let (Some(ai), Some(ci)) = (parts.first().and_then(|s| s.parse::<usize>().ok()), parts.get(1).and_then(|s| s.parse::<usize>().ok())) {
+            let value = if parts.get(2) == Some(&"ok") { parts.get(3).and_then(|s| parse_rust_value(s)) } else { None };
+            outputs.insert((ai, ci), value);
+        }
+    }
+    for (ai, run) in runs.iter_mut().enumerate().filter(|(_, r)| r.rust_note.is_none()) {
+        for (ci, case) in run.cases.iter().enumerate() {
+            let rust = match outputs.get(&(ai, ci)) {
+                Some(rust) => *rust,
+                None => {
+                    run.findings.push(Finding::Divergence {
+                        input: format_input(&run.params, &case.inputs),
+                        interpreter: describe(&case.expected),
+                        rust: "no output (harness aborted)".to_string(),
+                    });
+                    continue;
+                }
+            };
+            let agrees = match (&case.expected, rust) {
+                (Ok(expected), Some(actual)) => expected.same_as(&actual),
+                (Err(_), None) => true,
+                _ => false,
+            };
+            if !agrees {
+                run.findings.push(Finding::Divergence {
+                    input: format_input(&run.params, &case.inputs),
+                    interpreter: describe(&case.expected),
+                    rust: rust.map_or("panic".to_string(), |v| v.to_string()),
+                });
+            }
+        }
+    }
+}
+
+fn describe(result: &Result<Value, EvalError>) -> String {
+    match result {
+        Ok(v) => v.to_string(),
+        Err(e) => e.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{env_from_source, find_atom};
+
+    const SOURCE: &str = r#"
+type Nat = i64 where v >= 0;
+
+atom halve(n: Nat, d: i64)
+requires: d > 0 && n < 100;
+ensures: result >= 0;
+body: n / d;
+
+atom floor_div(a: i64, b: i64)
+requires: b > 0;
+ensures: result * b <= a;
+body: a / b;
+"#;
+
+    #[test]
+    fn test_samples_respect_requires_and_refinement() {
+        let (items, module_env) = env_from_source(SOURCE);
+        let atom = find_atom(&items, "halve");
+        let mut rng = Rng::new(7);
+        let run = match run_atom(&atom, &module_env, &SelftestConfig { samples: 30 }, &mut rng) {
+            AtomSelftest::Ran(run) => run,
+            AtomSelftest::Skipped { reason } => panic!("skipped: {}", reason),
+        };
+        assert_eq!(run.cases.len(), 30);
+        let int = |v: &Value| match v { Value::Int(n) => *n, other => panic!("expected int, got {}", other) };
+        for case in &run.cases {
+            let (n, d) = (int(&case.inputs[0]), int(&case.inputs[1]));
+            assert!((0..100).contains(&n) && d > 0, "{:?}", case.inputs);
+        }
+        assert!(run.findings.is_empty());
+    }
+
+    #[test]
+    fn test_truncating_division_violates_floor_ensures() {
+        // Z3 の Int 除算は floor、生成コードは 0 方向への切り捨て: 負の a で ensures が破れる
+        let (items, module_env) = env_from_source(SOURCE);
+        let atom = find_atom(&items, "floor_div");
+        let mut rng = Rng::new(1);
+        let run = match run_atom(&atom, &module_env, &SelftestConfig { samples: 50 }, &mut rng) {
+            AtomSelftest::Ran(run) => run,
+            AtomSelftest::Skipped { reason } => panic!("skipped: {}", reason),
+        };
+        let violation = run.findings.iter().find(|f| matches!(f, Finding::EnsuresViolated { .. })).expect("violation");
+        assert!(violation.to_string().contains("ensures violated for input (a = -"), "{}", violation);
+    }
+
+    #[test]
+    fn test_harness_and_output_comparison() {
+        let (items, module_env) = env_from_source(SOURCE);
+        let atom = find_atom(&items, "halve");
+        let mut run = match run_atom(&atom, &module_env, &SelftestConfig { samples: 2 }, &mut Rng::new(3)) {
+            AtomSelftest::Ran(run) => run,
+            AtomSelftest::Skipped { reason } => panic!("skipped: {}", reason),
+        };
+        let first = run.cases[0].inputs.clone();
+        let harness = rust_harness(&rust_bundle(&items, &module_env), std::slice::from_ref(&run));
+        assert!(harness.contains("pub fn halve("), "{}", harness);
+        assert!(harness.contains(&format!("case(0, 0, || format!(\"{{:?}}\", halve({}, {})));", first[0], first[1])), "{}", harness);
+
+        // 1 件目は一致、2 件目は Rust 側が panic
+        let expected = run.cases[0].expected.clone().unwrap();
+        let stdout = format!("0 0 ok {}\n0 1 panic\n", expected);
+        compare_rust_output(std::slice::from_mut(&mut run), &stdout);
+        assert_eq!(run.findings.len(), 1);
+        assert!(run.findings[0].to_string().contains("rust = panic"), "{}", run.findings[0]);
+    }
+}
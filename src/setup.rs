@@ -25,7 +25,7 @@ use crate::manifest;
 // =============================================================================
 // バージョン定数
 // =============================================================================
-const Z3_VERSION: &str = "4.13.4";
+pub const Z3_VERSION: &str = "4.13.4";
 const LLVM_VERSION: &str = "18.1.8";
 
 // =============================================================================
@@ -304,6 +304,44 @@ fn verify_installation(z3_dir: &Path, llvm_dir: &Path) {
     }
 }
 
+/// ~/.mumei/toolchains に Z3 と LLVM の両方が導入済みか（mumei inspect --fix の確認用）
+pub fn toolchains_installed() -> bool {
+    let toolchains_dir = manifest::mumei_home().join("toolchains");
+    toolchains_dir.join(format!("z3-{}", Z3_VERSION)).exists() && toolchains_dir.join(format!("llvm-{}", LLVM_VERSION)).exists()
+}
+
+// =============================================================================
+// llc の探索（mumei inspect / mumei build --emit obj|exe）
+// =============================================================================
+
+/// PATH 上で探す llc のコマンド名（新しい順）
+const LLC_COMMANDS: &[&str] = &["llc-18", "llc-17", "llc"];
+
+/// 使用する llc の実行ファイルを探す。優先順:
+/// 1. 環境変数 MUMEI_LLC（設定されていて実行できなければエラー。ほかの候補には進まない）
+/// 2. `mumei setup` が入れた ~/.mumei/toolchains/llvm-{version}/bin/llc
+/// 3. PATH 上の llc-18 / llc-17 / llc
+pub fn locate_llc() -> Result<PathBuf, String> {
+    locate_llc_with(std::env::var("MUMEI_LLC").ok().filter(|v| !v.is_empty()))
+}
+
+pub(crate) fn locate_llc_with(override_path: Option<String>) -> Result<PathBuf, String> {
+    if let Some(path) = override_path {
+        return match Cmd::new(&path).arg("--version").output() {
+            Ok(_) => Ok(PathBuf::from(path)),
+            Err(e) => Err(format!("MUMEI_LLC={} could not be run: {}", path, e)),
+        };
+    }
+    let toolchain_llc = manifest::mumei_home().join("toolchains").join(format!("llvm-{}", LLVM_VERSION)).join("bin").join("llc");
+    let candidates = std::iter::once(toolchain_llc.clone()).chain(LLC_COMMANDS.iter().map(PathBuf::from));
+    for candidate in candidates {
+        if Cmd::new(&candidate).arg("--version").output().is_ok() {
+            return Ok(candidate);
+        }
+    }
+    Err(format!("llc not found (tried {}, {}); run `mumei setup` or set MUMEI_LLC", toolchain_llc.display(), LLC_COMMANDS.join(", ")))
+}
+
 // =============================================================================
 // Download/extract helpers (external tools)
 // =============================================================================
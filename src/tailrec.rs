@@ -0,0 +1,343 @@
+// =============================================================================
+// 末尾再帰の検出とループ形への書き換え提案 (`mumei verify --suggest-invariant`)
+// =============================================================================
+//
+// アキュムレータ形式の補助 atom（`go(n, acc)`）は、再帰呼び出しがすべて末尾位置にあれば
+// while ループと等価になる。ここでは:
+// - 直接の自己呼び出しがすべて末尾位置（本体の最終式、またはその if / match の分岐）にあるかを判定し、
+// - `if c { base } else { f(args) }` の形の atom について、等価なループ形の body を提案する。
+//
+// 提案の invariant は ensures の `result == E` から導く: 現在のループ変数で E を評価した値は
+// 常に元のパラメータでの E と等しい（`E[cur] == E`）。ループを抜けた時点で base の値が
+// E[cur] と一致することが元の atom の基底ケースの検証に対応する。
+// 提案は表示するだけでソースは書き換えない。検証そのもの（再帰呼び出しの requires と
+// decreases の証明）は verification の呼び出し処理で行う。
+
+use crate::parser::{parse_expression, Atom, Expr, Op};
+use crate::printer::show;
+use std::collections::HashMap;
+
+/// atom 本体に含まれる直接の自己呼び出しの数
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SelfCalls {
+    /// 自己呼び出しの総数
+    pub total: usize,
+    /// そのうち末尾位置にある呼び出しの数
+    pub tail: usize,
+}
+
+impl SelfCalls {
+    /// 再帰していて、すべての自己呼び出しが末尾呼び出しなら true
+    pub fn is_tail_recursive(&self) -> bool {
+        self.total > 0 && self.total == self.tail
+    }
+}
+
+/// atom 本体の直接の自己呼び出しを数える
+pub fn self_calls(atom: &Atom) -> SelfCalls {
+    let mut calls = SelfCalls::default();
+    if let Ok(body) = parse_expression(&atom.body_expr) {
+        count_self_calls(&body, &atom.name, &mut calls);
+    }
+    calls
+}
+
+fn count_self_calls(expr: &Expr, name: &str, calls: &mut SelfCalls) {
+    let is_self_call = |e: &Expr| matches!(e, Expr::Call(callee, _) if callee == name);
+    expr.walk(|e| {
+        calls.total += usize::from(is_self_call(e));
+        true
+    });
+    // 末尾位置をたどる。分岐の値はそのまま式の値になるため、末尾位置は分岐とブロックの最後の文へ引き継がれる。
+    // acquire はブロック終了時に解放処理が走り、async / await は値を包むため末尾位置ではない
+    let mut tail_positions = vec![expr];
+    while let Some(e) = tail_positions.pop() {
+        match e {
+            _ if is_self_call(e) => calls.tail += 1,
+            Expr::IfThenElse { then_branch, else_branch, .. } => tail_positions.extend([&**then_branch, &**else_branch]),
+            Expr::Match { arms, .. } => tail_positions.extend(arms.iter().map(|arm| &*arm.body)),
+            Expr::Block(stmts) => tail_positions.extend(stmts.last()),
+            _ => {}
+        }
+    }
+}
+
+// =============================================================================
+// ループ形の提案
+// =============================================================================
+
+/// ループ変数名（パラメータ `n` → `n_cur`）
+fn loop_var(param: &str) -> String {
+    format!("{}_cur", param)
+}
+
+/// 末尾再帰の atom について、等価なループ形の body を提案する。
+/// 提案できない形なら理由を Err で返す。
+pub fn suggest_loop(atom: &Atom) -> Result<String, String> {
+    let calls = self_calls(atom);
+    if calls.total == 0 {
+        return Err(format!("atom '{}' is not recursive", atom.name));
+    }
+    if !calls.is_tail_recursive() {
+        return Err(format!(
+            "atom '{}' is not tail-recursive: {} of {} recursive call(s) are not in tail position",
+            atom.name, calls.total - calls.tail, calls.total
+        ));
+    }
+
+    let parse = |source: &str| parse_expression(source).map_err(|e| format!("atom '{}': {}", atom.name, e));
+    let body = parse(&atom.body_expr)?;
+    let shape_error = || format!(
+        "atom '{}': only bodies of the form `if c {{ base }} else {{ {}(...) }}` can be rewritten as a loop",
+        atom.name, atom.name
+    );
+    let (cond, then_branch, else_branch) = match unwrap_block(&body) {
+        Expr::IfThenElse { cond, then_branch, else_branch } => (cond.as_ref(), unwrap_block(then_branch), unwrap_block(else_branch)),
+        _ => return Err(shape_error()),
+    };
+    let self_call = |e: &Expr| match e {
+        Expr::Call(callee, args) if *callee == atom.name => Some(args.clone()),
+        _ => None,
+    };
+    // 再帰する分岐の条件（ループ条件）と、基底ケースの式
+    let (loop_cond, base, args) = match (self_call(then_branch), self_call(else_branch)) {
+        (Some(args), None) => (cond.clone(), else_branch, args),
+        (None, Some(args)) => match negate(cond) {
+            Some(negated) => (negated, then_branch, args),
+            None => return Err(format!("atom '{}': cannot negate the condition `{}` to form the loop condition", atom.name, show(cond).unwrap_or_default())),
+        },
+        _ => return Err(shape_error()),
+    };
+    let mut base_calls = SelfCalls::default();
+    count_self_calls(base, &atom.name, &mut base_calls);
+    if base_calls.total > 0 || args.len() != atom.params.len() {
+        return Err(shape_error());
+    }
+
+    let renames: HashMap<String, String> = atom.params.iter()
+        .map(|p| (p.name.clone(), loop_var(&p.name)))
+        .collect();
+    let cur = |e: &Expr| rename(e, &renames);
+    let render = |e: &Expr| show(e).ok_or_else(|| format!("atom '{}': the expression `{:?}` cannot be printed as a loop", atom.name, e));
+
+    // invariant: requires[cur] && E[cur] == E（ensures の `result == E` から導く）
+    let mut invariant_parts = Vec::new();
+    if atom.requires.trim() != "true" {
+        invariant_parts.push(cur(&parse(&atom.requires)?));
+    }
+    let ensures = parse(&atom.ensures)?;
+    let result_eq = result_equality(&ensures);
+    if let Some(value) = result_eq {
+        invariant_parts.push(Expr::BinaryOp(Box::new(cur(value)), Op::Eq, Box::new(value.clone())));
+    }
+    let invariant = invariant_parts.into_iter()
+        .reduce(|acc, e| Expr::BinaryOp(Box::new(acc), Op::And, Box::new(e)))
+        .unwrap_or(Expr::Bool(true));
+
+    let mut lines = vec![format!(
+        "// atom '{}': {} recursive call(s), all in tail position; equivalent loop (suggestion, not applied)",
+        atom.name, calls.total
+    )];
+    match result_eq {
+        Some(value) => lines.push(format!("// invariant derived from `ensures: result == {}`", render(value)?)),
+        None => lines.push("// ensures has no `result == ...` conjunct: strengthen the invariant by hand".to_string()),
+    }
+    if atom.decreases.is_none() {
+        lines.push(format!("// atom '{}' has no `decreases:` clause: add a loop `decreases:` to prove termination", atom.name));
+    }
+    lines.push("body: {".to_string());
+    for p in &atom.params {
+        lines.push(format!("    let {} = {};", loop_var(&p.name), p.name));
+    }
+    lines.push(format!("    while {}", render(&cur(&loop_cond))?));
+    lines.push(format!("    invariant: {}", render(&invariant)?));
+    if let Some(measure) = &atom.decreases {
+        lines.push(format!("    decreases: {}", render(&cur(&parse(measure)?))?));
+    }
+    lines.push("    {".to_string());
+    for statement in loop_updates(atom, &args, &cur) {
+        lines.push(format!("        {}", render_statement(&statement, &render)?));
+    }
+    lines.push("    };".to_string());
+    lines.push(format!("    {}", render(&cur(base))?));
+    lines.push("};".to_string());
+    Ok(lines.join("\n") + "\n")
+}
+
+/// 再帰呼び出しの実引数をループ変数への代入列にする。
+/// 他の代入の右辺が参照しない変数から順に代入し、互いに参照し合う残り（`go(b, a)` など）は
+/// 一時変数を経由して同時代入する。
+fn loop_updates(atom: &Atom, args: &[Expr], cur: &dyn Fn(&Expr) -> Expr) -> Vec<Expr> {
+    let mut pending: Vec<(String, Expr)> = atom.params.iter().zip(args)
+        .filter(|(p, arg)| !matches!(arg, Expr::Variable(v) if *v == p.name))
+        .map(|(p, arg)| (loop_var(&p.name), cur(arg)))
+        .collect();
+    let mut updates = Vec::new();
+    while let Some(i) = pending.iter().position(|(var, _)| {
+        !pending.iter().any(|(other, value)| other != var && mentions(value, var))
+    }) {
+        let (var, value) = pending.remove(i);
+        updates.push(Expr::Assign { var, value: Box::new(value) });
+    }
+    for (var, value) in &pending {
+        updates.push(Expr::Let { var: format!("{}_next", var), value: Box::new(value.clone()) });
+    }
+    for (var, _) in pending {
+        let temp = format!("{}_next", var);
+        updates.push(Expr::Assign { var, value: Box::new(Expr::Variable(temp)) });
+    }
+    updates
+}
+
+fn render_statement(statement: &Expr, render: &dyn Fn(&Expr) -> Result<String, String>) -> Result<String, String> {
+    match statement {
+        Expr::Let { var, value } => Ok(format!("let {} = {};", var, render(value)?)),
+        Expr::Assign { var, value } => Ok(format!("{} = {};", var, render(value)?)),
+        other => Ok(format!("{};", render(other)?)),
+    }
+}
+
+/// ensures の連言から `result == E`（または `E == result`）の E を探す
+fn result_equality(ensures: &Expr) -> Option<&Expr> {
+    match ensures {
+        Expr::BinaryOp(l, Op::And, r) => result_equality(l).or_else(|| result_equality(r)),
+        Expr::BinaryOp(l, Op::Eq, r) => match (l.as_ref(), r.as_ref()) {
+            (Expr::Variable(v), value) if v == "result" && !mentions(value, "result") => Some(value),
+            (value, Expr::Variable(v)) if v == "result" && !mentions(value, "result") => Some(value),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// 論理否定を演算子の反転で表す（Mumei の式には単項の `!` がないため）
+fn negate(expr: &Expr) -> Option<Expr> {
+    let binary = |l: &Expr, op: Op, r: &Expr| Some(Expr::BinaryOp(Box::new(l.clone()), op, Box::new(r.clone())));
+    match expr {
+        Expr::BinaryOp(l, op, r) => match op {
+            Op::Eq => binary(l, Op::Neq, r),
+            Op::Neq => binary(l, Op::Eq, r),
+            Op::Lt => binary(l, Op::Ge, r),
+            Op::Ge => binary(l, Op::Lt, r),
+            Op::Gt => binary(l, Op::Le, r),
+            Op::Le => binary(l, Op::Gt, r),
+            Op::And => binary(&negate(l)?, Op::Or, &negate(r)?),
+            Op::Or => binary(&negate(l)?, Op::And, &negate(r)?),
+            Op::Implies => binary(l, Op::And, &negate(r)?),
+            Op::Add | Op::Sub | Op::Mul | Op::Div => None,
+        },
+        Expr::Call(name, args) if name == "is_some" => Some(Expr::Call("is_none".to_string(), args.clone())),
+        Expr::Call(name, args) if name == "is_none" => Some(Expr::Call("is_some".to_string(), args.clone())),
+        _ => None,
+    }
+}
+
+/// 要素が 1 つだけのブロック（`{ acc }`）をその式として扱う
+fn unwrap_block(expr: &Expr) -> &Expr {
+    match expr {
+        Expr::Block(stmts) if stmts.len() == 1 => unwrap_block(&stmts[0]),
+        other => other,
+    }
+}
+
+/// 変数名を置き換えた式を返す（提案の表示用。束縛の導入は扱わない）
+fn rename(expr: &Expr, renames: &HashMap<String, String>) -> Expr {
+    let name = |n: &String| renames.get(n).cloned().unwrap_or_else(|| n.clone());
+    match expr {
+        Expr::Variable(v) => Expr::Variable(name(v)),
+        Expr::ArrayAccess(array, index) => Expr::ArrayAccess(name(array), Box::new(rename(index, renames))),
+        Expr::BinaryOp(l, op, r) => Expr::BinaryOp(Box::new(rename(l, renames)), op.clone(), Box::new(rename(r, renames))),
+        Expr::Call(callee, args) => Expr::Call(callee.clone(), args.iter().map(|a| rename(a, renames)).collect()),
+        Expr::FieldAccess(base, field) => Expr::FieldAccess(Box::new(rename(base, renames)), field.clone()),
+        Expr::IfThenElse { cond, then_branch, else_branch } => Expr::IfThenElse {
+            cond: Box::new(rename(cond, renames)),
+            then_branch: Box::new(rename(then_branch, renames)),
+            else_branch: Box::new(rename(else_branch, renames)),
+        },
+        Expr::Block(stmts) if stmts.len() == 1 => rename(&stmts[0], renames),
+        other => other.clone(),
+    }
+}
+
+/// 式が変数 name を参照するか
+fn mentions(expr: &Expr, name: &str) -> bool {
+    match expr {
+        Expr::Variable(v) => v == name,
+        Expr::ArrayAccess(array, index) => array == name || mentions(index, name),
+        Expr::BinaryOp(l, _, r) => mentions(l, name) || mentions(r, name),
+        Expr::Call(_, args) => args.iter().any(|a| mentions(a, name)),
+        Expr::FieldAccess(base, _) | Expr::RefArg { expr: base, .. } => mentions(base, name),
+        Expr::IfThenElse { cond, then_branch, else_branch } => {
+            mentions(cond, name) || mentions(then_branch, name) || mentions(else_branch, name)
+        }
+        Expr::Block(stmts) => stmts.iter().any(|s| mentions(s, name)),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{parse_module, Item};
+    use crate::test_support::assert_golden;
+
+    const SOURCE: &str = r#"
+atom sum_to(n: i64, acc: i64)
+requires: n >= 0;
+ensures: result == acc + n * (n + 1) / 2;
+decreases: n;
+body: if n == 0 { acc } else { sum_to(n - 1, acc + n) };
+
+atom gcd(a: i64, b: i64)
+requires: a >= 0 && b >= 0;
+ensures: result >= 0;
+decreases: b;
+body: {
+    if b == 0 { a } else { gcd(b, a - b * (a / b)) }
+};
+
+atom fact(n: i64)
+requires: n >= 0;
+ensures: result >= 1;
+body: if n <= 1 { 1 } else { n * fact(n - 1) };
+
+atom plain(x: i64)
+requires: true;
+ensures: result == x;
+body: x;
+"#;
+
+    fn atom(name: &str) -> Atom {
+        parse_module(SOURCE).unwrap().into_iter().find_map(|i| match i {
+            Item::Atom(a) if a.name == name => Some(a),
+            _ => None,
+        }).unwrap()
+    }
+
+    #[test]
+    fn test_detect_tail_self_calls() {
+        assert_eq!(self_calls(&atom("sum_to")), SelfCalls { total: 1, tail: 1 });
+        assert!(self_calls(&atom("gcd")).is_tail_recursive());
+        // `n * fact(n - 1)` の呼び出しは乗算のオペランドなので末尾位置ではない
+        assert_eq!(self_calls(&atom("fact")), SelfCalls { total: 1, tail: 0 });
+        assert!(!self_calls(&atom("plain")).is_tail_recursive());
+
+        let err = suggest_loop(&atom("fact")).unwrap_err();
+        assert!(err.contains("not tail-recursive"), "{}", err);
+        let err = suggest_loop(&atom("plain")).unwrap_err();
+        assert!(err.contains("not recursive"), "{}", err);
+    }
+
+    #[test]
+    fn test_suggest_loop_golden() {
+        assert_golden("suggest", "sum_to.golden", &suggest_loop(&atom("sum_to")).unwrap());
+        // 互いに参照し合う実引数（gcd(b, a mod b)）は一時変数で同時代入する
+        assert_golden("suggest", "gcd.golden", &suggest_loop(&atom("gcd")).unwrap());
+    }
+
+    #[test]
+    fn test_negate_loop_condition() {
+        let negated = negate(&parse_expression("n == 0 || k < 3").unwrap()).unwrap();
+        assert_eq!(show(&negated).unwrap(), "n != 0 && k >= 3");
+    }
+}
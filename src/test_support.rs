@@ -0,0 +1,78 @@
+//! テストで共有する下ごしらえ: ソースから組み立てる ModuleEnv、atom の取り出し、
+//! テストごとの一時ディレクトリ、tests/golden/ との比較
+
+use std::fs;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+
+use crate::parser::{parse_module, Atom, Item};
+use crate::verification::ModuleEnv;
+
+/// ソースを parse し、型・構造体・Enum・トレイト・impl・atom をすべて ModuleEnv に登録する
+pub fn env_from_source(source: &str) -> (Vec<Item>, ModuleEnv) {
+    let items = parse_module(source).unwrap();
+    let mut module_env = ModuleEnv::new();
+    for item in &items {
+        match item {
+            Item::TraitDef(t) => module_env.register_trait(t),
+            Item::ImplDef(i) => module_env.register_impl(i),
+            Item::EnumDef(e) => module_env.register_enum(e),
+            Item::StructDef(s) => module_env.register_struct(s),
+            Item::Atom(a) => module_env.register_atom(a),
+            Item::TypeDef(t) => module_env.register_type(t),
+            _ => {}
+        }
+    }
+    (items, module_env)
+}
+
+/// items の中の名前が name の atom（なければ panic）
+pub fn find_atom(items: &[Item], name: &str) -> Atom {
+    items.iter().find_map(|i| match i {
+        Item::Atom(a) if a.name == name => Some(a.clone()),
+        _ => None,
+    }).unwrap_or_else(|| panic!("no atom named '{}'", name))
+}
+
+/// テストごとの一時ディレクトリ `<temp>/mumei_<name>_<pid>`。作り直してから渡し、drop で消す
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("mumei_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        TempDir(path)
+    }
+}
+
+impl Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl AsRef<Path> for TempDir {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// tests/golden/<dir>/<name> と比較する。MUMEI_UPDATE_GOLDEN=1 なら書き換える
+pub fn assert_golden(dir: &str, name: &str, actual: &str) {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("golden").join(dir).join(name);
+    if std::env::var_os("MUMEI_UPDATE_GOLDEN").is_some() {
+        fs::write(&path, actual).unwrap();
+        return;
+    }
+    let expected = fs::read_to_string(&path).unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+    assert_eq!(actual, expected, "{} is out of date (rerun with MUMEI_UPDATE_GOLDEN=1)", path.display());
+}
@@ -0,0 +1,182 @@
+// =============================================================================
+// 理論拡張の例: Q16.16 固定小数点（feature "theory-fixed-point"）
+// =============================================================================
+//
+// `type Fx = q16_16 where ...` の値は 16.16 の固定小数点数で、i64 の生の値（実数値 × 65536）
+// として表現する。生の値は i32 の範囲に収まらなければならない。
+//
+// 組み込み関数:
+//   fx_from_int(n)  整数 → 固定小数点（n * 65536）
+//   fx_to_int(a)    固定小数点 → 整数（0 方向への切り捨て）
+//   fx_add(a, b)    加算
+//   fx_mul(a, b)    乗算（(a * b) / 65536、0 方向への切り捨て）
+//
+// fx_from_int / fx_add / fx_mul は結果が Q16.16 の範囲に収まることを呼び出し箇所の証明義務にする。
+// 除算の丸めは生成コード（LLVM sdiv / Rust / Go の `/`）に合わせて 0 方向への切り捨てで
+// モデル化する（Z3 の Int 除算は負数で床関数になるため、そのままでは使わない）。
+
+use super::{IntrinsicCall, TheoryExtension};
+use crate::parser::{Expr, Op};
+use crate::report::FailureKind;
+use crate::transpiler::TargetLanguage;
+use crate::verification::{MumeiError, MumeiResult};
+use z3::ast::{Bool, Dynamic, Int};
+
+/// 基底型名
+pub const BASE_TYPE: &str = "q16_16";
+/// 1.0 の生の値
+pub const SCALE: i64 = 1 << 16;
+/// 生の値の範囲（i32）
+pub const RAW_MIN: i64 = i32::MIN as i64;
+pub const RAW_MAX: i64 = i32::MAX as i64;
+
+const INTRINSICS: &[(&str, usize)] = &[("fx_from_int", 1), ("fx_to_int", 1), ("fx_add", 2), ("fx_mul", 2)];
+
+pub struct FixedPoint;
+
+/// 0 方向に切り捨てる整数除算（divisor > 0）
+fn trunc_div<'ctx>(call: &IntrinsicCall<'_, 'ctx>, dividend: &Int<'ctx>, divisor: i64) -> Int<'ctx> {
+    let zero = Int::from_i64(call.ctx, 0);
+    let divisor = Int::from_i64(call.ctx, divisor);
+    let floor = dividend / &divisor;
+    let negated = &(&zero - dividend) / &divisor;
+    dividend.ge(&zero).ite(&floor, &(&zero - &negated))
+}
+
+fn arity(name: &str) -> usize {
+    INTRINSICS.iter().find(|(n, _)| *n == name).map_or(0, |(_, a)| *a)
+}
+
+impl TheoryExtension for FixedPoint {
+    fn name(&self) -> &str {
+        "fixed-point (Q16.16)"
+    }
+
+    fn carrier_type(&self, base: &str) -> Option<&'static str> {
+        (base == BASE_TYPE).then_some("i64")
+    }
+
+    fn owns_intrinsic(&self, name: &str) -> bool {
+        INTRINSICS.iter().any(|(n, _)| *n == name)
+    }
+
+    fn translate_call<'ctx>(&self, call: &IntrinsicCall<'_, 'ctx>) -> MumeiResult<Dynamic<'ctx>> {
+        if call.args.len() != arity(call.name) {
+            return Err(MumeiError::TypeError(format!("{}() expects {} argument(s)", call.name, arity(call.name))));
+        }
+        let ctx = call.ctx;
+        let result = match call.name {
+            "fx_from_int" => &call.int_arg(0)? * &Int::from_i64(ctx, SCALE),
+            "fx_to_int" => return Ok(trunc_div(call, &call.int_arg(0)?, SCALE).into()),
+            "fx_add" => &call.int_arg(0)? + &call.int_arg(1)?,
+            _ => trunc_div(call, &(&call.int_arg(0)? * &call.int_arg(1)?), SCALE),
+        };
+        let in_range = Bool::and(ctx, &[
+            &result.ge(&Int::from_i64(ctx, RAW_MIN)),
+            &result.le(&Int::from_i64(ctx, RAW_MAX)),
+        ]);
+        call.require(FailureKind::Overflow, &in_range, &format!(
+            "Q16.16 overflow: result of {}() may be out of range (raw value must be within {}..={})",
+            call.name, RAW_MIN, RAW_MAX
+        ))?;
+        Ok(result.into())
+    }
+
+    fn lower_call(&self, name: &str, args: &[Expr]) -> MumeiResult<Expr> {
+        if args.len() != arity(name) {
+            return Err(MumeiError::CodegenError(format!("{}() expects {} argument(s)", name, arity(name))));
+        }
+        let bin = |l: Expr, op: Op, r: Expr| Expr::BinaryOp(Box::new(l), op, Box::new(r));
+        let scale = Expr::Number(SCALE);
+        Ok(match name {
+            "fx_from_int" => bin(args[0].clone(), Op::Mul, scale),
+            "fx_to_int" => bin(args[0].clone(), Op::Div, scale),
+            "fx_add" => bin(args[0].clone(), Op::Add, args[1].clone()),
+            _ => bin(bin(args[0].clone(), Op::Mul, args[1].clone()), Op::Div, scale),
+        })
+    }
+
+    fn transpile_call(&self, name: &str, lang: TargetLanguage, args: &[String]) -> Option<String> {
+        // TypeScript の number は浮動小数点なので、除算は切り捨て、2^53 を超えうる積は BigInt で計算する
+        match (lang, name) {
+            (TargetLanguage::TypeScript, "fx_mul") => {
+                Some(format!("Number((BigInt({}) * BigInt({})) / {}n)", args[0], args[1], SCALE))
+            }
+            (TargetLanguage::TypeScript, "fx_to_int") => Some(format!("Math.trunc({} / {})", args[0], SCALE)),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::{Interpreter, Value};
+    use crate::parser::{parse_module, Atom, Item};
+    use crate::test_support::{env_from_source, TempDir};
+    use crate::transpiler::transpile;
+    use crate::verification::{verify, ModuleEnv};
+    use std::sync::Arc;
+
+    const SOURCE: &str = r#"
+type Fx = q16_16 where v >= -2147483648 && v <= 2147483647;
+
+atom scale_up(x: i64)
+requires: x >= -100 && x <= 100;
+ensures: result == x * 65536;
+body: fx_from_int(x);
+
+atom half(a: Fx)
+requires: a >= -65536 && a <= 65536;
+ensures: a >= 0 => (result * 2 <= a && result * 2 >= a - 1);
+ensures: a < 0 => (result * 2 >= a && result * 2 <= a + 1);
+body: fx_mul(a, 32768);
+
+atom double(a: Fx)
+requires: true;
+ensures: true;
+body: fx_add(a, a);
+"#;
+
+    fn prepare() -> (Vec<Atom>, ModuleEnv) {
+        crate::theory::register(Arc::new(FixedPoint));
+        let (items, module_env) = env_from_source(SOURCE);
+        let mut atoms = Vec::new();
+        for item in items {
+            match item {
+                Item::TypeDef(t) => crate::theory::check_base_type(&t.name, &t._base_type).unwrap(),
+                Item::Atom(a) => atoms.push(a),
+                _ => {}
+            }
+        }
+        (atoms, module_env)
+    }
+
+    #[test]
+    fn test_fixed_point_verification() {
+        let (atoms, module_env) = prepare();
+        assert_eq!(module_env.resolve_base_type("Fx"), "i64");
+        let output_dir = TempDir::new("fixed_point");
+        assert!(verify(&atoms[0], &output_dir, &module_env).is_ok());
+        // 負数の乗算は 0 方向に切り捨てる（床関数なら a < 0 の ensures が成り立たない）
+        assert!(verify(&atoms[1], &output_dir, &module_env).is_ok());
+        let err = verify(&atoms[2], &output_dir, &module_env).expect_err("a + a may overflow");
+        assert!(err.to_string().contains("Q16.16 overflow: result of fx_add()"), "{}", err);
+    }
+
+    #[test]
+    fn test_fixed_point_lowering_matches_model() {
+        let (atoms, module_env) = prepare();
+        let mut interpreter = Interpreter::new(&module_env);
+        // -3 / 65536 * 0.5 → -1.5 / 65536 → -1（0 方向）
+        assert_eq!(interpreter.call_atom(&atoms[1], &[Value::Int(-3)]), Ok(Value::Int(-1)));
+        assert_eq!(interpreter.call_atom(&atoms[1], &[Value::Int(65536)]), Ok(Value::Int(32768)));
+
+        let rust = transpile(&atoms[1], TargetLanguage::Rust, &module_env).unwrap();
+        assert!(rust.contains("(a * 32768) / 65536"), "{}", rust);
+        let go = transpile(&atoms[1], TargetLanguage::Go, &module_env).unwrap();
+        assert!(go.contains("(a * 32768) / 65536"), "{}", go);
+        let ts = transpile(&atoms[1], TargetLanguage::TypeScript, &module_env).unwrap();
+        assert!(ts.contains("Number((BigInt(a) * BigInt(32768)) / 65536n)"), "{}", ts);
+    }
+}
@@ -0,0 +1,252 @@
+// =============================================================================
+// 理論拡張 (TheoryExtension): ドメイン固有の基底型と組み込み関数
+// =============================================================================
+//
+// Int / Float のどちらの意味論にも合わないドメイン（Q16.16 固定小数点など）を
+// コンパイラをフォークせずに検証するためのプラグイン境界。拡張は次の 3 点を担う:
+//   1. `type X = <base> where ...` の未知の基底型を引き受け、値の表現に使うコアの基底型を返す
+//   2. 自身が所有する組み込み関数の呼び出しを Z3 の式と証明義務に翻訳する
+//   3. 同じ呼び出しの LLVM codegen / transpile 用の展開（言語別スニペットも可）を与える
+//
+// コアは未知の基底型・未知の関数名をエラーにする前に、登録済みの拡張へ問い合わせる。
+// 同名の atom・トレイトメソッドがあればそちらが常に優先される。
+//
+// 登録: ライブラリ API からは register() を呼ぶ。CLI では cargo feature で
+// 有効にした組み込み拡張を register_builtin() が起動時に登録する
+// （例: `cargo build --features theory-fixed-point`）。
+
+#[cfg(feature = "theory-fixed-point")]
+pub mod fixed_point;
+
+use crate::parser::Expr;
+use crate::report::FailureKind;
+use crate::transpiler::TargetLanguage;
+use crate::verification::{MumeiError, MumeiResult};
+use std::sync::{Arc, RwLock};
+use z3::ast::{Bool, Dynamic, Int};
+use z3::{Context, Solver};
+
+/// コアが直接扱う基底型（Str は長さだけを推論する文字列、bool は Z3 の Bool）
+pub const CORE_BASE_TYPES: &[&str] = &["i64", "u64", "f64", "bool", "Str"];
+
+/// 組み込み関数呼び出し 1 件分の Z3 翻訳コンテキスト
+pub struct IntrinsicCall<'c, 'ctx> {
+    pub ctx: &'ctx Context,
+    pub name: &'c str,
+    /// 評価済みの引数
+    pub args: &'c [Dynamic<'ctx>],
+    /// 事実を追加できる solver（契約式の評価中は None）
+    pub solver: Option<&'c Solver<'ctx>>,
+    scope: &'c str,
+    prove: &'c dyn Fn(&Bool<'ctx>) -> bool,
+}
+
+impl<'c, 'ctx> IntrinsicCall<'c, 'ctx> {
+    pub(crate) fn new(
+        ctx: &'ctx Context,
+        name: &'c str,
+        args: &'c [Dynamic<'ctx>],
+        solver: Option<&'c Solver<'ctx>>,
+        scope: &'c str,
+        prove: &'c dyn Fn(&Bool<'ctx>) -> bool,
+    ) -> Self {
+        IntrinsicCall { ctx, name, args, solver, scope, prove }
+    }
+
+    /// 検証中の atom でスコープした Z3 シンボル名（新しい定数を作るときに使う）
+    pub fn sym(&self, name: &str) -> String {
+        if self.scope.is_empty() {
+            name.to_string()
+        } else {
+            format!("{}::{}", self.scope, name)
+        }
+    }
+
+    /// i 番目の引数を整数として取り出す
+    pub fn int_arg(&self, i: usize) -> MumeiResult<Int<'ctx>> {
+        self.args.get(i).and_then(|a| a.as_int()).ok_or_else(|| MumeiError::TypeError(format!(
+            "{}(): argument {} must be an integer ({} argument(s) given)",
+            self.name, i + 1, self.args.len()
+        )))
+    }
+
+    /// 呼び出し箇所での証明義務。証明できなければ message を kind の検証エラーとして返す
+    pub fn require(&self, kind: FailureKind, goal: &Bool<'ctx>, message: &str) -> MumeiResult<()> {
+        if self.solver.is_none() || (self.prove)(goal) {
+            Ok(())
+        } else {
+            Err(MumeiError::failure(kind, message))
+        }
+    }
+
+    /// 呼び出し結果についての事実を solver に追加する
+    pub fn assume(&self, fact: &Bool<'ctx>) {
+        if let Some(solver) = self.solver {
+            solver.assert(fact);
+        }
+    }
+}
+
+/// ドメイン固有の理論を提供するプラグイン
+pub trait TheoryExtension: Send + Sync {
+    /// 拡張名（`mumei inspect` に表示し、登録の重複判定にも使う）
+    fn name(&self) -> &str;
+
+    /// 基底型 base を引き受けるなら、その値の表現に使うコアの基底型（"i64" / "u64" / "f64"）を返す
+    fn carrier_type(&self, _base: &str) -> Option<&'static str> {
+        None
+    }
+
+    /// name がこの拡張の組み込み関数か
+    fn owns_intrinsic(&self, name: &str) -> bool;
+
+    /// 組み込み関数呼び出しを Z3 の値に翻訳する。
+    /// 範囲などの前提は call.require で証明義務に、結果の性質は call.assume で事実にする。
+    fn translate_call<'ctx>(&self, call: &IntrinsicCall<'_, 'ctx>) -> MumeiResult<Dynamic<'ctx>>;
+
+    /// 呼び出しをコアの式に展開する。LLVM codegen とインタプリタ、
+    /// transpile_call が None を返す言語ではこの展開を使う
+    fn lower_call(&self, name: &str, args: &[Expr]) -> MumeiResult<Expr>;
+
+    /// 言語別のスニペット（args は変換済みの引数）。None なら lower_call の展開を変換する
+    fn transpile_call(&self, _name: &str, _lang: TargetLanguage, _args: &[String]) -> Option<String> {
+        None
+    }
+}
+
+static REGISTRY: RwLock<Vec<Arc<dyn TheoryExtension>>> = RwLock::new(Vec::new());
+
+/// 拡張を登録する（同名の拡張は置き換える）
+pub fn register(extension: Arc<dyn TheoryExtension>) {
+    let mut registry = REGISTRY.write().unwrap_or_else(|e| e.into_inner());
+    registry.retain(|e| e.name() != extension.name());
+    registry.push(extension);
+}
+
+/// 登録済みの拡張（登録順）
+pub fn registered() -> Vec<Arc<dyn TheoryExtension>> {
+    REGISTRY.read().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+/// cargo feature で有効にした組み込み拡張を登録する（CLI の起動時とライブラリ API の各関数が呼ぶ）。
+/// 2 回目以降の呼び出しは何もしない
+pub fn register_builtin() {
+    static REGISTERED: std::sync::Once = std::sync::Once::new();
+    REGISTERED.call_once(|| {
+        #[cfg(feature = "theory-fixed-point")]
+        register(Arc::new(fixed_point::FixedPoint));
+    });
+}
+
+/// 組み込み関数 name を所有する拡張
+pub fn find_intrinsic(name: &str) -> Option<Arc<dyn TheoryExtension>> {
+    registered().into_iter().find(|e| e.owns_intrinsic(name))
+}
+
+/// 基底型の表現に使うコアの基底型。コアの基底型はそのまま、拡張の基底型は拡張に問い合わせる
+pub fn carrier_type(base: &str) -> Option<&'static str> {
+    if let Some(core) = CORE_BASE_TYPES.iter().find(|t| **t == base) {
+        return Some(core);
+    }
+    registered().iter().find_map(|e| e.carrier_type(base))
+}
+
+/// `type name = base where ...` の基底型が、コアか登録済みの拡張で扱えるか検査する
+pub fn check_base_type(type_name: &str, base: &str) -> MumeiResult<()> {
+    if carrier_type(base).is_some() {
+        return Ok(());
+    }
+    Err(MumeiError::TypeError(format!(
+        "Unknown base type '{}' for type '{}' (expected {}, or a base type claimed by a registered theory extension)",
+        base, type_name, CORE_BASE_TYPES.join(", ")
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{Item, Op};
+    use crate::test_support::{env_from_source, TempDir};
+    use crate::transpiler::{transpile, TargetLanguage};
+    use crate::verification::{verify, ModuleEnv};
+
+    /// テスト用の最小の拡張: 基底型 `even` と組み込み関数 `twice(x) = 2 * x`（結果は偶数）
+    struct Twice;
+
+    impl TheoryExtension for Twice {
+        fn name(&self) -> &str {
+            "test-twice"
+        }
+
+        fn carrier_type(&self, base: &str) -> Option<&'static str> {
+            (base == "even").then_some("i64")
+        }
+
+        fn owns_intrinsic(&self, name: &str) -> bool {
+            name == "twice"
+        }
+
+        fn translate_call<'ctx>(&self, call: &IntrinsicCall<'_, 'ctx>) -> MumeiResult<Dynamic<'ctx>> {
+            let x = call.int_arg(0)?;
+            call.require(FailureKind::Precondition, &x.ge(&Int::from_i64(call.ctx, 0)), "twice(): argument may be negative")?;
+            Ok((&x * &Int::from_i64(call.ctx, 2)).into())
+        }
+
+        fn lower_call(&self, _name: &str, args: &[Expr]) -> MumeiResult<Expr> {
+            Ok(Expr::BinaryOp(Box::new(Expr::Number(2)), Op::Mul, Box::new(args[0].clone())))
+        }
+
+        fn transpile_call(&self, _name: &str, lang: TargetLanguage, args: &[String]) -> Option<String> {
+            (lang == TargetLanguage::TypeScript).then(|| format!("twiceTs({})", args[0]))
+        }
+    }
+
+    const SOURCE: &str = r#"
+type Even = even where v >= 0;
+
+atom double_it(x: i64)
+requires: x >= 0;
+ensures: result == x + x;
+body: twice(x);
+
+atom double_any(x: i64)
+requires: true;
+ensures: true;
+body: twice(x);
+"#;
+
+    fn prepare() -> (Vec<crate::parser::Atom>, ModuleEnv) {
+        register(Arc::new(Twice));
+        let (items, module_env) = env_from_source(SOURCE);
+        let atoms = items.into_iter().filter_map(|item| match item {
+            Item::Atom(a) => Some(a),
+            _ => None,
+        }).collect();
+        (atoms, module_env)
+    }
+
+    #[test]
+    fn test_extension_claims_base_type() {
+        let (_, module_env) = prepare();
+        assert_eq!(module_env.resolve_base_type("Even"), "i64");
+        assert!(check_base_type("Even", "even").is_ok());
+        assert!(check_base_type("Fx", "q8_8_unregistered").unwrap_err().to_string().contains("Unknown base type 'q8_8_unregistered'"));
+        assert!(check_base_type("Nat", "i64").is_ok());
+    }
+
+    #[test]
+    fn test_extension_routes_unknown_calls() {
+        let (atoms, module_env) = prepare();
+        let output_dir = TempDir::new("theory");
+        // Z3: 拡張の翻訳で ensures が証明でき、拡張の証明義務は requires がないと失敗する
+        assert!(verify(&atoms[0], &output_dir, &module_env).is_ok());
+        let err = verify(&atoms[1], &output_dir, &module_env).expect_err("argument may be negative");
+        assert!(err.to_string().contains("twice(): argument may be negative"), "{}", err);
+
+        // transpile: 言語別スニペットがあればそれを、なければコアの式への展開を使う
+        let ts = transpile(&atoms[0], TargetLanguage::TypeScript, &module_env).unwrap();
+        assert!(ts.contains("twiceTs(x)"), "{}", ts);
+        let rust = transpile(&atoms[0], TargetLanguage::Rust, &module_env).unwrap();
+        assert!(rust.contains("2 * x"), "{}", rust);
+    }
+}
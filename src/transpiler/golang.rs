@@ -1,4 +1,11 @@
-use crate::parser::{Expr, Op, Atom, ImportDecl, EnumDef, StructDef, TraitDef, ImplDef, parse_expression};
+use crate::parser::{Expr, Op, Atom, ImportDecl, EnumDef, EnumVariant, StructDef, TraitDef, ImplDef};
+use crate::ast::ResultKind;
+use crate::verification::ModuleEnv;
+use super::{RuntimeChecks, TranspileCtx, Unsupported, UnsupportedPolicy, TargetLanguage, FieldStep, PatternTest, doc_contract, doc_elements, doc_where, is_recursive_field, param_enum, parse_body, param_type_name, return_enum, negated};
+
+/// 未対応構文のマーカー（CompileError ポリシー）を含むバンドルの先頭に付けるビルドタグ。
+/// 通常のビルドではファイルごと除外されるため、依存側で未定義シンボルとしてビルドが失敗する。
+pub const UNSUPPORTED_BUILD_TAG: &str = "//go:build mumei_unsupported\n\n";
 
 /// 型名をベース型に解決する（transpiler ローカル版）
 fn resolve_base_type(name: &str) -> String {
@@ -7,31 +14,39 @@ fn resolve_base_type(name: &str) -> String {
 
 /// import 宣言から Go のモジュールヘッダーを生成する
 /// 例: package main\nimport "path/to/math"
-pub fn transpile_module_header_go(imports: &[ImportDecl], module_name: &str) -> String {
+/// has_async が true の場合、async atom の ctx 引数用に "context" を import する。
+/// Go は未使用の import をコンパイルエラーにするため、呼び出し側は参照される import だけを渡す
+/// （transpiler::referenced_imports）。ブランク import（`_ "pkg"`）は自動では出さない
+pub fn transpile_module_header_go(imports: &[ImportDecl], module_name: &str, has_async: bool) -> String {
     let mut lines = Vec::new();
     lines.push(format!("package {}", module_name));
     lines.push(String::new());
 
-    // import ブロック
-    let mut import_paths = Vec::new();
-    for import in imports {
-        let pkg_name = import.alias.as_deref()
+    // import ブロック（パス順、重複なし）
+    let mut import_paths: Vec<&str> = imports.iter()
+        .map(|import| import.alias.as_deref()
             .unwrap_or_else(|| {
                 import.path.rsplit('/').next().unwrap_or(&import.path)
                     .trim_end_matches(".mm")
-            });
-        import_paths.push(format!("\t\"{}\"", pkg_name));
+            }))
+        .collect();
+    if has_async {
+        import_paths.push("context");
     }
+    import_paths.sort();
+    import_paths.dedup();
     if !import_paths.is_empty() {
         lines.push("import (".to_string());
-        lines.extend(import_paths);
+        lines.extend(import_paths.iter().map(|path| format!("\t\"{}\"", path)));
         lines.push(")".to_string());
         lines.push(String::new());
     }
     lines.join("\n")
 }
 
-/// Enum 定義を Go の const + type に変換する
+/// Enum 定義を Go の const + type に変換する。
+/// payload を持つ Enum は tag の型に加えて `{Name}Node` 構造体（Tag と Variant ごとのフィールド）を出力する。
+/// 再帰フィールドは `*{Name}Node` ポインタで表す（Go の struct は自身を値として含めないため）。
 pub fn transpile_enum_go(enum_def: &EnumDef) -> String {
     let mut lines = Vec::new();
     lines.push(format!("// Verified Enum: {}", enum_def.name));
@@ -46,13 +61,39 @@ pub fn transpile_enum_go(enum_def: &EnumDef) -> String {
         }
     }
     lines.push(")".to_string());
+    if has_node_go(enum_def) {
+        let node = format!("{}Node", enum_def.name);
+        lines.push(String::new());
+        if enum_def.is_recursive {
+            lines.push(format!("// {} holds a {} value; recursive fields point to the next node (nil when unset).", node, enum_def.name));
+        } else {
+            lines.push(format!("// {} holds a {} value; {{Variant}}{{i}} fields hold the payload of the Tag variant.", node, enum_def.name));
+        }
+        lines.push(format!("type {} struct {{", node));
+        lines.push(format!("\tTag {}", enum_def.name));
+        for variant in &enum_def.variants {
+            for (fi, field) in variant.fields.iter().enumerate() {
+                let go_type = if is_recursive_field(enum_def, field) {
+                    format!("*{}", node)
+                } else {
+                    map_type_go(Some(field.as_str()))
+                };
+                lines.push(format!("\t{}{} {}", variant.name, fi, go_type));
+            }
+        }
+        lines.push("}".to_string());
+    }
     lines.join("\n")
 }
 
-/// Struct 定義を Go の struct に変換する（Go 1.18+ Generics 対応）
+/// Struct 定義を Go の struct に変換する（Go 1.18+ Generics 対応）。
+/// フィールドには元の名前の json タグを付け、encoding/json の出力を Rust（serde）・TypeScript と揃える
 pub fn transpile_struct_go(struct_def: &StructDef) -> String {
     let mut lines = Vec::new();
     lines.push(format!("// Verified Struct: {}", struct_def.name));
+    if let Some(invariant) = &struct_def.invariant {
+        lines.push(format!("// Invariant: {}", invariant));
+    }
     // Generics: 型パラメータがある場合は [T any, U any] を付与（Go 1.18+）
     let type_params_str = if struct_def.type_params.is_empty() {
         String::new()
@@ -70,7 +111,7 @@ pub fn transpile_struct_go(struct_def: &StructDef) -> String {
         }
         // Go のフィールド名は大文字始まり（エクスポート）
         let capitalized = capitalize_first(&field.name);
-        lines.push(format!("\t{} {}", capitalized, go_type));
+        lines.push(format!("\t{} {} `json:\"{}\"`", capitalized, go_type, field.name));
     }
     lines.push("}".to_string());
     lines.join("\n")
@@ -119,41 +160,124 @@ pub fn transpile_impl_go(impl_def: &ImplDef) -> String {
     lines.join("\n")
 }
 
-pub fn transpile_to_go(atom: &Atom) -> String {
-    // パラメータの型を精緻型名からマッピング
-    // ref mut はポインタ型 *T、ref は値渡し（Go は暗黙的に参照渡し）
-    let params: Vec<String> = atom.params.iter()
+/// パラメータの型を精緻型名からマッピング
+/// ref mut はポインタ型 *T、ref は値渡し（Go は暗黙的に参照渡し）
+/// async atom は第1引数に ctx context.Context を取り、async な呼び出し先へ伝播する
+/// Enum 型のパラメータは payload を持つなら `{Name}Node`、持たなければ tag の型で受け取る
+fn format_params_go(atom: &Atom, module_env: &ModuleEnv) -> String {
+    let ctx_param = if atom.is_async { Some("ctx context.Context".to_string()) } else { None };
+    let params: Vec<String> = ctx_param.into_iter().chain(atom.params.iter()
         .map(|p| {
-            let go_type = map_type_go(p.type_name.as_deref());
+            let go_type = match param_enum(p, module_env) {
+                Some(e) if has_node_go(e) => format!("{}Node", e.name),
+                Some(e) => e.name.clone(),
+                None => map_type_go(param_type_name(p, module_env)),
+            };
             if p.is_ref_mut {
                 format!("{} *{}", p.name, go_type)
             } else {
                 format!("{} {}", p.name, go_type)
             }
-        })
+        }))
         .collect();
-    let params_str = params.join(", ");
+    params.join(", ")
+}
+
+pub fn transpile_to_go(atom: &Atom, ctx: &TranspileCtx) -> Result<String, Unsupported> {
+    let params_str = format_params_go(atom, ctx.module_env);
+    let return_type = return_type_go(atom, ctx.module_env);
 
     // ボディのパースと変換
-    let body = format_expr_go(&parse_expression(&atom.body_expr));
+    let mut body = format_expr_go(&super::as_block(parse_body(atom)?), ctx)?;
+
+    // [build] runtime_checks: requires は入口で panic する guard、ensures は名前付き戻り値 result を defer で検査する
+    let checks = RuntimeChecks::new(ctx, format_expr_go);
+    let mut return_type = return_type;
+    if !checks.is_empty() {
+        let mut lines = checks.omitted_comments("//");
+        for check in &checks.requires {
+            lines.push(format!("if {} {{\n        panic({:?})\n    }}", negated(&check.condition), check.message));
+        }
+        if !checks.ensures.is_empty() {
+            return_type = format!("(result {})", return_type);
+            lines.push("defer func() {".to_string());
+            for check in &checks.ensures {
+                lines.push(format!("    if {} {{\n            panic({:?})\n        }}", negated(&check.condition), check.message));
+            }
+            lines.push("}()".to_string());
+        }
+        lines.push(body);
+        body = lines.join("\n    ");
+    }
 
     // mathパッケージが必要な関数(sqrt等)があるか簡易チェック（実用上はASTを走査すべきですが、ここでは含めます）
-    let imports = if atom.body_expr.contains("sqrt") { "import \"math\"\n\n" } else { "" };
+    let uses_math = ["sqrt", "approx_eq", "~="].iter().any(|f| atom.body_expr.contains(f));
+    let imports = if uses_math { "import \"math\"\n\n" } else { "" };
+
+    let async_comment = if atom.is_async { "// NOTE: This function is async (ctx is propagated to awaited async callees)\n" } else { "" };
+    Ok(format!(
+        "{}{}// {} is a verified Atom.\n{}// Requires: {}\n// Ensures: {}\nfunc {}({}) {} {{\n    {}\n}}",
+        imports, async_comment, atom.name, doc_where(atom, "//") + &doc_elements(atom, ctx.module_env, "//"), doc_contract(&atom.requires_raw, "//"), doc_contract(&atom.ensures_raw, "//"), atom.name, params_str, return_type, body
+    ))
+}
 
-    let async_comment = if atom.is_async { "// NOTE: This function is async (use goroutine for concurrent execution)\n" } else { "" };
+/// 戻り値型（`-> Point` なら構造体、それ以外は本体の値の種類 crate::ast::result_kind から決める）
+fn return_type_go(atom: &Atom, module_env: &ModuleEnv) -> String {
+    if let Some(enum_def) = return_enum(atom, module_env) {
+        return if has_node_go(enum_def) { format!("{}Node", enum_def.name) } else { enum_def.name.clone() };
+    }
+    match crate::ast::struct_return(atom, module_env) {
+        Some(sdef) => sdef.name.clone(),
+        None => go_type_of(crate::ast::result_kind(atom, module_env)).to_string(),
+    }
+}
+
+/// 値の種類に対応する Go の型
+fn go_type_of(kind: ResultKind) -> &'static str {
+    match kind {
+        ResultKind::Int => "int64",
+        ResultKind::Float => "float64",
+        ResultKind::Bool => "bool",
+        ResultKind::Str => "string",
+    }
+}
+
+/// 未対応構文を含む atom の代替定義。
+/// CompileError: panic に加えてトップレベルコメントを残し、バンドルには UNSUPPORTED_BUILD_TAG が付く
+/// AllowPartial: panic する TODO スタブ
+pub fn unsupported_stub_go(atom: &Atom, module_env: &ModuleEnv, unsupported: &Unsupported, policy: UnsupportedPolicy) -> String {
+    let message = unsupported.message(TargetLanguage::Go);
+    let marker = match policy {
+        UnsupportedPolicy::CompileError => format!("// MUMEI UNSUPPORTED: {} ({})\n// This file is excluded from normal builds by the mumei_unsupported build tag.\n", unsupported.construct, unsupported.reason),
+        UnsupportedPolicy::AllowPartial => String::new(),
+    };
+    let todo = match policy {
+        UnsupportedPolicy::CompileError => String::new(),
+        UnsupportedPolicy::AllowPartial => format!("// TODO(mumei): {}\n    ", unsupported.reason),
+    };
     format!(
-        "{}{}// {} is a verified Atom.\n// Requires: {}\n// Ensures: {}\nfunc {}({}) int64 {{\n    {}\n}}",
-        imports, async_comment, atom.name, atom.requires, atom.ensures, atom.name, params_str, body
+        "{}// {} is a verified Atom.\n{}// Requires: {}\n// Ensures: {}\nfunc {}({}) {} {{\n    {}panic(\"{} (atom '{}')\")\n}}",
+        marker, atom.name, doc_where(atom, "//") + &doc_elements(atom, module_env, "//"), doc_contract(&atom.requires_raw, "//"), doc_contract(&atom.ensures_raw, "//"), atom.name, format_params_go(atom, module_env), return_type_go(atom, module_env), todo, message, atom.name
     )
 }
 
 fn map_type_go(type_name: Option<&str>) -> String {
+    // nullable（`T?` / Option<T>）は nil 許容のポインタで表す
+    if let Some(inner) = type_name.and_then(crate::ast::option_inner_type) {
+        return format!("*{}", map_type_go(Some(&inner)));
+    }
+    // 配列（`[T]`）はスライスで表す
+    if let Some(elem) = type_name.and_then(crate::ast::slice_elem_type) {
+        return format!("[]{}", map_type_go(Some(&elem)));
+    }
     match type_name {
         Some(name) => {
             let base = resolve_base_type(name);
             match base.as_str() {
                 "f64" => "float64".to_string(),
                 "u64" => "uint64".to_string(),
+                "Str" => "string".to_string(),
+                "bool" => "bool".to_string(),
                 _ => "int64".to_string(),
             }
         },
@@ -161,47 +285,100 @@ fn map_type_go(type_name: Option<&str>) -> String {
     }
 }
 
-fn format_expr_go(expr: &Expr) -> String {
-    match expr {
+fn format_expr_go(expr: &Expr, ctx: &TranspileCtx) -> Result<String, Unsupported> {
+    let code = match expr {
+        // 型推論で int に落ちないよう i64::MIN は int64 へ明示変換する
+        Expr::Number(n) if *n == i64::MIN => "int64(-9223372036854775808)".to_string(),
         Expr::Number(n) => n.to_string(),
         Expr::Float(f) => format!("{:.15}", f), // Type System 2.0: 浮動小数点
-        Expr::Variable(v) => v.clone(),
-        Expr::ArrayAccess(name, idx) => format!("{}[{}]", name, format_expr_go(idx)),
+        // JSON の文字列エスケープは Go の解釈付き文字列リテラルとしても有効
+        Expr::StringLit(s) => serde_json::to_string(s).unwrap_or_default(),
+        Expr::Bool(b) => b.to_string(),
+        Expr::Variable(v) => match ctx.variant_constructor(v) {
+            Some((enum_def, variant)) => format_variant_go(enum_def, variant, &[]),
+            None => v.clone(),
+        },
+        Expr::ArrayAccess(name, idx) => format!("{}[{}]", name, format_expr_go(idx, ctx)?),
+
+        // 理論拡張（crate::theory）の組み込み関数
+        Expr::Call(name, args) if ctx.is_intrinsic(name) => super::transpile_intrinsic(name, args, TargetLanguage::Go, ctx, format_expr_go)?,
 
         Expr::Call(name, args) => { // Standard Library 対応
-            let args_str: Vec<String> = args.iter().map(format_expr_go).collect();
+            let args_str = args.iter().map(|a| format_expr_go(a, ctx)).collect::<Result<Vec<_>, _>>()?;
             match name.as_str() {
                 "sqrt" => format!("math.Sqrt({})", args_str.join(", ")),
+                "approx_eq" => super::transpile_approx_eq(TargetLanguage::Go, &args_str)?,
                 "len" => format!("int64(len({}))", args_str.join(", ")),
-                _ => format!("{}({})", name, args_str.join(", ")),
+                "is_some" if ctx.nullable_arg(args).is_some() => format!("({} != nil)", args_str[0]),
+                "is_none" if ctx.nullable_arg(args).is_some() => format!("({} == nil)", args_str[0]),
+                // nil の参照外しは実行時 panic になる（検証済みなら到達しない）
+                "value" if ctx.nullable_arg(args).is_some() => format!("(*{})", args_str[0]),
+                _ if ctx.is_async_callee(name) => {
+                    // async atom は ctx を第1引数に取る。同期 atom から呼ぶ場合は Background を渡す
+                    let ctx_arg = if ctx.in_async { "ctx" } else { "context.Background()" };
+                    let all_args: Vec<String> = std::iter::once(ctx_arg.to_string()).chain(args_str).collect();
+                    format!("{}({})", name, all_args.join(", "))
+                },
+                _ => match ctx.variant_constructor(name) {
+                    Some((enum_def, variant)) => format_variant_go(enum_def, variant, &args_str),
+                    None => format!("{}({})", name, args_str.join(", ")),
+                },
             }
         },
 
-        Expr::BinaryOp(l, op, r) => {
-            let op_str = match op {
-                Op::Add => "+", Op::Sub => "-", Op::Mul => "*", Op::Div => "/",
-                Op::Eq => "==", Op::Neq => "!=", Op::Gt => ">", Op::Lt => "<",
-                Op::Ge => ">=", Op::Le => "<=", Op::And => "&&", Op::Or => "||",
-                Op::Implies => "/* implies */",
-            };
-            format!("({} {} {})", format_expr_go(l), op_str, format_expr_go(r))
+        Expr::BinaryOp(..) => {
+            // 左に連なる連鎖は左端から順に組み立て、連鎖の長さぶん再帰しない。
+            // 含意 `a => b` は `(!(a) || b)` にする（開き括弧は外側の演算子から順に並ぶ）
+            let (leaf, nodes) = expr.left_spine();
+            let mut code: String = nodes.iter().rev()
+                .map(|node| if matches!(node, Expr::BinaryOp(_, Op::Implies, _)) { "(!(" } else { "(" })
+                .collect();
+            code.push_str(&format_expr_go(leaf, ctx)?);
+            for node in nodes {
+                if let Expr::BinaryOp(_, op, r) = node {
+                    if matches!(op, Op::Implies) {
+                        code.push(')');
+                    }
+                    let op_str = match op {
+                        Op::Add => "+", Op::Sub => "-", Op::Mul => "*", Op::Div => "/",
+                        Op::Eq => "==", Op::Neq => "!=", Op::Gt => ">", Op::Lt => "<",
+                        Op::Ge => ">=", Op::Le => "<=", Op::And => "&&", Op::Or => "||",
+                        Op::Implies => "||",
+                    };
+                    code.push_str(&format!(" {} {})", op_str, format_expr_go(r, ctx)?));
+                }
+            }
+            code
+        },
+
+        // 条件演算子 `c ? a : b`: Go には三項演算子がないため、式の位置では
+        // 即時実行の関数リテラルで if 文にする（let の右辺では下の Let で var + if に展開する）。
+        // 戻り値型は分岐の値の種類から決める
+        Expr::IfThenElse { cond, then_branch, else_branch } if super::is_conditional_expr(then_branch, else_branch) => {
+            format!(
+                "func() {} {{ if {} {{ return {} }}; return {} }}()",
+                go_type_of(crate::ast::expr_result_kind(expr, ctx.atom, ctx.module_env)),
+                format_expr_go(cond, ctx)?,
+                format_expr_go(then_branch, ctx)?,
+                format_expr_go(else_branch, ctx)?
+            )
         },
 
         Expr::IfThenElse { cond, then_branch, else_branch } => {
             format!(
                 "if {} {{\n        {}\n    }} else {{\n        {}\n    }}",
-                format_expr_go(cond),
-                format_expr_go(then_branch),
-                format_expr_go(else_branch)
+                format_expr_go(cond, ctx)?,
+                format_expr_go(then_branch, ctx)?,
+                format_expr_go(else_branch, ctx)?
             )
         },
 
         Expr::While { cond, invariant, decreases: _, body } => {
             format!(
                 "// invariant: {}\n    for {} {{\n        {}\n    }}",
-                format_expr_go(invariant),
-                format_expr_go(cond),
-                format_expr_go(body)
+                format_expr_go(invariant, ctx)?,
+                format_expr_go(cond, ctx)?,
+                format_expr_go(body, ctx)?
             )
         },
 
@@ -209,80 +386,171 @@ fn format_expr_go(expr: &Expr) -> String {
             match value.as_ref() {
                 Expr::IfThenElse { cond, then_branch, else_branch } => {
                     format!(
-                        "var {} int64\n    if {} {{\n        {} = {}\n    }} else {{\n        {} = {}\n    }}",
-                        var, format_expr_go(cond), var, format_expr_go(then_branch), var, format_expr_go(else_branch)
+                        "var {} {}\n    if {} {{\n        {} = {}\n    }} else {{\n        {} = {}\n    }}",
+                        var, go_type_of(crate::ast::expr_result_kind(value, ctx.atom, ctx.module_env)),
+                        format_expr_go(cond, ctx)?, var, format_expr_go(then_branch, ctx)?, var, format_expr_go(else_branch, ctx)?
                     )
                 },
                 _ => {
                     // 型推論を利用した定義
-                    format!("{} := {}", var, format_expr_go(value))
+                    format!("{} := {}", var, format_expr_go(value, ctx)?)
                 }
             }
         },
 
         Expr::Assign { var, value } => {
-            format!("{} = {}", var, format_expr_go(value))
+            format!("{} = {}", var, format_expr_go(value, ctx)?)
         },
 
         Expr::Block(stmts) => {
-            stmts.iter().map(|s| {
-                let code = format_expr_go(s);
-                if code.starts_with("if") || code.contains(":=") || code.contains(" = ") ||
+            let mut lines = Vec::new();
+            for s in stmts {
+                let code = format_expr_go(s, ctx)?;
+                // match は値を返す関数リテラルなので、中の := や = があっても値として返す
+                if matches!(s, Expr::Match { .. }) {
+                    lines.push(format!("return {}", code));
+                } else if code.starts_with("if") || code.contains(":=") || code.contains(" = ") ||
                     code.starts_with("for") || code.starts_with("//") || code.starts_with("var") {
-                    code
+                    lines.push(code);
                 } else {
-                    format!("return {}", code)
+                    lines.push(format!("return {}", code));
                 }
-            }).collect::<Vec<_>>().join("\n    ")
+            }
+            lines.join("\n    ")
         },
 
         Expr::StructInit { type_name, fields } => {
-            let field_strs: Vec<String> = fields.iter()
-                .map(|(name, expr)| format!("{}: {}", name, format_expr_go(expr)))
-                .collect();
+            let field_strs = fields.iter()
+                .map(|(name, expr)| Ok(format!("{}: {}", name, format_expr_go(expr, ctx)?)))
+                .collect::<Result<Vec<_>, Unsupported>>()?;
             format!("{}{{{}}}", type_name, field_strs.join(", "))
         },
 
         Expr::FieldAccess(expr, field) => {
-            format!("{}.{}", format_expr_go(expr), field)
+            format!("{}.{}", format_expr_go(expr, ctx)?, field)
         },
 
         Expr::Match { target, arms } => {
-            // Go には match がないため switch 文に変換
-            let target_str = format_expr_go(target);
+            // Go には match 式がないため、値を返す即時実行の関数リテラル内の switch にする
+            let target_enum = super::match_target_enum(target, ctx);
+            let planned = super::plan_arms(arms, target_enum, ctx.module_env)?;
+            // Enum 値のターゲットはパラメータそのもの。それ以外（整数）は引数 __m で一度だけ評価する
+            let (subject, param, arg) = match target.as_ref() {
+                Expr::Variable(name) => (name.clone(), String::new(), String::new()),
+                _ => ("__m".to_string(), "__m int64".to_string(), format_expr_go(target, ctx)?),
+            };
+            let has_node = target_enum.map_or(false, has_node_go);
+            let simple = super::is_simple_switch(&planned);
             let mut cases = Vec::new();
-            for arm in arms {
-                let body = format_expr_go(&arm.body);
-                match &arm.pattern {
-                    crate::parser::Pattern::Literal(n) => {
-                        cases.push(format!("case {}:\n        return {}", n, body));
-                    },
-                    crate::parser::Pattern::Variant { variant_name, .. } => {
-                        cases.push(format!("// {}\n        case /* {} */:\n        return {}", variant_name, variant_name, body));
-                    },
-                    crate::parser::Pattern::Wildcard | crate::parser::Pattern::Variable(_) => {
-                        cases.push(format!("default:\n        return {}", body));
-                    },
-                }
+            for p in &planned {
+                let bind = |e: &Expr| super::substitute_bindings(e, &p.plan, |path| binding_go(&subject, path));
+                let label = if p.catch_all {
+                    "default:".to_string()
+                } else if simple {
+                    match &p.plan.tests[0] {
+                        PatternTest::Variant { variant, .. } => format!("case {}:", variant),
+                        PatternTest::Literal { value, .. } => format!("case {}:", value),
+                    }
+                } else {
+                    let mut conds: Vec<String> = p.plan.tests.iter().map(|t| test_go(&subject, t, has_node)).collect();
+                    if let Some(guard) = &p.arm.guard {
+                        conds.push(format_expr_go(&bind(guard), ctx)?);
+                    }
+                    format!("case {}:", conds.join(" && "))
+                };
+                let body = format_expr_go(&super::as_block(bind(&p.arm.body)), ctx)?;
+                cases.push(format!("{}\n            {}", label, body.replace("\n    ", "\n            ")));
+            }
+            // 網羅的な match でも switch は終端文にならないので、常に default を置く
+            if !planned.iter().any(|p| p.catch_all) {
+                cases.push(format!("default:\n            panic({:?})", super::UNREACHABLE_ARM));
             }
-            format!("switch {} {{\n    {}\n    }}", target_str, cases.join("\n    "))
+            let switch = if !simple {
+                "switch {".to_string()
+            } else if has_node {
+                format!("switch {}.Tag {{", subject)
+            } else {
+                format!("switch {} {{", subject)
+            };
+            let result_type = go_type_of(crate::ast::expr_result_kind(expr, ctx.atom, ctx.module_env));
+            format!("func({}) {} {{\n        {}\n        {}\n        }}\n    }}({})", param, result_type, switch, cases.join("\n        "), arg)
         },
 
-        Expr::Acquire { resource, body } => {
-            // Go: 即時実行関数リテラルでスコープを限定し、defer でブロック終了時に Unlock する。
-            // defer は関数スコープなので、ネストやループ内でも正しくブロック終了時に解放される。
-            let body_str = format_expr_go(body);
-            format!("func() int64 {{\n        {r}.Lock()\n        defer {r}.Unlock()\n        return {body}\n    }}()", r = resource, body = body_str)
+        Expr::Acquire { .. } => {
+            // resource は sync.Mutex の変数宣言として出力されていないため、Lock() の対象が存在しない
+            return Err(Unsupported::new("acquire", "resources are not lowered to sync.Mutex declarations"));
         },
         Expr::Async { body } => {
             // Go: goroutine + channel パターン
-            let body_str = format_expr_go(body);
+            let body_str = format_expr_go(body, ctx)?;
             format!("func() int64 {{\n        ch := make(chan int64, 1)\n        go func() {{ ch <- func() int64 {{ {} }}() }}()\n        return <-ch\n    }}()", body_str)
         },
         Expr::Await { expr } => {
-            // Go: channel receive（goroutine の結果を待機）
-            let expr_str = format_expr_go(expr);
-            format!("<-{}", expr_str)
+            // Go: async atom は ctx を受け取るブロッキング呼び出しとして出力する。
+            // async ブロックも即時に結果を返す関数リテラルなので、await はそのまま値になる。
+            return format_expr_go(expr, ctx);
+        },
+        Expr::Assume { text, justification, .. } => {
+            // assume は検証時の仮定なので、出力ではコメントとして残すだけにする
+            format!("// assume: {} ({})", text, justification.replace('\n', " "))
+        },
+        // ref mut パラメータはポインタ *T、ref は値渡し。自身の ref mut パラメータはすでにポインタ
+        Expr::RefArg { expr, mutable } => {
+            let arg = format_expr_go(expr, ctx)?;
+            let is_pointer = matches!(expr.as_ref(), Expr::Variable(v) if ctx.borrowed_params.get(v) == Some(&true));
+            match (*mutable, is_pointer) {
+                (true, false) => format!("&{}", arg),
+                (false, true) => format!("*{}", arg),
+                _ => arg,
+            }
+        },
+    };
+    Ok(code)
+}
+
+/// Enum を `{Name}Node` 構造体（Tag と payload のフィールド）で表すか。payload のない Enum は tag の型だけで表す
+fn has_node_go(enum_def: &EnumDef) -> bool {
+    enum_def.variants.iter().any(|v| !v.fields.is_empty())
+}
+
+/// Variant の構築。payload を持つ Enum は `{Name}Node` のリテラル（再帰フィールドは値のコピーへのポインタ）、
+/// 持たない Enum は tag の定数
+fn format_variant_go(enum_def: &EnumDef, variant: &EnumVariant, args: &[String]) -> String {
+    if !has_node_go(enum_def) {
+        return variant.name.clone();
+    }
+    let node = format!("{}Node", enum_def.name);
+    let payload = variant.fields.iter().zip(args).enumerate().map(|(i, (field, arg))| {
+        let value = if is_recursive_field(enum_def, field) {
+            format!("func(v {}) *{} {{ return &v }}({})", node, node, arg)
+        } else {
+            arg.clone()
+        };
+        format!("{}{}: {}", variant.name, i, value)
+    });
+    let fields: Vec<String> = std::iter::once(format!("Tag: {}", variant.name)).chain(payload).collect();
+    format!("{}{{{}}}", node, fields.join(", "))
+}
+
+/// match ターゲット subject からフィールドパスを辿る式（ポインタのフィールドも `.` で自動的に参照外しされる）
+fn field_path_go(subject: &str, path: &[FieldStep]) -> String {
+    path.iter().fold(subject.to_string(), |acc, step| format!("{}.{}{}", acc, step.variant, step.index))
+}
+
+/// パターン変数の値。再帰フィールドはポインタなので、参照外しした `{Name}Node` 値にする
+fn binding_go(subject: &str, path: &[FieldStep]) -> String {
+    let field = field_path_go(subject, path);
+    if path.last().map_or(false, |step| step.recursive) { format!("(*{})", field) } else { field }
+}
+
+/// パターンの判定の条件式。再帰フィールドの Enum 値は nil でないことを先に確かめる
+fn test_go(subject: &str, test: &PatternTest, has_node: bool) -> String {
+    match test {
+        PatternTest::Variant { path, variant } => {
+            let value = field_path_go(subject, path);
+            let tag = if has_node { format!("{}.Tag == {}", value, variant) } else { format!("{} == {}", value, variant) };
+            if path.last().map_or(false, |step| step.recursive) { format!("{} != nil && {}", value, tag) } else { tag }
         },
+        PatternTest::Literal { path, value } => format!("{} == {}", field_path_go(subject, path), value),
     }
 }
@@ -2,36 +2,704 @@ pub mod rust;
 pub mod golang;
 pub mod typescript;
 
-use crate::parser::{Atom, ImportDecl, EnumDef, StructDef, TraitDef, ImplDef};
+use crate::parser::{Atom, AtomTargets, Expr, ImportDecl, EnumDef, EnumVariant, RefinedType, StructDef, TraitDef, ImplDef, Item, MatchArm, Op, Param, Pattern, ATOM_TARGETS};
+use crate::verification::{self, ModuleEnv};
+use std::collections::{HashMap, HashSet};
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum TargetLanguage {
     TypeScript,
     Rust,
     Go,
 }
 
-pub fn transpile(atom: &Atom, lang: TargetLanguage) -> String {
+impl TargetLanguage {
+    /// ビルドサマリー・生成コード中のメッセージで使う言語名
+    pub fn name(&self) -> &'static str {
+        match self {
+            TargetLanguage::TypeScript => "TypeScript",
+            TargetLanguage::Rust => "Rust",
+            TargetLanguage::Go => "Go",
+        }
+    }
+
+    /// atom のターゲット注釈（`@only(...)` / `@exclude(...)`）で使う名前
+    pub fn target_name(&self) -> &'static str {
+        match self {
+            TargetLanguage::TypeScript => "typescript",
+            TargetLanguage::Rust => "rust",
+            TargetLanguage::Go => "go",
+        }
+    }
+}
+
+// =============================================================================
+// 未対応構文ポリシー
+// =============================================================================
+//
+// バックエンドがまだ正しく表現できない構文（例: ロック宣言を伴わない acquire）に対して
+// 推測のコードを出力すると、コンパイルが通ってしまい実行時に静かに誤動作することがある。
+// 式プリンタは未対応ノードで Err(Unsupported) を返し、atom 全体を以下のいずれかに置き換える:
+//   - CompileError（既定）: 生成先でコンパイルエラーになるマーカー
+//       Rust: compile_error!  /  Go: panic + ビルドタグ  /  TS: throw + @ts-expect-error
+//   - AllowPartial（--allow-partial）: 明示的な TODO スタブ（実行時に panic/throw）
+
+/// 変換先言語でまだ正しく表現できない構文
+#[derive(Debug, Clone, PartialEq)]
+pub struct Unsupported {
+    /// 構文名（例: "acquire", "implies (=>)"）
+    pub construct: String,
+    /// 未対応の理由
+    pub reason: String,
+}
+
+impl Unsupported {
+    pub fn new(construct: &str, reason: &str) -> Self {
+        Self { construct: construct.to_string(), reason: reason.to_string() }
+    }
+
+    /// 生成コードに埋め込むメッセージ
+    pub fn message(&self, lang: TargetLanguage) -> String {
+        format!("mumei: {} not supported in {} backend yet", self.construct, lang.name())
+    }
+}
+
+/// 未対応構文に遭遇したときの出力方針
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UnsupportedPolicy {
+    /// 生成先でコンパイルエラーになるマーカーを出力する（既定）
+    #[default]
+    CompileError,
+    /// TODO スタブを出力し、実行時まで失敗を遅らせる（--allow-partial）
+    AllowPartial,
+}
+
+/// ビルド全体で未対応構文に遭遇した atom × バックエンドの一覧
+#[derive(Debug, Default)]
+pub struct UnsupportedSummary {
+    pub entries: Vec<(String, TargetLanguage, Unsupported)>,
+}
+
+impl UnsupportedSummary {
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn count(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// 指定バックエンドで未対応構文があったか（Go のビルドタグ付与に使う）
+    pub fn has_backend(&self, lang: TargetLanguage) -> bool {
+        self.entries.iter().any(|(_, l, _)| *l == lang)
+    }
+
+    /// cmd_build のサマリー出力用の行
+    pub fn lines(&self) -> Vec<String> {
+        self.entries.iter()
+            .map(|(atom, lang, u)| format!("atom '{}' [{}]: {} ({})", atom, lang.name(), u.construct, u.reason))
+            .collect()
+    }
+}
+
+/// atom 本体の式を変換する際のコンテキスト。
+/// 呼び出し先 atom が async かどうかは ModuleEnv の atom 定義（is_async）から判定し、
+/// 各言語の async 呼び出し規約（Rust: .await / TS: await / Go: ctx 伝播）を切り替える。
+pub struct TranspileCtx<'a> {
+    /// 変換中の atom（match のターゲットが Enum 型パラメータかどうかの判定に使う）
+    pub atom: &'a Atom,
+    pub module_env: &'a ModuleEnv,
+    /// 変換中の atom 自身が async かどうか
+    pub in_async: bool,
+    /// nullable パラメータ（`x: T?` / `Option<T>`）の名前。
+    /// is_some / is_none / value（`x!`）を各言語の Option 表現への操作に変換する。
+    pub nullable_params: HashSet<String>,
+    /// ref / ref mut パラメータの名前 → ref mut か。生成コードではすでに参照なので、
+    /// `f(ref x)` の x がこれなら &x を重ねずにそのまま渡す
+    pub borrowed_params: HashMap<String, bool>,
+    /// requires / ensures を実行時の検査として出力するか（`[build] runtime_checks`）
+    pub runtime_checks: bool,
+}
+
+impl<'a> TranspileCtx<'a> {
+    pub fn new(atom: &'a Atom, module_env: &'a ModuleEnv) -> Self {
+        let nullable_params = atom.params.iter()
+            .filter(|p| p.type_name.as_deref().and_then(crate::ast::option_inner_type).is_some())
+            .map(|p| p.name.clone())
+            .collect();
+        let borrowed_params = atom.params.iter()
+            .filter(|p| p.is_ref || p.is_ref_mut)
+            .map(|p| (p.name.clone(), p.is_ref_mut))
+            .collect();
+        Self { atom, module_env, in_async: atom.is_async, nullable_params, borrowed_params, runtime_checks: false }
+    }
+
+    /// is_some / is_none / value の引数が nullable パラメータなら、その名前を返す
+    pub fn nullable_arg<'e>(&self, args: &'e [Expr]) -> Option<&'e str> {
+        match args {
+            [Expr::Variable(name)] if self.nullable_params.contains(name) => Some(name.as_str()),
+            _ => None,
+        }
+    }
+
+    /// 呼び出し先が理論拡張の組み込み関数か（同名の atom があればそちらを優先する）
+    pub fn is_intrinsic(&self, name: &str) -> bool {
+        self.module_env.get_atom(name).is_none() && crate::theory::find_intrinsic(name).is_some()
+    }
+
+    /// `name` が Variant の構築（`Circle(r)` / unit の `Empty`）なら、その Enum と Variant。
+    /// 同名の atom・パラメータを優先し、ジェネリックな Enum（Option 等）は対象外
+    pub fn variant_constructor(&self, name: &str) -> Option<(&'a EnumDef, &'a EnumVariant)> {
+        if self.module_env.get_atom(name).is_some() || self.atom.params.iter().any(|p| p.name == name) {
+            return None;
+        }
+        let enum_def = self.module_env.find_enum_by_variant(name).filter(|e| e.type_params.is_empty())?;
+        enum_def.variants.iter().find(|v| v.name == name).map(|variant| (enum_def, variant))
+    }
+
+    /// 呼び出し先が async atom かどうか（組み込み関数・未登録 atom は同期扱い）
+    pub fn is_async_callee(&self, name: &str) -> bool {
+        self.module_env.get_atom(name).map(|a| a.is_async).unwrap_or(false)
+    }
+}
+
+/// 契約（requires/ensures）の表示用テキストをドキュメントコメントに埋め込む。
+/// 複数行の契約は2行目以降にもコメント接頭辞を付け、記述時の改行を保つ。
+pub(crate) fn doc_contract(raw: &str, comment_prefix: &str) -> String {
+    crate::parser::indent_contract(raw, &format!("{}   ", comment_prefix))
+}
+
+/// atom の本体をパースする。構文エラーは未対応構文として報告する
+/// （parse_module は本体をパースして確かめてから atom を返すので、通常は起きない）
+pub(crate) fn parse_body(atom: &Atom) -> Result<Expr, Unsupported> {
+    crate::parser::parse_expression(&atom.body_expr).map_err(|e| Unsupported::new("syntax error", &e.to_string()))
+}
+
+/// パラメータの型名。transpiler の型マッピングは精緻型を解決しないため、
+/// ベース型が Str / bool の精緻型（`type NonEmpty = Str where ...` / `type Flag = bool where ...`）だけは
+/// "Str" / "bool" に読み替える。精緻型の配列（`[Nat]`）は要素のベース型の配列に読み替える
+/// （要素ごとの制約は doc_elements でドキュメントコメントに残す）
+pub(crate) fn param_type_name<'a>(param: &'a Param, module_env: &ModuleEnv) -> Option<&'a str> {
+    let elem_refined = param.type_name.as_deref()
+        .and_then(crate::ast::slice_elem_type)
+        .filter(|elem| module_env.get_type(elem).is_some());
+    if let Some(elem) = elem_refined {
+        Some(match module_env.resolve_base_type(&elem).as_str() {
+            "f64" => "[f64]",
+            "u64" => "[u64]",
+            "bool" => "[bool]",
+            "Str" => "[Str]",
+            _ => "[i64]",
+        })
+    } else if crate::ast::is_str_param(param, module_env) {
+        Some("Str")
+    } else if param.type_name.as_deref().map_or(false, |t| module_env.resolve_base_type(t) == "bool") {
+        Some("bool")
+    } else {
+        param.type_name.as_deref()
+    }
+}
+
+/// `where:` 節のドキュメントコメント行（末尾改行付き）。節がなければ空文字列。
+/// requires とは別の行に出し、パラメータ間の関係であることをシグネチャの近くで示す。
+pub(crate) fn doc_where(atom: &Atom, comment_prefix: &str) -> String {
+    atom.where_clause.as_ref()
+        .map(|clause| format!("{} Where: {}\n", comment_prefix, doc_contract(clause, comment_prefix)))
+        .unwrap_or_default()
+}
+
+/// 精緻型の配列パラメータ（`xs: [Nat]`）の要素制約のドキュメントコメント行（パラメータごと、末尾改行付き）。
+/// 生成コードの型はベース型の配列（`&[i64]` / `[]int64` / `number[]`）になるので、検証済みの要素制約をここに残す。
+pub(crate) fn doc_elements(atom: &Atom, module_env: &ModuleEnv, comment_prefix: &str) -> String {
+    atom.params.iter()
+        .filter_map(|param| {
+            let elem = param.type_name.as_deref().and_then(crate::ast::slice_elem_type)?;
+            let refined = module_env.get_type(&elem)?;
+            Some(format!(
+                "{} Elements: every {}[i] is {} ({} where {})\n",
+                comment_prefix, param.name, refined.name, refined.operand, doc_contract(&refined.predicate_raw, comment_prefix)
+            ))
+        })
+        .collect()
+}
+
+/// Enum のフィールド型が Enum 自身を指すか（`Cons(i64, Self)` の `Self` はパーサーが Enum 名に展開済み）。
+/// 再帰フィールドは値をそのまま埋め込めないため、各言語で間接参照（Box / ポインタ / ネストしたオブジェクト）にする。
+pub(crate) fn is_recursive_field(enum_def: &EnumDef, field: &str) -> bool {
+    field.split('<').next().unwrap_or(field).trim() == enum_def.name
+}
+
+// =============================================================================
+// match 式のパターン
+// =============================================================================
+//
+// Rust はパターンをそのまま出力できるが、Go（tag の switch）と TypeScript（kind の switch / if）は
+// パターンをターゲットの値に対する判定とフィールド参照に分解して出力する。
+// ターゲットが Enum 型のパラメータ（`s: Shape`、ジェネリックでない Enum）なら Enum 値として照合し、
+// それ以外（Enum を i64 のタグ番号で扱う書き方）は Variant を定義順のタグ番号のリテラルとして照合する。
+
+/// パラメータの型がジェネリックでないユーザー定義 Enum なら、その定義
+pub(crate) fn param_enum<'e>(param: &Param, module_env: &'e ModuleEnv) -> Option<&'e EnumDef> {
+    param.type_name.as_deref()
+        .and_then(|t| module_env.get_enum(t))
+        .filter(|e| e.type_params.is_empty())
+}
+
+/// atom の宣言した戻り値型（`-> Shape`）がジェネリックでないユーザー定義 Enum なら、その定義
+pub(crate) fn return_enum<'e>(atom: &Atom, module_env: &'e ModuleEnv) -> Option<&'e EnumDef> {
+    atom.return_type.as_deref()
+        .and_then(|t| module_env.get_enum(t))
+        .filter(|e| e.type_params.is_empty())
+}
+
+/// match のターゲットが Enum 型のパラメータなら、その Enum 定義
+pub(crate) fn match_target_enum<'e>(target: &Expr, ctx: &TranspileCtx<'e>) -> Option<&'e EnumDef> {
+    match target {
+        Expr::Variable(name) => ctx.atom.params.iter()
+            .find(|p| &p.name == name)
+            .and_then(|p| param_enum(p, ctx.module_env)),
+        _ => None,
+    }
+}
+
+/// Enum 値のフィールドを辿る1段（Variant の index 番目のフィールド）
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct FieldStep {
+    pub variant: String,
+    pub index: usize,
+    /// 再帰フィールド（Rust: Box / Go: ポインタ）か
+    pub recursive: bool,
+}
+
+/// パターンを分解した判定。path はターゲットからのフィールドパス（空ならターゲット自身）
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum PatternTest {
+    /// path の Enum 値の Variant が variant
+    Variant { path: Vec<FieldStep>, variant: String },
+    /// path の整数値（タグ番号で扱う Enum を含む）が value
+    Literal { path: Vec<FieldStep>, value: i64 },
+}
+
+/// match アームのパターンを、判定（外側から順にすべて成り立てば選ばれる）と変数の束縛に分解したもの
+#[derive(Debug, Clone, PartialEq, Default)]
+pub(crate) struct ArmPlan {
+    pub tests: Vec<PatternTest>,
+    /// パターン変数 → ターゲットからのフィールドパス
+    pub bindings: Vec<(String, Vec<FieldStep>)>,
+}
+
+/// 分解済みの match アーム
+pub(crate) struct PlannedArm<'m> {
+    pub arm: &'m MatchArm,
+    pub plan: ArmPlan,
+    /// ガードのない `_` / 変数パターン（言語の default 節にする）
+    pub catch_all: bool,
+}
+
+/// match のアームを分解する。最初の catch-all より後のアームは到達しないので除く
+pub(crate) fn plan_arms<'m>(arms: &'m [MatchArm], target_enum: Option<&EnumDef>, module_env: &ModuleEnv) -> Result<Vec<PlannedArm<'m>>, Unsupported> {
+    let mut planned = Vec::new();
+    for arm in arms {
+        let mut plan = ArmPlan::default();
+        plan_pattern(&arm.pattern, target_enum, &[], module_env, &mut plan)?;
+        let catch_all = arm.guard.is_none() && plan.tests.is_empty();
+        planned.push(PlannedArm { arm, plan, catch_all });
+        if catch_all {
+            break;
+        }
+    }
+    Ok(planned)
+}
+
+/// path の位置の値（enum_def の値、None なら整数）に対する pattern を plan に追加する
+fn plan_pattern(pattern: &Pattern, enum_def: Option<&EnumDef>, path: &[FieldStep], module_env: &ModuleEnv, plan: &mut ArmPlan) -> Result<(), Unsupported> {
+    match (pattern, enum_def) {
+        (Pattern::Wildcard, _) => {}
+        (Pattern::Variable(v), _) => plan.bindings.push((v.clone(), path.to_vec())),
+        (Pattern::Literal(n), None) => plan.tests.push(PatternTest::Literal { path: path.to_vec(), value: *n }),
+        (Pattern::Literal(_), Some(e)) => {
+            return Err(Unsupported::new("literal pattern", &format!("an integer literal cannot match a value of enum '{}'", e.name)));
+        }
+        (Pattern::Variant { variant_name, fields }, Some(e)) => {
+            let variant = e.variants.iter().find(|v| &v.name == variant_name)
+                .ok_or_else(|| Unsupported::new("enum variant pattern", &format!("'{}' is not a variant of enum '{}'", variant_name, e.name)))?;
+            plan.tests.push(PatternTest::Variant { path: path.to_vec(), variant: variant_name.clone() });
+            for (index, (field, field_type)) in fields.iter().zip(&variant.fields).enumerate() {
+                let recursive = is_recursive_field(e, field_type);
+                let mut field_path = path.to_vec();
+                field_path.push(FieldStep { variant: variant_name.clone(), index, recursive });
+                // payload の Enum 型フィールドは、再帰フィールドを除きタグ番号（整数）で保持される
+                plan_pattern(field, if recursive { Some(e) } else { None }, &field_path, module_env, plan)?;
+            }
+        }
+        (Pattern::Variant { variant_name, fields }, None) => {
+            let (e, tag) = module_env.find_enum_by_variant(variant_name)
+                .and_then(|e| e.variants.iter().position(|v| &v.name == variant_name).map(|tag| (e, tag)))
+                .ok_or_else(|| Unsupported::new("enum variant pattern", &format!("unknown variant '{}'", variant_name)))?;
+            if fields.iter().any(|f| *f != Pattern::Wildcard) {
+                return Err(Unsupported::new(
+                    "enum variant pattern",
+                    &format!("the payload of '{}' can only be bound when the match target is a parameter of type '{}'", variant_name, e.name),
+                ));
+            }
+            plan.tests.push(PatternTest::Literal { path: path.to_vec(), value: tag as i64 });
+        }
+    }
+    Ok(())
+}
+
+/// アームが構文上網羅的か（ガードのない catch-all があるか、Enum の全 Variant を
+/// ガードなし・フィールドが束縛だけのアームで覆う）。網羅性は検証で証明済みなので、
+/// そう分からない match には各言語で到達しない既定の分岐を足す
+pub(crate) fn arms_exhaustive(arms: &[MatchArm], target_enum: Option<&EnumDef>) -> bool {
+    let irrefutable = |p: &Pattern| matches!(p, Pattern::Wildcard | Pattern::Variable(_));
+    let unguarded = || arms.iter().filter(|arm| arm.guard.is_none());
+    unguarded().any(|arm| irrefutable(&arm.pattern))
+        || target_enum.map_or(false, |e| e.variants.iter().all(|v| unguarded().any(|arm| matches!(
+            &arm.pattern,
+            Pattern::Variant { variant_name, fields } if variant_name == &v.name && fields.iter().all(irrefutable)
+        ))))
+}
+
+/// すべてのアームがターゲット自身に対する1つの判定（重複なし）か catch-all なら、
+/// 判定を switch の case ラベルにできる（Go: `switch s.Tag` / TypeScript: `switch (s.kind)`）
+pub(crate) fn is_simple_switch(arms: &[PlannedArm]) -> bool {
+    let mut labels = Vec::new();
+    arms.iter().all(|a| a.catch_all || (a.arm.guard.is_none() && match a.plan.tests.as_slice() {
+        [test @ (PatternTest::Variant { path, .. } | PatternTest::Literal { path, .. })] if path.is_empty() && !labels.contains(test) => {
+            labels.push(test.clone());
+            true
+        }
+        _ => false,
+    }))
+}
+
+/// 式中のパターン変数を、言語ごとに描画したフィールド参照 render(path) に置き換える
+pub(crate) fn substitute_bindings(expr: &Expr, plan: &ArmPlan, render: impl Fn(&[FieldStep]) -> String) -> Expr {
+    plan.bindings.iter().fold(expr.clone(), |e, (var, path)| verification::substitute_bound(&e, var, &Expr::Variable(render(path))))
+}
+
+/// Go / TypeScript の本体・match アームを文の並びとして出力させる（Block は最後の値を return する）
+pub(crate) fn as_block(expr: Expr) -> Expr {
+    match expr {
+        Expr::Block(_) => expr,
+        _ => Expr::Block(vec![expr]),
+    }
+}
+
+/// 網羅性が検証済みの match で、どのアームにも当たらない分岐に置くメッセージ
+pub(crate) const UNREACHABLE_ARM: &str = "mumei: unreachable match arm (exhaustiveness is verified)";
+
+// =============================================================================
+// 実行時の契約検査（[build] runtime_checks = true）
+// =============================================================================
+//
+// 検証済みの requires / ensures は生成コードの呼び出し側を縛らないため、
+// 生成先の言語から契約に反する引数で呼ばれると黙って誤った値を返しうる。
+// runtime_checks では requires の && の項ごとに関数の入口で、ensures の項ごとに戻り値に対して検査し、
+// 反したら「precondition violated in f: n >= 0」のようなメッセージで失敗させる。
+// 関数の境界で評価できない項（量化子・本体の let 束縛・本体で値が変わるパラメータを参照する ensures、
+// 生成先に対応する構文がない式）は検査せず、その理由をコメントに残す。
+
+/// 1 つの契約の項の実行時検査
+pub(crate) struct RuntimeCheck {
+    /// 生成先の言語の真偽式
+    pub condition: String,
+    /// 失敗時のメッセージ（例: "precondition violated in inc: n >= 0"）
+    pub message: String,
+}
+
+/// atom の契約から作った実行時検査と、検査できなかった項の説明
+#[derive(Default)]
+pub(crate) struct RuntimeChecks {
+    pub requires: Vec<RuntimeCheck>,
+    pub ensures: Vec<RuntimeCheck>,
+    /// 検査しない項の説明（生成コードにコメントとして残す）
+    pub omitted: Vec<String>,
+}
+
+impl RuntimeChecks {
+    /// ctx.runtime_checks が無効なら空。format_expr は各言語の式の変換
+    pub(crate) fn new(ctx: &TranspileCtx, format_expr: fn(&Expr, &TranspileCtx) -> Result<String, Unsupported>) -> Self {
+        let mut checks = Self::default();
+        if !ctx.runtime_checks {
+            return checks;
+        }
+        let atom = ctx.atom;
+        let rebound: Vec<&str> = atom.params.iter()
+            .map(|p| p.name.as_str())
+            .filter(|name| rebinds(&atom.body_expr, name))
+            .collect();
+        for (kind, contract) in [("precondition", &atom.requires), ("postcondition", &atom.ensures)] {
+            let expr = match crate::parser::parse_expression(contract) {
+                Ok(expr) => expr,
+                Err(_) => continue,
+            };
+            let mut conjuncts = Vec::new();
+            contract_conjuncts(&expr, &mut conjuncts);
+            for conjunct in conjuncts {
+                let source = crate::printer::show(conjunct).unwrap_or_else(|| contract.trim().to_string());
+                let in_scope = |name: &str| {
+                    atom.params.iter().any(|p| p.name == name && !(kind == "postcondition" && rebound.contains(&name)))
+                        || (kind == "postcondition" && name == "result")
+                };
+                let checked = match contract_scope_issue(conjunct, &in_scope) {
+                    Some(reason) => Err(reason),
+                    None => format_contract(conjunct, ctx, format_expr).map_err(|u| u.reason),
+                };
+                match checked {
+                    Ok(condition) => {
+                        let check = RuntimeCheck { condition, message: format!("{} violated in {}: {}", kind, atom.name, source) };
+                        if kind == "precondition" { checks.requires.push(check) } else { checks.ensures.push(check) }
+                    }
+                    Err(reason) => checks.omitted.push(format!("{} `{}` is not checked at runtime: {}", kind, source, reason)),
+                }
+            }
+        }
+        checks
+    }
+
+    /// 検査も省略の説明もなければ true（生成コードは runtime_checks なしと同じになる）
+    pub(crate) fn is_empty(&self) -> bool {
+        self.requires.is_empty() && self.ensures.is_empty() && self.omitted.is_empty()
+    }
+
+    /// 検査しない項のコメント行
+    pub(crate) fn omitted_comments(&self, comment: &str) -> Vec<String> {
+        self.omitted.iter().map(|note| format!("{} {}", comment, note)).collect()
+    }
+}
+
+/// 真偽式の否定。全体が 1 組の括弧で囲まれていればそのまま `!` を付ける（Go / TypeScript の if 用）
+pub(crate) fn negated(condition: &str) -> String {
+    let mut depth = 0;
+    let wrapped = condition.starts_with('(') && condition.char_indices().all(|(i, c)| {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            _ => {}
+        }
+        depth > 0 || i == condition.len() - 1
+    });
+    if wrapped { format!("!{}", condition) } else { format!("!({})", condition) }
+}
+
+/// `a && b && c` の最上位の項（true は除く）
+fn contract_conjuncts<'e>(expr: &'e Expr, out: &mut Vec<&'e Expr>) {
+    match expr {
+        Expr::BinaryOp(l, Op::And, r) => {
+            contract_conjuncts(l, out);
+            contract_conjuncts(r, out);
+        }
+        Expr::Bool(true) => {}
+        _ => out.push(expr),
+    }
+}
+
+/// 関数の境界で評価できない項ならその理由（量化子・スコープ外の名前）
+fn contract_scope_issue(expr: &Expr, in_scope: &dyn Fn(&str) -> bool) -> Option<String> {
+    let name_issue = |name: &str| (!in_scope(name)).then(|| format!("'{}' is not in scope at the function boundary", name));
+    match expr {
+        Expr::Variable(v) => name_issue(v),
+        Expr::ArrayAccess(array, index) => name_issue(array).or_else(|| contract_scope_issue(index, in_scope)),
+        Expr::BinaryOp(l, _, r) => contract_scope_issue(l, in_scope).or_else(|| contract_scope_issue(r, in_scope)),
+        Expr::IfThenElse { cond, then_branch, else_branch } => contract_scope_issue(cond, in_scope)
+            .or_else(|| contract_scope_issue(then_branch, in_scope))
+            .or_else(|| contract_scope_issue(else_branch, in_scope)),
+        Expr::Call(name, _) if name == "forall" || name == "exists" => Some(format!("{}(...) cannot be evaluated at runtime", name)),
+        // 関数の末尾では入口の値を保持していない
+        Expr::Call(name, _) if name == "old" => Some("old(...) refers to a parameter's value on entry, which is not kept at runtime".to_string()),
+        Expr::Call(_, args) => args.iter().find_map(|a| contract_scope_issue(a, in_scope)),
+        Expr::FieldAccess(base, _) => contract_scope_issue(base, in_scope),
+        Expr::Block(stmts) => stmts.iter().find_map(|s| contract_scope_issue(s, in_scope)),
+        _ => None,
+    }
+}
+
+/// 本体が name を let で束縛し直すか代入するか（ensures の name は入口の値を指すので、関数の末尾では評価できない）
+fn rebinds(body: &str, name: &str) -> bool {
+    regex::Regex::new(&format!(r"\b{}\s*=[^=>]", regex::escape(name))).unwrap().is_match(body)
+}
+
+/// 契約の項を生成先の式にする。含意 `a => b` はどの言語にも演算子がないので `!(a) || (b)` に書き換える
+fn format_contract(
+    expr: &Expr,
+    ctx: &TranspileCtx,
+    format_expr: fn(&Expr, &TranspileCtx) -> Result<String, Unsupported>,
+) -> Result<String, Unsupported> {
+    match expr {
+        Expr::BinaryOp(l, op @ (Op::And | Op::Or | Op::Implies), r) => {
+            let (l, r) = (format_contract(l, ctx, format_expr)?, format_contract(r, ctx, format_expr)?);
+            Ok(match op {
+                Op::And => format!("({} && {})", l, r),
+                Op::Or => format!("({} || {})", l, r),
+                _ => format!("({} || {})", negated(&l), r),
+            })
+        }
+        _ => format_expr(expr, ctx),
+    }
+}
+
+/// atom を指定言語に変換する。未対応構文を含む場合は Err(Unsupported) を返す。
+pub fn transpile(atom: &Atom, lang: TargetLanguage, module_env: &ModuleEnv) -> Result<String, Unsupported> {
+    transpile_checked(atom, lang, module_env, false)
+}
+
+/// transpile に加え、runtime_checks（`[build] runtime_checks`）なら requires / ensures を実行時に検査するコードを出力する
+pub fn transpile_checked(atom: &Atom, lang: TargetLanguage, module_env: &ModuleEnv, runtime_checks: bool) -> Result<String, Unsupported> {
+    let ctx = TranspileCtx { runtime_checks, ..TranspileCtx::new(atom, module_env) };
     match lang {
-        TargetLanguage::TypeScript => typescript::transpile_to_ts(atom),
-        TargetLanguage::Rust => rust::transpile_to_rust(atom),
-        TargetLanguage::Go => golang::transpile_to_go(atom),
+        TargetLanguage::TypeScript => typescript::transpile_to_ts(atom, &ctx),
+        TargetLanguage::Rust => rust::transpile_to_rust(atom, &ctx),
+        TargetLanguage::Go => golang::transpile_to_go(atom, &ctx),
+    }
+}
+
+/// 理論拡張の組み込み関数呼び出しを変換する。
+/// 拡張が言語別のスニペットを返さなければ、コアの式に展開してから通常どおり変換する。
+pub(crate) fn transpile_intrinsic(
+    name: &str,
+    args: &[Expr],
+    lang: TargetLanguage,
+    ctx: &TranspileCtx,
+    format_expr: fn(&Expr, &TranspileCtx) -> Result<String, Unsupported>,
+) -> Result<String, Unsupported> {
+    let extension = crate::theory::find_intrinsic(name)
+        .ok_or_else(|| Unsupported::new(name, "theory extension is not registered"))?;
+    let args_str = args.iter().map(|a| format_expr(a, ctx)).collect::<Result<Vec<_>, _>>()?;
+    if let Some(code) = extension.transpile_call(name, lang, &args_str) {
+        return Ok(code);
     }
+    let lowered = extension.lower_call(name, args).map_err(|e| Unsupported::new(name, &e.to_string()))?;
+    format_expr(&lowered, ctx)
 }
 
-/// Enum 定義を各言語の型定義に変換する
-pub fn transpile_enum(enum_def: &EnumDef, lang: TargetLanguage) -> String {
+/// IfThenElse が条件演算子 `cond ? a : b` 由来か（`if` 式と違い分岐が Block でない）。
+/// 条件演算子は式の途中に現れるため、各言語で式として出力する
+pub(crate) fn is_conditional_expr(then_branch: &Expr, else_branch: &Expr) -> bool {
+    !matches!(then_branch, Expr::Block(_)) && !matches!(else_branch, Expr::Block(_))
+}
+
+/// approx_eq(a, b, eps)（`a ~= b within eps`）を |a - b| <= eps に展開する。args は変換済みの引数
+pub(crate) fn transpile_approx_eq(lang: TargetLanguage, args: &[String]) -> Result<String, Unsupported> {
+    let (a, b, eps) = match args {
+        [a, b, eps] => (a, b, eps),
+        _ => return Err(Unsupported::new("approx_eq", "expects 3 arguments")),
+    };
+    Ok(match lang {
+        TargetLanguage::Rust => format!("((({}) - ({})).abs() <= {})", a, b, eps),
+        TargetLanguage::Go => format!("(math.Abs(({}) - ({})) <= {})", a, b, eps),
+        TargetLanguage::TypeScript => format!("(Math.abs(({}) - ({})) <= {})", a, b, eps),
+    })
+}
+
+/// 未対応構文の atom を、ポリシーに従ったマーカー / TODO スタブに置き換えた関数定義を生成する
+pub fn unsupported_stub(atom: &Atom, lang: TargetLanguage, module_env: &ModuleEnv, unsupported: &Unsupported, policy: UnsupportedPolicy) -> String {
     match lang {
-        TargetLanguage::Rust => rust::transpile_enum_rust(enum_def),
+        TargetLanguage::TypeScript => typescript::unsupported_stub_ts(atom, module_env, unsupported, policy),
+        TargetLanguage::Rust => rust::unsupported_stub_rust(atom, module_env, unsupported, policy),
+        TargetLanguage::Go => golang::unsupported_stub_go(atom, module_env, unsupported, policy),
+    }
+}
+
+/// atom を変換し、未対応構文があればポリシーに従ったスタブを返して summary に記録する。
+/// cmd_build のバンドル生成はこの関数を使う（runtime_checks は `[build] runtime_checks`）。
+pub fn transpile_with_policy(
+    atom: &Atom,
+    lang: TargetLanguage,
+    module_env: &ModuleEnv,
+    policy: UnsupportedPolicy,
+    runtime_checks: bool,
+    summary: &mut UnsupportedSummary,
+) -> String {
+    match transpile_checked(atom, lang, module_env, runtime_checks) {
+        Ok(code) => code,
+        Err(unsupported) => {
+            let stub = unsupported_stub(atom, lang, module_env, &unsupported, policy);
+            summary.entries.push((atom.name.clone(), lang, unsupported));
+            stub
+        }
+    }
+}
+
+/// バンドルに追加する atom のコード。ターゲット注釈でこの言語から除外された atom は None
+pub fn transpile_for_bundle(
+    atom: &Atom,
+    lang: TargetLanguage,
+    module_env: &ModuleEnv,
+    policy: UnsupportedPolicy,
+    runtime_checks: bool,
+    summary: &mut UnsupportedSummary,
+) -> Option<String> {
+    if !atom.targets.includes(lang.target_name()) {
+        return None;
+    }
+    Some(transpile_with_policy(atom, lang, module_env, policy, runtime_checks, summary))
+}
+
+/// atom がターゲットに出力されるか。`llvm` は .ll の出力（ネイティブ系のいずれかに含まれる）で判定する
+fn emits_for(targets: &AtomTargets, target: &str) -> bool {
+    if target == "llvm" { targets.emits_ir() } else { targets.includes(target) }
+}
+
+/// ターゲット注釈を検証する。
+/// - 注釈に未知のターゲット名があればエラー
+/// - あるターゲットに出力される atom が、そのターゲットから除外された atom を呼び出していればエラー
+///   （生成先で未定義の関数を参照することになるため）
+pub fn check_atom_targets(items: &[Item], module_env: &ModuleEnv) -> Result<(), String> {
+    for item in items {
+        let atom = match item {
+            Item::Atom(atom) => atom,
+            _ => continue,
+        };
+        if let Some(unknown) = atom.targets.unknown().first() {
+            return Err(format!(
+                "atom '{}' names unknown target '{}' (expected one of: {})",
+                atom.name, unknown, ATOM_TARGETS.join(", ")
+            ));
+        }
+        let body = crate::parser::parse_expression(&atom.body_expr)
+            .map_err(|e| format!("atom '{}': {}", atom.name, e))?;
+        for callee_name in verification::collect_callees(&body) {
+            let callee = match module_env.get_atom(&callee_name) {
+                Some(callee) if callee.name != atom.name => callee,
+                _ => continue,
+            };
+            for target in ATOM_TARGETS {
+                if emits_for(&atom.targets, target) && !emits_for(&callee.targets, target) {
+                    return Err(format!(
+                        "atom '{}' is emitted for {} but calls '{}', which is excluded from {}",
+                        atom.name, target, callee.name, target
+                    ));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Enum 定義を各言語の型定義に変換する（derive_serde は `[build.rust] derive_serde`、Rust のみに効く）
+pub fn transpile_enum(enum_def: &EnumDef, lang: TargetLanguage, derive_serde: bool) -> String {
+    match lang {
+        TargetLanguage::Rust => rust::transpile_enum_rust(enum_def, derive_serde),
         TargetLanguage::Go => golang::transpile_enum_go(enum_def),
         TargetLanguage::TypeScript => typescript::transpile_enum_ts(enum_def),
     }
 }
 
-/// Struct 定義を各言語の型定義に変換する
-pub fn transpile_struct(struct_def: &StructDef, lang: TargetLanguage) -> String {
+/// 精緻型の定義を変換する。TypeScript だけがブランド型と検査関数 toX を出力し、
+/// Rust / Go はベース型のまま（述語は atom のドキュメントコメントに残る）なので None
+pub fn transpile_refined_type(refined: &RefinedType, lang: TargetLanguage) -> Option<String> {
     match lang {
-        TargetLanguage::Rust => rust::transpile_struct_rust(struct_def),
+        TargetLanguage::TypeScript => Some(typescript::transpile_refined_type_ts(refined)),
+        TargetLanguage::Rust | TargetLanguage::Go => None,
+    }
+}
+
+/// Struct 定義を各言語の型定義に変換する（derive_serde は `[build.rust] derive_serde`、Rust のみに効く）
+pub fn transpile_struct(struct_def: &StructDef, lang: TargetLanguage, derive_serde: bool) -> String {
+    match lang {
+        TargetLanguage::Rust => rust::transpile_struct_rust(struct_def, derive_serde),
         TargetLanguage::Go => golang::transpile_struct_go(struct_def),
         TargetLanguage::TypeScript => typescript::transpile_struct_ts(struct_def),
     }
@@ -55,11 +723,1199 @@ pub fn transpile_impl(impl_def: &ImplDef, lang: TargetLanguage) -> String {
     }
 }
 
+/// struct / enum / trait / impl 1 件についてバンドルに追加するコード（末尾の空行込み。ほかの item は None）。
+/// derive_serde は `[build.rust] derive_serde`。validators（`[build.ts] emit_validators`）なら
+/// TypeScript の struct / enum の直後に JSON バリデータ parseX を置く
+pub fn transpile_definition(item: &Item, lang: TargetLanguage, module_env: &ModuleEnv, derive_serde: bool, validators: bool) -> Option<String> {
+    let validate = validators && lang == TargetLanguage::TypeScript;
+    let (definition, validator) = match item {
+        Item::StructDef(struct_def) => (
+            transpile_struct(struct_def, lang, derive_serde),
+            validate.then(|| typescript::transpile_struct_validator_ts(struct_def, module_env)).flatten(),
+        ),
+        Item::EnumDef(enum_def) => (
+            transpile_enum(enum_def, lang, derive_serde),
+            validate.then(|| typescript::transpile_enum_validator_ts(enum_def, module_env)).flatten(),
+        ),
+        Item::TypeDef(refined) => (transpile_refined_type(refined, lang)?, None),
+        Item::TraitDef(trait_def) => (transpile_trait(trait_def, lang), None),
+        Item::ImplDef(impl_def) => (transpile_impl(impl_def, lang), None),
+        _ => return None,
+    };
+    let mut code = format!("{}\n\n", definition);
+    if let Some(validator) = validator {
+        code.push_str(&validator);
+        code.push_str("\n\n");
+    }
+    Some(code)
+}
+
+// =============================================================================
+// 検証状態の記録（verify=false の透かし / atom ごとの provenance）
+// =============================================================================
+//
+// `[build] verify = false` で生成した成果物は、見た目では検証済みのものと区別できない。
+// そのまま出荷されないよう、バンドルと .ll の先頭に目立つ透かしを入れ、
+// 各 atom の直前にはその atom の検証状態をコメントで残す。
+
+/// verify=false で生成した成果物の先頭に入れる透かし
+pub const UNVERIFIED_WATERMARK: &str = "MUMEI: contracts NOT verified (verify=false)";
+
+/// atom 1 件分の検証状態（生成コード中の provenance コメント用）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Provenance {
+    /// 今回 Z3 で検証した
+    Verified,
+    /// 変更がなく、ビルドキャッシュの検証結果を使った
+    Cached,
+    /// インポートされた atom（契約のみ信頼）
+    Imported,
+    /// `trusted atom`: 契約のみ信頼し、body は検証していない
+    Trusted,
+    /// `unverified atom`: 検証していない
+    Unverified,
+    /// verify=false により検証しなかった
+    Skipped,
+}
+
+impl Provenance {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Provenance::Verified => "verified",
+            Provenance::Cached => "verified (cached)",
+            Provenance::Imported => "imported (contract trusted)",
+            Provenance::Trusted => "trusted (body not verified)",
+            Provenance::Unverified => "unverified",
+            Provenance::Skipped => "skipped (verify=false)",
+        }
+    }
+}
+
+/// 成果物先頭の透かし。verified == true（通常のビルド）なら空文字列
+/// comment: 行コメントの接頭辞（生成言語では "//"、LLVM IR では ";"）
+pub fn watermark(comment: &str, verified: bool) -> String {
+    if verified {
+        return String::new();
+    }
+    format!(
+        "{c} ⚠️  {}\n{c} This file was generated by `mumei build` with `[build] verify = false` and must not be released.\n\n",
+        UNVERIFIED_WATERMARK,
+        c = comment
+    )
+}
+
+/// atom 定義の直前に置く検証状態のコメント
+pub fn provenance_comment(comment: &str, provenance: Provenance) -> String {
+    format!("{} mumei: verification = {}\n", comment, provenance.label())
+}
+
+/// `total atom` の provenance コメントに続けて置く totality の状態（total でなければ空文字列）
+pub fn totality_comment(comment: &str, total: bool, provenance: Provenance) -> String {
+    if !total {
+        return String::new();
+    }
+    let status = match provenance {
+        Provenance::Verified | Provenance::Cached => "proven (no runtime-failure obligations)",
+        Provenance::Imported => "assumed (imported contract)",
+        _ => "NOT proven",
+    };
+    format!("{} mumei: totality = {}\n", comment, status)
+}
+
+/// バンドルファイルの先頭（verify=false の透かし + import 宣言）を生成する
+pub fn transpile_bundle_header(imports: &[ImportDecl], module_name: &str, lang: TargetLanguage, has_async: bool, verified: bool) -> String {
+    format!("{}{}", watermark("//", verified), transpile_module_header(imports, module_name, lang, has_async))
+}
+
 /// import 宣言からバンドルファイルのヘッダー（mod/use, package/import, import/export）を生成する
-pub fn transpile_module_header(imports: &[ImportDecl], module_name: &str, lang: TargetLanguage) -> String {
+/// has_async: モジュール内に async atom がある場合 true（Go では "context" を import する）
+pub fn transpile_module_header(imports: &[ImportDecl], module_name: &str, lang: TargetLanguage, has_async: bool) -> String {
     match lang {
         TargetLanguage::Rust => rust::transpile_module_header_rust(imports, module_name),
-        TargetLanguage::Go => golang::transpile_module_header_go(imports, module_name),
+        TargetLanguage::Go => golang::transpile_module_header_go(imports, module_name, has_async),
         TargetLanguage::TypeScript => typescript::transpile_module_header_ts(imports),
     }
 }
+
+/// バンドル（lang に出力される atom の本体と impl のメソッド本体）が参照する名前。
+/// 呼び出し先と構造体リテラルの型名に加え、修飾付きの呼び出し `alias::name` / `alias.name(...)` は
+/// `alias::name` の形で記録する（ヘッダーに出す import を決めるための近似。変数名は含めない）
+pub fn bundle_references(items: &[Item], lang: TargetLanguage) -> HashSet<String> {
+    let bodies = items.iter().flat_map(|item| match item {
+        Item::Atom(atom) if atom.targets.includes(lang.target_name()) => vec![atom.body_expr.as_str()],
+        Item::ImplDef(impl_def) => impl_def.method_bodies.iter().map(|(_, body)| body.as_str()).collect(),
+        _ => Vec::new(),
+    });
+    let mut names = HashSet::new();
+    for body in bodies {
+        let tokens = crate::parser::tokenize(body);
+        for (i, token) in tokens.iter().enumerate() {
+            if !token.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+                continue;
+            }
+            let next = |k: usize| tokens.get(i + k).copied().unwrap_or("");
+            match (next(1), next(2), next(3)) {
+                ("(", _, _) => { names.insert(token.to_string()); }
+                ("{", _, _) if token.starts_with(|c: char| c.is_ascii_uppercase()) => { names.insert(token.to_string()); }
+                (":", ":", member) | (".", member, "(") => { names.insert(format!("{}::{}", token, member)); }
+                _ => {}
+            }
+        }
+    }
+    names
+}
+
+/// imports のうち、バンドルが参照する名前（bundle_references）を定義しているものだけを返す。
+/// definitions は imports と同じ順に、import 先モジュールが定義する名前。
+/// None（読み込めなかった）の import は判断できないため残す
+pub fn referenced_imports(imports: &[ImportDecl], definitions: &[Option<HashSet<String>>], references: &HashSet<String>) -> Vec<ImportDecl> {
+    imports.iter().zip(definitions)
+        .filter(|(import, defined)| match defined {
+            None => true,
+            Some(defined) => references.iter().any(|name| match name.split_once("::") {
+                Some((alias, member)) => import.alias.as_deref() == Some(alias) && defined.contains(member),
+                None => defined.contains(name),
+            }),
+        })
+        .map(|(import, _)| import.clone())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{parse_module, Item};
+    use crate::test_support::{assert_golden, env_from_source, find_atom, TempDir};
+
+    const ASYNC_SOURCE: &str = r#"
+atom double(x: i64)
+requires: x >= 0;
+ensures: result == x * 2;
+body: x * 2;
+
+async atom fetch(x: i64)
+requires: x >= 0;
+ensures: result >= 0;
+body: x;
+
+async atom pipeline(x: i64)
+requires: x >= 0;
+ensures: result >= 0;
+body: {
+    let a = await fetch(x);
+    let b = await double(a);
+    b
+};
+"#;
+
+    fn prepare() -> (Atom, ModuleEnv) {
+        let (items, module_env) = env_from_source(ASYNC_SOURCE);
+        (find_atom(&items, "pipeline"), module_env)
+    }
+
+    #[test]
+    fn test_transpile_async_golden() {
+        // async atom が async の fetch と同期の double を await する。
+        // Rust: async fn で .await は async の呼び出しだけ / TS: Promise を返し await も async の呼び出しだけ /
+        // Go: ctx を受け取り、async の呼び出しにだけ渡す
+        let (atom, module_env) = prepare();
+        for (lang, golden) in [(TargetLanguage::Rust, "pipeline.rs.golden"), (TargetLanguage::Go, "pipeline.go.golden"), (TargetLanguage::TypeScript, "pipeline.ts.golden")] {
+            assert_golden("async", golden, &transpile(&atom, lang, &module_env).unwrap());
+        }
+
+        let header = transpile_module_header(&[], "main", TargetLanguage::Go, true);
+        assert!(header.contains("\"context\""), "{}", header);
+    }
+
+    const UNSUPPORTED_SOURCE: &str = r#"
+resource db priority: 1 mode: exclusive;
+
+atom locked(x: i64)
+resources: [db];
+requires: x >= 0;
+ensures: result >= 0;
+body: acquire db { x };
+
+atom area(s: Shape)
+requires: true;
+ensures: true;
+body: match s { Circle(r) => r * r, Rect(w, h) => w * h };
+"#;
+
+    #[test]
+    fn test_unsupported_acquire_rust_compile_error() {
+        let (items, module_env) = env_from_source(UNSUPPORTED_SOURCE);
+        let atom = &find_atom(&items, "locked");
+        let err = transpile(atom, TargetLanguage::Rust, &module_env).unwrap_err();
+        assert_eq!(err.construct, "acquire");
+
+        let mut summary = UnsupportedSummary::default();
+        let code = transpile_with_policy(atom, TargetLanguage::Rust, &module_env, UnsupportedPolicy::CompileError, false, &mut summary);
+        assert!(code.contains("compile_error!(\"mumei: acquire not supported in Rust backend yet"), "{}", code);
+        assert!(code.contains("pub fn locked(x: i64) -> i64"), "{}", code);
+        assert_eq!(summary.count(), 1);
+        assert!(summary.lines()[0].contains("atom 'locked' [Rust]: acquire"), "{:?}", summary.lines());
+    }
+
+    #[test]
+    fn test_unsupported_variant_match_go() {
+        // Shape の定義がない（ModuleEnv に登録されない）ので、Variant も payload の型も分からない
+        let (items, module_env) = env_from_source(UNSUPPORTED_SOURCE);
+        let atom = &find_atom(&items, "area");
+        let mut summary = UnsupportedSummary::default();
+        let code = transpile_with_policy(atom, TargetLanguage::Go, &module_env, UnsupportedPolicy::CompileError, false, &mut summary);
+        assert!(code.contains("panic(\"mumei: enum variant pattern not supported in Go backend yet"), "{}", code);
+        assert!(summary.has_backend(TargetLanguage::Go));
+        assert_eq!(summary.count(), 1);
+
+        // --allow-partial では TODO スタブになる
+        let mut partial = UnsupportedSummary::default();
+        let code = transpile_with_policy(atom, TargetLanguage::Go, &module_env, UnsupportedPolicy::AllowPartial, false, &mut partial);
+        assert!(code.contains("// TODO(mumei):"), "{}", code);
+        assert_eq!(partial.count(), 1);
+    }
+
+    #[test]
+    fn test_unsupported_acquire_typescript_sync_atom() {
+        let (items, module_env) = env_from_source(UNSUPPORTED_SOURCE);
+        let atom = &find_atom(&items, "locked");
+        let mut summary = UnsupportedSummary::default();
+        let code = transpile_with_policy(atom, TargetLanguage::TypeScript, &module_env, UnsupportedPolicy::CompileError, false, &mut summary);
+        assert!(code.contains("// @ts-expect-error mumei: acquire not supported in TypeScript backend yet"), "{}", code);
+        assert!(code.contains("throw new Error("), "{}", code);
+        assert!(code.contains("function locked(x: number): number"), "{}", code);
+        assert_eq!(summary.count(), 1);
+    }
+
+    #[test]
+    fn test_supported_atoms_do_not_hit_policy() {
+        let (atom, module_env) = prepare();
+        let mut summary = UnsupportedSummary::default();
+        for lang in [TargetLanguage::Rust, TargetLanguage::Go, TargetLanguage::TypeScript] {
+            transpile_with_policy(&atom, lang, &module_env, UnsupportedPolicy::CompileError, false, &mut summary);
+        }
+        assert!(summary.is_empty());
+    }
+
+    #[test]
+    fn test_unverified_watermark() {
+        for lang in [TargetLanguage::Rust, TargetLanguage::Go, TargetLanguage::TypeScript] {
+            // verify = true: 透かしなし、ヘッダーは従来どおり
+            let verified = transpile_bundle_header(&[], "m", lang, false, true);
+            assert!(!verified.contains(UNVERIFIED_WATERMARK), "{}", verified);
+            assert_eq!(verified, transpile_module_header(&[], "m", lang, false));
+
+            // verify = false: 先頭行に透かし
+            let skipped = transpile_bundle_header(&[], "m", lang, false, false);
+            assert!(skipped.lines().next().unwrap().contains(UNVERIFIED_WATERMARK), "{}", skipped);
+            assert!(skipped.ends_with(&transpile_module_header(&[], "m", lang, false)));
+        }
+        assert!(watermark(";", false).starts_with("; ⚠️  MUMEI: contracts NOT verified (verify=false)"));
+        assert!(watermark(";", true).is_empty());
+    }
+
+    #[test]
+    fn test_transpile_deep_left_chain() {
+        // 10 万項の `x + x + ... + x` の本体を、既定のテストスレッドのスタックのまま各言語へ出力する
+        let source = format!("atom deep(x: i64) requires: true; ensures: true; body: {};\n", vec!["x"; 100_000].join(" + "));
+        let (items, module_env) = env_from_source(&source);
+        let atom = find_atom(&items, "deep");
+        for lang in [TargetLanguage::Rust, TargetLanguage::Go, TargetLanguage::TypeScript] {
+            let code = transpile(&atom, lang, &module_env).unwrap();
+            assert_eq!(code.matches(" + x)").count(), 99_999, "{:?}", lang);
+        }
+    }
+
+    #[test]
+    fn test_provenance_comment() {
+        assert_eq!(provenance_comment("//", Provenance::Verified), "// mumei: verification = verified\n");
+        assert_eq!(provenance_comment(";", Provenance::Trusted), "; mumei: verification = trusted (body not verified)\n");
+        assert!(provenance_comment("//", Provenance::Skipped).contains("skipped (verify=false)"));
+        assert_eq!(totality_comment("//", false, Provenance::Verified), "");
+        assert_eq!(totality_comment("//", true, Provenance::Verified), "// mumei: totality = proven (no runtime-failure obligations)\n");
+        assert!(totality_comment(";", true, Provenance::Skipped).contains("NOT proven"));
+    }
+
+    #[test]
+    fn test_transpile_nullable_param() {
+        let (items, module_env) = env_from_source("atom first_or_zero(x: i64?) requires: is_some(x); ensures: true; body: x! + 1;");
+        let atom = find_atom(&items, "first_or_zero");
+
+        let rust = transpile(&atom, TargetLanguage::Rust, &module_env).unwrap();
+        assert!(rust.contains("x: Option<i64>"), "{}", rust);
+        assert!(rust.contains("x.expect(\"mumei: 'x' is None\")"), "{}", rust);
+
+        let go = transpile(&atom, TargetLanguage::Go, &module_env).unwrap();
+        assert!(go.contains("x *int64"), "{}", go);
+        assert!(go.contains("(*x)"), "{}", go);
+
+        let ts = transpile(&atom, TargetLanguage::TypeScript, &module_env).unwrap();
+        assert!(ts.contains("x: number | null"), "{}", ts);
+        assert!(ts.contains("(x ?? (() => { throw new Error(\"mumei: 'x' is null\"); })())"), "{}", ts);
+    }
+
+    #[test]
+    fn test_transpile_ref_arguments() {
+        let (items, module_env) = env_from_source(r#"
+atom push(ref mut stack: i64, item: i64) requires: true; ensures: true; body: item;
+atom peek(ref stack: i64) requires: true; ensures: true; body: stack;
+atom caller(s: i64) requires: true; ensures: true; body: push(ref mut s, 1) + peek(ref s);
+atom forward(ref mut t: i64, ref u: i64) requires: true; ensures: true; body: push(ref mut t, 2) + peek(ref u) + peek(ref t);
+"#);
+        let atom = |name: &str| find_atom(&items, name);
+
+        let rust = transpile(&atom("caller"), TargetLanguage::Rust, &module_env).unwrap();
+        assert!(rust.contains("push(&mut s, 1)") && rust.contains("peek(&s)"), "{}", rust);
+        // 値渡しのパラメータを &mut で貸すので、生成する Rust では mut で受け取る
+        assert!(rust.contains("pub fn caller(mut s: i64)"), "{}", rust);
+        // 自身の ref / ref mut パラメータはすでに参照なので & を重ねない
+        let rust = transpile(&atom("forward"), TargetLanguage::Rust, &module_env).unwrap();
+        assert!(rust.contains("push(t, 2)") && rust.contains("peek(u)") && rust.contains("peek(t)"), "{}", rust);
+
+        // Go は ref mut だけがポインタ
+        let go = transpile(&atom("caller"), TargetLanguage::Go, &module_env).unwrap();
+        assert!(go.contains("push(&s, 1)") && go.contains("peek(s)"), "{}", go);
+        let go = transpile(&atom("forward"), TargetLanguage::Go, &module_env).unwrap();
+        assert!(go.contains("push(t, 2)") && go.contains("peek(u)") && go.contains("peek(*t)"), "{}", go);
+
+        let ts = transpile(&atom("caller"), TargetLanguage::TypeScript, &module_env).unwrap();
+        assert!(ts.contains("push(s, 1)") && ts.contains("peek(s)"), "{}", ts);
+    }
+
+    #[test]
+    fn test_transpile_slice_params() {
+        let (items, module_env) = env_from_source("atom first(xs: [f64], ns: [i64]) requires: len_xs > 0; ensures: true; body: xs[0];");
+        let atom = find_atom(&items, "first");
+
+        let rust = transpile(&atom, TargetLanguage::Rust, &module_env).unwrap();
+        assert!(rust.contains("xs: &[f64], ns: &[i64]"), "{}", rust);
+        assert!(rust.contains("-> f64"), "{}", rust);
+
+        let go = transpile(&atom, TargetLanguage::Go, &module_env).unwrap();
+        assert!(go.contains("xs []float64, ns []int64"), "{}", go);
+
+        let ts = transpile(&atom, TargetLanguage::TypeScript, &module_env).unwrap();
+        assert!(ts.contains("xs: number[], ns: number[]"), "{}", ts);
+    }
+
+    #[test]
+    fn test_transpile_str_types() {
+        let (items, module_env) = env_from_source(r#"
+type NonEmpty = Str where len(v) > 0;
+atom size(s: NonEmpty) requires: true; ensures: result > 0; body: len(s);
+atom greet(n: i64) requires: true; ensures: true; body: if n > 0 { "hi \"you\"" } else { "bye" };
+"#);
+        let atom = |name: &str| find_atom(&items, name);
+
+        let rust = transpile(&atom("size"), TargetLanguage::Rust, &module_env).unwrap();
+        assert!(rust.contains("s: &str") && rust.contains("-> i64") && rust.contains("s.len() as i64"), "{}", rust);
+        let rust = transpile(&atom("greet"), TargetLanguage::Rust, &module_env).unwrap();
+        assert!(rust.contains("-> String") && rust.contains("String::from(") && rust.contains(r#""hi \"you\"""#), "{}", rust);
+
+        let go = transpile(&atom("size"), TargetLanguage::Go, &module_env).unwrap();
+        assert!(go.contains("s string") && go.contains("int64(len(s))"), "{}", go);
+        let go = transpile(&atom("greet"), TargetLanguage::Go, &module_env).unwrap();
+        assert!(go.contains(") string {") && go.contains(r#""hi \"you\"""#), "{}", go);
+
+        // TypeScript: 精緻型のパラメータはブランド型（ベース型は string）
+        let ts = transpile(&atom("size"), TargetLanguage::TypeScript, &module_env).unwrap();
+        assert!(ts.contains("s: NonEmpty") && ts.contains("): number") && ts.contains("s.length"), "{}", ts);
+        let ts = transpile(&atom("greet"), TargetLanguage::TypeScript, &module_env).unwrap();
+        assert!(ts.contains("): string {"), "{}", ts);
+        let declarations = typescript::transpile_declarations_ts(&[], &items, &module_env, false);
+        assert!(declarations.contains("size(s: NonEmpty): number;"), "{}", declarations);
+        assert!(declarations.contains("export type NonEmpty = string & { readonly __brand: \"NonEmpty\" };"), "{}", declarations);
+    }
+
+    #[test]
+    fn test_transpiled_return_types_follow_result_kind() {
+        let (items, module_env) = env_from_source(r#"
+atom is_pos(x: i64) requires: true; ensures: true; body: x > 0;
+atom half(x: i64) requires: true; ensures: true; body: x / 2.0;
+atom count(xs: [f64]) requires: true; ensures: true; body: len(xs);
+"#);
+        let atom = |name: &str| find_atom(&items, name);
+        let signature = |name: &str, lang: TargetLanguage| {
+            let code = transpile(&atom(name), lang, &module_env).unwrap();
+            code.lines().find(|l| l.contains(&format!("{}(", name)) && l.ends_with('{')).unwrap_or_default().to_string()
+        };
+
+        assert!(signature("is_pos", TargetLanguage::Rust).ends_with("-> bool {"));
+        assert!(signature("is_pos", TargetLanguage::Go).ends_with(") bool {"));
+        assert!(signature("is_pos", TargetLanguage::TypeScript).ends_with("): boolean {"));
+        assert!(signature("half", TargetLanguage::Rust).ends_with("-> f64 {"));
+        assert!(signature("half", TargetLanguage::Go).ends_with(") float64 {"));
+        // f64 の配列パラメータがあっても、len の結果は整数
+        assert!(signature("count", TargetLanguage::Rust).ends_with("-> i64 {"));
+        assert!(signature("count", TargetLanguage::Go).ends_with(") int64 {"));
+    }
+
+    #[test]
+    fn test_transpile_approx_eq() {
+        let (items, module_env) = env_from_source("atom close(a: f64, b: f64) requires: true; ensures: true; body: if a ~= b within 0.001 { 1 } else { 0 };");
+        let atom = find_atom(&items, "close");
+
+        let rust = transpile(&atom, TargetLanguage::Rust, &module_env).unwrap();
+        assert!(rust.contains("(((a) - (b)).abs() <= "), "{}", rust);
+
+        let go = transpile(&atom, TargetLanguage::Go, &module_env).unwrap();
+        assert!(go.contains("(math.Abs((a) - (b)) <= "), "{}", go);
+        assert!(go.contains("import \"math\""), "{}", go);
+
+        let ts = transpile(&atom, TargetLanguage::TypeScript, &module_env).unwrap();
+        assert!(ts.contains("(Math.abs((a) - (b)) <= "), "{}", ts);
+    }
+
+    #[test]
+    fn test_transpile_conditional_operator() {
+        let (items, module_env) = env_from_source("atom max2(a: i64, b: i64) requires: true; ensures: result == (a > b ? a : b); body: { a > b ? a : b };");
+        let atom = find_atom(&items, "max2");
+
+        // Rust: if-else 式
+        let rust = transpile(&atom, TargetLanguage::Rust, &module_env).unwrap();
+        assert!(rust.contains("if (a > b) { a } else { b }"), "{}", rust);
+
+        // Go: 三項演算子がないため関数リテラル内の if 文にする
+        let go = transpile(&atom, TargetLanguage::Go, &module_env).unwrap();
+        assert!(go.contains("return func() int64 { if (a > b) { return a }; return b }()"), "{}", go);
+        // 関数リテラルと let の一時変数の型は分岐の値の種類に従う
+        let items = parse_module("atom fmax(a: f64, b: f64) requires: true; ensures: true; body: { let m = a > b ? a : b; m > 0.0 ? m : 0.0 };").unwrap();
+        let float_atom = find_atom(&items, "fmax");
+        let go_float = transpile(&float_atom, TargetLanguage::Go, &module_env).unwrap();
+        assert!(go_float.contains("var m float64\n"), "{}", go_float);
+        assert!(go_float.contains("return func() float64 { if (m > "), "{}", go_float);
+
+        // TypeScript: 三項演算子のまま
+        let ts = transpile(&atom, TargetLanguage::TypeScript, &module_env).unwrap();
+        assert!(ts.contains("return ((a > b) ? a : b);"), "{}", ts);
+
+        // if 式（分岐が Block）は従来どおり文として出力する
+        let items = parse_module("atom pick(a: i64) requires: true; ensures: true; body: { if a > 0 { a } else { 0 } };").unwrap();
+        let atom = find_atom(&items, "pick");
+        let ts = transpile(&atom, TargetLanguage::TypeScript, &module_env).unwrap();
+        assert!(ts.contains("if ((a > 0)) {"), "{}", ts);
+    }
+
+    #[test]
+    fn test_where_clause_in_doc_comments() {
+        let (items, module_env) = env_from_source("atom span(lo: i64, hi: i64) where: lo <= hi; requires: lo >= 0; ensures: result >= 0; body: hi - lo;");
+        let atom = find_atom(&items, "span");
+
+        // where 節は requires と別の行に出す（requires 側には混ぜない）
+        let rust = transpile(&atom, TargetLanguage::Rust, &module_env).unwrap();
+        assert!(rust.contains("/// Where: lo <= hi\n/// Requires: lo >= 0\n"), "{}", rust);
+        let go = transpile(&atom, TargetLanguage::Go, &module_env).unwrap();
+        assert!(go.contains("// Where: lo <= hi\n// Requires: lo >= 0\n"), "{}", go);
+        let ts = transpile(&atom, TargetLanguage::TypeScript, &module_env).unwrap();
+        assert!(ts.contains(" * Where: lo <= hi\n * Requires: lo >= 0\n"), "{}", ts);
+    }
+
+    #[test]
+    fn test_struct_return_types() {
+        let (items, module_env) = env_from_source("struct Point { x: i64, y: i64 }\natom origin(a: i64) -> Point requires: true; ensures: result.x == a; body: Point { x: a, y: 0 };");
+        let atom = find_atom(&items, "origin");
+
+        // `-> Point` はどの言語でも構造体型の戻り値になる
+        let rust = transpile(&atom, TargetLanguage::Rust, &module_env).unwrap();
+        assert!(rust.contains("pub fn origin(a: i64) -> Point {"), "{}", rust);
+        let go = transpile(&atom, TargetLanguage::Go, &module_env).unwrap();
+        assert!(go.contains("func origin(a int64) Point {"), "{}", go);
+        let ts = transpile(&atom, TargetLanguage::TypeScript, &module_env).unwrap();
+        assert!(ts.contains("function origin(a: number): Point {"), "{}", ts);
+    }
+
+    #[test]
+    fn test_runtime_contract_checks() {
+        let (items, module_env) = env_from_source(r#"
+atom bump(n: i64, xs: [i64])
+requires: n >= 0 && len(xs) > n && (n > 10 => n < 100);
+ensures: result > n && forall(i, 0, n, xs[i] >= 0);
+body: n + 1;
+
+atom midpoint(lo: i64, hi: i64)
+requires: lo <= hi;
+ensures: result == mid && result >= lo;
+body: {
+    let mid = lo + (hi - lo) / 2;
+    mid
+};
+"#);
+        let atom = |name: &str| find_atom(&items, name);
+
+        // 既定では契約は doc コメントにだけ残る
+        let go = transpile(&atom("bump"), TargetLanguage::Go, &module_env).unwrap();
+        assert!(!go.contains("panic("), "{}", go);
+
+        // Go: requires は入口の guard、ensures は名前付き戻り値の defer、含意は !(a) || b
+        let go = transpile_checked(&atom("bump"), TargetLanguage::Go, &module_env, true).unwrap();
+        assert!(go.contains("func bump(n int64, xs []int64) (result int64) {"), "{}", go);
+        assert!(go.contains("if !(n >= 0) {\n        panic(\"precondition violated in bump: n >= 0\")\n    }"), "{}", go);
+        assert!(go.contains("if !(int64(len(xs)) > n) {"), "{}", go);
+        assert!(go.contains("if !(!(n > 10) || (n < 100)) {"), "{}", go);
+        assert!(go.contains("defer func() {\n        if !(result > n) {\n            panic(\"postcondition violated in bump: result > n\")"), "{}", go);
+        assert!(go.contains("// postcondition `forall(i, 0, n, xs[i] >= 0)` is not checked at runtime"), "{}", go);
+
+        // Rust: assert!、ensures は本体の値を result に束縛してから検査する
+        let rust = transpile_checked(&atom("bump"), TargetLanguage::Rust, &module_env, true).unwrap();
+        assert!(rust.contains("assert!(n >= 0, \"{}\", \"precondition violated in bump: n >= 0\");"), "{}", rust);
+        assert!(rust.contains("let result: i64 = "), "{}", rust);
+        assert!(rust.contains("assert!(result > n, \"{}\", \"postcondition violated in bump: result > n\");\n    result\n}"), "{}", rust);
+
+        // TypeScript: throw する guard
+        let ts = transpile_checked(&atom("bump"), TargetLanguage::TypeScript, &module_env, true).unwrap();
+        assert!(ts.contains("if (!(n >= 0)) throw new Error(\"precondition violated in bump: n >= 0\");"), "{}", ts);
+        assert!(ts.contains("const result = (() => {"), "{}", ts);
+        assert!(ts.contains("if (!(result > n)) throw new Error(\"postcondition violated in bump: result > n\");\n    return result;"), "{}", ts);
+
+        // 本体の let 束縛を参照する ensures の項は関数の境界で評価できないので検査しない
+        let go = transpile_checked(&atom("midpoint"), TargetLanguage::Go, &module_env, true).unwrap();
+        assert!(go.contains("// postcondition `result == mid` is not checked at runtime: 'mid' is not in scope at the function boundary"), "{}", go);
+        assert!(go.contains("if !(result >= lo) {"), "{}", go);
+    }
+
+    const TS_MODULE_SOURCE: &str = r#"
+import "./geometry.mm" as geo;
+
+struct Point { x: i64, y: i64 where v >= 0 }
+
+enum Shape { Circle(i64), Rect(i64, i64), Empty }
+
+enum Maybe<T> { Just(T), Nothing }
+
+trait Ord {
+    fn leq(a: Self, b: Self) -> bool;
+    law reflexive: leq(x, x) == true;
+}
+
+impl Ord for i64 {
+    fn leq(a: i64, b: i64) -> bool { a <= b }
+}
+
+atom area(w: i64, h: i64)
+requires: w >= 0 && h >= 0;
+ensures: result >= 0;
+body: { w * h };
+
+atom span(lo: i64, hi: i64)
+where: lo <= hi;
+requires: lo >= 0;
+ensures: result >= 0;
+body: { hi - lo };
+
+async atom scaled_area(w: i64, h: i64)
+requires: w >= 0 && h >= 0;
+ensures: result >= 0;
+body: { area(w, h) * 2 };
+"#;
+
+    #[test]
+    fn test_typescript_bundle_and_declarations_golden() {
+        let (items, module_env) = env_from_source(TS_MODULE_SOURCE);
+        let imports: Vec<ImportDecl> = items.iter()
+            .filter_map(|item| if let Item::Import(i) = item { Some(i.clone()) } else { None })
+            .collect();
+
+        // ESM の名前付き export のみ: 名前空間オブジェクト・TS の enum・トップレベルの副作用を出さない
+        let bundle = format!(
+            "{}{}",
+            transpile_bundle_header(&imports, "shapes", TargetLanguage::TypeScript, true, true),
+            crate::selftest::bundle(&items, &module_env, TargetLanguage::TypeScript)
+        );
+        assert!(!bundle.contains("enum "), "{}", bundle);
+        assert_golden("typescript", "shapes.ts.golden", &bundle);
+
+        // .d.ts はバンドルと同じ型定義と、atom / impl の宣言だけを持つ
+        let declarations = typescript::transpile_declarations_ts(&imports, &items, &module_env, false);
+        assert_golden("typescript", "shapes.d.ts.golden", &declarations);
+    }
+
+    const IMPORTS_SOURCE: &str = r#"
+import "./lib/text.mm";
+import "./lib/geometry.mm" as geo;
+
+atom scale(w: i64)
+requires: w >= 0;
+ensures: result >= 0;
+body: w * 2;
+
+atom doubled_area(w: i64, h: i64)
+requires: w >= 0 && h >= 0;
+ensures: result >= 0;
+body: area(w, h) * 2;
+"#;
+
+    /// IMPORTS_SOURCE の import 先が定義する名前（text は 1 つも呼ばれない）
+    fn import_definitions(imports: &[ImportDecl]) -> Vec<Option<HashSet<String>>> {
+        imports.iter().map(|import| {
+            let names: &[&str] = if import.path.ends_with("geometry.mm") { &["area", "Point"] } else { &["concat_len"] };
+            Some(names.iter().map(|n| n.to_string()).collect())
+        }).collect()
+    }
+
+    #[test]
+    fn test_module_header_lists_only_referenced_imports() {
+        let items = parse_module(IMPORTS_SOURCE).unwrap();
+        let imports: Vec<ImportDecl> = items.iter()
+            .filter_map(|item| if let Item::Import(i) = item { Some(i.clone()) } else { None })
+            .collect();
+        let definitions = import_definitions(&imports);
+
+        // 2 つの lib を import しているが呼んでいるのは geo だけ: どのバックエンドのヘッダーも geo だけを参照する
+        for (lang, golden) in [
+            (TargetLanguage::Rust, "header.rs.golden"),
+            (TargetLanguage::Go, "header.go.golden"),
+            (TargetLanguage::TypeScript, "header.ts.golden"),
+        ] {
+            let used = referenced_imports(&imports, &definitions, &bundle_references(&items, lang));
+            assert_eq!(used.iter().map(|i| i.path.as_str()).collect::<Vec<_>>(), vec!["./lib/geometry.mm"]);
+            assert_golden("imports", golden, &transpile_bundle_header(&used, "shapes", lang, false, true));
+        }
+
+        // 修飾付きの呼び出しは alias が一致する import だけを指す
+        let qualified: HashSet<String> = ["geo::area".to_string()].into_iter().collect();
+        assert_eq!(referenced_imports(&imports, &definitions, &qualified).len(), 1);
+        let wrong_alias: HashSet<String> = ["text::concat_len".to_string()].into_iter().collect();
+        assert!(referenced_imports(&imports, &definitions, &wrong_alias).is_empty());
+        // 定義を読めなかった import は残す
+        assert_eq!(referenced_imports(&imports, &[None, None], &HashSet::new()).len(), 2);
+
+        // 何も参照しなければ import 行は出さない。複数あれば並びは入力順ではなくソート順
+        assert_eq!(transpile_module_header(&[], "shapes", TargetLanguage::Go, false), "package shapes\n");
+        assert_eq!(transpile_module_header(&[], "shapes", TargetLanguage::Rust, false), "");
+        let mut reversed = imports.clone();
+        reversed.reverse();
+        assert_eq!(transpile_module_header(&reversed, "shapes", TargetLanguage::Rust, false), "mod geo;\nuse geo::*;\nmod text;\nuse text::*;\n");
+        assert_eq!(
+            transpile_module_header(&imports, "shapes", TargetLanguage::Go, true),
+            "package shapes\n\nimport (\n\t\"context\"\n\t\"geo\"\n\t\"text\"\n)\n"
+        );
+    }
+
+    /// MUMEI_CHECK_OUTPUT=1 のとき、import 先の呼び出しを含まない Go バンドルを `go vet` に通す
+    /// （未使用の import があるとコンパイルエラーになる）。go が PATH にない環境では失敗する
+    #[test]
+    fn test_go_bundle_without_imported_calls_passes_go_vet() {
+        if std::env::var_os("MUMEI_CHECK_OUTPUT").is_none() {
+            return;
+        }
+        // import 先を呼ぶ atom は Go に出力しない
+        let source = IMPORTS_SOURCE.replace("atom doubled_area", "@only(rust, typescript)\natom doubled_area");
+        let (items, module_env) = env_from_source(&source);
+        let imports: Vec<ImportDecl> = items.iter()
+            .filter_map(|item| if let Item::Import(i) = item { Some(i.clone()) } else { None })
+            .collect();
+        let used = referenced_imports(&imports, &import_definitions(&imports), &bundle_references(&items, TargetLanguage::Go));
+        assert!(used.is_empty());
+        let mut summary = UnsupportedSummary::default();
+        let mut bundle = transpile_bundle_header(&used, "shapes", TargetLanguage::Go, false, true);
+        for item in &items {
+            if let Item::Atom(atom) = item {
+                if let Some(code) = transpile_for_bundle(atom, TargetLanguage::Go, &module_env, UnsupportedPolicy::CompileError, false, &mut summary) {
+                    bundle.push_str(&code);
+                    bundle.push_str("\n\n");
+                }
+            }
+        }
+
+        let dir = TempDir::new("go_vet");
+        std::fs::write(dir.join("go.mod"), "module shapes\n\ngo 1.18\n").unwrap();
+        std::fs::write(dir.join("shapes.go"), &bundle).unwrap();
+        let output = std::process::Command::new("go").args(["vet", "."]).current_dir(&dir).output()
+            .unwrap_or_else(|e| panic!("MUMEI_CHECK_OUTPUT is set but go could not be run: {}", e));
+        assert!(output.status.success(), "go vet failed:\n{}\n{}", String::from_utf8_lossy(&output.stderr), bundle);
+    }
+
+    #[test]
+    fn test_recursive_enum_uses_indirection() {
+        let items = parse_module("enum List { Nil, Cons(i64, Self) }\nenum Shape { Circle(f64), Empty }").unwrap();
+        let enums: Vec<&EnumDef> = items.iter()
+            .filter_map(|item| if let Item::EnumDef(e) = item { Some(e) } else { None })
+            .collect();
+        assert!(enums[0].is_recursive && !enums[1].is_recursive);
+
+        // Rust: 再帰フィールドは Box、Box を含むので Copy は導出しない
+        let rust = transpile_enum(enums[0], TargetLanguage::Rust, false);
+        assert!(rust.contains("    Cons(i64, Box<List>),"), "{}", rust);
+        assert!(!rust.contains("Copy"), "{}", rust);
+        assert!(transpile_enum(enums[1], TargetLanguage::Rust, false).contains("Copy"));
+
+        // Go: tag 定数に加えて、再帰フィールドをポインタで持つノード構造体
+        let go = transpile_enum(enums[0], TargetLanguage::Go, false);
+        assert!(go.contains("type ListNode struct {\n\tTag List\n\tCons0 int64\n\tCons1 *ListNode\n}"), "{}", go);
+        // 再帰しない Enum も payload を持てばノード構造体で表すが、ポインタは使わない
+        let go = transpile_enum(enums[1], TargetLanguage::Go, false);
+        assert!(go.contains("type ShapeNode struct {\n\tTag Shape\n\tCircle0 float64\n}"), "{}", go);
+        assert!(!go.contains("*ShapeNode"), "{}", go);
+
+        // TypeScript: 自身の型のネストしたオブジェクト
+        let ts = transpile_enum(enums[0], TargetLanguage::TypeScript, false);
+        assert!(ts.contains("{ kind: \"Cons\"; field_0: number; field_1: List }"), "{}", ts);
+    }
+
+    #[test]
+    fn test_variant_construction() {
+        let (items, module_env) = env_from_source(r#"
+enum Shape { Circle(f64), Rect(f64, f64), Empty }
+enum List { Nil, Cons(i64, Self) }
+enum Color { Red, Green }
+atom circle(r: f64) -> Shape requires: true; ensures: true; body: Circle(r);
+atom empty() -> Shape requires: true; ensures: true; body: Empty;
+atom push(x: i64, xs: List) -> List requires: true; ensures: true; body: Cons(x, xs);
+atom red() -> Color requires: true; ensures: true; body: Red;
+"#);
+        let atoms: Vec<&Atom> = items.iter().filter_map(|i| if let Item::Atom(a) = i { Some(a) } else { None }).collect();
+        let out = |lang: TargetLanguage| atoms.iter().map(|a| transpile(a, lang, &module_env).unwrap()).collect::<Vec<_>>().join("\n");
+
+        // Rust: Enum 名で修飾した Variant、再帰フィールドは Box
+        let rust = out(TargetLanguage::Rust);
+        assert!(rust.contains("-> Shape {") && rust.contains("Shape::Circle(r)") && rust.contains("Shape::Empty"), "{}", rust);
+        assert!(rust.contains("List::Cons(x, Box::new(xs))") && rust.contains("Color::Red"), "{}", rust);
+        // Go: payload を持つ Enum は {Name}Node のリテラル、持たない Enum は tag の定数
+        let go = out(TargetLanguage::Go);
+        assert!(go.contains(") ShapeNode {") && go.contains("ShapeNode{Tag: Circle, Circle0: r}") && go.contains("ShapeNode{Tag: Empty}"), "{}", go);
+        assert!(go.contains("ListNode{Tag: Cons, Cons0: x, Cons1: func(v ListNode) *ListNode { return &v }(xs)}"), "{}", go);
+        assert!(go.contains(") Color {") && go.contains("Red") && !go.contains("ColorNode"), "{}", go);
+        // TypeScript: discriminated union のオブジェクトリテラル
+        let ts = out(TargetLanguage::TypeScript);
+        assert!(ts.contains("): Shape {") && ts.contains(r#"({ kind: "Circle", field_0: r })"#) && ts.contains(r#"({ kind: "Empty" })"#), "{}", ts);
+        assert!(ts.contains(r#"({ kind: "Cons", field_0: x, field_1: xs })"#), "{}", ts);
+    }
+
+    #[test]
+    fn test_transpiled_bool_params() {
+        let (items, module_env) = env_from_source(r#"
+type Flag = bool where v == true;
+atom pick(flag: bool, a: i64, b: i64) requires: flag == true; ensures: true; body: if flag { a } else { b };
+atom pick_flag(flag: Flag, a: i64) requires: true; ensures: true; body: if flag { a } else { 0 };
+atom always() requires: true; ensures: true; body: pick(true, 1, 2);
+"#);
+        let atoms: Vec<&Atom> = items.iter().filter_map(|i| if let Item::Atom(a) = i { Some(a) } else { None }).collect();
+        let out = |lang: TargetLanguage| atoms.iter().map(|a| transpile(a, lang, &module_env).unwrap()).collect::<Vec<_>>().join("\n");
+
+        // bool とベース型が bool の精緻型は各言語の真偽値型、リテラルはそのまま
+        let rust = out(TargetLanguage::Rust);
+        assert!(rust.contains("flag: bool, a: i64") && rust.contains("pick(true, 1, 2)"), "{}", rust);
+        assert!(rust.matches("flag: bool").count() == 2, "{}", rust);
+        let go = out(TargetLanguage::Go);
+        assert!(go.contains("flag bool, a int64") && go.contains("pick(true, 1, 2)"), "{}", go);
+        let ts = out(TargetLanguage::TypeScript);
+        assert!(ts.contains("flag: boolean, a: number") && ts.contains("pick(true, 1, 2)"), "{}", ts);
+    }
+
+    #[test]
+    fn test_implies_in_body() {
+        // 契約のトップレベル以外（body の if の条件など）の `=>` も `!a || b` にする。連鎖は左結合
+        let (items, module_env) = env_from_source(r#"
+atom gate(x: i64, y: i64) requires: true; ensures: true; body: if x > 0 => y > 0 { 1 } else { 0 };
+atom chain(a: bool, b: bool, c: bool) requires: true; ensures: true; body: a => b => c;
+"#);
+        let atoms: Vec<&Atom> = items.iter().filter_map(|i| if let Item::Atom(a) = i { Some(a) } else { None }).collect();
+        for lang in [TargetLanguage::Rust, TargetLanguage::Go, TargetLanguage::TypeScript] {
+            let out = atoms.iter().map(|a| transpile(a, lang, &module_env).unwrap()).collect::<Vec<_>>().join("\n");
+            assert!(out.contains("(!((x > 0)) || (y > 0))"), "{:?}: {}", lang, out);
+            assert!(out.contains("(!((!(a) || b)) || c)"), "{:?}: {}", lang, out);
+        }
+    }
+
+    #[test]
+    fn test_refined_array_params() {
+        let (items, module_env) = env_from_source(r#"
+type Nat = i64 where v >= 0;
+type Prob = f64 where p >= 0.0 && p <= 1.0;
+atom head_plus(xs: [Nat]) requires: len(xs) > 0; ensures: result >= 1; body: xs[0] + 1;
+atom first_prob(ps: [Prob]) requires: len(ps) > 0; ensures: result <= 1.0; body: ps[0];
+"#);
+        let atoms: Vec<&Atom> = items.iter().filter_map(|i| if let Item::Atom(a) = i { Some(a) } else { None }).collect();
+        let out = |lang: TargetLanguage| atoms.iter().map(|a| transpile(a, lang, &module_env).unwrap()).collect::<Vec<_>>().join("\n");
+
+        // 精緻型の配列は要素のベース型の配列になり、要素の制約はドキュメントコメントに残る
+        let rust = out(TargetLanguage::Rust);
+        assert!(rust.contains("xs: &[i64]") && rust.contains("ps: &[f64]"), "{}", rust);
+        assert!(rust.contains("/// Elements: every xs[i] is Nat (v where v >= 0)"), "{}", rust);
+        let go = out(TargetLanguage::Go);
+        assert!(go.contains("xs []int64") && go.contains("ps []float64"), "{}", go);
+        assert!(go.contains("// Elements: every ps[i] is Prob (p where p >= 0.0 && p <= 1.0)"), "{}", go);
+        let ts = out(TargetLanguage::TypeScript);
+        assert!(ts.contains("xs: number[]") && ts.contains(" * Elements: every xs[i] is Nat"), "{}", ts);
+    }
+
+    const BRANDED_SOURCE: &str = r#"
+type Nat = i64 where v >= 0;
+type Pct = f64 where p >= 0.0 && p <= 100.0;
+type Tagged = i64 where v >= 0 && tag_ok(v);
+enum Slot { Held(i64), Free }
+atom inc(x: Nat) requires: true; ensures: result > x; body: x + 1;
+atom two() requires: true; ensures: result == 2; body: inc(1);
+atom countdown(n: Nat)
+requires: true;
+ensures: result == 0;
+body: {
+    let i = n;
+    while i > 0
+    invariant: i >= 0
+    decreases: i
+    {
+        i = i - 1;
+    };
+    i
+};
+atom doubled(x: Nat) requires: true; ensures: result == old(x) * 2; body: { x = x * 2; x };
+atom half(p: Pct) requires: true; ensures: result >= 0.0; body: p / 2.0;
+atom hold(x: Nat) -> Slot requires: true; ensures: true; body: Held(x);
+"#;
+
+    fn branded_bundle() -> (Vec<Item>, ModuleEnv, String) {
+        let (items, module_env) = env_from_source(BRANDED_SOURCE);
+        let bundle = crate::selftest::bundle(&items, &module_env, TargetLanguage::TypeScript);
+        (items, module_env, bundle)
+    }
+
+    #[test]
+    fn test_typescript_branded_refined_types() {
+        let (items, module_env, ts) = branded_bundle();
+
+        // 精緻型はブランド型と、述語を検査してブランドを付ける toX
+        assert!(ts.contains("export type Nat = number & { readonly __brand: \"Nat\" };"), "{}", ts);
+        assert!(ts.contains("export function toNat(v: number): Nat {\n    if (!(v >= 0)) throw new Error(\"refinement violated: Nat requires v >= 0\");\n    return v as Nat;\n}"), "{}", ts);
+        assert!(ts.contains("export function toPct(p: number): Pct {\n    if (!((p >= 0) && (p <= 100))) throw"), "{}", ts);
+        // 翻訳できない述語は検査せずにブランドを付ける
+        assert!(ts.contains("    // the predicate `v >= 0 && tag_ok(v)` cannot be checked at runtime\n    return v as Tagged;"), "{}", ts);
+        let nat = items.iter().find_map(|i| if let Item::TypeDef(t) = i { Some(t) } else { None }).unwrap();
+        assert!(transpile_refined_type(nat, TargetLanguage::Rust).is_none() && transpile_refined_type(nat, TargetLanguage::Go).is_none());
+
+        // シグネチャはブランド型、検証済みの呼び出し元の実引数は型変換する
+        assert!(ts.contains("export function inc(x: Nat): number {") && ts.contains("return inc((1 as Nat));"), "{}", ts);
+        assert!(ts.contains("export function half(p: Pct): number {"), "{}", ts);
+        // ブランド型で初期化する let はベース型にして、後の代入を許す
+        assert!(ts.contains("export function countdown(n: Nat): number {") && ts.contains("let i: number = n;"), "{}", ts);
+        // 本体が代入するパラメータはベース型のまま
+        assert!(ts.contains("export function doubled(x: number): number {"), "{}", ts);
+
+        // Enum は判別共用体と Variant ごとの構築関数
+        assert!(ts.contains("export function slotHeld(field_0: number): Slot { return { kind: \"Held\", field_0 }; }"), "{}", ts);
+        assert!(ts.contains("export function slotFree(): Slot { return { kind: \"Free\" }; }"), "{}", ts);
+
+        // .d.ts はブランド型と関数の宣言
+        let declarations = typescript::transpile_declarations_ts(&[], &items, &module_env, false);
+        assert!(declarations.contains("export type Nat = number & { readonly __brand: \"Nat\" };\nexport declare function toNat(v: number): Nat;"), "{}", declarations);
+        assert!(declarations.contains("export declare function slotHeld(field_0: number): Slot;"), "{}", declarations);
+        assert!(declarations.contains("export declare function inc(x: Nat): number;"), "{}", declarations);
+    }
+
+    /// tsc があればブランド型を使うバンドルと .d.ts を `tsc --strict --noEmit` に通す（ない環境では何もしない）
+    #[test]
+    fn test_typescript_branded_output_passes_tsc_strict() {
+        if std::process::Command::new("tsc").arg("--version").output().map_or(true, |o| !o.status.success()) {
+            return;
+        }
+        let (items, module_env, bundle) = branded_bundle();
+        let dir = TempDir::new("branded_tsc");
+        std::fs::write(dir.join("branded.ts"), &bundle).unwrap();
+        std::fs::write(dir.join("declarations.d.ts"), typescript::transpile_declarations_ts(&[], &items, &module_env, false)).unwrap();
+        let output = std::process::Command::new("tsc")
+            .args(["--strict", "--noEmit", "--target", "es2020", "--module", "es2020"])
+            .arg(dir.join("branded.ts"))
+            .arg(dir.join("declarations.d.ts"))
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "tsc failed:\n{}\n{}", String::from_utf8_lossy(&output.stdout), bundle);
+    }
+
+    const MATCH_SOURCE: &str = r#"
+enum Shape { Circle(f64), Rect(f64, f64), Empty }
+enum List { Nil, Cons(i64, Self) }
+
+atom area(s: Shape)
+requires: true;
+ensures: result >= 0.0;
+body: match s { Circle(r) => r * r, Rect(w, h) => w * h, Empty => 0.0 };
+
+atom bucket(n: i64)
+requires: true;
+ensures: result >= 0;
+body: match n { 0 => 0, 1 => 1, _ => 2 };
+
+atom wide(s: Shape)
+requires: true;
+ensures: result >= 0;
+body: match s { Rect(w, h) if w > h => 1, Circle(_) => 2, _ => 0 };
+
+atom length(xs: List)
+requires: true;
+ensures: result >= 0;
+body: match xs { Nil => 0, Cons(_, t) => length(t) + 1 };
+
+atom second_or(xs: List, d: i64)
+requires: true;
+ensures: true;
+body: match xs { Cons(_, Cons(y, _)) => y, _ => d };
+"#;
+
+    fn match_bundle(lang: TargetLanguage) -> String {
+        let (items, module_env) = env_from_source(MATCH_SOURCE);
+        crate::selftest::bundle(&items, &module_env, lang)
+    }
+
+    #[test]
+    fn test_match_expressions_golden() {
+        // Rust: 修飾した Variant と Box の中身の matches! / Go: Tag の switch / TS: kind の switch と never による網羅性検査
+        for (lang, golden) in [(TargetLanguage::Rust, "match.rs.golden"), (TargetLanguage::Go, "match.go.golden"), (TargetLanguage::TypeScript, "match.ts.golden")] {
+            let bundle = match_bundle(lang);
+            assert!(!bundle.contains("TODO(mumei)"), "{}", bundle);
+            assert_golden("match", golden, &bundle);
+        }
+    }
+
+    /// MUMEI_CHECK_OUTPUT=1 のとき、Rust の出力をライブラリとして rustc でコンパイルする。
+    /// rustc が PATH にない環境では失敗する
+    #[test]
+    fn test_match_rust_output_compiles() {
+        if std::env::var_os("MUMEI_CHECK_OUTPUT").is_none() {
+            return;
+        }
+        let dir = TempDir::new("match_rustc");
+        let src = dir.join("matches.rs");
+        std::fs::write(&src, match_bundle(TargetLanguage::Rust)).unwrap();
+        let output = std::process::Command::new("rustc")
+            .args(["--edition", "2021", "--crate-type", "lib", "--out-dir"])
+            .arg(&*dir)
+            .arg(&src)
+            .output()
+            .unwrap_or_else(|e| panic!("MUMEI_CHECK_OUTPUT is set but rustc could not be run: {}", e));
+        assert!(output.status.success(), "rustc failed:\n{}", String::from_utf8_lossy(&output.stderr));
+    }
+
+    #[test]
+    fn test_match_guard_on_boxed_binding_is_unsupported_in_rust() {
+        let (items, module_env) = env_from_source(
+            "enum List { Nil, Cons(i64, Self) }\natom head2(xs: List) requires: true; ensures: true; body: match xs { Cons(_, Cons(y, _)) if y > 0 => y, _ => 0 };",
+        );
+        let atom = find_atom(&items, "head2");
+        // Box の中の変数はガードの時点で取り出せない
+        let err = transpile(&atom, TargetLanguage::Rust, &module_env).unwrap_err();
+        assert_eq!(err.construct, "nested enum pattern");
+        // Go / TS はフィールド参照で判定するので、ガードも条件に並べられる
+        let go = transpile(&atom, TargetLanguage::Go, &module_env).unwrap();
+        assert!(go.contains("case xs.Tag == Cons && xs.Cons1 != nil && xs.Cons1.Tag == Cons && (xs.Cons1.Cons0 > 0):"), "{}", go);
+        let ts = transpile(&atom, TargetLanguage::TypeScript, &module_env).unwrap();
+        assert!(ts.contains("if (xs.kind === \"Cons\" && xs.field_1.kind === \"Cons\" && (xs.field_1.field_0 > 0)) return xs.field_1.field_0;"), "{}", ts);
+    }
+
+    const SERDE_SOURCE: &str = r#"
+type Nat = i64 where v >= 0;
+struct Point { x: f64 where v >= 0.0, y: f64 }
+struct Account { id: Nat, limit: u64, balance: i64 where v <= 1000000 }
+enum Shape { Circle(f64), Rect(i64, i64), Empty }
+enum List { Nil, Cons(i64, Self) }
+enum Color { Red, Green }
+"#;
+
+    /// 型の形に合わない JSON。Rust（serde）と TypeScript（parseX）のどちらでも拒否される
+    const SERDE_REJECTED: &[&str] = &[
+        r#"Account {"id":1.5,"limit":0,"balance":0}"#,
+        r#"Shape {"kind":"Oval"}"#,
+        r#"List {"kind":"Cons","field_0":1,"field_1":{"kind":"Cons"}}"#,
+    ];
+
+    /// 形は合うが where 句・精緻型の述語に反する JSON。述語を検査する TypeScript の parseX だけが拒否する
+    const SERDE_REJECTED_BY_PREDICATE: &[&str] = &[
+        r#"Point {"x":-1,"y":0}"#,
+        r#"Account {"id":-1,"limit":0,"balance":0}"#,
+    ];
+
+    /// [build.rust] derive_serde の型定義（Rust）と、型 + [build.ts] emit_validators の parseX（TypeScript）
+    fn serde_definitions(lang: TargetLanguage) -> String {
+        let (items, module_env) = env_from_source(SERDE_SOURCE);
+        let mut parts = Vec::new();
+        for item in &items {
+            let (definition, validator) = match item {
+                Item::StructDef(s) => (transpile_struct(s, lang, true), typescript::transpile_struct_validator_ts(s, &module_env)),
+                Item::EnumDef(e) => (transpile_enum(e, lang, true), typescript::transpile_enum_validator_ts(e, &module_env)),
+                _ => continue,
+            };
+            parts.push(definition);
+            if lang == TargetLanguage::TypeScript {
+                parts.extend(validator);
+            }
+        }
+        format!("{}\n", parts.join("\n\n"))
+    }
+
+    #[test]
+    fn test_serde_definitions_golden() {
+        assert_golden("serde", "types.rs.golden", &serde_definitions(TargetLanguage::Rust));
+        assert_golden("serde", "types.ts.golden", &serde_definitions(TargetLanguage::TypeScript));
+        // Go の struct は encoding/json のキーを元のフィールド名に揃える
+        let go = serde_definitions(TargetLanguage::Go);
+        assert!(go.contains("\tX float64 `json:\"x\"`\n\tY float64 `json:\"y\"`"), "{}", go);
+        // derive_serde がなければ serde の derive も JSON 用の型も出さない
+        let items = parse_module(SERDE_SOURCE).unwrap();
+        for item in &items {
+            let plain = match item {
+                Item::StructDef(s) => transpile_struct(s, TargetLanguage::Rust, false),
+                Item::EnumDef(e) => transpile_enum(e, TargetLanguage::Rust, false),
+                _ => continue,
+            };
+            assert!(!plain.contains("serde"), "{}", plain);
+        }
+    }
+
+    /// tests/golden/serde/types.rs.golden（生成した Rust の型定義）そのもの
+    #[allow(dead_code)]
+    mod serde_types {
+        include!(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/golden/serde/types.rs.golden"));
+    }
+
+    /// golden の "型名 JSON" の行を (型名, JSON) に分ける
+    fn serde_value_lines(text: &str) -> Vec<(&str, &str)> {
+        text.lines().filter_map(|line| line.split_once(' ')).collect()
+    }
+
+    /// 型名で選んだ生成型として JSON を読み、serde で書き戻す
+    fn serde_reencode(type_name: &str, json: &str) -> Result<String, serde_json::Error> {
+        use serde_types::*;
+        match type_name {
+            "Point" => serde_json::from_str::<Point>(json).and_then(|v| serde_json::to_string(&v)),
+            "Account" => serde_json::from_str::<Account>(json).and_then(|v| serde_json::to_string(&v)),
+            "Shape" => serde_json::from_str::<Shape>(json).and_then(|v| serde_json::to_string(&v)),
+            "List" => serde_json::from_str::<List>(json).and_then(|v| serde_json::to_string(&v)),
+            "Color" => serde_json::from_str::<Color>(json).and_then(|v| serde_json::to_string(&v)),
+            other => panic!("unknown type {}", other),
+        }
+    }
+
+    #[test]
+    fn test_serde_json_round_trip_rust() {
+        use serde_types::*;
+        // Rust の出力する JSON は TypeScript の判別共用体と同じ形（kind + field_{i}）
+        let list = List::Cons(1, Box::new(List::Cons(2, Box::new(List::Nil))));
+        let values = [
+            ("Point", serde_json::to_string(&Point { x: 1.5, y: 2.0 })),
+            ("Account", serde_json::to_string(&Account { id: 7, limit: 42, balance: -3 })),
+            ("Shape", serde_json::to_string(&Shape::Circle(0.5))),
+            ("Shape", serde_json::to_string(&Shape::Rect(2, 3))),
+            ("Shape", serde_json::to_string(&Shape::Empty)),
+            ("List", serde_json::to_string(&list)),
+            ("Color", serde_json::to_string(&Color::Green)),
+        ];
+        let text: String = values.into_iter().map(|(name, json)| format!("{} {}\n", name, json.unwrap())).collect();
+        assert_golden("serde", "values.json.golden", &text);
+
+        for (type_name, json) in serde_value_lines(&text) {
+            assert_eq!(serde_reencode(type_name, json).unwrap(), json);
+        }
+        assert_eq!(serde_json::from_str::<List>(r#"{"kind":"Cons","field_0":1,"field_1":{"kind":"Nil"}}"#).unwrap(), List::Cons(1, Box::new(List::Nil)));
+        // TypeScript の JSON.stringify は整数値の f64 を小数点なしで書き、キーの順序も保証しない
+        assert_eq!(serde_reencode("Point", r#"{"y":2,"x":1}"#).unwrap(), r#"{"x":1.0,"y":2.0}"#);
+        assert_eq!(serde_reencode("Shape", r#"{"field_0":1,"kind":"Circle"}"#).unwrap(), r#"{"kind":"Circle","field_0":1.0}"#);
+        // 形が合わない値は serde でも拒否する（where 句は TypeScript 側だけが検査する）
+        for (type_name, json) in serde_value_lines(&SERDE_REJECTED.join("\n")) {
+            assert!(serde_reencode(type_name, json).is_err(), "{} {}", type_name, json);
+        }
+        for (type_name, json) in serde_value_lines(&SERDE_REJECTED_BY_PREDICATE.join("\n")) {
+            assert!(serde_reencode(type_name, json).is_ok(), "{} {}", type_name, json);
+        }
+    }
+
+    /// MUMEI_CHECK_OUTPUT=1 のとき、TypeScript の parseX が Rust の JSON をそのまま受け入れ、
+    /// 同じ JSON に書き戻すこと（と不正な値を拒否すること）を node で確かめる。
+    /// node は型注釈の除去（--experimental-strip-types、Node 22.6+）に対応していなければならない
+    #[test]
+    fn test_serde_json_round_trip_typescript() {
+        if std::env::var_os("MUMEI_CHECK_OUTPUT").is_none() {
+            return;
+        }
+        let values = std::fs::read_to_string(std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden/serde/values.json.golden")).unwrap();
+        let driver = format!(
+            r#"
+const parsers: Record<string, (json: unknown) => unknown> = {{ Point: parsePoint, Account: parseAccount, Shape: parseShape, List: parseList, Color: parseColor }};
+const split = (line: string): [string, unknown] => [line.slice(0, line.indexOf(" ")), JSON.parse(line.slice(line.indexOf(" ") + 1))];
+for (const line of {accepted}) {{
+    const [name, value] = split(line);
+    const parsed = parsers[name](value);
+    if (JSON.stringify(parsed) !== JSON.stringify(value)) throw new Error("round trip changed " + line);
+}}
+for (const line of {rejected}) {{
+    const [name, value] = split(line);
+    let threw = false;
+    try {{ parsers[name](value); }} catch {{ threw = true; }}
+    if (!threw) throw new Error("accepted " + line);
+}}
+"#,
+            accepted = serde_json::to_string(&values.lines().collect::<Vec<_>>()).unwrap(),
+            rejected = serde_json::to_string(&[SERDE_REJECTED, SERDE_REJECTED_BY_PREDICATE].concat()).unwrap(),
+        );
+        let dir = TempDir::new("serde_ts");
+        let script = dir.join("roundtrip.mts");
+        std::fs::write(&script, format!("{}{}", serde_definitions(TargetLanguage::TypeScript), driver)).unwrap();
+        let output = std::process::Command::new("node").arg("--experimental-strip-types").arg(&script).output()
+            .unwrap_or_else(|e| panic!("MUMEI_CHECK_OUTPUT is set but node could not be run: {}", e));
+        assert!(output.status.success(), "node failed:\n{}", String::from_utf8_lossy(&output.stderr));
+    }
+
+    const TARGETS_SOURCE: &str = r#"
+atom shared(x: i64) requires: true; ensures: true; body: x;
+
+@only(rust, go)
+atom native(x: i64) requires: true; ensures: true; body: shared(x);
+
+@exclude(typescript)
+atom no_ts(x: i64) requires: true; ensures: true; body: x + 1;
+
+@only(typescript)
+atom web(x: i64) requires: true; ensures: true; body: shared(x);
+"#;
+
+    #[test]
+    fn test_bundles_respect_target_annotations() {
+        let (items, module_env) = env_from_source(TARGETS_SOURCE);
+        assert_eq!(check_atom_targets(&items, &module_env), Ok(()));
+
+        let emitted = |lang: TargetLanguage| -> Vec<String> {
+            let mut summary = UnsupportedSummary::default();
+            items.iter().filter_map(|item| match item {
+                Item::Atom(atom) => transpile_for_bundle(atom, lang, &module_env, UnsupportedPolicy::CompileError, false, &mut summary)
+                    .map(|_| atom.name.clone()),
+                _ => None,
+            }).collect()
+        };
+        assert_eq!(emitted(TargetLanguage::Rust), vec!["shared", "native", "no_ts"]);
+        assert_eq!(emitted(TargetLanguage::Go), vec!["shared", "native", "no_ts"]);
+        assert_eq!(emitted(TargetLanguage::TypeScript), vec!["shared", "web"]);
+
+        // .d.ts も TypeScript のバンドルと同じ atom だけを宣言する
+        let declarations = typescript::transpile_declarations_ts(&[], &items, &module_env, false);
+        assert!(declarations.contains("web("), "{}", declarations);
+        assert!(!declarations.contains("native("), "{}", declarations);
+        assert!(!declarations.contains("no_ts("), "{}", declarations);
+
+        // LLVM IR は @only(typescript) の atom だけ出力しない
+        let emits_ir: Vec<bool> = items.iter().filter_map(|item| match item {
+            Item::Atom(atom) => Some(atom.targets.emits_ir()),
+            _ => None,
+        }).collect();
+        assert_eq!(emits_ir, vec![true, true, true, false]);
+    }
+
+    #[test]
+    fn test_check_atom_targets_rejects_excluded_callee() {
+        let source = r#"
+@exclude(go)
+atom helper(x: i64) requires: true; ensures: true; body: x;
+
+atom caller(x: i64) requires: true; ensures: true; body: helper(x);
+"#;
+        let (items, module_env) = env_from_source(source);
+        let err = check_atom_targets(&items, &module_env).unwrap_err();
+        assert_eq!(err, "atom 'caller' is emitted for go but calls 'helper', which is excluded from go");
+
+        // 呼び出し側も同じターゲットから除外すれば通る
+        let fixed = source.replace("atom caller", "@exclude(go) atom caller");
+        let (items, module_env) = env_from_source(&fixed);
+        assert_eq!(check_atom_targets(&items, &module_env), Ok(()));
+
+        let items = parse_module("@only(wasm) atom f(x: i64) requires: true; ensures: true; body: x;").unwrap();
+        let err = check_atom_targets(&items, &ModuleEnv::new()).unwrap_err();
+        assert!(err.contains("unknown target 'wasm'"), "{}", err);
+    }
+}
@@ -1,4 +1,8 @@
-use crate::parser::{Expr, Op, Atom, ImportDecl, EnumDef, StructDef, TraitDef, ImplDef, parse_expression};
+use std::collections::HashSet;
+use crate::parser::{Expr, Op, Atom, ImportDecl, EnumDef, EnumVariant, StructDef, TraitDef, ImplDef, Pattern};
+use crate::ast::ResultKind;
+use crate::verification::ModuleEnv;
+use super::{RuntimeChecks, TranspileCtx, Unsupported, UnsupportedPolicy, TargetLanguage, doc_contract, doc_elements, doc_where, is_recursive_field, param_enum, parse_body, param_type_name, return_enum};
 
 /// 型名をベース型に解決する（transpiler ローカル版）
 /// 精緻型の解決は ModuleEnv が担当するが、transpiler は単相化後の具体型名を受け取るため、
@@ -8,17 +12,21 @@ fn resolve_base_type(name: &str) -> String {
     name.to_string()
 }
 
-/// import 宣言から Rust のモジュールヘッダーを生成する
+/// import 宣言から Rust のモジュールヘッダーを生成する（モジュール名順、重複なし）
 /// 例: mod math; use math::*;
 pub fn transpile_module_header_rust(imports: &[ImportDecl], _module_name: &str) -> String {
-    let mut lines = Vec::new();
-    for import in imports {
-        // パスからモジュール名を推定（例: "./lib/math.mm" → "math"）
-        let mod_name = import.alias.as_deref()
+    // パスからモジュール名を推定（例: "./lib/math.mm" → "math"）
+    let mut mod_names: Vec<&str> = imports.iter()
+        .map(|import| import.alias.as_deref()
             .unwrap_or_else(|| {
                 import.path.rsplit('/').next().unwrap_or(&import.path)
                     .trim_end_matches(".mm")
-            });
+            }))
+        .collect();
+    mod_names.sort();
+    mod_names.dedup();
+    let mut lines = Vec::new();
+    for mod_name in mod_names {
         lines.push(format!("mod {};", mod_name));
         lines.push(format!("use {}::*;", mod_name));
     }
@@ -28,37 +36,112 @@ pub fn transpile_module_header_rust(imports: &[ImportDecl], _module_name: &str)
     lines.join("\n")
 }
 
-/// Enum 定義を Rust の enum に変換する
-pub fn transpile_enum_rust(enum_def: &EnumDef) -> String {
+/// Enum 定義を Rust の enum に変換する。
+/// 再帰的 Enum（`Cons(i64, Self)`）の再帰フィールドは `Box<List>` で間接参照し、Box を含むため Copy は導出しない。
+/// derive_serde（`[build.rust] derive_serde = true`）では JSON を TypeScript の判別共用体と同じ形
+/// （`kind` にバリアント名、payload は `field_{i}`）にする。serde の `tag` はタプル variant に使えないため、
+/// payload を持つ Enum は名前付きフィールドの `{Name}Json` を経由して変換する。
+pub fn transpile_enum_rust(enum_def: &EnumDef, derive_serde: bool) -> String {
     let mut lines = Vec::new();
     lines.push(format!("/// Verified Enum: {}", enum_def.name));
-    lines.push(format!("#[derive(Debug, Clone, Copy, PartialEq)]"));
+    let serde_derive = if derive_serde { ", serde::Serialize, serde::Deserialize" } else { "" };
+    if enum_def.is_recursive {
+        lines.push(format!("#[derive(Debug, Clone, PartialEq{})]", serde_derive));
+    } else {
+        lines.push(format!("#[derive(Debug, Clone, Copy, PartialEq{})]", serde_derive));
+    }
     // Generics: 型パラメータがある場合は <T, U> を付与
     let type_params_str = if enum_def.type_params.is_empty() {
         String::new()
     } else {
         format!("<{}>", enum_def.type_params.join(", "))
     };
+    let has_payload = enum_def.variants.iter().any(|v| !v.fields.is_empty());
+    let json_name = format!("{}Json{}", enum_def.name, type_params_str);
+    if derive_serde && has_payload {
+        lines.push(format!("#[serde(into = \"{}\", from = \"{}\")]", json_name, json_name));
+    } else if derive_serde {
+        lines.push("#[serde(tag = \"kind\")]".to_string());
+    }
     lines.push(format!("pub enum {}{} {{", enum_def.name, type_params_str));
+    let mut variant_fields = Vec::new();
     for variant in &enum_def.variants {
-        if variant.fields.is_empty() {
+        let field_types: Vec<String> = variant.fields.iter()
+            .map(|f| if is_recursive_field(enum_def, f) {
+                format!("Box<{}{}>", enum_def.name, type_params_str)
+            } else {
+                map_type_rust(Some(f.as_str()))
+            })
+            .collect();
+        if field_types.is_empty() {
             lines.push(format!("    {},", variant.name));
         } else {
-            let field_types: Vec<String> = variant.fields.iter()
-                .map(|f| map_type_rust(Some(f.as_str())))
-                .collect();
             lines.push(format!("    {}({}),", variant.name, field_types.join(", ")));
         }
+        variant_fields.push((variant.name.as_str(), field_types));
     }
     lines.push("}".to_string());
+    if derive_serde && has_payload {
+        lines.push(String::new());
+        lines.push(serde_json_enum_rust(&enum_def.name, &type_params_str, &variant_fields));
+    }
+    lines.join("\n")
+}
+
+/// payload を持つ Enum の JSON 表現（`kind` で判別し、payload を `field_{i}` に持つ）と相互変換
+fn serde_json_enum_rust(name: &str, type_params_str: &str, variants: &[(&str, Vec<String>)]) -> String {
+    let json = format!("{}Json", name);
+    let field_names = |count: usize| (0..count).map(|i| format!("field_{}", i)).collect::<Vec<_>>().join(", ");
+    let mut lines = Vec::new();
+    lines.push(format!("/// JSON form of {}: tagged by `kind`, payload in `field_{{i}}` (same shape as the TypeScript union)", name));
+    lines.push("#[doc(hidden)]".to_string());
+    lines.push("#[derive(serde::Serialize, serde::Deserialize)]".to_string());
+    lines.push("#[serde(tag = \"kind\")]".to_string());
+    lines.push(format!("pub enum {}{} {{", json, type_params_str));
+    for (variant, field_types) in variants {
+        if field_types.is_empty() {
+            lines.push(format!("    {},", variant));
+        } else {
+            let fields: Vec<String> = field_types.iter().enumerate().map(|(i, t)| format!("field_{}: {}", i, t)).collect();
+            lines.push(format!("    {} {{ {} }},", variant, fields.join(", ")));
+        }
+    }
+    lines.push("}".to_string());
+    for (from, to) in [(name, json.as_str()), (json.as_str(), name)] {
+        let from_json = from == json;
+        lines.push(String::new());
+        lines.push(format!("impl{tp} From<{from}{tp}> for {to}{tp} {{", tp = type_params_str, from = from, to = to));
+        lines.push(format!("    fn from(value: {}{}) -> Self {{", from, type_params_str));
+        lines.push("        match value {".to_string());
+        for (variant, field_types) in variants {
+            let fields = field_names(field_types.len());
+            let (tuple, named) = if field_types.is_empty() {
+                (String::new(), String::new())
+            } else {
+                (format!("({})", fields), format!(" {{ {} }}", fields))
+            };
+            let (pattern, value) = if from_json { (named, tuple) } else { (tuple, named) };
+            lines.push(format!("            {}::{}{} => {}::{}{},", from, variant, pattern, to, variant, value));
+        }
+        lines.push("        }".to_string());
+        lines.push("    }".to_string());
+        lines.push("}".to_string());
+    }
     lines.join("\n")
 }
 
-/// Struct 定義を Rust の struct に変換する
-pub fn transpile_struct_rust(struct_def: &StructDef) -> String {
+/// Struct 定義を Rust の struct に変換する（derive_serde なら JSON のキーはフィールド名）
+pub fn transpile_struct_rust(struct_def: &StructDef, derive_serde: bool) -> String {
     let mut lines = Vec::new();
     lines.push(format!("/// Verified Struct: {}", struct_def.name));
-    lines.push(format!("#[derive(Debug, Clone)]"));
+    if let Some(invariant) = &struct_def.invariant {
+        lines.push(format!("/// Invariant: {}", invariant));
+    }
+    if derive_serde {
+        lines.push("#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]".to_string());
+    } else {
+        lines.push("#[derive(Debug, Clone)]".to_string());
+    }
     // Generics: 型パラメータがある場合は <T, U> を付与
     let type_params_str = if struct_def.type_params.is_empty() {
         String::new()
@@ -113,66 +196,145 @@ pub fn transpile_impl_rust(impl_def: &ImplDef) -> String {
     lines.join("\n")
 }
 
-pub fn transpile_to_rust(atom: &Atom) -> String {
-    // 引数の型を精緻型のベース型からマッピング (Type System 2.0)
-    // ref パラメータは &T に、ref mut は &mut T に、consume はそのまま T（所有権移動）に変換
+/// 引数の型を精緻型のベース型からマッピング (Type System 2.0)
+/// ref パラメータは &T に、ref mut は &mut T に、consume はそのまま T（所有権移動）に変換
+/// Str はもともと借用（&str）なので ref を重ねない。Enum 型のパラメータは生成した enum で受け取る。
+/// 本体が書き換える（`ref mut x` で渡す・代入する）値渡しのパラメータは `mut x` で受け取る
+fn format_params_rust(atom: &Atom, module_env: &ModuleEnv, body: Option<&Expr>) -> String {
+    let mut mutated = HashSet::new();
+    if let Some(body) = body {
+        body.walk(|e| {
+            match e {
+                Expr::RefArg { expr, mutable: true } => {
+                    if let Expr::Variable(v) = expr.as_ref() {
+                        mutated.insert(v.as_str());
+                    }
+                }
+                Expr::Assign { var, .. } => { mutated.insert(var.as_str()); }
+                _ => {}
+            }
+            true
+        });
+    }
     let params: Vec<String> = atom.params.iter()
         .map(|p| {
-            let rust_type = map_type_rust(p.type_name.as_deref());
-            if p.is_ref_mut {
+            let type_name = param_type_name(p, module_env);
+            let rust_type = param_enum(p, module_env).map_or_else(|| map_type_rust(type_name), |e| e.name.clone());
+            if type_name == Some("Str") {
+                format!("{}: {}", p.name, rust_type)
+            } else if p.is_ref_mut {
                 format!("{}: &mut {}", p.name, rust_type)
             } else if p.is_ref {
                 format!("{}: &{}", p.name, rust_type)
+            } else if mutated.contains(p.name.as_str()) {
+                format!("mut {}: {}", p.name, rust_type)
             } else {
                 format!("{}: {}", p.name, rust_type)
             }
         })
         .collect();
-    let params_str = params.join(", ");
+    params.join(", ")
+}
 
-    let body_ast = parse_expression(&atom.body_expr);
-    let body = format_expr_rust(&body_ast);
+pub fn transpile_to_rust(atom: &Atom, ctx: &TranspileCtx) -> Result<String, Unsupported> {
+    let body_ast = parse_body(atom)?;
+    let params_str = format_params_rust(atom, ctx.module_env, Some(&body_ast));
+    let mut body = format_expr_rust(&body_ast, ctx)?;
 
-    // 戻り値型の推論: ボディに f64 リテラルや f64 パラメータが含まれていれば f64
-    let has_float_param = atom.params.iter().any(|p| {
-        p.type_name.as_deref()
-            .map(|t| resolve_base_type(t) == "f64")
-            .unwrap_or(false)
-    });
-    let return_type = if has_float_param || body_contains_float(&body_ast) { "f64" } else { "i64" };
+    // Str を返す atom は借用（&str）を呼び出し側へ返せないので String として所有権を渡す
+    let return_type = return_type_rust(atom, ctx.module_env);
+    if return_type == "String" {
+        body = format!("String::from({})", body);
+    }
+
+    // [build] runtime_checks: requires は入口の assert!、ensures は本体の値を result に束縛してから assert! する
+    let checks = RuntimeChecks::new(ctx, format_expr_rust);
+    if !checks.is_empty() {
+        let mut lines = checks.omitted_comments("//");
+        for check in &checks.requires {
+            lines.push(format!("assert!({}, \"{{}}\", {:?});", strip_parens(&check.condition), check.message));
+        }
+        if checks.ensures.is_empty() {
+            lines.push(body);
+        } else {
+            lines.push(format!("let result: {} = {};", return_type, body));
+            for check in &checks.ensures {
+                lines.push(format!("assert!({}, \"{{}}\", {:?});", strip_parens(&check.condition), check.message));
+            }
+            lines.push("result".to_string());
+        }
+        body = lines.join("\n    ");
+    }
 
     let async_keyword = if atom.is_async { "async " } else { "" };
+    Ok(format!(
+        "/// Verified Atom: {}\n{}/// Requires: {}\n/// Ensures: {}\npub {}fn {}({}) -> {} {{\n    {}\n}}",
+        atom.name, doc_where(atom, "///") + &doc_elements(atom, ctx.module_env, "///"), doc_contract(&atom.requires_raw, "///"), doc_contract(&atom.ensures_raw, "///"), async_keyword, atom.name, params_str, return_type, body
+    ))
+}
+
+/// 未対応構文を含む atom の代替定義。
+/// CompileError: compile_error! により生成コードのビルドを意図的に失敗させる
+/// AllowPartial: todo! で実行時まで失敗を遅らせる TODO スタブ
+pub fn unsupported_stub_rust(atom: &Atom, module_env: &ModuleEnv, unsupported: &Unsupported, policy: UnsupportedPolicy) -> String {
+    let message = unsupported.message(TargetLanguage::Rust);
+    let body = match policy {
+        UnsupportedPolicy::CompileError => format!("compile_error!(\"{} (atom '{}')\")", message, atom.name),
+        UnsupportedPolicy::AllowPartial => format!("// TODO(mumei): {}\n    todo!(\"{} (atom '{}')\")", unsupported.reason, message, atom.name),
+    };
+    let async_keyword = if atom.is_async { "async " } else { "" };
+    let return_type = return_type_rust(atom, module_env);
     format!(
-        "/// Verified Atom: {}\n/// Requires: {}\n/// Ensures: {}\npub {}fn {}({}) -> {} {{\n    {}\n}}",
-        atom.name, atom.requires, atom.ensures, async_keyword, atom.name, params_str, return_type, body
+        "/// Verified Atom: {}\n{}/// Requires: {}\n/// Ensures: {}\n/// UNSUPPORTED: {} ({})\npub {}fn {}({}) -> {} {{\n    {}\n}}",
+        atom.name, doc_where(atom, "///") + &doc_elements(atom, module_env, "///"), doc_contract(&atom.requires_raw, "///"), doc_contract(&atom.ensures_raw, "///"), unsupported.construct, unsupported.reason,
+        async_keyword, atom.name, format_params_rust(atom, module_env, None), return_type, body
     )
 }
 
-/// AST に f64 リテラルが含まれるかを再帰的にチェック
-fn body_contains_float(expr: &Expr) -> bool {
-    match expr {
-        Expr::Float(_) => true,
-        Expr::BinaryOp(l, _, r) => body_contains_float(l) || body_contains_float(r),
-        Expr::Block(stmts) => stmts.iter().any(body_contains_float),
-        Expr::Let { value, .. } | Expr::Assign { value, .. } => body_contains_float(value),
-        Expr::IfThenElse { cond, then_branch, else_branch } =>
-            body_contains_float(cond) || body_contains_float(then_branch) || body_contains_float(else_branch),
-        Expr::While { cond, body, .. } => body_contains_float(cond) || body_contains_float(body),
-        Expr::Call(_, args) => args.iter().any(body_contains_float),
-        Expr::Match { target, arms } => body_contains_float(target) || arms.iter().any(|a| body_contains_float(&a.body)),
-        Expr::Acquire { body, .. } | Expr::Async { body } => body_contains_float(body),
-        Expr::Await { expr } => body_contains_float(expr),
-        _ => false,
+/// 戻り値型（`-> Point` なら構造体、それ以外は本体の値の種類 crate::ast::result_kind から決める）
+/// Variant の構築（`Shape::Circle(r)` / `Shape::Empty`）。再帰フィールドの値は Box に包む
+fn format_variant_rust(enum_def: &EnumDef, variant: &EnumVariant, args: &[String]) -> String {
+    if args.is_empty() {
+        return format!("{}::{}", enum_def.name, variant.name);
     }
+    let fields: Vec<String> = variant.fields.iter().zip(args)
+        .map(|(field, arg)| if is_recursive_field(enum_def, field) { format!("Box::new({})", arg) } else { arg.clone() })
+        .collect();
+    format!("{}::{}({})", enum_def.name, variant.name, fields.join(", "))
+}
+
+fn return_type_rust(atom: &Atom, module_env: &ModuleEnv) -> String {
+    if let Some(sdef) = crate::ast::struct_return(atom, module_env) {
+        return sdef.name.clone();
+    }
+    if let Some(enum_def) = return_enum(atom, module_env) {
+        return enum_def.name.clone();
+    }
+    match crate::ast::result_kind(atom, module_env) {
+        ResultKind::Int => "i64",
+        ResultKind::Float => "f64",
+        ResultKind::Bool => "bool",
+        ResultKind::Str => "String",
+    }.to_string()
 }
 
 fn map_type_rust(type_name: Option<&str>) -> String {
+    // nullable（`T?` / Option<T>）は Rust の Option にそのまま対応させる
+    if let Some(inner) = type_name.and_then(crate::ast::option_inner_type) {
+        return format!("Option<{}>", map_type_rust(Some(&inner)));
+    }
+    // 配列（`[T]`）は借用スライスで受け取る
+    if let Some(elem) = type_name.and_then(crate::ast::slice_elem_type) {
+        return format!("&[{}]", map_type_rust(Some(&elem)));
+    }
     match type_name {
         Some(name) => {
             let base = resolve_base_type(name);
             match base.as_str() {
                 "f64" => "f64".to_string(),
                 "u64" => "u64".to_string(),
+                "Str" => "&str".to_string(),
+                "bool" => "bool".to_string(),
                 _ => "i64".to_string(),
             }
         },
@@ -185,79 +347,116 @@ fn strip_parens(s: &str) -> &str {
     if s.starts_with('(') && s.ends_with(')') { &s[1..s.len()-1] } else { s }
 }
 
-fn format_expr_rust(expr: &Expr) -> String {
-    match expr {
+fn format_expr_rust(expr: &Expr, ctx: &TranspileCtx) -> Result<String, Unsupported> {
+    let code = match expr {
+        // i64::MIN は符号付きリテラルの境界値のため、意図が明確な定数名で出力する
+        Expr::Number(n) if *n == i64::MIN => "i64::MIN".to_string(),
         Expr::Number(n) => n.to_string(),
         Expr::Float(f) => {
             // Rustのリテラルとして明確にするため、.0を保証
             let s = f.to_string();
             if s.contains('.') { s } else { format!("{}.0", s) }
         },
-        Expr::Variable(v) => v.clone(),
+        // Debug 表示は Rust の文字列リテラルとしてそのまま有効なエスケープになる
+        Expr::StringLit(s) => format!("{:?}", s),
+        Expr::Bool(b) => b.to_string(),
+        // unit variant の構築は Enum 名で修飾する
+        Expr::Variable(v) => match ctx.variant_constructor(v) {
+            Some((enum_def, variant)) => format_variant_rust(enum_def, variant, &[]),
+            None => v.clone(),
+        },
         Expr::ArrayAccess(name, idx) => {
             // インデックスは常に usize にキャスト
-            format!("{}[{} as usize]", name, format_expr_rust(idx))
+            format!("{}[{} as usize]", name, format_expr_rust(idx, ctx)?)
         },
 
+        // 理論拡張（crate::theory）の組み込み関数
+        Expr::Call(name, args) if ctx.is_intrinsic(name) => super::transpile_intrinsic(name, args, TargetLanguage::Rust, ctx, format_expr_rust)?,
+
         Expr::Call(name, args) => {
-            let args_str: Vec<String> = args.iter().map(format_expr_rust).collect();
+            let args_str = args.iter().map(|a| format_expr_rust(a, ctx)).collect::<Result<Vec<_>, _>>()?;
             match name.as_str() {
                 "sqrt" => {
                     // Rustでは f64 のメソッドとして呼び出す。整数ならキャストが必要。
                     format!("(({}) as f64).sqrt()", args_str.join(", "))
                 },
+                "approx_eq" => super::transpile_approx_eq(TargetLanguage::Rust, &args_str)?,
                 "len" => format!("{}.len() as i64", args_str.join(", ")),
-                _ => format!("{}({})", name, args_str.join(", ")),
+                "is_some" | "is_none" if ctx.nullable_arg(args).is_some() => format!("{}.{}()", args_str[0], name),
+                // 値アクセスは None なら panic（検証済みなら到達しない）
+                "value" if ctx.nullable_arg(args).is_some() => {
+                    format!("{}.expect(\"mumei: '{}' is None\")", args_str[0], args_str[0])
+                },
+                _ => match ctx.variant_constructor(name) {
+                    Some((enum_def, variant)) => format_variant_rust(enum_def, variant, &args_str),
+                    None => format!("{}({})", name, args_str.join(", ")),
+                },
             }
         },
 
-        Expr::BinaryOp(l, op, r) => {
-            let op_str = match op {
-                Op::Add => "+", Op::Sub => "-", Op::Mul => "*", Op::Div => "/",
-                Op::Eq => "==", Op::Neq => "!=", Op::Gt => ">", Op::Lt => "<",
-                Op::Ge => ">=", Op::Le => "<=", Op::And => "&&", Op::Or => "||",
-                Op::Implies => "/* implies */",
-            };
-            format!("({} {} {})", format_expr_rust(l), op_str, format_expr_rust(r))
+        Expr::BinaryOp(..) => {
+            // 左に連なる連鎖は左端から順に組み立て、連鎖の長さぶん再帰しない。
+            // 含意 `a => b` は `(!(a) || b)` にする（開き括弧は外側の演算子から順に並ぶ）
+            let (leaf, nodes) = expr.left_spine();
+            let mut code: String = nodes.iter().rev()
+                .map(|node| if matches!(node, Expr::BinaryOp(_, Op::Implies, _)) { "(!(" } else { "(" })
+                .collect();
+            code.push_str(&format_expr_rust(leaf, ctx)?);
+            for node in nodes {
+                if let Expr::BinaryOp(_, op, r) = node {
+                    if matches!(op, Op::Implies) {
+                        code.push(')');
+                    }
+                    let op_str = match op {
+                        Op::Add => "+", Op::Sub => "-", Op::Mul => "*", Op::Div => "/",
+                        Op::Eq => "==", Op::Neq => "!=", Op::Gt => ">", Op::Lt => "<",
+                        Op::Ge => ">=", Op::Le => "<=", Op::And => "&&", Op::Or => "||",
+                        Op::Implies => "||",
+                    };
+                    code.push_str(&format!(" {} {})", op_str, format_expr_rust(r, ctx)?));
+                }
+            }
+            code
         },
 
         Expr::IfThenElse { cond, then_branch, else_branch } => {
             format!(
                 "if {} {{ {} }} else {{ {} }}",
-                format_expr_rust(cond),
-                format_expr_rust(then_branch),
-                format_expr_rust(else_branch)
+                format_expr_rust(cond, ctx)?,
+                format_expr_rust(then_branch, ctx)?,
+                format_expr_rust(else_branch, ctx)?
             )
         },
 
         Expr::While { cond, invariant, decreases, body } => {
-            let cond_str = format_expr_rust(cond);
-            let dec_comment = decreases.as_ref()
-                .map(|d| format!(" decreases: {}", format_expr_rust(d)))
-                .unwrap_or_default();
+            let cond_str = format_expr_rust(cond, ctx)?;
+            let dec_comment = match decreases {
+                Some(d) => format!(" decreases: {}", format_expr_rust(d, ctx)?),
+                None => String::new(),
+            };
             format!(
                 "{{ // invariant: {}{}\n        while {} {{ {} }} \n    }}",
-                format_expr_rust(invariant),
+                format_expr_rust(invariant, ctx)?,
                 dec_comment,
                 strip_parens(&cond_str),
-                format_expr_rust(body)
+                format_expr_rust(body, ctx)?
             )
         },
 
         Expr::Let { var, value } => {
-            let val_str = format_expr_rust(value);
+            let val_str = format_expr_rust(value, ctx)?;
             format!("let mut {} = {};", var, strip_parens(&val_str))
         },
 
         Expr::Assign { var, value } => {
-            let val_str = format_expr_rust(value);
+            let val_str = format_expr_rust(value, ctx)?;
             format!("{} = {};", var, strip_parens(&val_str))
         },
 
         Expr::Block(stmts) => {
             let mut lines = Vec::new();
             for (i, stmt) in stmts.iter().enumerate() {
-                let s = format_expr_rust(stmt);
+                let s = format_expr_rust(stmt, ctx)?;
                 if i == stmts.len() - 1 {
                     lines.push(strip_parens(&s).to_string());
                 } else {
@@ -272,57 +471,174 @@ fn format_expr_rust(expr: &Expr) -> String {
         },
 
         Expr::StructInit { type_name, fields } => {
-            let field_strs: Vec<String> = fields.iter()
-                .map(|(name, expr)| format!("{}: {}", name, format_expr_rust(expr)))
-                .collect();
+            let field_strs = fields.iter()
+                .map(|(name, expr)| Ok(format!("{}: {}", name, format_expr_rust(expr, ctx)?)))
+                .collect::<Result<Vec<_>, Unsupported>>()?;
             format!("{} {{ {} }}", type_name, field_strs.join(", "))
         },
 
         Expr::FieldAccess(expr, field) => {
-            format!("{}.{}", format_expr_rust(expr), field)
+            format!("{}.{}", format_expr_rust(expr, ctx)?, field)
         },
 
         Expr::Match { target, arms } => {
-            let target_str = format_expr_rust(target);
-            let arms_str: Vec<String> = arms.iter().map(|arm| {
-                let pat = format_pattern_rust(&arm.pattern);
-                let guard = arm.guard.as_ref()
-                    .map(|g| format!(" if {}", format_expr_rust(g)))
-                    .unwrap_or_default();
-                let body = format_expr_rust(&arm.body);
-                format!("{}{} => {}", pat, guard, body)
-            }).collect();
+            let target_enum = super::match_target_enum(target, ctx);
+            let planned = super::plan_arms(arms, target_enum, ctx.module_env)?;
+            let mut target_str = format_expr_rust(target, ctx)?;
+            if let (Some(enum_def), Expr::Variable(name)) = (target_enum, target.as_ref()) {
+                // ref パラメータの Enum は参照なので、Copy な（再帰しない）Enum だけ値として照合する
+                if ctx.borrowed_params.contains_key(name) {
+                    if enum_def.is_recursive {
+                        return Err(Unsupported::new("enum variant pattern", "a borrowed recursive enum cannot be matched by value"));
+                    }
+                    target_str = format!("*{}", name);
+                }
+            }
+            let mut next_box = 0;
+            let mut arms_str = Vec::new();
+            for p in &planned {
+                // 再帰フィールドの束縛は Box<T> なので、使う側では参照外しした値にする
+                let boxed: Vec<&String> = p.plan.bindings.iter()
+                    .filter(|(_, path)| path.last().map_or(false, |step| step.recursive))
+                    .map(|(var, _)| var)
+                    .collect();
+                let unbox = |e: &Expr| boxed.iter()
+                    .fold(e.clone(), |e, var| crate::verification::substitute_bound(&e, var, &Expr::Variable(format!("(*{})", var))));
+                let mut boxes = Vec::new();
+                let pat = format_pattern_rust(&p.arm.pattern, target_enum, ctx.module_env, true, &mut boxes, &mut next_box);
+                let mut conds = match target_enum {
+                    Some(e) => boxed_conditions_rust(&boxes, e, ctx.module_env, "*", &mut next_box),
+                    None => Vec::new(),
+                };
+                if let Some(guard) = &p.arm.guard {
+                    // Box の中で束縛する変数は、ガードの時点ではまだ取り出していない
+                    let inside_box = p.plan.bindings.iter()
+                        .filter(|(_, path)| path[..path.len().saturating_sub(1)].iter().any(|step| step.recursive));
+                    for (var, _) in inside_box {
+                        if crate::verification::substitute_bound(guard, var, &Expr::Variable(String::new())) != **guard {
+                            return Err(Unsupported::new("nested enum pattern", "a guard cannot use variables bound inside a recursive (boxed) field"));
+                        }
+                    }
+                    conds.push(format_expr_rust(&unbox(guard), ctx)?);
+                }
+                let guard = if conds.is_empty() { String::new() } else { format!(" if {}", conds.join(" && ")) };
+                let mut body = format_expr_rust(&unbox(&p.arm.body), ctx)?;
+                if let Some(e) = target_enum {
+                    body = unboxed_body_rust(body, &boxes, e, ctx.module_env, &mut next_box);
+                }
+                arms_str.push(format!("{}{} => {}", pat, guard, body));
+            }
+            if !super::arms_exhaustive(arms, target_enum) {
+                arms_str.push(format!("_ => unreachable!({:?})", super::UNREACHABLE_ARM));
+            }
             format!("match {} {{ {} }}", target_str, arms_str.join(", "))
         },
 
-        Expr::Acquire { resource, body } => {
-            // Rust: スコープガードパターン（MutexGuard の RAII）
-            let body_str = format_expr_rust(body);
-            format!("{{\n        let _guard_{r} = {r}.lock().unwrap();\n        {body}\n    }}", r = resource, body = body_str)
+        Expr::Acquire { .. } => {
+            // resource は Mutex の static 宣言として出力されていないため、lock() の対象が存在しない
+            return Err(Unsupported::new("acquire", "resources are not lowered to Mutex declarations"));
         },
         Expr::Async { body } => {
-            let body_str = format_expr_rust(body);
+            let body_str = format_expr_rust(body, ctx)?;
             format!("async {{ {} }}", body_str)
         },
         Expr::Await { expr } => {
-            let expr_str = format_expr_rust(expr);
+            // async atom の呼び出しは Future を返し、await で値になる。同期 atom の呼び出しは .await 不要
+            if let Expr::Call(name, _) = expr.as_ref() {
+                if !ctx.is_async_callee(name) {
+                    return format_expr_rust(expr, ctx);
+                }
+            }
+            let expr_str = format_expr_rust(expr, ctx)?;
             format!("{}.await", expr_str)
         },
-    }
+        Expr::Assume { cond, justification, .. } => {
+            // assume は検証時の仮定。リリースビルドでは消え、デバッグビルドでのみ成り立つことを確かめる
+            format!("debug_assert!({}, \"assume: {{}}\", {:?});", strip_parens(&format_expr_rust(cond, ctx)?), justification)
+        },
+        // ref パラメータは &T、ref mut は &mut T。自身の ref パラメータはすでに参照なのでそのまま渡す
+        Expr::RefArg { expr, mutable } => {
+            let arg = format_expr_rust(expr, ctx)?;
+            match expr.as_ref() {
+                Expr::Variable(v) if ctx.borrowed_params.contains_key(v) => arg,
+                _ if *mutable => format!("&mut {}", arg),
+                _ => format!("&{}", arg),
+            }
+        },
+    };
+    Ok(code)
 }
 
-fn format_pattern_rust(pattern: &crate::parser::Pattern) -> String {
+/// match のパターンを Rust のパターンにする。Enum 値の Variant は `Shape::Circle(r)` と修飾し、
+/// タグ番号で扱う値（整数のターゲット・payload の Enum 型フィールド）の Variant は定義順の番号にする。
+/// Box（再帰フィールド）の中身は直接照合できないので、中身に条件があれば Box を `__b{n}` で束縛して
+/// boxes に積む（ガードの matches! と本体の入れ子の match に展開する）。
+/// bind が false（matches! の中）なら変数は `_`、Box は `ref` で束縛する。パターンは super::plan_arms で検査済み
+fn format_pattern_rust<'p>(
+    pattern: &'p Pattern,
+    enum_def: Option<&EnumDef>,
+    module_env: &ModuleEnv,
+    bind: bool,
+    boxes: &mut Vec<(String, &'p Pattern)>,
+    next_box: &mut usize,
+) -> String {
     match pattern {
-        crate::parser::Pattern::Wildcard => "_".to_string(),
-        crate::parser::Pattern::Literal(n) => n.to_string(),
-        crate::parser::Pattern::Variable(name) => name.clone(),
-        crate::parser::Pattern::Variant { variant_name, fields } => {
-            if fields.is_empty() {
-                variant_name.clone()
-            } else {
-                let field_strs: Vec<String> = fields.iter().map(format_pattern_rust).collect();
-                format!("{}({})", variant_name, field_strs.join(", "))
-            }
+        Pattern::Wildcard => "_".to_string(),
+        Pattern::Literal(n) => n.to_string(),
+        Pattern::Variable(name) => if bind { name.clone() } else { "_".to_string() },
+        Pattern::Variant { variant_name, fields } => match enum_def {
+            Some(e) => {
+                let field_types = e.variants.iter().find(|v| &v.name == variant_name).map(|v| v.fields.as_slice()).unwrap_or_default();
+                let mut field_strs = Vec::new();
+                for (field, field_type) in fields.iter().zip(field_types) {
+                    let recursive = is_recursive_field(e, field_type);
+                    if recursive && matches!(field, Pattern::Variant { .. }) {
+                        let name = format!("__b{}", next_box);
+                        *next_box += 1;
+                        field_strs.push(if bind { name.clone() } else { format!("ref {}", name) });
+                        boxes.push((name, field));
+                    } else {
+                        field_strs.push(format_pattern_rust(field, if recursive { Some(e) } else { None }, module_env, bind, boxes, next_box));
+                    }
+                }
+                if field_strs.is_empty() {
+                    format!("{}::{}", e.name, variant_name)
+                } else {
+                    format!("{}::{}({})", e.name, variant_name, field_strs.join(", "))
+                }
+            },
+            None => module_env.find_enum_by_variant(variant_name)
+                .and_then(|e| e.variants.iter().position(|v| &v.name == variant_name))
+                .map_or_else(|| variant_name.clone(), |tag| tag.to_string()),
         },
     }
 }
+
+/// Box に束縛した中身がパターンに合うかの条件（deref は Box の束縛から中身への参照外し）
+fn boxed_conditions_rust(boxes: &[(String, &Pattern)], enum_def: &EnumDef, module_env: &ModuleEnv, deref: &str, next_box: &mut usize) -> Vec<String> {
+    let mut conds = Vec::new();
+    for (name, sub) in boxes {
+        let mut inner = Vec::new();
+        let pat = format_pattern_rust(sub, Some(enum_def), module_env, false, &mut inner, next_box);
+        // matches! の中の Box は ref で束縛するので、参照と Box の2段を外す
+        let inner_conds = boxed_conditions_rust(&inner, enum_def, module_env, "**", next_box);
+        if inner_conds.is_empty() {
+            conds.push(format!("matches!({}{}, {})", deref, name, pat));
+        } else {
+            conds.push(format!("matches!({}{}, {} if {})", deref, name, pat, inner_conds.join(" && ")));
+        }
+    }
+    conds
+}
+
+/// Box の中のパターン変数を、本体の外側から順に入れ子の match で取り出す（条件はガードで確認済み）
+fn unboxed_body_rust(body: String, boxes: &[(String, &Pattern)], enum_def: &EnumDef, module_env: &ModuleEnv, next_box: &mut usize) -> String {
+    let mut body = body;
+    for (name, sub) in boxes.iter().rev() {
+        let mut inner = Vec::new();
+        let pat = format_pattern_rust(sub, Some(enum_def), module_env, true, &mut inner, next_box);
+        let inner_body = unboxed_body_rust(body, &inner, enum_def, module_env, next_box);
+        body = format!("match *{} {{ {} => {}, _ => unreachable!({:?}) }}", name, pat, inner_body, super::UNREACHABLE_ARM);
+    }
+    body
+}
@@ -1,16 +1,20 @@
-use crate::parser::{Expr, Op, Atom, ImportDecl, EnumDef, StructDef, TraitDef, ImplDef, parse_expression};
+use crate::parser::{Expr, Op, Atom, Param, Item, ImportDecl, EnumDef, EnumVariant, RefinedType, StructDef, TraitDef, ImplDef};
+use crate::ast::ResultKind;
+use crate::verification::ModuleEnv;
+use super::{RuntimeChecks, TranspileCtx, Unsupported, UnsupportedPolicy, TargetLanguage, FieldStep, PatternTest, doc_contract, doc_elements, doc_where, is_recursive_field, param_enum, parse_body, param_type_name, return_enum, negated};
 
 /// 型名をベース型に解決する（transpiler ローカル版）
 fn resolve_base_type(name: &str) -> String {
     name.to_string()
 }
 
-/// import 宣言から TypeScript のモジュールヘッダーを生成する
-/// 例: import { add } from "./lib/math";
+/// import 宣言から TypeScript のモジュールヘッダーを生成する（行の辞書順、重複なし）
+/// 例: import * as math from "./lib/math.js";
+/// ESM では相対 import に拡張子が必要なため、生成先の .js を指定する（tsc は .ts / .d.ts に解決する）
 pub fn transpile_module_header_ts(imports: &[ImportDecl]) -> String {
     let mut lines = Vec::new();
     for import in imports {
-        let module_path = import.path.trim_end_matches(".mm");
+        let module_path = format!("{}.js", import.path.trim_end_matches(".mm"));
         if let Some(alias) = &import.alias {
             lines.push(format!("import * as {} from \"{}\";", alias, module_path));
         } else {
@@ -20,6 +24,8 @@ pub fn transpile_module_header_ts(imports: &[ImportDecl]) -> String {
             lines.push(format!("import * as {} from \"{}\";", mod_name, module_path));
         }
     }
+    lines.sort();
+    lines.dedup();
     if !lines.is_empty() {
         lines.push(String::new()); // 空行で区切り
     }
@@ -27,11 +33,21 @@ pub fn transpile_module_header_ts(imports: &[ImportDecl]) -> String {
 }
 
 fn map_type_ts(type_name: Option<&str>) -> String {
+    // nullable（`T?` / Option<T>）は null との union で表す
+    if let Some(inner) = type_name.and_then(crate::ast::option_inner_type) {
+        return format!("{} | null", map_type_ts(Some(&inner)));
+    }
+    // 配列（`[T]`）は number[] で表す
+    if let Some(elem) = type_name.and_then(crate::ast::slice_elem_type) {
+        return format!("{}[]", map_type_ts(Some(&elem)));
+    }
     match type_name {
         Some(name) => {
             let base = resolve_base_type(name);
             match base.as_str() {
                 "f64" | "i64" | "u64" => "number".to_string(),
+                "Str" => "string".to_string(),
+                "bool" => "boolean".to_string(),
                 _ => "number".to_string(),
             }
         },
@@ -39,46 +55,83 @@ fn map_type_ts(type_name: Option<&str>) -> String {
     }
 }
 
-/// Enum 定義を TypeScript の const enum + discriminated union に変換する（Generics 対応）
+/// Enum 定義を TypeScript の discriminated union に変換する（Generics 対応）。
+/// `enum` / `const enum` は実行時のオブジェクトを生成して tree shaking を妨げ、型除去だけでも実行できないため、
+/// バリアントは文字列リテラルの `kind` で判別する型と、バリアントごとの構築関数（enum_constructors_ts）として出力する。
+/// 再帰フィールド（`Cons(i64, Self)`）は Enum 自身の型のネストしたオブジェクトとして表す。
 pub fn transpile_enum_ts(enum_def: &EnumDef) -> String {
-    let mut lines = Vec::new();
-    lines.push(format!("/** Verified Enum: {} */", enum_def.name));
-    // Generics: 型パラメータがある場合は discriminated union の型に <T> を付与
-    let type_params_str = if enum_def.type_params.is_empty() {
+    format!("{}\n{}", enum_type_ts(enum_def), enum_constructors_ts(enum_def, false))
+}
+
+/// Enum の型パラメータ（`<T>`、なければ空文字列）
+fn enum_type_params_ts(enum_def: &EnumDef) -> String {
+    if enum_def.type_params.is_empty() {
         String::new()
     } else {
         format!("<{}>", enum_def.type_params.join(", "))
-    };
-    lines.push(format!("export const enum {}Tag {{", enum_def.name));
-    for variant in &enum_def.variants {
-        lines.push(format!("    {},", variant.name));
     }
-    lines.push("}".to_string());
+}
 
-    // Discriminated union 型も生成
-    let mut union_members = Vec::new();
-    for (i, variant) in enum_def.variants.iter().enumerate() {
-        if variant.fields.is_empty() {
-            union_members.push(format!("{{ tag: {}Tag.{} }}", enum_def.name, variant.name));
+/// Variant のフィールドの型（`field_{i}: T`）
+fn variant_fields_ts(enum_def: &EnumDef, variant: &EnumVariant) -> Vec<String> {
+    variant.fields.iter().enumerate()
+        .map(|(fi, f)| if is_recursive_field(enum_def, f) {
+            format!("field_{}: {}{}", fi, enum_def.name, enum_type_params_ts(enum_def))
         } else {
-            let field_types: Vec<String> = variant.fields.iter().enumerate()
-                .map(|(fi, f)| format!("field_{}: {}", fi, map_type_ts(Some(f.as_str()))))
-                .collect();
-            union_members.push(format!(
-                "{{ tag: {}Tag.{}; {} }}",
-                enum_def.name, variant.name, field_types.join("; ")
-            ));
-        }
-        let _ = i;
-    }
-    lines.push(format!("export type {}{} = {};", enum_def.name, type_params_str, union_members.join(" | ")));
-    lines.join("\n")
+            format!("field_{}: {}", fi, map_type_ts(Some(f.as_str())))
+        })
+        .collect()
+}
+
+/// discriminated union の型の定義
+fn enum_type_ts(enum_def: &EnumDef) -> String {
+    let union_members: Vec<String> = enum_def.variants.iter()
+        .map(|variant| match variant_fields_ts(enum_def, variant) {
+            fields if fields.is_empty() => format!("{{ kind: \"{}\" }}", variant.name),
+            fields => format!("{{ kind: \"{}\"; {} }}", variant.name, fields.join("; ")),
+        })
+        .collect();
+    format!(
+        "/** Verified Enum: {} */\nexport type {}{} = {};",
+        enum_def.name, enum_def.name, enum_type_params_ts(enum_def), union_members.join(" | ")
+    )
+}
+
+/// Variant の構築関数の名前（`Shape` の `Circle` なら `shapeCircle`）。
+/// Variant 名そのままでは `Error` などの組み込みの名前や、別の Enum の同名の Variant と衝突する
+fn variant_constructor_name_ts(enum_def: &EnumDef, variant: &EnumVariant) -> String {
+    let mut chars = enum_def.name.chars();
+    let head: String = chars.next().map(|c| c.to_lowercase().collect()).unwrap_or_default();
+    format!("{}{}{}", head, chars.as_str(), variant.name)
+}
+
+/// Variant ごとの構築関数（`export function shapeCircle(field_0: number): Shape`）。
+/// declare なら .d.ts 用の宣言だけを出力する
+fn enum_constructors_ts(enum_def: &EnumDef, declare: bool) -> String {
+    let type_params = enum_type_params_ts(enum_def);
+    enum_def.variants.iter()
+        .map(|variant| {
+            let signature = format!(
+                "function {}{}({}): {}{}",
+                variant_constructor_name_ts(enum_def, variant), type_params, variant_fields_ts(enum_def, variant).join(", "), enum_def.name, type_params
+            );
+            if declare {
+                return format!("export declare {};", signature);
+            }
+            let fields: String = (0..variant.fields.len()).map(|fi| format!(", field_{}", fi)).collect();
+            format!("export {} {{ return {{ kind: \"{}\"{} }}; }}", signature, variant.name, fields)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
 }
 
 /// Struct 定義を TypeScript の interface に変換する（Generics 対応）
 pub fn transpile_struct_ts(struct_def: &StructDef) -> String {
     let mut lines = Vec::new();
-    lines.push(format!("/** Verified Struct: {} */", struct_def.name));
+    match &struct_def.invariant {
+        Some(invariant) => lines.push(format!("/**\n * Verified Struct: {}\n * Invariant: {}\n */", struct_def.name, invariant)),
+        None => lines.push(format!("/** Verified Struct: {} */", struct_def.name)),
+    }
     // Generics: 型パラメータがある場合は <T, U> を付与
     let type_params_str = if struct_def.type_params.is_empty() {
         String::new()
@@ -97,6 +150,114 @@ pub fn transpile_struct_ts(struct_def: &StructDef) -> String {
     lines.join("\n")
 }
 
+/// 精緻型を TypeScript のブランド型（`type Nat = number & { readonly __brand: "Nat" }`）と、
+/// 述語を検査してブランドを付ける関数 `toNat` に変換する。`__brand` は型だけの印で実行時には存在しないので、
+/// 素の number は toNat（か検証済みの呼び出し元の型変換）を経由しないと Nat のパラメータに渡せない。
+/// 述語を翻訳できなければ検査せずにブランドを付け、その旨をコメントに残す
+pub fn transpile_refined_type_ts(refined: &RefinedType) -> String {
+    let operand = &refined.operand;
+    let check = match refinement_check_ts(&refined.predicate_raw, operand) {
+        Some(condition) => format!(
+            "if ({}) throw new Error({:?});",
+            negated(&condition), format!("refinement violated: {} requires {}", refined.name, refined.predicate_raw)
+        ),
+        None => format!("// the predicate `{}` cannot be checked at runtime", refined.predicate_raw),
+    };
+    format!(
+        "{}\n\n/** Checks the predicate of {} and brands the value. */\nexport function to{}({}: {}): {} {{\n    {}\n    return {} as {};\n}}",
+        refined_type_alias_ts(refined), refined.name, refined.name, operand, map_type_ts(Some(&refined._base_type)), refined.name, check, operand, refined.name
+    )
+}
+
+/// 精緻型のブランド型の定義
+fn refined_type_alias_ts(refined: &RefinedType) -> String {
+    format!(
+        "/** Verified Refined Type: {} ({} where {}) */\nexport type {} = {} & {{ readonly __brand: \"{}\" }};",
+        refined.name, refined.operand, refined.predicate_raw, refined.name, map_type_ts(Some(&refined._base_type)), refined.name
+    )
+}
+
+/// 精緻型の述語を TypeScript の条件式に翻訳する。演算対象の変数・リテラル・算術・比較・論理演算・
+/// 条件演算子・演算対象の len 以外を含めば None
+fn refinement_check_ts(predicate: &str, operand: &str) -> Option<String> {
+    fn render(expr: &Expr, operand: &str) -> Option<String> {
+        Some(match expr {
+            Expr::Number(n) => n.to_string(),
+            Expr::Float(f) => f.to_string(),
+            Expr::Bool(b) => b.to_string(),
+            Expr::Variable(v) if v == operand => v.clone(),
+            Expr::Call(name, args) if name == "len" && matches!(args.as_slice(), [Expr::Variable(v)] if v == operand) => format!("{}.length", operand),
+            Expr::BinaryOp(l, Op::Implies, r) => format!("({} || {})", negated(&render(l, operand)?), render(r, operand)?),
+            Expr::BinaryOp(l, op, r) => {
+                let op_str = match op {
+                    Op::Add => "+", Op::Sub => "-", Op::Mul => "*", Op::Div => "/",
+                    Op::Eq => "===", Op::Neq => "!==", Op::Gt => ">", Op::Lt => "<",
+                    Op::Ge => ">=", Op::Le => "<=", Op::And => "&&", Op::Or => "||",
+                    Op::Implies => unreachable!(),
+                };
+                format!("({} {} {})", render(l, operand)?, op_str, render(r, operand)?)
+            }
+            Expr::IfThenElse { cond, then_branch, else_branch } => {
+                format!("({} ? {} : {})", render(cond, operand)?, render(then_branch, operand)?, render(else_branch, operand)?)
+            }
+            _ => return None,
+        })
+    }
+    crate::parser::parse_expression(predicate).ok().and_then(|e| render(&e, operand))
+}
+
+/// このバンドルが定義する精緻型（ブランド型で受け渡す）。素の名前で import した精緻型は
+/// import 先のモジュールの型で、このバンドルには定義がないので含めない
+fn local_refined_type<'e>(type_name: &str, module_env: &'e ModuleEnv) -> Option<&'e RefinedType> {
+    if type_name.contains("::") || module_env.item_origins.contains_key(&("type", type_name.to_string())) {
+        return None;
+    }
+    module_env.get_type(type_name)
+}
+
+/// パラメータをブランド型で受け取るなら、その精緻型。
+/// 本体が代入するパラメータは、代入した値が述語を満たすとは限らないのでベース型のままにする
+fn branded_param<'e>(atom: &Atom, param: &Param, module_env: &'e ModuleEnv) -> Option<&'e RefinedType> {
+    let refined = local_refined_type(param.type_name.as_deref()?, module_env)?;
+    (!super::rebinds(&atom.body_expr, &param.name)).then_some(refined)
+}
+
+/// 呼び出し先のブランド型のパラメータへの実引数を、その型に変換する（述語は呼び出し元の検証で証明済み）。
+/// `geo::f(...)` の呼び出し先のブランド型は import 先のモジュールの型（`geo.Nat`）
+fn brand_args_ts(name: &str, args: Vec<String>, module_env: &ModuleEnv) -> Vec<String> {
+    let fqn_name = name.replace('.', "::");
+    let callee = match module_env.get_atom(name).or_else(|| module_env.get_atom(&fqn_name)) {
+        Some(callee) => callee,
+        None => return args,
+    };
+    let alias = fqn_name.rsplit_once("::").map(|(alias, _)| alias);
+    let brand = |param: &Param| match alias {
+        None => branded_param(callee, param, module_env).map(|refined| refined.name.clone()),
+        Some(alias) => param.type_name.as_deref()
+            .filter(|t| module_env.get_type(&format!("{}::{}", alias, t)).is_some() && !super::rebinds(&callee.body_expr, &param.name))
+            .map(|t| format!("{}.{}", alias, t)),
+    };
+    args.into_iter().enumerate()
+        .map(|(i, arg)| match callee.params.get(i).and_then(brand) {
+            Some(brand) => format!("({} as {})", arg, brand),
+            None => arg,
+        })
+        .collect()
+}
+
+/// 値がブランド型（ブランド型のパラメータそのもの、またはそれを返しうる条件式）なら、そのベース型。
+/// let をブランド型で初期化すると変数もブランド型になり、後の代入（`i = i - 1`）が型エラーになる
+fn branded_value_ts(expr: &Expr, ctx: &TranspileCtx) -> Option<String> {
+    match expr {
+        Expr::Variable(v) => ctx.atom.params.iter()
+            .find(|p| &p.name == v)
+            .and_then(|p| branded_param(ctx.atom, p, ctx.module_env))
+            .map(|refined| map_type_ts(Some(&refined._base_type))),
+        Expr::IfThenElse { then_branch, else_branch, .. } => branded_value_ts(then_branch, ctx).or_else(|| branded_value_ts(else_branch, ctx)),
+        _ => None,
+    }
+}
+
 /// Trait 定義を TypeScript の interface に変換する
 pub fn transpile_trait_ts(trait_def: &TraitDef) -> String {
     let mut lines = Vec::new();
@@ -118,7 +279,7 @@ pub fn transpile_trait_ts(trait_def: &TraitDef) -> String {
     lines.join("\n")
 }
 
-/// Impl 定義を TypeScript のクラスに変換する
+/// Impl 定義を TypeScript のオブジェクトリテラルに変換する（副作用のない export const）
 pub fn transpile_impl_ts(impl_def: &ImplDef) -> String {
     let mut lines = Vec::new();
     lines.push(format!("/** impl {} for {} */", impl_def.trait_name, impl_def.target_type));
@@ -130,94 +291,435 @@ pub fn transpile_impl_ts(impl_def: &ImplDef) -> String {
     lines.join("\n")
 }
 
-pub fn transpile_to_ts(atom: &Atom) -> String {
-    // TSでは number (f64/i64) または bigint (u64的な扱い) ですが、
-    // 汎用性を考慮しすべて number として出力します。
-    // ref パラメータは Readonly<T> コメントで論理的な読み取り専用を示す。
-    // ref mut パラメータは @mutable JSDoc で可変参照を示す。
-    // consume パラメータは @consume JSDoc で使用禁止を示す。
-    let params: String = atom.params.iter()
+/// TSでは number (f64/i64) または bigint (u64的な扱い) ですが、
+/// 汎用性を考慮しすべて number として出力します。
+/// ref パラメータは Readonly<T> コメントで論理的な読み取り専用を示す。
+/// ref mut パラメータは @mutable JSDoc で可変参照を示す。
+/// consume パラメータは @consume JSDoc で使用禁止を示す。
+/// Enum 型のパラメータは生成した判別共用体の型、精緻型のパラメータはブランド型（branded_param）で受け取る。
+fn format_params_ts(atom: &Atom, module_env: &ModuleEnv) -> String {
+    atom.params.iter()
         .map(|p| {
+            let ts_type = match (param_enum(p, module_env), branded_param(atom, p, module_env)) {
+                (Some(enum_def), _) => enum_def.name.clone(),
+                (None, Some(refined)) => refined.name.clone(),
+                (None, None) => map_type_ts(param_type_name(p, module_env)),
+            };
             if p.is_ref_mut {
-                format!("/* &mut */ {}: number", p.name)
+                format!("/* &mut */ {}: {}", p.name, ts_type)
             } else if p.is_ref {
-                format!("/* readonly */ {}: number", p.name)
+                format!("/* readonly */ {}: {}", p.name, ts_type)
             } else {
-                format!("{}: number", p.name)
+                format!("{}: {}", p.name, ts_type)
             }
         })
         .collect::<Vec<_>>()
-        .join(", ");
+        .join(", ")
+}
+
+/// 戻り値型（`-> Point` なら構造体の interface、それ以外は本体の値の種類 crate::ast::result_kind から決める）。
+/// async なら Promise で包む
+fn return_type_ts(atom: &Atom, module_env: &ModuleEnv) -> String {
+    let base = match crate::ast::struct_return(atom, module_env) {
+        Some(sdef) => sdef.name.as_str(),
+        None if return_enum(atom, module_env).is_some() => atom.return_type.as_deref().unwrap_or_default(),
+        None => match crate::ast::result_kind(atom, module_env) {
+            ResultKind::Int | ResultKind::Float => "number",
+            ResultKind::Bool => "boolean",
+            ResultKind::Str => "string",
+        },
+    };
+    if atom.is_async { format!("Promise<{}>", base) } else { base.to_string() }
+}
+
+pub fn transpile_to_ts(atom: &Atom, ctx: &TranspileCtx) -> Result<String, Unsupported> {
+    let params = format_params_ts(atom, ctx.module_env);
+    let mut body = format_expr_ts(&super::as_block(parse_body(atom)?), ctx)?;
+
+    // [build] runtime_checks: requires は入口で throw する guard、ensures は本体を即時実行した値 result に対して検査する
+    let checks = RuntimeChecks::new(ctx, format_expr_ts);
+    if !checks.is_empty() {
+        let mut lines = checks.omitted_comments("//");
+        for check in &checks.requires {
+            lines.push(format!("if ({}) throw new Error({:?});", negated(&check.condition), check.message));
+        }
+        if checks.ensures.is_empty() {
+            lines.push(body);
+        } else {
+            let (await_keyword, async_keyword) = if atom.is_async { ("await ", "async ") } else { ("", "") };
+            lines.push(format!("const result = {}({}() => {{\n        {}\n    }})();", await_keyword, async_keyword, body.replace("\n    ", "\n        ")));
+            for check in &checks.ensures {
+                lines.push(format!("if ({}) throw new Error({:?});", negated(&check.condition), check.message));
+            }
+            lines.push("return result;".to_string());
+        }
+        body = lines.join("\n    ");
+    }
 
-    let body = format_expr_ts(&parse_expression(&atom.body_expr));
+    let async_keyword = if atom.is_async { "async " } else { "" };
+    let return_type = return_type_ts(atom, ctx.module_env);
+    Ok(format!(
+        "/**\n * Verified Atom: {}\n{} * Requires: {}\n * Ensures: {}\n */\nexport {}function {}({}): {} {{\n    {}\n}}",
+        atom.name, doc_where(atom, " *") + &doc_elements(atom, ctx.module_env, " *"), doc_contract(&atom.requires_raw, " *"), doc_contract(&atom.ensures_raw, " *"), async_keyword, atom.name, params, return_type, body
+    ))
+}
 
+/// 未対応構文を含む atom の代替定義。
+/// CompileError: 型エラーのない throw に @ts-expect-error を付け、未使用ディレクティブとして tsc を失敗させる
+/// AllowPartial: throw する TODO スタブ
+pub fn unsupported_stub_ts(atom: &Atom, module_env: &ModuleEnv, unsupported: &Unsupported, policy: UnsupportedPolicy) -> String {
+    let message = unsupported.message(TargetLanguage::TypeScript);
+    let directive = match policy {
+        UnsupportedPolicy::CompileError => format!("// @ts-expect-error {}", message),
+        UnsupportedPolicy::AllowPartial => format!("// TODO(mumei): {}", unsupported.reason),
+    };
     let async_keyword = if atom.is_async { "async " } else { "" };
-    let return_type = if atom.is_async { "Promise<number>" } else { "number" };
+    let return_type = return_type_ts(atom, module_env);
     format!(
-        "/**\n * Verified Atom: {}\n * Requires: {}\n * Ensures: {}\n */\n{}function {}({}): {} {{\n    {}\n}}",
-        atom.name, atom.requires, atom.ensures, async_keyword, atom.name, params, return_type, body
+        "/**\n * Verified Atom: {}\n{} * Requires: {}\n * Ensures: {}\n * UNSUPPORTED: {} ({})\n */\nexport {}function {}({}): {} {{\n    {}\n    throw new Error(\"{} (atom '{}')\");\n}}",
+        atom.name, doc_where(atom, " *") + &doc_elements(atom, module_env, " *"), doc_contract(&atom.requires_raw, " *"), doc_contract(&atom.ensures_raw, " *"), unsupported.construct, unsupported.reason,
+        async_keyword, atom.name, format_params_ts(atom, module_env), return_type, directive, message, atom.name
     )
 }
 
-fn format_expr_ts(expr: &Expr) -> String {
-    match expr {
+// =============================================================================
+// 型宣言ファイル（[build.ts] declarations = true で <stem>.d.ts に書き出す）
+// =============================================================================
+
+/// atom の関数シグネチャ宣言（契約は本体と同じく JSDoc に残す）
+pub fn declare_atom_ts(atom: &Atom, module_env: &ModuleEnv) -> String {
+    let return_type = return_type_ts(atom, module_env);
+    format!(
+        "/**\n * Verified Atom: {}\n{} * Requires: {}\n * Ensures: {}\n */\nexport declare function {}({}): {};",
+        atom.name, doc_where(atom, " *") + &doc_elements(atom, module_env, " *"), doc_contract(&atom.requires_raw, " *"), doc_contract(&atom.ensures_raw, " *"), atom.name, format_params_ts(atom, module_env), return_type
+    )
+}
+
+/// impl が生成するオブジェクトの宣言
+pub fn declare_impl_ts(impl_def: &ImplDef) -> String {
+    format!(
+        "/** impl {} for {} */\nexport declare const {}{}: {};",
+        impl_def.trait_name, impl_def.target_type, impl_def.target_type, impl_def.trait_name, impl_def.trait_name
+    )
+}
+
+/// JSON バリデータの宣言（型パラメータを持つ型にはバリデータを出力しない）
+fn declare_validator_ts(name: &str, type_params: &[String]) -> String {
+    if type_params.is_empty() {
+        format!("\nexport declare function parse{}(json: unknown): {};", name, name)
+    } else {
+        String::new()
+    }
+}
+
+/// 精緻型のブランド型と、toX の宣言
+fn declare_refined_type_ts(refined: &RefinedType) -> String {
+    format!(
+        "{}\nexport declare function to{}({}: {}): {};",
+        refined_type_alias_ts(refined), refined.name, refined.operand, map_type_ts(Some(&refined._base_type)), refined.name
+    )
+}
+
+/// Enum の型と、構築関数の宣言
+fn declare_enum_ts(enum_def: &EnumDef) -> String {
+    format!("{}\n{}", enum_type_ts(enum_def), enum_constructors_ts(enum_def, true))
+}
+
+/// バンドルと同じ型情報から .d.ts の内容を生成する。
+/// struct / trait は型だけの定義なのでバンドルと同じものを出し、enum / 精緻型は型と関数の宣言、atom / impl は宣言にする。
+/// validators（`[build.ts] emit_validators`）なら struct / enum の parseX も宣言する。
+pub fn transpile_declarations_ts(imports: &[ImportDecl], items: &[Item], module_env: &ModuleEnv, validators: bool) -> String {
+    let mut out = transpile_module_header_ts(imports);
+    for item in items {
+        let code = match item {
+            Item::StructDef(s) if validators => format!("{}{}", transpile_struct_ts(s), declare_validator_ts(&s.name, &s.type_params)),
+            Item::EnumDef(e) if validators => format!("{}{}", declare_enum_ts(e), declare_validator_ts(&e.name, &e.type_params)),
+            Item::TypeDef(t) => declare_refined_type_ts(t),
+            Item::StructDef(s) => transpile_struct_ts(s),
+            Item::EnumDef(e) => declare_enum_ts(e),
+            Item::TraitDef(t) => transpile_trait_ts(t),
+            Item::ImplDef(i) => declare_impl_ts(i),
+            Item::Atom(a) if a.targets.includes("typescript") => declare_atom_ts(a, module_env),
+            _ => continue,
+        };
+        out.push_str(&code);
+        out.push_str("\n\n");
+    }
+    out
+}
+
+// =============================================================================
+// JSON バリデータ（[build.ts] emit_validators = true で struct / enum ごとに parseX を出力する）
+// =============================================================================
+//
+// JSON.parse の結果を構造的に検査し、型付きの値として返す。JSON の形は Rust の serde 出力
+// （[build.rust] derive_serde = true）と同じ: struct はフィールド名をキーに持つオブジェクト、
+// enum は `kind` にバリアント名、payload を `field_{i}` に持つオブジェクト。
+// - i64 / u64 は整数であること、u64 は加えて非負であることを検査する。生成する型と同じく、
+//   その他の型名（bool やフィールドに使った struct 名など）も i64 として扱う
+// - フィールドの where 句と精緻型の述語は、v と数値リテラルの比較を && でつないだものなら検査する。
+//   それ以外の述語は静的に翻訳できないので検査しない
+// - 型パラメータを持つ struct / enum にはバリデータを出力しない
+
+/// JSON の値の検査条件と、検査済みの値を型付きにする式（どちらも `$` を検査対象の式に置き換えて使う）
+struct JsonCheck {
+    cond: String,
+    convert: String,
+}
+
+/// 型名の値の検査。predicate はフィールドの where 句（演算対象は v）
+fn json_check_ts(type_name: &str, predicate: Option<&str>, module_env: &ModuleEnv) -> JsonCheck {
+    if let Some(inner) = crate::ast::option_inner_type(type_name) {
+        let inner = json_check_ts(&inner, None, module_env);
+        return JsonCheck {
+            cond: format!("$ === null || ({})", inner.cond),
+            convert: format!("$ as {}", map_type_ts(Some(type_name))),
+        };
+    }
+    if let Some(elem) = crate::ast::slice_elem_type(type_name) {
+        let elem = json_check_ts(&elem, None, module_env);
+        return JsonCheck {
+            cond: format!("Array.isArray($) && $.every((e: unknown) => {})", elem.cond.replace('$', "e")),
+            convert: format!("$ as {}", map_type_ts(Some(type_name))),
+        };
+    }
+    // 精緻型はベース型の検査に述語を重ねる
+    let (base, refinement) = match module_env.get_type(type_name) {
+        Some(refined) => (refined._base_type.as_str(), range_check_ts(&refined.predicate_raw, &refined.operand)),
+        None => (type_name, None),
+    };
+    let mut conds = vec![match base {
+        "f64" => "typeof $ === \"number\"".to_string(),
+        "Str" => "typeof $ === \"string\"".to_string(),
+        "bool" => "typeof $ === \"boolean\"".to_string(),
+        "u64" => "typeof $ === \"number\" && Number.isInteger($) && $ >= 0".to_string(),
+        _ => "typeof $ === \"number\" && Number.isInteger($)".to_string(),
+    }];
+    conds.extend(refinement);
+    conds.extend(predicate.and_then(|p| range_check_ts(p, "v")));
+    JsonCheck { cond: conds.join(" && "), convert: format!("$ as {}", map_type_ts(Some(type_name))) }
+}
+
+/// 述語を TypeScript の条件に翻訳する（operand は `$`）。数値リテラルとの比較の && 以外は None
+fn range_check_ts(predicate: &str, operand: &str) -> Option<String> {
+    fn render(expr: &Expr, operand: &str) -> Option<String> {
+        let side = |e: &Expr| match e {
+            Expr::Variable(v) if v == operand => Some("$".to_string()),
+            Expr::Number(n) => Some(n.to_string()),
+            Expr::Float(f) => Some(f.to_string()),
+            _ => None,
+        };
+        match expr {
+            Expr::BinaryOp(l, Op::And, r) => Some(format!("{} && {}", render(l, operand)?, render(r, operand)?)),
+            Expr::BinaryOp(l, op, r) => {
+                let op_str = match op {
+                    Op::Eq => "===", Op::Neq => "!==", Op::Gt => ">", Op::Lt => "<", Op::Ge => ">=", Op::Le => "<=",
+                    _ => return None,
+                };
+                Some(format!("{} {} {}", side(l)?, op_str, side(r)?))
+            }
+            _ => None,
+        }
+    }
+    crate::parser::parse_expression(predicate).ok().and_then(|e| render(&e, operand))
+}
+
+/// 値を検査して失敗なら例外を送出する文（what はエラーメッセージに出す期待値の説明）
+fn json_guard_ts(check: &JsonCheck, subject: &str, label: &str, what: &str, indent: &str) -> String {
+    format!(
+        "{indent}if (!({})) {{\n{indent}    throw new Error({});\n{indent}}}",
+        check.cond.replace('$', subject),
+        serde_json::to_string(&format!("{}: expected {}", label, what)).unwrap_or_default(),
+        indent = indent
+    )
+}
+
+/// parseX の先頭: オブジェクトであることを確かめて `o` に束縛する
+fn json_object_prologue_ts(name: &str) -> Vec<String> {
+    vec![
+        "    if (typeof json !== \"object\" || json === null || Array.isArray(json)) {".to_string(),
+        format!("        throw new Error(\"{}: expected an object\");", name),
+        "    }".to_string(),
+        "    const o = json as Record<string, unknown>;".to_string(),
+    ]
+}
+
+/// struct の JSON バリデータ `parseX(json: unknown): X`（型パラメータを持つ struct は None）
+pub fn transpile_struct_validator_ts(struct_def: &StructDef, module_env: &ModuleEnv) -> Option<String> {
+    if !struct_def.type_params.is_empty() {
+        return None;
+    }
+    let name = &struct_def.name;
+    let mut lines = vec![
+        format!("/** Parses a JSON value as {} (checks the structure and statically known where clauses) */", name),
+        format!("export function parse{}(json: unknown): {} {{", name, name),
+    ];
+    lines.extend(json_object_prologue_ts(name));
+    let mut fields = Vec::new();
+    for field in &struct_def.fields {
+        let check = json_check_ts(&field.type_name, field.constraint.as_deref(), module_env);
+        let subject = format!("o.{}", field.name);
+        let what = match &field.constraint {
+            Some(c) => format!("{} where {}", field.type_name, c),
+            None => field.type_name.clone(),
+        };
+        lines.push(json_guard_ts(&check, &subject, &format!("{}.{}", name, field.name), &what, "    "));
+        fields.push(format!("{}: {}", field.name, check.convert.replace('$', &subject)));
+    }
+    lines.push(format!("    return {{ {} }};", fields.join(", ")));
+    lines.push("}".to_string());
+    Some(lines.join("\n"))
+}
+
+/// enum の JSON バリデータ `parseX(json: unknown): X`（型パラメータを持つ enum は None）
+pub fn transpile_enum_validator_ts(enum_def: &EnumDef, module_env: &ModuleEnv) -> Option<String> {
+    if !enum_def.type_params.is_empty() {
+        return None;
+    }
+    let name = &enum_def.name;
+    let mut lines = vec![
+        format!("/** Parses a JSON value as {} (checks the kind tag and each payload field) */", name),
+        format!("export function parse{}(json: unknown): {} {{", name, name),
+    ];
+    lines.extend(json_object_prologue_ts(name));
+    lines.push("    switch (o.kind) {".to_string());
+    for variant in &enum_def.variants {
+        lines.push(format!("        case \"{}\": {{", variant.name));
+        let mut fields = vec![format!("kind: \"{}\"", variant.name)];
+        for (fi, field_type) in variant.fields.iter().enumerate() {
+            let subject = format!("o.field_{}", fi);
+            // 再帰フィールドは同じバリデータで入れ子のオブジェクトを検査する
+            if is_recursive_field(enum_def, field_type) {
+                fields.push(format!("field_{}: parse{}({})", fi, name, subject));
+                continue;
+            }
+            let check = json_check_ts(field_type, None, module_env);
+            let label = format!("{}.{}.field_{}", name, variant.name, fi);
+            lines.push(json_guard_ts(&check, &subject, &label, field_type, "            "));
+            fields.push(format!("field_{}: {}", fi, check.convert.replace('$', &subject)));
+        }
+        lines.push(format!("            return {{ {} }};", fields.join(", ")));
+        lines.push("        }".to_string());
+    }
+    lines.push("        default:".to_string());
+    lines.push(format!("            throw new Error(\"{}: unknown kind \" + JSON.stringify(o.kind));", name));
+    lines.push("    }".to_string());
+    lines.push("}".to_string());
+    Some(lines.join("\n"))
+}
+
+/// Variant の構築: discriminated union のオブジェクトリテラル（`{ kind: "Circle", field_0: r }`）。
+/// 文の先頭でブロックと解釈されないよう括弧で包む
+fn format_variant_ts(variant: &EnumVariant, args: &[String]) -> String {
+    let fields: Vec<String> = std::iter::once(format!("kind: \"{}\"", variant.name))
+        .chain(args.iter().enumerate().map(|(i, arg)| format!("field_{}: {}", i, arg)))
+        .collect();
+    format!("({{ {} }})", fields.join(", "))
+}
+
+fn format_expr_ts(expr: &Expr, ctx: &TranspileCtx) -> Result<String, Unsupported> {
+    let code = match expr {
+        // number は 2^53 を超える整数を正確に表現できないため、精度落ちを出力上で明示する
+        Expr::Number(n) if n.unsigned_abs() > 9_007_199_254_740_991 => format!("{} /* exceeds Number.MAX_SAFE_INTEGER */", n),
         Expr::Number(n) => n.to_string(),
         Expr::Float(f) => f.to_string(), // TypeScriptはそのままのリテラルでOK
-        Expr::Variable(v) => v.clone(),
-        Expr::ArrayAccess(name, idx) => format!("{}[{}]", name, format_expr_ts(idx)),
+        Expr::StringLit(s) => serde_json::to_string(s).unwrap_or_default(),
+        Expr::Bool(b) => b.to_string(),
+        Expr::Variable(v) => match ctx.variant_constructor(v) {
+            Some((_, variant)) => format_variant_ts(variant, &[]),
+            None => v.clone(),
+        },
+        Expr::ArrayAccess(name, idx) => format!("{}[{}]", name, format_expr_ts(idx, ctx)?),
+
+        // 理論拡張（crate::theory）の組み込み関数
+        Expr::Call(name, args) if ctx.is_intrinsic(name) => super::transpile_intrinsic(name, args, TargetLanguage::TypeScript, ctx, format_expr_ts)?,
 
         Expr::Call(name, args) => {
-            let args_str: Vec<String> = args.iter().map(format_expr_ts).collect();
+            let args_str = args.iter().map(|a| format_expr_ts(a, ctx)).collect::<Result<Vec<_>, _>>()?;
             match name.as_str() {
                 "sqrt" => format!("Math.sqrt({})", args_str.join(", ")),
+                "approx_eq" => super::transpile_approx_eq(TargetLanguage::TypeScript, &args_str)?,
                 "len" => format!("{}.length", args_str.join(", ")),
-                _ => format!("{}({})", name, args_str.join(", ")),
+                "is_some" if ctx.nullable_arg(args).is_some() => format!("({} !== null)", args_str[0]),
+                "is_none" if ctx.nullable_arg(args).is_some() => format!("({} === null)", args_str[0]),
+                // null への値アクセスは例外を送出する（検証済みなら到達しない）
+                "value" if ctx.nullable_arg(args).is_some() => format!(
+                    "({} ?? (() => {{ throw new Error(\"mumei: '{}' is null\"); }})())",
+                    args_str[0], args_str[0]
+                ),
+                _ => match ctx.variant_constructor(name) {
+                    Some((_, variant)) => format_variant_ts(variant, &args_str),
+                    None => format!("{}({})", name, brand_args_ts(name, args_str, ctx.module_env).join(", ")),
+                },
             }
         },
 
-        Expr::BinaryOp(l, op, r) => {
-            let op_str = match op {
-                Op::Add => "+", Op::Sub => "-", Op::Mul => "*", Op::Div => "/",
-                Op::Eq => "===", Op::Neq => "!==", Op::Gt => ">", Op::Lt => "<",
-                Op::Ge => ">=", Op::Le => "<=", Op::And => "&&", Op::Or => "||",
-                Op::Implies => "/* implies: (!a || b) */",
-            };
-            format!("({} {} {})", format_expr_ts(l), op_str, format_expr_ts(r))
+        Expr::BinaryOp(..) => {
+            // 左に連なる連鎖は左端から順に組み立て、連鎖の長さぶん再帰しない。
+            // 含意 `a => b` は `(!(a) || b)` にする（開き括弧は外側の演算子から順に並ぶ）
+            let (leaf, nodes) = expr.left_spine();
+            let mut code: String = nodes.iter().rev()
+                .map(|node| if matches!(node, Expr::BinaryOp(_, Op::Implies, _)) { "(!(" } else { "(" })
+                .collect();
+            code.push_str(&format_expr_ts(leaf, ctx)?);
+            for node in nodes {
+                if let Expr::BinaryOp(_, op, r) = node {
+                    if matches!(op, Op::Implies) {
+                        code.push(')');
+                    }
+                    let op_str = match op {
+                        Op::Add => "+", Op::Sub => "-", Op::Mul => "*", Op::Div => "/",
+                        Op::Eq => "===", Op::Neq => "!==", Op::Gt => ">", Op::Lt => "<",
+                        Op::Ge => ">=", Op::Le => "<=", Op::And => "&&", Op::Or => "||",
+                        Op::Implies => "||",
+                    };
+                    code.push_str(&format!(" {} {})", op_str, format_expr_ts(r, ctx)?));
+                }
+            }
+            code
+        },
+
+        // 条件演算子 `c ? a : b` はそのまま三項演算子に戻す
+        Expr::IfThenElse { cond, then_branch, else_branch } if super::is_conditional_expr(then_branch, else_branch) => {
+            format!("({} ? {} : {})", format_expr_ts(cond, ctx)?, format_expr_ts(then_branch, ctx)?, format_expr_ts(else_branch, ctx)?)
         },
 
         Expr::IfThenElse { cond, then_branch, else_branch } => {
             format!(
                 "if ({}) {{\n        {}\n    }} else {{\n        {}\n    }}",
-                format_expr_ts(cond),
-                format_expr_ts(then_branch),
-                format_expr_ts(else_branch)
+                format_expr_ts(cond, ctx)?,
+                format_expr_ts(then_branch, ctx)?,
+                format_expr_ts(else_branch, ctx)?
             )
         },
 
         Expr::While { cond, invariant, decreases: _, body } => {
             format!(
                 "// invariant: {}\n    while ({}) {{\n        {}\n    }}",
-                format_expr_ts(invariant),
-                format_expr_ts(cond),
-                format_expr_ts(body)
+                format_expr_ts(invariant, ctx)?,
+                format_expr_ts(cond, ctx)?,
+                format_expr_ts(body, ctx)?
             )
         },
 
-        Expr::Let { var, value } => {
-            format!("let {} = {};", var, format_expr_ts(value))
+        Expr::Let { var, value } => match branded_value_ts(value, ctx) {
+            Some(base) => format!("let {}: {} = {};", var, base, format_expr_ts(value, ctx)?),
+            None => format!("let {} = {};", var, format_expr_ts(value, ctx)?),
         },
 
         Expr::Assign { var, value } => {
-            format!("{} = {};", var, format_expr_ts(value))
+            format!("{} = {};", var, format_expr_ts(value, ctx)?)
         },
 
         Expr::Block(stmts) => {
             let mut lines = Vec::new();
             for (i, s) in stmts.iter().enumerate() {
-                let code = format_expr_ts(s);
+                let code = format_expr_ts(s, ctx)?;
                 if i == stmts.len() - 1 {
-                    // 最後の要素が式なら return をつける、既に文ならそのまま
-                    if code.starts_with("if") || code.starts_with("let") ||
+                    // 最後の要素が式なら return をつける、既に文ならそのまま（match は値を返すアロー関数）
+                    if matches!(s, Expr::Match { .. }) {
+                        lines.push(format!("return {};", code));
+                    } else if code.starts_with("if") || code.starts_with("let") ||
                         code.starts_with("while") || code.contains(" = ") {
                         lines.push(code);
                     } else {
@@ -236,50 +738,115 @@ fn format_expr_ts(expr: &Expr) -> String {
         },
 
         Expr::StructInit { type_name: _, fields } => {
-            let field_strs: Vec<String> = fields.iter()
-                .map(|(name, expr)| format!("{}: {}", name, format_expr_ts(expr)))
-                .collect();
+            let field_strs = fields.iter()
+                .map(|(name, expr)| Ok(format!("{}: {}", name, format_expr_ts(expr, ctx)?)))
+                .collect::<Result<Vec<_>, Unsupported>>()?;
             format!("{{ {} }}", field_strs.join(", "))
         },
 
         Expr::FieldAccess(expr, field) => {
-            format!("{}.{}", format_expr_ts(expr), field)
+            format!("{}.{}", format_expr_ts(expr, ctx)?, field)
         },
 
         Expr::Match { target, arms } => {
-            // TypeScript では switch 文に変換
-            let target_str = format_expr_ts(target);
-            let mut cases = Vec::new();
-            for arm in arms {
-                let body = format_expr_ts(&arm.body);
-                match &arm.pattern {
-                    crate::parser::Pattern::Literal(n) => {
-                        cases.push(format!("case {}: return {};", n, body));
-                    },
-                    crate::parser::Pattern::Variant { variant_name, .. } => {
-                        cases.push(format!("case /* {} */: return {};", variant_name, body));
-                    },
-                    crate::parser::Pattern::Wildcard | crate::parser::Pattern::Variable(_) => {
-                        cases.push(format!("default: return {};", body));
-                    },
-                }
+            // 値を返す即時実行のアロー関数にする。Enum 値は判別共用体の kind で分岐し、
+            // ガードやネストしたパターンのあるアームは上から順の if にする
+            let target_enum = super::match_target_enum(target, ctx);
+            let planned = super::plan_arms(arms, target_enum, ctx.module_env)?;
+            // Enum 値のターゲットはパラメータそのもの。それ以外（整数）は引数 __m で一度だけ評価する
+            let (subject, param, arg) = match target.as_ref() {
+                Expr::Variable(name) => (name.clone(), String::new(), String::new()),
+                _ => ("__m".to_string(), "__m: number".to_string(), format_expr_ts(target, ctx)?),
+            };
+            let simple = super::is_simple_switch(&planned);
+            let mut lines = Vec::new();
+            for p in &planned {
+                let bind = |e: &Expr| super::substitute_bindings(e, &p.plan, |path| field_path_ts(&subject, path));
+                let body = format_expr_ts(&super::as_block(bind(&p.arm.body)), ctx)?;
+                let body = if body.contains('\n') {
+                    format!("{{\n                {}\n            }}", body.replace("\n    ", "\n                "))
+                } else {
+                    body
+                };
+                let line = if simple {
+                    match p.plan.tests.first() {
+                        None => format!("default: {}", body),
+                        Some(PatternTest::Variant { variant, .. }) => format!("case \"{}\": {}", variant, body),
+                        Some(PatternTest::Literal { value, .. }) => format!("case {}: {}", value, body),
+                    }
+                } else if p.catch_all {
+                    body
+                } else {
+                    let mut conds: Vec<String> = p.plan.tests.iter().map(|t| test_ts(&subject, t)).collect();
+                    if let Some(guard) = &p.arm.guard {
+                        conds.push(format_expr_ts(&bind(guard), ctx)?);
+                    }
+                    format!("if ({}) {}", conds.join(" && "), body)
+                };
+                lines.push(line);
+            }
+            if !planned.iter().any(|p| p.catch_all) {
+                let throw = format!("throw new Error({:?});", super::UNREACHABLE_ARM);
+                lines.push(if !simple {
+                    throw
+                } else if target_enum.is_some() && super::arms_exhaustive(arms, target_enum) {
+                    // 全 Variant を case で覆っていれば subject は never に絞られ、Variant の追加は tsc が検出する
+                    format!("default: return ((_: never): never => {{ {} }})({});", throw, subject)
+                } else {
+                    format!("default: {}", throw)
+                });
             }
-            format!("(() => {{ switch ({}) {{ {} }} }})()", target_str, cases.join(" "))
+            let body = if simple {
+                let kind = if target_enum.is_some() { format!("{}.kind", subject) } else { subject.clone() };
+                format!("switch ({}) {{\n            {}\n        }}", kind, lines.join("\n            "))
+            } else {
+                lines.join("\n        ")
+            };
+            format!("(({}) => {{\n        {}\n    }})({})", param, body, arg)
         },
 
         Expr::Acquire { resource, body } => {
-            // acquire を即時実行 async 関数で包むことで、外側の関数が async でなくても動作する。
-            // async 関数内で呼ばれる場合は await で展開される。
-            let body_str = format_expr_ts(body);
-            format!("(async () => {{ await {r}.acquire(); try {{ return {body}; }} finally {{ {r}.release(); }} }})()", r = resource, body = body_str)
+            // acquire は即時実行 async 関数で包むため、値を得るには await が必要。
+            // 同期 atom では Promise が number として扱われてしまうため未対応とする。
+            if !ctx.in_async {
+                return Err(Unsupported::new("acquire", "acquire in a non-async atom would return a Promise instead of a value"));
+            }
+            let body_str = format_expr_ts(body, ctx)?;
+            // 修飾名 `alias::name` は識別子として `alias__name` に変換する
+            format!("(await (async () => {{ await {r}.acquire(); try {{ return {body}; }} finally {{ {r}.release(); }} }})())", r = resource.replace("::", "__"), body = body_str)
         },
         Expr::Async { body } => {
-            let body_str = format_expr_ts(body);
+            let body_str = format_expr_ts(body, ctx)?;
             format!("(async () => {{ {} }})()", body_str)
         },
         Expr::Await { expr } => {
-            let expr_str = format_expr_ts(expr);
-            format!("await {}", expr_str)
+            // async atom の呼び出しは Promise を返し、await で値になる。
+            // acquire は内側で await 済み、同期 atom の呼び出しは await 不要
+            match expr.as_ref() {
+                Expr::Call(name, _) if ctx.is_async_callee(name) => format!("(await {})", format_expr_ts(expr, ctx)?),
+                Expr::Call(_, _) | Expr::Acquire { .. } => return format_expr_ts(expr, ctx),
+                _ => format!("await {}", format_expr_ts(expr, ctx)?),
+            }
+        },
+        Expr::Assume { text, justification, .. } => {
+            // assume は検証時の仮定なので、出力ではコメントとして残すだけにする
+            format!("// assume: {} ({})", text, justification.replace('\n', " "))
         },
+        // TS のパラメータは ref / ref mut もコメント付きの値渡し
+        Expr::RefArg { expr, .. } => return format_expr_ts(expr, ctx),
+    };
+    Ok(code)
+}
+
+/// match ターゲット subject からフィールドパスを辿る式（payload は field_{i}）
+fn field_path_ts(subject: &str, path: &[FieldStep]) -> String {
+    path.iter().fold(subject.to_string(), |acc, step| format!("{}.field_{}", acc, step.index))
+}
+
+/// パターンの判定の条件式（kind の比較で TypeScript が後続のフィールド参照の型を絞り込む）
+fn test_ts(subject: &str, test: &PatternTest) -> String {
+    match test {
+        PatternTest::Variant { path, variant } => format!("{}.kind === \"{}\"", field_path_ts(subject, path), variant),
+        PatternTest::Literal { path, value } => format!("{} === {}", field_path_ts(subject, path), value),
     }
 }
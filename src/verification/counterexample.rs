@@ -0,0 +1,543 @@
+// =============================================================================
+// 反例（Counter-example）の構造化と表示
+// =============================================================================
+//
+// ensures 失敗・law 失敗・網羅性エラーの反例を共通の構造で保持し、
+// `--counterexample-format` に応じて inline / table / json で表示する。
+
+use super::*;
+
+/// 反例の表示形式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CounterexampleFormat {
+    /// `a: i64 = 3, b: i64 = -1`（従来形式に型を付加）
+    #[default]
+    Inline,
+    /// 列を揃えた表形式
+    Table,
+    /// 失敗オブジェクトにネストした JSON
+    Json,
+}
+
+impl std::str::FromStr for CounterexampleFormat {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "inline" => Ok(CounterexampleFormat::Inline),
+            "table" => Ok(CounterexampleFormat::Table),
+            "json" => Ok(CounterexampleFormat::Json),
+            other => Err(format!("unknown counterexample format '{}' (expected json, inline or table)", other)),
+        }
+    }
+}
+
+/// model で評価したシンボル1つ分の値
+#[derive(Debug, Clone)]
+pub struct CounterexampleValue {
+    pub name: String,
+    /// 宣言された型名（精緻型名をそのまま保持: 例 "Nat"）
+    pub declared_type: String,
+    /// 解決後のベース型（例 "i64"）
+    pub base_type: String,
+    pub value: ModelValue,
+    /// Z3 が印字したままの値（例 `(- 3)`, `(/ 1.0 3.0)`）
+    pub raw: String,
+}
+
+/// Z3 の model 値を正規化したもの。表示と JSON の型付きフィールドに使う
+#[derive(Debug, Clone, PartialEq)]
+pub enum ModelValue {
+    /// 整数（10 進表記。i64 に収まらない値もそのまま保持する）
+    Int(String),
+    /// 有理数（Real ソートのシンボル）。分母は常に正
+    Rational { numerator: i128, denominator: i128 },
+    /// 倍精度浮動小数点（NaN / ±Infinity を含む）
+    Float(f64),
+    Bool(bool),
+    /// 解釈できなかった値（raw をそのまま表示する）
+    Unknown(String),
+}
+
+/// 有理数を 10 進で表示するときの小数点以下の最大桁数
+const RATIONAL_DIGITS: usize = 6;
+
+impl ModelValue {
+    /// Z3 の印字形式（`(- 3)`, `(/ 1.0 3.0)`, `(fp ...)`, `true` 等）を解釈する
+    pub fn parse(text: &str) -> ModelValue {
+        let text = text.trim();
+        match text {
+            "true" => return ModelValue::Bool(true),
+            "false" => return ModelValue::Bool(false),
+            _ => {}
+        }
+        if let Some(f) = parse_fp_literal(text) {
+            return ModelValue::Float(f);
+        }
+        if let Some(n) = parse_int_literal(text) {
+            return ModelValue::Int(n);
+        }
+        match parse_real_literal(text) {
+            Some((numerator, denominator)) => ModelValue::Rational { numerator, denominator },
+            None => ModelValue::Unknown(text.to_string()),
+        }
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ModelValue::Int(_) => "int",
+            ModelValue::Rational { .. } => "rational",
+            ModelValue::Float(_) => "float",
+            ModelValue::Bool(_) => "bool",
+            ModelValue::Unknown(_) => "unknown",
+        }
+    }
+
+    /// JSON の型付きフィールド（`value` と、種類に応じた `exact` / `special`）
+    fn json_fields(&self, obj: &mut serde_json::Value) {
+        obj["kind"] = json!(self.kind());
+        match self {
+            // i64 に収まらない整数は精度を落とさないよう文字列で出す
+            ModelValue::Int(n) => obj["value"] = n.parse::<i64>().map_or_else(|_| json!(n), |v| json!(v)),
+            ModelValue::Rational { numerator, denominator } => {
+                obj["value"] = json!(*numerator as f64 / *denominator as f64);
+                obj["exact"] = json!(format!("{}/{}", numerator, denominator));
+            }
+            ModelValue::Float(f) if f.is_finite() => obj["value"] = json!(f),
+            ModelValue::Float(_) => {
+                obj["value"] = serde_json::Value::Null;
+                obj["special"] = json!(self.to_string());
+            }
+            ModelValue::Bool(b) => obj["value"] = json!(b),
+            ModelValue::Unknown(raw) => obj["value"] = json!(raw),
+        }
+    }
+}
+
+impl std::fmt::Display for ModelValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ModelValue::Int(n) => write!(f, "{}", n),
+            ModelValue::Rational { numerator, denominator } => {
+                let decimal = format!("{:.*}", RATIONAL_DIGITS, *numerator as f64 / *denominator as f64);
+                let decimal = decimal.trim_end_matches('0').trim_end_matches('.');
+                // 表示桁数で割り切れない値は正確な分数を添える
+                let exact = 10i128.checked_pow(RATIONAL_DIGITS as u32)
+                    .and_then(|scale| numerator.checked_mul(scale))
+                    .map_or(false, |scaled| scaled % denominator == 0);
+                if exact {
+                    write!(f, "{}", decimal)
+                } else {
+                    write!(f, "{} (= {}/{})", decimal, numerator, denominator)
+                }
+            }
+            ModelValue::Float(x) if x.is_nan() => write!(f, "NaN"),
+            ModelValue::Float(x) if x.is_infinite() => write!(f, "{}Infinity", if *x > 0.0 { "+" } else { "-" }),
+            ModelValue::Float(x) => write!(f, "{:?}", x),
+            ModelValue::Bool(b) => write!(f, "{}", b),
+            ModelValue::Unknown(raw) => write!(f, "{}", raw),
+        }
+    }
+}
+
+/// 網羅性エラーで再構成したバリアントと payload
+#[derive(Debug, Clone)]
+pub struct CounterexampleVariant {
+    pub enum_name: String,
+    pub variant_name: String,
+    pub tag: i64,
+    pub payload: Vec<CounterexampleValue>,
+}
+
+/// 失敗した証明義務の反例
+#[derive(Debug, Clone, Default)]
+pub struct Counterexample {
+    pub values: Vec<CounterexampleValue>,
+    pub variant: Option<CounterexampleVariant>,
+    pub note: Option<String>,
+}
+
+impl Counterexample {
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty() && self.variant.is_none()
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        let value_json = |v: &CounterexampleValue| {
+            let mut obj = json!({
+                "name": v.name,
+                "type": v.declared_type,
+                "base_type": v.base_type,
+                "raw": v.raw,
+            });
+            v.value.json_fields(&mut obj);
+            obj
+        };
+        let mut obj = json!({
+            "values": self.values.iter().map(value_json).collect::<Vec<_>>(),
+        });
+        if let Some(variant) = &self.variant {
+            obj["variant"] = json!({
+                "enum": variant.enum_name,
+                "name": variant.variant_name,
+                "tag": variant.tag,
+                "payload": variant.payload.iter().map(value_json).collect::<Vec<_>>(),
+            });
+        }
+        if let Some(note) = &self.note {
+            obj["note"] = json!(note);
+        }
+        obj
+    }
+
+    /// 失敗メッセージと反例を指定形式で1つの文字列に整形する
+    pub fn render(&self, failure: &str, format: CounterexampleFormat) -> String {
+        match format {
+            CounterexampleFormat::Json => {
+                let obj = json!({
+                    "failure": failure,
+                    "counterexample": self.to_json(),
+                    "z3_version": linked_z3_version(),
+                });
+                // 見出しは1行目のみ（複数行の契約引用は JSON 側の failure に含まれる）。
+                // 2行目以降が丸ごと JSON になるため、利用側は最初の改行で分割すればよい。
+                let headline = failure.lines().next().unwrap_or("");
+                format!("{}\n{}", headline, serde_json::to_string_pretty(&obj).unwrap_or_default())
+            }
+            CounterexampleFormat::Inline => {
+                if self.is_empty() {
+                    return match &self.note {
+                        Some(note) => format!("{}\n  Counter-example: ({})", failure, note),
+                        None => failure.to_string(),
+                    };
+                }
+                let mut parts: Vec<String> = Vec::new();
+                if let Some(variant) = &self.variant {
+                    let payload: Vec<String> = variant.payload.iter()
+                        .map(|p| format!("{}: {}", p.value, p.declared_type))
+                        .collect();
+                    let payload_str = if payload.is_empty() { String::new() } else { format!("({})", payload.join(", ")) };
+                    parts.push(format!("{}::{}{} (tag={})", variant.enum_name, variant.variant_name, payload_str, variant.tag));
+                }
+                parts.extend(self.values.iter().map(|v| format!("{}: {} = {}", v.name, v.declared_type, v.value)));
+                let note = self.note.as_ref().map(|n| format!(" -- {}", n)).unwrap_or_default();
+                format!("{}\n  Counter-example: {}{}", failure, parts.join(", "), note)
+            }
+            CounterexampleFormat::Table => {
+                let mut rows: Vec<[String; 4]> = vec![[
+                    "name".to_string(), "type".to_string(), "base".to_string(), "value".to_string(),
+                ]];
+                if let Some(variant) = &self.variant {
+                    rows.push([
+                        "(variant)".to_string(), variant.enum_name.clone(), "tag".to_string(),
+                        format!("{} ({})", variant.variant_name, variant.tag),
+                    ]);
+                    for p in &variant.payload {
+                        rows.push([p.name.clone(), p.declared_type.clone(), p.base_type.clone(), p.value.to_string()]);
+                    }
+                }
+                for v in &self.values {
+                    rows.push([v.name.clone(), v.declared_type.clone(), v.base_type.clone(), v.value.to_string()]);
+                }
+                let mut widths = [0usize; 4];
+                for row in &rows {
+                    for (i, cell) in row.iter().enumerate() {
+                        widths[i] = widths[i].max(cell.chars().count());
+                    }
+                }
+                let mut lines = vec![failure.to_string()];
+                for row in &rows {
+                    let cells: Vec<String> = row.iter().enumerate()
+                        .map(|(i, cell)| format!("{:<width$}", cell, width = widths[i]))
+                        .collect();
+                    lines.push(format!("  | {} |", cells.join(" | ")));
+                }
+                if let Some(note) = &self.note {
+                    lines.push(format!("  ({})", note));
+                }
+                lines.join("\n")
+            }
+        }
+    }
+}
+
+/// 宣言型付きのシンボル一覧を model で評価して反例を構築する
+/// symbols: (名前, 宣言型名, Z3 シンボル)
+pub(super) fn model_counterexample<'a>(
+    model: &z3::Model<'a>,
+    symbols: &[(String, String, Dynamic<'a>)],
+    module_env: &ModuleEnv,
+) -> Counterexample {
+    let mut ce = Counterexample::default();
+    for (name, declared_type, sym) in symbols {
+        if let Some(val) = model.eval(sym, true) {
+            let (value, raw) = render_model_value(&val);
+            ce.values.push(CounterexampleValue {
+                name: name.clone(),
+                declared_type: declared_type.clone(),
+                base_type: module_env.resolve_base_type(declared_type),
+                value,
+                raw,
+            });
+        }
+    }
+    ce
+}
+
+/// 証明できなかった義務 goal の反例 model を取り出す（¬goal が Sat のときだけ）。
+/// discharge は結果だけを返すので、失敗時にもう一度 ¬goal を解いて model を得る
+pub(super) fn violation_model<'a>(vc: &VCtx<'a>, solver: &Solver<'a>, goal: &Bool<'a>) -> Option<z3::Model<'a>> {
+    solver.push();
+    solver.assert(&goal.not());
+    let model = if vc.check(solver) == SatResult::Sat { solver.get_model() } else { None };
+    solver.pop(1);
+    model
+}
+
+/// 宣言型を持たないシンボル（result・let 束縛）の表示用の型名
+pub(super) fn symbol_type_name(sym: &Dynamic) -> &'static str {
+    if sym.as_float().is_some() {
+        "f64"
+    } else if sym.as_bool().is_some() {
+        "bool"
+    } else {
+        "i64"
+    }
+}
+
+/// 契約のソース文字列が識別子 name を参照するか（部分一致ではなく識別子単位）
+pub(super) fn mentions_identifier(source: &str, name: &str) -> bool {
+    source.split(|c: char| !(c.is_alphanumeric() || c == '_')).any(|word| word == name)
+}
+
+/// model の値を正規化する（ensures 失敗・law 失敗・網羅性エラーの反例で共通）。
+/// 負の整数 `(- 3)`・有理数 `(/ 1.0 3.0)`・FP `(fp #b0 #b... #x...)` を読める形にし、
+/// Z3 の印字形式も raw として返す
+pub(super) fn render_model_value(val: &Dynamic) -> (ModelValue, String) {
+    let raw = format!("{}", val);
+    (ModelValue::parse(&raw), raw)
+}
+
+/// Z3 の整数リテラル（`42` / `(- 3)`）を 10 進表記にする
+fn parse_int_literal(text: &str) -> Option<String> {
+    if let Some(inner) = text.strip_prefix("(-").and_then(|t| t.strip_suffix(')')) {
+        return parse_int_literal(inner.trim()).map(|n| match n.strip_prefix('-') {
+            Some(positive) => positive.to_string(),
+            None => format!("-{}", n),
+        });
+    }
+    (!text.is_empty() && text.chars().all(|c| c.is_ascii_digit())).then(|| text.to_string())
+}
+
+/// Z3 の実数リテラル（`2.0` / `1.5` / `(/ 1.0 3.0)` / `(- (/ 1.0 3.0))`）を既約分数（分母は正）にする
+fn parse_real_literal(text: &str) -> Option<(i128, i128)> {
+    let (numerator, denominator) = if let Some(inner) = text.strip_prefix("(-").and_then(|t| t.strip_suffix(')')) {
+        let (n, d) = parse_real_literal(inner.trim())?;
+        (n.checked_neg()?, d)
+    } else if let Some(inner) = text.strip_prefix("(/").and_then(|t| t.strip_suffix(')')) {
+        let (lhs, rhs) = split_operands(inner.trim())?;
+        let (ln, ld) = parse_real_literal(lhs)?;
+        let (rn, rd) = parse_real_literal(rhs)?;
+        if rn == 0 {
+            return None;
+        }
+        (ln.checked_mul(rd)?, ld.checked_mul(rn)?)
+    } else {
+        let (whole, fraction) = text.split_once('.').unwrap_or((text, ""));
+        if whole.is_empty() || !whole.chars().chain(fraction.chars()).all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+        let digits: i128 = format!("{}{}", whole, fraction).parse().ok()?;
+        (digits, 10i128.checked_pow(fraction.len() as u32)?)
+    };
+    let divisor = gcd(numerator, denominator);
+    let sign = if denominator < 0 { -1 } else { 1 };
+    Some((sign * numerator / divisor, sign * denominator / divisor))
+}
+
+/// `(/ a b)` の中身 `a b` を 2 つのオペランドに分ける（括弧の入れ子を考慮）
+fn split_operands(text: &str) -> Option<(&str, &str)> {
+    let mut depth = 0i32;
+    for (i, c) in text.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ' ' if depth == 0 => return Some((&text[..i], text[i + 1..].trim())),
+            _ => {}
+        }
+    }
+    None
+}
+
+fn gcd(a: i128, b: i128) -> i128 {
+    let (mut a, mut b) = (a.abs(), b.abs());
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a.max(1)
+}
+
+/// Z3 の倍精度 FP リテラル（`(fp #b0 #b01111111111 #x8000000000000)` / `(_ +zero 11 53)` 等）を f64 にする
+fn parse_fp_literal(text: &str) -> Option<f64> {
+    let inner = text.trim().strip_prefix('(')?.strip_suffix(')')?;
+    let parts: Vec<&str> = inner.split_whitespace().collect();
+    match parts.as_slice() {
+        ["_", special, "11", "53"] => match *special {
+            "+zero" => Some(0.0),
+            "-zero" => Some(-0.0),
+            "+oo" => Some(f64::INFINITY),
+            "-oo" => Some(f64::NEG_INFINITY),
+            "NaN" => Some(f64::NAN),
+            _ => None,
+        },
+        ["fp", sign, exponent, significand] => {
+            let bits = |lit: &str| -> Option<(u64, usize)> {
+                if let Some(b) = lit.strip_prefix("#b") {
+                    u64::from_str_radix(b, 2).ok().map(|v| (v, b.len()))
+                } else if let Some(h) = lit.strip_prefix("#x") {
+                    u64::from_str_radix(h, 16).ok().map(|v| (v, h.len() * 4))
+                } else {
+                    None
+                }
+            };
+            let (sign, sign_width) = bits(sign)?;
+            let (exponent, exponent_width) = bits(exponent)?;
+            let (significand, significand_width) = bits(significand)?;
+            if (sign_width, exponent_width, significand_width) != (1, 11, 52) {
+                return None;
+            }
+            Some(f64::from_bits((sign << 63) | (exponent << 52) | significand))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use z3::ast::Real;
+
+    #[test]
+    fn test_counterexample_json_structure() {
+        let ce = Counterexample {
+            values: vec![CounterexampleValue {
+                name: "n".to_string(),
+                declared_type: "Nat".to_string(),
+                base_type: "i64".to_string(),
+                value: ModelValue::Int("3".to_string()),
+                raw: "3".to_string(),
+            }],
+            variant: None,
+            note: None,
+        };
+        let rendered = ce.render("Postcondition (ensures) is not satisfied.", CounterexampleFormat::Json);
+        let json_part = rendered.splitn(2, '\n').nth(1).expect("json body");
+        let parsed: serde_json::Value = serde_json::from_str(json_part).expect("valid json");
+        assert_eq!(parsed["failure"], "Postcondition (ensures) is not satisfied.");
+        let values = parsed["counterexample"]["values"].as_array().expect("values array");
+        assert_eq!(values.len(), 1);
+        assert_eq!(values[0]["name"], "n");
+        assert_eq!(values[0]["type"], "Nat");
+        assert_eq!(values[0]["base_type"], "i64");
+        assert_eq!(values[0]["kind"], "int");
+        assert_eq!(values[0]["value"], 3);
+        assert_eq!(values[0]["raw"], "3");
+
+        let inline = ce.render("Postcondition (ensures) is not satisfied.", CounterexampleFormat::Inline);
+        assert!(inline.contains("n: Nat = 3"), "{}", inline);
+    }
+
+    #[test]
+    fn test_parse_fp_literal() {
+        assert_eq!(parse_fp_literal("(fp #b0 #b01111111111 #x8000000000000)"), Some(1.5));
+        assert_eq!(parse_fp_literal("(fp #b1 #b10000000000 #x0000000000000)"), Some(-2.0));
+        assert_eq!(parse_fp_literal("(_ +zero 11 53)"), Some(0.0));
+        assert_eq!(parse_fp_literal("(_ -oo 11 53)"), Some(f64::NEG_INFINITY));
+        assert!(parse_fp_literal("(_ NaN 11 53)").map_or(false, f64::is_nan));
+        assert_eq!(parse_fp_literal("42"), None);
+    }
+
+    #[test]
+    fn test_render_model_value() {
+        let cfg = Config::new();
+        let ctx = Context::new(&cfg);
+        let solver = Solver::new(&ctx);
+        let n = Int::new_const(&ctx, "n");
+        let r = Real::new_const(&ctx, "r");
+        let x = Float::new_const(&ctx, "x", 11, 53);
+        let b = Bool::new_const(&ctx, "b");
+        solver.assert(&n._eq(&Int::from_i64(&ctx, -3)));
+        solver.assert(&Real::mul(&ctx, &[&r, &Real::from_real(&ctx, 3, 1)])._eq(&Real::from_real(&ctx, -1, 1)));
+        solver.assert(&x._eq(&Float::from_f64(&ctx, 1.5)));
+        solver.assert(&b._eq(&Bool::from_bool(&ctx, true)));
+        assert_eq!(solver.check(), SatResult::Sat);
+        let model = solver.get_model().expect("model");
+        let render = |sym: Dynamic| render_model_value(&model.eval(&sym, true).expect("value"));
+
+        let (value, raw) = render(n.into());
+        assert_eq!(value, ModelValue::Int("-3".to_string()));
+        assert_eq!(raw, "(- 3)");
+        assert_eq!(value.to_string(), "-3");
+
+        let (value, _) = render(r.into());
+        assert_eq!(value, ModelValue::Rational { numerator: -1, denominator: 3 });
+        assert_eq!(value.to_string(), "-0.333333 (= -1/3)");
+
+        let (value, _) = render(x.into());
+        assert_eq!(value, ModelValue::Float(1.5));
+        assert_eq!(value.to_string(), "1.5");
+
+        let (value, _) = render(b.into());
+        assert_eq!(value, ModelValue::Bool(true));
+
+        // JSON: 型付きの value と、種類ごとの補足フィールド
+        let ce = Counterexample {
+            values: vec![
+                ("big", ModelValue::parse("123456789012345678901234")),
+                ("third", ModelValue::parse("(/ 1.0 3.0)")),
+                ("half", ModelValue::parse("(/ 1.0 2.0)")),
+                ("nan", ModelValue::parse("(_ NaN 11 53)")),
+                ("flag", ModelValue::parse("false")),
+            ].into_iter().map(|(name, value)| CounterexampleValue {
+                name: name.to_string(),
+                declared_type: "t".to_string(),
+                base_type: "t".to_string(),
+                raw: String::new(),
+                value,
+            }).collect(),
+            variant: None,
+            note: None,
+        };
+        let json = ce.to_json();
+        let values = json["values"].as_array().expect("values array");
+        assert_eq!(values[0]["value"], "123456789012345678901234");
+        assert_eq!((values[1]["kind"].as_str(), values[1]["exact"].as_str()), (Some("rational"), Some("1/3")));
+        assert!(values[1]["value"].as_f64().map_or(false, |v| (v - 1.0 / 3.0).abs() < 1e-12));
+        assert_eq!(values[2]["value"], 0.5);
+        assert_eq!(ce.values[2].value.to_string(), "0.5");
+        assert!(values[3]["value"].is_null());
+        assert_eq!(values[3]["special"], "NaN");
+        assert_eq!(values[4]["value"], false);
+    }
+
+    #[test]
+    fn test_parse_model_literals() {
+        assert_eq!(parse_int_literal("(- 3)"), Some("-3".to_string()));
+        assert_eq!(parse_int_literal("(- (- 3))"), Some("3".to_string()));
+        assert_eq!(parse_int_literal("1.5"), None);
+        assert_eq!(parse_real_literal("2.0"), Some((2, 1)));
+        assert_eq!(parse_real_literal("1.25"), Some((5, 4)));
+        assert_eq!(parse_real_literal("(- (/ 2.0 6.0))"), Some((-1, 3)));
+        assert_eq!(parse_real_literal("(/ (- 1.0) 3.0)"), Some((-1, 3)));
+        assert_eq!(parse_real_literal("(/ 1.0 0.0)"), None);
+        assert_eq!(ModelValue::parse("(_ +oo 11 53)").to_string(), "+Infinity");
+        assert_eq!(ModelValue::parse("(root-obj (+ (^ x 2) (- 2)) 1)"), ModelValue::Unknown("(root-obj (+ (^ x 2) (- 2)) 1)".to_string()));
+    }
+
+    #[test]
+    fn test_mentions_identifier() {
+        assert!(mentions_identifier("result == total + 1", "total"));
+        assert!(mentions_identifier("total>0", "total"));
+        assert!(!mentions_identifier("result == subtotal", "total"));
+        assert!(!mentions_identifier("total_len >= 0", "total"));
+    }
+}
@@ -0,0 +1,415 @@
+// =============================================================================
+// モジュール環境: グローバル static Mutex から構造体ベースの管理に移行
+// =============================================================================
+
+use super::*;
+
+/// モジュール単位の環境。型定義・構造体定義・atom 定義・enum 定義を保持する。
+/// グローバル static Mutex を廃止し、この構造体で一元管理する。
+/// main.rs で構築し、verify() / codegen / transpiler に参照渡しする。
+#[derive(Debug, Clone, Default)]
+pub struct ModuleEnv {
+    /// 精緻型定義（FQN キー: 例 "math::Nat" or 自モジュールなら "Nat"）
+    pub types: HashMap<String, RefinedType>,
+    /// 構造体定義（FQN キー）
+    pub structs: HashMap<String, StructDef>,
+    /// Atom 定義（FQN キー）。契約による検証で requires/ensures のみ参照する。
+    pub atoms: HashMap<String, Atom>,
+    /// Enum 定義（FQN キー）
+    pub enums: HashMap<String, EnumDef>,
+    /// トレイト定義
+    pub traits: HashMap<String, TraitDef>,
+    /// トレイト実装: (トレイト名, 型名) → ImplDef
+    pub impls: Vec<ImplDef>,
+    /// 検証済み Atom 名のキャッシュ
+    pub verified_cache: HashSet<String>,
+    /// リソース定義（非同期安全性検証用）
+    /// リソース名 → (優先度, アクセスモード)
+    /// エイリアス付きでインポートされたリソースは `alias::name` のみで登録される。
+    pub resources: HashMap<String, ResourceDef>,
+    /// リソースの宣言元（リソースキー → ファイルパス等）。衝突・曖昧性エラーの表示用
+    pub resource_origins: HashMap<String, String>,
+    /// 反例の表示形式（`mumei verify --counterexample-format` で指定）
+    pub counterexample_format: CounterexampleFormat,
+    /// `[proof] timeout_ms`: atom 1 件あたりの Z3 のタイムアウト（None なら DEFAULT_TIMEOUT_MS）
+    pub timeout_ms: Option<u64>,
+    /// Z3 のハードリミット（`[proof] timeout_grace_ms` / `max_memory_mb`）
+    pub solver_limits: SolverLimits,
+    /// インポートしたモジュールの非公開 item（item 名 → 宣言元モジュール）。
+    /// 契約の評価用に ModuleEnv には登録されるが、インポート側から参照するとエラーになる。
+    pub private_items: HashMap<String, PrivateItem>,
+    /// 素の名前で取り込んだ import 先の atom / 型 / struct の宣言元（(種類, 名前) → ファイルパス）。
+    /// 別ファイルの異なる定義が同じ名前を上書きしようとしたときの衝突エラーに使う
+    pub item_origins: HashMap<(&'static str, String), String>,
+    /// `[build] require_explicit_exports`: export 宣言のないモジュールの import をエラーにする
+    pub require_explicit_exports: bool,
+    /// `[proof] max_law_expansion_nodes`: law 展開結果の AST ノード数の上限（None なら既定値）
+    pub max_law_expansion_nodes: Option<usize>,
+    /// `[proof] deny_assume`: atom の body の assume をエラーにする
+    pub deny_assume: bool,
+    /// `[build] strict_ref_args`: ref / ref mut パラメータへの `ref` のない実引数を警告ではなくエラーにする
+    pub strict_ref_args: bool,
+    /// `[proof] strict_resources`: exclusive リソースの critical section からの値の持ち出しを、
+    /// スカラーも含めて警告ではなくエラーにする
+    pub strict_resources: bool,
+    /// 登録済み atom の本体が返す値の種類（crate::ast::result_kind）のメモ
+    pub result_kinds: ResultKindMemo,
+}
+
+/// atom 名 → 本体が返す値の種類のメモ。検証・コード生成・各 transpiler が同じ分類を読み、
+/// 呼び出しの連鎖でも atom ごとに 1 回だけ推論する。並列検証のスレッド間で共有するので Mutex で持つ
+#[derive(Debug, Default)]
+pub struct ResultKindMemo(Mutex<HashMap<String, crate::ast::ResultKind>>);
+
+impl ResultKindMemo {
+    pub fn get(&self, atom: &str) -> Option<crate::ast::ResultKind> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).get(atom).copied()
+    }
+
+    pub fn insert(&self, atom: &str, kind: crate::ast::ResultKind) {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).insert(atom.to_string(), kind);
+    }
+
+    fn clear(&mut self) {
+        self.0.get_mut().unwrap_or_else(|e| e.into_inner()).clear();
+    }
+}
+
+impl Clone for ResultKindMemo {
+    fn clone(&self) -> Self {
+        ResultKindMemo(Mutex::new(self.0.lock().unwrap_or_else(|e| e.into_inner()).clone()))
+    }
+}
+
+/// export リストに含まれない item の情報（可視性エラーの表示用）
+#[derive(Debug, Clone)]
+pub struct PrivateItem {
+    /// item の種類（"atom" / "type" / "struct" / "enum"）
+    pub kind: &'static str,
+    /// 宣言元モジュールのパス
+    pub module: String,
+    /// 宣言元モジュールが公開している item 名（ソート済み）
+    pub exports: Vec<String>,
+}
+
+impl ModuleEnv {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 基底の環境（組み込みトレイト・prelude・mumei.toml の依存）から、コンパイル単位ごとの
+    /// オーバーレイを作る。オーバーレイは基底の定義をすべて引き継ぎ（同名の定義は上書きする）、
+    /// その後の登録は基底にも、同じ基底から fork した他の単位にも見えない
+    pub fn fork(&self) -> ModuleEnv {
+        self.clone()
+    }
+
+    pub fn register_type(&mut self, refined_type: &RefinedType) {
+        self.types.insert(refined_type.name.clone(), refined_type.clone());
+    }
+
+    pub fn register_struct(&mut self, struct_def: &StructDef) {
+        self.structs.insert(struct_def.name.clone(), struct_def.clone());
+    }
+
+    pub fn register_atom(&mut self, atom: &Atom) {
+        // 呼び出し先の本体が変わると呼び出し元の推論も変わりうるので、メモはすべて捨てる
+        self.result_kinds.clear();
+        self.atoms.insert(atom.name.clone(), atom.clone());
+    }
+
+    pub fn register_enum(&mut self, enum_def: &EnumDef) {
+        self.enums.insert(enum_def.name.clone(), enum_def.clone());
+    }
+
+    pub fn get_type(&self, name: &str) -> Option<&RefinedType> {
+        self.types.get(name)
+    }
+
+    pub fn get_struct(&self, name: &str) -> Option<&StructDef> {
+        self.structs.get(name)
+    }
+
+    pub fn get_atom(&self, name: &str) -> Option<&Atom> {
+        self.atoms.get(name)
+    }
+
+    #[allow(dead_code)]
+    pub fn get_enum(&self, name: &str) -> Option<&EnumDef> {
+        self.enums.get(name)
+    }
+
+    /// Variant 名から所属する Enum 定義を逆引きする
+    pub fn find_enum_by_variant(&self, variant_name: &str) -> Option<&EnumDef> {
+        self.enums.values().find(|e| e.variants.iter().any(|v| v.name == variant_name))
+    }
+
+    /// 精緻型名からベース型名を解決する（例: "Nat" -> "i64", "Pos" -> "f64"）。
+    /// 理論拡張が引き受けた基底型（例: "q16_16"）は、その表現に使うコアの基底型を返す。
+    pub fn resolve_base_type(&self, type_name: &str) -> String {
+        if let Some(refined) = self.types.get(type_name) {
+            let base = refined._base_type.as_str();
+            return crate::theory::carrier_type(base).unwrap_or(base).to_string();
+        }
+        type_name.to_string()
+    }
+
+    pub fn register_trait(&mut self, trait_def: &TraitDef) {
+        self.traits.insert(trait_def.name.clone(), trait_def.clone());
+    }
+
+    pub fn register_impl(&mut self, impl_def: &ImplDef) {
+        self.impls.push(impl_def.clone());
+    }
+
+    pub fn get_trait(&self, name: &str) -> Option<&TraitDef> {
+        self.traits.get(name)
+    }
+
+    /// メソッド名からトレイトメソッドを検索する。atom 本体からのトレイトメソッド直接呼び出しの解決に使う。
+    /// 子トレイトが親のメソッドを宣言し直していれば子の宣言を使う。
+    /// 継承関係にない複数のトレイトが同名のメソッドを宣言していればエラー（どちらの契約を使うか決められない）
+    pub fn find_trait_method(&self, method_name: &str) -> MumeiResult<Option<(String, TraitMethod)>> {
+        let declares = |trait_def: &TraitDef| trait_def.methods.iter().any(|m| m.name == method_name);
+        let mut declaring: Vec<&String> = self.traits.iter()
+            .filter(|(_, trait_def)| declares(trait_def))
+            .map(|(name, _)| name)
+            .collect();
+        declaring.sort();
+        let candidates: Vec<&String> = declaring.iter().copied()
+            .filter(|name| !declaring.iter().any(|other| other != name && self.extends_trait(other, name, &mut Vec::new())))
+            .collect();
+        match candidates.as_slice() {
+            [] => Ok(None),
+            [trait_name] => Ok(self.traits[*trait_name].methods.iter()
+                .find(|m| m.name == method_name)
+                .map(|m| ((*trait_name).clone(), m.clone()))),
+            _ => {
+                let listed: Vec<&str> = candidates.iter().map(|name| name.as_str()).collect();
+                Err(MumeiError::TypeError(format!(
+                    "Ambiguous trait method call '{}': it is declared by traits {}. Rename the method in one of them.",
+                    method_name, listed.join(", ")
+                )))
+            }
+        }
+    }
+
+    /// trait_name が ancestor を（推移的に）extends しているか。循環は visited で打ち切る
+    fn extends_trait(&self, trait_name: &str, ancestor: &str, visited: &mut Vec<String>) -> bool {
+        if visited.iter().any(|t| t == trait_name) {
+            return false;
+        }
+        visited.push(trait_name.to_string());
+        self.traits.get(trait_name).is_some_and(|trait_def| {
+            trait_def.extends.iter().any(|(parent, _)| parent == ancestor || self.extends_trait(parent, ancestor, visited))
+        })
+    }
+
+    /// 指定した型がトレイトを実装しているか確認する
+    #[allow(dead_code)]
+    pub fn find_impl(&self, trait_name: &str, target_type: &str) -> Option<&ImplDef> {
+        self.impls.iter().find(|i| i.trait_name == trait_name && i.target_type == target_type)
+    }
+
+    /// 指定した型がトレイト境界を全て満たしているか検証する
+    #[allow(dead_code)]
+    pub fn check_trait_bounds(&self, type_name: &str, bounds: &[String]) -> Result<(), String> {
+        for bound in bounds {
+            if self.find_impl(bound, type_name).is_none() {
+                return Err(format!("Type '{}' does not implement trait '{}'", type_name, bound));
+            }
+        }
+        Ok(())
+    }
+
+    /// Atom を検証済みとしてマークする
+    pub fn mark_verified(&mut self, atom_name: &str) {
+        self.verified_cache.insert(atom_name.to_string());
+    }
+
+    /// Atom が検証済みかどうかを確認する
+    pub fn is_verified(&self, atom_name: &str) -> bool {
+        self.verified_cache.contains(atom_name)
+    }
+
+    /// リソース定義を宣言元 `origin` とともに登録する。
+    /// 同じキーが既に登録済みで priority / mode が異なる場合はモジュールグラフの
+    /// 不整合としてエラーにする（同一内容の再宣言は許容し、最初の宣言元を保持する）。
+    pub fn register_resource(&mut self, resource_def: &ResourceDef, origin: &str) -> MumeiResult<()> {
+        if let Some(existing) = self.resources.get(&resource_def.name) {
+            if existing.priority != resource_def.priority || existing.mode != resource_def.mode {
+                let existing_origin = self.resource_origins.get(&resource_def.name)
+                    .map(|o| o.as_str())
+                    .unwrap_or("<unknown>");
+                return Err(MumeiError::failure(FailureKind::Resource, format!(
+                    "Conflicting declarations of resource '{}': priority {} mode {:?} in '{}' vs priority {} mode {:?} in '{}'",
+                    resource_def.name,
+                    existing.priority, existing.mode, existing_origin,
+                    resource_def.priority, resource_def.mode, origin
+                )));
+            }
+            return Ok(());
+        }
+        self.resources.insert(resource_def.name.clone(), resource_def.clone());
+        self.resource_origins.insert(resource_def.name.clone(), origin.to_string());
+        Ok(())
+    }
+
+    /// `resources:` リストや `acquire` のリソース参照を登録キーに解決する。
+    /// - 登録キーと完全一致すればそれを返す（ローカル定義・`alias::name` 形式の修飾参照）
+    /// - 修飾なしの名前は、`alias::name` 形式のキーのうち一意に一致するものに解決する
+    /// - 複数の候補に一致する場合は候補を列挙した曖昧性エラー
+    /// - 見つからなければ Ok(None)
+    pub fn resolve_resource(&self, name: &str) -> MumeiResult<Option<(String, &ResourceDef)>> {
+        if let Some(rdef) = self.resources.get(name) {
+            return Ok(Some((name.to_string(), rdef)));
+        }
+        if name.contains("::") {
+            return Ok(None);
+        }
+        let suffix = format!("::{}", name);
+        let mut candidates: Vec<&String> = self.resources.keys()
+            .filter(|k| k.ends_with(&suffix))
+            .collect();
+        candidates.sort();
+        match candidates.len() {
+            0 => Ok(None),
+            1 => {
+                let key = candidates[0];
+                Ok(Some((key.clone(), &self.resources[key])))
+            }
+            _ => {
+                let listed: Vec<String> = candidates.iter()
+                    .map(|k| match self.resource_origins.get(*k) {
+                        Some(origin) => format!("{} (from '{}')", k, origin),
+                        None => k.to_string(),
+                    })
+                    .collect();
+                Err(MumeiError::failure(FailureKind::Resource, format!(
+                    "Ambiguous resource reference '{}': candidates are {}. Qualify it with the import alias (e.g. '{}').",
+                    name, listed.join(", "), candidates[0]
+                )))
+            }
+        }
+    }
+
+    /// リソース定義を取得する
+    #[allow(dead_code)]
+    pub fn get_resource(&self, name: &str) -> Option<&ResourceDef> {
+        self.resources.get(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Item;
+    use crate::test_support::env_from_source;
+
+    /// `alias::name` で登録された 2 つのライブラリのリソースと、それらを参照する atom を用意する
+    fn env_with_aliased_resources(atom_source: &str) -> (Vec<Item>, ModuleEnv) {
+        let (items, mut module_env) = env_from_source(atom_source);
+        let lib_resource = |name: &str, priority: i64| ResourceDef {
+            name: name.to_string(),
+            priority,
+            mode: ResourceMode::Exclusive,
+        };
+        module_env.register_resource(&lib_resource("lib_a::cache", 1), "lib_a.mm").unwrap();
+        module_env.register_resource(&lib_resource("lib_b::cache", 5), "lib_b.mm").unwrap();
+        module_env.register_resource(&lib_resource("lib_b::db", 3), "lib_b.mm").unwrap();
+        (items, module_env)
+    }
+
+    fn first_atom(items: &[Item]) -> Atom {
+        items.iter().find_map(|i| match i {
+            Item::Atom(a) => Some(a.clone()),
+            _ => None,
+        }).unwrap()
+    }
+
+    #[test]
+    fn test_resource_conflicting_redeclaration() {
+        let mut module_env = ModuleEnv::new();
+        let shared_db = |priority: i64| ResourceDef {
+            name: "db".to_string(),
+            priority,
+            mode: ResourceMode::Shared,
+        };
+        module_env.register_resource(&shared_db(1), "lib_a.mm").unwrap();
+        // 同一内容の再宣言は許容され、最初の宣言元が保持される
+        module_env.register_resource(&shared_db(1), "lib_c.mm").unwrap();
+        assert_eq!(module_env.resource_origins["db"], "lib_a.mm");
+
+        let err = module_env.register_resource(&shared_db(5), "lib_b.mm").expect_err("priority conflict");
+        let msg = err.to_string();
+        assert!(msg.contains("Conflicting declarations of resource 'db'"), "{}", msg);
+        assert!(msg.contains("lib_a.mm") && msg.contains("lib_b.mm"), "{}", msg);
+        assert_eq!(module_env.resources["db"].priority, 1);
+    }
+
+    #[test]
+    fn test_resource_ambiguous_bare_reference() {
+        let (items, module_env) = env_with_aliased_resources(r#"
+atom touch(x: i64)
+resources: [cache];
+requires: true;
+ensures: true;
+body: x;
+"#);
+        let err = verify_resource_hierarchy(&first_atom(&items), &module_env).expect_err("ambiguous");
+        let msg = err.to_string();
+        assert!(msg.contains("Ambiguous resource reference 'cache'"), "{}", msg);
+        assert!(msg.contains("lib_a::cache (from 'lib_a.mm')"), "{}", msg);
+        assert!(msg.contains("lib_b::cache (from 'lib_b.mm')"), "{}", msg);
+    }
+
+    #[test]
+    fn test_resource_qualified_references() {
+        // 別ライブラリの同名リソースは別の識別子として優先度比較される。
+        // 一意な修飾なし名（db → lib_b::db）はそのまま解決できる。
+        let (items, module_env) = env_with_aliased_resources(r#"
+atom touch(x: i64)
+resources: [lib_a::cache, db, lib_b::cache];
+requires: true;
+ensures: true;
+body: acquire lib_a::cache { acquire db { x } };
+"#);
+        assert!(verify_resource_hierarchy(&first_atom(&items), &module_env).is_ok());
+        assert_eq!(module_env.resolve_resource("db").unwrap().unwrap().0, "lib_b::db");
+
+        let (items, module_env) = env_with_aliased_resources(r#"
+atom touch(x: i64)
+resources: [lib_b::cache, lib_a::cache];
+requires: true;
+ensures: true;
+body: x;
+"#);
+        let err = verify_resource_hierarchy(&first_atom(&items), &module_env).expect_err("5 before 1");
+        assert!(err.to_string().contains("'lib_b::cache' (priority=5)"), "{}", err);
+    }
+
+    #[test]
+    fn test_find_trait_method_reports_ambiguity() {
+        let (_, module_env) = env_from_source(r#"
+trait Shape {
+    fn area(a: Self) -> i64;
+}
+trait Square extends Shape {
+    fn area(a: Self where v >= 0) -> i64;
+}
+trait Region {
+    fn area(a: Self) -> i64;
+    fn contains(a: Self, b: Self) -> bool;
+}
+"#);
+        let err = module_env.find_trait_method("area").expect_err("ambiguous");
+        assert!(err.to_string().contains("Ambiguous trait method call 'area': it is declared by traits Region, Square."), "{}", err);
+
+        // 親のメソッドを宣言し直した子トレイトの宣言を使う（親との間では曖昧にならない）
+        let mut module_env = module_env;
+        module_env.traits.remove("Region");
+        let (trait_name, method) = module_env.find_trait_method("area").unwrap().unwrap();
+        assert_eq!((trait_name.as_str(), method.param_constraints), ("Square", vec![Some("v >= 0".to_string())]));
+        assert!(module_env.find_trait_method("missing").unwrap().is_none());
+    }
+}
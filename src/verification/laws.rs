@@ -0,0 +1,796 @@
+// =============================================================================
+// impl の法則充足性検証 (Law Verification)
+// =============================================================================
+
+use super::*;
+
+/// law 式内のメソッド呼び出しを impl body で展開する（AST 上で置き換える）。
+///
+/// 例: law = "add(a, b) == add(b, a)", impl body = "a + b"
+/// → "a + b == b + a"
+///
+/// 呼び出し `method(x, y)` は、body の仮引数を実引数の式に同時に置き換えた式になる。
+/// 実引数や body に残ったメソッド呼び出し（例: "leq(a, b) && leq(b, c)" の body が別メソッドを呼ぶ場合）は
+/// 次のパスで展開する。
+/// サイズ上限付きの展開は expand_law を使う（law 本体はそちらで展開する）。
+fn substitute_method_calls(
+    expr: &Expr,
+    method_bodies: &HashMap<String, Expr>,
+    method_params: &HashMap<String, Vec<String>>,
+) -> Expr {
+    let mut result = expr.clone();
+
+    // 各メソッドについて繰り返し展開（ネスト対応のため複数パス）
+    for _pass in 0..MAX_LAW_EXPANSION_PASSES {
+        if substitute_method_calls_once(&mut result, method_bodies, method_params).is_empty() { break; }
+    }
+
+    result
+}
+
+/// law 展開結果の AST ノード数の既定の上限（`[proof] max_law_expansion_nodes`）
+pub const DEFAULT_MAX_LAW_EXPANSION_NODES: usize = 10_000;
+
+/// law 内のメソッド呼び出しを展開するパス数の上限（body が別メソッドを呼ぶネスト用）
+const MAX_LAW_EXPANSION_PASSES: usize = 5;
+
+/// law を展開した結果
+struct LawExpansion {
+    expanded: Expr,
+    /// 展開後の AST ノード数
+    nodes: usize,
+}
+
+/// law 式のメソッド呼び出しを展開し、パスごとに AST ノード数を数える。
+/// ノード数が limit を超えた時点で展開を打ち切り、law 名と
+/// それまでに展開したメソッドの連鎖（パスごと）を含むエラーを返す。
+fn expand_law(
+    law_name: &str,
+    law_expr: &str,
+    method_bodies: &HashMap<String, Expr>,
+    method_params: &HashMap<String, Vec<String>>,
+    limit: usize,
+) -> Result<LawExpansion, String> {
+    let mut expanded = parse_expression(law_expr).map_err(|e| format!("law '{}': {}", law_name, e))?;
+    let mut nodes = expr_node_count(&expanded);
+    let mut chain: Vec<String> = Vec::new();
+
+    for _pass in 0..MAX_LAW_EXPANSION_PASSES {
+        let expanded_methods = substitute_method_calls_once(&mut expanded, method_bodies, method_params);
+        if expanded_methods.is_empty() { break; }
+
+        let mut pass_methods: Vec<String> = Vec::new();
+        for method in expanded_methods {
+            if !pass_methods.contains(&method) { pass_methods.push(method); }
+        }
+        chain.push(pass_methods.join(", "));
+
+        nodes = expr_node_count(&expanded);
+        if nodes > limit {
+            return Err(format!(
+                "law '{}' expands to {} AST nodes after {} round(s) of method expansion (limit {}; raise `[proof] max_law_expansion_nodes` to allow it)\n  Method chain: {}",
+                law_name, nodes, chain.len(), limit, chain.join(" → ")
+            ));
+        }
+    }
+
+    Ok(LawExpansion { expanded, nodes })
+}
+
+/// 式の AST ノード数（law 展開サイズの計測用）
+fn expr_node_count(expr: &Expr) -> usize {
+    let mut nodes = 0;
+    expr.walk(|_| {
+        nodes += 1;
+        true
+    });
+    nodes
+}
+
+/// substitute_method_calls の 1 パス分。expr の中のメソッド呼び出しを body に置き換え、
+/// 展開したメソッド名（出現順）を返す。置き換えた式の中には降りない（次のパスで展開する）
+fn substitute_method_calls_once(
+    expr: &mut Expr,
+    method_bodies: &HashMap<String, Expr>,
+    method_params: &HashMap<String, Vec<String>>,
+) -> Vec<String> {
+    let mut expanded_methods = Vec::new();
+    expr.walk_mut(|e| {
+        let expanded = match e {
+            Expr::Call(name, args) => match method_bodies.get(name.as_str()) {
+                Some(body) => {
+                    expanded_methods.push(name.clone());
+                    instantiate_method_body(body, method_params.get(name.as_str()).map_or(&[], Vec::as_slice), args)
+                }
+                None => return true,
+            },
+            _ => return true,
+        };
+        *e = expanded;
+        false
+    });
+    expanded_methods
+}
+
+/// body の仮引数を実引数の式に同時に置き換える。
+/// 実引数に後続の仮引数名が含まれても再置換されないよう、いったん内部名を経由する
+fn instantiate_method_body(body: &Expr, params: &[String], args: &[Expr]) -> Expr {
+    let mut expanded = body.clone();
+    let bound = params.len().min(args.len());
+    for (j, param) in params.iter().take(bound).enumerate() {
+        expanded = substitute_bound(&expanded, param, &Expr::Variable(format!("__arg_{}__", j)));
+    }
+    for (j, arg) in args.iter().take(bound).enumerate() {
+        expanded = substitute_bound(&expanded, &format!("__arg_{}__", j), arg);
+    }
+    expanded
+}
+
+/// 単語境界を考慮した文字列置換。
+/// "a" を置換する際に "a" 単体のみマッチし、"add" 内の "a" にはマッチしない。
+fn replace_word(source: &str, word: &str, replacement: &str) -> String {
+    let mut result = String::new();
+    let chars: Vec<char> = source.chars().collect();
+    let word_chars: Vec<char> = word.chars().collect();
+    let mut i = 0;
+
+    while i < chars.len() {
+        if i + word_chars.len() <= chars.len()
+            && chars[i..i + word_chars.len()] == word_chars[..]
+            && (i == 0 || !chars[i - 1].is_alphanumeric() && chars[i - 1] != '_')
+            && (i + word_chars.len() >= chars.len() || !chars[i + word_chars.len()].is_alphanumeric() && chars[i + word_chars.len()] != '_')
+        {
+            result.push_str(replacement);
+            i += word_chars.len();
+        } else {
+            result.push(chars[i]);
+            i += 1;
+        }
+    }
+
+    result
+}
+
+/// カンマで引数を分割する（ネストした括弧を考慮）。
+fn split_args(input: &str) -> Vec<String> {
+    let mut result = Vec::new();
+    let mut depth = 0;
+    let mut current = String::new();
+    for c in input.chars() {
+        match c {
+            '(' => { depth += 1; current.push(c); }
+            ')' => { depth -= 1; current.push(c); }
+            ',' if depth == 0 => {
+                result.push(current.trim().to_string());
+                current.clear();
+            }
+            _ => { current.push(c); }
+        }
+    }
+    let trimmed = current.trim().to_string();
+    if !trimmed.is_empty() {
+        result.push(trimmed);
+    }
+    result
+}
+
+/// law 式の自由変数（出現順・重複なし）。メソッド名と forall / exists の束縛変数は含まない。
+/// law を検証するシンボルの宣言と反例の表示に使う
+fn law_variables(law_expr: &str) -> MumeiResult<Vec<String>> {
+    let law_ast = parse_expression(law_expr).map_err(MumeiError::from)?;
+    let mut names = Vec::new();
+    contract_variables(&law_ast, &mut names);
+    Ok(names)
+}
+
+/// トレイトメソッドの仮引数名（慣例的に a, b, c, ... を使用）
+pub(super) fn trait_param_name(index: usize) -> &'static str {
+    const NAMES: [&str; 6] = ["a", "b", "c", "d", "e", "f"];
+    NAMES.get(index).copied().unwrap_or("x")
+}
+
+/// 式中の method 呼び出しをすべて（ネストした呼び出しも含めて）列挙し、各呼び出しの実引数リストを返す。
+fn collect_method_calls(expr: &str, method_name: &str) -> Vec<Vec<String>> {
+    let chars: Vec<char> = expr.chars().collect();
+    let mn_chars: Vec<char> = method_name.chars().collect();
+    let mut calls = Vec::new();
+    let mut i = 0;
+    while i + mn_chars.len() < chars.len() {
+        if chars[i..i + mn_chars.len()] == mn_chars[..]
+            && chars[i + mn_chars.len()] == '('
+            && (i == 0 || !chars[i - 1].is_alphanumeric() && chars[i - 1] != '_')
+        {
+            let args_start = i + mn_chars.len() + 1;
+            let mut depth = 1;
+            let mut args_end = args_start;
+            while args_end < chars.len() {
+                match chars[args_end] {
+                    '(' => depth += 1,
+                    ')' => {
+                        depth -= 1;
+                        if depth == 0 { break; }
+                    }
+                    _ => {}
+                }
+                args_end += 1;
+            }
+            let args_str: String = chars[args_start..args_end].iter().collect();
+            calls.push(split_args(&args_str));
+        }
+        // 引数内のネストした呼び出しも拾うため、1文字ずつ進める
+        i += 1;
+    }
+    calls
+}
+
+/// law 式中のトレイトメソッド呼び出しについて、パラメータ制約（例: `b: Self where v != 0`）を
+/// 実引数に具体化した前提条件を返す。
+/// 例: law "div(a, a) == 1" と制約 [None, Some("v != 0")] → ["(a) != 0"]
+fn law_param_assumptions(law_expr: &str, methods: &[TraitMethod]) -> Vec<String> {
+    let mut assumptions = Vec::new();
+    for method in methods {
+        if method.param_constraints.iter().all(|c| c.is_none()) {
+            continue;
+        }
+        for args in collect_method_calls(law_expr, &method.name) {
+            for (constraint, arg) in method.param_constraints.iter().zip(args.iter()) {
+                if let Some(constraint) = constraint {
+                    let assumption = replace_word(constraint, "v", &format!("({})", arg));
+                    if !assumptions.contains(&assumption) {
+                        assumptions.push(assumption);
+                    }
+                }
+            }
+        }
+    }
+    assumptions
+}
+
+/// extends を辿ってトレイトのメソッドと law を推移的に収集する。
+/// 親トレイトのメソッド名・law 式にはリネーム表（親メソッド名 → 子メソッド名）を適用し、
+/// 戻り値はすべて trait_name 自身のメソッド名で表現される。
+/// 継承した law の名前は "親トレイト名::law 名" とする。
+/// extends グラフに循環がある場合はエラー。
+fn collect_trait_closure(
+    trait_name: &str,
+    module_env: &ModuleEnv,
+    visiting: &mut Vec<String>,
+) -> MumeiResult<(Vec<TraitMethod>, Vec<(String, String)>)> {
+    if let Some(pos) = visiting.iter().position(|t| t == trait_name) {
+        let mut cycle: Vec<String> = visiting[pos..].to_vec();
+        cycle.push(trait_name.to_string());
+        return Err(MumeiError::TypeError(
+            format!("Cyclic trait extends detected: {}", cycle.join(" -> "))
+        ));
+    }
+    let trait_def = module_env.get_trait(trait_name)
+        .ok_or_else(|| MumeiError::TypeError(format!("Trait '{}' not found", trait_name)))?;
+
+    visiting.push(trait_name.to_string());
+    let mut methods: Vec<TraitMethod> = Vec::new();
+    let mut laws: Vec<(String, String)> = Vec::new();
+    for (parent_name, renames) in &trait_def.extends {
+        let (parent_methods, parent_laws) = collect_trait_closure(parent_name, module_env, visiting)?;
+        for mut method in parent_methods {
+            if let Some(renamed) = renames.get(&method.name) {
+                method.name = renamed.clone();
+            }
+            if !methods.iter().any(|m| m.name == method.name) {
+                methods.push(method);
+            }
+        }
+        for (law_name, law_expr) in parent_laws {
+            let qualified = if law_name.contains("::") { law_name } else { format!("{}::{}", parent_name, law_name) };
+            laws.push((qualified, rename_methods(&law_expr, renames)));
+        }
+    }
+    visiting.pop();
+
+    // 子トレイト自身の宣言は継承したメソッドを上書きする
+    for method in &trait_def.methods {
+        methods.retain(|m| m.name != method.name);
+        methods.push(method.clone());
+    }
+    laws.extend(trait_def.laws.iter().cloned());
+    Ok((methods, laws))
+}
+
+/// law 式内のメソッド名を同時に置換する（swap 的なリネームでも連鎖しないよう一旦プレースホルダを経由）
+fn rename_methods(expr: &str, renames: &HashMap<String, String>) -> String {
+    let mut result = expr.to_string();
+    let mut placeholders = Vec::new();
+    for (i, (from, to)) in renames.iter().enumerate() {
+        let placeholder = format!("__rename_{}__", i);
+        result = replace_word(&result, from, &placeholder);
+        placeholders.push((placeholder, to));
+    }
+    for (placeholder, to) in placeholders {
+        result = replace_word(&result, &placeholder, to);
+    }
+    result
+}
+
+/// impl が対応する trait の全 law を満たしているかを Z3 で検証する。
+/// 各 law の論理式内のメソッド呼び出しを impl の具体的な body で置換し、
+/// ∀x. law_expr が成立するかを検証する。
+pub fn verify_impl(impl_def: &ImplDef, module_env: &ModuleEnv) -> MumeiResult<()> {
+    let trait_def = module_env.get_trait(&impl_def.trait_name)
+        .ok_or_else(|| MumeiError::TypeError(
+            format!("Trait '{}' not found for impl on '{}'", impl_def.trait_name, impl_def.target_type)
+        ))?;
+
+    // extends で継承したメソッドと law も含めて収集（リネーム適用済み）
+    let (all_methods, all_laws) = collect_trait_closure(&trait_def.name, module_env, &mut Vec::new())?;
+
+    // メソッドの完全性チェック: trait の全メソッド（継承分を含む）が impl されているか
+    for method in &all_methods {
+        if !impl_def.method_bodies.iter().any(|(name, _)| name == &method.name) {
+            return Err(MumeiError::TypeError(
+                format!("impl {} for {}: missing method '{}'", impl_def.trait_name, impl_def.target_type, method.name)
+            ));
+        }
+    }
+
+    // 各 law を Z3 で検証（暴走した law は watchdog が止める）。
+    // 全 law でプールの solver を共有し、law ごとに push/pop する
+    let guard = SolverGuard::new(5000, module_env.solver_limits);
+    with_pooled_solver(&guard, |ctx, solver| verify_impl_laws(impl_def, module_env, &all_methods, &all_laws, &guard, ctx, solver))
+}
+
+/// law 展開用のテーブル（メソッド名 → impl body の AST、メソッド名 → 仮引数名）
+type LawExpansionTables = (HashMap<String, Expr>, HashMap<String, Vec<String>>);
+
+/// impl と trait のメソッドから law 展開用のテーブルを構築する
+fn law_expansion_tables(impl_def: &ImplDef, all_methods: &[TraitMethod]) -> MumeiResult<LawExpansionTables> {
+    // impl のメソッド body マップを構築（未解釈関数展開用）
+    let method_body_map: HashMap<String, Expr> = impl_def.method_bodies.iter()
+        .map(|(name, body)| match parse_expression(body) {
+            Ok(body_ast) => Ok((name.clone(), body_ast)),
+            Err(e) => Err(MumeiError::TypeError(format!(
+                "impl {} for {}: method '{}': {}", impl_def.trait_name, impl_def.target_type, name, e
+            ))),
+        })
+        .collect::<MumeiResult<_>>()?;
+
+    // メソッドのパラメータ名マップを構築（trait 定義から取得）
+    // law 式内の関数呼び出し `method(a, b)` を body 式に展開する際、
+    // 仮引数名（a, b）を実引数に置換するために使用
+    let method_param_names: HashMap<String, Vec<String>> = all_methods.iter()
+        .map(|m| {
+            // トレイトメソッドのパラメータ名は慣例的に a, b, c, ... を使用
+            let param_names: Vec<String> = (0..m.param_types.len())
+                .map(|i| trait_param_name(i).to_string())
+                .collect();
+            (m.name.clone(), param_names)
+        })
+        .collect();
+
+    Ok((method_body_map, method_param_names))
+}
+
+/// impl の各 law を展開した後の AST ノード数を law の順に返す（`--verbose` の表示用）
+pub fn law_expansion_sizes(impl_def: &ImplDef, module_env: &ModuleEnv) -> MumeiResult<Vec<(String, usize)>> {
+    let trait_def = module_env.get_trait(&impl_def.trait_name)
+        .ok_or_else(|| MumeiError::TypeError(
+            format!("Trait '{}' not found for impl on '{}'", impl_def.trait_name, impl_def.target_type)
+        ))?;
+    let (all_methods, all_laws) = collect_trait_closure(&trait_def.name, module_env, &mut Vec::new())?;
+    let (method_body_map, method_param_names) = law_expansion_tables(impl_def, &all_methods)?;
+    let limit = module_env.max_law_expansion_nodes.unwrap_or(DEFAULT_MAX_LAW_EXPANSION_NODES);
+    all_laws.iter()
+        .map(|(law_name, law_expr)| {
+            expand_law(law_name, law_expr, &method_body_map, &method_param_names, limit)
+                .map(|expansion| (law_name.clone(), expansion.nodes))
+                .map_err(MumeiError::from)
+        })
+        .collect()
+}
+
+/// law の検証で失敗した義務（委譲先 atom の requires など）のエラーに、どの impl のどの law かを付記する
+fn in_law(error: MumeiError, impl_def: &ImplDef, law_name: &str) -> MumeiError {
+    let location = format!("impl {} for {}: law '{}'", impl_def.trait_name, impl_def.target_type, law_name);
+    match error {
+        MumeiError::VerificationError(m) => MumeiError::VerificationError(format!("{}: {}", location, m)),
+        MumeiError::VerificationFailure(kind, m) => MumeiError::VerificationFailure(kind, format!("{}: {}", location, m)),
+        MumeiError::CodegenError(m) => MumeiError::CodegenError(format!("{}: {}", location, m)),
+        MumeiError::TypeError(m) => MumeiError::TypeError(format!("{}: {}", location, m)),
+    }
+}
+
+/// impl の各 law を検証する（verify_impl から solver プール上で呼ばれる）
+fn verify_impl_laws<'ctx>(
+    impl_def: &ImplDef,
+    module_env: &ModuleEnv,
+    all_methods: &[TraitMethod],
+    all_laws: &[(String, String)],
+    guard: &SolverGuard,
+    ctx: &'ctx Context,
+    solver: &Solver<'ctx>,
+) -> MumeiResult<()> {
+    let scope = format!("impl_{}_for_{}", impl_def.trait_name, impl_def.target_type);
+    let (method_body_map, method_param_names) = law_expansion_tables(impl_def, all_methods)?;
+    let limit = module_env.max_law_expansion_nodes.unwrap_or(DEFAULT_MAX_LAW_EXPANSION_NODES);
+
+    for (law_name, law_expr) in all_laws {
+        // law 内のメソッド呼び出しを impl body で置換
+        // 例: law "add(a, b) == add(b, a)" で impl body が "a + b" の場合、
+        // "add(a, b)" → "(a + b)", "add(b, a)" → "(b + a)" に展開
+        // 展開結果が上限を超える law は Z3 に渡さずエラーにする
+        let law_ast = expand_law(law_name, law_expr, &method_body_map, &method_param_names, limit)
+            .map_err(|e| MumeiError::VerificationError(format!(
+                "impl {} for {}: {}", impl_def.trait_name, impl_def.target_type, e
+            )))?
+            .expanded;
+
+        // シンボリック変数で law を検証
+        let vc = VCtx { ctx, arrays: RefCell::default(), params: &[], module_env, obligations: None, guard: None, scope: &scope, counters: RefCell::default(), total: false, undecided: RefCell::default(), arm_stack: RefCell::default(), arm_paths: RefCell::default(), entry_measure: RefCell::default(), futures: RefCell::default(), call_lengths: RefCell::default(), struct_values: RefCell::default(), float_defs: RefCell::default(), callees: RefCell::default(), warnings: None };
+
+        let mut env: Env = HashMap::new();
+        // law 内の自由変数を impl の対象型のシンボルとして登録
+        let law_vars = law_variables(law_expr).map_err(|e| in_law(e, impl_def, law_name))?;
+        let base = module_env.resolve_base_type(&impl_def.target_type);
+        for var_name in &law_vars {
+            let var: Dynamic = match base.as_str() {
+                "f64" => Float::new_const(ctx, vc.sym(var_name), 11, 53).into(),
+                _ => Int::new_const(ctx, vc.sym(var_name)).into(),
+            };
+            env.insert(var_name.clone(), var);
+        }
+
+        // メソッドのパラメータ制約を law 変数に関する前提として仮定する
+        // 例: div(a, b where v != 0) を含む law では (b) != 0 を前提に検証し、ゼロ除算の場合を除外する
+        let assumptions = law_param_assumptions(law_expr, all_methods);
+
+        // law 式をパースして検証。
+        // 展開後の law に残るモジュールの atom 呼び出し（impl body からの委譲）は atom body と同じく
+        // 契約で扱う: requires を law の前提のもとで証明し、ensures を新しい結果シンボルについて仮定する
+        // 前提を黙って捨てると law が反例付きで失敗するので、翻訳できない制約はエラーにする
+        solver.push();
+        for assumption in &assumptions {
+            let assumption_bool = parse_expression(assumption).map_err(MumeiError::from)
+                .and_then(|assumption_ast| expr_to_z3(&vc, &substitute_method_calls(&assumption_ast, &method_body_map, &method_param_names), &mut env, None))
+                .and_then(|assumption_z3| assumption_z3.as_bool().ok_or_else(|| MumeiError::TypeError(format!(
+                    "parameter constraint '{}' must be a boolean expression", assumption
+                ))));
+            match assumption_bool {
+                Ok(assumption_bool) => solver.assert(&assumption_bool),
+                Err(e) => {
+                    solver.pop(1);
+                    return Err(in_law(e, impl_def, law_name));
+                }
+            }
+        }
+        let law_bool = match expr_to_z3(&vc, &law_ast, &mut env, Some(solver)).map(|law_z3| law_z3.as_bool()) {
+            Ok(Some(law_bool)) => law_bool,
+            Ok(None) => {
+                solver.pop(1);
+                return Err(MumeiError::TypeError(format!(
+                    "impl {} for {}: law '{}' must be a boolean expression\n  Law: {}",
+                    impl_def.trait_name, impl_def.target_type, law_name, law_expr
+                )));
+            }
+            Err(e) => {
+                solver.pop(1);
+                return Err(in_law(e, impl_def, law_name));
+            }
+        };
+        solver.assert(&law_bool.not());
+        let law_result = guard.check(ctx, solver);
+        if let Some(failure) = guard.failure() {
+            solver.pop(1);
+            return Err(MumeiError::failure(FailureKind::Law, format!(
+                "impl {} for {}: law '{}': {}",
+                impl_def.trait_name, impl_def.target_type, law_name, failure
+            )));
+        }
+        if law_result == SatResult::Sat {
+            // 反例（Counter-example）を Z3 model から取得
+            // law 式の自由変数を表示し、型は impl の対象型とする
+            let counterexample = if let Some(model) = solver.get_model() {
+                let symbols: Vec<(String, String, Dynamic)> = law_vars.iter()
+                    .filter_map(|var_name| env.get(var_name)
+                        .map(|sym| (var_name.clone(), impl_def.target_type.clone(), sym.clone())))
+                    .collect();
+                let ce = model_counterexample(&model, &symbols, module_env);
+                if ce.is_empty() {
+                    Counterexample { note: Some("no concrete values available".to_string()), ..Default::default() }
+                } else {
+                    ce
+                }
+            } else {
+                Counterexample { note: Some("could not retrieve model".to_string()), ..Default::default() }
+            };
+            solver.pop(1);
+            let mut failure = format!(
+                "impl {} for {}: law '{}' is not satisfied\n  Law: {}\n  Expanded: {}",
+                impl_def.trait_name, impl_def.target_type,
+                law_name, law_expr, crate::printer::show(&law_ast).unwrap_or_else(|| law_expr.clone())
+            );
+            if !assumptions.is_empty() {
+                failure.push_str(&format!("\n  Assuming: {}", assumptions.join(" && ")));
+            }
+            for callee in vc.callees.borrow().values() {
+                failure.push_str(&format!("\n  Using: {} (ensures: {})", callee.name, callee.ensures));
+            }
+            return Err(MumeiError::failure(FailureKind::Law,
+                counterexample.render(&failure, module_env.counterexample_format)
+            ));
+        }
+        solver.pop(1);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Item;
+    use crate::test_support::{env_from_source, find_atom, TempDir};
+
+    #[test]
+    fn test_inherited_law_with_rename_fails() {
+        let source = r#"
+trait Semigroup {
+    fn add(a: Self, b: Self) -> Self;
+    law associative: add(add(a, b), c) == add(a, add(b, c));
+}
+
+trait Monoid extends Semigroup with add = combine {
+    fn unit() -> Self;
+    law left_identity: combine(unit(), a) == a;
+}
+
+impl Monoid for i64 {
+    fn combine(a: i64, b: i64) -> i64 { 2 * a + b }
+    fn unit() -> i64 { 0 }
+}
+"#;
+        let (items, module_env) = env_from_source(source);
+        let impl_def = items.iter().find_map(|i| if let Item::ImplDef(d) = i { Some(d) } else { None }).unwrap();
+        let err = verify_impl(impl_def, &module_env).expect_err("inherited associativity must fail");
+        let msg = err.to_string();
+        assert!(msg.contains("Semigroup::associative"), "{}", msg);
+    }
+
+    #[test]
+    fn test_trait_extends_cycle_detected() {
+        let source = r#"
+trait A extends B {
+    fn f(a: Self) -> Self;
+}
+
+trait B extends A {
+    fn g(a: Self) -> Self;
+}
+
+impl A for i64 {
+    fn f(a: i64) -> i64 { a }
+    fn g(a: i64) -> i64 { a }
+}
+"#;
+        let (items, module_env) = env_from_source(source);
+        let impl_def = items.iter().find_map(|i| if let Item::ImplDef(d) = i { Some(d) } else { None }).unwrap();
+        let err = verify_impl(impl_def, &module_env).expect_err("cycle must be rejected");
+        assert!(err.to_string().contains("Cyclic trait extends"), "{}", err);
+    }
+
+    const SAFE_DIV_SOURCE: &str = r#"
+trait SafeDiv {
+    fn div(a: Self, b: Self where v != 0) -> Self;
+    law div_self: div(a, a) == 1;
+}
+
+impl SafeDiv for i64 {
+    fn div(a: i64, b: i64) -> i64 { a / b }
+}
+
+atom ratio(x: i64, y: i64)
+requires: y > 0;
+ensures: true;
+body: div(x, y);
+
+atom unchecked_ratio(x: i64, y: i64)
+requires: y >= 0;
+ensures: true;
+body: div(x, y);
+"#;
+
+    #[test]
+    fn test_law_assumes_trait_param_constraints() {
+        let (items, module_env) = env_from_source(SAFE_DIV_SOURCE);
+        let impl_def = items.iter().find_map(|i| if let Item::ImplDef(d) = i { Some(d) } else { None }).unwrap();
+        assert!(verify_impl(impl_def, &module_env).is_ok(), "div_self holds once the divisor constraint is assumed");
+
+        // 制約を外すと a == 0 の場合に law が成立しない
+        let unconstrained = SAFE_DIV_SOURCE.replace("b: Self where v != 0", "b: Self");
+        let (items, module_env) = env_from_source(&unconstrained);
+        let impl_def = items.iter().find_map(|i| if let Item::ImplDef(d) = i { Some(d) } else { None }).unwrap();
+        let err = verify_impl(impl_def, &module_env).expect_err("div_self must fail without the constraint");
+        assert!(err.to_string().contains("div_self"), "{}", err);
+    }
+
+    const RATIO_SOURCE: &str = r#"
+trait Ratio {
+    fn div(a: Self, b: Self where v != 0) -> Self;
+    fn neg(a: Self) -> Self;
+    law self_div: div(a, a) == 1 && div(neg(b), neg(b)) == 1;
+}
+
+impl Ratio for i64 {
+    fn div(a: i64, b: i64) -> i64 { a / b }
+    fn neg(a: i64) -> i64 { 0 - a }
+}
+"#;
+
+    #[test]
+    fn test_param_constraints_apply_per_call() {
+        // div の呼び出しごとに実引数で制約を具体化する: (a) != 0 と (neg(b)) != 0
+        let methods = vec![
+            TraitMethod { name: "div".into(), param_types: vec!["Self".into(), "Self".into()], return_type: "Self".into(), param_constraints: vec![None, Some("v != 0".into())] },
+            TraitMethod { name: "neg".into(), param_types: vec!["Self".into()], return_type: "Self".into(), param_constraints: vec![None] },
+        ];
+        assert_eq!(
+            law_param_assumptions("div(a, a) == 1 && div(neg(b), neg(b)) == 1", &methods),
+            vec!["(a) != 0".to_string(), "(neg(b)) != 0".to_string()]
+        );
+
+        // b == 0 は 2 つ目の呼び出しの制約で除外されるので law が成立する
+        let (items, module_env) = env_from_source(RATIO_SOURCE);
+        let impl_def = items.iter().find_map(|i| if let Item::ImplDef(d) = i { Some(d) } else { None }).unwrap();
+        assert!(verify_impl(impl_def, &module_env).is_ok());
+
+        // 翻訳できない制約は黙って捨てずに law のエラーにする
+        let broken = RATIO_SOURCE.replace("where v != 0", "where v + 1");
+        let (items, module_env) = env_from_source(&broken);
+        let impl_def = items.iter().find_map(|i| if let Item::ImplDef(d) = i { Some(d) } else { None }).unwrap();
+        let err = verify_impl(impl_def, &module_env).expect_err("a non-boolean constraint must be reported").to_string();
+        assert!(err.contains("law 'self_div'") && err.contains("must be a boolean expression"), "{}", err);
+    }
+
+    const TOWER_SOURCE: &str = r#"
+trait Tower {
+    fn sq(a: Self) -> Self;
+    fn quad(a: Self) -> Self;
+    fn oct(a: Self) -> Self;
+    law oct_refl: oct(x) == oct(x);
+}
+
+impl Tower for i64 {
+    fn sq(a: i64) -> i64 { a * a }
+    fn quad(a: i64) -> i64 { sq(a) * sq(a) }
+    fn oct(a: i64) -> i64 { quad(a) * quad(a) }
+}
+"#;
+
+    #[test]
+    fn test_method_calls_expand_on_the_ast() {
+        let bodies: HashMap<String, Expr> = [("sub", "a - b"), ("eq", "a == b"), ("twice", "sub(a, 0 - a)")].into_iter()
+            .map(|(name, body)| (name.to_string(), parse_expression(body).unwrap()))
+            .collect();
+        let params: HashMap<String, Vec<String>> = bodies.keys().map(|name| (name.clone(), vec!["a".to_string(), "b".to_string()])).collect();
+        let expand = |law: &str| crate::printer::show(&substitute_method_calls(&parse_expression(law).unwrap(), &bodies, &params)).unwrap();
+
+        // 実引数に仮引数名が含まれても、仮引数は同時に置き換える
+        assert_eq!(expand("sub(b, a)"), "b - a");
+        // 実引数は式のまま差し込むので、優先順位は AST が保つ
+        assert_eq!(expand("sub(a, b + 1) == 0"), "a - (b + 1) == 0");
+        // 識別子の一部（approx_eq の eq）は呼び出しではない
+        assert_eq!(expand("approx_eq(a, b)"), "approx_eq(a, b)");
+        // body が別メソッドを呼ぶ場合は次のパスで展開する
+        assert_eq!(expand("twice(x, y)"), "x - (0 - x)");
+    }
+
+    #[test]
+    fn test_law_variables_are_free_identifiers() {
+        // メソッド名（max の x を含む）と量化子の束縛変数は数えず、a/b/c/x/y/z 以外の名前も拾う
+        assert_eq!(law_variables("max(lhs, rhs) >= lhs").unwrap(), vec!["lhs", "rhs"]);
+        assert_eq!(law_variables("forall(i, 0, n, combine(i, acc) >= i)").unwrap(), vec!["n", "acc"]);
+        assert!(law_variables("max(").is_err());
+    }
+
+    #[test]
+    fn test_law_counterexample_reports_law_variables() {
+        let source = r#"
+trait Bounded {
+    fn max(lhs: Self, rhs: Self) -> Self;
+    law upper: max(lhs, rhs) >= rhs;
+}
+
+impl Bounded for i64 {
+    fn max(lhs: i64, rhs: i64) -> i64 { lhs }
+}
+"#;
+        let (items, module_env) = env_from_source(source);
+        let impl_def = items.iter().find_map(|i| if let Item::ImplDef(d) = i { Some(d) } else { None }).unwrap();
+        let msg = verify_impl(impl_def, &module_env).expect_err("max returning lhs must violate the law").to_string();
+        assert!(msg.contains("lhs: i64 = ") && msg.contains("rhs: i64 = "), "{}", msg);
+        // max の x は law の変数ではない
+        assert!(!msg.contains("x: i64"), "{}", msg);
+    }
+
+    #[test]
+    fn test_law_expansion_limit() {
+        let (items, mut module_env) = env_from_source(TOWER_SOURCE);
+        let impl_def = items.iter().find_map(|i| if let Item::ImplDef(d) = i { Some(d) } else { None }).unwrap();
+        assert!(verify_impl(impl_def, &module_env).is_ok());
+
+        // oct → quad → sq の 3 段の展開でノード数が倍々に増える
+        let sizes = law_expansion_sizes(impl_def, &module_env).unwrap();
+        assert_eq!(sizes.len(), 1);
+        assert_eq!(sizes[0].0, "oct_refl");
+        assert!(sizes[0].1 > 25, "{:?}", sizes);
+
+        module_env.max_law_expansion_nodes = Some(25);
+        let err = verify_impl(impl_def, &module_env).expect_err("expansion exceeds the limit");
+        let msg = err.to_string();
+        assert!(msg.contains("impl Tower for i64: law 'oct_refl' expands to"), "{}", msg);
+        assert!(msg.contains("limit 25"), "{}", msg);
+        assert!(msg.contains("Method chain: oct → quad → sq"), "{}", msg);
+        assert!(law_expansion_sizes(impl_def, &module_env).is_err());
+    }
+
+    const DELEGATE_SOURCE: &str = r#"
+trait Measure {
+    fn size(a: Self) -> Self;
+    law nonneg: size(x) >= 0;
+}
+
+atom magnitude(n: i64)
+requires: true;
+ensures: result >= 0 && (result == n || result == 0 - n);
+body: if n >= 0 { n } else { 0 - n };
+
+atom predecessor(n: i64)
+requires: true;
+ensures: result == n - 1;
+body: n - 1;
+
+atom halve(n: i64)
+requires: n >= 0;
+ensures: result >= 0;
+body: n / 2;
+
+impl Measure for i64 {
+    fn size(a: i64) -> i64 { magnitude(a) }
+}
+"#;
+
+    #[test]
+    fn test_impl_delegates_to_atom_contract() {
+        let impl_for = |source: &str| {
+            let (items, module_env) = env_from_source(source);
+            let impl_def = items.iter().find_map(|i| if let Item::ImplDef(d) = i { Some(d.clone()) } else { None }).unwrap();
+            verify_impl(&impl_def, &module_env)
+        };
+
+        // magnitude の ensures (result >= 0) で nonneg が閉じる
+        assert!(impl_for(DELEGATE_SOURCE).is_ok());
+
+        // predecessor の ensures では nonneg を導けない: law 名と委譲先が報告される
+        let err = impl_for(&DELEGATE_SOURCE.replace("{ magnitude(a) }", "{ predecessor(a) }"))
+            .expect_err("predecessor(x) may be negative").to_string();
+        assert!(err.contains("law 'nonneg' is not satisfied"), "{}", err);
+        assert!(err.contains("Using: predecessor (ensures: result == n - 1)"), "{}", err);
+
+        // 委譲先の requires は law の量化変数について証明する（x < 0 では halve を呼べない）
+        let err = impl_for(&DELEGATE_SOURCE.replace("{ magnitude(a) }", "{ halve(a) }"))
+            .expect_err("halve requires a non-negative argument").to_string();
+        assert!(err.contains("impl Measure for i64: law 'nonneg'"), "{}", err);
+        assert!(err.contains("Call to 'halve': precondition (requires) not satisfied"), "{}", err);
+    }
+
+    #[test]
+    fn test_trait_method_call_site_obligation() {
+        let (items, module_env) = env_from_source(SAFE_DIV_SOURCE);
+        let atom = |name: &str| find_atom(&items, name);
+        let output_dir = TempDir::new("trait_call");
+
+        assert!(verify(&atom("ratio"), &output_dir, &module_env).is_ok());
+        let err = verify(&atom("unchecked_ratio"), &output_dir, &module_env).expect_err("y may be 0");
+        let msg = err.to_string();
+        assert!(msg.contains("SafeDiv::div"), "{}", msg);
+        assert!(msg.contains("parameter 'b'"), "{}", msg);
+        assert!(msg.contains("v != 0"), "{}", msg);
+    }
+}
@@ -0,0 +1,729 @@
+// =============================================================================
+// 線形性チェック（Linear Types / Ownership Tracking）
+// =============================================================================
+//
+// 動的メモリ管理における二重解放・Use-After-Free を防ぐために、
+// 変数の「生存状態」を追跡する。
+//
+// 設計:
+// - LinearityCtx が各変数の生存フラグ (is_alive) を管理
+// - consume(x) 呼び出し時に x を「消費済み」としてマーク
+// - 消費済み変数へのアクセスはコンパイルエラー
+//
+// 将来の拡張:
+// - atom のパラメータに `consume` 修飾子を追加
+//   例: atom take_ownership(resource: T) consume resource;
+// - Z3 上で is_alive フラグをシンボリック Bool として表現し、
+//   consume 後のアクセスを ¬is_alive(x) として検出
+
+use super::*;
+
+/// 変数の線形性（所有権）追跡コンテキスト
+///
+/// 所有権（Ownership）と借用（Borrowing）の両方を追跡する。
+/// - consume: 所有権を消費（移動）。消費後のアクセスは Use-After-Free。
+/// - borrow: 読み取り専用の借用。借用中は所有者が consume/free できない。
+/// - release_borrow: 借用を解放。
+#[derive(Debug, Clone, Default)]
+pub struct LinearityCtx {
+    /// 変数名 → 生存状態（true = alive, false = consumed）
+    alive: HashMap<String, bool>,
+    /// 変数名 → 借用カウント（0 = 借用なし、1+ = 借用中）
+    borrow_count: HashMap<String, usize>,
+    /// 変数名 → 借用元の変数名リスト（誰がこの変数を借用しているか）
+    borrowers: HashMap<String, Vec<String>>,
+    /// 消費済み変数のアクセス違反リスト
+    violations: Vec<String>,
+}
+
+impl LinearityCtx {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 変数を生存状態で登録する
+    pub fn register(&mut self, name: &str) {
+        self.alive.insert(name.to_string(), true);
+        self.borrow_count.insert(name.to_string(), 0);
+    }
+
+    /// 変数を消費済みとしてマークする（所有権の移動）
+    /// 既に消費済みの場合は二重解放エラーを記録する。
+    /// 借用中の場合は消費を拒否する。
+    pub fn consume(&mut self, name: &str) -> Result<(), String> {
+        // 借用中チェック: 借用されている変数は消費できない
+        if let Some(&count) = self.borrow_count.get(name) {
+            if count > 0 {
+                let borrower_names = self.borrowers.get(name)
+                    .map(|v| v.join(", "))
+                    .unwrap_or_else(|| "unknown".to_string());
+                let msg = format!(
+                    "Cannot consume '{}': currently borrowed by [{}] ({} active borrow(s))",
+                    name, borrower_names, count
+                );
+                self.violations.push(msg.clone());
+                return Err(msg);
+            }
+        }
+
+        match self.alive.get(name) {
+            Some(true) => {
+                self.alive.insert(name.to_string(), false);
+                Ok(())
+            }
+            Some(false) => {
+                let msg = format!("Double-free detected: '{}' has already been consumed", name);
+                self.violations.push(msg.clone());
+                Err(msg)
+            }
+            None => {
+                // 追跡対象外の変数は無視（通常の値型）
+                Ok(())
+            }
+        }
+    }
+
+    /// 変数を借用する（読み取り専用の参照）
+    /// 借用中は所有者が consume/free できなくなる。
+    /// borrower_name: 借用する側の変数名（ライフタイム追跡用）
+    #[allow(dead_code)]
+    pub fn borrow(&mut self, owner_name: &str, borrower_name: &str) -> Result<(), String> {
+        // 生存チェック: 消費済み変数は借用できない
+        if let Some(false) = self.alive.get(owner_name) {
+            let msg = format!(
+                "Cannot borrow '{}': it has already been consumed (use-after-free)",
+                owner_name
+            );
+            self.violations.push(msg.clone());
+            return Err(msg);
+        }
+
+        let count = self.borrow_count.entry(owner_name.to_string()).or_insert(0);
+        *count += 1;
+        self.borrowers.entry(owner_name.to_string())
+            .or_insert_with(Vec::new)
+            .push(borrower_name.to_string());
+        Ok(())
+    }
+
+    /// 借用を解放する
+    #[allow(dead_code)]
+    pub fn release_borrow(&mut self, owner_name: &str, borrower_name: &str) {
+        if let Some(count) = self.borrow_count.get_mut(owner_name) {
+            if *count > 0 {
+                *count -= 1;
+            }
+        }
+        if let Some(borrowers) = self.borrowers.get_mut(owner_name) {
+            borrowers.retain(|b| b != borrower_name);
+        }
+    }
+
+    /// 変数が生存しているかチェックする
+    /// 消費済み変数へのアクセスはエラーを記録する
+    #[allow(dead_code)]
+    pub fn check_alive(&mut self, name: &str) -> Result<(), String> {
+        if let Some(false) = self.alive.get(name) {
+            let msg = format!("Use-after-free detected: '{}' has been consumed and is no longer valid", name);
+            self.violations.push(msg.clone());
+            return Err(msg);
+        }
+        Ok(())
+    }
+
+    /// 変数が借用中かどうかを確認する
+    #[allow(dead_code)]
+    pub fn is_borrowed(&self, name: &str) -> bool {
+        self.borrow_count.get(name).map_or(false, |&c| c > 0)
+    }
+
+    /// 蓄積された違反リストを返す
+    pub fn get_violations(&self) -> &[String] {
+        &self.violations
+    }
+
+    /// 違反があるかどうか
+    pub fn has_violations(&self) -> bool {
+        !self.violations.is_empty()
+    }
+}
+
+// =============================================================================
+// async ブロックの線形性検証 (Async Linearity)
+// =============================================================================
+//
+// async ブロックの本体は構築時ではなく、対応する await で実行される。
+// そのため async ブロック内で consume される変数は「await 時点で消費される」
+// ものとして扱い、以下を保守的に検出する:
+//
+// 1. 遅延消費との競合: async ブロックが消費用に捕捉した変数を、
+//    そのブロックの await より前（または await 後）に外側で使用する。
+// 2. await 跨ぎの Use-After-Consume: 同一ブロック内で await より前に
+//    消費した変数を await 後に参照する。
+//
+// 「消費」は consume 宣言されたパラメータへ変数を直接渡す呼び出しで判定する。
+
+/// 消費済み変数の記録
+#[derive(Debug, Clone)]
+struct ConsumeSite {
+    /// 消費した主体（"call to 'f'" / "async block bound to 'h'"）
+    by: String,
+    /// 消費後に await ポイントを通過したか
+    across_await: bool,
+}
+
+/// 構築済みだが未 await の async ブロック
+#[derive(Debug, Clone)]
+struct PendingAsync {
+    label: String,
+    handle: Option<String>,
+    captured: Vec<String>,
+}
+
+/// Expr ツリーを評価順に走査し、async ブロックの捕捉と消費を追跡する。
+#[derive(Clone)]
+struct AsyncLinearityCtx<'a> {
+    module_env: &'a ModuleEnv,
+    consumed: HashMap<String, ConsumeSite>,
+    pending: Vec<PendingAsync>,
+    violations: Vec<String>,
+    next_block_id: usize,
+}
+
+impl<'a> AsyncLinearityCtx<'a> {
+    fn new(module_env: &'a ModuleEnv) -> Self {
+        Self {
+            module_env,
+            consumed: HashMap::new(),
+            pending: Vec::new(),
+            violations: Vec::new(),
+            next_block_id: 0,
+        }
+    }
+
+    /// 変数の使用をチェックする。未 await のブロックが捕捉中、
+    /// または await 前に消費済みの変数であれば違反を記録する。
+    fn use_var(&mut self, name: &str, context: &str) {
+        if let Some(p) = self.pending.iter().find(|p| p.captured.iter().any(|c| c == name)) {
+            self.violations.push(format!(
+                "'{}' is captured for consumption by {}, but is used in {} before that block is awaited",
+                name, p.label, context
+            ));
+            return;
+        }
+        if let Some(site) = self.consumed.get(name) {
+            if site.across_await {
+                self.violations.push(format!(
+                    "'{}' is consumed by {} before an await point, but is referenced in {} after the await",
+                    name, site.by, context
+                ));
+            }
+        }
+    }
+
+    fn consume_var(&mut self, name: &str, by: String) {
+        self.consumed.insert(name.to_string(), ConsumeSite { by, across_await: false });
+    }
+
+    /// let / 代入 / パターン束縛による再束縛。以前の消費状態は新しい値に引き継がれない。
+    fn rebind(&mut self, name: &str) {
+        self.consumed.remove(name);
+    }
+
+    /// await ポイントを通過: それまでに消費された変数を await 跨ぎとしてマークする
+    fn cross_await(&mut self) {
+        for site in self.consumed.values_mut() {
+            site.across_await = true;
+        }
+    }
+
+    /// 分岐の合流: 消費・未 await ブロック・違反を和集合で保守的にマージする
+    fn merge(&mut self, other: AsyncLinearityCtx<'a>) {
+        for (name, site) in other.consumed {
+            self.consumed.entry(name)
+                .and_modify(|s| s.across_await |= site.across_await)
+                .or_insert(site);
+        }
+        for p in other.pending {
+            match self.pending.iter_mut().find(|q| q.label == p.label) {
+                Some(q) => {
+                    for c in p.captured {
+                        if !q.captured.contains(&c) { q.captured.push(c); }
+                    }
+                }
+                None => self.pending.push(p),
+            }
+        }
+        self.violations.extend(other.violations);
+        self.next_block_id = self.next_block_id.max(other.next_block_id);
+    }
+
+    /// async ブロックを走査し、本体が消費する変数を捕捉として登録する。
+    /// 本体の実行は await まで遅延されるため、消費状態は外側へ反映しない。
+    fn enter_async(&mut self, body: &Expr, handle: Option<&str>) {
+        self.next_block_id += 1;
+        let label = match handle {
+            Some(h) => format!("async block bound to '{}'", h),
+            None => format!("async block #{}", self.next_block_id),
+        };
+        let outer_consumed = self.consumed.clone();
+        let outer_pending = self.pending.len();
+        self.walk(body, &label);
+
+        let mut captured: Vec<String> = self.consumed.iter()
+            .filter(|(name, site)| outer_consumed.get(*name).map_or(true, |o| o.by != site.by))
+            .map(|(name, _)| name.clone())
+            .collect();
+        // 本体内で await されなかったネストした async ブロックの捕捉も引き継ぐ
+        for inner in self.pending.drain(outer_pending..) {
+            captured.extend(inner.captured);
+        }
+        captured.sort();
+        captured.dedup();
+        self.consumed = outer_consumed;
+        self.pending.push(PendingAsync { label, handle: handle.map(|h| h.to_string()), captured });
+    }
+
+    /// 完了した async ブロックの捕捉を await 時点での消費として確定する
+    fn settle(&mut self, p: PendingAsync) {
+        for name in p.captured {
+            self.consumed.insert(name, ConsumeSite { by: p.label.clone(), across_await: true });
+        }
+    }
+
+    fn walk(&mut self, expr: &Expr, context: &str) {
+        match expr {
+            Expr::Variable(name) | Expr::ArrayAccess(name, _) => {
+                if let Expr::ArrayAccess(_, idx) = expr { self.walk(idx, context); }
+                self.use_var(name, context);
+            }
+            Expr::BinaryOp(..) => {
+                // 左に連なる連鎖は再帰せずに辿る
+                let (leaf, nodes) = expr.left_spine();
+                self.walk(leaf, context);
+                for node in nodes {
+                    if let Expr::BinaryOp(_, _, r) = node {
+                        self.walk(r, context);
+                    }
+                }
+            }
+            Expr::Let { var, value } => {
+                if let Expr::Async { body } = value.as_ref() {
+                    self.enter_async(body, Some(var));
+                } else {
+                    self.walk(value, context);
+                }
+                self.rebind(var);
+            }
+            Expr::Assign { var, value } => {
+                self.walk(value, context);
+                if self.pending.iter().any(|p| p.captured.contains(var)) {
+                    self.use_var(var, context);
+                } else {
+                    self.rebind(var);
+                }
+            }
+            Expr::Block(stmts) => {
+                for s in stmts { self.walk(s, context); }
+            }
+            Expr::IfThenElse { cond, then_branch, else_branch } => {
+                self.walk(cond, context);
+                let mut else_ctx = self.clone();
+                else_ctx.violations.clear();
+                self.walk(then_branch, context);
+                else_ctx.walk(else_branch, context);
+                self.merge(else_ctx);
+            }
+            Expr::While { cond, invariant, decreases, body } => {
+                // 2 周分走査し、前の反復での消費と次の反復での使用の競合も検出する
+                for _ in 0..2 {
+                    self.walk(cond, context);
+                    self.walk(invariant, context);
+                    if let Some(d) = decreases { self.walk(d, context); }
+                    self.walk(body, context);
+                }
+            }
+            Expr::Call(name, args) => {
+                let consumed_positions: Vec<bool> = match self.module_env.get_atom(name) {
+                    Some(callee) => callee.params.iter()
+                        .map(|p| callee.consumed_params.contains(&p.name))
+                        .collect(),
+                    None => Vec::new(),
+                };
+                for (i, arg) in args.iter().enumerate() {
+                    self.walk(arg, context);
+                    if let Expr::Variable(v) = arg {
+                        if consumed_positions.get(i).copied().unwrap_or(false) {
+                            self.consume_var(v, format!("call to '{}'", name));
+                        }
+                    }
+                }
+            }
+            Expr::StructInit { fields, .. } => {
+                for (_, e) in fields { self.walk(e, context); }
+            }
+            Expr::FieldAccess(inner, _) => self.walk(inner, context),
+            Expr::Match { target, arms } => {
+                self.walk(target, context);
+                let base = self.clone();
+                let mut merged: Option<AsyncLinearityCtx<'a>> = None;
+                for arm in arms {
+                    let mut arm_ctx = base.clone();
+                    arm_ctx.violations.clear();
+                    if let Pattern::Variable(bound) = &arm.pattern { arm_ctx.rebind(bound); }
+                    if let Some(guard) = &arm.guard { arm_ctx.walk(guard, context); }
+                    arm_ctx.walk(&arm.body, context);
+                    match merged.as_mut() {
+                        Some(m) => m.merge(arm_ctx),
+                        None => merged = Some(arm_ctx),
+                    }
+                }
+                if let Some(mut m) = merged {
+                    let arm_violations = std::mem::take(&mut m.violations);
+                    let violations = std::mem::take(&mut self.violations);
+                    *self = m;
+                    self.violations = violations;
+                    self.violations.extend(arm_violations);
+                }
+            }
+            Expr::Acquire { body, .. } => self.walk(body, context),
+            Expr::Async { body } => self.enter_async(body, None),
+            Expr::Await { expr: inner } => {
+                match inner.as_ref() {
+                    Expr::Variable(h) if self.pending.iter().any(|p| p.handle.as_deref() == Some(h.as_str())) => {
+                        let idx = self.pending.iter().rposition(|p| p.handle.as_deref() == Some(h.as_str())).unwrap();
+                        let p = self.pending.remove(idx);
+                        self.cross_await();
+                        self.settle(p);
+                    }
+                    Expr::Async { body } => {
+                        self.enter_async(body, None);
+                        let p = self.pending.pop().unwrap();
+                        self.cross_await();
+                        self.settle(p);
+                    }
+                    other => {
+                        self.walk(other, context);
+                        self.cross_await();
+                    }
+                }
+            }
+            Expr::Assume { cond, .. } => self.walk(cond, context),
+            Expr::RefArg { expr, .. } => self.walk(expr, context),
+            Expr::Number(_) | Expr::Float(_) | Expr::StringLit(_) | Expr::Bool(_) => {}
+        }
+    }
+}
+
+/// async ブロックの捕捉と await 跨ぎの消費を検証する（body のみ、Z3 不要）。
+pub(super) fn verify_async_linearity(atom: &Atom, module_env: &ModuleEnv) -> MumeiResult<()> {
+    let body_ast = parse_expression(&atom.body_expr)?;
+    let mut lin = AsyncLinearityCtx::new(module_env);
+    let context = format!("atom '{}'", atom.name);
+    lin.walk(&body_ast, &context);
+
+    let mut violations: Vec<String> = Vec::new();
+    for v in lin.violations {
+        if !violations.contains(&v) { violations.push(v); }
+    }
+    if !violations.is_empty() {
+        return Err(MumeiError::failure(FailureKind::Linearity,
+            format!("Async linearity violations in atom '{}':\n  {}", atom.name, violations.join("\n  "))
+        ));
+    }
+    Ok(())
+}
+
+// =============================================================================
+// ensures 中の消費済みパラメータ (Consumed Parameters in Ensures)
+// =============================================================================
+//
+// ensures は atom の終了時点で評価されるが、consume 宣言されたパラメータはその時点で
+// 消費済みになっている。ensures 中の消費済みパラメータは「消費される前（atom 入口）の値」を指す
+// （ensures_env は body で代入されていても入口のシンボルを使う）。
+// - 値として参照するのは許可し、パラメータごとに 1 回だけ警告する
+// - ref / ref mut パラメータへ渡す（= 終了後も借用できることを要求する）のはエラー
+
+/// ensures 中の消費済みパラメータの参照を検査し、警告を返す
+pub(super) fn consumed_ensures_warnings(atom: &Atom, ensures: &Expr, module_env: &ModuleEnv) -> MumeiResult<Vec<String>> {
+    let mut referenced: Vec<String> = Vec::new();
+    let mut borrowed: Vec<String> = Vec::new();
+    collect_consumed_refs(ensures, &atom.consumed_params, module_env, &mut referenced, &mut borrowed);
+    if let Some(reason) = borrowed.first() {
+        return Err(MumeiError::TypeError(format!("Cannot consume {} in atom '{}'", reason, atom.name)));
+    }
+    Ok(referenced.iter()
+        .map(|name| format!("ensures references consumed parameter '{}'; this refers to its value before consumption", name))
+        .collect())
+}
+
+/// 式中の消費済みパラメータの参照（出現順・重複なし）と、ref 引数としての受け渡しを収集する
+fn collect_consumed_refs(expr: &Expr, consumed: &[String], module_env: &ModuleEnv, referenced: &mut Vec<String>, borrowed: &mut Vec<String>) {
+    expr.walk(|e| match e {
+        Expr::Variable(name) | Expr::ArrayAccess(name, _) => {
+            if consumed.contains(name) && !referenced.contains(name) {
+                referenced.push(name.clone());
+            }
+            true
+        }
+        // old(x) は入口の値を明示しているので警告しない
+        Expr::Call(callee, _) if callee == "old" => false,
+        Expr::Call(callee, args) => {
+            let callee_params = module_env.get_atom(callee).map(|a| a.params.as_slice()).unwrap_or(&[]);
+            for (arg, param) in args.iter().zip(callee_params.iter()) {
+                if let Expr::Variable(name) = arg.without_ref_marker() {
+                    if consumed.contains(name) && (param.is_ref || param.is_ref_mut) {
+                        let kind = if param.is_ref_mut { "ref mut" } else { "ref" };
+                        borrowed.push(format!(
+                            "parameter '{}': ensures passes it to {} parameter '{}' of '{}', which requires it to still be borrowed after the atom returns",
+                            name, kind, param.name, callee
+                        ));
+                    }
+                }
+            }
+            true
+        }
+        _ => true,
+    });
+}
+
+// =============================================================================
+// 呼び出し側の参照渡しマーカー（`f(ref x)` / `f(ref mut x)`）
+// =============================================================================
+//
+// 呼び出し側で借用を明示し、呼び出し先パラメータの宣言と突き合わせる。
+// - マーカー付きの実引数は変数でなければならない（一時的な値は借用できない）
+// - マーカーの有無と ref / ref mut の別は、呼び出し先パラメータの宣言と一致しなければならない
+// - マーカーのない実引数を ref / ref mut パラメータに渡すのは移行期間として警告にとどめる。
+//   `[build] strict_ref_args = true` ではエラーにする
+
+/// atom の body の呼び出しの参照渡しマーカーを検査し、移行期間の警告を返す
+pub(super) fn ref_argument_warnings(atom: &Atom, body: &Expr, module_env: &ModuleEnv) -> MumeiResult<Vec<String>> {
+    let mut calls = Vec::new();
+    collect_call_sites(body, &mut calls);
+    let mut warnings = Vec::new();
+    for (callee, args) in calls {
+        let params = module_env.get_atom(callee).map(|c| c.params.as_slice()).unwrap_or(&[]);
+        for (i, arg) in args.iter().enumerate() {
+            let param = params.get(i);
+            let declared = param.filter(|p| p.is_ref || p.is_ref_mut)
+                .map(|p| if p.is_ref_mut { "ref mut" } else { "ref" });
+            let position = format!("argument {} of '{}'", i + 1, callee);
+            match (arg, param, declared) {
+                (Expr::RefArg { expr, mutable }, _, _) => {
+                    let marker = if *mutable { "ref mut" } else { "ref" };
+                    let mismatch = if !matches!(expr.as_ref(), Expr::Variable(_)) {
+                        Some(format!("{} is passed by `{}` but is not a variable; a temporary value cannot be borrowed", position, marker))
+                    } else {
+                        match (param, declared) {
+                            (None, _) => Some(format!("{} is passed by `{}` but '{}' has no parameter at that position", position, marker, callee)),
+                            (Some(p), None) => Some(format!("{} is passed by `{}` but parameter '{}' is taken by value", position, marker, p.name)),
+                            (Some(p), Some(kind)) if kind != marker => {
+                                Some(format!("{} is passed by `{}` but parameter '{}' is declared `{}`", position, marker, p.name, kind))
+                            }
+                            _ => None,
+                        }
+                    };
+                    if let Some(reason) = mismatch {
+                        return Err(MumeiError::TypeError(format!("Atom '{}': {}", atom.name, reason)));
+                    }
+                }
+                (other, Some(p), Some(kind)) => {
+                    let hint = match other {
+                        Expr::Variable(v) => format!("write `{} {}`", kind, v),
+                        _ => format!("pass a variable as `{} name`", kind),
+                    };
+                    let reason = format!("{} is passed to `{}` parameter '{}' without `{}`; {}", position, kind, p.name, kind, hint);
+                    if module_env.strict_ref_args {
+                        return Err(MumeiError::TypeError(format!("Atom '{}': {} ([build] strict_ref_args = true)", atom.name, reason)));
+                    }
+                    warnings.push(format!("{} (unmarked ref arguments are accepted during migration)", reason));
+                }
+                _ => {}
+            }
+        }
+    }
+    Ok(warnings)
+}
+
+/// 式中のすべての呼び出し（呼び出し名, 実引数）を出現順に収集する
+fn collect_call_sites<'e>(expr: &'e Expr, out: &mut Vec<(&'e str, &'e [Expr])>) {
+    expr.walk(|e| {
+        if let Expr::Call(name, args) = e {
+            out.push((name.as_str(), args.as_slice()));
+        }
+        true
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{env_from_source, find_atom, TempDir};
+
+    const ASYNC_LINEARITY_SOURCE: &str = r#"
+atom sink(x: i64)
+    consume x;
+    requires: true;
+    ensures: true;
+    body: { x }
+
+atom deferred_conflict(x: i64)
+    requires: true;
+    ensures: true;
+    body: {
+        let h = async { sink(x) };
+        let y = x + 1;
+        await h
+    }
+
+atom consumed_inside(x: i64)
+    requires: true;
+    ensures: true;
+    body: {
+        let h = async { sink(x) };
+        await h
+    }
+
+atom nested_across_await(x: i64, t: i64)
+    requires: true;
+    ensures: true;
+    body: {
+        let outer = async {
+            let inner = async { sink(x) };
+            await inner;
+            await t;
+            x
+        };
+        await outer
+    }
+"#;
+
+    #[test]
+    fn test_async_linearity() {
+        let (items, module_env) = env_from_source(ASYNC_LINEARITY_SOURCE);
+        let atom = |name: &str| find_atom(&items, name);
+
+        // async ブロックが消費する x を await 前に外側で使用している
+        let err = verify_async_linearity(&atom("deferred_conflict"), &module_env).expect_err("x is used before h is awaited");
+        let msg = err.to_string();
+        assert!(msg.contains("'x' is captured for consumption by async block bound to 'h'"), "{}", msg);
+        assert!(msg.contains("before that block is awaited"), "{}", msg);
+
+        // async 内で消費し、外側では使用しない
+        assert!(verify_async_linearity(&atom("consumed_inside"), &module_env).is_ok());
+
+        // ネストした async: inner の await で消費された x を、後続の await 後に参照している
+        let err = verify_async_linearity(&atom("nested_across_await"), &module_env).expect_err("x is referenced after await");
+        let msg = err.to_string();
+        assert!(msg.contains("'x' is consumed by async block bound to 'inner' before an await point"), "{}", msg);
+        assert!(msg.contains("in async block bound to 'outer' after the await"), "{}", msg);
+    }
+
+    const CONSUMED_ENSURES_SOURCE: &str = r#"
+type Pos = i64 where v > 0;
+
+atom peek(ref h: i64) requires: true; ensures: true; body: h;
+
+atom release(buf: Pos)
+consume buf;
+requires: true;
+ensures: result == buf && buf > 0;
+body: {
+    let r = buf;
+    buf = 0;
+    r
+};
+
+atom release_old(buf: Pos)
+consume buf;
+requires: true;
+ensures: result == old(buf) && old(buf) > 0;
+body: {
+    let r = buf;
+    buf = 0;
+    r
+};
+
+atom close(h: i64)
+consume h;
+requires: h > 0;
+ensures: peek(h) > 0;
+body: 0;
+"#;
+
+    #[test]
+    fn test_ensures_sees_consumed_parameter_entry_value() {
+        let (items, module_env) = env_from_source(CONSUMED_ENSURES_SOURCE);
+        let atom = |name: &str| find_atom(&items, name);
+        let output_dir = TempDir::new("consumed_ensures");
+
+        // body で buf を上書きし、終了時に消費済みになっても ensures の buf は入口の値
+        let release = atom("release");
+        assert!(verify(&release, &output_dir, &module_env).is_ok());
+        let warnings = consumed_ensures_warnings(&release, &parse_expression(&release.ensures).unwrap(), &module_env).unwrap();
+        assert_eq!(warnings, vec![
+            "ensures references consumed parameter 'buf'; this refers to its value before consumption".to_string()
+        ]);
+        // old(buf) と書けば同じ意味で、警告は出ない
+        let release_old = atom("release_old");
+        assert!(verify(&release_old, &output_dir, &module_env).is_ok());
+        assert!(consumed_ensures_warnings(&release_old, &parse_expression(&release_old.ensures).unwrap(), &module_env).unwrap().is_empty());
+
+        // 消費済みのパラメータを ensures で ref 引数として借用することはできない
+        let err = verify(&atom("close"), &output_dir, &module_env).unwrap_err().to_string();
+        assert!(err.contains("Cannot consume parameter 'h' in atom 'close'"), "{}", err);
+        assert!(err.contains("ensures passes it to ref parameter 'h' of 'peek'"), "{}", err);
+    }
+
+    const REF_ARGS_SOURCE: &str = r#"
+atom push(ref mut stack: i64, item: i64) requires: true; ensures: true; body: item;
+atom peek(ref stack: i64) requires: true; ensures: true; body: stack;
+atom plain(x: i64) requires: true; ensures: true; body: x;
+
+atom marked(s: i64) requires: true; ensures: true; body: { push(ref mut s, 1); peek(ref s) };
+atom unmarked(s: i64) requires: true; ensures: true; body: peek(s) + push(s + 1, 1);
+atom temporary(s: i64) requires: true; ensures: true; body: peek(ref s + 1);
+atom by_value(s: i64) requires: true; ensures: true; body: plain(ref s);
+atom shared_for_mut(s: i64) requires: true; ensures: true; body: push(ref s, 1);
+atom mut_for_shared(s: i64) requires: true; ensures: true; body: peek(ref mut s);
+atom not_an_atom(s: i64) requires: true; ensures: true; body: len(ref s);
+"#;
+
+    #[test]
+    fn test_ref_argument_markers() {
+        let (items, mut module_env) = env_from_source(REF_ARGS_SOURCE);
+        let atom = |name: &str| find_atom(&items, name);
+        let check = |name: &str, env: &ModuleEnv| {
+            let a = atom(name);
+            ref_argument_warnings(&a, &parse_expression(&a.body_expr).unwrap(), env)
+        };
+
+        assert_eq!(check("marked", &module_env).unwrap(), Vec::<String>::new());
+        let output_dir = TempDir::new("ref_args");
+        assert!(verify(&atom("marked"), &output_dir, &module_env).is_ok());
+
+        // マーカーのない実引数は移行期間中は警告
+        assert_eq!(check("unmarked", &module_env).unwrap(), vec![
+            "argument 1 of 'peek' is passed to `ref` parameter 'stack' without `ref`; write `ref s` (unmarked ref arguments are accepted during migration)".to_string(),
+            "argument 1 of 'push' is passed to `ref mut` parameter 'stack' without `ref mut`; pass a variable as `ref mut name` (unmarked ref arguments are accepted during migration)".to_string(),
+        ]);
+
+        for (name, expected) in [
+            ("temporary", "argument 1 of 'peek' is passed by `ref` but is not a variable"),
+            ("by_value", "argument 1 of 'plain' is passed by `ref` but parameter 'x' is taken by value"),
+            ("shared_for_mut", "argument 1 of 'push' is passed by `ref` but parameter 'stack' is declared `ref mut`"),
+            ("mut_for_shared", "argument 1 of 'peek' is passed by `ref mut` but parameter 'stack' is declared `ref`"),
+            ("not_an_atom", "argument 1 of 'len' is passed by `ref` but 'len' has no parameter at that position"),
+        ] {
+            let err = check(name, &module_env).unwrap_err().to_string();
+            assert!(err.contains(&format!("Atom '{}'", name)) && err.contains(expected), "{}: {}", name, err);
+        }
+
+        // [build] strict_ref_args = true ではマーカーのない実引数もエラー
+        module_env.strict_ref_args = true;
+        let err = check("unmarked", &module_env).unwrap_err().to_string();
+        assert!(err.contains("without `ref`; write `ref s` ([build] strict_ref_args = true)"), "{}", err);
+        assert!(check("marked", &module_env).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,3218 @@
+use z3::ast::{Ast, Int, Bool, Array, Dynamic, Float};
+use z3::{Config, Context, ContextHandle, Params, Solver, SatResult};
+use crate::parser::{Atom, Param, QuantifierType, Expr, Op, parse_expression, ParseError, RefinedType, StructDef, EnumDef, Pattern, MatchArm, TraitDef, TraitMethod, ImplDef, ResourceDef, ResourceMode, TrustLevel};
+use std::path::Path;
+use std::fmt;
+use serde_json::json;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::cell::{Cell, RefCell};
+use std::mem::ManuallyDrop;
+use std::marker::PhantomData;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use sha2::{Sha256, Digest};
+use crate::report::{Report, AtomResult, AtomStatus, FailureKind, ItemKind, ObligationTiming};
+
+// 検証器はサブモジュールに分割している:
+// - env: ModuleEnv（型・atom・トレイト・リソースの登録と解決）
+// - translate: VCtx と式の Z3 への変換 (expr_to_z3)
+// - patterns: match のパターン条件生成・変数バインド
+// - linearity: 所有権・借用と async ブロックの線形性
+// - taint: 式の値の汚染ラベル伝播と、排他リソースの critical section からの流出検査
+// - traits / laws: 組み込みトレイトと impl の法則検証
+// - counterexample: 反例の構造化と表示
+mod counterexample;
+mod env;
+mod laws;
+mod linearity;
+mod patterns;
+mod taint;
+mod traits;
+mod translate;
+
+pub use self::counterexample::CounterexampleFormat;
+pub use self::env::{ModuleEnv, PrivateItem};
+pub use self::laws::{law_expansion_sizes, verify_impl, DEFAULT_MAX_LAW_EXPANSION_NODES};
+pub use self::traits::register_builtin_traits;
+pub(crate) use self::translate::substitute_bound;
+use self::counterexample::{mentions_identifier, model_counterexample, render_model_value, symbol_type_name, violation_model, Counterexample, CounterexampleValue, CounterexampleVariant, ModelValue};
+use self::laws::trait_param_name;
+use self::linearity::{consumed_ensures_warnings, ref_argument_warnings, verify_async_linearity, LinearityCtx};
+use self::patterns::{bind_nullable_payload, detect_enum_from_arms, match_counterexample, pattern_bind_variables, pattern_to_z3_condition};
+use self::taint::resource_escape_warnings;
+use self::translate::{apply_element_refinement, apply_refinement_constraint, bind_old_values, expr_label, expr_to_z3, nullable_value_name, option_some_tag, prove_struct_invariant, rename_bound, string_length, symbolic_struct_fields, where_line, VCtx};
+
+// --- エラー型の定義 ---
+#[derive(Debug)]
+pub enum MumeiError {
+    VerificationError(String),
+    /// 失敗カテゴリ付きの検証エラー（表示は VerificationError と同じ。カテゴリはレポートと SARIF の rule になる）
+    VerificationFailure(FailureKind, String),
+    CodegenError(String),
+    TypeError(String),
+}
+
+impl fmt::Display for MumeiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MumeiError::VerificationError(msg) | MumeiError::VerificationFailure(_, msg) => write!(f, "Verification Error: {}", msg),
+            MumeiError::CodegenError(msg) => write!(f, "Codegen Error: {}", msg),
+            MumeiError::TypeError(msg) => write!(f, "Type Error: {}", msg),
+        }
+    }
+}
+
+impl MumeiError {
+    /// 失敗カテゴリ付きの検証エラーを作る
+    pub fn failure(kind: FailureKind, message: impl Into<String>) -> Self {
+        MumeiError::VerificationFailure(kind, message.into())
+    }
+
+    /// レポート・SARIF 用の失敗カテゴリ（カテゴリのない検証エラー・型エラーなどは Verification）
+    pub fn failure_kind(&self) -> FailureKind {
+        match self {
+            MumeiError::VerificationFailure(kind, _) => *kind,
+            _ => FailureKind::Verification,
+        }
+    }
+}
+
+impl From<String> for MumeiError {
+    fn from(s: String) -> Self {
+        MumeiError::VerificationError(s)
+    }
+}
+
+impl From<ParseError> for MumeiError {
+    fn from(e: ParseError) -> Self {
+        MumeiError::TypeError(format!("Parse error: {}", e))
+    }
+}
+
+impl From<&str> for MumeiError {
+    fn from(s: &str) -> Self {
+        MumeiError::VerificationError(s.to_string())
+    }
+}
+
+pub type MumeiResult<T> = Result<T, MumeiError>;
+type Env<'a> = HashMap<String, Dynamic<'a>>;
+type DynResult<'a> = MumeiResult<Dynamic<'a>>;
+
+// =============================================================================
+// Z3 のハードリミット (Watchdog / メモリ上限)
+// =============================================================================
+//
+// Z3 のソフトタイムアウト（timeout_ms）は非線形算術などで大幅に超過することがあり、
+// メモリも際限なく消費しうる。そこで:
+// - 各クエリの前にプロセス共通の watchdog スレッドへアラームを登録し、timeout_ms + grace_ms を
+//   過ぎたら Context を interrupt する（Z3 でスレッドを跨いで呼べるのは interrupt のみ。
+//   watchdog に渡すのは ContextHandle だけで、クエリが終わればアラームを解除する）
+// - `[proof] max_memory_mb` を Z3 の memory_max_size に渡し、ソルバ自身に上限を守らせる。
+//   グローバルパラメータなので、プールの Context を作った直後に無制限（0）へ戻す
+// どちらかに達した場合はタイムアウト/メモリ超過として検証失敗にし、上限値をメッセージに含める。
+
+/// Z3 のハードリミット設定（`mumei.toml` の `[proof]`）
+#[derive(Debug, Clone, Copy)]
+pub struct SolverLimits {
+    /// ソフトタイムアウト超過後、watchdog が interrupt するまでの猶予（ミリ秒）
+    pub grace_ms: u64,
+    /// Z3 の memory_max_size（MB）。None なら無制限
+    pub max_memory_mb: Option<u64>,
+}
+
+impl Default for SolverLimits {
+    fn default() -> Self {
+        Self { grace_ms: 5000, max_memory_mb: None }
+    }
+}
+
+/// 1 つの Context 上のクエリを監視する watchdog。
+/// 最初に上限へ達したクエリの失敗理由を記録する。
+struct SolverGuard {
+    timeout_ms: u64,
+    limits: SolverLimits,
+    failure: RefCell<Option<String>>,
+    /// watchdog が interrupt したクエリがあったか（結果が出ていても Context の再利用をやめる）
+    interrupted: Cell<bool>,
+}
+
+impl SolverGuard {
+    /// guard を作成する。メモリ上限はプールが Context を作るときに設定する（SolverPool::new）
+    fn new(timeout_ms: u64, limits: SolverLimits) -> Self {
+        Self { timeout_ms, limits, failure: RefCell::new(None), interrupted: Cell::new(false) }
+    }
+
+    /// 失敗メッセージに添える上限値
+    fn describe_limits(&self) -> String {
+        let memory = match self.limits.max_memory_mb {
+            Some(mb) => format!("{} MB", mb),
+            None => "unlimited".to_string(),
+        };
+        format!("timeout_ms={}, grace={}ms, max_memory_mb={}", self.timeout_ms, self.limits.grace_ms, memory)
+    }
+
+    /// watchdog 付きで solver.check() を実行する。
+    /// timeout_ms + grace_ms 以内に終わらなければ Context を interrupt する。
+    fn check<'ctx>(&self, ctx: &'ctx Context, solver: &Solver<'ctx>) -> SatResult {
+        self.check_assuming(ctx, solver, &[])
+    }
+
+    /// check と同じ。assumptions が空でなければ、それらを仮定して解く（solver.check_assumptions）
+    fn check_assuming<'ctx>(&self, ctx: &'ctx Context, solver: &Solver<'ctx>, assumptions: &[Bool<'ctx>]) -> SatResult {
+        let hard_limit = Duration::from_millis(self.timeout_ms.saturating_add(self.limits.grace_ms));
+        let alarm = Alarm::arm(ctx, hard_limit);
+        let result = if assumptions.is_empty() { solver.check() } else { solver.check_assumptions(assumptions) };
+        let interrupted = alarm.disarm();
+        if interrupted {
+            self.interrupted.set(true);
+        }
+
+        if result == SatResult::Unknown && self.failure.borrow().is_none() {
+            let reason = solver.get_reason_unknown().unwrap_or_default();
+            let failure = if interrupted {
+                Some(format!(
+                    "Z3 query exceeded the hard time limit and was interrupted by the watchdog ({})",
+                    self.describe_limits()
+                ))
+            } else if reason.contains("memory") {
+                Some(format!(
+                    "Z3 query exceeded the memory limit: {} ({})",
+                    reason, self.describe_limits()
+                ))
+            } else {
+                None
+            };
+            *self.failure.borrow_mut() = failure;
+        }
+        result
+    }
+
+    /// 上限に達したクエリがあればその理由を返す
+    fn failure(&self) -> Option<String> {
+        self.failure.borrow().clone()
+    }
+}
+
+/// watchdog に登録したアラーム 1 件。deadline を過ぎても解除されていなければ handle の Context を interrupt する
+struct AlarmRequest {
+    deadline: Instant,
+    handle: ContextHandle<'static>,
+    state: Arc<Mutex<AlarmState>>,
+}
+
+#[derive(Default)]
+struct AlarmState {
+    disarmed: bool,
+    fired: bool,
+}
+
+/// クエリ 1 回分のアラーム。disarm（または Drop）するまで Context を借用し続ける
+struct Alarm<'ctx> {
+    state: Arc<Mutex<AlarmState>>,
+    _ctx: PhantomData<&'ctx Context>,
+}
+
+impl<'ctx> Alarm<'ctx> {
+    /// limit 後に ctx を interrupt するアラームを watchdog に登録する
+    fn arm(ctx: &'ctx Context, limit: Duration) -> Self {
+        let state = Arc::new(Mutex::new(AlarmState::default()));
+        // SAFETY: watchdog は state のロックを取って disarmed でないことを確かめてから handle を使い、
+        // Alarm は Drop で disarmed にするまで ctx を借用する。そのため handle は ctx より長く使われない
+        let handle = unsafe { std::mem::transmute::<ContextHandle<'ctx>, ContextHandle<'static>>(ctx.handle()) };
+        let _ = watchdog().send(AlarmRequest { deadline: Instant::now() + limit, handle, state: Arc::clone(&state) });
+        Self { state, _ctx: PhantomData }
+    }
+
+    /// アラームを解除し、watchdog が interrupt したかを返す
+    fn disarm(self) -> bool {
+        self.state.lock().unwrap_or_else(|e| e.into_inner()).fired
+    }
+}
+
+impl Drop for Alarm<'_> {
+    fn drop(&mut self) {
+        self.state.lock().unwrap_or_else(|e| e.into_inner()).disarmed = true;
+    }
+}
+
+/// プロセスで 1 本の watchdog スレッドへのキュー。
+/// スレッドは期限の最も近いアラームまで待ち、期限を過ぎて解除されていないアラームの Context を interrupt する
+fn watchdog() -> &'static mpsc::Sender<AlarmRequest> {
+    static WATCHDOG: OnceLock<mpsc::Sender<AlarmRequest>> = OnceLock::new();
+    WATCHDOG.get_or_init(|| {
+        let (requests, queue) = mpsc::channel::<AlarmRequest>();
+        std::thread::spawn(move || {
+            let mut alarms: Vec<AlarmRequest> = Vec::new();
+            loop {
+                let now = Instant::now();
+                alarms.retain(|alarm| {
+                    let mut state = alarm.state.lock().unwrap_or_else(|e| e.into_inner());
+                    if !state.disarmed && alarm.deadline <= now {
+                        state.fired = true;
+                        alarm.handle.interrupt();
+                    }
+                    !state.disarmed && !state.fired
+                });
+                let received = match alarms.iter().map(|alarm| alarm.deadline).min() {
+                    Some(deadline) => queue.recv_timeout(deadline.saturating_duration_since(Instant::now())),
+                    None => queue.recv().map_err(|_| RecvTimeoutError::Disconnected),
+                };
+                match received {
+                    Ok(alarm) => alarms.push(alarm),
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+            }
+        });
+        requests
+    })
+}
+
+// =============================================================================
+// ソルバプール（スレッドごとの Context / Solver の再利用）
+// =============================================================================
+//
+// 小さな atom が多いモジュールでは Context / Solver の生成が検証時間の多くを占めるため、
+// ワーカースレッドごとに Context と Solver を 1 つだけ作って使い回す:
+// - base レベル: モジュール共通の前提（現状は空。共通の前提はここへ巻き上げる）
+// - atom / impl ごとの検証は base レベルに push した 1 フレーム内で行い、終了時に pop する
+// - シンボル名には VCtx::sym で atom 名を接頭辞に付け、atom 間の衝突を防ぐ
+// watchdog が interrupt した Context と、memory_max_size の設定が変わった場合は作り直す。
+// プールは Context を所有し、作り直したときとスレッドの終了時に Solver ごと解放する。
+
+struct SolverPool {
+    /// Box::into_raw した Context。solver と base が借用するため、Drop でそれらより後に解放する
+    ctx: *mut Context,
+    solver: ManuallyDrop<Solver<'static>>,
+    /// base レベルの前提
+    base: Vec<Bool<'static>>,
+    /// Context 作成時の memory_max_size（Context 作成前に設定するグローバルパラメータ）
+    max_memory_mb: Option<u64>,
+}
+
+thread_local! {
+    static SOLVER_POOL: RefCell<Option<SolverPool>> = RefCell::new(None);
+}
+
+impl SolverPool {
+    fn new(max_memory_mb: Option<u64>) -> Self {
+        // memory_max_size は Context の作成時に読まれるグローバルパラメータ。
+        // ほかの Context（リソース検査など）へ上限が漏れないよう、作成後は無制限に戻す
+        z3::set_global_param("memory_max_size", &max_memory_mb.unwrap_or(0).to_string());
+        let ctx = Box::into_raw(Box::new(Context::new(&Config::new())));
+        z3::set_global_param("memory_max_size", "0");
+        // SAFETY: ctx は Drop で solver と base を破棄するまで解放しない。
+        // 'static の借用はプールの外へは ctx() の寿命に縮めてから渡す
+        let solver = Solver::new(unsafe { &*ctx });
+        Self { ctx, solver: ManuallyDrop::new(solver), base: Vec::new(), max_memory_mb }
+    }
+
+    /// プールの Context（プールを借用している間だけ有効）
+    fn ctx(&self) -> &Context {
+        // SAFETY: ctx はプールが Drop されるまで有効
+        unsafe { &*self.ctx }
+    }
+
+    /// base レベルへ巻き戻す。push/pop の対応が崩れていた場合は reset して base の前提を assert し直す
+    fn rewind(&self) {
+        self.solver.pop(1);
+        if self.solver.get_assertions().len() != self.base.len() {
+            self.solver.reset();
+            for fact in &self.base {
+                self.solver.assert(fact);
+            }
+        }
+    }
+}
+
+impl Drop for SolverPool {
+    fn drop(&mut self) {
+        // Context を借用している solver と base を先に破棄する
+        self.base.clear();
+        // SAFETY: solver はここでだけ破棄し、ctx は new の Box::into_raw で作ったものを 1 度だけ解放する
+        unsafe {
+            ManuallyDrop::drop(&mut self.solver);
+            drop(Box::from_raw(self.ctx));
+        }
+    }
+}
+
+/// 呼び出したスレッドのソルバプールを Context ごと解放する。
+/// 検証ワーカーが終了する前に呼び、実行のたびに作るワーカーの Context を残さない
+pub fn release_solver_pool() {
+    drop(SOLVER_POOL.with(|cell| cell.borrow_mut().take()));
+}
+
+/// スレッドのソルバプール上で f を実行する。
+/// f は base レベルに push した 1 フレーム内で呼ばれ、戻ったら base レベルへ巻き戻す。
+fn with_pooled_solver<R>(guard: &SolverGuard, f: impl for<'ctx> FnOnce(&'ctx Context, &Solver<'ctx>) -> R) -> R {
+    // f の中から再度呼ばれても RefCell を借用したままにならないよう、プールを取り出して使う
+    let pool = SOLVER_POOL.with(|cell| cell.borrow_mut().take())
+        .filter(|pool| pool.max_memory_mb == guard.limits.max_memory_mb)
+        .unwrap_or_else(|| SolverPool::new(guard.limits.max_memory_mb));
+
+    let mut params = Params::new(pool.ctx());
+    params.set_u32("timeout", guard.timeout_ms.min(u32::MAX as u64) as u32);
+    pool.solver.set_params(&params);
+
+    pool.solver.push();
+    let result = f(pool.ctx(), &pool.solver);
+    pool.rewind();
+
+    // interrupt された Context は再利用しない
+    if guard.failure().is_none() && !guard.interrupted.get() {
+        SOLVER_POOL.with(|cell| *cell.borrow_mut() = Some(pool));
+    }
+    result
+}
+
+// =============================================================================
+// 証明義務（Obligation）単位の検証キャッシュ
+// =============================================================================
+//
+// atom 内の各証明義務（配列境界・ゼロ除算・呼び出し先 requires・ループ不変量・
+// 停止性・ensures）を「種別 + 正規化ハッシュ」で識別し、前回証明済みの義務は
+// Z3 を呼ばずに再利用する。ハッシュには solver に assert 済みの前提
+// （requires・精緻型制約など）とゴールの両方が含まれるため、前提が変われば
+// その前提に依存する全義務のハッシュも変わり、自動的に再証明される。
+
+/// 証明義務の種別
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ObligationKind {
+    Bounds,
+    DivZero,
+    CallRequires,
+    LoopInvariant,
+    Termination,
+    Ensures,
+    /// nullable パラメータ（`x: T?`）の値アクセス `x!` / `value(x)` 時点での存在性
+    Presence,
+    /// 理論拡張（crate::theory）の組み込み関数が課す義務
+    Theory,
+}
+
+impl ObligationKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            ObligationKind::Bounds => "bounds",
+            ObligationKind::DivZero => "div_zero",
+            ObligationKind::CallRequires => "call_requires",
+            ObligationKind::LoopInvariant => "loop_invariant",
+            ObligationKind::Termination => "termination",
+            ObligationKind::Ensures => "ensures",
+            ObligationKind::Presence => "presence",
+            ObligationKind::Theory => "theory",
+        }
+    }
+}
+
+/// atom 1つ分の義務キャッシュ。
+/// previous は前回ビルドで証明済みの義務キー、current は今回の検証で証明された義務キー。
+/// 検証後は current をビルドキャッシュに保存し、次回の previous として使う。
+#[derive(Debug, Clone, Default)]
+pub struct ObligationCache {
+    pub previous: HashSet<String>,
+    pub current: HashSet<String>,
+    /// 前回の結果を再利用した義務数
+    pub reused: usize,
+    /// Z3 で新たに解いた義務数
+    pub solved: usize,
+    /// Z3 で解いた義務ごとのクエリ（push / check / pop）の所要時間（解いた順）
+    pub timings: Vec<ObligationTiming>,
+    /// 検証を止めない警告（verify_with_cache が成否に関わらず記録する）
+    pub warnings: Vec<String>,
+}
+
+impl ObligationCache {
+    pub fn from_previous(previous: HashSet<String>) -> Self {
+        Self { previous, ..Default::default() }
+    }
+}
+
+/// 義務キーを計算する: "種別:SHA-256(前提 + ゴール)"。
+/// 一時シンボル名は VCtx::fresh により atom 内で決定的なので、正規化せずそのまま hash する。
+fn obligation_key<'a>(solver: &Solver<'a>, kind: ObligationKind, goal: &Bool<'a>) -> String {
+    let raw = format!("{}\n(goal {})", solver, goal);
+    let mut hasher = Sha256::new();
+    hasher.update(raw.as_bytes());
+    format!("{}:{:x}", kind.as_str(), hasher.finalize())
+}
+
+/// 証明義務を discharge する。現在の前提の下で goal が常に成り立つ
+/// （¬goal が SAT にならない）場合 true を返す。
+/// 前回証明済みの義務はキャッシュから再利用し、Z3 を呼ばない。
+fn discharge<'a>(vc: &VCtx<'a>, solver: &Solver<'a>, kind: ObligationKind, goal: &Bool<'a>, label: impl FnOnce() -> String) -> bool {
+    let key = vc.obligations.map(|_| obligation_key(solver, kind, goal));
+    if let (Some(cache), Some(key)) = (vc.obligations, key.as_ref()) {
+        let mut cache = cache.borrow_mut();
+        if cache.previous.contains(key) {
+            cache.reused += 1;
+            cache.current.insert(key.clone());
+            return true;
+        }
+    }
+    let started = std::time::Instant::now();
+    solver.push();
+    solver.assert(&goal.not());
+    let result = vc.check(solver);
+    solver.pop(1);
+    if let (Some(cache), Some(key)) = (vc.obligations, key) {
+        let mut cache = cache.borrow_mut();
+        cache.solved += 1;
+        cache.timings.push(ObligationTiming {
+            kind: kind.as_str().to_string(),
+            label: label(),
+            duration_ms: started.elapsed().as_secs_f64() * 1000.0,
+        });
+        // Unknown（タイムアウト）は証明済みとしてキャッシュしない
+        if result == SatResult::Unsat {
+            cache.current.insert(key);
+        }
+    }
+    // total atom では Unknown も証明失敗。ここでは記録だけして検証を続け、最後にまとめて報告する
+    if result == SatResult::Unknown {
+        vc.note_undecided(kind.as_str(), goal);
+    }
+    result != SatResult::Sat
+}
+
+// =============================================================================
+// リソース階層検証 (Resource Hierarchy Verification)
+// =============================================================================
+//
+// デッドロック防止: リソース取得順序の半順序関係を Z3 で検証する。
+//
+// 不変条件: ∀ r1, r2 ∈ Held(thread, t):
+//   acquire(r2) かつ r1 ∈ Held → Priority(r2) > Priority(r1)
+//
+// これにより、待機グラフ（Wait-For Graph）に循環が生じないことを
+// コンパイル時に数学的に保証する。
+
+/// リソース取得コンテキスト: 現在保持中のリソースとその優先度を追跡する。
+/// acquire 式の検証時に、リソース階層制約をチェックする。
+#[derive(Debug, Clone, Default)]
+struct ResourceCtx {
+    /// 現在保持中のリソース: (リソース名, 優先度)
+    held: Vec<(String, i64)>,
+    /// 違反リスト
+    violations: Vec<String>,
+}
+
+impl ResourceCtx {
+    fn new() -> Self {
+        Self::default()
+    }
+
+    /// リソースを取得する。階層制約を検証し、違反があればエラーを記録する。
+    fn acquire(&mut self, resource_name: &str, priority: i64) -> Result<(), String> {
+        // 現在保持中の全リソースに対して、新リソースの優先度が厳密に高いことを検証
+        for (held_name, held_priority) in &self.held {
+            if priority <= *held_priority {
+                let msg = format!(
+                    "Deadlock risk: acquiring '{}' (priority={}) while holding '{}' (priority={}). \
+                     New resource must have strictly higher priority.",
+                    resource_name, priority, held_name, held_priority
+                );
+                self.violations.push(msg.clone());
+                return Err(msg);
+            }
+        }
+        self.held.push((resource_name.to_string(), priority));
+        Ok(())
+    }
+
+    /// リソースを解放する（acquire ブロック終了時に呼ばれる）
+    fn release(&mut self, resource_name: &str) {
+        self.held.retain(|(name, _)| name != resource_name);
+    }
+
+    #[allow(dead_code)]
+    fn has_violations(&self) -> bool {
+        !self.violations.is_empty()
+    }
+}
+
+/// atom のリソース使用順序を Z3 で検証する。
+/// atom の resources 宣言と body 内の acquire 式から、
+/// リソース階層制約 Priority(r2) > Priority(r1) を検証する。
+///
+/// 検証方法:
+/// 1. atom の resources リストから使用リソースを特定
+/// 2. body 内の acquire 式を走査し、取得順序を抽出
+/// 3. Z3 で半順序関係の非循環性を証明
+fn verify_resource_hierarchy(atom: &Atom, module_env: &ModuleEnv) -> MumeiResult<()> {
+    if atom.resources.is_empty() {
+        return Ok(());
+    }
+
+    // リソース定義の存在チェック
+    // 参照は登録キーに解決する（修飾なしの名前が複数モジュールに一致すれば曖昧性エラー）
+    let mut resource_priorities: Vec<(String, i64)> = Vec::new();
+    let mut resolved_defs: Vec<(String, ResourceMode)> = Vec::new();
+    for res_name in &atom.resources {
+        if let Some((key, rdef)) = module_env.resolve_resource(res_name)? {
+            resource_priorities.push((key.clone(), rdef.priority));
+            resolved_defs.push((key, rdef.mode.clone()));
+        } else {
+            return Err(MumeiError::TypeError(
+                format!("Resource '{}' used in atom '{}' is not defined. Add: resource {} priority:<N> mode:exclusive|shared;",
+                    res_name, atom.name, res_name)
+            ));
+        }
+    }
+
+    // Z3 で半順序関係を検証
+    let mut cfg = Config::new();
+    cfg.set_timeout_msec(5000);
+    let ctx = Context::new(&cfg);
+    let solver = Solver::new(&ctx);
+
+    // 各リソースの優先度をシンボリック整数として定義
+    let mut priority_vars: HashMap<String, Int> = HashMap::new();
+    for (name, priority) in &resource_priorities {
+        let var = Int::new_const(&ctx, format!("priority_{}", name).as_str());
+        // 優先度を具体値に束縛
+        solver.assert(&var._eq(&Int::from_i64(&ctx, *priority)));
+        priority_vars.insert(name.clone(), var);
+    }
+
+    // リソース間の順序制約を検証:
+    // resources リスト内で前に宣言されたリソースは先に取得されると仮定し、
+    // 後に宣言されたリソースは厳密に高い優先度を持つ必要がある。
+    for i in 0..resource_priorities.len() {
+        for j in (i + 1)..resource_priorities.len() {
+            let (name_i, _) = &resource_priorities[i];
+            let (name_j, _) = &resource_priorities[j];
+            let pri_i = &priority_vars[name_i];
+            let pri_j = &priority_vars[name_j];
+
+            // Priority(r_j) > Priority(r_i) を検証
+            solver.push();
+            solver.assert(&pri_j.le(pri_i)); // 否定: Priority(r_j) <= Priority(r_i)
+            if solver.check() == SatResult::Sat {
+                solver.pop(1);
+                return Err(MumeiError::failure(FailureKind::Resource,
+                    format!(
+                        "Resource hierarchy violation in atom '{}': \
+                         '{}' (priority={}) must have strictly lower priority than '{}' (priority={}). \
+                         Reorder resources or adjust priorities to prevent potential deadlock.",
+                        atom.name, name_i, resource_priorities[i].1,
+                        name_j, resource_priorities[j].1
+                    )
+                ));
+            }
+            solver.pop(1);
+        }
+    }
+
+    // データレース検証: exclusive リソースの排他性チェック
+    // 同一 atom 内で同じ exclusive リソースを複数回 acquire していないことを確認
+    // （`db` と `lib::db` のように別表記でも同じ登録キーなら同一リソースとして扱う）
+    let mut exclusive_set: HashSet<String> = HashSet::new();
+    for (key, mode) in &resolved_defs {
+        if *mode == ResourceMode::Exclusive {
+            if !exclusive_set.insert(key.clone()) {
+                return Err(MumeiError::failure(FailureKind::Resource,
+                    format!(
+                        "Data race risk in atom '{}': exclusive resource '{}' is listed multiple times",
+                        atom.name, key
+                    )
+                ));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// =============================================================================
+// 有界モデル検査 (Bounded Model Checking — BMC)
+// =============================================================================
+//
+// ループ内の acquire パターンや非同期処理の安全性を、ループ不変量を
+// ユーザーが記述しなくても検証するための補助的な検証手法。
+//
+// 設計:
+// - ループを最大 BMC_UNROLL_DEPTH 回展開し、各展開でリソース階層制約を検証
+// - ループ不変量が提供されている場合はそちらを優先（BMC はフォールバック）
+// - Z3 タイムアウトリスクがあるため、展開回数は保守的に制限
+//
+// 制約:
+// - 無限ループの停止性は証明しない（それは decreases 句の役割）
+// - BMC は「展開回数以内でのバグ不在」を証明するのみ（完全性はない）
+
+/// BMC のループ展開回数上限（グローバルデフォルト）
+/// atom 単位で `max_unroll: N;` によりオーバーライド可能。
+const BMC_DEFAULT_UNROLL_DEPTH: usize = 3;
+
+/// 再帰的 async 呼び出しの最大展開深度。
+/// async atom が自身を呼び出す場合、この深度を超えると
+/// 「Unknown（未定義）」として扱い、Z3 探索を打ち切る。
+const MAX_ASYNC_RECURSION_DEPTH: usize = 3;
+
+/// body 内の Acquire 式を再帰的に収集する（BMC 用）。
+/// ループ内で acquire が使われているパターンを検出するために使用。
+fn collect_acquire_resources(expr: &Expr) -> Vec<String> {
+    let mut resources = Vec::new();
+    match expr {
+        Expr::Acquire { resource, body } => {
+            resources.push(resource.clone());
+            resources.extend(collect_acquire_resources(body));
+        }
+        Expr::Block(stmts) => {
+            for stmt in stmts {
+                resources.extend(collect_acquire_resources(stmt));
+            }
+        }
+        Expr::While { body, .. } => {
+            resources.extend(collect_acquire_resources(body));
+        }
+        Expr::IfThenElse { then_branch, else_branch, .. } => {
+            resources.extend(collect_acquire_resources(then_branch));
+            resources.extend(collect_acquire_resources(else_branch));
+        }
+        Expr::Let { value, .. } | Expr::Assign { value, .. } => {
+            resources.extend(collect_acquire_resources(value));
+        }
+        Expr::Async { body } => {
+            resources.extend(collect_acquire_resources(body));
+        }
+        Expr::Await { expr } => {
+            resources.extend(collect_acquire_resources(expr));
+        }
+        _ => {}
+    }
+    resources
+}
+
+/// 有界モデル検査: atom の body 内のループを展開し、
+/// 各展開でリソース階層制約が維持されることを検証する。
+///
+/// 展開回数は atom.max_unroll（指定時）または BMC_DEFAULT_UNROLL_DEPTH を使用。
+/// ループ不変量が提供されている場合はスキップ（不変量ベースの検証が優先）。
+/// BMC は「ユーザーが不変量を書けない場合」の補助的な検証手段。
+fn verify_bmc_resource_safety(atom: &Atom, module_env: &ModuleEnv) -> MumeiResult<()> {
+    // body 内に acquire が含まれない場合はスキップ
+    let body_ast = parse_expression(&atom.body_expr)?;
+    let acquired_resources = collect_acquire_resources(&body_ast);
+    if acquired_resources.is_empty() {
+        return Ok(());
+    }
+
+    // While ループ内に acquire があるかチェック
+    fn has_acquire_in_while(expr: &Expr) -> bool {
+        match expr {
+            Expr::While { body, .. } => {
+                !collect_acquire_resources(body).is_empty()
+            }
+            Expr::Block(stmts) => stmts.iter().any(has_acquire_in_while),
+            Expr::IfThenElse { then_branch, else_branch, .. } => {
+                has_acquire_in_while(then_branch) || has_acquire_in_while(else_branch)
+            }
+            _ => false,
+        }
+    }
+
+    if !has_acquire_in_while(&body_ast) {
+        return Ok(()); // ループ外の acquire は通常の検証で十分
+    }
+
+    // 展開回数: atom 単位のオーバーライド > グローバルデフォルト
+    let unroll_depth = atom.max_unroll.unwrap_or(BMC_DEFAULT_UNROLL_DEPTH);
+
+    // BMC: ループを展開して各ステップでリソース階層をチェック
+    let mut resource_ctx = ResourceCtx::new();
+
+    for unroll_step in 0..unroll_depth {
+        // 各展開ステップで acquire されるリソースの順序を検証
+        for res_name in &acquired_resources {
+            if let Some((key, rdef)) = module_env.resolve_resource(res_name)? {
+                if let Err(e) = resource_ctx.acquire(&key, rdef.priority) {
+                    return Err(MumeiError::failure(FailureKind::Resource,
+                        format!(
+                            "BMC (unroll step {}/{}, max_unroll={}): resource ordering violation in loop body: {}",
+                            unroll_step, unroll_depth, unroll_depth, e
+                        )
+                    ));
+                }
+            }
+        }
+        // 各ステップ終了時にリソースを解放（ループの次のイテレーションをシミュレート）
+        for res_name in &acquired_resources {
+            match module_env.resolve_resource(res_name)? {
+                Some((key, _)) => resource_ctx.release(&key),
+                None => resource_ctx.release(res_name),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// 再帰的 async 呼び出しの深度を検証する。
+/// async atom が自身を（直接的または間接的に）呼び出す場合、
+/// MAX_ASYNC_RECURSION_DEPTH を超える再帰がないことを静的にチェックする。
+///
+/// 仕組み: body 内の Call 式を走査し、呼び出し先が async atom かつ
+/// 自身と同名の場合、再帰深度カウンタをインクリメント。
+/// 上限を超えたら「Unknown」として打ち切り、警告を出す。
+fn verify_async_recursion_depth(atom: &Atom, module_env: &ModuleEnv) -> MumeiResult<()> {
+    if !atom.is_async {
+        return Ok(());
+    }
+
+    let body_ast = parse_expression(&atom.body_expr)?;
+    let self_call_count = collect_callees(&body_ast).iter().filter(|callee| **callee == atom.name).count();
+
+    if self_call_count > 0 {
+        // 再帰的 async 呼び出しが検出された
+        // 呼び出し先の async atom も再帰する可能性があるため、
+        // 深度制限を超える場合は警告
+        let max_depth = atom.max_unroll.unwrap_or(MAX_ASYNC_RECURSION_DEPTH);
+        if self_call_count > max_depth {
+            return Err(MumeiError::failure(FailureKind::Termination,
+                format!(
+                    "Async recursion depth exceeded in atom '{}': {} self-calls detected \
+                     (max_depth={}). Use max_unroll: {}; to increase the limit, or \
+                     refactor to use iteration with invariant.",
+                    atom.name, self_call_count, max_depth, self_call_count + 1
+                )
+            ));
+        }
+
+        // 再帰呼び出し先の契約を信頼して展開（Compositional Verification）
+        // 各展開ステップで ensures を仮定として使用する。
+        // これにより、f_depth_1, f_depth_2 ... と別シンボルとして扱われ、
+        // Z3 が無限ループに陥ることを防ぐ。
+        if let Some(callee) = module_env.get_atom(&atom.name) {
+            if callee.ensures.trim() == "true" {
+                // ensures が trivial な場合、再帰の安全性を証明できない
+                return Err(MumeiError::VerificationError(
+                    format!(
+                        "Recursive async atom '{}' requires a non-trivial ensures clause \
+                         for inductive verification. Add: ensures: <postcondition>;",
+                        atom.name
+                    )
+                ));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// =============================================================================
+// Atom レベル Invariant の帰納的検証 (Inductive Invariant Verification)
+// =============================================================================
+//
+// atom シグネチャに `invariant: <expr>;` が指定されている場合、
+// 帰納法（数学的帰納法）により不変量の正しさを証明する。
+//
+// 証明構造:
+// 1. 導入 (Induction Base):
+//    requires が成立するとき、invariant が成立することを証明する。
+//    ∀ params. requires(params) → invariant(params)
+//
+// 2. 維持 (Induction Step / Preservation):
+//    invariant が成立する状態で body を実行した後も invariant が維持されることを証明する。
+//    ∀ params. invariant(params) ∧ requires(params) → invariant(body(params))
+//    ※ 再帰呼び出しがある場合、呼び出し先の invariant を帰納法の仮定として使用する。
+//
+// これにより、再帰的 async atom の安全性を、ループ不変量と同様の
+// 帰納的推論で証明できる。BMC の「有界」な保証を「完全」な保証に昇格させる。
+
+/// atom レベルの invariant を帰納的に検証する。
+fn verify_atom_invariant(atom: &Atom, invariant_raw: &str, module_env: &ModuleEnv, warnings: &RefCell<Vec<String>>) -> MumeiResult<()> {
+    let mut cfg = Config::new();
+    cfg.set_timeout_msec(5000);
+    let ctx = Context::new(&cfg);
+    let solver = Solver::new(&ctx);
+
+    let vc = VCtx { ctx: &ctx, arrays: RefCell::default(), params: &atom.params, module_env, obligations: None, guard: None, scope: &atom.name, counters: RefCell::default(), total: false, undecided: RefCell::default(), arm_stack: RefCell::default(), arm_paths: RefCell::default(), entry_measure: RefCell::default(), futures: RefCell::default(), call_lengths: RefCell::default(), struct_values: RefCell::default(), float_defs: RefCell::default(), callees: RefCell::default(), warnings: Some(warnings) };
+
+    let mut env: Env = HashMap::new();
+
+    // パラメータをシンボリック変数として登録
+    for param in &atom.params {
+        let base = param.type_name.as_deref()
+            .map(|t| module_env.resolve_base_type(t))
+            .unwrap_or_else(|| "i64".to_string());
+        let var: Dynamic = match base.as_str() {
+            "f64" => Float::new_const(&ctx, vc.sym(&param.name), 11, 53).into(),
+            "bool" => Bool::new_const(&ctx, vc.sym(&param.name)).into(),
+            _ => Int::new_const(&ctx, vc.sym(&param.name)).into(),
+        };
+        env.insert(param.name.clone(), var);
+
+        // 精緻型制約も適用
+        if let Some(type_name) = &param.type_name {
+            if let Some(refined) = module_env.get_type(type_name) {
+                apply_refinement_constraint(&vc, &solver, &param.name, refined, &mut env)?;
+            }
+        }
+    }
+
+    // invariant 式をパース
+    let inv_ast = parse_expression(invariant_raw)?;
+    let inv_z3 = expr_to_z3(&vc, &inv_ast, &mut env, None)?
+        .as_bool().ok_or(MumeiError::TypeError(
+            format!("Invariant for atom '{}' must be a boolean expression", atom.name)
+        ))?;
+
+    // === Step 1: 導入 (Induction Base) ===
+    // requires → invariant を証明する
+    if atom.requires.trim() != "true" {
+        let req_ast = parse_expression(&atom.requires)?;
+        let req_z3 = expr_to_z3(&vc, &req_ast, &mut env, None)?;
+        if let Some(req_bool) = req_z3.as_bool() {
+            solver.push();
+            // requires を仮定
+            solver.assert(&req_bool);
+            // invariant の否定を assert
+            solver.assert(&inv_z3.not());
+            // Unsat なら requires → invariant が証明された
+            if vc.check(&solver) == SatResult::Sat {
+                solver.pop(1);
+                return Err(MumeiError::failure(FailureKind::LoopInvariant,
+                    format!(
+                        "Invariant induction base failed for atom '{}': \
+                         requires does not imply invariant.\n  \
+                         Invariant: {}{}\n  \
+                         Requires: {}\n  \
+                         The invariant must hold whenever the precondition is satisfied.",
+                        atom.name, invariant_raw, where_line(atom), crate::parser::indent_contract(&atom.requires_raw, "            ")
+                    )
+                ));
+            }
+            solver.pop(1);
+        }
+    } else {
+        // requires が true の場合、invariant は無条件に成立する必要がある
+        solver.push();
+        solver.assert(&inv_z3.not());
+        if vc.check(&solver) == SatResult::Sat {
+            solver.pop(1);
+            return Err(MumeiError::failure(FailureKind::LoopInvariant,
+                format!(
+                    "Invariant induction base failed for atom '{}': \
+                     invariant '{}' is not universally true (no requires constraint).",
+                    atom.name, invariant_raw
+                )
+            ));
+        }
+        solver.pop(1);
+    }
+
+    // === Step 2: 維持 (Preservation) ===
+    // invariant ∧ requires のもとで body を実行した後も invariant が維持されることを証明
+    {
+        let env_snapshot = env.clone();
+        solver.push();
+
+        // invariant を仮定（帰納法の仮定）
+        solver.assert(&inv_z3);
+
+        // requires も仮定
+        if atom.requires.trim() != "true" {
+            let req_ast = parse_expression(&atom.requires)?;
+            let req_z3 = expr_to_z3(&vc, &req_ast, &mut env, None)?;
+            if let Some(req_bool) = req_z3.as_bool() {
+                solver.assert(&req_bool);
+            }
+        }
+
+        // body を実行
+        let body_ast = parse_expression(&atom.body_expr)?;
+        let _body_result = expr_to_z3(&vc, &body_ast, &mut env, Some(&solver))?;
+
+        // body 実行後の invariant を再評価
+        // （env が body の実行で更新されている可能性がある）
+        let inv_after = expr_to_z3(&vc, &inv_ast, &mut env, None)?
+            .as_bool().ok_or(MumeiError::TypeError("Invariant must be boolean".into()))?;
+
+        // invariant の維持を検証: ¬inv_after が Unsat なら維持されている
+        solver.assert(&inv_after.not());
+        if vc.check(&solver) == SatResult::Sat {
+            solver.pop(1);
+            return Err(MumeiError::failure(FailureKind::LoopInvariant,
+                format!(
+                    "Invariant preservation failed for atom '{}': \
+                     body execution may violate the invariant.\n  \
+                     Invariant: {}\n  \
+                     The invariant must be maintained after executing the body.",
+                    atom.name, invariant_raw
+                )
+            ));
+        }
+        solver.pop(1);
+        let _ = env_snapshot; // env_snapshot はスコープ終了で破棄
+    }
+
+    Ok(())
+}
+
+// =============================================================================
+// Call Graph サイクル検知 (Call Graph Cycle Detection)
+// =============================================================================
+//
+// 間接再帰（A→B→A）を含む呼び出しグラフのサイクルを検出する。
+// 直接再帰は verify_async_recursion_depth で検出済みだが、
+// 間接再帰はグラフ全体を走査する必要がある。
+//
+// アルゴリズム: DFS による強連結成分（SCC）の簡易検出。
+// サイクルが検出された場合、invariant の記述を要求するか、
+// BMC の深度制限を適用する。
+
+/// body 内の全 Call 式から呼び出し先の atom 名を収集する。
+pub fn collect_callees(expr: &Expr) -> Vec<String> {
+    let mut callees = Vec::new();
+    expr.walk(|e| {
+        if let Expr::Call(name, _) = e {
+            callees.push(name.clone());
+        }
+        true
+    });
+    callees
+}
+
+/// Call Graph のサイクルを DFS で検出する。
+/// atom_name から到達可能なサイクルがある場合、サイクルのパスを返す。
+/// include_direct が false なら直接の自己再帰（`f → f`）は無視し、他の atom を経由するサイクルだけを探す。
+fn detect_call_cycle(atom_name: &str, module_env: &ModuleEnv, include_direct: bool) -> Option<Vec<String>> {
+    let mut visited: HashSet<String> = HashSet::new();
+    let mut path: Vec<String> = Vec::new();
+
+    fn dfs(
+        current: &str,
+        target: &str,
+        module_env: &ModuleEnv,
+        visited: &mut HashSet<String>,
+        path: &mut Vec<String>,
+    ) -> bool {
+        if current == target && !path.is_empty() {
+            return true; // サイクル検出
+        }
+        if visited.contains(current) {
+            return false;
+        }
+        visited.insert(current.to_string());
+        path.push(current.to_string());
+
+        if let Some(callee_atom) = module_env.get_atom(current) {
+            let callees = parse_expression(&callee_atom.body_expr).map(|body| collect_callees(&body)).unwrap_or_default();
+            for callee_name in &callees {
+                if let Some(_) = module_env.get_atom(callee_name) {
+                    if callee_name == target && !path.is_empty() {
+                        path.push(callee_name.clone());
+                        return true;
+                    }
+                    if dfs(callee_name, target, module_env, visited, path) {
+                        return true;
+                    }
+                }
+            }
+        }
+
+        path.pop();
+        false
+    }
+
+    // atom_name の呼び出し先から DFS 開始
+    if let Some(atom) = module_env.get_atom(atom_name) {
+        let callees = parse_expression(&atom.body_expr).map(|body| collect_callees(&body)).unwrap_or_default();
+        for callee_name in &callees {
+            if !include_direct && callee_name == atom_name {
+                continue;
+            }
+            if let Some(_) = module_env.get_atom(callee_name) {
+                visited.clear();
+                path.clear();
+                path.push(atom_name.to_string());
+                if dfs(callee_name, atom_name, module_env, &mut visited, &mut path) {
+                    return Some(path);
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Call Graph サイクル検知を実行し、invariant も BMC 深度制限もないサイクルについての警告を返す。
+fn call_graph_cycle_warning(atom: &Atom, module_env: &ModuleEnv) -> Option<String> {
+    // atom レベルの decreases があれば直接の自己再帰は呼び出し箇所ごとに停止性を証明する
+    if let Some(cycle_path) = detect_call_cycle(&atom.name, module_env, atom.decreases.is_none()) {
+        let cycle_str = cycle_path.join(" → ");
+
+        // invariant が指定されていれば帰納的検証で対応可能
+        if atom.invariant.is_some() {
+            // invariant が指定されている → 帰納的検証で安全性を保証
+            // （verify_atom_invariant で検証済み）
+            return None;
+        }
+
+        // max_unroll が指定されていれば BMC で対応
+        if atom.max_unroll.is_some() {
+            // BMC 深度制限が明示されている → 有界検証で対応
+            return None;
+        }
+
+        // どちらもない場合は警告（エラーではなく警告にとどめる）
+        return Some(format!(
+            "call graph cycle detected: {}; consider adding `invariant: <expr>;` for complete proof, \
+             or `max_unroll: N;` for bounded verification",
+            cycle_str
+        ));
+    }
+    None
+}
+
+/// total atom の再帰に停止性の根拠があることを確認する。
+/// 直接の自己再帰は atom レベルの `decreases:` があれば呼び出し箇所ごとに測度の減少を証明するため許可する。
+/// 相互再帰は測度を atom 間で比較できないため、decreases の有無にかかわらず拒否する。
+fn verify_total_recursion(atom: &Atom, module_env: &ModuleEnv) -> MumeiResult<()> {
+    let cycle = if atom.decreases.is_none() && collect_callees(&parse_expression(&atom.body_expr)?).contains(&atom.name) {
+        Some(vec![atom.name.clone(), atom.name.clone()])
+    } else {
+        detect_call_cycle(&atom.name, module_env, false)
+    };
+    match cycle {
+        Some(path) => Err(MumeiError::failure(FailureKind::Totality, format!(
+            "Totality violation in atom '{}': recursive call cycle {} has no termination measure. \
+             Add an atom-level `decreases:` clause (direct recursion only) or rewrite the recursion as a `while` loop with a `decreases` clause",
+            atom.name, path.join(" → ")
+        ))),
+        None => Ok(()),
+    }
+}
+
+// =============================================================================
+// ensures から参照できる body の束縛 (Observable Bindings)
+// =============================================================================
+//
+// ensures はパラメータと result に加え、body のトップレベル Block 直下の let 束縛を
+// その名前で参照できる（例: body: { let mid = (lo + hi) / 2; ... } / ensures: result >= mid）。
+//
+// 規則:
+// - 見えるのは body の Block 直下の `let` のみ。値は body 終了時点のもの（後続の代入を反映）。
+// - ネストした Block・if / while / match の内側の `let` は見えない。
+//   その名前がパラメータと同じなら、ensures ではパラメータを指す。
+// - トップレベルの `let` がパラメータと同名の場合、ensures ではその let を指し、
+//   パラメータは参照できなくなる（警告を出す）。
+
+/// body から ensures に公開される束縛
+struct ObservableBindings {
+    /// トップレベルの let 束縛名（出現順・重複なし）
+    names: Vec<String>,
+    /// パラメータを隠すトップレベル let の警告
+    warnings: Vec<String>,
+}
+
+/// body AST から observable bindings を求める
+fn observable_bindings(atom: &Atom, body: &Expr) -> ObservableBindings {
+    let mut names: Vec<String> = Vec::new();
+    if let Expr::Block(stmts) = body {
+        for stmt in stmts {
+            if let Expr::Let { var, .. } = stmt {
+                if !names.contains(var) {
+                    names.push(var.clone());
+                }
+            }
+        }
+    }
+    let warnings = names.iter()
+        .filter(|name| atom.params.iter().any(|p| &p.name == *name))
+        .map(|name| format!(
+            "let binding '{}' in the body of atom '{}' shadows parameter '{}'; \
+             ensures refers to the let binding and the parameter is not accessible there",
+            name, atom.name, name
+        ))
+        .collect();
+    ObservableBindings { names, warnings }
+}
+
+/// body 評価前後の env から ensures 用の env を組み立てる。
+/// - body 評価前からあるシンボル（パラメータ等）は body 終了時点の値
+///   （ネストした let はブロックを抜けるときに外側の値へ戻っている）
+/// - トップレベル let は body 終了時点の値
+/// - `__` で始まる内部シンボル（所有権・リソース・nullable など）はそのまま引き継ぐ
+/// - それ以外は含めない
+fn ensures_env<'a>(pre_body: &Env<'a>, post_body: &Env<'a>, bindings: &ObservableBindings) -> Env<'a> {
+    let mut ens_env: Env = HashMap::new();
+    for (name, value) in post_body {
+        if bindings.names.contains(name) || name.starts_with("__") || pre_body.contains_key(name) {
+            ens_env.insert(name.clone(), value.clone());
+        }
+    }
+    ens_env
+}
+
+// =============================================================================
+// result の型の食い違い (Result Usage)
+// =============================================================================
+//
+// body の値の種類（数値 / 真偽値。codegen / transpiler と同じ crate::ast::result_kind）と、ensures が result を数値・真偽値のどちらとして
+// 使っているかを Z3 に渡す前に突き合わせ、食い違いを最初の連言を示すエラーにする。
+// （そのまま渡すと ensures の翻訳の奥で "Expected int" になるか、検査が空振りする）
+
+/// ensures 中の result の扱い
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ResultUse {
+    Number,
+    Boolean,
+}
+
+impl ResultUse {
+    fn describe(&self) -> &'static str {
+        match self {
+            ResultUse::Number => "a number",
+            ResultUse::Boolean => "a boolean",
+        }
+    }
+}
+
+fn is_result(expr: &Expr) -> bool {
+    matches!(expr, Expr::Variable(v) if v == "result")
+}
+
+/// `result == x` の比較相手から result の扱いを決める（構文だけで決まるものに限る）
+fn operand_use(expr: &Expr) -> Option<ResultUse> {
+    match expr {
+        Expr::Number(_) | Expr::Float(_) => Some(ResultUse::Number),
+        Expr::Bool(_) => Some(ResultUse::Boolean),
+        Expr::BinaryOp(_, Op::Add | Op::Sub | Op::Mul | Op::Div, _) => Some(ResultUse::Number),
+        Expr::BinaryOp(..) => Some(ResultUse::Boolean),
+        _ => None,
+    }
+}
+
+/// 式の中で result が最初に使われる箇所の扱い。決められなければ None。
+/// result そのものが真偽値の位置（連言・論理演算のオペランド・if の条件）にある場合は呼び出し側が判定する
+fn result_use(expr: &Expr) -> Option<ResultUse> {
+    match expr {
+        Expr::BinaryOp(l, op, r) => {
+            if is_result(l) || is_result(r) {
+                let used = match op {
+                    Op::And | Op::Or | Op::Implies => Some(ResultUse::Boolean),
+                    Op::Eq | Op::Neq => operand_use(if is_result(l) { r } else { l }),
+                    _ => Some(ResultUse::Number),
+                };
+                if used.is_some() {
+                    return used;
+                }
+            }
+            result_use(l).or_else(|| result_use(r))
+        }
+        Expr::IfThenElse { cond, then_branch, else_branch } => {
+            if is_result(cond) {
+                return Some(ResultUse::Boolean);
+            }
+            result_use(cond).or_else(|| result_use(then_branch)).or_else(|| result_use(else_branch))
+        }
+        Expr::Call(name, args) if name == "approx_eq" && args.iter().take(2).any(is_result) => Some(ResultUse::Number),
+        Expr::Call(_, args) => args.iter().find_map(result_use),
+        Expr::Block(stmts) => stmts.iter().find_map(result_use),
+        Expr::Let { value, .. } => result_use(value),
+        _ => None,
+    }
+}
+
+/// 契約（requires / ensures）をトップレベルの && で分けた連言
+fn contract_conjuncts<'e>(expr: &'e Expr, out: &mut Vec<&'e Expr>) {
+    match expr {
+        Expr::BinaryOp(l, Op::And, r) => {
+            contract_conjuncts(l, out);
+            contract_conjuncts(r, out);
+        }
+        _ => out.push(expr),
+    }
+}
+
+/// body の値の種類（codegen / transpiler と共有する crate::ast::result_kind）と ensures での result の扱いが
+/// 食い違えば、最初の食い違う連言を示す型エラーにする。構造体・文字列を返す atom は対象外
+fn check_result_usage(atom: &Atom, module_env: &ModuleEnv, ens_ast: &Expr) -> MumeiResult<()> {
+    if crate::ast::struct_return(atom, module_env).is_some() {
+        return Ok(());
+    }
+    let body_use = match crate::ast::result_kind(atom, module_env) {
+        crate::ast::ResultKind::Bool => ResultUse::Boolean,
+        crate::ast::ResultKind::Int | crate::ast::ResultKind::Float => ResultUse::Number,
+        crate::ast::ResultKind::Str => return Ok(()),
+    };
+    let mut conjuncts = Vec::new();
+    contract_conjuncts(ens_ast, &mut conjuncts);
+    for conjunct in conjuncts {
+        let used = if is_result(conjunct) { Some(ResultUse::Boolean) } else { result_use(conjunct) };
+        if let Some(used) = used.filter(|used| *used != body_use) {
+            return Err(MumeiError::TypeError(format!(
+                "body of atom '{}' evaluates to {} but ensures treats result as {}\n  Ensures conjunct: {}",
+                atom.name,
+                body_use.describe(),
+                used.describe(),
+                crate::printer::show(conjunct).unwrap_or_else(|| atom.ensures_raw.clone())
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Str の誤用（crate::ast::str_misuse）を、見つかった部分（requires / ensures / body）を示す型エラーにする
+fn check_str_usage(atom: &Atom, module_env: &ModuleEnv, part: &str, expr: &Expr) -> MumeiResult<()> {
+    match crate::ast::str_misuse(expr, atom, module_env) {
+        Some(misuse) => Err(MumeiError::TypeError(format!("{} in the {} of atom '{}'", misuse, part, atom.name))),
+        None => Ok(()),
+    }
+}
+
+// =============================================================================
+// シャドーイング (Shadowing)
+// =============================================================================
+//
+// 規則:
+// - match アームのパターン変数はそのアーム（ガードと body）だけの新しいスコープに束縛され、
+//   外側の同名の束縛（パラメータ・let）はアームの外では元の値のまま。
+// - Block は新しいスコープ。ただし ensures から見える束縛は observable bindings の規則に従う。
+// - パターン変数・let が既存の束縛を隠す場合は警告を出す。
+//   （body 直下の let がパラメータを隠す場合は observable bindings 側の警告で扱う）
+
+/// 束縛名 → 束縛の種類（"parameter" / "let" / "pattern variable"）のスコープスタック
+type ShadowScopes = Vec<HashMap<String, &'static str>>;
+
+/// atom の body からシャドーイングの警告を収集する
+fn shadowing_warnings(atom: &Atom, body: &Expr) -> Vec<String> {
+    let params: HashMap<String, &'static str> = atom.params.iter()
+        .map(|p| (p.name.clone(), "parameter"))
+        .collect();
+    let mut scopes: ShadowScopes = vec![params];
+    let mut warnings = Vec::new();
+    match body {
+        Expr::Block(stmts) => {
+            scopes.push(HashMap::new());
+            for stmt in stmts {
+                collect_shadowing(stmt, &mut scopes, true, &mut warnings);
+            }
+        }
+        other => collect_shadowing(other, &mut scopes, false, &mut warnings),
+    }
+    warnings
+}
+
+fn lookup_binding(scopes: &ShadowScopes, name: &str) -> Option<&'static str> {
+    scopes.iter().rev().find_map(|scope| scope.get(name).copied())
+}
+
+/// パターンが束縛する変数名を収集する
+fn pattern_variables(pattern: &Pattern, out: &mut Vec<String>) {
+    match pattern {
+        Pattern::Variable(name) => out.push(name.clone()),
+        Pattern::Variant { fields, .. } => {
+            for field in fields {
+                pattern_variables(field, out);
+            }
+        }
+        Pattern::Wildcard | Pattern::Literal(_) => {}
+    }
+}
+
+/// body_top: body の Block 直下の文かどうか
+fn collect_shadowing(expr: &Expr, scopes: &mut ShadowScopes, body_top: bool, out: &mut Vec<String>) {
+    match expr {
+        Expr::Let { var, value } => {
+            collect_shadowing(value, scopes, false, out);
+            match lookup_binding(scopes, var) {
+                Some("parameter") if body_top => {}
+                Some(kind) => out.push(format!("let '{}' shadows {} '{}'", var, kind, var)),
+                None => {}
+            }
+            if let Some(scope) = scopes.last_mut() {
+                scope.insert(var.clone(), "let");
+            }
+        }
+        Expr::Block(stmts) => {
+            scopes.push(HashMap::new());
+            for stmt in stmts {
+                collect_shadowing(stmt, scopes, false, out);
+            }
+            scopes.pop();
+        }
+        Expr::Match { target, arms } => {
+            collect_shadowing(target, scopes, false, out);
+            for arm in arms {
+                let mut vars = Vec::new();
+                pattern_variables(&arm.pattern, &mut vars);
+                let mut arm_scope = HashMap::new();
+                for var in vars {
+                    if let Some(kind) = lookup_binding(scopes, &var) {
+                        out.push(format!("pattern variable '{}' shadows {} '{}'", var, kind, var));
+                    }
+                    arm_scope.insert(var, "pattern variable");
+                }
+                scopes.push(arm_scope);
+                if let Some(guard) = &arm.guard {
+                    collect_shadowing(guard, scopes, false, out);
+                }
+                collect_shadowing(&arm.body, scopes, false, out);
+                scopes.pop();
+            }
+        }
+        Expr::Assign { value, .. } => collect_shadowing(value, scopes, false, out),
+        Expr::IfThenElse { cond, then_branch, else_branch } => {
+            collect_shadowing(cond, scopes, false, out);
+            collect_shadowing(then_branch, scopes, false, out);
+            collect_shadowing(else_branch, scopes, false, out);
+        }
+        Expr::While { cond, body, .. } => {
+            collect_shadowing(cond, scopes, false, out);
+            collect_shadowing(body, scopes, false, out);
+        }
+        Expr::Acquire { body, .. } | Expr::Async { body } => collect_shadowing(body, scopes, false, out),
+        Expr::Await { expr } | Expr::FieldAccess(expr, _) | Expr::RefArg { expr, .. } => {
+            collect_shadowing(expr, scopes, false, out)
+        }
+        Expr::Assume { cond, .. } => collect_shadowing(cond, scopes, false, out),
+        Expr::BinaryOp(..) => {
+            // 左に連なる連鎖は再帰せずに辿る
+            let (leaf, nodes) = expr.left_spine();
+            collect_shadowing(leaf, scopes, false, out);
+            for node in nodes {
+                if let Expr::BinaryOp(_, _, r) = node {
+                    collect_shadowing(r, scopes, false, out);
+                }
+            }
+        }
+        Expr::Call(_, args) => {
+            for arg in args {
+                collect_shadowing(arg, scopes, false, out);
+            }
+        }
+        Expr::StructInit { fields, .. } => {
+            for (_, field_expr) in fields {
+                collect_shadowing(field_expr, scopes, false, out);
+            }
+        }
+        Expr::ArrayAccess(_, idx) => collect_shadowing(idx, scopes, false, out),
+        Expr::Number(_) | Expr::Float(_) | Expr::StringLit(_) | Expr::Bool(_) | Expr::Variable(_) => {}
+    }
+}
+
+// =============================================================================
+// Taint Analysis (汚染解析)
+// =============================================================================
+//
+// unverified な外部関数から戻ってきた値を「汚染済み（tainted）」としてマークし、
+// tainted 値が安全性の証明に使われた場合に警告を出す。
+//
+// 仕組み:
+// - expr_to_z3 の Call 処理で、呼び出し先が unverified の場合、
+//   戻り値に __tainted_{call_id} マーカーを付与する。
+// - ensures の検証時、env 内に __tainted_* が存在する場合、
+//   「検証結果が未検証コードに依存している」旨の警告を出す。
+
+/// unverified 関数の呼び出しを検出し、検証結果がそれに依存している旨の警告を返す。
+/// verify() の body 検証後に呼び出される。
+fn check_taint_propagation(atom: &Atom, env: &Env, module_env: &ModuleEnv) -> Option<String> {
+    // body 内で呼び出されている関数を収集
+    let callees = parse_expression(&atom.body_expr).map(|body| collect_callees(&body)).unwrap_or_default();
+
+    let mut tainted_sources: Vec<String> = Vec::new();
+    for callee_name in &callees {
+        if let Some(callee) = module_env.get_atom(callee_name) {
+            if callee.trust_level == TrustLevel::Unverified {
+                tainted_sources.push(callee_name.clone());
+            }
+        }
+    }
+
+    if !tainted_sources.is_empty() {
+        // env 内の __tainted_* マーカーを確認
+        let taint_markers: Vec<&String> = env.keys()
+            .filter(|k| k.starts_with("__tainted_"))
+            .collect();
+
+        if !taint_markers.is_empty() || !tainted_sources.is_empty() {
+            return Some(format!(
+                "verification depends on unverified function(s): [{}]; results may be unsound",
+                tainted_sources.join(", ")
+            ));
+        }
+    }
+    None
+}
+
+/// mumei.toml の [proof]/[build] 設定を反映した verify
+/// timeout_ms: Z3 ソルバのタイムアウト（ミリ秒）
+/// global_max_unroll: BMC のグローバル展開深度
+pub fn verify_with_config(atom: &Atom, output_dir: &Path, module_env: &ModuleEnv, timeout_ms: u64, _global_max_unroll: usize) -> MumeiResult<Vec<String>> {
+    let warnings = RefCell::default();
+    verify_inner(atom, output_dir, module_env, timeout_ms, None, &warnings)?;
+    Ok(warnings.into_inner())
+}
+
+/// atom 1 件あたりの Z3 のタイムアウトの既定値（ミリ秒）。`[proof] timeout_ms` で変更できる
+pub const DEFAULT_TIMEOUT_MS: u64 = 10_000;
+
+/// module_env の `[proof] timeout_ms`（なければ DEFAULT_TIMEOUT_MS）で検証し、
+/// 検証を止めない警告（呼び出しサイクル・unverified への依存・未使用の requires など）を返す
+pub fn verify(atom: &Atom, output_dir: &Path, module_env: &ModuleEnv) -> MumeiResult<Vec<String>> {
+    verify_with_config(atom, output_dir, module_env, module_env.timeout_ms.unwrap_or(DEFAULT_TIMEOUT_MS), 0)
+}
+
+/// 義務単位キャッシュ付きの verify。
+/// cache.previous に含まれる義務は再証明せず、今回証明された義務を cache.current に蓄積する。
+/// atom 全体の成否は再利用分と新規証明分を合わせた結果から決まる。
+/// 警告は失敗した atom の分も含めて cache.warnings に記録する。
+pub fn verify_with_cache(atom: &Atom, output_dir: &Path, module_env: &ModuleEnv, timeout_ms: u64, cache: &mut ObligationCache) -> MumeiResult<()> {
+    let cell = RefCell::new(std::mem::take(cache));
+    let warnings = RefCell::default();
+    let result = verify_inner(atom, output_dir, module_env, timeout_ms, Some(&cell), &warnings);
+    *cache = cell.into_inner();
+    cache.warnings = warnings.into_inner();
+    result
+}
+
+/// 検証を止めない警告は warnings に積む（表示は呼び出し元が行う）
+fn verify_inner(atom: &Atom, output_dir: &Path, module_env: &ModuleEnv, timeout_ms: u64, obligations: Option<&RefCell<ObligationCache>>, warnings: &RefCell<Vec<String>>) -> MumeiResult<()> {
+    // Phase 0a: 整数リテラルの範囲検査（trusted atom の契約も呼び出し元で使われるため常に実施）
+    for source in [&atom.requires, &atom.ensures, &atom.body_expr] {
+        crate::parser::validate_literals(source)
+            .map_err(|e| MumeiError::TypeError(format!("{} in atom '{}'", e, atom.name)))?;
+    }
+
+    // Phase 0b: atom 名・パラメータ名が内部シンボルと衝突しないこと、量化子が書ける位置にあること
+    crate::parser::validate_names(atom).map_err(MumeiError::VerificationError)?;
+    crate::parser::validate_quantifiers(atom).map_err(MumeiError::VerificationError)?;
+
+    // Phase 0c: assume（証明なしの仮定）は正当化文が必須で、deny_assume のプロジェクトと total atom では使えない
+    let assumptions = crate::parser::atom_assumptions(atom).map_err(MumeiError::VerificationError)?;
+    if let Some(first) = assumptions.first() {
+        if module_env.deny_assume {
+            return Err(MumeiError::VerificationError(format!(
+                "assume is denied by [proof] deny_assume = true: atom '{}' assumes {} (\"{}\")",
+                atom.name, first.condition, first.justification
+            )));
+        }
+        if atom.is_total {
+            return Err(MumeiError::failure(FailureKind::Totality, format!(
+                "Totality violation in atom '{}': a total atom cannot contain assume ({}); its obligations must follow from requires alone",
+                atom.name, first.condition
+            )));
+        }
+    }
+
+    // Phase 0: 信頼レベルチェック（Trust Boundary）
+    if atom.is_total && atom.trust_level != TrustLevel::Verified {
+        return Err(MumeiError::failure(FailureKind::Totality, format!(
+            "Totality violation in atom '{}': a total atom must be fully verified and cannot be trusted or unverified",
+            atom.name
+        )));
+    }
+    match &atom.trust_level {
+        TrustLevel::Trusted => {
+            // trusted atom: body の検証をスキップし、契約（requires/ensures）のみ信頼する。
+            // 呼び出し元は契約に基づいて Compositional Verification を行う。
+            // ただし充足不能な requires はどの呼び出しでも満たせない契約なので、trusted でも拒否する
+            if let Some(failure) = requires_contradiction(atom, module_env, timeout_ms)? {
+                save_visualizer_report(output_dir, AtomStatus::Failed, &atom.name, &failure);
+                return Err(MumeiError::VerificationError(failure));
+            }
+            save_visualizer_report(output_dir, AtomStatus::Trusted, &atom.name,
+                "Trusted: body verification skipped, contract assumed correct.");
+            return Ok(());
+        }
+        TrustLevel::Unverified => {
+            // unverified atom: 警告を出すが、検証は続行する。
+            // ensures が non-trivial な場合のみ検証を試みる。
+            warnings.borrow_mut().push("atom is marked as 'unverified'; verification results may be incomplete".to_string());
+            if atom.ensures.trim() == "true" && atom.requires.trim() == "true" {
+                // 契約が trivial な場合、検証する意味がないのでスキップ
+                save_visualizer_report(output_dir, AtomStatus::Unverified, &atom.name,
+                    "Unverified: no contract to verify.");
+                return Ok(());
+            }
+        }
+        TrustLevel::Verified => {
+            // 通常の検証フロー
+        }
+    }
+
+    // Phase 1: リソース階層検証（デッドロック防止）
+    verify_resource_hierarchy(atom, module_env)?;
+
+    // Phase 1b: 有界モデル検査（ループ内 acquire パターン）
+    verify_bmc_resource_safety(atom, module_env)?;
+
+    // Phase 1c: 再帰的 async 呼び出しの深度検証
+    verify_async_recursion_depth(atom, module_env)?;
+
+    // Phase 1d: atom レベル invariant の帰納的検証
+    if let Some(ref invariant_expr) = atom.invariant {
+        verify_atom_invariant(atom, invariant_expr, module_env, warnings)?;
+    }
+
+    // Phase 1e: Call Graph サイクル検知（間接再帰の検出）
+    if let Some(warning) = call_graph_cycle_warning(atom, module_env) {
+        warnings.borrow_mut().push(warning);
+    }
+
+    // Phase 1f: async ブロックによる線形値の遅延消費と await 跨ぎの使用を検証
+    verify_async_linearity(atom, module_env)?;
+
+    // Phase 1g: total atom の再帰は停止性の根拠（atom レベルの decreases）がある直接再帰だけを許す
+    if atom.is_total {
+        verify_total_recursion(atom, module_env)?;
+    }
+
+    // Z3 のハードリミット: ソフトタイムアウトを超えて暴走したクエリは watchdog が止める
+    let guard = SolverGuard::new(timeout_ms, module_env.solver_limits);
+    let result = with_pooled_solver(&guard, |ctx, solver| {
+        verify_with_solver(atom, output_dir, module_env, obligations, warnings, &guard, ctx, solver)
+    });
+    match guard.failure() {
+        Some(failure) => {
+            save_visualizer_report(output_dir, AtomStatus::Failed, &atom.name, &failure);
+            Err(MumeiError::failure(FailureKind::SolverLimit, format!("{} in atom '{}'", failure, atom.name)))
+        }
+        None => result,
+    }
+}
+
+/// パラメータの型から分かる制約を solver に仮定し、パラメータのシンボルを env に登録する
+/// （精緻型の述語・bool・構造体のフィールド・配列長・精緻型の配列要素・nullable）
+fn assume_params<'a>(vc: &VCtx<'a>, solver: &Solver<'a>, atom: &Atom, env: &mut Env<'a>) -> MumeiResult<()> {
+    let ctx = vc.ctx;
+    let module_env = vc.module_env;
+    // 2. 引数（params）に対する精緻型制約の自動適用
+    for param in &atom.params {
+        if let Some(type_name) = &param.type_name {
+            if let Some(refined) = module_env.get_type(type_name) {
+                apply_refinement_constraint(vc, solver, &param.name, refined, env)?;
+            } else if type_name == "bool" {
+                // 精緻化のない bool 引数は Bool シンボルにする（`if flag` や `flag == true` をそのまま扱う）
+                env.insert(param.name.clone(), Bool::new_const(ctx, vc.sym(&param.name)).into());
+            }
+        }
+    }
+
+    // 2b. 引数（params）に対する構造体フィールド制約の自動適用
+    for param in &atom.params {
+        if let Some(type_name) = &param.type_name {
+            if let Some(sdef) = module_env.get_struct(type_name) {
+                // 構造体の各フィールドをシンボリック変数として env に登録し、制約を適用。
+                // パラメータ自体は構造体の値として登録し、別の atom へ渡したり返したりできるようにする
+                let fields = symbolic_struct_fields(vc, sdef, &param.name, env, Some(solver))?;
+                for (field_name, field_z3) in &fields {
+                    env.insert(format!("{}_{}", param.name, field_name), field_z3.clone());
+                    // qualified name も登録
+                    env.insert(format!("__struct_{}_{}", param.name, field_name), field_z3.clone());
+                }
+                let handle: Dynamic = Int::new_const(ctx, vc.sym(&param.name)).into();
+                vc.register_struct_value(&handle, &sdef.name, fields);
+                env.insert(param.name.clone(), handle);
+            }
+        }
+    }
+
+    // 2c. 全パラメータに対して配列長シンボルを事前生成
+    for param in &atom.params {
+        let len_name = format!("len_{}", param.name);
+        if !env.contains_key(&len_name) {
+            let len_var = Int::new_const(ctx, vc.sym(&len_name));
+            solver.assert(&len_var.ge(&Int::from_i64(ctx, 0)));
+            env.insert(len_name, len_var.into());
+        }
+    }
+
+    // 2f. 精緻型の配列パラメータ（`xs: [Nat]`）の要素制約を ∀i ∈ [0, len_xs) で仮定する
+    for param in &atom.params {
+        let elem = param.type_name.as_deref().and_then(crate::ast::slice_elem_type);
+        if let Some(refined) = elem.as_deref().and_then(|e| module_env.get_type(e)) {
+            apply_element_refinement(vc, solver, &param.name, refined, env)?;
+        }
+    }
+
+    // 2e. nullable パラメータ（`x: T?` / `x: Option<T>`）のモデル化
+    // x 自体は Option の tag（None / Some）、値は `__some_x` として別シンボルで持ち、
+    // T の精緻型制約は「存在する場合のみ」仮定する（x == Some ⇒ P(__some_x)）。
+    for param in &atom.params {
+        let inner = match param.type_name.as_deref().and_then(crate::ast::option_inner_type) {
+            Some(inner) => inner,
+            None => continue,
+        };
+        let tag = Int::new_const(ctx, vc.sym(&param.name));
+        solver.assert(&tag.ge(&Int::from_i64(ctx, 0)));
+        solver.assert(&tag.le(&Int::from_i64(ctx, 1)));
+        env.insert(param.name.clone(), tag.clone().into());
+
+        let present = tag._eq(&Int::from_i64(ctx, option_some_tag(module_env)));
+        let value_name = nullable_value_name(&param.name);
+        let base = module_env.resolve_base_type(&inner);
+        let value: Dynamic = match base.as_str() {
+            "f64" => Float::new_const(ctx, vc.sym(&value_name), 11, 53).into(),
+            _ => Int::new_const(ctx, vc.sym(&value_name)).into(),
+        };
+        env.insert(value_name, value.clone());
+        if base == "u64" {
+            if let Some(v) = value.as_int() {
+                solver.assert(&present.implies(&v.ge(&Int::from_i64(ctx, 0))));
+            }
+        }
+        if let Some(refined) = module_env.get_type(&inner) {
+            let mut local_env = env.clone();
+            local_env.insert(refined.operand.clone(), value);
+            let predicate = expr_to_z3(vc, &parse_expression(&refined.predicate_raw)?, &mut local_env, None)?
+                .as_bool().ok_or(MumeiError::TypeError(format!("Predicate for {} must be boolean", refined.name)))?;
+            solver.assert(&present.implies(&predicate));
+        }
+    }
+    Ok(())
+}
+
+/// Z3 による atom 本体の検証（量化制約・パラメータ・requires・body・ensures・線形性）。
+/// ctx / solver はソルバプールのもので、呼び出し元が push したフレーム内で実行される。
+#[allow(clippy::too_many_arguments)]
+fn verify_with_solver<'ctx>(
+    atom: &Atom,
+    output_dir: &Path,
+    module_env: &ModuleEnv,
+    obligations: Option<&RefCell<ObligationCache>>,
+    warnings: &RefCell<Vec<String>>,
+    guard: &SolverGuard,
+    ctx: &'ctx Context,
+    solver: &Solver<'ctx>,
+) -> MumeiResult<()> {
+    let vc = VCtx { ctx, arrays: RefCell::default(), params: &atom.params, module_env, obligations, guard: Some(guard), scope: &atom.name, counters: RefCell::default(), total: atom.is_total, undecided: RefCell::default(), arm_stack: RefCell::default(), arm_paths: RefCell::default(), entry_measure: RefCell::default(), futures: RefCell::default(), call_lengths: RefCell::default(), struct_values: RefCell::default(), float_defs: RefCell::default(), callees: RefCell::default(), warnings: Some(warnings) };
+
+    let mut env: Env = HashMap::new();
+
+    // 1. 量子化制約の処理
+    // 束縛変数は一意な内部シンボル（"__q_i_0" など）にする。パラメータと同名でも捕獲は起きない
+    for warning in crate::parser::quantifier_shadowing(atom) {
+        vc.warn(warning);
+    }
+    for q in &atom.forall_constraints {
+        let bound_name = vc.fresh_bound(&q.var);
+        let i = Int::new_const(ctx, vc.sym(&bound_name));
+        let start = Int::from_i64(ctx, q.start.parse::<i64>().unwrap_or(0));
+        let end = if let Ok(val) = q.end.parse::<i64>() {
+            Int::from_i64(ctx, val)
+        } else {
+            Int::new_const(ctx, vc.sym(&q.end))
+        };
+
+        let range_cond = Bool::and(ctx, &[&i.ge(&start), &i.lt(&end)]);
+        let expr_ast = rename_bound(&parse_expression(&q.condition)?, &q.var, &bound_name);
+        let condition_z3 = expr_to_z3(&vc, &expr_ast, &mut env, None)?
+            .as_bool().ok_or(MumeiError::VerificationError("Condition must be boolean".into()))?;
+
+        let quantifier_expr = match q.q_type {
+            QuantifierType::ForAll => z3::ast::forall_const(ctx, &[&i], &[], &range_cond.implies(&condition_z3)),
+            QuantifierType::Exists => z3::ast::exists_const(ctx, &[&i], &[], &Bool::and(ctx, &[&range_cond, &condition_z3])),
+        };
+        solver.assert(&quantifier_expr);
+    }
+
+    // 2. パラメータの型（精緻型・構造体・配列長・nullable）の制約
+    assume_params(&vc, solver, atom, &mut env)?;
+
+    // 2d. 線形性チェック: consumed_params + ref パラメータの Z3 シンボリック Bool 連携
+    // consume 宣言されたパラメータに対して is_alive フラグを Z3 上で追跡する。
+    // ref パラメータに対しては借用カウントを追跡し、借用中の consume を禁止する。
+    let mut linearity_ctx = LinearityCtx::new();
+
+    // consume 対象パラメータの登録
+    if !atom.consumed_params.is_empty() {
+        for param_name in &atom.consumed_params {
+            // パラメータが実際に存在するか検証
+            if !atom.params.iter().any(|p| p.name == *param_name) {
+                return Err(MumeiError::TypeError(
+                    format!("consume target '{}' is not a parameter of atom '{}'", param_name, atom.name)
+                ));
+            }
+            // ref / ref mut パラメータは consume できない
+            if atom.params.iter().any(|p| p.name == *param_name && (p.is_ref || p.is_ref_mut)) {
+                let kind = if atom.params.iter().any(|p| p.name == *param_name && p.is_ref_mut) { "ref mut" } else { "ref" };
+                return Err(MumeiError::TypeError(
+                    format!("Cannot consume {} parameter '{}' in atom '{}': {} parameters are borrowed, not owned", kind, param_name, atom.name, kind)
+                ));
+            }
+            // LinearityCtx に登録
+            linearity_ctx.register(param_name);
+
+            // Z3 上で is_alive シンボリック Bool を作成し、初期値 true を assert
+            let alive_name = format!("__alive_{}", param_name);
+            let alive_bool = Bool::new_const(ctx, vc.sym(&alive_name));
+            solver.assert(&alive_bool); // 初期状態: alive = true
+            env.insert(alive_name, alive_bool.into());
+        }
+        // ensures 中の消費済みパラメータは消費前（入口）の値を指す。ref 引数として渡すのは拒否する
+        for warning in consumed_ensures_warnings(atom, &parse_expression(&atom.ensures)?, module_env)? {
+            vc.warn(warning);
+        }
+    }
+
+    // ref / ref mut パラメータの借用登録
+    // ref パラメータは読み取り専用で貸し出される。
+    // ref mut パラメータは排他的な書き込み参照として貸し出される。
+    // 借用中は元の所有者（呼び出し元）が consume/free できない。
+    // この制約は呼び出し元の verify() で検証される（Compositional Verification）。
+    for param in &atom.params {
+        if param.is_ref || param.is_ref_mut {
+            // ref/ref mut パラメータを LinearityCtx に登録（借用として）
+            linearity_ctx.register(&param.name);
+
+            // Z3 上で borrowed フラグを作成
+            let borrowed_name = format!("__borrowed_{}", param.name);
+            let borrowed_bool = Bool::new_const(ctx, vc.sym(&borrowed_name));
+            solver.assert(&borrowed_bool); // 借用中: true
+            env.insert(borrowed_name, borrowed_bool.into());
+
+            // ref/ref mut パラメータは consume 不可であることを Z3 で表現
+            // __alive_{name} は常に true（借用中は解放不可）
+            let alive_name = format!("__alive_{}", param.name);
+            let alive_bool = Bool::new_const(ctx, vc.sym(&alive_name));
+            solver.assert(&alive_bool); // ref は常に alive
+            env.insert(alive_name, alive_bool.into());
+
+            // ref mut の場合: 排他的アクセス（exclusive）を Z3 で表現
+            if param.is_ref_mut {
+                let exclusive_name = format!("__exclusive_{}", param.name);
+                let exclusive_bool = Bool::new_const(ctx, vc.sym(&exclusive_name));
+                solver.assert(&exclusive_bool); // exclusive = true
+                env.insert(exclusive_name, exclusive_bool.into());
+            }
+        }
+    }
+
+    // 3. 前提条件 (requires)
+    // NOTE: requires は エイリアシング検証より先に assert する必要がある。
+    // requires: x != y; のような制約がエイリアシング検証で活用されるため。
+    if atom.requires.trim() != "true" {
+        let req_ast = parse_expression(&atom.requires)?;
+        check_str_usage(atom, module_env, "requires", &req_ast)?;
+        let req_z3 = expr_to_z3(&vc, &req_ast, &mut env, None)?;
+        if let Some(req_bool) = req_z3.as_bool() {
+            // requires が充足不能なら以降の証明はすべて空虚に成り立つので、body を見る前に失敗にする
+            if let Some(failure) = unsatisfiable_requires(&vc, solver, atom, &req_ast, &req_bool, &env)? {
+                save_visualizer_report(output_dir, AtomStatus::Failed, &atom.name, &failure);
+                return Err(MumeiError::VerificationError(failure));
+            }
+            solver.assert(&req_bool);
+        }
+    }
+    for warning in unused_requires_warnings(atom)? {
+        vc.warn(warning);
+    }
+
+    // 3a. atom レベルの decreases: 入口（パラメータの初期値）での測度を記録する。
+    // 自己再帰呼び出しではこの値との比較で停止性を証明する
+    if let Some(measure) = &atom.decreases {
+        let entry = expr_to_z3(&vc, &parse_expression(measure)?, &mut env, None)?
+            .as_int().ok_or(MumeiError::TypeError(format!("decreases measure of atom '{}' must be integer", atom.name)))?;
+        *vc.entry_measure.borrow_mut() = Some(entry);
+    }
+
+    // 3b. エイリアシング検証 (Aliasing Prevention)
+    // requires が assert された後に実行する。
+    // これにより requires: x != y; のような制約が Z3 で活用され、
+    // 「provably distinct」なパラメータはエイリアシングエラーにならない。
+    //
+    // ref mut パラメータが存在する場合、同じ型の他の ref/ref mut パラメータ
+    // とのエイリアシング（同一データへの複数参照）を禁止する。
+    //
+    // Rust の借用規則と同等:
+    // - &mut T が存在する場合、同じデータへの &T も &mut T も存在できない
+    // - &T は複数同時に存在可能
+    //
+    // Z3 制約:
+    // ∀ p1, p2 ∈ params:
+    //   p1.is_ref_mut ∧ p1.type == p2.type ∧ p1 ≠ p2
+    //   → ¬(p2.is_ref ∨ p2.is_ref_mut)  // エイリアシング禁止
+    {
+        let ref_mut_params: Vec<&crate::parser::Param> = atom.params.iter()
+            .filter(|p| p.is_ref_mut)
+            .collect();
+
+        for ref_mut_p in &ref_mut_params {
+            for other_p in &atom.params {
+                if other_p.name == ref_mut_p.name {
+                    continue; // 自分自身はスキップ
+                }
+                // 同じ型の ref または ref mut パラメータがある場合、エイリアシングの可能性
+                if (other_p.is_ref || other_p.is_ref_mut)
+                    && other_p.type_name == ref_mut_p.type_name
+                {
+                    // Z3 で同一データへの参照でないことを検証
+                    // パラメータが異なる値を持つことを確認
+                    // （同じ値を持つ場合、エイリアシングが発生）
+                    if let (Some(ref_mut_val), Some(other_val)) = (env.get(&ref_mut_p.name), env.get(&other_p.name)) {
+                        if let (Some(rm_int), Some(ot_int)) = (ref_mut_val.as_int(), other_val.as_int()) {
+                            // ref_mut_val == other_val が SAT ならエイリアシングの可能性あり
+                            solver.push();
+                            solver.assert(&rm_int._eq(&ot_int));
+                            if vc.check(solver) == SatResult::Sat {
+                                solver.pop(1);
+                                let other_kind = if other_p.is_ref_mut { "ref mut" } else { "ref" };
+                                return Err(MumeiError::failure(FailureKind::Linearity,
+                                    format!(
+                                        "Aliasing violation in atom '{}': \
+                                         'ref mut {}' and '{} {}' may reference the same data (type: {}). \
+                                         A mutable reference requires exclusive access — \
+                                         no other references to the same data are allowed.\n  \
+                                         Hint: Use different types, or ensure the values are provably distinct \
+                                         via requires.",
+                                        atom.name, ref_mut_p.name, other_kind, other_p.name,
+                                        ref_mut_p.type_name.as_deref().unwrap_or("unknown")
+                                    )
+                                ));
+                            }
+                            solver.pop(1);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // 4. ボディの検証
+    // トップレベル Block の let は ensures から参照できるよう、body のスコープとして
+    // ここで逐次評価する（Block 式自体のスコープ規則に依存しない）。
+    let body_ast = parse_expression(&atom.body_expr)?;
+    check_str_usage(atom, module_env, "body", &body_ast)?;
+    let bindings = observable_bindings(atom, &body_ast);
+    for warning in bindings.warnings.iter().chain(shadowing_warnings(atom, &body_ast).iter()) {
+        vc.warn(warning.clone());
+    }
+    for warning in ref_argument_warnings(atom, &body_ast, module_env)? {
+        vc.warn(warning);
+    }
+    for warning in resource_escape_warnings(atom, &body_ast, module_env)? {
+        vc.warn(warning);
+    }
+    let pre_body_env = env.clone();
+    let body_result = match &body_ast {
+        Expr::Block(stmts) => {
+            let mut last: Dynamic = Int::from_i64(ctx, 0).into();
+            for stmt in stmts {
+                last = expr_to_z3(&vc, stmt, &mut env, Some(solver))?;
+            }
+            last
+        }
+        _ => expr_to_z3(&vc, &body_ast, &mut env, Some(solver))?,
+    };
+
+    // 4b. Taint Analysis: unverified 関数の呼び出しを検出し警告
+    if let Some(warning) = check_taint_propagation(atom, &env, module_env) {
+        vc.warn(warning);
+    }
+
+    // 4c. 構造体を返す atom（`-> Point`）: 本体はその構造体の値でなければならない。
+    // フィールド制約は構造体リテラル・呼び出し結果・パラメータのそれぞれで検証または保証済み。
+    // 構造体の invariant は返す値について改めて証明する
+    let result_fields = match crate::ast::struct_return(atom, module_env) {
+        Some(sdef) => match vc.struct_value(&body_result) {
+            Some(value) if value.type_name == sdef.name => {
+                if let Err(e) = prove_struct_invariant(&vc, solver, sdef, &value.fields, &format!("by the value atom '{}' returns", atom.name)) {
+                    save_visualizer_report(output_dir, AtomStatus::Failed, &atom.name, "Struct invariant violated.");
+                    return Err(e);
+                }
+                value.fields
+            }
+            found => {
+                return Err(MumeiError::TypeError(format!(
+                    "atom '{}' declares return type {} but its body evaluates to {}",
+                    atom.name,
+                    sdef.name,
+                    found.map_or("a non-struct value".to_string(), |value| format!("a {} value", value.type_name))
+                )));
+            }
+        },
+        None => Vec::new(),
+    };
+
+    // 5. 事後条件 (ensures)
+    if atom.ensures.trim() != "true" {
+        // Str を返す atom の len(result) は本体が返す文字列の長さ（body 末尾の let 束縛も見えるうちに求める）
+        let result_length = string_length(ctx, &body_ast, &env);
+        let mut env = ensures_env(&pre_body_env, &env, &bindings);
+        // old(x) は body の評価前（入口）のパラメータの値を指す
+        bind_old_values(ctx, &mut env, atom.params.iter().filter_map(|p| pre_body_env.get(&p.name).map(|v| (p.name.as_str(), v.clone()))));
+        // 消費済みパラメータは body での代入や alive フラグの更新に関わらず入口のシンボルを使う
+        for param_name in atom.consumed_params.iter().filter(|p| !bindings.names.contains(p)) {
+            match pre_body_env.get(param_name) {
+                Some(entry) => { env.insert(param_name.clone(), entry.clone()); }
+                None => { env.remove(param_name); }
+            }
+        }
+        let ens_ast = parse_expression(&atom.ensures)?;
+        check_result_usage(atom, module_env, &ens_ast)?;
+        check_str_usage(atom, module_env, "ensures", &ens_ast)?;
+        env.insert("result".to_string(), body_result);
+        // result.x / result_x は返す構造体のフィールド
+        for (field_name, val) in &result_fields {
+            env.insert(format!("result_{}", field_name), val.clone());
+            env.insert(format!("__struct_result_{}", field_name), val.clone());
+        }
+        if let Some(length) = result_length {
+            env.insert("len_result".to_string(), length.into());
+        }
+        // && で結ばれた連言ごとに義務を立て、ラベルと失敗報告でどの連言かを示す
+        let mut conjuncts = Vec::new();
+        contract_conjuncts(&ens_ast, &mut conjuncts);
+        for (i, conjunct) in conjuncts.iter().enumerate() {
+            let ens_bool = match expr_to_z3(&vc, conjunct, &mut env, None)?.as_bool() {
+                Some(ens_bool) => ens_bool,
+                None => continue,
+            };
+            if !discharge(&vc, solver, ObligationKind::Ensures, &ens_bool, || {
+                if conjuncts.len() > 1 {
+                    format!("ensures conjunct {}: {}", i + 1, expr_label(conjunct))
+                } else {
+                    format!("ensures {}", expr_label(conjunct))
+                }
+            }) {
+                save_visualizer_report(output_dir, AtomStatus::Failed, &atom.name, "Postcondition violated.");
+                // 反例: パラメータ・result・ensures が参照する let 束縛を model で評価する
+                let model = violation_model(&vc, solver, &ens_bool);
+                // 反例が通る match アーム（アームを削って二分探索しなくて済むように）
+                let exercised: Vec<String> = model.as_ref().map(|model| vc.exercised_arms(model)).unwrap_or_default();
+                let counterexample = model.map(|model| {
+                    // env 未登録のパラメータは expr_to_z3 と同じ名前のシンボルで評価する
+                    let mut symbols: Vec<(String, String, Dynamic)> = atom.params.iter()
+                        .map(|p| {
+                            let declared = p.type_name.clone().unwrap_or_else(|| "i64".to_string());
+                            let sym = env.get(&p.name).cloned().unwrap_or_else(|| {
+                                match module_env.resolve_base_type(&declared).as_str() {
+                                    "f64" => Float::new_const(ctx, vc.sym(&p.name), 11, 53).into(),
+                                    "bool" => Bool::new_const(ctx, vc.sym(&p.name)).into(),
+                                    _ => Int::new_const(ctx, vc.sym(&p.name)).into(),
+                                }
+                            });
+                            (p.name.clone(), declared, sym)
+                        })
+                        .collect();
+                    if let Some(result_sym) = env.get("result") {
+                        symbols.push(("result".to_string(), symbol_type_name(result_sym).to_string(), result_sym.clone()));
+                    }
+                    // パラメータを隠す let はパラメータの行が body 後の値を示すので重複させない
+                    for name in bindings.names.iter().filter(|n| !atom.params.iter().any(|p| &p.name == *n)) {
+                        if let Some(sym) = env.get(name).filter(|_| mentions_identifier(&atom.ensures, name)) {
+                            symbols.push((name.clone(), symbol_type_name(sym).to_string(), sym.clone()));
+                        }
+                    }
+                    model_counterexample(&model, &symbols, module_env)
+                }).unwrap_or_default();
+                let mut failure = format!(
+                    "Postcondition (ensures) is not satisfied.\n  Ensures: {}",
+                    crate::parser::indent_contract(&atom.ensures_raw, "           ")
+                );
+                if conjuncts.len() > 1 {
+                    failure.push_str(&format!("\n  Failing conjunct: {}", expr_label(conjunct)));
+                }
+                if !exercised.is_empty() {
+                    failure.push_str(&format!("\n  Counter-example path: {}", exercised.join(", ")));
+                }
+                return Err(MumeiError::failure(FailureKind::Postcondition, counterexample.render(
+                    &failure,
+                    module_env.counterexample_format,
+                )));
+            }
+        }
+        env.remove("result");
+    }
+
+    // 5b. 線形性チェック: consume 対象パラメータの検証
+    // body 実行後、consume 宣言されたパラメータが正しく消費されていることを確認。
+    // LinearityCtx に蓄積された違反（二重解放・Use-After-Free）があればエラー。
+    if !atom.consumed_params.is_empty() {
+        // consume 対象パラメータを消費済みとしてマーク
+        for param_name in &atom.consumed_params {
+            if let Err(e) = linearity_ctx.consume(param_name) {
+                return Err(MumeiError::failure(FailureKind::Linearity,
+                    format!("Linearity violation in atom '{}': {}", atom.name, e)
+                ));
+            }
+
+            // Z3 上で is_alive を false に更新（消費後のアクセスを禁止）
+            let alive_name = format!("__alive_{}", param_name);
+            let alive_false = Bool::from_bool(ctx, false);
+            env.insert(alive_name, alive_false.into());
+        }
+
+        // 蓄積された違反をチェック
+        if linearity_ctx.has_violations() {
+            let violations = linearity_ctx.get_violations().join("\n  ");
+            return Err(MumeiError::failure(FailureKind::Linearity,
+                format!("Linearity violations in atom '{}':\n  {}", atom.name, violations)
+            ));
+        }
+    }
+
+    // total atom: Unknown で終わった義務が 1 つでもあれば totality は証明されていない
+    let undecided = vc.undecided.borrow();
+    if !undecided.is_empty() {
+        let message = format!(
+            "Totality not proven for atom '{}': {} obligation(s) were not decided by the solver \
+             (unknown is a failure for total atoms):\n  - {}",
+            atom.name, undecided.len(), undecided.join("\n  - ")
+        );
+        save_visualizer_report(output_dir, AtomStatus::Failed, &atom.name, &message);
+        return Err(MumeiError::failure(FailureKind::Totality, message));
+    }
+    drop(undecided);
+
+    if vc.check(solver) == SatResult::Unsat {
+        save_visualizer_report(output_dir, AtomStatus::Failed, &atom.name, "Logic contradiction.");
+        return Err(MumeiError::VerificationError("Contradiction found.".into()));
+    }
+
+    save_visualizer_report(output_dir, AtomStatus::Verified, &atom.name, "Verified safe.");
+    Ok(())
+}
+
+// =============================================================================
+// requires の健全性チェック
+// =============================================================================
+//
+// - 充足不能な requires（`a >= 0 && a < 0`）のもとでは ensures も含めすべての義務が
+//   空虚に証明されてしまう。パラメータの型の制約と合わせて解き、Unsat なら失敗にする。
+//   どの連言が矛盾しているかは、連言ごとの追跡リテラルを仮定して得た unsat core を
+//   1 つずつ削って極小化して示す。
+// - body・ensures に一度も現れない変数だけを参照する連言は、パラメータ名の打ち間違いで
+//   新しい（制約されない）変数を作っていることが多いので警告する（失敗にはしない）。
+// どちらも `mumei check` から body の検証なしで実行できる（check_requires）。
+
+/// `mumei check` での requires の検査の Z3 タイムアウト（ミリ秒）
+pub const REQUIRES_CHECK_TIMEOUT_MS: u64 = 1000;
+
+/// requires（req_bool）がパラメータの制約のもとで充足不能なら、極小の unsat core を添えた失敗メッセージを返す。
+/// Unknown（タイムアウト）は充足可能として扱う
+fn unsatisfiable_requires<'a>(vc: &VCtx<'a>, solver: &Solver<'a>, atom: &Atom, req_ast: &Expr, req_bool: &Bool<'a>, env: &Env<'a>) -> MumeiResult<Option<String>> {
+    solver.push();
+    solver.assert(req_bool);
+    let result = vc.check(solver);
+    solver.pop(1);
+    if result != SatResult::Unsat {
+        return Ok(None);
+    }
+
+    let mut conjuncts = Vec::new();
+    contract_conjuncts(req_ast, &mut conjuncts);
+    solver.push();
+    let mut literals = Vec::new();
+    let mut shown = Vec::new();
+    for (i, conjunct) in conjuncts.iter().enumerate() {
+        let condition = expr_to_z3(vc, conjunct, &mut env.clone(), None)?.as_bool();
+        if let Some(condition) = condition {
+            let literal = Bool::new_const(vc.ctx, vc.sym(&format!("__requires_{}", i)));
+            solver.assert(&literal.implies(&condition));
+            literals.push(literal);
+            shown.push(crate::printer::show(conjunct).unwrap_or_else(|| atom.requires_raw.clone()));
+        }
+    }
+    let unsat = |subset: &[usize]| {
+        let assumptions: Vec<Bool<'a>> = subset.iter().map(|&i| literals[i].clone()).collect();
+        vc.check_assuming(solver, &assumptions) == SatResult::Unsat
+    };
+    // Z3 の unsat core は極小とは限らないので、外しても Unsat のままの連言を 1 つずつ取り除く
+    let all: Vec<usize> = (0..literals.len()).collect();
+    let mut core = if unsat(&all) {
+        solver.get_unsat_core().iter().filter_map(|c| literals.iter().position(|l| l == c)).collect()
+    } else {
+        all.clone()
+    };
+    if !unsat(&core) {
+        core = all;
+    }
+    for i in core.clone() {
+        let without: Vec<usize> = core.iter().copied().filter(|&j| j != i).collect();
+        if unsat(&without) {
+            core = without;
+        }
+    }
+    solver.pop(1);
+
+    let mut failure = format!(
+        "requires is unsatisfiable — all proofs would be vacuous in atom '{}'.\n  Requires: {}",
+        atom.name, crate::parser::indent_contract(&atom.requires_raw, "            ")
+    );
+    if core.is_empty() {
+        failure.push_str("\n  The parameter types and forall / exists constraints are already contradictory on their own.");
+    } else {
+        let core: Vec<String> = core.iter().map(|&i| format!("`{}`", shown[i])).collect();
+        failure.push_str(&format!("\n  Unsat core: {}", core.join(", ")));
+    }
+    Ok(Some(failure))
+}
+
+/// requires の連言のうち、body・ensures（と decreases・invariant）に現れない変数だけを参照するものへの警告
+pub fn unused_requires_warnings(atom: &Atom) -> MumeiResult<Vec<String>> {
+    if atom.requires.trim() == "true" {
+        return Ok(Vec::new());
+    }
+    let req_ast = parse_expression(&atom.requires)?;
+    let mut conjuncts = Vec::new();
+    contract_conjuncts(&req_ast, &mut conjuncts);
+    let sources: Vec<&String> = [Some(&atom.body_expr), Some(&atom.ensures), atom.decreases.as_ref(), atom.invariant.as_ref()]
+        .into_iter().flatten().collect();
+
+    let mut warnings = Vec::new();
+    for conjunct in conjuncts {
+        let mut names = Vec::new();
+        contract_variables(conjunct, &mut names);
+        if names.is_empty() || names.iter().any(|name| sources.iter().any(|source| mentions_identifier(source, name))) {
+            continue;
+        }
+        let quoted = |names: &[&String]| names.iter().map(|n| format!("'{}'", n)).collect::<Vec<_>>().join(", ");
+        let mut warning = format!(
+            "requires conjunct `{}` only mentions {}, which never appear in the body or ensures",
+            crate::printer::show(conjunct).unwrap_or_else(|| atom.requires_raw.clone()),
+            quoted(&names.iter().collect::<Vec<_>>())
+        );
+        let unknown: Vec<&String> = names.iter().filter(|name| !atom.params.iter().any(|p| &p.name == *name)).collect();
+        if !unknown.is_empty() {
+            warning.push_str(&format!("; {} is not a parameter (misspelled parameter name?)", quoted(&unknown)));
+        }
+        warnings.push(warning);
+    }
+    Ok(warnings)
+}
+
+/// 契約の式が参照する変数（出現順・重複なし）。forall / exists の束縛変数は除く
+fn contract_variables(expr: &Expr, out: &mut Vec<String>) {
+    match expr {
+        Expr::Variable(name) | Expr::ArrayAccess(name, _) => {
+            if !out.contains(name) {
+                out.push(name.clone());
+            }
+            if let Expr::ArrayAccess(_, index) = expr {
+                contract_variables(index, out);
+            }
+        }
+        Expr::Call(name, args) if (name == "forall" || name == "exists") && args.len() == 4 => {
+            let mut inner = Vec::new();
+            for arg in &args[1..] {
+                contract_variables(arg, &mut inner);
+            }
+            if let Expr::Variable(bound) = &args[0] {
+                inner.retain(|name| name != bound);
+            }
+            for name in inner {
+                if !out.contains(&name) {
+                    out.push(name);
+                }
+            }
+        }
+        Expr::Call(_, args) => args.iter().for_each(|arg| contract_variables(arg, out)),
+        Expr::BinaryOp(l, _, r) => {
+            contract_variables(l, out);
+            contract_variables(r, out);
+        }
+        Expr::IfThenElse { cond, then_branch, else_branch } => {
+            for e in [cond, then_branch, else_branch] {
+                contract_variables(e, out);
+            }
+        }
+        Expr::FieldAccess(base, _) | Expr::RefArg { expr: base, .. } => contract_variables(base, out),
+        Expr::Block(stmts) => stmts.iter().for_each(|s| contract_variables(s, out)),
+        _ => {}
+    }
+}
+
+/// `mumei check` 用: body を検証せずに requires だけを検査する。
+/// 充足不能なら VerificationError、そうでなければ unused_requires_warnings の警告を返す
+/// （trusted atom の body はスタブであることが多いので警告しない）。
+/// Z3 は REQUIRES_CHECK_TIMEOUT_MS で打ち切り、決着しなければ充足可能として扱う
+pub fn check_requires(atom: &Atom, module_env: &ModuleEnv) -> MumeiResult<Vec<String>> {
+    match requires_contradiction(atom, module_env, REQUIRES_CHECK_TIMEOUT_MS)? {
+        Some(failure) => Err(MumeiError::VerificationError(failure)),
+        None if atom.trust_level == TrustLevel::Trusted => Ok(Vec::new()),
+        None => unused_requires_warnings(atom),
+    }
+}
+
+/// body を検証せずに、パラメータの型のもとで requires が充足不能かを調べる（unsatisfiable_requires を参照）。
+/// check_requires と trusted atom の検証で使う。Z3 が timeout_ms で決着しなければ充足可能として扱う
+fn requires_contradiction(atom: &Atom, module_env: &ModuleEnv, timeout_ms: u64) -> MumeiResult<Option<String>> {
+    if atom.requires.trim() == "true" {
+        return Ok(None);
+    }
+    let guard = SolverGuard::new(timeout_ms, module_env.solver_limits);
+    with_pooled_solver(&guard, |ctx, solver| -> MumeiResult<Option<String>> {
+        let vc = VCtx { ctx, arrays: RefCell::default(), params: &atom.params, module_env, obligations: None, guard: Some(&guard), scope: &atom.name, counters: RefCell::default(), total: false, undecided: RefCell::default(), arm_stack: RefCell::default(), arm_paths: RefCell::default(), entry_measure: RefCell::default(), futures: RefCell::default(), call_lengths: RefCell::default(), struct_values: RefCell::default(), float_defs: RefCell::default(), callees: RefCell::default(), warnings: None };
+        let mut env: Env = HashMap::new();
+        assume_params(&vc, solver, atom, &mut env)?;
+        let req_ast = parse_expression(&atom.requires)?;
+        match expr_to_z3(&vc, &req_ast, &mut env, None)?.as_bool() {
+            Some(req_bool) => unsatisfiable_requires(&vc, solver, atom, &req_ast, &req_bool, &env),
+            None => Ok(None),
+        }
+    })
+}
+
+// =============================================================================
+// Z3 バージョン診断
+// =============================================================================
+//
+// 検証は z3 crate 経由でリンクされた libz3 で行われるため、PATH 上の `z3` コマンドの
+// バージョンとは一致しないことがある。inspect・--version・JSON レポートでは
+// リンク済みライブラリのバージョンを報告し、CLI との major/minor 不一致を警告する。
+
+/// 比較対象の Z3 バイナリを上書きする環境変数
+pub const Z3_BINARY_ENV: &str = "MUMEI_Z3_BINARY";
+
+/// リンク済み libz3 のバージョン文字列（例: "Z3 4.8.12.0"）
+pub fn linked_z3_version() -> String {
+    z3::full_version().to_string()
+}
+
+/// バージョン文字列から最初の `major.minor[.patch]` を取り出す。
+/// "Z3 version 4.13.0 - 64 bit" / "Z3 4.8.12.0" / "4.13.4" のいずれにも対応する。
+pub fn parse_z3_version(text: &str) -> Option<(u32, u32, u32)> {
+    text.split(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .filter(|word| word.contains('.'))
+        .find_map(|word| {
+            let mut parts = word.split('.').filter(|p| !p.is_empty()).map(|p| p.parse::<u32>());
+            let major = parts.next()?.ok()?;
+            let minor = parts.next()?.ok()?;
+            let patch = parts.next().and_then(|p| p.ok()).unwrap_or(0);
+            Some((major, minor, patch))
+        })
+}
+
+/// 比較に使う Z3 バイナリ: CLI 指定 > MUMEI_Z3_BINARY > PATH 上の `z3`
+pub fn z3_binary_path(cli_override: Option<&str>) -> String {
+    cli_override.map(|s| s.to_string())
+        .or_else(|| std::env::var(Z3_BINARY_ENV).ok().filter(|s| !s.is_empty()))
+        .unwrap_or_else(|| "z3".to_string())
+}
+
+/// `<binary> --version` の出力（見つからない・出力が空なら None）
+pub fn query_z3_binary_version(binary: &str) -> Option<String> {
+    let output = std::process::Command::new(binary).arg("--version").output().ok()?;
+    let version = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if version.is_empty() { None } else { Some(version) }
+}
+
+/// 2つのバージョン文字列の major.minor が一致するか（どちらかが解析できなければ None）
+pub fn z3_versions_compatible(linked: &str, binary: &str) -> Option<bool> {
+    let (l_major, l_minor, _) = parse_z3_version(linked)?;
+    let (b_major, b_minor, _) = parse_z3_version(binary)?;
+    Some(l_major == b_major && l_minor == b_minor)
+}
+
+/// atom 単位の検証結果を report.json（crate::report のスキーマ）として書き出す。
+/// cmd_verify / cmd_build はモジュール全体のレポートで最後に上書きする。
+fn save_visualizer_report(output_dir: &Path, status: AtomStatus, name: &str, reason: &str) {
+    let mut report = Report::new("atom", "");
+    let mut result = AtomResult::new(name, ItemKind::Atom, status);
+    result.message = Some(reason.to_string());
+    report.push(result);
+    report.finish(0);
+    let _ = report.save(output_dir);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Item;
+    use crate::test_support::{env_from_source, find_atom, TempDir};
+    use std::fs;
+
+    #[test]
+    fn test_parse_z3_version_formats() {
+        assert_eq!(parse_z3_version("Z3 version 4.13.0 - 64 bit"), Some((4, 13, 0)));
+        assert_eq!(parse_z3_version("Z3 4.8.12.0"), Some((4, 8, 12)));
+        assert_eq!(parse_z3_version("4.13.4"), Some((4, 13, 4)));
+        assert_eq!(parse_z3_version("z3 (unknown)"), None);
+        assert_eq!(z3_versions_compatible("Z3 4.13.2.0", "Z3 version 4.13.0 - 64 bit"), Some(true));
+        assert_eq!(z3_versions_compatible("Z3 4.8.12.0", "Z3 version 4.13.0 - 64 bit"), Some(false));
+        assert_eq!(z3_versions_compatible("Z3 4.8.12.0", "garbage"), None);
+        assert!(parse_z3_version(&linked_z3_version()).is_some(), "{}", linked_z3_version());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_mock_z3_binary_version_mismatch() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = TempDir::new("z3_mock");
+        let mock = dir.join("z3");
+        fs::write(&mock, "#!/bin/sh\necho 'Z3 version 99.1.0 - 64 bit'\n").unwrap();
+        fs::set_permissions(&mock, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let mock_path = mock.to_str().unwrap();
+        // CLI 指定は環境変数・PATH より優先される
+        assert_eq!(z3_binary_path(Some(mock_path)), mock_path);
+        let version = query_z3_binary_version(mock_path).expect("mock z3 version");
+        assert_eq!(version, "Z3 version 99.1.0 - 64 bit");
+        assert_eq!(z3_versions_compatible(&linked_z3_version(), &version), Some(false));
+        assert!(query_z3_binary_version(dir.join("missing-z3").to_str().unwrap()).is_none());
+    }
+
+    const OBSERVABLE_BINDINGS_SOURCE: &str = r#"
+atom midpoint(lo: i64, hi: i64)
+requires: lo >= 0 && hi >= lo;
+ensures: result == mid && mid >= lo && mid <= hi;
+body: {
+    let mid = lo + (hi - lo) / 2;
+    mid
+};
+
+atom nested_let(n: i64)
+requires: n > 5;
+ensures: n > 5 && result == 0;
+body: {
+    if n > 0 { let n = 0; n } else { 0 }
+};
+
+atom nested_not_visible(n: i64)
+requires: n > 0;
+ensures: inner == 1;
+body: {
+    if n > 0 { let inner = 1; inner } else { 1 }
+};
+
+atom shadow_param(n: i64)
+requires: n >= 0;
+ensures: result == n;
+body: {
+    let n = n + 1;
+    n
+};
+"#;
+
+    #[test]
+    fn test_ensures_observable_bindings() {
+        let (items, module_env) = env_from_source(OBSERVABLE_BINDINGS_SOURCE);
+        let atom = |name: &str| find_atom(&items, name);
+        let output_dir = TempDir::new("observable");
+
+        // トップレベルの let は ensures から名前で参照できる
+        assert!(verify(&atom("midpoint"), &output_dir, &module_env).is_ok());
+        // ネストした let はパラメータを隠さない
+        assert!(verify(&atom("nested_let"), &output_dir, &module_env).is_ok());
+        // ネストした let は ensures から見えない（未束縛の記号になる）
+        assert!(verify(&atom("nested_not_visible"), &output_dir, &module_env).is_err());
+
+        // パラメータを隠すトップレベル let: ensures は let を指し、警告が出る
+        let shadow = atom("shadow_param");
+        let warnings = verify(&shadow, &output_dir, &module_env).unwrap();
+        assert!(warnings.iter().any(|w| w.contains("shadows parameter 'n'")), "{:?}", warnings);
+        let bindings = observable_bindings(&shadow, &parse_expression(&shadow.body_expr).unwrap());
+        assert_eq!(bindings.names, vec!["n".to_string()]);
+        assert_eq!(bindings.warnings.len(), 1);
+        assert!(bindings.warnings[0].contains("shadows parameter 'n'"), "{}", bindings.warnings[0]);
+        assert!(observable_bindings(&atom("midpoint"), &parse_expression(&atom("midpoint").body_expr).unwrap())
+            .warnings.is_empty());
+    }
+
+    const WARNINGS_SOURCE: &str = r#"
+atom ping(n: i64) requires: n >= 0; ensures: true; body: if n == 0 { 0 } else { pong(n - 1) };
+atom pong(n: i64) requires: n >= 0; ensures: true; body: if n == 0 { 0 } else { ping(n - 1) };
+unverified atom sketch(x: i64) requires: true; ensures: result >= 0; body: x * x;
+"#;
+
+    #[test]
+    fn test_warnings_are_returned_not_printed() {
+        let (items, module_env) = env_from_source(WARNINGS_SOURCE);
+        let atom = |name: &str| find_atom(&items, name);
+        let output_dir = TempDir::new("warnings");
+
+        // invariant も max_unroll もない相互再帰は警告（検証は続ける）
+        let cycle = call_graph_cycle_warning(&atom("ping"), &module_env).expect("ping → pong → ping is a cycle");
+        assert!(cycle.contains("ping → pong → ping") && cycle.contains("max_unroll"), "{}", cycle);
+        let warnings = verify(&atom("ping"), &output_dir, &module_env).unwrap();
+        assert!(warnings.contains(&cycle), "{:?}", warnings);
+
+        // 警告は verify_with_cache では cache.warnings に入る
+        let mut cache = ObligationCache::default();
+        verify_with_cache(&atom("sketch"), &output_dir, &module_env, 10000, &mut cache).unwrap();
+        assert!(cache.warnings.iter().any(|w| w.contains("marked as 'unverified'")), "{:?}", cache.warnings);
+    }
+
+    const OLD_VALUES_SOURCE: &str = r#"
+atom double(x: i64)
+requires: true;
+ensures: result == old(x) * 2 && result == x;
+body: {
+    x = x * 2;
+    x
+};
+
+atom double_post(x: i64) requires: true; ensures: result == x * 2; body: { x = x * 2; x };
+atom twice(x: i64) requires: true; ensures: result == x * 2 && result == old(x) * 2; body: x * 2;
+atom quadruple(y: i64) requires: true; ensures: result == y * 4; body: double(double(y));
+atom old_let(x: i64) requires: true; ensures: result == old(y); body: { let y = x; y };
+atom old_requires(x: i64) requires: old(x) > 0; ensures: true; body: x;
+"#;
+
+    #[test]
+    fn test_ensures_old_refers_to_entry_value() {
+        let (items, module_env) = env_from_source(OLD_VALUES_SOURCE);
+        let atom = |name: &str| find_atom(&items, name);
+        let output_dir = TempDir::new("old_values");
+
+        // 代入されたパラメータ: old(x) は入口の値、x は body の終了時の値
+        assert!(verify(&atom("double"), &output_dir, &module_env).is_ok());
+        assert!(verify(&atom("double_post"), &output_dir, &module_env).is_err());
+        // 代入しないパラメータでは x と old(x) は同じ
+        assert!(verify(&atom("twice"), &output_dir, &module_env).is_ok());
+        // 呼び出し元では old(x) が実引数の値になる
+        assert!(verify(&atom("quadruple"), &output_dir, &module_env).is_ok());
+
+        let err = verify(&atom("old_let"), &output_dir, &module_env).unwrap_err();
+        assert!(matches!(err, MumeiError::TypeError(_)), "{:?}", err);
+        assert!(err.to_string().contains("old(y): 'y' is not a parameter of the atom"), "{}", err);
+        let err = verify(&atom("old_requires"), &output_dir, &module_env).unwrap_err();
+        assert!(matches!(err, MumeiError::TypeError(_)), "{:?}", err);
+        assert!(err.to_string().contains("old(x) can only be used in ensures"), "{}", err);
+    }
+
+    const REQUIRES_SANITY_SOURCE: &str = r#"
+type Nat = i64 where v >= 0;
+atom vacuous(a: i64, b: i64) requires: b > 0 && a >= 0 && a < 0; ensures: result == 42; body: a;
+atom negative(n: Nat) requires: n < 0; ensures: true; body: n;
+atom typo(count: i64) requires: count >= 0 && cuont < 10; ensures: result >= 0; body: count;
+atom caller_only(x: i64, y: i64) requires: y > 0 && forall(i, 0, y, i >= 0); ensures: result == x; body: x;
+atom fine(x: i64) requires: x > 0 && forall(i, 0, x, i < x); ensures: result > 0; body: x;
+trusted atom read_clock() ensures: result >= 0; body: { 0 - 1 };
+trusted atom stub(n: Nat) requires: n < 0; ensures: true; body: 0;
+trusted atom wrapped(fd: i64) requires: fd >= 0 && unused > 0; ensures: result >= 0; body: 0;
+"#;
+
+    #[test]
+    fn test_requires_unsatisfiable_and_unused_conjuncts() {
+        let (items, module_env) = env_from_source(REQUIRES_SANITY_SOURCE);
+        let atom = |name: &str| find_atom(&items, name);
+        let output_dir = TempDir::new("requires_sanity");
+
+        // 矛盾する requires は ensures が何であれ失敗にし、極小の unsat core（b > 0 を含まない）を示す
+        let err = verify(&atom("vacuous"), &output_dir, &module_env).unwrap_err();
+        assert!(matches!(err, MumeiError::VerificationError(_)), "{:?}", err);
+        let message = err.to_string();
+        assert!(message.contains("requires is unsatisfiable — all proofs would be vacuous"), "{}", message);
+        assert!(message.contains("Unsat core: `a >= 0`, `a < 0`"), "{}", message);
+        // パラメータの精緻型との矛盾も空虚になる
+        let err = verify(&atom("negative"), &output_dir, &module_env).unwrap_err();
+        assert!(err.to_string().contains("Unsat core: `n < 0`"), "{}", err);
+        // body を検証しない `mumei check` の経路でも同じ
+        let err = check_requires(&atom("vacuous"), &module_env).unwrap_err();
+        assert!(err.to_string().contains("requires is unsatisfiable"), "{}", err);
+
+        // 警告だけで検証は続ける
+        assert!(verify(&atom("typo"), &output_dir, &module_env).is_ok());
+        let warnings = check_requires(&atom("typo"), &module_env).unwrap();
+        assert_eq!(warnings.len(), 1, "{:?}", warnings);
+        assert!(warnings[0].contains("`cuont < 10` only mentions 'cuont'") && warnings[0].contains("'cuont' is not a parameter"), "{}", warnings[0]);
+        let warnings = unused_requires_warnings(&atom("caller_only")).unwrap();
+        assert_eq!(warnings.len(), 1, "{:?}", warnings);
+        assert!(warnings[0].contains("`y > 0` only mentions 'y'") && !warnings[0].contains("not a parameter"), "{}", warnings[0]);
+        assert!(check_requires(&atom("fine"), &module_env).unwrap().is_empty());
+
+        // trusted atom は body（ensures を破るスタブでも）を検証しないが、requires の矛盾は拒否する
+        assert!(verify(&atom("read_clock"), &output_dir, &module_env).is_ok());
+        let err = verify(&atom("stub"), &output_dir, &module_env).unwrap_err();
+        assert!(err.to_string().contains("requires is unsatisfiable") && err.to_string().contains("Unsat core: `n < 0`"), "{}", err);
+        assert!(check_requires(&atom("stub"), &module_env).is_err());
+        // スタブの body に現れない変数への警告は出さない
+        assert!(verify(&atom("wrapped"), &output_dir, &module_env).is_ok());
+        assert!(check_requires(&atom("wrapped"), &module_env).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_watchdog_fires_only_after_deadline() {
+        // Z3 の探索時間に依らないよう、クエリを解かずにアラームだけを掛ける（idle な Context の interrupt は無害）
+        let ctx = Context::new(&Config::new());
+        let fired = |alarm: &Alarm| alarm.state.lock().unwrap().fired;
+
+        // 期限前に解除したアラームは interrupt しない
+        let alarm = Alarm::arm(&ctx, Duration::from_secs(60));
+        assert!(!alarm.disarm());
+
+        // 期限を過ぎたアラームは watchdog が interrupt する。2 件目も同じ watchdog スレッドが処理する
+        for _ in 0..2 {
+            let alarm = Alarm::arm(&ctx, Duration::ZERO);
+            let start = Instant::now();
+            while !fired(&alarm) && start.elapsed() < Duration::from_secs(10) {
+                std::thread::sleep(Duration::from_millis(1));
+            }
+            assert!(alarm.disarm());
+        }
+
+        let guard = SolverGuard::new(0, SolverLimits { grace_ms: 10, max_memory_mb: None });
+        assert_eq!(guard.describe_limits(), "timeout_ms=0, grace=10ms, max_memory_mb=unlimited");
+    }
+
+    const POOLED_SOURCE: &str = r#"
+atom strong(x: i64)
+requires: x > 10;
+ensures: result > 5;
+body: x;
+
+atom strong_twin(x: i64)
+requires: x > 10;
+ensures: result > 5;
+body: x;
+
+atom weak(x: i64)
+requires: true;
+ensures: result > 5;
+body: x;
+
+atom divides(x: i64, y: i64)
+requires: y != 0;
+ensures: true;
+body: x / y;
+"#;
+
+    #[test]
+    fn test_pooled_solver_isolates_atoms() {
+        // 同一スレッドの atom は 1 つの Context / Solver を共有する。
+        // 前の atom の前提（x > 10 / y != 0）が後の atom に漏れないこと、失敗後も base レベルへ戻ることを確認する
+        let (items, module_env) = env_from_source(POOLED_SOURCE);
+        let output_dir = TempDir::new("pooled");
+        let atom = |name: &str| find_atom(&items, name);
+        let pool_depth = || SOLVER_POOL.with(|cell| cell.borrow().as_ref().map(|pool| pool.solver.get_assertions().len()));
+
+        for _ in 0..2 {
+            assert!(verify(&atom("strong"), &output_dir, &module_env).is_ok());
+            assert_eq!(pool_depth(), Some(0));
+            let err = verify(&atom("weak"), &output_dir, &module_env).unwrap_err().to_string();
+            assert!(err.contains("Postcondition"), "{}", err);
+            assert_eq!(pool_depth(), Some(0));
+            assert!(verify(&atom("divides"), &output_dir, &module_env).is_ok());
+        }
+        // シンボルには atom 名が付くため、同じシグネチャでも別のシンボルになる
+        let mut strong_cache = ObligationCache::default();
+        let mut twin_cache = ObligationCache::default();
+        assert!(verify_with_cache(&atom("strong"), &output_dir, &module_env, 10000, &mut strong_cache).is_ok());
+        assert!(verify_with_cache(&atom("strong_twin"), &output_dir, &module_env, 10000, &mut twin_cache).is_ok());
+        assert!(!strong_cache.current.is_empty());
+        assert!(strong_cache.current.is_disjoint(&twin_cache.current));
+
+        // プールを解放すると Context ごと捨て、次の atom で作り直す
+        release_solver_pool();
+        assert_eq!(pool_depth(), None);
+        assert!(verify(&atom("divides"), &output_dir, &module_env).is_ok());
+        assert_eq!(pool_depth(), Some(0));
+    }
+
+    #[test]
+    fn test_verify_deep_left_chain() {
+        // 生成コードの 10 万項の `x + x + ... + x` を、既定のテストスレッドのスタックのまま検証する
+        // （全フェーズの走査と式の破棄が連鎖の長さぶん再帰しないこと）
+        let body = vec!["x"; 100_000].join(" + ");
+        let source = format!(
+            "atom deep(x: i64)\nrequires: x >= 0 && x <= 10;\nensures: result >= 0;\nbody: {};\n\n\
+             atom deep_weak(x: i64)\nrequires: x >= 0 && x <= 10;\nensures: result >= 1;\nbody: {};\n",
+            body, body
+        );
+        let (items, module_env) = env_from_source(&source);
+        let output_dir = TempDir::new("deep_chain");
+        let atom = |name: &str| find_atom(&items, name);
+
+        assert!(verify(&atom("deep"), &output_dir, &module_env).is_ok());
+        let err = verify(&atom("deep_weak"), &output_dir, &module_env).unwrap_err().to_string();
+        assert!(err.contains("Postcondition"), "{}", err);
+    }
+
+    // =========================================================================
+    // ソルバプールのベンチマーク（通常のテストでは実行しない）
+    //   cargo test --release bench_solver_pool_speedup -- --ignored --nocapture --test-threads=1
+    // =========================================================================
+
+    #[test]
+    #[ignore]
+    fn bench_solver_pool_speedup() {
+        // 小さな atom を繰り返し検証し、スレッドのプールを使い回す場合と atom ごとに Context を作り直す場合を比べる
+        let (items, module_env) = env_from_source(POOLED_SOURCE);
+        let atoms: Vec<&Atom> = items.iter().filter_map(|i| match i {
+            Item::Atom(a) => Some(a),
+            _ => None,
+        }).collect();
+        let output_dir = TempDir::new("pool_bench");
+        let run = |pooled: bool| {
+            let start = std::time::Instant::now();
+            for _ in 0..50 {
+                for atom in &atoms {
+                    if !pooled {
+                        release_solver_pool();
+                    }
+                    let _ = verify(atom, &output_dir, &module_env);
+                }
+            }
+            start.elapsed()
+        };
+        run(true);
+        let fresh = run(false);
+        let pooled = run(true);
+        println!("{} atoms x 50: fresh context {:?}, pooled {:?} ({:.1}x)", atoms.len(), fresh, pooled, fresh.as_secs_f64() / pooled.as_secs_f64());
+        assert!(pooled < fresh, "pooled {:?} should beat fresh {:?}", pooled, fresh);
+    }
+
+    const OBLIGATION_CACHE_SOURCE: &str = r#"
+atom scaled_div(n: i64)
+requires: n > 0;
+ensures: result >= 0;
+body: 100 / n;
+
+atom scaled_div_edited(n: i64)
+requires: n > 1;
+ensures: result >= 0;
+body: 100 / n;
+
+atom broken_div(n: i64)
+requires: n > 0;
+ensures: result > 100;
+body: 100 / n;
+"#;
+
+    #[test]
+    fn test_obligation_cache_hit_miss_and_invalidation() {
+        let (items, module_env) = env_from_source(OBLIGATION_CACHE_SOURCE);
+        let atom = |name: &str| find_atom(&items, name);
+        let output_dir = TempDir::new("obligation_cache");
+
+        // miss: 前回の結果がなければすべての義務を Z3 で解く
+        let mut cold = ObligationCache::default();
+        assert!(verify_with_cache(&atom("scaled_div"), &output_dir, &module_env, 10000, &mut cold).is_ok());
+        assert_eq!(cold.reused, 0);
+        assert!(cold.solved > 0);
+        assert!(!cold.current.is_empty());
+
+        // hit: 前回の義務キーがそろっていれば Z3 を呼ばずに再利用し、current へ引き継ぐ
+        let mut warm = ObligationCache::from_previous(cold.current.clone());
+        assert!(verify_with_cache(&atom("scaled_div"), &output_dir, &module_env, 10000, &mut warm).is_ok());
+        assert_eq!(warm.solved, 0);
+        assert_eq!(warm.reused, cold.current.len());
+        assert_eq!(warm.current, cold.current);
+        assert!(warm.timings.is_empty());
+
+        // 無関係なキーは再利用されない
+        let mut unrelated = ObligationCache::from_previous(["ensures:0000".to_string()].into_iter().collect());
+        assert!(verify_with_cache(&atom("scaled_div"), &output_dir, &module_env, 10000, &mut unrelated).is_ok());
+        assert_eq!(unrelated.reused, 0);
+        assert!(!unrelated.current.contains("ensures:0000"));
+
+        // invalidation: requires が変われば前提が変わるので、前回のキーは一致せず解き直す
+        let mut edited = ObligationCache::from_previous(cold.current.clone());
+        assert!(verify_with_cache(&atom("scaled_div_edited"), &output_dir, &module_env, 10000, &mut edited).is_ok());
+        assert_eq!(edited.reused, 0);
+        assert!(edited.solved > 0);
+        assert!(edited.current.is_disjoint(&cold.current));
+
+        // 証明できなかった義務はキャッシュせず、次回も Z3 で解き直して同じく失敗する
+        let mut failed = ObligationCache::default();
+        assert!(verify_with_cache(&atom("broken_div"), &output_dir, &module_env, 10000, &mut failed).is_err());
+        assert!(failed.current.len() < failed.solved);
+        let mut retried = ObligationCache::from_previous(failed.current.clone());
+        assert!(verify_with_cache(&atom("broken_div"), &output_dir, &module_env, 10000, &mut retried).is_err());
+        assert!(retried.solved > 0);
+        assert!(!retried.current.iter().any(|key| key.starts_with("ensures:")));
+    }
+
+    // =========================================================================
+    // 義務キャッシュのベンチマーク（通常のテストでは実行しない）
+    //   cargo test --release bench_obligation_cache_reuse -- --ignored --nocapture --test-threads=1
+    // =========================================================================
+
+    #[test]
+    #[ignore]
+    fn bench_obligation_cache_reuse() {
+        // 同じ atom を、前回の義務キーなしで検証する場合と再利用する場合を比べる
+        let (items, module_env) = env_from_source(OBLIGATION_CACHE_SOURCE);
+        let atom = items.iter().find_map(|i| match i {
+            Item::Atom(a) if a.name == "scaled_div" => Some(a.clone()),
+            _ => None,
+        }).unwrap();
+        let output_dir = TempDir::new("obligation_bench");
+        let mut seed = ObligationCache::default();
+        assert!(verify_with_cache(&atom, &output_dir, &module_env, 10000, &mut seed).is_ok());
+        let run = |previous: &HashSet<String>| {
+            let start = std::time::Instant::now();
+            for _ in 0..200 {
+                let mut cache = ObligationCache::from_previous(previous.clone());
+                let _ = verify_with_cache(&atom, &output_dir, &module_env, 10000, &mut cache);
+            }
+            start.elapsed()
+        };
+        let cold = run(&HashSet::new());
+        let warm = run(&seed.current);
+        println!("scaled_div x 200: cold {:?}, cached {:?} ({:.1}x)", cold, warm, cold.as_secs_f64() / warm.as_secs_f64());
+        assert!(warm < cold, "cached {:?} should beat cold {:?}", warm, cold);
+    }
+
+    const TOTAL_SOURCE: &str = r#"
+total atom guarded_div(x: i64, y: i64)
+requires: y != 0;
+ensures: true;
+body: x / y;
+
+total atom unguarded_div(x: i64, y: i64)
+requires: true;
+ensures: true;
+body: x / y;
+
+atom partial_loop(n: i64)
+requires: n >= 0;
+ensures: result >= 0;
+body: {
+    let i = 0;
+    while i < n
+    invariant: i >= 0
+    {
+        i = i + 1;
+    };
+    i
+};
+
+total atom total_loop(n: i64)
+requires: n >= 0;
+ensures: result >= 0;
+body: {
+    let i = 0;
+    while i < n
+    invariant: i >= 0
+    {
+        i = i + 1;
+    };
+    i
+};
+
+total atom countdown(n: i64)
+requires: n >= 0;
+ensures: true;
+body: if n == 0 { 0 } else { countdown(n - 1) };
+"#;
+
+    #[test]
+    fn test_total_atom_requires_guard_and_termination() {
+        let (items, module_env) = env_from_source(TOTAL_SOURCE);
+        let output_dir = TempDir::new("total");
+        let atom = |name: &str| find_atom(&items, name);
+
+        // requires のガードがあればゼロ除算の義務は requires だけから証明できる
+        assert!(verify(&atom("guarded_div"), &output_dir, &module_env).is_ok());
+        let err = verify(&atom("unguarded_div"), &output_dir, &module_env).unwrap_err().to_string();
+        assert!(err.contains("division by zero"), "{}", err);
+
+        // decreases のないループは total でなければ従来どおり通り、total なら停止性の欠落で失敗する
+        assert!(verify(&atom("partial_loop"), &output_dir, &module_env).is_ok());
+        let err = verify(&atom("total_loop"), &output_dir, &module_env).unwrap_err().to_string();
+        assert!(err.contains("Totality violation") && err.contains("decreases"), "{}", err);
+
+        // atom レベルの decreases がなければ停止性を示せないため、total atom の再帰は拒否する
+        let err = verify(&atom("countdown"), &output_dir, &module_env).unwrap_err().to_string();
+        assert!(err.contains("Totality violation") && err.contains("countdown"), "{}", err);
+    }
+
+    const TAILREC_SOURCE: &str = r#"
+atom sum_to(n: i64, acc: i64)
+requires: n >= 0;
+ensures: result == acc + n * (n + 1) / 2;
+decreases: n;
+body: if n == 0 { acc } else { sum_to(n - 1, acc + n) };
+
+atom sum_wrong(n: i64, acc: i64)
+requires: n >= 0;
+ensures: result == acc + n * n;
+decreases: n;
+body: if n == 0 { acc } else { sum_wrong(n - 1, acc + n) };
+
+atom stuck(n: i64, acc: i64)
+requires: n >= 0;
+ensures: result >= acc;
+decreases: n;
+body: if n == 0 { acc } else { stuck(n, acc + 1) };
+
+atom overshoot(n: i64)
+requires: true;
+ensures: result == 0;
+decreases: n;
+body: if n <= 0 { 0 } else { overshoot(n - 2) };
+
+total atom countdown_measured(n: i64)
+requires: n >= 0;
+ensures: result == 0;
+decreases: n;
+body: if n == 0 { 0 } else { countdown_measured(n - 1) };
+"#;
+
+    #[test]
+    fn test_tail_recursion_proves_measure_before_assuming_ensures() {
+        let (items, module_env) = env_from_source(TAILREC_SOURCE);
+        let output_dir = TempDir::new("tailrec");
+        let atom = |name: &str| find_atom(&items, name);
+
+        // 再帰呼び出しの requires と測度の減少を証明したうえで ensures を仮定する（整礎帰納法）
+        assert!(verify(&atom("sum_to"), &output_dir, &module_env).is_ok());
+        let err = verify(&atom("sum_wrong"), &output_dir, &module_env).unwrap_err().to_string();
+        assert!(err.contains("Postcondition"), "{}", err);
+
+        // 測度が減らない・負になりうる再帰呼び出しは ensures を仮定する前に失敗する
+        let err = verify(&atom("stuck"), &output_dir, &module_env).unwrap_err().to_string();
+        assert!(err.contains("Termination check failed") && err.contains("does not strictly decrease"), "{}", err);
+        let err = verify(&atom("overshoot"), &output_dir, &module_env).unwrap_err().to_string();
+        assert!(err.contains("Termination check failed") && err.contains("may be negative"), "{}", err);
+
+        // decreases のある直接再帰は total atom でも許可される
+        assert!(verify(&atom("countdown_measured"), &output_dir, &module_env).is_ok());
+    }
+
+    const FACTORIAL_SOURCE: &str = r#"
+type Nat = i64 where v >= 0;
+
+atom fact(n: Nat)
+requires: n >= 0;
+ensures: result >= 1 && result >= n;
+decreases: n;
+body: if n <= 1 { 1 } else { n * fact(n - 1) };
+
+atom fact_forever(n: Nat)
+requires: n >= 0;
+ensures: result >= 1;
+decreases: n;
+body: if n <= 1 { 1 } else { n * fact_forever(n + 1 - 1) };
+"#;
+
+    #[test]
+    fn test_non_tail_recursion_uses_contract_and_measure() {
+        let (items, module_env) = env_from_source(FACTORIAL_SOURCE);
+        let output_dir = TempDir::new("factorial");
+        let atom = |name: &str| find_atom(&items, name);
+
+        // 再帰呼び出しは乗算のオペランドでも、本体を展開せず自身の契約（ensures）で置き換える
+        assert!(verify(&atom("fact"), &output_dir, &module_env).is_ok());
+        // 測度が減らない再帰は、ensures を仮定すれば成り立つ場合でも停止性で失敗する
+        let err = verify(&atom("fact_forever"), &output_dir, &module_env).unwrap_err().to_string();
+        assert!(err.contains("Termination check failed") && err.contains("'fact_forever'"), "{}", err);
+    }
+
+    const STR_SOURCE: &str = r#"
+type NonEmpty = Str where len(v) > 0;
+
+atom non_empty_len(s: NonEmpty)
+requires: true;
+ensures: result > 0;
+body: len(s);
+
+atom any_len(s: Str)
+requires: true;
+ensures: result > 0;
+body: len(s);
+
+atom literal_len(n: i64)
+requires: true;
+ensures: result == 6;
+body: {
+    let greeting = "héllo";
+    len(greeting)
+};
+"#;
+
+    #[test]
+    fn test_str_refinement_on_length() {
+        let (items, module_env) = env_from_source(STR_SOURCE);
+        let output_dir = TempDir::new("str");
+        let atom = |name: &str| find_atom(&items, name);
+
+        // 精緻型の述語 len(v) > 0 がパラメータの長さの前提になる
+        assert!(verify(&atom("non_empty_len"), &output_dir, &module_env).is_ok());
+        // ただの Str は空文字列でありうる
+        let err = verify(&atom("any_len"), &output_dir, &module_env).unwrap_err().to_string();
+        assert!(err.contains("Postcondition"), "{}", err);
+        // リテラルの長さは UTF-8 のバイト数
+        assert!(verify(&atom("literal_len"), &output_dir, &module_env).is_ok());
+    }
+
+    const RESULT_KIND_SOURCE: &str = r#"
+atom is_pos_counted(x: i64)
+requires: true;
+ensures: result >= 1;
+body: x > 0;
+
+atom inc_as_flag(x: i64)
+requires: true;
+ensures: result >= x + 1 && result;
+body: x + 1;
+
+atom is_pos(x: i64)
+requires: x > 0;
+ensures: result == true;
+body: x > 0;
+
+atom inc(x: i64)
+requires: true;
+ensures: result >= x + 1;
+body: x + 1;
+
+atom use_pos(x: i64)
+requires: x > 0;
+ensures: result == true;
+body: is_pos(x);
+"#;
+
+    #[test]
+    fn test_result_usage_matches_body_sort() {
+        let (items, module_env) = env_from_source(RESULT_KIND_SOURCE);
+        let output_dir = TempDir::new("result_kind");
+        let atom = |name: &str| find_atom(&items, name);
+
+        // 真偽値の body を ensures が数値として比較する
+        let err = verify(&atom("is_pos_counted"), &output_dir, &module_env).unwrap_err().to_string();
+        assert!(err.contains("evaluates to a boolean but ensures treats result as a number"), "{}", err);
+        assert!(err.contains("Ensures conjunct: result >= 1"), "{}", err);
+        // 数値の body を ensures の連言が真偽値として使う（最初に食い違う連言を示す）
+        let err = verify(&atom("inc_as_flag"), &output_dir, &module_env).unwrap_err().to_string();
+        assert!(err.contains("evaluates to a number but ensures treats result as a boolean"), "{}", err);
+        assert!(err.contains("Ensures conjunct: result") && !err.contains("Ensures conjunct: result >="), "{}", err);
+
+        // 揃っていれば通常どおり検証する。真偽値を返す呼び出し先の result も真偽値のまま仮定される
+        assert!(verify(&atom("is_pos"), &output_dir, &module_env).is_ok());
+        assert!(verify(&atom("inc"), &output_dir, &module_env).is_ok());
+        assert!(verify(&atom("use_pos"), &output_dir, &module_env).is_ok());
+    }
+
+    const BOOL_SOURCE: &str = r#"
+type Flag = bool where v == true;
+
+atom pick(flag: bool, a: i64, b: i64)
+requires: flag == true;
+ensures: result == a;
+body: if flag { a } else { b };
+
+atom pick_flag(flag: Flag, a: i64, b: i64)
+requires: true;
+ensures: result == a;
+body: if flag { a } else { b };
+
+atom pick_any(flag: bool, a: i64, b: i64)
+requires: true;
+ensures: result == a;
+body: if flag { a } else { b };
+
+atom negate(flag: bool)
+requires: true;
+ensures: result != flag;
+body: if flag { false } else { true };
+
+atom clamp_if(n: i64, flag: bool)
+requires: n >= 0;
+ensures: result >= 0;
+body: match n { k if flag => k, _ => 0 };
+
+atom pick_first(a: i64, b: i64)
+requires: true;
+ensures: result == a;
+body: pick(true, a, b);
+
+atom pick_second(a: i64, b: i64)
+requires: true;
+ensures: true;
+body: pick(false, a, b);
+"#;
+
+    #[test]
+    fn test_bool_params_and_refinements() {
+        let (items, module_env) = env_from_source(BOOL_SOURCE);
+        let output_dir = TempDir::new("bool_params");
+        let atom = |name: &str| find_atom(&items, name);
+
+        // bool の引数は Bool シンボルなので、requires の `flag == true` も if の条件もそのまま使える
+        assert!(verify(&atom("pick"), &output_dir, &module_env).is_ok());
+        // 精緻型 `bool where v == true` の述語は引数の仮定になる
+        assert!(verify(&atom("pick_flag"), &output_dir, &module_env).is_ok());
+        assert!(verify(&atom("negate"), &output_dir, &module_env).is_ok());
+        assert!(verify(&atom("clamp_if"), &output_dir, &module_env).is_ok());
+        assert!(verify(&atom("pick_first"), &output_dir, &module_env).is_ok());
+
+        // 仮定がなければ flag は false もとりうる
+        let err = verify(&atom("pick_any"), &output_dir, &module_env).unwrap_err().to_string();
+        assert!(err.contains("Postcondition"), "{}", err);
+        // 呼び出し側の false は requires を満たさない
+        assert!(verify(&atom("pick_second"), &output_dir, &module_env).is_err());
+    }
+
+    const SHADOWING_SOURCE: &str = r#"
+atom shadow_match(n: i64)
+requires: n >= 0;
+ensures: result == n + 1;
+body: match n + 1 { n if n > 0 => n };
+
+atom shadow_lets(x: i64)
+requires: true;
+ensures: true;
+body: {
+    let y = x;
+    let y = y + 1;
+    if y > 0 { let x = 0; x } else { y }
+};
+"#;
+
+    #[test]
+    fn test_match_arm_shadowing_keeps_parameter() {
+        use crate::interpreter::{Interpreter, Value};
+
+        let (items, module_env) = env_from_source(SHADOWING_SOURCE);
+        let atom = |name: &str| find_atom(&items, name);
+        let output_dir = TempDir::new("shadowing");
+
+        // ガード `n > 0` はアームの n（= パラメータ n + 1）を指すので網羅的で、
+        // ensures の n はアームの外のパラメータ n のまま
+        let shadow_match = atom("shadow_match");
+        assert!(verify(&shadow_match, &output_dir, &module_env).is_ok());
+        assert_eq!(
+            shadowing_warnings(&shadow_match, &parse_expression(&shadow_match.body_expr).unwrap()),
+            vec!["pattern variable 'n' shadows parameter 'n'".to_string()]
+        );
+
+        // 参照インタプリタ（生成コードと同じスコープ規則）でも同じ結果になる
+        let mut interp = Interpreter::new(&module_env);
+        assert_eq!(interp.call_atom(&shadow_match, &[Value::Int(4)]), Ok(Value::Int(5)));
+        let bindings: HashMap<String, Value> = [("n".to_string(), Value::Int(4)), ("result".to_string(), Value::Int(5))]
+            .into_iter().collect();
+        assert_eq!(interp.eval_contract(&shadow_match.ensures, &bindings), Ok(true));
+
+        let shadow_lets = atom("shadow_lets");
+        assert_eq!(
+            shadowing_warnings(&shadow_lets, &parse_expression(&shadow_lets.body_expr).unwrap()),
+            vec!["let 'y' shadows let 'y'".to_string(), "let 'x' shadows parameter 'x'".to_string()]
+        );
+    }
+
+    const ASSUME_SOURCE: &str = r#"
+atom read_reg(raw: i64)
+requires: raw >= 0;
+ensures: result <= 255;
+body: {
+    assume(raw <= 255, "the status register is 8 bits wide (datasheet 4.2)");
+    raw
+};
+
+atom read_reg_unassumed(raw: i64)
+requires: raw >= 0;
+ensures: result <= 255;
+body: raw;
+
+atom read_reg_unjustified(raw: i64)
+requires: raw >= 0;
+ensures: result <= 255;
+body: {
+    assume(raw <= 255);
+    raw
+};
+
+total atom read_reg_total(raw: i64)
+requires: raw >= 0;
+ensures: result <= 255;
+body: {
+    assume(raw <= 255, "the status register is 8 bits wide (datasheet 4.2)");
+    raw
+};
+"#;
+
+    #[test]
+    fn test_assume_closes_proof_and_is_gated() {
+        let (items, mut module_env) = env_from_source(ASSUME_SOURCE);
+        let atom = |name: &str| find_atom(&items, name);
+        let output_dir = TempDir::new("assume");
+
+        // assume がなければ事後条件は証明できず、assume があれば閉じる
+        let err = verify(&atom("read_reg_unassumed"), &output_dir, &module_env).unwrap_err().to_string();
+        assert!(err.contains("Postcondition"), "{}", err);
+        assert!(verify(&atom("read_reg"), &output_dir, &module_env).is_ok());
+
+        // 正当化文のない assume はパースエラー
+        let err = verify(&atom("read_reg_unjustified"), &output_dir, &module_env).unwrap_err().to_string();
+        assert!(err.contains("assume(raw <= 255) requires a justification string"), "{}", err);
+
+        // total atom は assume を含められない
+        let err = verify(&atom("read_reg_total"), &output_dir, &module_env).unwrap_err().to_string();
+        assert!(err.contains("Totality violation") && err.contains("assume"), "{}", err);
+
+        // [proof] deny_assume = true ではすべての assume を拒否する
+        module_env.deny_assume = true;
+        let err = verify(&atom("read_reg"), &output_dir, &module_env).unwrap_err().to_string();
+        assert!(err.contains("deny_assume") && err.contains("datasheet 4.2"), "{}", err);
+    }
+
+    /// 量化子の束縛変数 i とパラメータ i が同名の atom
+    const QUANTIFIER_SHADOW_SOURCE: &str = r#"
+atom shadow_requires(i: i64)
+requires: i > 0 && exists(i, 0, i, i >= 0);
+ensures: result < 0;
+body: i;
+
+atom shadow_ensures(i: i64)
+requires: i > 0;
+ensures: exists(i, 0, i, i >= 0);
+body: i;
+
+atom shadow_forall(arr: i64, i: i64, n: i64)
+requires: len_arr == n && i >= 0 && i < n && forall(i, 0, n, arr[i] >= 0);
+ensures: result >= 0;
+body: arr[i];
+"#;
+
+    #[test]
+    fn test_quantifier_variable_does_not_capture_parameter() {
+        let (items, module_env) = env_from_source(QUANTIFIER_SHADOW_SOURCE);
+        let atom = |name: &str| find_atom(&items, name);
+        let output_dir = TempDir::new("quantifier_shadow");
+
+        // 以前は範囲の上端 i（パラメータ）が束縛変数に捕獲され、∃i. 0 <= i < i が偽になって
+        // requires 全体が矛盾し、偽の事後条件 result < 0 が証明されていた
+        let err = verify(&atom("shadow_requires"), &output_dir, &module_env).unwrap_err().to_string();
+        assert!(err.contains("Postcondition"), "{}", err);
+
+        // ensures 内の量化子も同様: 範囲 [0, i) はパラメータ i を指し、i > 0 なら 0 が証人になる
+        assert!(verify(&atom("shadow_ensures"), &output_dir, &module_env).is_ok());
+
+        // 量化子の内側の i は束縛変数、外側の i はパラメータ。forall をパラメータ i で具体化できる
+        assert!(verify(&atom("shadow_forall"), &output_dir, &module_env).is_ok());
+    }
+
+    const ENSURES_COUNTEREXAMPLE_SOURCE: &str = r#"
+atom off_by_one(x: i64)
+requires: x >= 0;
+ensures: result == doubled;
+body: {
+    let doubled = x + x;
+    let unused = x * 3;
+    doubled + 1
+};
+
+atom shrink(a: f64)
+requires: a >= 1.0 && a <= 8.0;
+ensures: result > a;
+body: a / 2.0;
+
+atom clamp_low(x: i64)
+requires: x >= 0;
+ensures: result >= 0 && result <= x && result < 10;
+body: x;
+"#;
+
+    #[test]
+    fn test_ensures_counterexample_shows_parameters_result_and_lets() {
+        let (items, module_env) = env_from_source(ENSURES_COUNTEREXAMPLE_SOURCE);
+        let atom = |name: &str| find_atom(&items, name);
+        let output_dir = TempDir::new("ensures_ce");
+
+        // ensures が参照する let 束縛だけを、パラメータと result に続けて表示する
+        let err = verify(&atom("off_by_one"), &output_dir, &module_env).unwrap_err().to_string();
+        assert!(err.contains("Counter-example: x: i64 = "), "{}", err);
+        assert!(err.contains(", result: i64 = ") && err.contains(", doubled: i64 = "), "{}", err);
+        assert!(!err.contains("unused"), "{}", err);
+
+        // f64 の値は Z3 の FP リテラルではなく 10 進で表示する
+        let err = verify(&atom("shrink"), &output_dir, &module_env).unwrap_err().to_string();
+        assert!(err.contains("a: f64 = ") && err.contains("result: f64 = "), "{}", err);
+        assert!(!err.contains("(fp ") && !err.contains("#b"), "{}", err);
+
+        // 連言ごとに義務を立て、成り立たない連言を示す
+        let err = verify(&atom("clamp_low"), &output_dir, &module_env).unwrap_err().to_string();
+        assert!(err.contains("Failing conjunct: result < 10"), "{}", err);
+        assert!(err.contains("x: i64 = "), "{}", err);
+    }
+}
@@ -0,0 +1,369 @@
+// =============================================================================
+// パターンマッチング: Z3 条件生成 + 変数バインド + 反例フォーマット
+// =============================================================================
+
+use super::*;
+
+/// パターンから Z3 の Bool 条件を生成する（再帰的: ネストパターン対応）
+///
+/// Phase 1-B: tag + payload 表現
+/// - Wildcard / Variable → true（常にマッチ）
+/// - Literal(n) → target == n
+/// - Variant { name, fields } → (tag == variant_index) ∧ (各フィールドの再帰条件)
+///
+/// フィールドは "projector" シンボル `__proj_{VariantName}_{i}` として表現。
+/// 同一 match 内で同じ projector 名を使うことで、異なるアーム間で
+/// 同じフィールドへの参照が一貫する。
+pub(super) fn pattern_to_z3_condition<'a>(
+    ctx: &'a Context,
+    pattern: &Pattern,
+    target: &Dynamic<'a>,
+    env: &mut Env<'a>,
+    vc: &VCtx<'a>,
+    solver_opt: Option<&Solver<'a>>,
+) -> MumeiResult<Bool<'a>> {
+    match pattern {
+        Pattern::Wildcard | Pattern::Variable(_) => {
+            Ok(Bool::from_bool(ctx, true))
+        },
+        Pattern::Literal(n) => {
+            let target_int = target.as_int().unwrap_or(Int::new_const(ctx, vc.sym("__match_target")));
+            let lit = Int::from_i64(ctx, *n);
+            Ok(target_int._eq(&lit))
+        },
+        Pattern::Variant { variant_name, fields } => {
+            if let Some(enum_def) = vc.module_env.find_enum_by_variant(variant_name) {
+                let variant_idx = enum_def.variants.iter()
+                    .position(|v| v.name == *variant_name)
+                    .unwrap_or(0) as i64;
+
+                let tag = target.as_int().unwrap_or(Int::new_const(ctx, vc.sym("__match_tag")));
+                let tag_match = tag._eq(&Int::from_i64(ctx, variant_idx));
+
+                let variant_def = &enum_def.variants[variant_idx as usize];
+                let mut field_conditions: Vec<Bool> = vec![tag_match];
+
+                for (i, field_pattern) in fields.iter().enumerate() {
+                    // Projector シンボル: __proj_{VariantName}_{i}
+                    // 同一バリアントの同一フィールドは常に同じシンボルを共有
+                    let proj_name = format!("__proj_{}_{}", variant_name, i);
+                    let field_sym: Dynamic = if i < variant_def.fields.len() {
+                        let field_type = &variant_def.fields[i];
+                        // 再帰的 ADT: フィールド型が自身の Enum なら tag として Int を使用
+                        let base = if *field_type == enum_def.name {
+                            "i64".to_string() // 再帰フィールドは tag 値
+                        } else {
+                            vc.module_env.resolve_base_type(field_type)
+                        };
+                        match base.as_str() {
+                            "f64" => Float::new_const(ctx, vc.sym(&proj_name), 11, 53).into(),
+                            _ => Int::new_const(ctx, vc.sym(&proj_name)).into(),
+                        }
+                    } else {
+                        Int::new_const(ctx, vc.sym(&proj_name)).into()
+                    };
+
+                    // env にも projector を登録（body 内で参照可能にする）
+                    env.insert(proj_name.clone(), field_sym.clone());
+
+                    // 再帰フィールドの場合: ドメイン制約を追加
+                    if i < variant_def.fields.len() && variant_def.fields[i] == enum_def.name {
+                        if let Some(solver) = solver_opt {
+                            if let Some(field_int) = field_sym.as_int() {
+                                let n = enum_def.variants.len() as i64;
+                                solver.assert(&field_int.ge(&Int::from_i64(ctx, 0)));
+                                solver.assert(&field_int.lt(&Int::from_i64(ctx, n)));
+                            }
+                        }
+                    }
+
+                    // 再帰的にフィールドパターンの条件を生成
+                    let field_cond = pattern_to_z3_condition(ctx, field_pattern, &field_sym, env, vc, solver_opt)?;
+                    field_conditions.push(field_cond);
+                }
+
+                let cond_refs: Vec<&Bool> = field_conditions.iter().collect();
+                Ok(Bool::and(ctx, &cond_refs))
+            } else {
+                let tag = target.as_int().unwrap_or(Int::new_const(ctx, vc.sym("__match_tag")));
+                let hash = variant_name.bytes().fold(0i64, |acc, b| acc.wrapping_mul(31).wrapping_add(b as i64));
+                Ok(tag._eq(&Int::from_i64(ctx, hash)))
+            }
+        },
+    }
+}
+
+/// パターンから変数バインドを env に登録する（再帰的: ネストパターン対応）
+///
+/// Phase 1-B: projector シンボルを使ったバインド
+/// - Variable(name) → target の値を name にバインド
+/// - Variant の fields 内の Variable → projector シンボル `__proj_{Variant}_{i}` にバインド
+/// - Variant の fields 内の Variant → 再帰的に projector を生成してバインド
+pub(super) fn pattern_bind_variables<'a>(
+    vc: &VCtx<'a>,
+    pattern: &Pattern,
+    target: &Dynamic<'a>,
+    env: &mut Env<'a>,
+) {
+    let ctx = vc.ctx;
+    let module_env = vc.module_env;
+    match pattern {
+        Pattern::Variable(name) => {
+            env.insert(name.clone(), target.clone());
+        },
+        Pattern::Variant { variant_name, fields } => {
+            if let Some(enum_def) = module_env.find_enum_by_variant(variant_name) {
+                if let Some(variant_def) = enum_def.variants.iter().find(|v| v.name == *variant_name) {
+                    for (i, field_pattern) in fields.iter().enumerate() {
+                        let proj_name = format!("__proj_{}_{}", variant_name, i);
+                        let field_sym: Dynamic = if i < variant_def.fields.len() {
+                            let field_type = &variant_def.fields[i];
+                            let base = if *field_type == enum_def.name {
+                                "i64".to_string()
+                            } else {
+                                module_env.resolve_base_type(field_type)
+                            };
+                            match base.as_str() {
+                                "f64" => Float::new_const(ctx, vc.sym(&proj_name), 11, 53).into(),
+                                _ => Int::new_const(ctx, vc.sym(&proj_name)).into(),
+                            }
+                        } else {
+                            Int::new_const(ctx, vc.sym(&proj_name)).into()
+                        };
+                        env.insert(proj_name.clone(), field_sym.clone());
+
+                        // Variable パターン: projector を変数名にもバインド
+                        match field_pattern {
+                            Pattern::Variable(fname) => {
+                                env.insert(fname.clone(), field_sym.clone());
+                            },
+                            Pattern::Variant { .. } => {
+                                // ネストした Variant: 再帰的にバインド
+                                pattern_bind_variables(vc, field_pattern, &field_sym, env);
+                            },
+                            _ => {}
+                        }
+                    }
+                }
+            }
+        },
+        Pattern::Wildcard | Pattern::Literal(_) => {},
+    }
+}
+
+/// nullable パラメータへの match では、`Some(v)` の v を共有 projector ではなく
+/// そのパラメータ固有の値シンボル（`__some_x`）に束縛する。
+pub(super) fn bind_nullable_payload<'a>(pattern: &Pattern, target: &Expr, env: &mut Env<'a>) {
+    if let (Pattern::Variant { variant_name, fields }, Expr::Variable(name)) = (pattern, target) {
+        if variant_name != "Some" {
+            return;
+        }
+        if let ([Pattern::Variable(bound)], Some(value)) = (fields.as_slice(), env.get(&nullable_value_name(name)).cloned()) {
+            env.insert(bound.clone(), value);
+        }
+    }
+}
+
+/// アームの Variant パターンから対応する EnumDef を検出する。
+/// 最初に見つかった Variant パターンの所属 Enum を返す。
+pub(super) fn detect_enum_from_arms<'a>(arms: &[MatchArm], module_env: &'a ModuleEnv) -> Option<&'a EnumDef> {
+    for arm in arms {
+        if let Pattern::Variant { variant_name, .. } = &arm.pattern {
+            if let Some(enum_def) = module_env.find_enum_by_variant(variant_name) {
+                return Some(enum_def);
+            }
+        }
+    }
+    None
+}
+
+/// 網羅性エラーの反例を model から構築する。
+/// ターゲットの tag 値からバリアント名を逆引きし、payload は projector シンボル
+/// （__proj_{Variant}_{i}）を model で評価して具体値を得る。
+pub(super) fn match_counterexample<'a>(
+    vc: &VCtx<'a>,
+    model: &z3::Model<'a>,
+    target: &Dynamic<'a>,
+    arms: &[MatchArm],
+) -> Counterexample {
+    let ctx = vc.ctx;
+    let module_env = vc.module_env;
+    let mut ce = Counterexample::default();
+    // アームから Enum 定義を特定（ドメイン制約と同じロジック）
+    let enum_ctx = detect_enum_from_arms(arms, module_env);
+
+    // ターゲット変数の具体的な値を取得
+    let target_val = match model.eval(target, true) {
+        Some(v) => v,
+        None => {
+            // 評価に失敗した場合、アームの情報からヒントを生成
+            let covered: Vec<String> = arms.iter().map(|arm| {
+                match &arm.pattern {
+                    Pattern::Literal(n) => format!("{}", n),
+                    Pattern::Variant { variant_name, .. } => variant_name.clone(),
+                    Pattern::Variable(name) => format!("_{} (bind)", name),
+                    Pattern::Wildcard => "_".to_string(),
+                }
+            }).collect();
+            ce.note = Some(format!("could not evaluate; covered patterns: [{}]", covered.join(", ")));
+            return ce;
+        }
+    };
+    let (target_value, target_raw) = render_model_value(&target_val);
+    let tag_val = target_val.as_int().and_then(|i| i.as_i64());
+
+    if let Some(tag_val) = tag_val {
+        // まず arms から特定した Enum を優先し、なければ module_env の全 Enum 定義を走査
+        let found = enum_ctx
+            .and_then(|edef| edef.variants.get(tag_val as usize).map(|v| (edef, v)))
+            .or_else(|| module_env.enums.values()
+                .find_map(|edef| edef.variants.get(tag_val as usize).map(|v| (edef, v))));
+        if let Some((edef, variant)) = found {
+            let mut payload = Vec::new();
+            for (i, field_type) in variant.fields.iter().enumerate() {
+                let proj_name = format!("__proj_{}_{}", variant.name, i);
+                let base = if *field_type == edef.name {
+                    "i64".to_string() // 再帰フィールドは tag 値
+                } else {
+                    module_env.resolve_base_type(field_type)
+                };
+                let proj_sym: Dynamic = match base.as_str() {
+                    "f64" => Float::new_const(ctx, vc.sym(&proj_name), 11, 53).into(),
+                    _ => Int::new_const(ctx, vc.sym(&proj_name)).into(),
+                };
+                let (value, raw) = model.eval(&proj_sym, true)
+                    .map(|v| render_model_value(&v))
+                    .unwrap_or_else(|| (ModelValue::Unknown("?".to_string()), "?".to_string()));
+                payload.push(CounterexampleValue {
+                    name: format!("{}.{}", variant.name, i),
+                    declared_type: field_type.clone(),
+                    base_type: base,
+                    value,
+                    raw,
+                });
+            }
+            ce.variant = Some(CounterexampleVariant {
+                enum_name: edef.name.clone(),
+                variant_name: variant.name.clone(),
+                tag: tag_val,
+                payload,
+            });
+            ce.note = Some("missing from match arms".to_string());
+            return ce;
+        }
+    }
+
+    ce.values.push(CounterexampleValue {
+        name: "value".to_string(),
+        declared_type: "i64".to_string(),
+        base_type: "i64".to_string(),
+        value: target_value,
+        raw: target_raw,
+    });
+    ce.note = Some("no matching arm".to_string());
+    ce
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{parse_module, Item};
+    use crate::test_support::{env_from_source, find_atom, TempDir};
+
+    #[test]
+    fn test_missing_variant_counterexample_payload() {
+        let source = r#"
+enum Shape {
+    Circle(i64),
+    Rect(i64, i64)
+}
+
+atom area(s: i64)
+requires: s >= 0 && s <= 1;
+ensures: true;
+body: {
+    match s {
+        Circle(r) => r
+    }
+};
+"#;
+        let items = parse_module(source).unwrap();
+        let mut module_env = ModuleEnv::new();
+        let mut target = None;
+        for item in &items {
+            match item {
+                Item::EnumDef(e) => module_env.register_enum(e),
+                Item::Atom(a) => {
+                    module_env.register_atom(a);
+                    target = Some(a.clone());
+                }
+                _ => {}
+            }
+        }
+        module_env.counterexample_format = CounterexampleFormat::Json;
+        let atom = target.expect("atom");
+        let err = verify(&atom, &std::env::temp_dir(), &module_env).expect_err("match must be non-exhaustive");
+        let msg = err.to_string();
+        let json_start = msg.find('{').expect("json in message");
+        let parsed: serde_json::Value = serde_json::from_str(&msg[json_start..]).expect("valid json");
+        let variant = &parsed["counterexample"]["variant"];
+        assert_eq!(variant["enum"], "Shape");
+        assert_eq!(variant["name"], "Rect");
+        let payload = variant["payload"].as_array().expect("payload array");
+        assert_eq!(payload.len(), 2);
+        for field in payload {
+            assert!(field["value"].is_number() && field["raw"] != "?", "{}", field);
+        }
+    }
+
+    const MATCH_ARMS_SOURCE: &str = r#"
+atom arm_first(x: i64, d: i64)
+requires: true;
+ensures: true;
+body: match d {
+    0 => x / d,
+    1 => x / d,
+    _ => x / d
+};
+
+atom arm_last(x: i64, d: i64)
+requires: d >= 0;
+ensures: true;
+body: match x {
+    0 => 0,
+    1 => x / (d + 1),
+    _ => x / d
+};
+
+atom arm_ensures(x: i64)
+requires: x >= 0;
+ensures: result >= 0;
+body: match x {
+    0 => 0,
+    1 => 1,
+    _ => 0 - x
+};
+"#;
+
+    #[test]
+    fn test_match_failures_are_attributed_to_arms() {
+        let (items, module_env) = env_from_source(MATCH_ARMS_SOURCE);
+        let output_dir = TempDir::new("match_arms");
+        let atom = |name: &str| find_atom(&items, name);
+
+        // 各アームの義務はそのアームの経路条件の下で証明する: d == 0 のアームだけがゼロ除算になる
+        let err = verify(&atom("arm_first"), &output_dir, &module_env).unwrap_err().to_string();
+        assert!(err.contains("in arm 1 of match #1 at atom 'arm_first': Potential division by zero"), "{}", err);
+
+        // 先行アームに該当しないこと（x != 0 && x != 1）だけでは d != 0 は示せない
+        let err = verify(&atom("arm_last"), &output_dir, &module_env).unwrap_err().to_string();
+        assert!(err.contains("in arm 3 of match #1 at atom 'arm_last'"), "{}", err);
+        assert!(!err.contains("in arm 2"), "{}", err);
+
+        // ensures の反例がどのアームを通るかを示す
+        let err = verify(&atom("arm_ensures"), &output_dir, &module_env).unwrap_err().to_string();
+        assert!(err.contains("Postcondition"), "{}", err);
+        assert!(err.contains("Counter-example path: arm 3 (`_`) of match #1"), "{}", err);
+        assert!(!err.contains("arm 1 (") && !err.contains("arm 2 ("), "{}", err);
+    }
+}
@@ -0,0 +1,390 @@
+// =============================================================================
+// 式の値に付く汚染ラベル（taint）の伝播
+// =============================================================================
+//
+// 値がどこから来たかをラベルの集合として式の上で追跡する。検査ごとの規則（TaintRules）が
+// ラベルの発生源と流出先（sink）を決め、伝播そのものはここで共通に扱う:
+// - 演算・構造体・フィールド参照・呼び出しの結果は、構成要素のラベルの和を持つ
+// - let は束縛した変数に値のラベルを付ける。代入は分岐やループの中にもあり得るので、
+//   以前のラベルに合併する（弱い更新）
+// - if / match の結果は条件（match の対象）のラベルも持つ（どの分岐が選ばれたかも値の出所）
+// - match のパターン変数は対象のラベルを持つ
+// - while は変数のラベルが変わらなくなるまで本体を評価し直す
+// - ブロック内の let はブロックを出ると外側の束縛に戻る
+
+use crate::ast::{result_kind, ResultKind};
+use crate::parser::{Atom, Expr, Pattern, ResourceMode};
+use std::collections::{BTreeSet, HashMap};
+use crate::report::FailureKind;
+use super::{ModuleEnv, MumeiError, MumeiResult};
+
+/// 値に付いた汚染ラベルの集合
+pub(super) type Labels = BTreeSet<String>;
+
+/// 汚染ラベルの発生源と流出先を決める検査ごとの規則
+pub(super) trait TaintRules {
+    /// 式の評価を始める前に呼ばれる（acquire のようなスコープを持つ式の入口）
+    fn enter(&mut self, _expr: &Expr) {}
+    /// 式の値のラベルが決まった後に呼ばれる。発生源はここで labels にラベルを加える
+    fn exit(&mut self, _expr: &Expr, _labels: &mut Labels) {}
+    /// 呼び出しの実引数のラベルが決まったときに呼ばれる（流出先の検査）
+    fn call(&mut self, _callee: &str, _args: &[Expr], _arg_labels: &[Labels]) {}
+}
+
+/// 変数ごとのラベルを保持して式を評価する
+pub(super) struct TaintFlow<'r, R: TaintRules> {
+    rules: &'r mut R,
+    vars: HashMap<String, Labels>,
+}
+
+impl<'r, R: TaintRules> TaintFlow<'r, R> {
+    pub(super) fn new(rules: &'r mut R) -> Self {
+        TaintFlow { rules, vars: HashMap::new() }
+    }
+
+    /// 式の値のラベルを求める
+    pub(super) fn eval(&mut self, expr: &Expr) -> Labels {
+        self.rules.enter(expr);
+        let mut labels = match expr {
+            Expr::Number(_) | Expr::Float(_) | Expr::StringLit(_) | Expr::Bool(_) => Labels::new(),
+            Expr::Variable(name) => self.vars.get(name).cloned().unwrap_or_default(),
+            Expr::ArrayAccess(name, index) => {
+                let mut labels = self.vars.get(name).cloned().unwrap_or_default();
+                labels.extend(self.eval(index));
+                labels
+            }
+            Expr::BinaryOp(..) => {
+                // 左に連なる連鎖は再帰せずに辿る。途中の BinaryOp にも再帰と同じ順で enter / exit を通す
+                let (leaf, nodes) = expr.left_spine();
+                for node in nodes[..nodes.len() - 1].iter().rev() {
+                    self.rules.enter(node);
+                }
+                let mut labels = self.eval(leaf);
+                for (i, node) in nodes.iter().enumerate() {
+                    if let Expr::BinaryOp(_, _, right) = node {
+                        labels.extend(self.eval(right));
+                    }
+                    if i + 1 < nodes.len() {
+                        self.rules.exit(node, &mut labels);
+                    }
+                }
+                labels
+            }
+            Expr::IfThenElse { cond, then_branch, else_branch } => {
+                let mut labels = self.eval(cond);
+                labels.extend(self.eval(then_branch));
+                labels.extend(self.eval(else_branch));
+                labels
+            }
+            Expr::Let { var, value } => {
+                let labels = self.eval(value);
+                self.vars.insert(var.clone(), labels.clone());
+                labels
+            }
+            Expr::Assign { var, value } => {
+                let labels = self.eval(value);
+                self.vars.entry(var.clone()).or_default().extend(labels.iter().cloned());
+                labels
+            }
+            Expr::Block(stmts) => {
+                let bound: Vec<&String> = stmts.iter().filter_map(|s| match s {
+                    Expr::Let { var, .. } => Some(var),
+                    _ => None,
+                }).collect();
+                self.scoped(&bound, |flow| {
+                    let mut last = Labels::new();
+                    for stmt in stmts {
+                        last = flow.eval(stmt);
+                    }
+                    last
+                })
+            }
+            Expr::While { cond, body, .. } => {
+                loop {
+                    let before = self.vars.clone();
+                    self.eval(cond);
+                    self.eval(body);
+                    if self.vars == before {
+                        break;
+                    }
+                }
+                Labels::new()
+            }
+            Expr::Call(name, args) => {
+                let arg_labels: Vec<Labels> = args.iter().map(|a| self.eval(a)).collect();
+                self.rules.call(name, args, &arg_labels);
+                arg_labels.into_iter().flatten().collect()
+            }
+            Expr::StructInit { fields, .. } => {
+                fields.iter().flat_map(|(_, value)| self.eval(value)).collect()
+            }
+            Expr::FieldAccess(base, _) => self.eval(base),
+            Expr::Match { target, arms } => {
+                let target_labels = self.eval(target);
+                let mut labels = target_labels.clone();
+                for arm in arms {
+                    let mut bound = Vec::new();
+                    pattern_variables(&arm.pattern, &mut bound);
+                    let arm_labels = self.scoped(&bound, |flow| {
+                        for var in &bound {
+                            flow.vars.insert(var.to_string(), target_labels.clone());
+                        }
+                        let mut arm_labels = arm.guard.as_ref().map(|g| flow.eval(g)).unwrap_or_default();
+                        arm_labels.extend(flow.eval(&arm.body));
+                        arm_labels
+                    });
+                    labels.extend(arm_labels);
+                }
+                labels
+            }
+            Expr::Acquire { body, .. } | Expr::Async { body } => self.eval(body),
+            Expr::Await { expr } | Expr::RefArg { expr, .. } => self.eval(expr),
+            Expr::Assume { cond, .. } => {
+                self.eval(cond);
+                Labels::new()
+            }
+        };
+        self.rules.exit(expr, &mut labels);
+        labels
+    }
+
+    /// names の束縛を f の間だけ有効にし、終了後に外側の束縛（なければ未束縛）に戻す
+    fn scoped<T>(&mut self, names: &[&String], f: impl FnOnce(&mut Self) -> T) -> T {
+        let saved: Vec<(String, Option<Labels>)> = names.iter()
+            .map(|n| (n.to_string(), self.vars.get(n.as_str()).cloned()))
+            .collect();
+        let result = f(self);
+        for (name, labels) in saved {
+            match labels {
+                Some(labels) => { self.vars.insert(name, labels); }
+                None => { self.vars.remove(&name); }
+            }
+        }
+        result
+    }
+}
+
+/// パターンが束縛する変数名
+fn pattern_variables<'p>(pattern: &'p Pattern, out: &mut Vec<&'p String>) {
+    match pattern {
+        Pattern::Variable(name) => out.push(name),
+        Pattern::Variant { fields, .. } => for field in fields { pattern_variables(field, out); },
+        Pattern::Wildcard | Pattern::Literal(_) => {}
+    }
+}
+
+// =============================================================================
+// 排他リソースの critical section からの流出
+// =============================================================================
+//
+// `acquire r { ... }` の中で計算した値は r のラベルを持つ。r が exclusive のとき、そのラベルが
+// ブロックの外で atom の結果か atom 呼び出しの実引数に流れたら、保護された値が
+// critical section の外に持ち出されたとして報告する。
+// - スカラー（i64 / u64 / f64 / bool）はコピーで持ち出されるので既定では対象外。
+//   `[proof] strict_resources = true` ではスカラーも含めてエラーにする
+// - `ref` / `ref mut` の実引数は値ではなく借用なので、型に関わらずスカラー扱いしない
+// - 組み込み関数の呼び出しは流出先ではない（結果にラベルが伝播するだけ）
+
+/// 流出の経路
+struct Escape {
+    resource: String,
+    route: String,
+    scalar: bool,
+}
+
+/// acquire を発生源、ブロック外の atom 呼び出しを流出先とする規則
+struct ResourceEscapeRules<'m> {
+    module_env: &'m ModuleEnv,
+    /// 評価中の式を囲む acquire のリソース（外側から順）
+    held: Vec<String>,
+    escapes: Vec<Escape>,
+}
+
+impl ResourceEscapeRules<'_> {
+    /// リソース名を登録キーに解決する（未定義・曖昧な名前はリソース階層の検査が報告する）
+    fn resource_key(&self, name: &str) -> String {
+        self.module_env.resolve_resource(name).ok().flatten()
+            .map_or_else(|| name.to_string(), |(key, _)| key)
+    }
+
+    fn is_exclusive(&self, key: &str) -> bool {
+        self.module_env.get_resource(key).is_some_and(|r| r.mode == ResourceMode::Exclusive)
+    }
+
+    fn record(&mut self, labels: &Labels, route: String, scalar: bool) {
+        for resource in labels {
+            if self.held.contains(resource) || !self.is_exclusive(resource) {
+                continue;
+            }
+            // while の再評価で同じ経路を何度も通るので重複は記録しない
+            if !self.escapes.iter().any(|e| &e.resource == resource && e.route == route) {
+                self.escapes.push(Escape { resource: resource.clone(), route: route.clone(), scalar });
+            }
+        }
+    }
+}
+
+impl TaintRules for ResourceEscapeRules<'_> {
+    fn enter(&mut self, expr: &Expr) {
+        if let Expr::Acquire { resource, .. } = expr {
+            let key = self.resource_key(resource);
+            self.held.push(key);
+        }
+    }
+
+    fn exit(&mut self, expr: &Expr, labels: &mut Labels) {
+        if let Expr::Acquire { .. } = expr {
+            if let Some(key) = self.held.pop() {
+                labels.insert(key);
+            }
+        }
+    }
+
+    fn call(&mut self, callee: &str, args: &[Expr], arg_labels: &[Labels]) {
+        let params = match self.module_env.get_atom(callee) {
+            Some(atom) => atom.params.clone(),
+            None => return,
+        };
+        for (i, (arg, labels)) in args.iter().zip(arg_labels).enumerate() {
+            let scalar = !matches!(arg, Expr::RefArg { .. })
+                && params.get(i).is_some_and(|p| is_scalar_type(p.type_name.as_deref(), self.module_env));
+            self.record(labels, format!("through argument {} of '{}'", i + 1, callee), scalar);
+        }
+    }
+}
+
+/// パラメータの型がスカラーか（型注釈がなければ i64）
+fn is_scalar_type(type_name: Option<&str>, module_env: &ModuleEnv) -> bool {
+    type_name.map_or(true, |t| matches!(module_env.resolve_base_type(t).as_str(), "i64" | "u64" | "f64" | "bool"))
+}
+
+/// exclusive リソースの下で計算した値が critical section の外に流れる経路を検査し、警告を返す。
+/// `[proof] strict_resources = true` ではスカラーも含めてエラーにする
+pub(super) fn resource_escape_warnings(atom: &Atom, body: &Expr, module_env: &ModuleEnv) -> MumeiResult<Vec<String>> {
+    let mut rules = ResourceEscapeRules { module_env, held: Vec::new(), escapes: Vec::new() };
+    let result = TaintFlow::new(&mut rules).eval(body);
+    let result_scalar = result_kind(atom, module_env) != ResultKind::Str;
+    rules.record(&result, format!("as the result of '{}'", atom.name), result_scalar);
+
+    let mut warnings = Vec::new();
+    for escape in rules.escapes {
+        let message = format!(
+            "value derived under exclusive resource '{}' escapes its critical section {}",
+            escape.resource, escape.route
+        );
+        if module_env.strict_resources {
+            return Err(MumeiError::failure(FailureKind::Resource, format!(
+                "Atom '{}': {} ([proof] strict_resources = true)", atom.name, message
+            )));
+        }
+        if !escape.scalar {
+            warnings.push(message);
+        }
+    }
+    Ok(warnings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{parse_expression, Item};
+    use crate::test_support::{env_from_source, find_atom, TempDir};
+    use crate::verification::verify;
+
+    /// secret(..) の結果に "secret" を付け、sink(..) に渡されたラベルを記録する規則
+    #[derive(Default)]
+    struct SecretRules {
+        sunk: Vec<Labels>,
+    }
+
+    impl TaintRules for SecretRules {
+        fn exit(&mut self, expr: &Expr, labels: &mut Labels) {
+            if matches!(expr, Expr::Call(name, _) if name == "secret") {
+                labels.insert("secret".to_string());
+            }
+        }
+
+        fn call(&mut self, callee: &str, _args: &[Expr], arg_labels: &[Labels]) {
+            if callee == "sink" {
+                self.sunk.extend(arg_labels.iter().cloned());
+            }
+        }
+    }
+
+    fn labels_of(source: &str) -> (Labels, SecretRules) {
+        let mut rules = SecretRules::default();
+        let labels = TaintFlow::new(&mut rules).eval(&parse_expression(source).unwrap());
+        (labels, rules)
+    }
+
+    fn secret() -> Labels {
+        Labels::from(["secret".to_string()])
+    }
+
+    #[test]
+    fn test_taint_propagation_rules() {
+        // 演算と let は構成要素のラベルを引き継ぐ
+        assert_eq!(labels_of("{ let a = secret(0); let b = a + 1; let c = 2; b * c }").0, secret());
+        assert!(labels_of("{ let a = secret(0); let c = 2; c * 3 }").0.is_empty());
+        // if の条件のラベルは結果に流れる
+        assert_eq!(labels_of("if secret(0) > 0 { 1 } else { 2 }").0, secret());
+        // match のパターン変数は対象のラベルを持ち、アームの本体のラベルも結果に流れる
+        assert_eq!(labels_of("{ let n = 1; match n { 0 => secret(0), _ => 0 } }").0, secret());
+        let (_, rules) = labels_of("match secret(0) { x => sink(x + 1) }");
+        assert_eq!(rules.sunk, vec![secret()]);
+        // ブロック内の let はブロックの外の同名変数を汚さない
+        assert!(labels_of("{ let a = 1; let b = if a > 0 { let a = secret(0); a } else { 0 }; a }").0.is_empty());
+        // 代入は以前のラベルに合併し、while は固定点まで評価し直す（2 周目で b に流れる）
+        let source = "{ let a = 0; let b = 0; let i = 0; while i < 3 invariant: true { b = a; a = secret(0); i = i + 1; }; sink(b) }";
+        let (_, rules) = labels_of(source);
+        assert!(rules.sunk.iter().any(|l| l.contains("secret")), "{:?}", rules.sunk);
+        // 呼び出しの結果は実引数のラベルを持つ
+        assert_eq!(labels_of("{ let a = secret(0); abs(a) }").0, secret());
+    }
+
+    fn fixture() -> (Vec<Item>, ModuleEnv) {
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("test_resource_escape.mm");
+        let source = std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+        let (items, mut module_env) = env_from_source(&source);
+        for item in &items {
+            if let Item::ResourceDef(r) = item {
+                module_env.register_resource(r, "test_resource_escape.mm").unwrap();
+            }
+        }
+        (items, module_env)
+    }
+
+    #[test]
+    fn test_exclusive_resource_escape_fixture() {
+        let (items, mut module_env) = fixture();
+        let atom = |name: &str| find_atom(&items, name);
+        let check = |name: &str, env: &ModuleEnv| {
+            let a = atom(name);
+            resource_escape_warnings(&a, &parse_expression(&a.body_expr).unwrap(), env)
+        };
+
+        for (name, route) in [
+            ("leak_entry", "as the result of 'leak_entry'"),
+            ("forward_entry", "through argument 1 of 'audit'"),
+            ("lend_balance", "through argument 1 of 'bump'"),
+        ] {
+            assert_eq!(check(name, &module_env).unwrap(), vec![
+                format!("value derived under exclusive resource 'ledger' escapes its critical section {}", route)
+            ], "{}", name);
+        }
+        for name in ["record_entry", "read_balance", "read_setting"] {
+            assert!(check(name, &module_env).unwrap().is_empty(), "{}", name);
+        }
+
+        // 警告は検証を失敗させない
+        let output_dir = TempDir::new("resource_escape");
+        assert!(verify(&atom("leak_entry"), &output_dir, &module_env).is_ok());
+
+        // [proof] strict_resources = true ではスカラーのコピーもエラー
+        module_env.strict_resources = true;
+        let err = verify(&atom("read_balance"), &output_dir, &module_env).unwrap_err().to_string();
+        assert!(err.contains("Atom 'read_balance': value derived under exclusive resource 'ledger' escapes its critical section as the result of 'read_balance' ([proof] strict_resources = true)"), "{}", err);
+        assert!(check("record_entry", &module_env).is_err());
+        assert!(check("read_setting", &module_env).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,86 @@
+// =============================================================================
+// 組み込みトレイト (Built-in Traits)
+// =============================================================================
+
+use super::*;
+
+/// 組み込みトレイトを ModuleEnv に自動登録する。
+/// Numeric（算術演算）、Ord（比較）、Eq（等価性）の3つを提供。
+pub fn register_builtin_traits(module_env: &mut ModuleEnv) {
+    use crate::parser::{TraitDef as TD, ImplDef as ID};
+
+    // --- trait Eq ---
+    // fn eq(a: Self, b: Self) -> bool;
+    // law reflexive: eq(x, x) == true;
+    // law symmetric: eq(a, b) => eq(b, a);
+    module_env.register_trait(&TD {
+        name: "Eq".to_string(),
+        methods: vec![
+            TraitMethod { name: "eq".to_string(), param_types: vec!["Self".into(), "Self".into()], return_type: "bool".into(), param_constraints: vec![None, None] },
+        ],
+        laws: vec![
+            ("reflexive".into(), "eq(x, x) == true".into()),
+            ("symmetric".into(), "eq(a, b) => eq(b, a)".into()),
+        ],
+        extends: vec![],
+    });
+
+    // --- trait Ord (extends Eq implicitly) ---
+    // fn leq(a: Self, b: Self) -> bool;
+    // law reflexive: leq(x, x) == true;
+    // law antisymmetric: leq(a, b) && leq(b, a) => eq(a, b);
+    // law transitive: leq(a, b) && leq(b, c) => leq(a, c);
+    module_env.register_trait(&TD {
+        name: "Ord".to_string(),
+        methods: vec![
+            TraitMethod { name: "leq".to_string(), param_types: vec!["Self".into(), "Self".into()], return_type: "bool".into(), param_constraints: vec![None, None] },
+        ],
+        laws: vec![
+            ("reflexive".into(), "leq(x, x) == true".into()),
+            ("transitive".into(), "leq(a, b) && leq(b, c) => leq(a, c)".into()),
+        ],
+        extends: vec![],
+    });
+
+    // --- trait Numeric (extends Ord implicitly) ---
+    // fn add(a: Self, b: Self) -> Self;
+    // fn sub(a: Self, b: Self) -> Self;
+    // fn mul(a: Self, b: Self) -> Self;
+    // law additive_identity: add(a, 0) == a;
+    // law commutative_add: add(a, b) == add(b, a);
+    module_env.register_trait(&TD {
+        name: "Numeric".to_string(),
+        methods: vec![
+            TraitMethod { name: "add".to_string(), param_types: vec!["Self".into(), "Self".into()], return_type: "Self".into(), param_constraints: vec![None, None] },
+            TraitMethod { name: "sub".to_string(), param_types: vec!["Self".into(), "Self".into()], return_type: "Self".into(), param_constraints: vec![None, None] },
+            TraitMethod { name: "mul".to_string(), param_types: vec!["Self".into(), "Self".into()], return_type: "Self".into(), param_constraints: vec![None, None] },
+        ],
+        laws: vec![
+            ("commutative_add".into(), "add(a, b) == add(b, a)".into()),
+        ],
+        extends: vec![],
+    });
+
+    // --- 組み込み impl: i64, u64, f64 は Eq + Ord + Numeric を自動実装 ---
+    for base_type in &["i64", "u64", "f64"] {
+        module_env.register_impl(&ID {
+            trait_name: "Eq".into(),
+            target_type: base_type.to_string(),
+            method_bodies: vec![("eq".into(), "a == b".into())],
+        });
+        module_env.register_impl(&ID {
+            trait_name: "Ord".into(),
+            target_type: base_type.to_string(),
+            method_bodies: vec![("leq".into(), "a <= b".into())],
+        });
+        module_env.register_impl(&ID {
+            trait_name: "Numeric".into(),
+            target_type: base_type.to_string(),
+            method_bodies: vec![
+                ("add".into(), "a + b".into()),
+                ("sub".into(), "a - b".into()),
+                ("mul".into(), "a * b".into()),
+            ],
+        });
+    }
+}
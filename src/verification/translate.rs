@@ -0,0 +1,3064 @@
+// =============================================================================
+// 式の Z3 への変換 (Expression Translation)
+// =============================================================================
+//
+// VCtx（検証コンテキスト）と expr_to_z3、呼び出し先の ensures の仮定・等式伝播。
+// 文・式ごとの検証条件（配列境界・ゼロ除算・呼び出し先の requires など）もここで生成する。
+
+use super::*;
+
+/// 検証時に共有するコンテキスト（ctx, arr, module_env を束ねて引数を削減）
+pub(super) struct VCtx<'a> {
+    pub(super) ctx: &'a Context,
+    /// 配列名 → Z3 配列（Int → 要素型）。VCtx::array で名前ごとに遅延生成する
+    pub(super) arrays: RefCell<HashMap<String, Array<'a>>>,
+    /// 検証中の atom のパラメータ（配列の要素型の解決に使う。impl の law では空）
+    pub(super) params: &'a [Param],
+    pub(super) module_env: &'a ModuleEnv,
+    /// 義務単位の検証キャッシュ（None ならキャッシュなしで毎回 Z3 を呼ぶ）
+    pub(super) obligations: Option<&'a RefCell<ObligationCache>>,
+    /// Z3 クエリの watchdog（None なら Z3 のソフトタイムアウトのみ）
+    pub(super) guard: Option<&'a SolverGuard>,
+    /// シンボル名の接頭辞（atom 名など）。空なら接頭辞なし
+    pub(super) scope: &'a str,
+    /// 呼び出し結果などの一時シンボルの atom 内連番（base 名 → 次の番号）
+    pub(super) counters: RefCell<HashMap<String, usize>>,
+    /// `total atom` の検証中なら true。Unknown を証明済みとみなさず undecided に記録する
+    pub(super) total: bool,
+    /// total atom で Z3 が決着できなかった（Unknown）義務の一覧（"kind: goal"）
+    pub(super) undecided: RefCell<Vec<String>>,
+    /// 評価中の match アームの経路条件（ネストした match の外側から順）
+    pub(super) arm_stack: RefCell<Vec<Bool<'a>>>,
+    /// これまでに評価した match アームと、そのアームに到達する経路条件（ensures の反例の帰属用）
+    pub(super) arm_paths: RefCell<Vec<(String, Bool<'a>)>>,
+    /// 検証中の atom の入口での decreases 測度の値（atom レベルの `decreases:` がある場合のみ）。
+    /// 自己再帰呼び出しの測度がこれより真に小さいことを証明する
+    pub(super) entry_measure: RefCell<Option<Int<'a>>>,
+    /// async atom 呼び出しの結果（future ハンドル）。キーはハンドルの Z3 シンボル
+    pub(super) futures: RefCell<HashMap<String, PendingFuture<'a>>>,
+    /// atom 呼び出しの結果の長さ `len(result)`。キーは結果の Z3 シンボルで、
+    /// 結果を let で束縛したり別の atom に渡したりしたときに長さを引き継ぐ
+    pub(super) call_lengths: RefCell<HashMap<String, Int<'a>>>,
+    /// 構造体の値（構造体リテラル・構造体を返す atom の呼び出し結果・構造体パラメータ）。
+    /// キーは値を表すハンドル（Int 定数）の Z3 シンボルで、フィールドアクセスはここから引く
+    pub(super) struct_values: RefCell<HashMap<String, StructValue<'a>>>,
+    /// 浮動小数点演算の結果の定義（オペランドを名前付けする等式と、SMT-LIB の `(fp.add RNE ..)` 等）。
+    /// 式の評価時には solver がないこともあるので、check のたびにすべて assert する（push/pop で消えても戻る）
+    pub(super) float_defs: RefCell<Vec<(Vec<Bool<'a>>, String)>>,
+    /// 契約（requires / ensures）で扱ったユーザー定義 atom の呼び出し先（名前順）。
+    /// law の失敗メッセージで、どの atom の ensures に頼ったかを示すのに使う
+    pub(super) callees: RefCell<BTreeMap<String, Atom>>,
+    /// 検証を止めない警告の出力先（verify の呼び出し元に返す）。None なら捨てる（law・requires の充足可能性検査）
+    pub(super) warnings: Option<&'a RefCell<Vec<String>>>,
+}
+
+/// 構造体の値。フィールドは構造体定義の順
+#[derive(Clone)]
+pub(super) struct StructValue<'a> {
+    pub(super) type_name: String,
+    pub(super) fields: Vec<(String, Dynamic<'a>)>,
+}
+
+/// await される前の async atom 呼び出し。
+/// ensures はハンドルではなく await した値についての事実なので、Await 式で初めて仮定する
+pub(super) struct PendingFuture<'a> {
+    callee: Atom,
+    /// 仮引数を実引数の値に束縛した環境（呼び出し時点のもの）
+    call_env: Env<'a>,
+}
+
+impl<'a> VCtx<'a> {
+    /// Z3 シンボル名に scope を付ける（例: "clamp::x"）。
+    /// プールの Context を共有する atom 間でシンボルが衝突しないようにし、モデル表示でも由来が分かる。
+    pub(super) fn sym(&self, name: &str) -> String {
+        if self.scope.is_empty() {
+            name.to_string()
+        } else {
+            format!("{}::{}", self.scope, name)
+        }
+    }
+
+    /// 配列名に対応する Z3 配列を返す（例: "clamp::arr_xs"）。
+    /// 配列ごとに別の Z3 配列を作るため、`a[0]` についての事実が `b[0]` に漏れない。
+    /// 要素型は `xs: [f64]` なら Float、それ以外（`[i64]` や型注釈のない配列名）は Int。
+    fn array(&self, name: &str) -> Array<'a> {
+        if let Some(existing) = self.arrays.borrow().get(name) {
+            return existing.clone();
+        }
+        let elem = self.params.iter()
+            .find(|p| p.name == name)
+            .and_then(|p| p.type_name.as_deref())
+            .and_then(crate::ast::slice_elem_type)
+            .map(|t| self.module_env.resolve_base_type(&t));
+        let range = match elem.as_deref() {
+            Some("f64") => z3::Sort::float(self.ctx, 11, 53),
+            _ => z3::Sort::int(self.ctx),
+        };
+        let array = Array::new_const(self.ctx, self.sym(&format!("arr_{}", name)), &z3::Sort::int(self.ctx), &range);
+        self.arrays.borrow_mut().insert(name.to_string(), array.clone());
+        array
+    }
+
+    /// atom 内で決定的な一時シンボル名を払い出す（例: "call_inc@0", "call_inc@1"）。
+    /// 番号は base 名ごとの出現順なので、同じ atom を何度検証しても同じ名前になり、
+    /// 反例や SMT ダンプを実行間で diff できる。body と ensures で同じ callee を呼んでも
+    /// 同じ VCtx の連番を共有するため衝突しない。
+    fn fresh(&self, base: &str) -> String {
+        let mut counters = self.counters.borrow_mut();
+        let n = counters.entry(base.to_string()).or_insert(0);
+        let name = format!("{}@{}", base, n);
+        *n += 1;
+        name
+    }
+
+    /// 検証を止めない警告を記録する
+    pub(super) fn warn(&self, warning: String) {
+        if let Some(warnings) = self.warnings {
+            warnings.borrow_mut().push(warning);
+        }
+    }
+
+    /// 次の `name` 呼び出しが atom 内で何回目か（1 始まり。義務のラベル用）
+    fn call_number(&self, name: &str) -> usize {
+        self.counters.borrow().get(&format!("call_{}", name)).copied().unwrap_or(0) + 1
+    }
+
+    /// 量化子の束縛変数に atom 内で一意な内部シンボル名を払い出す（例: `forall(i, ...)` → "__q_i_0"）。
+    /// 利用者の名前のまま Z3 定数を作ると、同名のパラメータと同じシンボルになり、
+    /// 範囲式や外側の事実が量化子に捕獲されてしまう。"__q_" は予約接頭辞なので利用者の名前とは衝突しない。
+    pub(super) fn fresh_bound(&self, var: &str) -> String {
+        let base = format!("__q_{}", var);
+        let mut counters = self.counters.borrow_mut();
+        let n = counters.entry(base.clone()).or_insert(0);
+        let name = format!("{}_{}", base, n);
+        *n += 1;
+        name
+    }
+
+    /// 構造体の値を表すハンドルを払い出して登録する（例: "__struct_Point@0"）
+    pub(super) fn new_struct_value(&self, type_name: &str, fields: Vec<(String, Dynamic<'a>)>) -> Dynamic<'a> {
+        let handle = Int::new_const(self.ctx, self.sym(&self.fresh(&format!("__struct_{}", type_name))));
+        self.register_struct_value(&handle.clone().into(), type_name, fields);
+        handle.into()
+    }
+
+    /// 既存の値（呼び出し結果のシンボル等）を構造体の値として登録する
+    pub(super) fn register_struct_value(&self, handle: &Dynamic<'a>, type_name: &str, fields: Vec<(String, Dynamic<'a>)>) {
+        self.struct_values.borrow_mut().insert(handle.to_string(), StructValue { type_name: type_name.to_string(), fields });
+    }
+
+    /// value が構造体の値ならその内容を返す
+    pub(super) fn struct_value(&self, value: &Dynamic<'a>) -> Option<StructValue<'a>> {
+        self.struct_values.borrow().get(&value.to_string()).cloned()
+    }
+
+    /// 条件で選ぶ値（if / match の合流）。両辺が同じ型の構造体ならフィールドごとに ite した構造体の値にする
+    fn select(&self, cond: &Bool<'a>, then_val: &Dynamic<'a>, else_val: &Dynamic<'a>) -> Dynamic<'a> {
+        if let (Some(t), Some(e)) = (self.struct_value(then_val), self.struct_value(else_val)) {
+            let same_shape = t.type_name == e.type_name
+                && t.fields.iter().zip(&e.fields).all(|((_, tv), (_, ev))| tv.get_sort() == ev.get_sort());
+            if same_shape {
+                let fields = t.fields.iter().zip(&e.fields)
+                    .map(|((name, tv), (_, ev))| (name.clone(), cond.ite(tv, ev)))
+                    .collect();
+                return self.new_struct_value(&t.type_name, fields);
+            }
+        }
+        cond.ite(then_val, else_val)
+    }
+
+    /// value が async atom 呼び出しの future ハンドルなら、呼び出し先の atom 名を返す
+    fn future_callee(&self, value: &Dynamic<'a>) -> Option<String> {
+        self.futures.borrow().get(&value.to_string()).map(|f| f.callee.name.clone())
+    }
+
+    /// total atom の検証中に Unknown となった義務を記録する（非 total なら何もしない）
+    pub(super) fn note_undecided(&self, kind: &str, goal: &Bool<'a>) {
+        if self.total {
+            let text = goal.to_string().split_whitespace().collect::<Vec<_>>().join(" ");
+            self.undecided.borrow_mut().push(format!("{}: {}", kind, text));
+        }
+    }
+
+    /// atom 内の match 式の通し番号（1 始まり、出現順）
+    fn next_match(&self) -> usize {
+        let mut counters = self.counters.borrow_mut();
+        let n = counters.entry("match".to_string()).or_insert(0);
+        *n += 1;
+        *n
+    }
+
+    /// 反例 model が通るアームの一覧（例: "arm 2 (`Some(x)`) of match #1"）
+    pub(super) fn exercised_arms(&self, model: &z3::Model<'a>) -> Vec<String> {
+        self.arm_paths.borrow().iter()
+            .filter(|(_, cond)| model.eval(cond, true).and_then(|b| b.as_bool()) == Some(true))
+            .map(|(label, _)| label.clone())
+            .collect()
+    }
+
+    /// float_arith_rne が記録した浮動小数点演算の定義を solver に加える
+    fn assert_float_defs(&self, solver: &Solver<'a>) {
+        for (operands, definition) in self.float_defs.borrow().iter() {
+            for operand in operands {
+                solver.assert(operand);
+            }
+            solver.from_string(definition.as_str());
+        }
+    }
+
+    /// solver.check() を（guard があれば watchdog 付きで）実行する
+    pub(super) fn check(&self, solver: &Solver<'a>) -> SatResult {
+        self.assert_float_defs(solver);
+        match self.guard {
+            Some(guard) => guard.check(self.ctx, solver),
+            None => solver.check(),
+        }
+    }
+
+    /// check と同じ。assumptions を仮定して解く
+    pub(super) fn check_assuming(&self, solver: &Solver<'a>, assumptions: &[Bool<'a>]) -> SatResult {
+        self.assert_float_defs(solver);
+        match self.guard {
+            Some(guard) => guard.check_assuming(self.ctx, solver, assumptions),
+            None => solver.check_assumptions(assumptions),
+        }
+    }
+}
+
+/// nullable パラメータ x の値シンボル名
+pub(super) fn nullable_value_name(param_name: &str) -> String {
+    format!("__some_{}", param_name)
+}
+
+/// `old(x)` が指すパラメータ x の入口の値の env キー
+pub(super) fn old_value_name(param_name: &str) -> String {
+    format!("__old_{}", param_name)
+}
+
+/// env にあれば `old(...)` を評価できる（ensures の評価中）ことを示す印。識別子にはならない名前なので
+/// パラメータ名の `__old_<name>` とは衝突しない
+const OLD_SCOPE: &str = "__old@ensures";
+
+/// env から `old(...)` の参照先と印を取り除く（呼び出し先の requires などに呼び出し元のものを漏らさない）
+pub(super) fn clear_old_values(env: &mut Env) {
+    env.retain(|name, _| !name.starts_with("__old_") && name.as_str() != OLD_SCOPE);
+}
+
+/// ensures の評価用に、各パラメータの入口の値（atom の検証では body の評価前の値、
+/// 呼び出し元では実引数の値）を `old(param)` の参照先として env に登録する
+pub(super) fn bind_old_values<'a, 'p>(ctx: &'a Context, env: &mut Env<'a>, values: impl IntoIterator<Item = (&'p str, Dynamic<'a>)>) {
+    clear_old_values(env);
+    for (param_name, value) in values {
+        env.insert(old_value_name(param_name), value);
+    }
+    env.insert(OLD_SCOPE.to_string(), Bool::from_bool(ctx, true).into());
+}
+
+/// Option の Some の tag 値（prelude 未ロード時は std/option.mm の定義順 None=0, Some=1）
+pub(super) fn option_some_tag(module_env: &ModuleEnv) -> i64 {
+    module_env.get_enum("Option")
+        .and_then(|e| e.variants.iter().position(|v| v.name == "Some"))
+        .unwrap_or(1) as i64
+}
+
+/// 変数 `name` の長さシンボル `len_<name>`（>= 0）。未生成なら作って env に登録する
+fn length_symbol<'a>(vc: &VCtx<'a>, name: &str, env: &mut Env<'a>, solver_opt: Option<&Solver<'a>>) -> Int<'a> {
+    let len_name = format!("len_{}", name);
+    if let Some(length) = env.get(&len_name).and_then(|len| len.as_int()) {
+        return length;
+    }
+    let len_var = Int::new_const(vc.ctx, vc.sym(&len_name));
+    if let Some(solver) = solver_opt {
+        solver.assert(&len_var.ge(&Int::from_i64(vc.ctx, 0)));
+    }
+    env.insert(len_name, len_var.clone().into());
+    len_var
+}
+
+/// パラメータが長さを持つ型（配列、または Str とそれをベースにした精緻型）か
+fn has_length(param: &Param, module_env: &ModuleEnv) -> bool {
+    param.type_name.as_deref().map_or(false, |t| crate::ast::slice_elem_type(&module_env.resolve_base_type(t)).is_some())
+        || crate::ast::is_str_param(param, module_env)
+}
+
+/// `is_some(x)` / `is_none(x)` / `value(x)` の引数が nullable パラメータなら、その名前を返す
+fn nullable_arg<'e>(args: &'e [Expr], env: &Env) -> Option<&'e str> {
+    match args {
+        [Expr::Variable(name)] if env.contains_key(&nullable_value_name(name)) => Some(name.as_str()),
+        _ => None,
+    }
+}
+
+/// nullable 仮引数（`T?`）に渡す実引数を評価し、(tag, 値) の組にする。
+/// - nullable パラメータの変数: その tag と値シンボル（`__some_{name}`）
+/// - if 式: 分岐ごとの組を ite で合流する（分岐の義務はそれぞれの経路条件のもとで証明する）
+/// - それ以外（T の値）: Some の tag と評価した値
+fn nullable_arg_to_z3<'a>(
+    vc: &VCtx<'a>,
+    arg: &Expr,
+    env: &mut Env<'a>,
+    solver_opt: Option<&Solver<'a>>,
+    path_cond: Option<&Bool<'a>>,
+) -> MumeiResult<(Int<'a>, Dynamic<'a>)> {
+    let ctx = vc.ctx;
+    let arg = arg.without_ref_marker();
+    if let Some(name) = nullable_arg(std::slice::from_ref(arg), env) {
+        let tag = env[name].as_int().ok_or(MumeiError::TypeError(format!("nullable '{}' must be an Option tag", name)))?;
+        return Ok((tag, env[&nullable_value_name(name)].clone()));
+    }
+    if let Expr::IfThenElse { cond, then_branch, else_branch } = arg {
+        let c = match path_cond {
+            Some(path) => eval_under_path(vc, cond, &mut env.clone(), solver_opt, path)?,
+            None => expr_to_z3(vc, cond, env, solver_opt)?,
+        }.as_bool().ok_or(MumeiError::TypeError("If condition must be boolean".into()))?;
+        let then_path = path_cond.map_or_else(|| c.clone(), |path| Bool::and(ctx, &[path, &c]));
+        let else_path = path_cond.map_or_else(|| c.not(), |path| Bool::and(ctx, &[path, &c.not()]));
+        let (then_tag, then_value) = nullable_arg_to_z3(vc, then_branch, &mut env.clone(), solver_opt, Some(&then_path))?;
+        let (else_tag, else_value) = nullable_arg_to_z3(vc, else_branch, &mut env.clone(), solver_opt, Some(&else_path))?;
+        return Ok((c.ite(&then_tag, &else_tag), vc.select(&c, &then_value, &else_value)));
+    }
+    let value = match path_cond {
+        Some(path) => eval_under_path(vc, arg, &mut env.clone(), solver_opt, path)?,
+        None => expr_to_z3(vc, arg, env, solver_opt)?,
+    };
+    Ok((Int::from_i64(ctx, option_some_tag(vc.module_env)), value))
+}
+
+/// 文字列リテラルの長さ（UTF-8 のバイト数）、または変数に束縛済みの長さ `len_<name>`。
+/// let の右辺・呼び出しの実引数・ブロックの末尾の式に使い、長さを束縛先へ引き継ぐ
+pub(super) fn string_length<'a>(ctx: &'a Context, expr: &Expr, env: &Env<'a>) -> Option<Int<'a>> {
+    match expr {
+        Expr::StringLit(s) => Some(Int::from_i64(ctx, s.len() as i64)),
+        Expr::Variable(name) => env.get(&format!("len_{}", name)).and_then(|len| len.as_int()),
+        Expr::Block(stmts) => stmts.last().and_then(|last| string_length(ctx, last, env)),
+        _ => None,
+    }
+}
+
+pub(super) fn apply_refinement_constraint<'a>(
+    vc: &VCtx<'a>,
+    solver: &Solver<'a>,
+    var_name: &str,
+    refined: &RefinedType,
+    global_env: &mut Env<'a>
+) -> MumeiResult<()> {
+    let ctx = vc.ctx;
+    // Type System 2.0: ベース型に基づいて変数を生成（理論拡張の基底型はその表現型で扱う）
+    let var_z3: Dynamic = match crate::theory::carrier_type(&refined._base_type).unwrap_or(refined._base_type.as_str()) {
+        "f64" => Float::new_const(ctx, vc.sym(var_name), 11, 53).into(),
+        "bool" => Bool::new_const(ctx, vc.sym(var_name)).into(),
+        "u64" => {
+            let v = Int::new_const(ctx, vc.sym(var_name));
+            solver.assert(&v.ge(&Int::from_i64(ctx, 0)));
+            v.into()
+        },
+        _ => Int::new_const(ctx, vc.sym(var_name)).into(),
+    };
+
+    global_env.insert(var_name.to_string(), var_z3.clone());
+
+    let mut local_env = global_env.clone();
+    local_env.insert(refined.operand.clone(), var_z3);
+    // Str の述語 `len(v) > 0` の v の長さは変数自身の長さ len_<var>
+    if refined._base_type == "Str" {
+        let len_name = format!("len_{}", var_name);
+        let len_var = Int::new_const(ctx, vc.sym(&len_name));
+        solver.assert(&len_var.ge(&Int::from_i64(ctx, 0)));
+        global_env.insert(len_name, len_var.clone().into());
+        local_env.insert(format!("len_{}", refined.operand), len_var.into());
+    }
+
+    let predicate_ast = parse_expression(&refined.predicate_raw)?;
+    let predicate_z3 = expr_to_z3(vc, &predicate_ast, &mut local_env, None)?
+        .as_bool().ok_or(MumeiError::TypeError(format!("Predicate for {} must be boolean", refined.name)))?;
+
+    solver.assert(&predicate_z3);
+    Ok(())
+}
+
+/// 精緻型の配列パラメータ（`xs: [Nat]`）の要素制約を量化して仮定する:
+/// `∀i. 0 <= i < len_xs ⇒ P(xs[i])`。要素は ArrayAccess と同じ Z3 配列から select するので、
+/// 本体や ensures の `xs[k]` に述語がそのまま効く。len_xs は呼び出し元で env に登録済みであること
+pub(super) fn apply_element_refinement<'a>(
+    vc: &VCtx<'a>,
+    solver: &Solver<'a>,
+    array_name: &str,
+    refined: &RefinedType,
+    global_env: &Env<'a>
+) -> MumeiResult<()> {
+    let ctx = vc.ctx;
+    let base = crate::theory::carrier_type(&refined._base_type).unwrap_or(refined._base_type.as_str());
+    if base == "Str" || base == "bool" {
+        return Err(MumeiError::TypeError(format!(
+            "Array parameter '{}': element type '{}' is a refinement of {}; only i64 / u64 / f64 refinements are supported as array elements",
+            array_name, refined.name, base
+        )));
+    }
+    let len_name = format!("len_{}", array_name);
+    let len = global_env.get(&len_name)
+        .and_then(|len| len.as_int())
+        .unwrap_or_else(|| Int::new_const(ctx, vc.sym(&len_name)));
+    let i = Int::new_const(ctx, vc.sym(&vc.fresh_bound("i")));
+    let elem = vc.array(array_name).select(&i);
+
+    let mut local_env = global_env.clone();
+    local_env.insert(refined.operand.clone(), elem.clone());
+    let predicate_ast = parse_expression(&refined.predicate_raw)?;
+    let mut element_facts = vec![expr_to_z3(vc, &predicate_ast, &mut local_env, None)?
+        .as_bool().ok_or(MumeiError::TypeError(format!("Predicate for {} must be boolean", refined.name)))?];
+    // u64 の要素はスカラーの u64 引数と同じく非負
+    if base == "u64" {
+        if let Some(elem) = elem.as_int() {
+            element_facts.push(elem.ge(&Int::from_i64(ctx, 0)));
+        }
+    }
+
+    let in_range = Bool::and(ctx, &[&i.ge(&Int::from_i64(ctx, 0)), &i.lt(&len)]);
+    let facts: Vec<&Bool> = element_facts.iter().collect();
+    solver.assert(&z3::ast::forall_const(ctx, &[&i], &[], &in_range.implies(&Bool::and(ctx, &facts))));
+    Ok(())
+}
+
+/// エラー表示用の `where:` 節の行（節がなければ空文字列）。requires の行の直前に置く
+pub(super) fn where_line(atom: &Atom) -> String {
+    atom.where_clause.as_ref()
+        .map(|clause| format!("\n  Where: {}", crate::parser::indent_contract(clause, "         ")))
+        .unwrap_or_default()
+}
+
+/// 反例・エラー表示用のパターン表記（例: "Some(x)", "_"）
+fn pattern_label(pattern: &Pattern) -> String {
+    match pattern {
+        Pattern::Wildcard => "_".to_string(),
+        Pattern::Literal(n) => n.to_string(),
+        Pattern::Variable(name) => name.clone(),
+        Pattern::Variant { variant_name, fields } if fields.is_empty() => variant_name.clone(),
+        Pattern::Variant { variant_name, fields } => {
+            let fields: Vec<String> = fields.iter().map(pattern_label).collect();
+            format!("{}({})", variant_name, fields.join(", "))
+        }
+    }
+}
+
+/// 経路条件 path_cond を仮定して expr を評価する（if の分岐・match のアーム）。
+/// 評価中の義務は path_cond の下で証明され、solver に追加された事実（呼び出し結果の ensures 等）は
+/// pop 後に「path_cond ⇒ 事実」として戻す。外側の経路条件は arm_stack で反例の経路判定に使う。
+fn eval_under_path<'a>(
+    vc: &VCtx<'a>,
+    expr: &Expr,
+    env: &mut Env<'a>,
+    solver_opt: Option<&Solver<'a>>,
+    path_cond: &Bool<'a>,
+) -> DynResult<'a> {
+    vc.arm_stack.borrow_mut().push(path_cond.clone());
+    let value = match solver_opt {
+        Some(solver) => {
+            let before = solver.get_assertions().len();
+            solver.push();
+            solver.assert(path_cond);
+            let value = expr_to_z3(vc, expr, env, solver_opt);
+            let facts: Vec<Bool> = solver.get_assertions().into_iter().skip(before + 1).collect();
+            solver.pop(1);
+            for fact in &facts {
+                solver.assert(&path_cond.implies(fact));
+            }
+            value
+        }
+        None => expr_to_z3(vc, expr, env, None),
+    };
+    vc.arm_stack.borrow_mut().pop();
+    value
+}
+
+/// ループ本体で代入される外側の変数を出現順に集める。
+/// local は本体内の let・パターンで束縛された名前（そのスコープ内の代入は外側に届かない）。
+fn loop_assigned(expr: &Expr, local: &mut Vec<String>, out: &mut Vec<String>) {
+    match expr {
+        Expr::Assign { var, value } => {
+            loop_assigned(value, local, out);
+            if !local.contains(var) && !out.contains(var) {
+                out.push(var.clone());
+            }
+        },
+        Expr::Let { var, value } => {
+            loop_assigned(value, local, out);
+            local.push(var.clone());
+        },
+        Expr::Block(stmts) => {
+            let mark = local.len();
+            for stmt in stmts {
+                loop_assigned(stmt, local, out);
+            }
+            local.truncate(mark);
+        },
+        Expr::IfThenElse { cond, then_branch, else_branch } => {
+            loop_assigned(cond, local, out);
+            for branch in [then_branch, else_branch] {
+                let mark = local.len();
+                loop_assigned(branch, local, out);
+                local.truncate(mark);
+            }
+        },
+        Expr::While { cond, body, .. } => {
+            loop_assigned(cond, local, out);
+            let mark = local.len();
+            loop_assigned(body, local, out);
+            local.truncate(mark);
+        },
+        Expr::Match { target, arms } => {
+            loop_assigned(target, local, out);
+            for arm in arms {
+                let mark = local.len();
+                pattern_variables(&arm.pattern, local);
+                if let Some(guard) = &arm.guard {
+                    loop_assigned(guard, local, out);
+                }
+                loop_assigned(&arm.body, local, out);
+                local.truncate(mark);
+            }
+        },
+        Expr::BinaryOp(l, _, r) => {
+            loop_assigned(l, local, out);
+            loop_assigned(r, local, out);
+        },
+        Expr::Call(_, args) => {
+            for arg in args {
+                loop_assigned(arg, local, out);
+            }
+        },
+        Expr::StructInit { fields, .. } => {
+            for (_, value) in fields {
+                loop_assigned(value, local, out);
+            }
+        },
+        Expr::ArrayAccess(_, index) => loop_assigned(index, local, out),
+        Expr::FieldAccess(base, _) => loop_assigned(base, local, out),
+        Expr::Acquire { body, .. } | Expr::Async { body } => {
+            let mark = local.len();
+            loop_assigned(body, local, out);
+            local.truncate(mark);
+        },
+        Expr::Await { expr } | Expr::RefArg { expr, .. } => loop_assigned(expr, local, out),
+        Expr::Assume { cond, .. } => loop_assigned(cond, local, out),
+        Expr::Number(_) | Expr::Float(_) | Expr::StringLit(_) | Expr::Bool(_) | Expr::Variable(_) => {},
+    }
+}
+
+/// value と同じソートの新しい記号（構造体の値ならフィールドごとに新しい記号を持つ値）
+fn fresh_like<'a>(vc: &VCtx<'a>, value: &Dynamic<'a>, base: &str) -> Option<Dynamic<'a>> {
+    if let Some(sv) = vc.struct_value(value) {
+        let mut fields = Vec::new();
+        for (field, field_value) in &sv.fields {
+            fields.push((field.clone(), fresh_like(vc, field_value, &format!("{}_{}", base, field))?));
+        }
+        return Some(vc.new_struct_value(&sv.type_name, fields));
+    }
+    let name = vc.sym(&vc.fresh(base));
+    if value.as_int().is_some() {
+        Some(Int::new_const(vc.ctx, name).into())
+    } else if value.as_bool().is_some() {
+        Some(Bool::new_const(vc.ctx, name).into())
+    } else if value.as_float().is_some() {
+        Some(Float::new_const(vc.ctx, name, 11, 53).into())
+    } else {
+        None
+    }
+}
+
+/// ループで代入される変数を新しい記号に置き換える（env にない名前・扱えないソートはそのまま）
+fn havoc_loop_targets<'a>(vc: &VCtx<'a>, targets: &[String], env: &mut Env<'a>, tag: &str) {
+    for name in targets {
+        let fresh = env.get(name).and_then(|value| fresh_like(vc, value, &format!("{}_{}", name, tag)));
+        if let Some(value) = fresh {
+            env.insert(name.clone(), value);
+        }
+    }
+}
+
+/// 義務のラベルに埋め込む式のテキスト（表示できない式は "…"）
+pub(super) fn expr_label(expr: &Expr) -> String {
+    crate::printer::show(expr).unwrap_or_else(|| "…".to_string())
+}
+
+/// requires の && で結ばれた `forall(var, start, end, cond)` のうち、cond が `array[var]` を
+/// 参照するものの (var, start, end) を集める
+fn quantified_ranges<'e>(expr: &'e Expr, array: &str, out: &mut Vec<(&'e str, &'e Expr, &'e Expr)>) {
+    match expr {
+        Expr::BinaryOp(l, Op::And, r) => {
+            quantified_ranges(l, array, out);
+            quantified_ranges(r, array, out);
+        }
+        Expr::Call(name, args) if name == "forall" => {
+            if let [Expr::Variable(var), start, end, cond] = args.as_slice() {
+                if indexes_with(cond, array, var) {
+                    out.push((var, start, end));
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// 式が `array[var]`（添字が量化変数そのもの）を含むか
+fn indexes_with(expr: &Expr, array: &str, var: &str) -> bool {
+    expr.any(|e| matches!(e, Expr::ArrayAccess(name, index) if name == array && matches!(index.as_ref(), Expr::Variable(v) if v == var)))
+}
+
+/// 範囲外アクセスの義務が証明できなかったとき、requires の量化子がこの配列を制約する範囲
+/// [start, end) の外に添字が出うるなら、量化子の範囲を疑う注記を返す。
+/// 「範囲外かつ量化子の範囲外」の反例があるときだけ出す（範囲内の添字の失敗は len の不足が原因）
+fn quantifier_range_note<'a>(
+    vc: &VCtx<'a>,
+    solver: &Solver<'a>,
+    safe: &Bool<'a>,
+    env: &Env<'a>,
+    array: &str,
+    index_expr: &Expr,
+    idx: &Int<'a>,
+) -> Option<String> {
+    let atom = vc.module_env.get_atom(vc.scope)?;
+    let requires = parse_expression(&atom.requires).ok()?;
+    let mut ranges = Vec::new();
+    quantified_ranges(&requires, array, &mut ranges);
+    ranges.into_iter().find_map(|(var, start, end)| {
+        let mut range_env = env.clone();
+        let lo = expr_to_z3(vc, start, &mut range_env, None).ok()?.as_int()?;
+        let hi = expr_to_z3(vc, end, &mut range_env, None).ok()?.as_int()?;
+        let inside = Bool::and(vc.ctx, &[&idx.ge(&lo), &idx.lt(&hi)]);
+        let model = violation_model(vc, solver, &Bool::or(vc.ctx, &[safe, &inside]))?;
+        let index_value = model.eval(idx, true).and_then(|v| v.as_i64());
+        let shown = match index_value {
+            Some(value) if expr_label(index_expr) != value.to_string() => format!("{} (= {} in the counterexample)", expr_label(index_expr), value),
+            _ => expr_label(index_expr),
+        };
+        Some(format!(
+            "note: requires only constrains {}[{}] for {} <= {} < {}, but this access uses index {} — did you mean {} <= {} or len({})?",
+            array, var, expr_label(start), var, expr_label(end), shown, var, expr_label(end), array
+        ))
+    })
+}
+
+/// match アームの body で失敗した義務のエラーに、どのアームかを付記する
+fn in_match_arm(error: MumeiError, arm: usize, match_no: usize, scope: &str) -> MumeiError {
+    let location = if scope.is_empty() {
+        format!("in arm {} of match #{}", arm, match_no)
+    } else {
+        format!("in arm {} of match #{} at atom '{}'", arm, match_no, scope)
+    };
+    match error {
+        MumeiError::VerificationError(m) => MumeiError::VerificationError(format!("{}: {}", location, m)),
+        MumeiError::VerificationFailure(kind, m) => MumeiError::VerificationFailure(kind, format!("{}: {}", location, m)),
+        MumeiError::CodegenError(m) => MumeiError::CodegenError(format!("{}: {}", location, m)),
+        MumeiError::TypeError(m) => MumeiError::TypeError(format!("{}: {}", location, m)),
+    }
+}
+
+/// let / 代入の対象が予約名（result や len_ 等の内部シンボル）なら拒否する。
+/// env はフラットなマップなので、許すと配列長や生存フラグを上書きして偽の性質が証明できてしまう。
+fn reject_reserved_target(var: &str, action: &str) -> MumeiResult<()> {
+    match crate::parser::reserved_name(var) {
+        Some(reason) => Err(MumeiError::VerificationError(format!("Cannot {} '{}': {}", action, var, reason))),
+        None => Ok(()),
+    }
+}
+
+/// 浮動小数点の四則演算を、生成コードと同じ最近接偶数丸め（RNE）の IEEE 754 倍精度演算として表す。
+/// z3 crate 0.12 の Float API には RNE の演算がないため、結果とオペランドを新しい定数にして
+/// SMT-LIB の `(fp.add RNE ..)` 等による定義を vc.float_defs に記録し、check の前に solver に加える
+fn float_arith_rne<'a>(vc: &VCtx<'a>, op: &Op, l: &Float<'a>, r: &Float<'a>) -> MumeiResult<Float<'a>> {
+    let ctx = vc.ctx;
+    let function = match op {
+        Op::Add => "fp.add",
+        Op::Sub => "fp.sub",
+        Op::Mul => "fp.mul",
+        Op::Div => "fp.div",
+        _ => return Err("Invalid float op".into()),
+    };
+    let result_name = vc.sym(&vc.fresh("float_arith"));
+    let lhs_name = vc.sym(&vc.fresh("float_lhs"));
+    let rhs_name = vc.sym(&vc.fresh("float_rhs"));
+    let operands = vec![
+        Float::new_const(ctx, lhs_name.as_str(), 11, 53)._eq(l),
+        Float::new_const(ctx, rhs_name.as_str(), 11, 53)._eq(r),
+    ];
+    // 同じ名前・ソートで宣言した定数は Rust 側で作った定数と同じ項になる
+    let definition = format!(
+        "(declare-const |{r}| (_ FloatingPoint 11 53))\n\
+         (declare-const |{a}| (_ FloatingPoint 11 53))\n\
+         (declare-const |{b}| (_ FloatingPoint 11 53))\n\
+         (assert (= |{r}| ({f} RNE |{a}| |{b}|)))",
+        r = result_name, a = lhs_name, b = rhs_name, f = function
+    );
+    vc.float_defs.borrow_mut().push((operands, definition));
+    Ok(Float::new_const(ctx, result_name.as_str(), 11, 53))
+}
+
+/// approx_eq の許容誤差（第 3 引数）。正の数値リテラルでなければならない
+fn approx_tolerance(args: &[Expr]) -> MumeiResult<f64> {
+    if args.len() != 3 {
+        return Err(MumeiError::TypeError(format!("approx_eq() expects 3 arguments ({} given)", args.len())));
+    }
+    let eps = match &args[2] {
+        Expr::Float(f) => *f,
+        Expr::Number(n) => *n as f64,
+        _ => f64::NAN,
+    };
+    if eps.is_finite() && eps > 0.0 {
+        Ok(eps)
+    } else {
+        Err(MumeiError::TypeError("approx_eq(): tolerance (eps) must be a positive numeric literal".into()))
+    }
+}
+
+// =============================================================
+// 量化子の束縛変数のスコープ
+// =============================================================
+
+/// pattern が変数 name を束縛するか
+fn pattern_binds(pattern: &Pattern, name: &str) -> bool {
+    match pattern {
+        Pattern::Variable(v) => v == name,
+        Pattern::Variant { fields, .. } => fields.iter().any(|f| pattern_binds(f, name)),
+        Pattern::Wildcard | Pattern::Literal(_) => false,
+    }
+}
+
+/// 量化子の本体 expr 中の束縛変数 var への参照を内部名 fresh に置き換える。
+/// 内側で var を束縛し直す箇所（同名の量化子・let・match パターン）から先は置き換えない。
+pub(super) fn rename_bound(expr: &Expr, var: &str, fresh: &str) -> Expr {
+    substitute_bound(expr, var, &Expr::Variable(fresh.to_string()))
+}
+
+/// expr 中の変数 var への参照を式 value に置き換える（スコープの扱いは rename_bound と同じ）。
+/// トランスパイラが match パターンの束縛変数をフィールド参照に展開するのにも使う。
+pub(crate) fn substitute_bound(expr: &Expr, var: &str, value: &Expr) -> Expr {
+    let mut result = expr.clone();
+    substitute_in_place(&mut result, var, value);
+    result
+}
+
+fn substitute_in_place(expr: &mut Expr, var: &str, value: &Expr) {
+    expr.walk_mut(|e| match e {
+        Expr::Variable(v) if v == var => {
+            *e = value.clone();
+            false
+        }
+        // var を let で束縛し直したブロックは、その let（の値）までを置き換える
+        Expr::Block(stmts) => match stmts.iter().position(|s| matches!(s, Expr::Let { var: v, .. } if v == var)) {
+            Some(shadow) => {
+                for stmt in &mut stmts[..=shadow] {
+                    substitute_in_place(stmt, var, value);
+                }
+                false
+            }
+            None => true,
+        },
+        // 同じ名前を束縛し直す内側の量化子は、範囲式だけが外側のスコープに属する
+        Expr::Call(name, args) if matches!(name.as_str(), "forall" | "exists")
+            && matches!(args.first(), Some(Expr::Variable(v)) if v == var) =>
+        {
+            for (k, arg) in args.iter_mut().enumerate() {
+                if k != 0 && k != 3 {
+                    substitute_in_place(arg, var, value);
+                }
+            }
+            false
+        }
+        // var を束縛するパターンのアームは置き換えない
+        Expr::Match { target, arms } if arms.iter().any(|arm| pattern_binds(&arm.pattern, var)) => {
+            substitute_in_place(target, var, value);
+            for arm in arms.iter_mut().filter(|arm| !pattern_binds(&arm.pattern, var)) {
+                if let Some(guard) = &mut arm.guard {
+                    substitute_in_place(guard, var, value);
+                }
+                substitute_in_place(&mut arm.body, var, value);
+            }
+            false
+        }
+        _ => true,
+    });
+}
+
+/// 評価済みのオペランドに二項演算を適用する（expr は演算全体。ゼロ除算の義務の表示に使う）
+fn binary_op_to_z3<'a>(
+    vc: &VCtx<'a>,
+    expr: &Expr,
+    op: &Op,
+    l: Dynamic<'a>,
+    r: Dynamic<'a>,
+    solver_opt: Option<&Solver<'a>>
+) -> DynResult<'a> {
+    let ctx = vc.ctx;
+    // await していない future ハンドルは値ではない（ensures も仮定されていない）
+    if let Some(callee) = vc.future_callee(&l).or_else(|| vc.future_callee(&r)) {
+        return Err(MumeiError::TypeError(format!(
+            "Unawaited future: the result of async atom '{}' is used in an arithmetic or comparison expression; await it first (e.g. `let v = await {}(...);`)",
+            callee, callee
+        )));
+    }
+
+    // 浮動小数点か整数かで Z3 の AST メソッドを使い分ける
+    if l.as_float().is_some() || r.as_float().is_some() {
+        // 浮動小数点の算術は実行時と同じ最近接偶数丸めの FP 演算としてモデル化する
+        let lf = l.as_float().unwrap_or(Float::from_f64(ctx, 0.0));
+        let rf = r.as_float().unwrap_or(Float::from_f64(ctx, 0.0));
+        match op {
+            Op::Gt  => Ok(lf.gt(&rf).into()),
+            Op::Lt  => Ok(lf.lt(&rf).into()),
+            Op::Ge  => Ok(lf.ge(&rf).into()),
+            Op::Le  => Ok(lf.le(&rf).into()),
+            Op::Eq  => Ok(lf._eq(&rf).into()),
+            Op::Neq => Ok(lf._eq(&rf).not().into()),
+            Op::Add | Op::Sub | Op::Mul | Op::Div => Ok(float_arith_rne(vc, op, &lf, &rf)?.into()),
+            _ => Err("Invalid float op".into()),
+        }
+    } else {
+        // Boolean 演算子は as_int() の前に処理する（オペランドが Bool のため）
+        match op {
+            Op::And => {
+                let lb = l.as_bool().ok_or("Expected bool for &&")?;
+                let rb = r.as_bool().ok_or("Expected bool for &&")?;
+                return Ok(Bool::and(ctx, &[&lb, &rb]).into());
+            },
+            Op::Or => {
+                let lb = l.as_bool().ok_or("Expected bool for ||")?;
+                let rb = r.as_bool().ok_or("Expected bool for ||")?;
+                return Ok(Bool::or(ctx, &[&lb, &rb]).into());
+            },
+            Op::Implies => {
+                let lb = l.as_bool().ok_or("Expected bool for =>")?;
+                let rb = r.as_bool().ok_or("Expected bool for =>")?;
+                return Ok(lb.implies(&rb).into());
+            },
+            // bool 同士の比較（`flag == true`）。片方だけ bool なら整数との比較はできない
+            Op::Eq | Op::Neq if l.as_bool().is_some() || r.as_bool().is_some() => {
+                let lb = l.as_bool().ok_or("Expected bool on both sides of a boolean comparison")?;
+                let rb = r.as_bool().ok_or("Expected bool on both sides of a boolean comparison")?;
+                let eq = lb._eq(&rb);
+                return Ok(if *op == Op::Eq { eq.into() } else { eq.not().into() });
+            },
+            _ => {}
+        }
+        let li = l.as_int().ok_or("Expected int")?;
+        let ri = r.as_int().ok_or("Expected int")?;
+        match op {
+            Op::Add => Ok((&li + &ri).into()),
+            Op::Sub => Ok((&li - &ri).into()),
+            Op::Mul => Ok((&li * &ri).into()),
+            Op::Div => {
+                if let Some(solver) = solver_opt {
+                    let non_zero = ri._eq(&Int::from_i64(ctx, 0)).not();
+                    if !discharge(vc, solver, ObligationKind::DivZero, &non_zero, || format!("divisor of {}", expr_label(expr))) {
+                        return Err(MumeiError::failure(FailureKind::DivisionByZero, "Potential division by zero."));
+                    }
+                }
+                Ok((&li / &ri).into())
+            },
+            Op::Gt  => Ok(li.gt(&ri).into()),
+            Op::Lt  => Ok(li.lt(&ri).into()),
+            Op::Ge  => Ok(li.ge(&ri).into()),
+            Op::Le  => Ok(li.le(&ri).into()),
+            Op::Eq  => Ok(li._eq(&ri).into()),
+            Op::Neq => Ok(li._eq(&ri).not().into()),
+            _ => Err(MumeiError::VerificationError(format!("Unsupported int operator {:?}", op))),
+        }
+    }
+}
+
+pub(super) fn expr_to_z3<'a>(
+    vc: &VCtx<'a>,
+    expr: &Expr,
+    env: &mut Env<'a>,
+    solver_opt: Option<&Solver<'a>>
+) -> DynResult<'a> {
+    let ctx = vc.ctx;
+    match expr {
+        Expr::Number(n) => Ok(Int::from_i64(ctx, *n).into()),
+        Expr::Float(f) => Ok(Float::from_f64(ctx, *f).into()),
+        // Str は長さだけを推論するので、値は内容ごとの未解釈の定数にする（同じリテラルは等しい）。
+        // 整数として算術・比較に混ざらないよう、Str の誤用は事前に crate::ast::str_misuse が型エラーにする
+        Expr::StringLit(s) => Ok(Int::new_const(ctx, format!("__str_{:?}", s)).into()),
+        Expr::Bool(b) => Ok(Bool::from_bool(ctx, *b).into()),
+        Expr::Variable(name) => {
+            Ok(env.get(name).cloned().unwrap_or_else(|| Int::new_const(ctx, vc.sym(&name)).into()))
+        },
+        Expr::Call(name, args) => {
+            match name.as_str() {
+                // =============================================================
+                // ensures / invariant 内の forall/exists 量化子サポート
+                // =============================================================
+                // forall(var, start, end, condition) → Z3 ∀ var ∈ [start, end). condition
+                // exists(var, start, end, condition) → Z3 ∃ var ∈ [start, end). condition
+                //
+                // これにより ensures: forall(i, 0, result - 1, arr[i] <= arr[i+1])
+                // のようなソート済み不変量を事後条件として記述・検証できる。
+                "forall" | "exists" => {
+                    if args.len() != 4 {
+                        return Err(MumeiError::VerificationError(
+                            format!("{}() requires exactly 4 arguments: (var, start, end, condition)", name)
+                        ));
+                    }
+                    // 第1引数: 束縛変数名
+                    let var_name = match &args[0] {
+                        Expr::Variable(v) => v.clone(),
+                        _ => return Err(MumeiError::VerificationError(
+                            format!("{}(): first argument must be a variable name", name)
+                        )),
+                    };
+
+                    // 第2引数: 範囲の開始
+                    let start_z3 = expr_to_z3(vc, &args[1], env, None)?
+                        .as_int().ok_or(MumeiError::TypeError(
+                            format!("{}(): start must be integer", name)
+                        ))?;
+
+                    // 第3引数: 範囲の終了
+                    let end_z3 = expr_to_z3(vc, &args[2], env, None)?
+                        .as_int().ok_or(MumeiError::TypeError(
+                            format!("{}(): end must be integer", name)
+                        ))?;
+
+                    // 束縛変数は一意な内部シンボルにし、condition 中の参照をその名前に置き換えて評価する。
+                    // 同名のパラメータ（start / end 中の参照を含む）は量化子に捕獲されない
+                    let bound_name = vc.fresh_bound(&var_name);
+                    let bound_var = Int::new_const(ctx, vc.sym(&bound_name));
+                    env.insert(bound_name.clone(), bound_var.clone().into());
+
+                    let range_cond = Bool::and(ctx, &[
+                        &bound_var.ge(&start_z3),
+                        &bound_var.lt(&end_z3),
+                    ]);
+
+                    let condition = rename_bound(&args[3], &var_name, &bound_name);
+                    let condition_z3 = expr_to_z3(vc, &condition, env, None);
+                    env.remove(&bound_name);
+                    let condition_z3 = condition_z3?
+                        .as_bool().ok_or(MumeiError::TypeError(
+                            format!("{}(): condition must be boolean", name)
+                        ))?;
+
+                    let quantifier_expr = if name == "forall" {
+                        // ∀ var ∈ [start, end). condition
+                        z3::ast::forall_const(ctx, &[&bound_var], &[], &range_cond.implies(&condition_z3))
+                    } else {
+                        // ∃ var ∈ [start, end). condition
+                        z3::ast::exists_const(ctx, &[&bound_var], &[], &Bool::and(ctx, &[&range_cond, &condition_z3]))
+                    };
+
+                    Ok(quantifier_expr.into())
+                },
+                "len" => {
+                    // len(arr_name) → 配列名（Str の変数名）に紐づくシンボリック長を返す
+                    // len_<name> >= 0 の制約を自動付与。束縛済みの長さ（精緻型の述語変数・文字列の let）があればそれを使う
+                    if let Some(length) = args.first().and_then(|arg| string_length(ctx, arg, env)) {
+                        return Ok(length.into());
+                    }
+                    let arr_name = if !args.is_empty() {
+                        if let Expr::Variable(name) = &args[0] { name.clone() } else { "arr".to_string() }
+                    } else { "arr".to_string() };
+                    Ok(length_symbol(vc, &arr_name, env, solver_opt).into())
+                },
+                "sqrt" => {
+                    // Z3 0.12 の Float には sqrt メソッドがないため、
+                    // シンボリック変数として扱い、sqrt(x) >= 0 の制約を付与
+                    let _val = expr_to_z3(vc, &args[0], env, solver_opt)?;
+                    let result = Float::new_const(ctx, vc.sym("sqrt_result"), 11, 53);
+                    if let Some(solver) = solver_opt {
+                        let zero = Float::from_f64(ctx, 0.0);
+                        solver.assert(&result.ge(&zero));
+                    }
+                    Ok(result.into())
+                },
+                "approx_eq" => {
+                    // approx_eq(a, b, eps)（`a ~= b within eps`）: |a - b| <= eps を FP 演算で表す。
+                    // NaN を含む比較は偽になり、実行時の fabs + 比較と一致する
+                    let eps = approx_tolerance(args)?;
+                    let operand = |v: Dynamic<'a>, i: usize| v.as_float().ok_or_else(|| MumeiError::TypeError(format!(
+                        "approx_eq(): argument {} must be a float (f64)", i + 1
+                    )));
+                    let a = operand(expr_to_z3(vc, &args[0], env, solver_opt)?, 0)?;
+                    let b = operand(expr_to_z3(vc, &args[1], env, solver_opt)?, 1)?;
+                    let diff = float_arith_rne(vc, &Op::Sub, &a, &b)?;
+                    Ok(Bool::and(ctx, &[
+                        &diff.le(&Float::from_f64(ctx, eps)),
+                        &diff.ge(&Float::from_f64(ctx, -eps)),
+                    ]).into())
+                },
+                // =============================================================
+                // nullable パラメータ（`x: T?`）のヘルパー述語と値アクセス
+                // =============================================================
+                // is_some(x) / is_none(x) は tag の比較、value(x)（`x!`）は値シンボルを返す。
+                // value(x) は本体中では「この時点で x が Some であること」を証明義務とする。
+                "is_some" | "is_none" | "value" if nullable_arg(args, env).is_some() => {
+                    let param = nullable_arg(args, env).unwrap_or_default().to_string();
+                    let tag = expr_to_z3(vc, &args[0], env, solver_opt)?
+                        .as_int().ok_or(MumeiError::TypeError(format!("nullable '{}' must be an Option tag", param)))?;
+                    let present = tag._eq(&Int::from_i64(ctx, option_some_tag(vc.module_env)));
+                    match name.as_str() {
+                        "is_some" => Ok(present.into()),
+                        "is_none" => Ok(present.not().into()),
+                        _ => {
+                            if let Some(solver) = solver_opt {
+                                if !discharge(vc, solver, ObligationKind::Presence, &present, || format!("presence of {}!", param)) {
+                                    return Err(MumeiError::failure(FailureKind::Presence, format!(
+                                        "Presence obligation for '{}' not proven: '{}!' (value({})) may be None here; guard it with is_some({})",
+                                        param, param, param, param
+                                    )));
+                                }
+                            }
+                            Ok(env[&nullable_value_name(&param)].clone())
+                        }
+                    }
+                },
+                // =============================================================
+                // old(x): ensures 中でパラメータ x の入口の値を参照する
+                // =============================================================
+                // body で代入されたパラメータの ensures は事後の値を指すので、入力との関係は old(x) で書く
+                // （例: body: { x = x * 2; x } / ensures: result == old(x) * 2）。
+                // 参照先は bind_old_values が ensures の env に登録した値で、呼び出し元では実引数の値になる
+                "old" => {
+                    let param = match args.as_slice() {
+                        [Expr::Variable(param)] => param,
+                        _ => return Err(MumeiError::TypeError(
+                            "old() takes exactly one parameter name, e.g. old(x)".to_string()
+                        )),
+                    };
+                    if !env.contains_key(OLD_SCOPE) {
+                        return Err(MumeiError::TypeError(format!(
+                            "old({}) can only be used in ensures: it refers to the value of parameter '{}' on entry to the atom",
+                            param, param
+                        )));
+                    }
+                    env.get(&old_value_name(param)).cloned().ok_or_else(|| MumeiError::TypeError(format!(
+                        "old({}): '{}' is not a parameter of the atom; old() can only refer to parameters",
+                        param, param
+                    )))
+                },
+                "cast_to_int" => {
+                    // Z3 0.12 では Float->Int 直接変換がないため、シンボリック整数を返す
+                    let _val = expr_to_z3(vc, &args[0], env, solver_opt)?;
+                    Ok(Int::new_const(ctx, vc.sym("cast_result")).into())
+                }
+                _ => {
+                    // ユーザー定義関数呼び出し: 契約による検証（Compositional Verification）
+                    // 呼び出し先の requires を現在のコンテキストで証明し、
+                    // 成功すれば ensures を事実として追加する
+                    //
+                    // FQN dot-notation サポート:
+                    // "math.add" → "math::add" として ModuleEnv から解決する。
+                    // これにより `math.add(x, y)` と `math::add(x, y)` の両方が動作する。
+                    let fqn_name = name.replace('.', "::");
+                    let resolved_callee = vc.module_env.get_atom(name).cloned()
+                        .or_else(|| vc.module_env.get_atom(&fqn_name).cloned());
+                    if let Some(callee) = resolved_callee {
+                        // 引数を評価（nullable 仮引数には実引数の tag を渡し、値は nullable_vals で引き継ぐ）
+                        let mut arg_vals = Vec::new();
+                        let mut nullable_vals = Vec::new();
+                        for (i, arg) in args.iter().enumerate() {
+                            let nullable = callee.params.get(i)
+                                .and_then(|p| p.type_name.as_deref())
+                                .is_some_and(|t| crate::ast::option_inner_type(t).is_some());
+                            if nullable {
+                                let (tag, value) = nullable_arg_to_z3(vc, arg, env, solver_opt, None)?;
+                                arg_vals.push(tag.into());
+                                nullable_vals.push(Some(value));
+                            } else {
+                                arg_vals.push(expr_to_z3(vc, arg, env, solver_opt)?);
+                                nullable_vals.push(None);
+                            }
+                        }
+
+                        // 仮引数名と実引数値の対応を構築（呼び出し元の old(...) の参照先は引き継がない）
+                        let mut call_env = env.clone();
+                        clear_old_values(&mut call_env);
+                        for (i, param) in callee.params.iter().enumerate() {
+                            if let Some(val) = arg_vals.get(i) {
+                                call_env.insert(param.name.clone(), val.clone());
+                            }
+                            // nullable 実引数はその値も仮引数側の値シンボルへ引き継ぐ
+                            call_env.remove(&nullable_value_name(&param.name));
+                            if let Some(Some(value)) = nullable_vals.get(i) {
+                                call_env.insert(nullable_value_name(&param.name), value.clone());
+                            }
+                        }
+
+                        // 配列・Str の実引数の長さを仮引数の len_<param> に引き継ぐ。
+                        // 変数の実引数は呼び出し元の len_<arg> そのものを渡すので、requires / ensures の
+                        // len(xs) が呼び出し元の len(ys) と同じシンボルになる
+                        for (i, (param, arg)) in callee.params.iter().zip(args).enumerate() {
+                            let arg = arg.without_ref_marker();
+                            let known = string_length(ctx, arg, env)
+                                .or_else(|| arg_vals.get(i).and_then(|val| vc.call_lengths.borrow().get(&val.to_string()).cloned()));
+                            let length = match (known, arg) {
+                                (Some(length), _) => length,
+                                (None, _) if !has_length(param, vc.module_env) => continue,
+                                (None, Expr::Variable(arg_name)) => length_symbol(vc, arg_name, env, solver_opt),
+                                (None, _) => {
+                                    // 長さの分からない式（配列リテラル・添字アクセスなど）は新しいシンボルで近似する
+                                    vc.warn(format!(
+                                        "length of argument {} of '{}' is unknown; len({}) is unconstrained at this call",
+                                        i + 1, name, param.name
+                                    ));
+                                    let len_var = Int::new_const(ctx, vc.sym(&vc.fresh(&format!("len_arg_{}", param.name))));
+                                    if let Some(solver) = solver_opt {
+                                        solver.assert(&len_var.ge(&Int::from_i64(ctx, 0)));
+                                    }
+                                    len_var
+                                }
+                            };
+                            call_env.insert(format!("len_{}", param.name), length.into());
+                        }
+
+                        // 呼び出し先の精緻型制約を call_env に適用
+                        for (i, param) in callee.params.iter().enumerate() {
+                            if let Some(type_name) = &param.type_name {
+                                if let Some(refined) = vc.module_env.get_type(type_name) {
+                                    // 実引数値を精緻型の述語変数に束縛して制約を検証
+                                    if let Some(val) = arg_vals.get(i) {
+                                        call_env.insert(refined.operand.clone(), val.clone());
+                                    }
+                                    if let Some(length) = call_env.get(&format!("len_{}", param.name)).cloned() {
+                                        call_env.insert(format!("len_{}", refined.operand), length);
+                                    }
+                                }
+                            }
+                        }
+
+                        // requires の検証: 呼び出し元のコンテキストで事前条件が満たされるか
+                        if callee.requires.trim() != "true" {
+                            if let Some(solver) = solver_opt {
+                                let req_ast = parse_expression(&callee.requires)?;
+                                let req_z3 = expr_to_z3(vc, &req_ast, &mut call_env, None)?;
+                                if let Some(req_bool) = req_z3.as_bool() {
+                                    let call_no = vc.call_number(name);
+                                    if !discharge(vc, solver, ObligationKind::CallRequires, &req_bool, || format!("requires of callee {} at call #{}", name, call_no)) {
+                                        // 反例: 呼び出し先の仮引数名で実引数の値を示す
+                                        let symbols: Vec<(String, String, Dynamic)> = callee.params.iter().zip(&arg_vals)
+                                            .map(|(p, val)| (p.name.clone(), p.type_name.clone().unwrap_or_else(|| "i64".to_string()), val.clone()))
+                                            .collect();
+                                        let counterexample = violation_model(vc, solver, &req_bool)
+                                            .map(|model| model_counterexample(&model, &symbols, vc.module_env))
+                                            .unwrap_or_default();
+                                        let failure = format!(
+                                            "Call to '{}': precondition (requires) not satisfied at call site{}\n  Requires: {}",
+                                            name, where_line(&callee), crate::parser::indent_contract(&callee.requires_raw, "            ")
+                                        );
+                                        return Err(MumeiError::failure(FailureKind::Precondition, counterexample.render(&failure, vc.module_env.counterexample_format)));
+                                    }
+                                }
+                            }
+                        }
+
+                        // 自己再帰呼び出し（整礎帰納法）: atom レベルの decreases があれば、
+                        // requires に加えて「測度が 0 以上」かつ「入口の測度より真に小さい」ことを証明してから
+                        // ensures を仮定する。decreases がなければ部分正当性（停止は仮定）のみを示す
+                        let entry_measure = vc.entry_measure.borrow().clone();
+                        if callee.name == vc.scope {
+                            if let (Some(measure), Some(entry), Some(solver)) = (&callee.decreases, entry_measure, solver_opt) {
+                                let at_call = expr_to_z3(vc, &parse_expression(measure)?, &mut call_env, None)?
+                                    .as_int().ok_or(MumeiError::TypeError(format!("decreases measure of atom '{}' must be integer", name)))?;
+                                if !discharge(vc, solver, ObligationKind::Termination, &at_call.ge(&Int::from_i64(ctx, 0)), || format!("decreases {} >= 0 at recursive call to {}", measure, name)) {
+                                    return Err(MumeiError::failure(FailureKind::Termination, format!(
+                                        "Termination check failed: decreases measure `{}` may be negative at recursive call to '{}'",
+                                        measure, name
+                                    )));
+                                }
+                                if !discharge(vc, solver, ObligationKind::Termination, &at_call.lt(&entry), || format!("decreases {} at recursive call to {}", measure, name)) {
+                                    return Err(MumeiError::failure(FailureKind::Termination, format!(
+                                        "Termination check failed: decreases measure `{}` does not strictly decrease at recursive call to '{}'",
+                                        measure, name
+                                    )));
+                                }
+                            }
+                        }
+
+                        // ensures からシンボリック結果を生成し、事後条件を事実として追加。
+                        // 呼び出し先の ensures の old(param) は呼び出し時点の実引数の値を指す
+                        bind_old_values(ctx, &mut call_env, callee.params.iter().map(|p| p.name.as_str()).zip(arg_vals.iter().cloned()));
+                        let result_name = vc.fresh(&format!("call_{}", name));
+                        vc.callees.borrow_mut().entry(callee.name.clone()).or_insert_with(|| callee.clone());
+
+                        // 戻り値のソートは codegen / transpiler と同じ推論（crate::ast::result_kind）で決める
+                        let result_z3: Dynamic = match crate::ast::result_kind(&callee, vc.module_env) {
+                            crate::ast::ResultKind::Float => Float::new_const(ctx, vc.sym(&result_name), 11, 53).into(),
+                            crate::ast::ResultKind::Bool => Bool::new_const(ctx, vc.sym(&result_name)).into(),
+                            crate::ast::ResultKind::Int | crate::ast::ResultKind::Str => Int::new_const(ctx, vc.sym(&result_name)).into(),
+                        };
+
+                        // 配列・Str を返す atom の len(result) は呼び出しごとの長さシンボルにする
+                        // （let で束縛した先や、別の atom の実引数へ call_lengths 経由で引き継ぐ）
+                        if result_z3.as_int().is_some() {
+                            let len_var = Int::new_const(ctx, vc.sym(&format!("len_{}", result_name)));
+                            if let Some(solver) = solver_opt {
+                                solver.assert(&len_var.ge(&Int::from_i64(ctx, 0)));
+                            }
+                            call_env.insert("len_result".to_string(), len_var.clone().into());
+                            vc.call_lengths.borrow_mut().insert(result_z3.to_string(), len_var);
+                        }
+
+                        // 構造体を返す atom（`-> Point`）の結果はフィールドごとのシンボルを持つ構造体の値。
+                        // フィールド制約は呼び出し先の検証で保証済みなので仮定し、ensures の result.x / result_x に束縛する
+                        if let Some(sdef) = crate::ast::struct_return(&callee, vc.module_env) {
+                            let fields = symbolic_struct_fields(vc, sdef, &result_name, env, solver_opt)?;
+                            for (field_name, val) in &fields {
+                                call_env.insert(format!("result_{}", field_name), val.clone());
+                                call_env.insert(format!("__struct_result_{}", field_name), val.clone());
+                            }
+                            vc.register_struct_value(&result_z3, &sdef.name, fields);
+                        }
+
+                        // async atom の呼び出し結果は future ハンドルで、ensures は await した値についての事実。
+                        // ここでは仮定せず、このハンドルを unwrap する Await 式で仮定する
+                        if callee.is_async {
+                            vc.futures.borrow_mut().insert(result_z3.to_string(), PendingFuture { callee: callee.clone(), call_env });
+                        } else {
+                            assume_callee_ensures(vc, &callee, &result_z3, &mut call_env, solver_opt)?;
+                        }
+
+                        // Taint Analysis: 呼び出し先が unverified の場合、
+                        // 戻り値を __tainted_ マーカーで汚染済みとしてマークする。
+                        if callee.trust_level == TrustLevel::Unverified {
+                            let taint_key = format!("__tainted_{}", result_name);
+                            let taint_marker = Bool::from_bool(ctx, true);
+                            env.insert(taint_key, taint_marker.into());
+                        }
+
+                        Ok(result_z3)
+                    } else if let Some((trait_name, method)) = vc.module_env.find_trait_method(name)? {
+                        // トレイトメソッドの直接呼び出し: パラメータ制約（where v != 0 等）を
+                        // 呼び出し箇所の requires 相当の証明義務として検証する
+                        let mut arg_vals = Vec::new();
+                        for arg in args {
+                            arg_vals.push(expr_to_z3(vc, arg, env, solver_opt)?);
+                        }
+                        if let Some(solver) = solver_opt {
+                            for (i, constraint) in method.param_constraints.iter().enumerate() {
+                                if let (Some(constraint), Some(val)) = (constraint, arg_vals.get(i)) {
+                                    let mut constraint_env = env.clone();
+                                    constraint_env.insert("v".to_string(), val.clone());
+                                    let constraint_ast = parse_expression(constraint)?;
+                                    let constraint_z3 = expr_to_z3(vc, &constraint_ast, &mut constraint_env, None)?;
+                                    if let Some(constraint_bool) = constraint_z3.as_bool() {
+                                        if !discharge(vc, solver, ObligationKind::CallRequires, &constraint_bool, || format!("parameter '{}' of trait method {}::{}", trait_param_name(i), trait_name, method.name)) {
+                                            return Err(MumeiError::failure(FailureKind::Precondition, format!(
+                                                "Call to trait method '{}::{}': parameter '{}' must satisfy '{}', but it is not proven at call site",
+                                                trait_name, method.name, trait_param_name(i), constraint
+                                            )));
+                                        }
+                                    }
+                                }
+                            }
+                        }
+
+                        // impl は単相化後に選択されるため、結果は契約を持たないシンボリック値とする
+                        let result_name = vc.fresh(&format!("call_{}", name));
+                        let result_z3: Dynamic = if method.return_type == "bool" {
+                            Bool::new_const(ctx, vc.sym(&result_name)).into()
+                        } else if arg_vals.iter().any(|v| v.as_float().is_some()) {
+                            Float::new_const(ctx, vc.sym(&result_name), 11, 53).into()
+                        } else {
+                            Int::new_const(ctx, vc.sym(&result_name)).into()
+                        };
+                        Ok(result_z3)
+                    } else if let Some(extension) = crate::theory::find_intrinsic(name) {
+                        // 理論拡張が所有する組み込み関数: 翻訳と証明義務は拡張に任せる
+                        let mut arg_vals = Vec::new();
+                        for arg in args {
+                            arg_vals.push(expr_to_z3(vc, arg, env, solver_opt)?);
+                        }
+                        let prove = |goal: &Bool<'a>| {
+                            solver_opt.map_or(true, |solver| discharge(vc, solver, ObligationKind::Theory, goal, || format!("{}() obligation", name)))
+                        };
+                        let call = crate::theory::IntrinsicCall::new(ctx, name, &arg_vals, solver_opt, vc.scope, &prove);
+                        extension.translate_call(&call)
+                    } else {
+                        Err(MumeiError::VerificationError(format!("Unknown function: {}", name)))
+                    }
+                },
+            }
+        },
+        Expr::ArrayAccess(name, index_expr) => {
+            let idx = expr_to_z3(vc, index_expr, env, solver_opt)?
+                .as_int().ok_or(MumeiError::TypeError("Index must be integer".into()))?;
+
+            // 配列名に紐づく長さシンボルを使った境界チェック
+            if let Some(solver) = solver_opt {
+                let len_name = format!("len_{}", name);
+                let len = if let Some(existing) = env.get(&len_name) {
+                    existing.as_int().unwrap_or(Int::new_const(ctx, vc.sym(&len_name)))
+                } else {
+                    let l = Int::new_const(ctx, vc.sym(&len_name));
+                    solver.assert(&l.ge(&Int::from_i64(ctx, 0)));
+                    env.insert(len_name.clone(), l.clone().into());
+                    l
+                };
+                let safe = Bool::and(ctx, &[&idx.ge(&Int::from_i64(ctx, 0)), &idx.lt(&len)]);
+                if !discharge(vc, solver, ObligationKind::Bounds, &safe, || format!("array bounds for {}[{}]", name, expr_label(index_expr))) {
+                    let note = quantifier_range_note(vc, solver, &safe, env, name, index_expr, &idx)
+                        .map(|note| format!("\n  {}", note))
+                        .unwrap_or_default();
+                    return Err(MumeiError::failure(FailureKind::Bounds, format!("Potential Out-of-Bounds on '{}' (index may be < 0 or >= len_{}){}", name, name, note)));
+                }
+            }
+            Ok(vc.array(name).select(&idx))
+        },
+        Expr::BinaryOp(..) => {
+            // 左に連なる連鎖（`a + b + c + ...`）は左端から順に畳み込み、連鎖の長さぶん再帰しない
+            let (leaf, nodes) = expr.left_spine();
+            let mut acc = expr_to_z3(vc, leaf, env, solver_opt)?;
+            for node in nodes {
+                if let Expr::BinaryOp(_, op, right) = node {
+                    let r = expr_to_z3(vc, right, env, solver_opt)?;
+                    acc = binary_op_to_z3(vc, node, op, acc, r, solver_opt)?;
+                }
+            }
+            Ok(acc)
+        },
+        Expr::IfThenElse { cond, then_branch, else_branch } => {
+            // then 側は cond、else 側は !cond を仮定して評価する（パス依存の義務）。
+            // `if n > 0 { 100 / n } else { 0 }` のゼロ除算義務は then 側の仮定で証明される。
+            let c = expr_to_z3(vc, cond, env, solver_opt)?
+                .as_bool().ok_or(MumeiError::TypeError("If condition must be boolean".into()))?;
+            let not_c = c.not();
+            let mut then_env = env.clone();
+            let t = eval_under_path(vc, then_branch, &mut then_env, solver_opt, &c)?;
+            let mut else_env = env.clone();
+            let e = eval_under_path(vc, else_branch, &mut else_env, solver_opt, &not_c)?;
+
+            // 分岐内の代入は ite で合流させる。分岐内で新たに束縛した変数は分岐の外に出さないが、
+            // 内部マーカー（__tainted_ 等）は後続の検査が参照するため引き継ぐ。
+            let outer: Vec<String> = env.keys().cloned().collect();
+            for name in outer {
+                let merged = match (then_env.get(&name), else_env.get(&name)) {
+                    (Some(tv), Some(ev)) if tv == ev => tv.clone(),
+                    (Some(tv), Some(ev)) if tv.get_sort() == ev.get_sort() => c.ite(tv, ev),
+                    _ => continue,
+                };
+                env.insert(name, merged);
+            }
+            for (name, value) in then_env.into_iter().chain(else_env) {
+                if name.starts_with("__") && !env.contains_key(&name) {
+                    env.insert(name, value);
+                }
+            }
+            Ok(vc.select(&c, &t, &e))
+        },
+        Expr::Let { var, value } => {
+            reject_reserved_target(var, "bind")?;
+            // 束縛は env に入れ、囲む Block を抜けるときに外側の値へ戻す（スコープ管理は Block 側で行う）
+            let val = expr_to_z3(vc, value, env, solver_opt)?;
+            let length = string_length(ctx, value, env).or_else(|| vc.call_lengths.borrow().get(&val.to_string()).cloned());
+            if let Some(length) = length {
+                env.insert(format!("len_{}", var), length.into());
+            }
+            env.insert(var.clone(), val.clone());
+            Ok(val)
+        },
+        Expr::Assign { var, value } => {
+            reject_reserved_target(var, "assign to")?;
+            let val = expr_to_z3(vc, value, env, solver_opt)?;
+            env.insert(var.clone(), val.clone());
+            Ok(val)
+        },
+        Expr::Block(stmts) => {
+            // ブロックはスコープを作る: 直下の let が束縛（シャドーイング）した名前は、
+            // ブロックを抜けるときに外側の値へ戻すか取り除く。外側の変数への代入はそのまま残る。
+            let mut outer: Vec<(String, Option<Dynamic>)> = Vec::new();
+            let mut last = Int::from_i64(ctx, 0).into();
+            let mut result = Ok(());
+            for stmt in stmts {
+                if let Expr::Let { var, .. } = stmt {
+                    for name in [var.clone(), format!("len_{}", var)] {
+                        if !outer.iter().any(|(n, _)| *n == name) {
+                            let saved = env.get(&name).cloned();
+                            outer.push((name, saved));
+                        }
+                    }
+                }
+                match expr_to_z3(vc, stmt, env, solver_opt) {
+                    Ok(value) => last = value,
+                    Err(e) => {
+                        result = Err(e);
+                        break;
+                    }
+                }
+            }
+            for (name, saved) in outer.into_iter().rev() {
+                match saved {
+                    Some(value) => { env.insert(name, value); }
+                    None => { env.remove(&name); }
+                }
+            }
+            result.map(|_| last)
+        },
+        Expr::While { cond, invariant, decreases, body } => {
+            // total atom のループは停止性の証明が必須
+            if vc.total && decreases.is_none() {
+                return Err(MumeiError::failure(FailureKind::Totality, format!(
+                    "Totality violation in atom '{}': loop has no `decreases` clause, so termination cannot be proven",
+                    vc.scope
+                )));
+            }
+            // Loop Invariant 検証ロジック
+            // 本体で代入される外側の変数は、任意の反復・ループ後の時点では入口の値を保つとは限らない。
+            // 帰納ステップとループ後の状態では、それらを新しい記号に置き換え（havoc）、invariant だけで制約する。
+            let mut targets = Vec::new();
+            loop_assigned(body, &mut Vec::new(), &mut targets);
+            if let Some(solver) = solver_opt {
+                let inv = expr_to_z3(vc, invariant, env, None)?
+                    .as_bool().ok_or(MumeiError::TypeError("Invariant must be boolean".into()))?;
+
+                // Base case: 現在の env（let で初期化済み）で invariant が成立するか
+                if !discharge(vc, solver, ObligationKind::LoopInvariant, &inv, || format!("loop invariant {} on entry", expr_label(invariant))) {
+                    return Err(MumeiError::failure(FailureKind::LoopInvariant, "Invariant fails initially"));
+                }
+
+                // Inductive step: 任意の反復の先頭の状態（havoc した env）で invariant && cond を仮定する
+                let entry_env = env.clone();
+                havoc_loop_targets(vc, &targets, env, "loop");
+                let inv = expr_to_z3(vc, invariant, env, None)?
+                    .as_bool().ok_or(MumeiError::TypeError("Invariant must be boolean".into()))?;
+                let c = expr_to_z3(vc, cond, env, None)?
+                    .as_bool().ok_or(MumeiError::TypeError("While condition must be boolean".into()))?;
+
+                // Invariant preservation: invariant && cond のもとで body 実行後も invariant が保たれるか
+                // env のスナップショットを保存し、各チェックを独立に行う
+                {
+                    let env_snapshot = env.clone();
+                    solver.push();
+                    solver.assert(&inv);
+                    solver.assert(&c);
+                    expr_to_z3(vc, body, env, Some(solver))?;
+
+                    let inv_after = expr_to_z3(vc, invariant, env, None)?
+                        .as_bool().ok_or(MumeiError::TypeError("Invariant must be boolean".into()))?;
+
+                    if !discharge(vc, solver, ObligationKind::LoopInvariant, &inv_after, || format!("loop invariant {} preserved", expr_label(invariant))) {
+                        solver.pop(1);
+                        return Err(MumeiError::failure(FailureKind::LoopInvariant, "Invariant not preserved"));
+                    }
+                    solver.pop(1);
+                    *env = env_snapshot; // env を復元
+                }
+
+                // Termination Check: decreases 句が指定されている場合、停止性を検証
+                if let Some(dec_expr) = decreases {
+                    let env_snapshot = env.clone();
+
+                    // V_before: ループ本体実行前の減少式の値
+                    let v_before = expr_to_z3(vc, dec_expr, env, None)?
+                        .as_int().ok_or(MumeiError::TypeError("decreases expression must be integer".into()))?;
+
+                    // A. 下界の証明: invariant && cond => V >= 0
+                    solver.push();
+                    solver.assert(&inv);
+                    solver.assert(&c);
+                    if !discharge(vc, solver, ObligationKind::Termination, &v_before.ge(&Int::from_i64(ctx, 0)), || format!("loop decreases {} >= 0", expr_label(dec_expr))) {
+                        solver.pop(1);
+                        return Err(MumeiError::failure(FailureKind::Termination,
+                            "Termination check failed: decreases expression may be negative"
+                        ));
+                    }
+                    solver.pop(1);
+
+                    // B. 厳密な減少の証明: body 実行後に V' < V
+                    solver.push();
+                    solver.assert(&inv);
+                    solver.assert(&c);
+                    expr_to_z3(vc, body, env, Some(solver))?;
+
+                    let v_after = expr_to_z3(vc, dec_expr, env, None)?
+                        .as_int().ok_or(MumeiError::TypeError("decreases expression must be integer".into()))?;
+
+                    if !discharge(vc, solver, ObligationKind::Termination, &v_after.lt(&v_before), || format!("loop decreases {} strictly", expr_label(dec_expr))) {
+                        solver.pop(1);
+                        *env = env_snapshot;
+                        return Err(MumeiError::failure(FailureKind::Termination,
+                            "Termination check failed: decreases expression does not strictly decrease"
+                        ));
+                    }
+                    solver.pop(1);
+                    *env = env_snapshot; // env を復元
+                }
+                *env = entry_env;
+            }
+
+            // ループ後: 代入された変数は新しい記号で、invariant && !cond だけが分かっている
+            havoc_loop_targets(vc, &targets, env, "after_loop");
+            let inv = expr_to_z3(vc, invariant, env, None)?
+                .as_bool().ok_or(MumeiError::TypeError("Invariant must be boolean".into()))?;
+            let c_not = expr_to_z3(vc, cond, env, None)?
+                .as_bool().ok_or(MumeiError::TypeError("While condition must be boolean".into()))?
+                .not();
+            let exit = Bool::and(ctx, &[&inv, &c_not]);
+            if let Some(solver) = solver_opt {
+                solver.assert(&exit);
+            }
+            Ok(exit.into())
+        },
+        Expr::StructInit { type_name, fields } => {
+            // 構造体の各フィールドを検証し、env に登録
+            // フィールドに精緻型制約がある場合は solver で検証する。
+            // 構造体定義があれば値は構造体のハンドル（フィールドは定義順）、なければ最後のフィールドの値
+            let mut last: Dynamic = Int::from_i64(ctx, 0).into();
+            let mut values: Vec<(String, Dynamic)> = Vec::new();
+            for (field_name, field_expr) in fields {
+                let val = expr_to_z3(vc, field_expr, env, solver_opt)?;
+                let qualified_name = format!("__struct_{}_{}", type_name, field_name);
+                env.insert(qualified_name, val.clone());
+                last = val.clone();
+                values.push((field_name.clone(), val.clone()));
+
+                // フィールド制約の検証: 構造体定義から constraint を取得
+                if let Some(sdef) = vc.module_env.get_struct(type_name) {
+                    if let Some(sfield) = sdef.fields.iter().find(|f| f.name == *field_name) {
+                        if let Some(constraint_raw) = &sfield.constraint {
+                            // constraint 内の "v" をフィールド値に置き換えて検証
+                            let mut local_env = env.clone();
+                            local_env.insert("v".to_string(), val.clone());
+                            let constraint_ast = parse_expression(constraint_raw)?;
+                            let constraint_z3 = expr_to_z3(vc, &constraint_ast, &mut local_env, None)?;
+                            if let Some(constraint_bool) = constraint_z3.as_bool() {
+                                if let Some(solver) = solver_opt {
+                                    solver.push();
+                                    solver.assert(&constraint_bool.not());
+                                    let result = vc.check(solver);
+                                    if result == SatResult::Sat {
+                                        solver.pop(1);
+                                        return Err(MumeiError::failure(FailureKind::Refinement,
+                                            format!("Struct '{}' field '{}' constraint violated: {}", type_name, field_name, constraint_raw)
+                                        ));
+                                    }
+                                    solver.pop(1);
+                                    if result == SatResult::Unknown {
+                                        vc.note_undecided("refinement", &constraint_bool);
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+            match vc.module_env.get_struct(type_name) {
+                Some(sdef) => {
+                    let ordered: Vec<(String, Dynamic)> = sdef.fields.iter()
+                        .filter_map(|f| values.iter().find(|(name, _)| *name == f.name).cloned())
+                        .collect();
+                    // 複数フィールドにまたがる invariant は、すべてのフィールドが揃った値について証明する
+                    if let Some(solver) = solver_opt.filter(|_| ordered.len() == sdef.fields.len()) {
+                        prove_struct_invariant(vc, solver, sdef, &ordered, "at a struct literal")?;
+                    }
+                    Ok(vc.new_struct_value(type_name, ordered))
+                }
+                None => Ok(last),
+            }
+        },
+        Expr::Match { target, arms } => {
+            let target_z3 = expr_to_z3(vc, target, env, solver_opt)?;
+
+            // ========================================================
+            // Enum ドメイン制約の自動注入
+            // ========================================================
+            // アームに Variant パターンが含まれる場合、対応する EnumDef を探し、
+            // target の値域を 0..n_variants に制約する。
+            // これにより Z3 が「これら以外のバリアントは存在しない」ことを知り、
+            // 網羅性チェックの信頼性が 100% になる。
+            if let Some(solver) = solver_opt {
+                if let Some(enum_def) = detect_enum_from_arms(arms, vc.module_env) {
+                    let n = enum_def.variants.len() as i64;
+                    if let Some(tag_int) = target_z3.as_int() {
+                        // tag ∈ [0, n_variants)
+                        solver.assert(&tag_int.ge(&Int::from_i64(ctx, 0)));
+                        solver.assert(&tag_int.lt(&Int::from_i64(ctx, n)));
+                    }
+                }
+            }
+
+            // ========================================================
+            // Z3 網羅性チェック (Exhaustiveness Check)
+            // ========================================================
+            // 各アームの条件 P_i を構築し、¬(P_1 ∨ P_2 ∨ ... ∨ P_n) が
+            // Unsat であることを証明する。Sat なら網羅性欠如エラー。
+            if let Some(solver) = solver_opt {
+                let mut arm_conditions: Vec<Bool> = Vec::new();
+                for arm in arms {
+                    // ガードはアームのスコープで評価する（パターン変数が外側の同名の束縛を隠す）
+                    let mut arm_env = env.clone();
+                    pattern_bind_variables(vc, &arm.pattern, &target_z3, &mut arm_env);
+                    bind_nullable_payload(&arm.pattern, target, &mut arm_env);
+                    let cond = pattern_to_z3_condition(ctx, &arm.pattern, &target_z3, &mut arm_env, vc, solver_opt)?;
+                    // ガード条件がある場合は AND で結合
+                    let full_cond = if let Some(guard) = &arm.guard {
+                        let guard_z3 = expr_to_z3(vc, guard, &mut arm_env, None)?
+                            .as_bool().ok_or(MumeiError::TypeError("Guard must be boolean".into()))?;
+                        Bool::and(ctx, &[&cond, &guard_z3])
+                    } else {
+                        cond
+                    };
+                    arm_conditions.push(full_cond);
+                }
+
+                // 網羅性: ¬(P_1 ∨ ... ∨ P_n) が Unsat か？
+                let arm_refs: Vec<&Bool> = arm_conditions.iter().collect();
+                let coverage = Bool::or(ctx, &arm_refs);
+                solver.push();
+                solver.assert(&coverage.not());
+                let coverage_result = vc.check(solver);
+                let exhaustive = coverage_result == SatResult::Unsat;
+                solver.pop(1);
+                if coverage_result == SatResult::Unknown {
+                    vc.note_undecided("exhaustiveness", &coverage);
+                }
+
+                if !exhaustive {
+                    // 反例（Counter-example）の取得と表示
+                    // solver はまだ Sat 状態なので、再度チェックして model を取得
+                    solver.push();
+                    solver.assert(&coverage.not());
+                    if vc.check(solver) == SatResult::Sat {
+                        let counterexample = if let Some(model) = solver.get_model() {
+                            // ターゲット変数とバリアント payload の具体的な値を取得
+                            match_counterexample(vc, &model, &target_z3, arms)
+                        } else {
+                            Counterexample { note: Some("unknown value".to_string()), ..Default::default() }
+                        };
+                        solver.pop(1);
+                        return Err(MumeiError::failure(FailureKind::Exhaustiveness, counterexample.render(
+                            "Match is not exhaustive: the following value is not covered by any arm:",
+                            vc.module_env.counterexample_format,
+                        )));
+                    }
+                    solver.pop(1);
+                    return Err(MumeiError::failure(FailureKind::Exhaustiveness,
+                        "Match is not exhaustive: there exist values not covered by any arm."
+                    ));
+                }
+            }
+
+            // ========================================================
+            // Match 式の値の構築（if-then-else チェーンとして Z3 式を構築）
+            // ========================================================
+            // アーム i の経路条件は「P_i かつ先行アームのどれにも該当しない」（first-match 意味論）。
+            // body 内の義務（ゼロ除算・範囲外・呼び出し先の requires・ネストした match の網羅性）は
+            // この経路条件を仮定して個別に証明し、失敗は「arm i of match #n」として報告する。
+            // body の評価中に solver へ追加された事実（呼び出し結果の ensures 等）は pop 後に
+            // 「経路条件 ⇒ 事実」として戻し、他のアームには漏らさず ensures の証明には使えるようにする。
+            let match_no = vc.next_match();
+            let mut prior_negations: Vec<Bool> = Vec::new();
+            let mut arm_values: Vec<(Bool, Dynamic)> = Vec::new();
+            let mut arm_envs: Vec<Env> = Vec::new();
+
+            for (i, arm) in arms.iter().enumerate() {
+                let mut arm_env = env.clone();
+
+                // ネストパターンの再帰解体:
+                //    pattern_bind_variables が再帰的にパターンを分解し、
+                //    バインド変数を arm_env に登録する。
+                pattern_bind_variables(vc, &arm.pattern, &target_z3, &mut arm_env);
+                bind_nullable_payload(&arm.pattern, target, &mut arm_env);
+
+                let arm_cond = pattern_to_z3_condition(ctx, &arm.pattern, &target_z3, &mut arm_env, vc, solver_opt)?;
+                let full_cond = if let Some(guard) = &arm.guard {
+                    let guard_z3 = expr_to_z3(vc, guard, &mut arm_env, None)?
+                        .as_bool().ok_or(MumeiError::TypeError("Guard must be boolean".into()))?;
+                    Bool::and(ctx, &[&arm_cond, &guard_z3])
+                } else {
+                    arm_cond
+                };
+                let mut path_parts: Vec<&Bool> = prior_negations.iter().collect();
+                path_parts.push(&full_cond);
+                let path_cond = Bool::and(ctx, &path_parts);
+
+                // ensures の反例がどのアームを通るかを判定できるよう、外側のアームの経路条件も含めて記録する
+                let reach = {
+                    let stack = vc.arm_stack.borrow();
+                    let mut parts: Vec<&Bool> = stack.iter().collect();
+                    parts.push(&path_cond);
+                    Bool::and(ctx, &parts)
+                };
+                vc.arm_paths.borrow_mut().push((
+                    format!("arm {} (`{}`) of match #{}", i + 1, pattern_label(&arm.pattern), match_no),
+                    reach,
+                ));
+
+                let body_val = eval_under_path(vc, &arm.body, &mut arm_env, solver_opt, &path_cond)
+                    .map_err(|e| in_match_arm(e, i + 1, match_no, vc.scope))?;
+
+                prior_negations.push(full_cond.not());
+                arm_values.push((full_cond, body_val));
+                arm_envs.push(arm_env);
+            }
+
+            // アーム内の外側の変数への代入は、アームの条件で ite に合流させる（if と同じ扱い）。
+            // アーム内の let とパターン変数はアームの外に出さない。
+            let outer: Vec<String> = env.keys().cloned().collect();
+            for name in outer {
+                let mut merged: Option<Dynamic> = None;
+                let mut mergeable = true;
+                for ((cond, _), arm_env) in arm_values.iter().zip(&arm_envs).rev() {
+                    let value = match arm_env.get(&name) {
+                        Some(value) => value.clone(),
+                        None => {
+                            mergeable = false;
+                            break;
+                        }
+                    };
+                    merged = Some(match merged {
+                        Some(else_val) if else_val == value => value,
+                        Some(else_val) if else_val.get_sort() == value.get_sort() => vc.select(cond, &value, &else_val),
+                        Some(_) => {
+                            mergeable = false;
+                            break;
+                        }
+                        None => value,
+                    });
+                }
+                if let (true, Some(value)) = (mergeable, merged) {
+                    env.insert(name, value);
+                }
+            }
+            for arm_env in arm_envs {
+                for (name, value) in arm_env {
+                    if name.starts_with("__") && !env.contains_key(&name) {
+                        env.insert(name, value);
+                    }
+                }
+            }
+
+            let mut result: Option<Dynamic> = None;
+            for (cond, body_val) in arm_values.into_iter().rev() {
+                result = Some(match result {
+                    Some(else_val) => vc.select(&cond, &body_val, &else_val),
+                    None => body_val,
+                });
+            }
+            result.ok_or_else(|| MumeiError::VerificationError("Match expression has no arms".into()))
+        },
+
+        // =================================================================
+        // 非同期処理 + リソース管理の Z3 検証
+        // =================================================================
+        Expr::Acquire { resource, body } => {
+            // acquire ブロック: リソースを取得して body を実行し、自動解放する。
+            // Z3 上ではリソースの保持状態をシンボリック Bool で追跡する。
+            // 保持フラグは登録キー単位（`cache` と `lib::cache` が同じリソースなら同じフラグ）。
+            let resource_key = match vc.module_env.resolve_resource(resource)? {
+                Some((key, _)) => key,
+                None => resource.clone(),
+            };
+            let held_name = format!("__resource_held_{}", resource_key);
+            let held_bool = Bool::new_const(ctx, vc.sym(&held_name));
+            if let Some(solver) = solver_opt {
+                // リソース取得: held = true
+                solver.assert(&held_bool);
+            }
+            env.insert(held_name.clone(), held_bool.into());
+
+            // body を検証
+            let body_result = expr_to_z3(vc, body, env, solver_opt)?;
+
+            // リソース解放: held = false
+            let released = Bool::from_bool(ctx, false);
+            env.insert(held_name, released.into());
+
+            Ok(body_result)
+        },
+        Expr::Async { body } => {
+            // async ブロック: body を非同期コンテキストとして検証する。
+            // Z3 上では通常の式として扱い、結果をシンボリック値として返す。
+            // await ポイントでの所有権検証は Await 式で行う。
+            expr_to_z3(vc, body, env, solver_opt)
+        },
+        Expr::Await { expr } => {
+            // =============================================================
+            // await 跨ぎの安全性検証 (Await Safety Verification)
+            // =============================================================
+            //
+            // await ポイントはコルーチンの中断点であり、以下の安全性を検証する:
+            //
+            // 1. リソース保持検証 (Resource Held Across Await):
+            //    acquire ブロック内で await を呼ぶと、リソースを保持したまま
+            //    スレッドが中断される。これはデッドロックの典型パターン。
+            //    env 内の __resource_held_* が true のリソースを検出してエラーにする。
+            //
+            // 2. 所有権一貫性検証 (Ownership Consistency):
+            //    await 前に消費済み（__alive_ = false）の変数が、await 後に
+            //    アクセスされないことを確認する。Z3 で __alive_ フラグをチェック。
+
+            // --- 1. リソース保持検証 ---
+            // env 内の __resource_held_* キーを走査し、Z3 で true かどうかを確認する。
+            // acquire ブロック内で await を呼ぶパターンを検出する。
+            if let Some(solver) = solver_opt {
+                let held_resources: Vec<String> = env.keys()
+                    .filter(|k| k.starts_with("__resource_held_"))
+                    .cloned()
+                    .collect();
+
+                for held_key in &held_resources {
+                    let resource_name = held_key.strip_prefix("__resource_held_").unwrap_or(held_key);
+                    if let Some(held_val) = env.get(held_key) {
+                        // Z3 で held_val == true が証明可能かチェック
+                        // （acquire ブロック内なら held = true が assert されている）
+                        if let Some(held_bool) = held_val.as_bool() {
+                            solver.push();
+                            // held が true であることを仮定し、矛盾がなければ保持中
+                            solver.assert(&held_bool);
+                            if vc.check(solver) != SatResult::Unsat {
+                                solver.pop(1);
+                                return Err(MumeiError::failure(FailureKind::Resource,
+                                    format!(
+                                        "Unsafe await: resource '{}' is held across an await point. \
+                                         This can cause deadlock because the resource lock is not released \
+                                         during suspension. Move the await outside the acquire block, or \
+                                         release the resource before awaiting.\n  \
+                                         Hint: acquire {} {{ ... }}; let val = await expr; // OK\n  \
+                                         Bad:  acquire {} {{ let val = await expr; ... }}  // deadlock risk",
+                                        resource_name, resource_name, resource_name
+                                    )
+                                ));
+                            }
+                            solver.pop(1);
+                        }
+                    }
+                }
+            }
+
+            // --- 2. 所有権一貫性検証 ---
+            // await 前に消費済みの変数を検出し、Z3 で __alive_ = false を確認する。
+            // 消費済み変数が await 後にアクセスされる可能性がある場合、警告する。
+            if let Some(solver) = solver_opt {
+                let consumed_vars: Vec<String> = env.keys()
+                    .filter(|k| k.starts_with("__alive_"))
+                    .cloned()
+                    .collect();
+
+                for alive_key in &consumed_vars {
+                    let var_name = alive_key.strip_prefix("__alive_").unwrap_or(alive_key);
+                    if let Some(alive_val) = env.get(alive_key) {
+                        if let Some(alive_bool) = alive_val.as_bool() {
+                            // __alive_ が false（消費済み）であることを Z3 で確認
+                            solver.push();
+                            solver.assert(&alive_bool.not()); // alive = false を仮定
+                            if vc.check(solver) == SatResult::Sat {
+                                // 消費済み変数が存在する → await 後のアクセスは use-after-free
+                                // await ポイントでの状態をマーク（後続の検証で参照）
+                                let await_consumed_key = format!("__await_consumed_{}", var_name);
+                                let marker = Bool::from_bool(vc.ctx, true);
+                                env.insert(await_consumed_key, marker.into());
+                            }
+                            solver.pop(1);
+                        }
+                    }
+                }
+            }
+
+            // 内側の式を評価する。async atom 呼び出しの future ハンドルなら、
+            // await した値を新しいシンボルで表し、呼び出し先の ensures をその値について仮定する
+            let inner_result = expr_to_z3(vc, expr, env, solver_opt)?;
+            let pending = vc.futures.borrow().get(&inner_result.to_string())
+                .map(|f| (f.callee.clone(), f.call_env.clone()));
+            match pending {
+                Some((callee, mut call_env)) => {
+                    let awaited_name = vc.fresh(&format!("await_{}", callee.name));
+                    let awaited: Dynamic = if inner_result.as_float().is_some() {
+                        Float::new_const(ctx, vc.sym(&awaited_name), 11, 53).into()
+                    } else {
+                        Int::new_const(ctx, vc.sym(&awaited_name)).into()
+                    };
+                    assume_callee_ensures(vc, &callee, &awaited, &mut call_env, solver_opt)?;
+                    if callee.trust_level == TrustLevel::Unverified {
+                        env.insert(format!("__tainted_{}", awaited_name), Bool::from_bool(ctx, true).into());
+                    }
+                    Ok(awaited)
+                }
+                None => Ok(inner_result),
+            }
+        },
+        Expr::Assume { cond, text, .. } => {
+            // assume: 証明義務を作らず、この時点の事実として solver に追加する。
+            // if / match の中では eval_under_path が「経路条件 ⇒ 事実」に弱めて戻す
+            let fact = expr_to_z3(vc, cond, env, solver_opt)?
+                .as_bool()
+                .ok_or_else(|| MumeiError::TypeError(format!("assume({}) must be a boolean condition", text)))?;
+            if let Some(solver) = solver_opt {
+                solver.assert(&fact);
+            }
+            Ok(Int::from_i64(ctx, 0).into())
+        },
+        // 参照渡しの実引数は値としては変数そのもの（マーカーの整合性は ref_argument_warnings で検査済み）
+        Expr::RefArg { expr, .. } => expr_to_z3(vc, expr, env, solver_opt),
+
+        Expr::FieldAccess(inner_expr, field_name) => {
+            // ネスト構造体のフィールドアクセスを再帰的に解決する。
+            //
+            // 1段階: v.x → env["__struct_v_x"] or env["v_x"]
+            // 2段階: v.point.x → まず v.point を解決し、その結果から .x を解決
+            //
+            // 解決戦略:
+            // A. 内側の式が Variable の場合: 直接 env から探す
+            // B. 内側の式が FieldAccess の場合: 再帰的に解決し、
+            //    結果のパスを使って env から探す
+            // C. どちらでもない場合: 式を評価してシンボリック変数を生成
+
+            // フラットなパス文字列を構築するヘルパー
+            // v.point.x → "v_point_x" のようなパスを生成
+            fn build_field_path(expr: &Expr) -> Option<Vec<String>> {
+                match expr {
+                    Expr::Variable(name) => Some(vec![name.clone()]),
+                    Expr::FieldAccess(inner, field) => {
+                        let mut path = build_field_path(inner)?;
+                        path.push(field.clone());
+                        Some(path)
+                    }
+                    _ => None,
+                }
+            }
+
+            // 完全なフィールドパスを構築（例: ["v", "point", "x"]）
+            let full_path = {
+                let mut path = build_field_path(inner_expr).unwrap_or_default();
+                path.push(field_name.clone());
+                path
+            };
+
+            if full_path.len() >= 2 {
+                // パスの各プレフィックスで env を探索
+                // 例: ["v", "point", "x"] → "v_point_x", "__struct_v_point_x"
+                let underscore_path = full_path.join("_");
+                let struct_path = format!("__struct_{}", underscore_path);
+
+                // 直接パスで見つかればそれを返す
+                if let Some(val) = env.get(&struct_path) {
+                    return Ok(val.clone());
+                }
+                if let Some(val) = env.get(&underscore_path) {
+                    return Ok(val.clone());
+                }
+
+                // 1段階ずつ解決を試みる
+                // 例: v.point → env["__struct_v_point"] or env["v_point"]
+                //     その結果が構造体型なら、.x のフィールドをさらに解決
+                if full_path.len() == 2 {
+                    // 単純な1段階アクセス: v.x
+                    let var_name = &full_path[0];
+                    let candidates = [
+                        format!("__struct_{}_{}", var_name, field_name),
+                        format!("{}_{}", var_name, field_name),
+                    ];
+                    for candidate in &candidates {
+                        if let Some(val) = env.get(candidate) {
+                            return Ok(val.clone());
+                        }
+                    }
+                }
+
+                // ネスト構造体の再帰解決:
+                // 内側の式を先に Z3 で評価し、構造体の値（let で束縛した呼び出し結果等）ならそのフィールドを返す
+                let base_val = expr_to_z3(vc, inner_expr, env, solver_opt)?;
+                if let Some(val) = struct_field(vc, &base_val, field_name) {
+                    return Ok(val);
+                }
+
+                // 内側の式の型を推定し、構造体定義からフィールドの型を取得
+                // フィールドの精緻型制約も再帰的に適用する
+                let nested_sym_name = format!("{}_{}", underscore_path.rsplit_once('_').map(|(prefix, _)| prefix).unwrap_or(&underscore_path), field_name);
+                let sym = if let Some(val) = env.get(&nested_sym_name) {
+                    return Ok(val.clone());
+                } else {
+                    let s = Int::new_const(ctx, vc.sym(&full_path.join("_")));
+                    env.insert(full_path.join("_"), s.clone().into());
+                    s
+                };
+                Ok(sym.into())
+            } else {
+                // パスが構築できない場合: 式を評価し、構造体の値（`make_point(a).x` 等）ならそのフィールド、
+                // そうでなければシンボリック変数を生成
+                let base = expr_to_z3(vc, inner_expr, env, solver_opt)?;
+                if let Some(val) = struct_field(vc, &base, field_name) {
+                    return Ok(val);
+                }
+                let sym = Int::new_const(ctx, vc.sym(&format!("field_{}", field_name)));
+                Ok(sym.into())
+            }
+        },
+    }
+}
+
+/// 構造体の値 base のフィールド（base が構造体の値でない・フィールドがなければ None）
+fn struct_field<'a>(vc: &VCtx<'a>, base: &Dynamic<'a>, field_name: &str) -> Option<Dynamic<'a>> {
+    vc.struct_value(base)?.fields.into_iter().find(|(name, _)| name == field_name).map(|(_, val)| val)
+}
+
+/// 構造体 sdef のフィールドを `<prefix>_<field>` のシンボルとして作り、フィールド制約を solver に仮定する。
+/// 構造体パラメータ（入口で制約が成り立つ）と構造体を返す atom の呼び出し結果（呼び出し先の検証で保証済み）に使う
+pub(super) fn symbolic_struct_fields<'a>(
+    vc: &VCtx<'a>,
+    sdef: &StructDef,
+    prefix: &str,
+    env: &Env<'a>,
+    solver_opt: Option<&Solver<'a>>,
+) -> MumeiResult<Vec<(String, Dynamic<'a>)>> {
+    let mut fields = Vec::new();
+    for field in &sdef.fields {
+        let field_var_name = format!("{}_{}", prefix, field.name);
+        let field_z3: Dynamic = match vc.module_env.resolve_base_type(&field.type_name).as_str() {
+            "f64" => Float::new_const(vc.ctx, vc.sym(&field_var_name), 11, 53).into(),
+            _ => Int::new_const(vc.ctx, vc.sym(&field_var_name)).into(),
+        };
+        if let (Some(constraint_raw), Some(solver)) = (&field.constraint, solver_opt) {
+            let mut local_env = env.clone();
+            local_env.insert("v".to_string(), field_z3.clone());
+            let constraint_ast = parse_expression(constraint_raw)?;
+            if let Some(constraint_bool) = expr_to_z3(vc, &constraint_ast, &mut local_env, None)?.as_bool() {
+                solver.assert(&constraint_bool);
+            }
+        }
+        fields.push((field.name.clone(), field_z3));
+    }
+    if let (Some(invariant), Some(solver)) = (struct_invariant(vc, sdef, &fields)?, solver_opt) {
+        solver.assert(&invariant);
+    }
+    Ok(fields)
+}
+
+/// 構造体 sdef の invariant をフィールドの値 fields に対する条件にする（invariant がなければ None）。
+/// invariant が参照してよいのはフィールドだけ
+pub(super) fn struct_invariant<'a>(vc: &VCtx<'a>, sdef: &StructDef, fields: &[(String, Dynamic<'a>)]) -> MumeiResult<Option<Bool<'a>>> {
+    let invariant_raw = match &sdef.invariant {
+        Some(invariant) => invariant,
+        None => return Ok(None),
+    };
+    let invariant_ast = parse_expression(invariant_raw)?;
+    let mut names = Vec::new();
+    contract_variables(&invariant_ast, &mut names);
+    if let Some(unknown) = names.iter().find(|name| !sdef.fields.iter().any(|f| &f.name == *name)) {
+        return Err(MumeiError::TypeError(format!(
+            "invariant of struct '{}' mentions '{}', which is not a field: {}",
+            sdef.name, unknown, invariant_raw
+        )));
+    }
+    let mut local_env: Env = fields.iter().cloned().collect();
+    expr_to_z3(vc, &invariant_ast, &mut local_env, None)?
+        .as_bool()
+        .map(Some)
+        .ok_or(MumeiError::TypeError(format!("invariant of struct '{}' must be boolean: {}", sdef.name, invariant_raw)))
+}
+
+/// 構造体の値（フィールドの値 fields）が sdef の invariant を満たすことを証明する。
+/// 満たさなければ invariant とフィールドの値の反例を示す。site は違反した場所の説明（"in a struct literal" など）
+pub(super) fn prove_struct_invariant<'a>(vc: &VCtx<'a>, solver: &Solver<'a>, sdef: &StructDef, fields: &[(String, Dynamic<'a>)], site: &str) -> MumeiResult<()> {
+    let invariant = match struct_invariant(vc, sdef, fields)? {
+        Some(invariant) => invariant,
+        None => return Ok(()),
+    };
+    solver.push();
+    solver.assert(&invariant.not());
+    let result = vc.check(solver);
+    let model = if result == SatResult::Sat { solver.get_model() } else { None };
+    solver.pop(1);
+    if result == SatResult::Unknown {
+        vc.note_undecided("struct_invariant", &invariant);
+    }
+    if result != SatResult::Sat {
+        return Ok(());
+    }
+    let failure = format!(
+        "Struct '{}' invariant violated {}.\n  Invariant: {}",
+        sdef.name, site, sdef.invariant.as_deref().unwrap_or_default()
+    );
+    let counterexample = model.map(|model| {
+        let symbols: Vec<(String, String, Dynamic)> = fields.iter()
+            .map(|(name, value)| {
+                let declared = sdef.fields.iter().find(|f| &f.name == name).map_or_else(|| symbol_type_name(value).to_string(), |f| f.type_name.clone());
+                (name.clone(), declared, value.clone())
+            })
+            .collect();
+        model_counterexample(&model, &symbols, vc.module_env)
+    }).unwrap_or_default();
+    Err(MumeiError::failure(FailureKind::Refinement, counterexample.render(&failure, vc.module_env.counterexample_format)))
+}
+
+/// 呼び出し先の ensures を、result を result_z3 に束縛した事実として solver に追加する。
+/// call_env は仮引数を実引数の値に束縛した環境。
+/// async atom の ensures は await した値についての事実なので、Await 式の評価で呼ぶ
+fn assume_callee_ensures<'a>(
+    vc: &VCtx<'a>,
+    callee: &Atom,
+    result_z3: &Dynamic<'a>,
+    call_env: &mut Env<'a>,
+    solver_opt: Option<&Solver<'a>>,
+) -> MumeiResult<()> {
+    // ensures を事実として solver に追加（result を呼び出し結果に束縛）
+    //
+    // Equality Ensures Propagation:
+    // ensures 内に `result == expr` の形式の等式が含まれる場合、
+    // シンボリック result を具体的な式に直接束縛する。
+    // これにより `let x = increment(n);` で `x == n + 1` が
+    // 呼び出し元のコンテキストに伝播し、連鎖呼び出しの検証精度が向上する。
+    //
+    // 例: ensures: result == n + 1;
+    //   → call_env に result = call_increment@0 を挿入
+    //   → Z3 に call_increment@0 == n + 1 を assert
+    //   → 後続の `increment(x)` で x >= 1 だけでなく x == n + 1 が使える
+    if callee.ensures.trim() != "true" {
+        call_env.insert("result".to_string(), result_z3.clone());
+        let ens_ast = parse_expression(&callee.ensures)?;
+
+        // Equality ensures の特別処理:
+        // ensures が `result == expr` の形式の場合、
+        // expr を評価して result と等価であることを直接 assert する。
+        // これにより Z3 が等式を完全に活用できる。
+        let ens_z3 = expr_to_z3(vc, &ens_ast, call_env, None)?;
+        if let Some(ens_bool) = ens_z3.as_bool() {
+            if let Some(solver) = solver_opt {
+                solver.assert(&ens_bool);
+            }
+        }
+
+        // 追加: ensures 式が `result == expr` の形式かチェックし、
+        // 該当する場合は result のシンボリック値に対して
+        // 等式制約を明示的に追加する（Z3 の等式推論を強化）
+        if let Expr::BinaryOp(left, Op::Eq, right) = &ens_ast {
+            if let Expr::Variable(ref var_name) = left.as_ref() {
+                if var_name == "result" {
+                    // ensures: result == <expr> の場合
+                    // <expr> を call_env で評価し、result_z3 == eval(<expr>) を assert
+                    if let Ok(rhs_val) = expr_to_z3(vc, right, call_env, None) {
+                        if let Some(solver) = solver_opt {
+                            if let (Some(res_int), Some(rhs_int)) = (result_z3.as_int(), rhs_val.as_int()) {
+                                solver.assert(&res_int._eq(&rhs_int));
+                            } else if let (Some(res_float), Some(rhs_float)) = (result_z3.as_float(), rhs_val.as_float()) {
+                                solver.assert(&res_float._eq(&rhs_float));
+                            }
+                        }
+                    }
+                }
+            }
+            // ensures: <expr> == result の逆順もサポート
+            if let Expr::Variable(ref var_name) = right.as_ref() {
+                if var_name == "result" {
+                    if let Ok(lhs_val) = expr_to_z3(vc, left, call_env, None) {
+                        if let Some(solver) = solver_opt {
+                            if let (Some(res_int), Some(lhs_int)) = (result_z3.as_int(), lhs_val.as_int()) {
+                                solver.assert(&res_int._eq(&lhs_int));
+                            } else if let (Some(res_float), Some(lhs_float)) = (result_z3.as_float(), lhs_val.as_float()) {
+                                solver.assert(&res_float._eq(&lhs_float));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        // 複合 ensures（&& で結合された複数条件）内の等式も伝播
+        // ensures: result >= 0 && result == n + 1 のような場合
+        propagate_equality_from_ensures(vc, &ens_ast, result_z3, call_env, solver_opt)?;
+    }
+    Ok(())
+}
+
+/// 複合 ensures 式（&& で結合された複数条件）から等式 `result == expr` を
+/// 再帰的に抽出し、Z3 solver に assert する。
+///
+/// ensures: result >= 0 && result == n + 1
+/// → `result >= 0` と `result == n + 1` の両方を assert
+/// → 特に `result == n + 1` は等式制約として明示的に追加
+///
+/// ensures: result == a + b && result >= 0 && result <= 100
+/// → 3つの条件すべてを assert + `result == a + b` の等式を追加
+fn propagate_equality_from_ensures<'a>(
+    vc: &VCtx<'a>,
+    expr: &Expr,
+    result_z3: &Dynamic<'a>,
+    call_env: &mut Env<'a>,
+    solver_opt: Option<&Solver<'a>>,
+) -> MumeiResult<()> {
+    match expr {
+        // && で結合された複合条件: 左右を再帰的に処理
+        Expr::BinaryOp(left, Op::And, right) => {
+            propagate_equality_from_ensures(vc, left, result_z3, call_env, solver_opt)?;
+            propagate_equality_from_ensures(vc, right, result_z3, call_env, solver_opt)?;
+        }
+        // result == <expr> の等式
+        Expr::BinaryOp(left, Op::Eq, right) => {
+            let is_result_left = matches!(left.as_ref(), Expr::Variable(ref v) if v == "result");
+            let is_result_right = matches!(right.as_ref(), Expr::Variable(ref v) if v == "result");
+
+            if is_result_left {
+                if let Ok(rhs_val) = expr_to_z3(vc, right, call_env, None) {
+                    if let Some(solver) = solver_opt {
+                        if let (Some(res_int), Some(rhs_int)) = (result_z3.as_int(), rhs_val.as_int()) {
+                            solver.assert(&res_int._eq(&rhs_int));
+                        } else if let (Some(res_float), Some(rhs_float)) = (result_z3.as_float(), rhs_val.as_float()) {
+                            solver.assert(&res_float._eq(&rhs_float));
+                        }
+                    }
+                }
+            } else if is_result_right {
+                if let Ok(lhs_val) = expr_to_z3(vc, left, call_env, None) {
+                    if let Some(solver) = solver_opt {
+                        if let (Some(res_int), Some(lhs_int)) = (result_z3.as_int(), lhs_val.as_int()) {
+                            solver.assert(&res_int._eq(&lhs_int));
+                        } else if let (Some(res_float), Some(lhs_float)) = (result_z3.as_float(), lhs_val.as_float()) {
+                            solver.assert(&res_float._eq(&lhs_float));
+                        }
+                    }
+                }
+            }
+        }
+        _ => {
+            // 等式でも && でもない条件はスキップ（既に全体の ensures として assert 済み）
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Item;
+    use crate::test_support::{env_from_source, find_atom, TempDir};
+
+    const NULLABLE_SOURCE: &str = r#"
+type Nat = i64 where v >= 0;
+
+atom guarded_value(x: Nat?)
+requires: is_some(x);
+ensures: result >= 0;
+body: x!;
+
+atom forward(y: Nat?)
+requires: is_some(y);
+ensures: result >= 0;
+body: guarded_value(y);
+
+atom unguarded_value(x: Nat?)
+requires: true;
+ensures: true;
+body: value(x);
+
+atom abs_nat(n: i64)
+requires: true;
+ensures: result >= 0;
+body: if n >= 0 { n } else { 0 - n };
+
+atom forward_call(n: i64)
+requires: true;
+ensures: result >= 0;
+body: guarded_value(abs_nat(n));
+
+atom forward_either(a: Nat?, b: Nat?, c: bool)
+requires: is_some(a) && is_some(b);
+ensures: result >= 0;
+body: guarded_value(if c { a } else { b });
+
+atom forward_maybe(a: Nat?, c: bool)
+requires: true;
+ensures: true;
+body: guarded_value(if c { a } else { 1 });
+"#;
+
+    #[test]
+    fn test_nullable_presence_obligation() {
+        let (items, module_env) = env_from_source(NULLABLE_SOURCE);
+        let atom = |name: &str| find_atom(&items, name);
+        let output_dir = TempDir::new("nullable");
+
+        // is_some ガードの下では存在性が成り立ち、Nat の制約も仮定できる
+        assert!(verify(&atom("guarded_value"), &output_dir, &module_env).is_ok());
+        // 呼び出し元の nullable 実引数は呼び出し先の is_some(x) にそのまま対応する
+        assert!(verify(&atom("forward"), &output_dir, &module_env).is_ok());
+
+        // 呼び出し結果や if 式の実引数も評価してから (tag, 値) に詰める
+        assert!(verify(&atom("forward_call"), &output_dir, &module_env).is_ok());
+        assert!(verify(&atom("forward_either"), &output_dir, &module_env).is_ok());
+
+        let err = verify(&atom("unguarded_value"), &output_dir, &module_env).expect_err("x may be None");
+        assert_eq!(err.failure_kind(), FailureKind::Presence);
+        let msg = err.to_string();
+        assert!(msg.contains("Presence obligation for 'x'"), "{}", msg);
+        let err = verify(&atom("forward_maybe"), &output_dir, &module_env).expect_err("a may be None when c");
+        assert_eq!(err.failure_kind(), FailureKind::Precondition);
+        assert!(err.to_string().contains("precondition (requires) not satisfied"), "{}", err);
+    }
+
+    const ARRAY_SOURCE: &str = r#"
+atom first_of_a(a: [i64], b: [i64])
+requires: len_a > 0 && len_b > 0 && a[0] == 5;
+ensures: result == 5;
+body: a[0];
+
+atom first_of_b(a: [i64], b: [i64])
+requires: len_a > 0 && len_b > 0 && a[0] == 5;
+ensures: result == 5;
+body: b[0];
+
+atom first_float(xs: [f64])
+requires: len_xs > 0 && xs[0] > 0.0;
+ensures: result > 0.0;
+body: xs[0];
+"#;
+
+    const ELEMENT_REFINEMENT_SOURCE: &str = r#"
+type Nat = i64 where v >= 0;
+type Prob = f64 where p >= 0.0 && p <= 1.0;
+
+atom head_plus(xs: [Nat])
+requires: len(xs) > 0;
+ensures: result >= 1;
+body: xs[0] + 1;
+
+atom last_plus(xs: [Nat], n: i64)
+requires: n > 0 && n == len(xs);
+ensures: result >= 1;
+body: xs[n - 1] + 1;
+
+atom plain_head(xs: [i64])
+requires: len(xs) > 0;
+ensures: result >= 1;
+body: xs[0] + 1;
+
+atom first_prob(ps: [Prob])
+requires: len(ps) > 0;
+ensures: result <= 1.0;
+body: ps[0];
+"#;
+
+    #[test]
+    fn test_array_element_refinements() {
+        // `[Nat]` の要素は ∀i ∈ [0, len_xs) で v >= 0 を満たすと仮定される
+        let (items, module_env) = env_from_source(ELEMENT_REFINEMENT_SOURCE);
+        let output_dir = TempDir::new("elements");
+        let atom = |name: &str| find_atom(&items, name);
+
+        assert!(verify(&atom("head_plus"), &output_dir, &module_env).is_ok());
+        assert!(verify(&atom("last_plus"), &output_dir, &module_env).is_ok());
+        assert!(verify(&atom("first_prob"), &output_dir, &module_env).is_ok());
+        // 精緻化のない `[i64]` の要素には何も仮定しない
+        let err = verify(&atom("plain_head"), &output_dir, &module_env).unwrap_err().to_string();
+        assert!(err.contains("Postcondition"), "{}", err);
+    }
+
+    #[test]
+    fn test_array_params_do_not_alias() {
+        // 配列パラメータごとに別の Z3 配列を使うため、a[0] についての事実は b[0] に漏れない
+        let (items, module_env) = env_from_source(ARRAY_SOURCE);
+        let output_dir = TempDir::new("arrays");
+        let atom = |name: &str| find_atom(&items, name);
+
+        assert!(verify(&atom("first_of_a"), &output_dir, &module_env).is_ok());
+        let err = verify(&atom("first_of_b"), &output_dir, &module_env).unwrap_err().to_string();
+        assert!(err.contains("Postcondition"), "{}", err);
+        // `[f64]` の要素は Float として扱われる
+        assert!(verify(&atom("first_float"), &output_dir, &module_env).is_ok());
+    }
+
+    const QUANTIFIER_RANGE_SOURCE: &str = r#"
+atom off_by_one(arr: [i64], n: i64)
+requires: n >= 0 && n <= len(arr) && forall(i, 0, n, arr[i] >= 0);
+ensures: true;
+body: arr[n];
+
+atom next_element(arr: [i64], n: i64, k: i64)
+requires: n <= len(arr) && k >= 0 && k < n && forall(i, 0, n, arr[i] >= 0);
+ensures: true;
+body: arr[k + 1];
+
+atom unrelated_length(arr: [i64], n: i64, k: i64)
+requires: k >= 0 && k < n && forall(i, 0, n, arr[i] >= 0);
+ensures: true;
+body: arr[k];
+
+atom bounded(arr: [i64], n: i64, k: i64)
+requires: n <= len(arr) && k >= 0 && k < n && forall(i, 0, n, arr[i] >= 0);
+ensures: result >= 0;
+body: arr[k];
+"#;
+
+    #[test]
+    fn test_out_of_bounds_notes_quantifier_range() {
+        let (items, module_env) = env_from_source(QUANTIFIER_RANGE_SOURCE);
+        let output_dir = TempDir::new("quantifier_range");
+        let atom = |name: &str| find_atom(&items, name);
+
+        // 添字が forall の範囲 [0, n) の外に出る失敗には、量化子の範囲を疑う注記が付く
+        let err = verify(&atom("off_by_one"), &output_dir, &module_env).unwrap_err().to_string();
+        assert!(err.contains("Potential Out-of-Bounds on 'arr'"), "{}", err);
+        assert!(err.contains("note: requires only constrains arr[i] for 0 <= i < n, but this access uses index n"), "{}", err);
+        assert!(err.contains("did you mean i <= n or len(arr)?"), "{}", err);
+        let err = verify(&atom("next_element"), &output_dir, &module_env).unwrap_err().to_string();
+        assert!(err.contains("but this access uses index k + 1 (= "), "{}", err);
+
+        // 添字が範囲内なら原因は len の不足なので注記しない
+        let err = verify(&atom("unrelated_length"), &output_dir, &module_env).unwrap_err().to_string();
+        assert!(err.contains("Potential Out-of-Bounds on 'arr'") && !err.contains("note:"), "{}", err);
+        assert!(verify(&atom("bounded"), &output_dir, &module_env).is_ok());
+    }
+
+    const ARRAY_LENGTH_CALL_SOURCE: &str = r#"
+atom first_index(xs: [i64])
+requires: len(xs) > 0;
+ensures: result >= 0 && result < len(xs);
+body: 0;
+
+atom forward_index(ys: [i64])
+requires: len(ys) > 0;
+ensures: result < len(ys);
+body: first_index(ys);
+
+atom unguarded_index(ys: [i64])
+requires: true;
+ensures: true;
+body: first_index(ys);
+
+atom other_guarded(xs: [i64], ys: [i64])
+requires: len(xs) > 0;
+ensures: true;
+body: first_index(ys);
+
+trusted atom make_buffer(n: i64)
+requires: n > 0;
+ensures: len(result) == n;
+body: n;
+
+atom buffered_index(n: i64)
+requires: n > 1;
+ensures: result < n;
+body: {
+    let buf = make_buffer(n);
+    first_index(buf)
+};
+
+atom nested_index(n: i64)
+requires: n > 0;
+ensures: result < n;
+body: first_index(make_buffer(n));
+"#;
+
+    #[test]
+    fn test_array_length_flows_through_calls() {
+        let (items, module_env) = env_from_source(ARRAY_LENGTH_CALL_SOURCE);
+        let output_dir = TempDir::new("array_len_calls");
+        let atom = |name: &str| find_atom(&items, name);
+
+        // 呼び出し先の len(xs) は呼び出し元の len(ys) と同じシンボルなので、requires も ensures も伝わる
+        assert!(verify(&atom("forward_index"), &output_dir, &module_env).is_ok());
+        let err = verify(&atom("unguarded_index"), &output_dir, &module_env).unwrap_err().to_string();
+        assert!(err.contains("precondition"), "{}", err);
+        // 呼び出し元に同名の xs があっても、呼び出し先の len(xs) は実引数 ys の長さを指す
+        let err = verify(&atom("other_guarded"), &output_dir, &module_env).unwrap_err().to_string();
+        assert!(err.contains("precondition"), "{}", err);
+        // 返された配列の len(result) は let の束縛先や、そのまま渡した実引数へ引き継がれる
+        assert!(verify(&atom("buffered_index"), &output_dir, &module_env).is_ok());
+        assert!(verify(&atom("nested_index"), &output_dir, &module_env).is_ok());
+    }
+
+    const ENSURES_QUANTIFIER_SOURCE: &str = r#"
+atom positive_prefix(xs: [i64], n: i64)
+requires: n >= 0 && n <= len(xs) && forall(i, 0, len(xs), xs[i] >= 1);
+ensures: result == n && forall(i, 0, result, xs[i] > 0);
+body: n;
+
+atom has_positive(xs: [i64])
+requires: len(xs) > 0 && forall(i, 0, len(xs), xs[i] >= 1);
+ensures: exists(i, 0, len(xs), xs[i] >= 1);
+body: 0;
+
+atom too_strong(xs: [i64])
+requires: forall(i, 0, len(xs), xs[i] >= 1);
+ensures: forall(i, 0, len(xs), xs[i] > 1);
+body: 0;
+"#;
+
+    #[test]
+    fn test_quantified_ensures() {
+        let (items, module_env) = env_from_source(ENSURES_QUANTIFIER_SOURCE);
+        let output_dir = TempDir::new("ensures_quantifier");
+        let atom = |name: &str| find_atom(&items, name);
+
+        // ensures の量化子は result を束縛した事後状態で検査される
+        assert!(verify(&atom("positive_prefix"), &output_dir, &module_env).is_ok());
+        assert!(verify(&atom("has_positive"), &output_dir, &module_env).is_ok());
+        let err = verify(&atom("too_strong"), &output_dir, &module_env).unwrap_err().to_string();
+        assert!(err.contains("Postcondition"), "{}", err);
+    }
+
+    const STRUCT_RETURN_SOURCE: &str = r#"
+struct Point { x: i64 where v >= 0, y: i64 }
+
+atom make_point(a: i64) -> Point
+requires: a >= 0;
+ensures: result.x == a && result_y == 0;
+body: Point { y: 0, x: a };
+
+atom any_point(a: i64) -> Point
+requires: true;
+ensures: true;
+body: Point { x: 0, y: a };
+
+atom negative_point(a: i64) -> Point
+requires: true;
+ensures: true;
+body: Point { x: a, y: 0 };
+
+atom point_x(a: i64)
+requires: a >= 0;
+ensures: result == a;
+body: make_point(a).x;
+
+atom any_x(a: i64)
+requires: true;
+ensures: result >= 0;
+body: any_point(a).x;
+
+atom bound_sum(a: i64)
+requires: a >= 1;
+ensures: result == a - 1;
+body: {
+    let p = make_point(a - 1);
+    p.x + p.y
+};
+
+atom farther(a: i64, b: i64) -> Point
+requires: a >= 0 && b >= 0;
+ensures: result.x >= a && result.x >= b && result.y == 0;
+body: if a > b { make_point(a) } else { make_point(b) };
+
+atom passthrough(p: Point) -> Point
+requires: true;
+ensures: result.x == p.x;
+body: p;
+
+atom too_strong(a: i64) -> Point
+requires: a >= 0;
+ensures: result.x > a;
+body: make_point(a);
+
+atom not_a_point(a: i64) -> Point
+requires: true;
+ensures: true;
+body: a;
+"#;
+
+    #[test]
+    fn test_struct_return_values() {
+        let (items, module_env) = env_from_source(STRUCT_RETURN_SOURCE);
+        let output_dir = TempDir::new("struct_return");
+        let atom = |name: &str| find_atom(&items, name);
+
+        // ensures の result.x / result_y は返す構造体のフィールドで、呼び出し側はそれを事実として使う。
+        // 呼び出し結果のフィールド制約（x >= 0）は ensures がなくても仮定される
+        for name in ["make_point", "any_point", "point_x", "any_x", "bound_sum", "farther", "passthrough"] {
+            assert!(verify(&atom(name), &output_dir, &module_env).is_ok(), "{} should verify", name);
+        }
+        let err = verify(&atom("negative_point"), &output_dir, &module_env).unwrap_err().to_string();
+        assert!(err.contains("Struct 'Point' field 'x' constraint violated"), "{}", err);
+        let err = verify(&atom("too_strong"), &output_dir, &module_env).unwrap_err().to_string();
+        assert!(err.contains("Postcondition"), "{}", err);
+        let err = verify(&atom("not_a_point"), &output_dir, &module_env).unwrap_err().to_string();
+        assert!(err.contains("declares return type Point but its body evaluates to a non-struct value"), "{}", err);
+    }
+
+    const STRUCT_INVARIANT_SOURCE: &str = r#"
+struct Account {
+    balance: i64,
+    reserved: i64 where v >= 0,
+    invariant: balance >= reserved
+}
+struct Window { lo: i64, hi: i64, invariant: lo <= hgh }
+
+atom open(amount: i64) -> Account
+requires: amount >= 0;
+ensures: result.balance == amount;
+body: Account { balance: amount, reserved: 0 };
+
+atom withdraw(a: Account, x: i64) -> Account
+requires: x >= 0 && x <= a.balance - a.reserved;
+ensures: result.reserved == a.reserved;
+body: Account { balance: a.balance - x, reserved: a.reserved };
+
+atom overdraw(a: Account, x: i64) -> Account
+requires: x >= 0;
+ensures: true;
+body: Account { balance: a.balance - x, reserved: a.reserved };
+
+atom available(a: Account)
+requires: true;
+ensures: result >= 0;
+body: a.balance - a.reserved;
+
+atom reopened(amount: i64)
+requires: amount >= 0;
+ensures: result >= 0;
+body: {
+    let a = open(amount);
+    a.balance - a.reserved
+};
+
+atom width(w: Window)
+requires: true;
+ensures: true;
+body: w.lo;
+"#;
+
+    #[test]
+    fn test_struct_invariant_assumed_and_proven() {
+        let (items, module_env) = env_from_source(STRUCT_INVARIANT_SOURCE);
+        let output_dir = TempDir::new("struct_invariant");
+        let atom = |name: &str| find_atom(&items, name);
+
+        // 構造体リテラルと返す値では証明し、パラメータと呼び出し結果では仮定する
+        for name in ["open", "withdraw", "available", "reopened"] {
+            assert!(verify(&atom(name), &output_dir, &module_env).is_ok(), "{} should verify", name);
+        }
+        let err = verify(&atom("overdraw"), &output_dir, &module_env).unwrap_err();
+        assert!(matches!(err, MumeiError::VerificationFailure(FailureKind::Refinement, _)), "{:?}", err);
+        let message = err.to_string();
+        assert!(message.contains("Struct 'Account' invariant violated at a struct literal.\n  Invariant: balance >= reserved"), "{}", message);
+        assert!(message.contains("Counter-example") && message.contains("balance") && message.contains("reserved"), "{}", message);
+
+        // invariant が参照できるのはフィールドだけ
+        let err = verify(&atom("width"), &output_dir, &module_env).unwrap_err();
+        assert!(matches!(err, MumeiError::TypeError(_)), "{:?}", err);
+        assert!(err.to_string().contains("invariant of struct 'Window' mentions 'hgh', which is not a field"), "{}", err);
+    }
+
+    const ASYNC_ENSURES_SOURCE: &str = r#"
+async atom fetch(x: i64)
+requires: x >= 0;
+ensures: result > x;
+body: x + 1;
+
+async atom awaited(x: i64)
+requires: x >= 0;
+ensures: result > 0;
+body: {
+    let v = await fetch(x);
+    v
+};
+
+async atom awaited_later(x: i64)
+requires: x >= 0;
+ensures: result > x;
+body: {
+    let f = fetch(x);
+    let v = await f;
+    v
+};
+
+async atom unawaited(x: i64)
+requires: x >= 0;
+ensures: true;
+body: {
+    let f = fetch(x);
+    f + 1
+};
+"#;
+
+    #[test]
+    fn test_async_ensures_hold_for_awaited_value() {
+        let (items, module_env) = env_from_source(ASYNC_ENSURES_SOURCE);
+        let output_dir = TempDir::new("async_ensures");
+        let atom = |name: &str| find_atom(&items, name);
+
+        // 呼び出し先の ensures は await した値について成り立つ（await を呼び出しと分けても同じ）
+        assert!(verify(&atom("awaited"), &output_dir, &module_env).is_ok());
+        assert!(verify(&atom("awaited_later"), &output_dir, &module_env).is_ok());
+
+        // await していないハンドルは値として使えない
+        let err = verify(&atom("unawaited"), &output_dir, &module_env).unwrap_err();
+        assert!(matches!(err, MumeiError::TypeError(_)), "{}", err);
+        assert!(err.to_string().contains("Unawaited future: the result of async atom 'fetch'"), "{}", err);
+    }
+
+    const RESERVED_SOURCE: &str = r#"
+atom forged_len(a: [i64])
+requires: true;
+ensures: true;
+body: {
+    len_a = 10;
+    a[5]
+};
+
+atom let_alive(x: i64) requires: true; ensures: true; body: { let __alive_x = true; x };
+
+atom set_borrowed(x: i64) requires: true; ensures: true; body: { __borrowed_x = false; x };
+
+atom let_proj(x: i64) requires: true; ensures: true; body: { let __proj_Some_0 = 1; x };
+
+atom set_struct(x: i64) requires: true; ensures: true; body: { __struct_p_x = 0; x };
+
+atom let_call(x: i64) requires: true; ensures: true; body: { let call_inc = 3; x };
+
+atom forged_result(x: i64)
+requires: true;
+ensures: result == 5;
+body: {
+    result = 5;
+    x
+};
+"#;
+
+    #[test]
+    fn test_reserved_names_cannot_be_assigned() {
+        let (items, module_env) = env_from_source(RESERVED_SOURCE);
+        let output_dir = TempDir::new("reserved");
+        let atom = |name: &str| find_atom(&items, name);
+
+        // 配列長を上書きすれば範囲外アクセスが「証明」できてしまっていた
+        let err = verify(&atom("forged_len"), &output_dir, &module_env).unwrap_err().to_string();
+        assert!(err.contains("Cannot assign to 'len_a'") && err.contains("'len_'"), "{}", err);
+
+        for (name, target) in [
+            ("let_alive", "Cannot bind '__alive_x'"),
+            ("set_borrowed", "Cannot assign to '__borrowed_x'"),
+            ("let_proj", "Cannot bind '__proj_Some_0'"),
+            ("set_struct", "Cannot assign to '__struct_p_x'"),
+            ("let_call", "Cannot bind 'call_inc'"),
+        ] {
+            let err = verify(&atom(name), &output_dir, &module_env).unwrap_err().to_string();
+            assert!(err.contains(target) && err.contains("reserved"), "{}: {}", name, err);
+        }
+
+        // result への代入は ensures の戻り値と紛らわしいため、事後条件の判定に進む前に拒否する
+        let err = verify(&atom("forged_result"), &output_dir, &module_env).unwrap_err().to_string();
+        assert!(err.contains("Cannot assign to 'result'"), "{}", err);
+        assert!(!err.contains("Postcondition"), "{}", err);
+    }
+
+    const DETERMINISTIC_SOURCE: &str = r#"
+atom inc(n: i64)
+requires: n >= 0;
+ensures: result == n + 1;
+body: n + 1;
+
+atom twice_inc(x: i64)
+requires: x >= 0;
+ensures: result == inc(x) + inc(x);
+body: inc(x) + inc(x);
+
+atom scale(a: f64)
+requires: a > 0.0;
+ensures: result > 0.0;
+body: a * 2.0;
+"#;
+
+    #[test]
+    fn test_temporary_symbols_are_deterministic() {
+        // 呼び出し結果・Float 一時変数の名前は atom 内の出現順で決まる。
+        // 同じ atom を同一プロセスで 2 回検証しても義務キー（＝シンボル集合を含む SMT 文字列の hash）が一致し、
+        // body と ensures で同じ callee を呼んでも名前が衝突しない（衝突すれば ensures が証明できない）
+        let (items, module_env) = env_from_source(DETERMINISTIC_SOURCE);
+        let output_dir = TempDir::new("deterministic");
+        for name in ["twice_inc", "scale"] {
+            let atom = find_atom(&items, name);
+            let mut first = ObligationCache::default();
+            let mut second = ObligationCache::default();
+            assert!(verify_with_cache(&atom, &output_dir, &module_env, 10000, &mut first).is_ok(), "{}", name);
+            assert!(verify_with_cache(&atom, &output_dir, &module_env, 10000, &mut second).is_ok(), "{}", name);
+            assert!(!first.current.is_empty(), "{}", name);
+            assert_eq!(first.current, second.current, "{}", name);
+
+            // 前回の義務キーをそのまま再利用できる
+            let mut reused = ObligationCache::from_previous(first.current.clone());
+            assert!(verify_with_cache(&atom, &output_dir, &module_env, 10000, &mut reused).is_ok(), "{}", name);
+            assert_eq!(reused.solved, 0, "{}", name);
+        }
+    }
+
+    const IF_PATHS_SOURCE: &str = r#"
+atom guarded_div(n: i64)
+requires: true;
+ensures: true;
+body: if n > 0 { 100 / n } else { 0 };
+
+atom nested_guard(n: i64, d: i64)
+requires: true;
+ensures: true;
+body: if n > 0 { if d != 0 { n / d } else { n } } else { 0 };
+
+atom weak_guard(n: i64)
+requires: true;
+ensures: true;
+body: if n >= 0 { 100 / n } else { 0 };
+
+atom branch_assign(x: i64)
+requires: true;
+ensures: result >= 0;
+body: {
+    let y = 0;
+    if x > 0 { y = x } else { y = 0 - x };
+    y
+};
+"#;
+
+    #[test]
+    fn test_if_branches_are_path_sensitive() {
+        let (items, module_env) = env_from_source(IF_PATHS_SOURCE);
+        let output_dir = TempDir::new("if_paths");
+        let atom = |name: &str| find_atom(&items, name);
+
+        // then 側は条件を仮定できるので requires なしでゼロ除算の義務が証明される（ネストも同様）
+        assert!(verify(&atom("guarded_div"), &output_dir, &module_env).is_ok());
+        assert!(verify(&atom("nested_guard"), &output_dir, &module_env).is_ok());
+
+        // n >= 0 では n == 0 を除外できない
+        let err = verify(&atom("weak_guard"), &output_dir, &module_env).unwrap_err().to_string();
+        assert!(err.contains("division by zero"), "{}", err);
+
+        // 分岐内の代入は条件付きで合流する（else 側の代入が then 側を上書きしない）
+        assert!(verify(&atom("branch_assign"), &output_dir, &module_env).is_ok());
+    }
+
+    const TERNARY_SOURCE: &str = r#"
+atom max2(a: i64, b: i64)
+requires: true;
+ensures: result == (a > b ? a : b) && result >= a && result >= b;
+body: a > b ? a : b;
+
+atom clamp3(x: i64)
+requires: true;
+ensures: result == (x < 0 ? 0 : x > 10 ? 10 : x);
+body: x < 0 ? 0 : x > 10 ? 10 : x;
+
+atom wrong_max(a: i64, b: i64)
+requires: true;
+ensures: result == (a > b ? a : b);
+body: a < b ? a : b;
+"#;
+
+    #[test]
+    fn test_conditional_operator_in_contracts() {
+        let (items, module_env) = env_from_source(TERNARY_SOURCE);
+        let output_dir = TempDir::new("ternary");
+        let atom = |name: &str| find_atom(&items, name);
+
+        // `c ? a : b` は if-then-else と同じく検証される（右結合のネストも含む）
+        assert!(verify(&atom("max2"), &output_dir, &module_env).is_ok());
+        assert!(verify(&atom("clamp3"), &output_dir, &module_env).is_ok());
+        let err = verify(&atom("wrong_max"), &output_dir, &module_env).unwrap_err().to_string();
+        assert!(err.contains("Postcondition"), "{}", err);
+    }
+
+    const APPROX_SOURCE: &str = r#"
+atom average(a: f64, b: f64)
+requires: a >= 0.0 && a <= 1000.0 && b >= 0.0 && b <= 1000.0 && a ~= b within 1.0;
+ensures: result ~= a within 0.501;
+body: (a + b) / 2.0;
+
+atom average_tight(a: f64, b: f64)
+requires: a >= 0.0 && a <= 1000.0 && b >= 0.0 && b <= 1000.0 && a ~= b within 1.0;
+ensures: approx_eq(result, a, 0.25);
+body: (a + b) / 2.0;
+
+atom no_tolerance(a: f64)
+requires: true;
+ensures: result ~= a;
+body: a;
+
+trait Halve {
+    fn halve(a: Self) -> Self;
+    law halves_sum: a >= -1000.0 && a <= 1000.0 => halve(a) + halve(a) ~= a within 0.000001;
+}
+
+impl Halve for f64 {
+    fn halve(a: f64) -> f64 { a * 0.5 }
+}
+"#;
+
+    #[test]
+    fn test_approx_eq_contracts() {
+        // `a ~= b within eps` は approx_eq(a, b, eps) の糖衣構文
+        match &parse_expression("x ~= y + 1.0 within 0.5").unwrap() {
+            Expr::Call(name, args) => {
+                assert_eq!(name, "approx_eq");
+                assert!(matches!(args.as_slice(), [Expr::Variable(_), Expr::BinaryOp(_, Op::Add, _), Expr::Float(eps)] if *eps == 0.5));
+            }
+            other => panic!("unexpected parse: {:?}", other),
+        }
+
+        let (items, module_env) = env_from_source(APPROX_SOURCE);
+        let output_dir = TempDir::new("approx");
+        let atom = |name: &str| find_atom(&items, name);
+
+        // 平均は a から |a - b| / 2 以内（丸め誤差を含めて 0.501 に収まる）
+        assert!(verify(&atom("average"), &output_dir, &module_env).is_ok());
+
+        // 許容誤差が小さすぎれば反例が出る。FP の値は 10 進で表示する
+        let err = verify(&atom("average_tight"), &output_dir, &module_env).unwrap_err().to_string();
+        assert!(err.contains("Postcondition"), "{}", err);
+        assert!(err.contains("a: f64 = ") && err.contains("result: f64 = "), "{}", err);
+        assert!(!err.contains("(fp "), "{}", err);
+
+        // within 節のない `~=` は正の許容誤差がないため型エラー
+        let err = verify(&atom("no_tolerance"), &output_dir, &module_env).unwrap_err().to_string();
+        assert!(err.contains("tolerance (eps) must be a positive numeric literal"), "{}", err);
+
+        // law でも使える（メソッド名 halve の展開が approx_eq を壊さない）
+        let impl_def = items.iter().find_map(|i| if let Item::ImplDef(d) = i { Some(d) } else { None }).unwrap();
+        assert!(verify_impl(impl_def, &module_env).is_ok());
+    }
+
+    #[test]
+    fn test_float_arithmetic_rounds_to_nearest_even() {
+        // 0.1 + 0.2 の真の和は 2 つの double の間にある。最近接偶数丸めでは 0.30000000000000004（実行時の値）、
+        // 0 方向の丸めなら 0.3 になる
+        let source = r#"
+atom sum_rne() requires: true; ensures: result == 0.30000000000000004; body: 0.1 + 0.2;
+atom sum_rtz() requires: true; ensures: result == 0.3; body: 0.1 + 0.2;
+"#;
+        let (items, module_env) = env_from_source(source);
+        let output_dir = TempDir::new("rne");
+        let atom = |name: &str| find_atom(&items, name);
+        assert_eq!(0.1_f64 + 0.2, 0.30000000000000004);
+        assert!(verify(&atom("sum_rne"), &output_dir, &module_env).is_ok());
+        let err = verify(&atom("sum_rtz"), &output_dir, &module_env).unwrap_err().to_string();
+        assert!(err.contains("Postcondition"), "{}", err);
+    }
+
+    const WHERE_SOURCE: &str = r#"
+atom span(lo: i64, hi: i64)
+where: lo <= hi;
+requires: lo >= 0;
+ensures: result >= 0;
+body: hi - lo;
+
+atom good_caller(x: i64)
+requires: x >= 0;
+ensures: result >= 0;
+body: span(x, x + 1);
+
+atom bad_caller(x: i64)
+requires: x >= 1;
+ensures: true;
+body: span(x, x - 1);
+"#;
+
+    #[test]
+    fn test_where_clause_is_a_precondition() {
+        let (items, module_env) = env_from_source(WHERE_SOURCE);
+        let output_dir = TempDir::new("where");
+        let atom = |name: &str| find_atom(&items, name);
+
+        // body は where 節を仮定できる（hi - lo >= 0 は lo <= hi からしか従わない）
+        assert!(verify(&atom("span"), &output_dir, &module_env).is_ok());
+        assert!(verify(&atom("good_caller"), &output_dir, &module_env).is_ok());
+
+        // 呼び出し側は where 節も証明しなければならず、エラーでは requires と分けて表示する
+        let err = verify(&atom("bad_caller"), &output_dir, &module_env).unwrap_err().to_string();
+        assert!(err.contains("Call to 'span': precondition (requires) not satisfied"), "{}", err);
+        assert!(err.contains("Where: lo <= hi\n  Requires: lo >= 0"), "{}", err);
+        // 反例は呼び出し先の仮引数名で実引数の値を示す（hi = lo - 1）
+        assert!(err.contains("Counter-example: lo: i64 = ") && err.contains(", hi: i64 = "), "{}", err);
+    }
+
+    const SCOPING_SOURCE: &str = r#"
+atom arm_temps(n: i64)
+requires: true;
+ensures: (n == 0 && result == 1) || (n != 0 && result == 2);
+body: {
+    let x = 0;
+    match n {
+        0 => { let t = 1; x = t; 0 },
+        _ => { let t = 2; x = t; 0 }
+    };
+    x
+};
+
+atom arm_temps_false(n: i64)
+requires: true;
+ensures: result == 0;
+body: {
+    let x = 0;
+    match n {
+        0 => { let t = 1; x = t; 0 },
+        _ => { let t = 2; x = t; 0 }
+    };
+    x
+};
+
+atom shadowed(n: i64)
+requires: true;
+ensures: result == 1;
+body: {
+    let t = 1;
+    let inner = { let t = 5; t + n };
+    t
+};
+
+atom shadowed_false(n: i64)
+requires: true;
+ensures: result == 5;
+body: {
+    let t = 1;
+    let inner = { let t = 5; t + n };
+    t
+};
+
+atom counted(n: i64)
+requires: n >= 0;
+ensures: result == n;
+body: {
+    let i = 0;
+    while i < n
+    invariant: i >= 0 && i <= n
+    {
+        i = i + 1;
+    };
+    i
+};
+
+atom counted_false(n: i64)
+requires: n >= 0;
+ensures: result == 0;
+body: {
+    let i = 0;
+    while i < n
+    invariant: i >= 0
+    {
+        i = i + 1;
+    };
+    i
+};
+"#;
+
+    #[test]
+    fn test_let_scoping_and_loop_state() {
+        let (items, module_env) = env_from_source(SCOPING_SOURCE);
+        let output_dir = TempDir::new("scoping");
+        let atom = |name: &str| find_atom(&items, name);
+
+        // 各アームの let t はアーム内だけで見え、外側の x への代入はアームの条件で合流する
+        // （以前は代入が捨てられ、x == 0 が誤って証明された）
+        assert!(verify(&atom("arm_temps"), &output_dir, &module_env).is_ok());
+        let err = verify(&atom("arm_temps_false"), &output_dir, &module_env).unwrap_err().to_string();
+        assert!(err.contains("Postcondition"), "{}", err);
+
+        // ブロック内のシャドーイングはブロックを抜けると外側の値に戻る
+        assert!(verify(&atom("shadowed"), &output_dir, &module_env).is_ok());
+        let err = verify(&atom("shadowed_false"), &output_dir, &module_env).unwrap_err().to_string();
+        assert!(err.contains("Postcondition"), "{}", err);
+
+        // ループ後の i は入口の値 0 ではなく invariant && !cond だけで制約される
+        assert!(verify(&atom("counted"), &output_dir, &module_env).is_ok());
+        let err = verify(&atom("counted_false"), &output_dir, &module_env).unwrap_err().to_string();
+        assert!(err.contains("Postcondition"), "{}", err);
+    }
+
+    #[test]
+    fn test_expr_to_z3_deep_left_chain() {
+        // 10 万項の `x + 1 + ... + 1 > x` を、連鎖の長さぶん再帰せずに Z3 の式へ変換する。
+        let x = || Box::new(Expr::Variable("x".into()));
+        let sum = (0..100_000).fold(*x(), |acc, _| Expr::BinaryOp(Box::new(acc), Op::Add, Box::new(Expr::Number(1))));
+        let expr = Expr::BinaryOp(Box::new(sum), Op::Gt, x());
+
+        let ctx = Context::new(&Config::new());
+        let solver = Solver::new(&ctx);
+        let module_env = ModuleEnv::new();
+        let vc = VCtx { ctx: &ctx, arrays: RefCell::default(), params: &[], module_env: &module_env, obligations: None, guard: None, scope: "deep", counters: RefCell::default(), total: false, undecided: RefCell::default(), arm_stack: RefCell::default(), arm_paths: RefCell::default(), entry_measure: RefCell::default(), futures: RefCell::default(), call_lengths: RefCell::default(), struct_values: RefCell::default(), float_defs: RefCell::default(), callees: RefCell::default(), warnings: None };
+        let mut env: Env = HashMap::new();
+        env.insert("x".into(), Int::new_const(&ctx, "x").into());
+        let value = expr_to_z3(&vc, &expr, &mut env, Some(&solver)).unwrap();
+        assert!(value.as_bool().is_some(), "{:?}", value.get_sort());
+    }
+}
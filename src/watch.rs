@@ -0,0 +1,198 @@
+//! # Watch モジュール
+//!
+//! `mumei check|verify|build --watch` の実装。入力ファイル・推移的な import 先・std/ の .mm・mumei.toml の
+//! 更新時刻をポーリングし、変化があれば（DEBOUNCE の間落ち着くのを待って）同じコマンドを
+//! `--watch` なしの子プロセスとして再実行する。
+//!
+//! 各回は別プロセスなので、検証失敗（exit 1）でも監視は続く。ビルドキャッシュ（.mumei_build_cache）は
+//! ディスク経由で次の回に引き継がれるので、変更のない atom は skipped のままになる。
+//! 監視対象は毎回の実行後に計算し直し、ファイルの作成・削除はそれぞれの親ディレクトリの
+//! 更新時刻でも検知するので、import の追加・削除や後から作られたファイルも再起動なしで拾う。
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command as Cmd;
+use std::time::{Duration, Instant, SystemTime};
+
+/// 変更を確認する間隔
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+/// 最後の変更からこの間ほかの変更がなければ再実行する（エディタの連続した書き込みをまとめる）
+const DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// 監視対象のパスごとの更新時刻（存在しなければ None）
+pub type Snapshot = BTreeMap<PathBuf, Option<SystemTime>>;
+
+/// 監視対象
+#[derive(Debug, Default)]
+pub struct WatchSet {
+    /// 入力、推移的な import 先、std/ の .mm、mumei.toml（まだ存在しないものを含む）
+    pub files: Vec<PathBuf>,
+    /// files の親ディレクトリと std/ 以下のディレクトリ（ファイルの作成・削除の検知用）
+    pub dirs: Vec<PathBuf>,
+}
+
+impl WatchSet {
+    /// 入力ファイルの監視対象を計算する。
+    /// 構文エラーや未解決の import があっても、入力と mumei.toml・ディレクトリの監視は続ける
+    pub fn for_input(input: &Path) -> Self {
+        let base_dir = parent_dir(input);
+        let mut files = vec![input.to_path_buf()];
+        let imports = crate::resolver::read_source(input).ok()
+            .and_then(|source| crate::resolver::parse_source(&source, input).ok())
+            .and_then(|items| crate::resolver::import_closure(&items, &base_dir).ok())
+            .unwrap_or_default();
+        files.extend(imports);
+        let mut dirs = Vec::new();
+        // prelude が解決できる std/ を監視する（prelude は import しなくても読み込まれる）
+        if let Some(std_dir) = crate::resolver::resolve_path("std/prelude", &base_dir).ok().and_then(|p| p.parent().map(Path::to_path_buf)) {
+            collect_sources(&std_dir, &mut files, &mut dirs);
+        }
+        files.push(manifest_path());
+        dirs.extend(files.iter().map(|f| parent_dir(f)));
+        files.sort();
+        files.dedup();
+        dirs.sort();
+        dirs.dedup();
+        WatchSet { files, dirs }
+    }
+
+    /// 現在の更新時刻
+    pub fn snapshot(&self) -> Snapshot {
+        self.files.iter().chain(&self.dirs)
+            .map(|path| (path.clone(), std::fs::metadata(path).and_then(|m| m.modified()).ok()))
+            .collect()
+    }
+}
+
+/// パスの親ディレクトリ（`input.mm` のように親がなければカレントディレクトリ）
+fn parent_dir(path: &Path) -> PathBuf {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    }
+}
+
+/// dir 以下の .mm ファイルとディレクトリを再帰的に集める
+fn collect_sources(dir: &Path, files: &mut Vec<PathBuf>, dirs: &mut Vec<PathBuf>) {
+    dirs.push(dir.to_path_buf());
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for path in entries.flatten().map(|entry| entry.path()) {
+        if path.is_dir() {
+            collect_sources(&path, files, dirs);
+        } else if path.extension().map_or(false, |ext| ext == "mm") {
+            files.push(path);
+        }
+    }
+}
+
+/// カレントディレクトリから親を辿って最初に見つかる mumei.toml。
+/// なければカレントディレクトリの mumei.toml（後から作られたら検知する）
+fn manifest_path() -> PathBuf {
+    let cwd = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+    cwd.ancestors()
+        .map(|dir| dir.join("mumei.toml"))
+        .find(|path| path.exists())
+        .unwrap_or_else(|| cwd.join("mumei.toml"))
+}
+
+/// 子プロセスに渡す引数（`--watch` を除いた元のコマンドライン）
+pub fn child_args(args: impl IntoIterator<Item = OsString>) -> Vec<OsString> {
+    args.into_iter().filter(|arg| arg != "--watch").collect()
+}
+
+/// 各回の後に表示する1行のまとめ
+fn summary_line(command: &str, run: usize, success: bool, elapsed: Duration, watched: usize) -> String {
+    let status = if success { "✅ passed" } else { "❌ failed" };
+    format!(
+        "👀 mumei {} {} (run #{}, {:.1}s) — watching {} file(s) for changes, Ctrl-C to stop",
+        command, status, run, elapsed.as_secs_f64(), watched
+    )
+}
+
+/// 監視対象のどれかが変わり、その後 DEBOUNCE の間落ち着くまで待つ
+fn wait_for_change(watch_set: &WatchSet) {
+    let before = watch_set.snapshot();
+    while watch_set.snapshot() == before {
+        std::thread::sleep(POLL_INTERVAL);
+    }
+    let mut last = watch_set.snapshot();
+    loop {
+        std::thread::sleep(DEBOUNCE);
+        let now = watch_set.snapshot();
+        if now == last {
+            return;
+        }
+        last = now;
+    }
+}
+
+/// `--watch` のループ。command（check / verify / build）を args で再実行し続け、
+/// 子プロセスを起動できなくなったときだけ戻る
+pub fn run(command: &str, input: &Path, args: &[OsString]) -> Result<std::convert::Infallible, String> {
+    let exe = std::env::current_exe().map_err(|e| format!("could not locate the mumei executable: {}", e))?;
+    let clear = std::io::stdout().is_terminal();
+    let mut run = 0;
+    loop {
+        run += 1;
+        if clear {
+            // 画面を消してカーソルを先頭に戻す
+            print!("\x1b[2J\x1b[H");
+            let _ = std::io::stdout().flush();
+        }
+        let started = Instant::now();
+        let status = Cmd::new(&exe).args(args).status()
+            .map_err(|e| format!("failed to run {}: {}", exe.display(), e))?;
+        // 実行のたびに計算し直し、追加・削除された import を監視対象に反映する
+        let watch_set = WatchSet::for_input(input);
+        println!();
+        println!("{}", summary_line(command, run, status.success(), started.elapsed(), watch_set.files.len()));
+        wait_for_change(&watch_set);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_child_args_drop_watch_flag() {
+        let args = ["verify", "--watch", "src/main.mm", "--jobs", "2"].map(OsString::from);
+        assert_eq!(child_args(args), ["verify", "src/main.mm", "--jobs", "2"].map(OsString::from).to_vec());
+        let line = summary_line("verify", 3, false, Duration::from_millis(1250), 4);
+        assert!(line.contains("mumei verify ❌ failed (run #3, 1.2s)") && line.contains("watching 4 file(s)"), "{}", line);
+    }
+
+    #[test]
+    fn test_watch_set_follows_imports() {
+        let dir = std::env::temp_dir().join(format!("mumei_watch_{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("lib")).unwrap();
+        let dir = dir.canonicalize().unwrap();
+        let main = dir.join("main.mm");
+        let lib = dir.join("lib").join("math.mm");
+        std::fs::write(&lib, "atom inc(x: i64) requires: true; ensures: result == x + 1; body: x + 1;\n").unwrap();
+        std::fs::write(&main, "import \"./lib/math.mm\";\natom two() requires: true; ensures: true; body: inc(1);\n").unwrap();
+
+        let watch_set = WatchSet::for_input(&main);
+        let lib = lib.canonicalize().unwrap();
+        assert!(watch_set.files.contains(&main) && watch_set.files.contains(&lib), "{:?}", watch_set);
+        assert!(watch_set.files.iter().any(|f| f.ends_with("mumei.toml")), "{:?}", watch_set);
+        assert!(watch_set.dirs.contains(&dir) && watch_set.dirs.contains(&lib.parent().unwrap().to_path_buf()), "{:?}", watch_set);
+
+        // 削除した import 先は存在しない（None）として変化を検知する
+        let before = watch_set.snapshot();
+        std::fs::remove_file(&lib).unwrap();
+        let after = watch_set.snapshot();
+        assert_ne!(before, after);
+        assert_eq!(after[&lib], None);
+
+        // import 先が解決できなくても入力は監視し続ける
+        let watch_set = WatchSet::for_input(&main);
+        assert!(watch_set.files.contains(&main) && !watch_set.files.contains(&lib), "{:?}", watch_set);
+        assert!(watch_set.dirs.contains(&dir));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
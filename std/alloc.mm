@@ -7,6 +7,16 @@
 //
 // Usage:
 //   import "std/alloc" as alloc;
+
+// 公開 API（export にない item はインポート側から参照できない）
+export {
+    RawPtr, NullablePtr, Owned, Vector, alloc_raw, dealloc_raw, vec_new,
+    vec_push, vec_get, vec_len, vec_is_empty, vec_grow, vec_drop,
+    vec_push_safe, HashMap, map_new, map_insert, map_get, map_contains_key,
+    map_remove, map_size, map_is_empty, map_rehash, map_drop,
+    map_insert_safe, map_should_rehash
+};
+
 // --- STEP 1: RawPtr — 生ポインタの精緻型表現 ---
 type RawPtr = i64 where v >= 0;
 type NullablePtr = i64 where v >= -1;
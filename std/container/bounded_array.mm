@@ -6,6 +6,14 @@
 //
 // Usage:
 //   import "std/container/bounded_array" as bounded;
+
+// 公開 API（export にない item はインポート側から参照できない）
+export {
+    BoundedArray, bounded_push, bounded_pop, bounded_is_empty,
+    bounded_is_full, sorted_identity, sorted_min, sorted_max,
+    sorted_insert_len
+};
+
 struct BoundedArray {
     len: i64 where v >= 0,
     cap: i64 where v > 0
@@ -9,6 +9,15 @@
 // Usage:
 //   import "std/list" as list;
 
+// 公開 API（export にない item はインポート側から参照できない）
+export {
+    List, is_empty, head_or, is_sorted_pair, insert_sorted, list_head,
+    list_tail, list_append, list_prepend, list_length, list_reverse,
+    fold_sum, fold_count_gte, fold_min_index, fold_max_index, fold_all_gte,
+    fold_any_gte, insertion_sort, merge_sort, verified_insertion_sort,
+    verified_merge_sort, binary_search, binary_search_sorted
+};
+
 enum List {
     Nil,
     Cons(i64, Self)
@@ -324,8 +333,8 @@ body: {
     if n <= 1 { n }
     else {
         let mid = n / 2;
-        let left = merge_sort(mid);
-        let right = merge_sort(n - mid);
+        let left = await merge_sort(mid);
+        let right = await merge_sort(n - mid);
         left + right
     }
 };
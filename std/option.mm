@@ -12,6 +12,12 @@
 //       Some(T)
 //   }
 
+// 公開 API（export にない item はインポート側から参照できない）
+export {
+    Option, is_some, is_none, unwrap_or, map_apply, and_then_apply, or_else,
+    filter
+};
+
 enum Option<T> {
     None,
     Some(T)
@@ -12,6 +12,12 @@
 //     コンパイラが i64/u64/f64 に対して自動適用する。
 //     law（推移律など）は Z3 上で既知の公理として扱われる。
 
+// 公開 API（export にない item はインポート側から参照できない）
+export {
+    Eq, Ord, Numeric, Pair, Option, Result, List, Sequential, Hashable,
+    prelude_is_some, prelude_is_none, prelude_is_ok
+};
+
 // =============================================================
 // A. 基本トレイト（数学的基盤）
 // =============================================================
@@ -7,6 +7,13 @@
 // Usage:
 //   import "std/result" as result;
 
+// 公開 API（export にない item はインポート側から参照できない）
+export {
+    Result, is_ok, is_err, unwrap_or_default, safe_divide, result_map_apply,
+    result_and_then, result_or_else, result_map_err, result_wrap_err,
+    result_unwrap_or_else, result_flatten
+};
+
 enum Result<T, E> {
     Ok(T),
     Err(E)
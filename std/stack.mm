@@ -6,6 +6,13 @@
 //
 // Usage:
 //   import "std/stack" as stack;
+
+// 公開 API（export にない item はインポート側から参照できない）
+export {
+    Nat, Stack, stack_push, stack_pop, stack_is_empty, stack_is_full,
+    stack_clear
+};
+
 type Nat = i64 where v >= 0;
 struct Stack<T> {
     top: i64 where v >= 0,
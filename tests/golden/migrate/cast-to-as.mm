@@ -0,0 +1,31 @@
+import "./util.mm" as util;
+
+// cast_to_int(x) はコメントの中なので書き換えない
+atom scaled(x: f64, k: f64)
+requires: x >= 0.0 && k >= 1.0;
+ensures: result >= 0;
+body: cast_to_int(x * k);
+
+atom nested(x: f64)
+requires: true;
+ensures: true;
+body: cast_to_int(cast_to_int(x)) + cast_to_int((x));
+
+atom negated(x: f64)
+requires: x >= 0.0;
+ensures: true;
+body: -cast_to_int(x) * 2 - cast_to_int(sqrt(x));
+
+atom clamped(x: f64)
+requires: true;
+ensures: result >= 1;
+body: {
+    assume(x >= 0.0, "cast_to_int(x) is non-negative");
+    let n = cast_to_int(if x > 1.0 { x } else { 1.0 });
+    n
+};
+
+atom qualified(x: f64)
+requires: true;
+ensures: true;
+body: util::cast_to_int(x) + cast_to_int(x);
@@ -0,0 +1,10 @@
+atom peek(ref h: i64)
+    requires: true;
+    ensures: true;
+    body: h;
+
+atom close(h: i64)
+    consume h;
+    requires: h > 0;
+    ensures: peek(h) > 0;
+    body: 0;
@@ -0,0 +1,4 @@
+total atom unguarded_div(x: i64, y: i64)
+    requires: true;
+    ensures: true;
+    body: x / y;
@@ -0,0 +1,26 @@
+// =============================================================
+// Regression corpus: 式の表示 → 再パースの往復
+// =============================================================
+// tailrec::show（義務ラベル・ループ提案で使う表示）が出力した式を
+// parse_expression で読み直すと元の AST に戻ることを確認する。
+// test_show_round_trips_generated が見つけた最小の反例はここに atom として追加する。
+// --- 指数表記になる浮動小数点（トークナイザは 1e20 を読めない） ---
+atom regression_float_exponent(x: f64)
+requires: true;
+ensures: true;
+body: x * 100000000000000000000.0 + 0.0000001;
+// --- 大文字始まりの識別子で終わる if 条件（構造体リテラルと読まれる） ---
+atom regression_if_cond_type_name(flag: i64)
+requires: true;
+ensures: true;
+body: if (flag == None) { 1 } else { 0 };
+// --- 負のリテラルと単項マイナス・フィールドアクセス ---
+atom regression_negative_operands(x: i64)
+requires: true;
+ensures: result == x - -3 - (0 - x);
+body: x - -3 - (0 - x);
+// --- 含意は左結合、比較は連鎖しない ---
+atom regression_implies_grouping(a: i64, b: i64)
+requires: (a > 0 => b > 0) => (a == b) == (b == a);
+ensures: true;
+body: a;
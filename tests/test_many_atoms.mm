@@ -0,0 +1,1010 @@
+// =============================================================
+// Test: 小さな atom を大量に含むモジュール（ソルバプールの計測用）
+// =============================================================
+// 200 個の小さな atom。atom ごとに Context / Solver を作り直していた頃は
+// その生成コストが検証時間の大半を占めていた。現在はスレッドごとのソルバプールを
+// push/pop で使い回す。ビルドキャッシュを消してから計測する:
+//
+//   rm -f tests/.mumei_build_cache && time mumei verify tests/test_many_atoms.mm
+type Nat = i64 where v >= 0;
+type Pos = i64 where v > 0;
+
+atom add_0(n: Nat)
+requires: n >= 0;
+ensures: result >= 0;
+body: n + 0;
+
+atom clamp_1(x: i64)
+requires: true;
+ensures: result >= 0 && result <= 1;
+body: if x < 0 { 0 } else { if x > 1 { 1 } else { x } };
+
+atom div_2(a: Nat, b: Pos)
+requires: b > 0;
+ensures: result >= 0;
+body: a / b;
+
+atom max_3(a: i64, b: i64)
+requires: true;
+ensures: result >= a && result >= b;
+body: if a > b { a } else { b };
+
+atom add_4(n: Nat)
+requires: n >= 0;
+ensures: result >= 4;
+body: n + 4;
+
+atom clamp_5(x: i64)
+requires: true;
+ensures: result >= 0 && result <= 5;
+body: if x < 0 { 0 } else { if x > 5 { 5 } else { x } };
+
+atom div_6(a: Nat, b: Pos)
+requires: b > 0;
+ensures: result >= 0;
+body: a / b;
+
+atom max_7(a: i64, b: i64)
+requires: true;
+ensures: result >= a && result >= b;
+body: if a > b { a } else { b };
+
+atom add_8(n: Nat)
+requires: n >= 0;
+ensures: result >= 8;
+body: n + 8;
+
+atom clamp_9(x: i64)
+requires: true;
+ensures: result >= 0 && result <= 9;
+body: if x < 0 { 0 } else { if x > 9 { 9 } else { x } };
+
+atom div_10(a: Nat, b: Pos)
+requires: b > 0;
+ensures: result >= 0;
+body: a / b;
+
+atom max_11(a: i64, b: i64)
+requires: true;
+ensures: result >= a && result >= b;
+body: if a > b { a } else { b };
+
+atom add_12(n: Nat)
+requires: n >= 0;
+ensures: result >= 12;
+body: n + 12;
+
+atom clamp_13(x: i64)
+requires: true;
+ensures: result >= 0 && result <= 13;
+body: if x < 0 { 0 } else { if x > 13 { 13 } else { x } };
+
+atom div_14(a: Nat, b: Pos)
+requires: b > 0;
+ensures: result >= 0;
+body: a / b;
+
+atom max_15(a: i64, b: i64)
+requires: true;
+ensures: result >= a && result >= b;
+body: if a > b { a } else { b };
+
+atom add_16(n: Nat)
+requires: n >= 0;
+ensures: result >= 16;
+body: n + 16;
+
+atom clamp_17(x: i64)
+requires: true;
+ensures: result >= 0 && result <= 17;
+body: if x < 0 { 0 } else { if x > 17 { 17 } else { x } };
+
+atom div_18(a: Nat, b: Pos)
+requires: b > 0;
+ensures: result >= 0;
+body: a / b;
+
+atom max_19(a: i64, b: i64)
+requires: true;
+ensures: result >= a && result >= b;
+body: if a > b { a } else { b };
+
+atom add_20(n: Nat)
+requires: n >= 0;
+ensures: result >= 20;
+body: n + 20;
+
+atom clamp_21(x: i64)
+requires: true;
+ensures: result >= 0 && result <= 21;
+body: if x < 0 { 0 } else { if x > 21 { 21 } else { x } };
+
+atom div_22(a: Nat, b: Pos)
+requires: b > 0;
+ensures: result >= 0;
+body: a / b;
+
+atom max_23(a: i64, b: i64)
+requires: true;
+ensures: result >= a && result >= b;
+body: if a > b { a } else { b };
+
+atom add_24(n: Nat)
+requires: n >= 0;
+ensures: result >= 24;
+body: n + 24;
+
+atom clamp_25(x: i64)
+requires: true;
+ensures: result >= 0 && result <= 25;
+body: if x < 0 { 0 } else { if x > 25 { 25 } else { x } };
+
+atom div_26(a: Nat, b: Pos)
+requires: b > 0;
+ensures: result >= 0;
+body: a / b;
+
+atom max_27(a: i64, b: i64)
+requires: true;
+ensures: result >= a && result >= b;
+body: if a > b { a } else { b };
+
+atom add_28(n: Nat)
+requires: n >= 0;
+ensures: result >= 28;
+body: n + 28;
+
+atom clamp_29(x: i64)
+requires: true;
+ensures: result >= 0 && result <= 29;
+body: if x < 0 { 0 } else { if x > 29 { 29 } else { x } };
+
+atom div_30(a: Nat, b: Pos)
+requires: b > 0;
+ensures: result >= 0;
+body: a / b;
+
+atom max_31(a: i64, b: i64)
+requires: true;
+ensures: result >= a && result >= b;
+body: if a > b { a } else { b };
+
+atom add_32(n: Nat)
+requires: n >= 0;
+ensures: result >= 32;
+body: n + 32;
+
+atom clamp_33(x: i64)
+requires: true;
+ensures: result >= 0 && result <= 33;
+body: if x < 0 { 0 } else { if x > 33 { 33 } else { x } };
+
+atom div_34(a: Nat, b: Pos)
+requires: b > 0;
+ensures: result >= 0;
+body: a / b;
+
+atom max_35(a: i64, b: i64)
+requires: true;
+ensures: result >= a && result >= b;
+body: if a > b { a } else { b };
+
+atom add_36(n: Nat)
+requires: n >= 0;
+ensures: result >= 36;
+body: n + 36;
+
+atom clamp_37(x: i64)
+requires: true;
+ensures: result >= 0 && result <= 37;
+body: if x < 0 { 0 } else { if x > 37 { 37 } else { x } };
+
+atom div_38(a: Nat, b: Pos)
+requires: b > 0;
+ensures: result >= 0;
+body: a / b;
+
+atom max_39(a: i64, b: i64)
+requires: true;
+ensures: result >= a && result >= b;
+body: if a > b { a } else { b };
+
+atom add_40(n: Nat)
+requires: n >= 0;
+ensures: result >= 40;
+body: n + 40;
+
+atom clamp_41(x: i64)
+requires: true;
+ensures: result >= 0 && result <= 41;
+body: if x < 0 { 0 } else { if x > 41 { 41 } else { x } };
+
+atom div_42(a: Nat, b: Pos)
+requires: b > 0;
+ensures: result >= 0;
+body: a / b;
+
+atom max_43(a: i64, b: i64)
+requires: true;
+ensures: result >= a && result >= b;
+body: if a > b { a } else { b };
+
+atom add_44(n: Nat)
+requires: n >= 0;
+ensures: result >= 44;
+body: n + 44;
+
+atom clamp_45(x: i64)
+requires: true;
+ensures: result >= 0 && result <= 45;
+body: if x < 0 { 0 } else { if x > 45 { 45 } else { x } };
+
+atom div_46(a: Nat, b: Pos)
+requires: b > 0;
+ensures: result >= 0;
+body: a / b;
+
+atom max_47(a: i64, b: i64)
+requires: true;
+ensures: result >= a && result >= b;
+body: if a > b { a } else { b };
+
+atom add_48(n: Nat)
+requires: n >= 0;
+ensures: result >= 48;
+body: n + 48;
+
+atom clamp_49(x: i64)
+requires: true;
+ensures: result >= 0 && result <= 49;
+body: if x < 0 { 0 } else { if x > 49 { 49 } else { x } };
+
+atom div_50(a: Nat, b: Pos)
+requires: b > 0;
+ensures: result >= 0;
+body: a / b;
+
+atom max_51(a: i64, b: i64)
+requires: true;
+ensures: result >= a && result >= b;
+body: if a > b { a } else { b };
+
+atom add_52(n: Nat)
+requires: n >= 0;
+ensures: result >= 52;
+body: n + 52;
+
+atom clamp_53(x: i64)
+requires: true;
+ensures: result >= 0 && result <= 53;
+body: if x < 0 { 0 } else { if x > 53 { 53 } else { x } };
+
+atom div_54(a: Nat, b: Pos)
+requires: b > 0;
+ensures: result >= 0;
+body: a / b;
+
+atom max_55(a: i64, b: i64)
+requires: true;
+ensures: result >= a && result >= b;
+body: if a > b { a } else { b };
+
+atom add_56(n: Nat)
+requires: n >= 0;
+ensures: result >= 56;
+body: n + 56;
+
+atom clamp_57(x: i64)
+requires: true;
+ensures: result >= 0 && result <= 57;
+body: if x < 0 { 0 } else { if x > 57 { 57 } else { x } };
+
+atom div_58(a: Nat, b: Pos)
+requires: b > 0;
+ensures: result >= 0;
+body: a / b;
+
+atom max_59(a: i64, b: i64)
+requires: true;
+ensures: result >= a && result >= b;
+body: if a > b { a } else { b };
+
+atom add_60(n: Nat)
+requires: n >= 0;
+ensures: result >= 60;
+body: n + 60;
+
+atom clamp_61(x: i64)
+requires: true;
+ensures: result >= 0 && result <= 61;
+body: if x < 0 { 0 } else { if x > 61 { 61 } else { x } };
+
+atom div_62(a: Nat, b: Pos)
+requires: b > 0;
+ensures: result >= 0;
+body: a / b;
+
+atom max_63(a: i64, b: i64)
+requires: true;
+ensures: result >= a && result >= b;
+body: if a > b { a } else { b };
+
+atom add_64(n: Nat)
+requires: n >= 0;
+ensures: result >= 64;
+body: n + 64;
+
+atom clamp_65(x: i64)
+requires: true;
+ensures: result >= 0 && result <= 65;
+body: if x < 0 { 0 } else { if x > 65 { 65 } else { x } };
+
+atom div_66(a: Nat, b: Pos)
+requires: b > 0;
+ensures: result >= 0;
+body: a / b;
+
+atom max_67(a: i64, b: i64)
+requires: true;
+ensures: result >= a && result >= b;
+body: if a > b { a } else { b };
+
+atom add_68(n: Nat)
+requires: n >= 0;
+ensures: result >= 68;
+body: n + 68;
+
+atom clamp_69(x: i64)
+requires: true;
+ensures: result >= 0 && result <= 69;
+body: if x < 0 { 0 } else { if x > 69 { 69 } else { x } };
+
+atom div_70(a: Nat, b: Pos)
+requires: b > 0;
+ensures: result >= 0;
+body: a / b;
+
+atom max_71(a: i64, b: i64)
+requires: true;
+ensures: result >= a && result >= b;
+body: if a > b { a } else { b };
+
+atom add_72(n: Nat)
+requires: n >= 0;
+ensures: result >= 72;
+body: n + 72;
+
+atom clamp_73(x: i64)
+requires: true;
+ensures: result >= 0 && result <= 73;
+body: if x < 0 { 0 } else { if x > 73 { 73 } else { x } };
+
+atom div_74(a: Nat, b: Pos)
+requires: b > 0;
+ensures: result >= 0;
+body: a / b;
+
+atom max_75(a: i64, b: i64)
+requires: true;
+ensures: result >= a && result >= b;
+body: if a > b { a } else { b };
+
+atom add_76(n: Nat)
+requires: n >= 0;
+ensures: result >= 76;
+body: n + 76;
+
+atom clamp_77(x: i64)
+requires: true;
+ensures: result >= 0 && result <= 77;
+body: if x < 0 { 0 } else { if x > 77 { 77 } else { x } };
+
+atom div_78(a: Nat, b: Pos)
+requires: b > 0;
+ensures: result >= 0;
+body: a / b;
+
+atom max_79(a: i64, b: i64)
+requires: true;
+ensures: result >= a && result >= b;
+body: if a > b { a } else { b };
+
+atom add_80(n: Nat)
+requires: n >= 0;
+ensures: result >= 80;
+body: n + 80;
+
+atom clamp_81(x: i64)
+requires: true;
+ensures: result >= 0 && result <= 81;
+body: if x < 0 { 0 } else { if x > 81 { 81 } else { x } };
+
+atom div_82(a: Nat, b: Pos)
+requires: b > 0;
+ensures: result >= 0;
+body: a / b;
+
+atom max_83(a: i64, b: i64)
+requires: true;
+ensures: result >= a && result >= b;
+body: if a > b { a } else { b };
+
+atom add_84(n: Nat)
+requires: n >= 0;
+ensures: result >= 84;
+body: n + 84;
+
+atom clamp_85(x: i64)
+requires: true;
+ensures: result >= 0 && result <= 85;
+body: if x < 0 { 0 } else { if x > 85 { 85 } else { x } };
+
+atom div_86(a: Nat, b: Pos)
+requires: b > 0;
+ensures: result >= 0;
+body: a / b;
+
+atom max_87(a: i64, b: i64)
+requires: true;
+ensures: result >= a && result >= b;
+body: if a > b { a } else { b };
+
+atom add_88(n: Nat)
+requires: n >= 0;
+ensures: result >= 88;
+body: n + 88;
+
+atom clamp_89(x: i64)
+requires: true;
+ensures: result >= 0 && result <= 89;
+body: if x < 0 { 0 } else { if x > 89 { 89 } else { x } };
+
+atom div_90(a: Nat, b: Pos)
+requires: b > 0;
+ensures: result >= 0;
+body: a / b;
+
+atom max_91(a: i64, b: i64)
+requires: true;
+ensures: result >= a && result >= b;
+body: if a > b { a } else { b };
+
+atom add_92(n: Nat)
+requires: n >= 0;
+ensures: result >= 92;
+body: n + 92;
+
+atom clamp_93(x: i64)
+requires: true;
+ensures: result >= 0 && result <= 93;
+body: if x < 0 { 0 } else { if x > 93 { 93 } else { x } };
+
+atom div_94(a: Nat, b: Pos)
+requires: b > 0;
+ensures: result >= 0;
+body: a / b;
+
+atom max_95(a: i64, b: i64)
+requires: true;
+ensures: result >= a && result >= b;
+body: if a > b { a } else { b };
+
+atom add_96(n: Nat)
+requires: n >= 0;
+ensures: result >= 96;
+body: n + 96;
+
+atom clamp_97(x: i64)
+requires: true;
+ensures: result >= 0 && result <= 97;
+body: if x < 0 { 0 } else { if x > 97 { 97 } else { x } };
+
+atom div_98(a: Nat, b: Pos)
+requires: b > 0;
+ensures: result >= 0;
+body: a / b;
+
+atom max_99(a: i64, b: i64)
+requires: true;
+ensures: result >= a && result >= b;
+body: if a > b { a } else { b };
+
+atom add_100(n: Nat)
+requires: n >= 0;
+ensures: result >= 100;
+body: n + 100;
+
+atom clamp_101(x: i64)
+requires: true;
+ensures: result >= 0 && result <= 101;
+body: if x < 0 { 0 } else { if x > 101 { 101 } else { x } };
+
+atom div_102(a: Nat, b: Pos)
+requires: b > 0;
+ensures: result >= 0;
+body: a / b;
+
+atom max_103(a: i64, b: i64)
+requires: true;
+ensures: result >= a && result >= b;
+body: if a > b { a } else { b };
+
+atom add_104(n: Nat)
+requires: n >= 0;
+ensures: result >= 104;
+body: n + 104;
+
+atom clamp_105(x: i64)
+requires: true;
+ensures: result >= 0 && result <= 105;
+body: if x < 0 { 0 } else { if x > 105 { 105 } else { x } };
+
+atom div_106(a: Nat, b: Pos)
+requires: b > 0;
+ensures: result >= 0;
+body: a / b;
+
+atom max_107(a: i64, b: i64)
+requires: true;
+ensures: result >= a && result >= b;
+body: if a > b { a } else { b };
+
+atom add_108(n: Nat)
+requires: n >= 0;
+ensures: result >= 108;
+body: n + 108;
+
+atom clamp_109(x: i64)
+requires: true;
+ensures: result >= 0 && result <= 109;
+body: if x < 0 { 0 } else { if x > 109 { 109 } else { x } };
+
+atom div_110(a: Nat, b: Pos)
+requires: b > 0;
+ensures: result >= 0;
+body: a / b;
+
+atom max_111(a: i64, b: i64)
+requires: true;
+ensures: result >= a && result >= b;
+body: if a > b { a } else { b };
+
+atom add_112(n: Nat)
+requires: n >= 0;
+ensures: result >= 112;
+body: n + 112;
+
+atom clamp_113(x: i64)
+requires: true;
+ensures: result >= 0 && result <= 113;
+body: if x < 0 { 0 } else { if x > 113 { 113 } else { x } };
+
+atom div_114(a: Nat, b: Pos)
+requires: b > 0;
+ensures: result >= 0;
+body: a / b;
+
+atom max_115(a: i64, b: i64)
+requires: true;
+ensures: result >= a && result >= b;
+body: if a > b { a } else { b };
+
+atom add_116(n: Nat)
+requires: n >= 0;
+ensures: result >= 116;
+body: n + 116;
+
+atom clamp_117(x: i64)
+requires: true;
+ensures: result >= 0 && result <= 117;
+body: if x < 0 { 0 } else { if x > 117 { 117 } else { x } };
+
+atom div_118(a: Nat, b: Pos)
+requires: b > 0;
+ensures: result >= 0;
+body: a / b;
+
+atom max_119(a: i64, b: i64)
+requires: true;
+ensures: result >= a && result >= b;
+body: if a > b { a } else { b };
+
+atom add_120(n: Nat)
+requires: n >= 0;
+ensures: result >= 120;
+body: n + 120;
+
+atom clamp_121(x: i64)
+requires: true;
+ensures: result >= 0 && result <= 121;
+body: if x < 0 { 0 } else { if x > 121 { 121 } else { x } };
+
+atom div_122(a: Nat, b: Pos)
+requires: b > 0;
+ensures: result >= 0;
+body: a / b;
+
+atom max_123(a: i64, b: i64)
+requires: true;
+ensures: result >= a && result >= b;
+body: if a > b { a } else { b };
+
+atom add_124(n: Nat)
+requires: n >= 0;
+ensures: result >= 124;
+body: n + 124;
+
+atom clamp_125(x: i64)
+requires: true;
+ensures: result >= 0 && result <= 125;
+body: if x < 0 { 0 } else { if x > 125 { 125 } else { x } };
+
+atom div_126(a: Nat, b: Pos)
+requires: b > 0;
+ensures: result >= 0;
+body: a / b;
+
+atom max_127(a: i64, b: i64)
+requires: true;
+ensures: result >= a && result >= b;
+body: if a > b { a } else { b };
+
+atom add_128(n: Nat)
+requires: n >= 0;
+ensures: result >= 128;
+body: n + 128;
+
+atom clamp_129(x: i64)
+requires: true;
+ensures: result >= 0 && result <= 129;
+body: if x < 0 { 0 } else { if x > 129 { 129 } else { x } };
+
+atom div_130(a: Nat, b: Pos)
+requires: b > 0;
+ensures: result >= 0;
+body: a / b;
+
+atom max_131(a: i64, b: i64)
+requires: true;
+ensures: result >= a && result >= b;
+body: if a > b { a } else { b };
+
+atom add_132(n: Nat)
+requires: n >= 0;
+ensures: result >= 132;
+body: n + 132;
+
+atom clamp_133(x: i64)
+requires: true;
+ensures: result >= 0 && result <= 133;
+body: if x < 0 { 0 } else { if x > 133 { 133 } else { x } };
+
+atom div_134(a: Nat, b: Pos)
+requires: b > 0;
+ensures: result >= 0;
+body: a / b;
+
+atom max_135(a: i64, b: i64)
+requires: true;
+ensures: result >= a && result >= b;
+body: if a > b { a } else { b };
+
+atom add_136(n: Nat)
+requires: n >= 0;
+ensures: result >= 136;
+body: n + 136;
+
+atom clamp_137(x: i64)
+requires: true;
+ensures: result >= 0 && result <= 137;
+body: if x < 0 { 0 } else { if x > 137 { 137 } else { x } };
+
+atom div_138(a: Nat, b: Pos)
+requires: b > 0;
+ensures: result >= 0;
+body: a / b;
+
+atom max_139(a: i64, b: i64)
+requires: true;
+ensures: result >= a && result >= b;
+body: if a > b { a } else { b };
+
+atom add_140(n: Nat)
+requires: n >= 0;
+ensures: result >= 140;
+body: n + 140;
+
+atom clamp_141(x: i64)
+requires: true;
+ensures: result >= 0 && result <= 141;
+body: if x < 0 { 0 } else { if x > 141 { 141 } else { x } };
+
+atom div_142(a: Nat, b: Pos)
+requires: b > 0;
+ensures: result >= 0;
+body: a / b;
+
+atom max_143(a: i64, b: i64)
+requires: true;
+ensures: result >= a && result >= b;
+body: if a > b { a } else { b };
+
+atom add_144(n: Nat)
+requires: n >= 0;
+ensures: result >= 144;
+body: n + 144;
+
+atom clamp_145(x: i64)
+requires: true;
+ensures: result >= 0 && result <= 145;
+body: if x < 0 { 0 } else { if x > 145 { 145 } else { x } };
+
+atom div_146(a: Nat, b: Pos)
+requires: b > 0;
+ensures: result >= 0;
+body: a / b;
+
+atom max_147(a: i64, b: i64)
+requires: true;
+ensures: result >= a && result >= b;
+body: if a > b { a } else { b };
+
+atom add_148(n: Nat)
+requires: n >= 0;
+ensures: result >= 148;
+body: n + 148;
+
+atom clamp_149(x: i64)
+requires: true;
+ensures: result >= 0 && result <= 149;
+body: if x < 0 { 0 } else { if x > 149 { 149 } else { x } };
+
+atom div_150(a: Nat, b: Pos)
+requires: b > 0;
+ensures: result >= 0;
+body: a / b;
+
+atom max_151(a: i64, b: i64)
+requires: true;
+ensures: result >= a && result >= b;
+body: if a > b { a } else { b };
+
+atom add_152(n: Nat)
+requires: n >= 0;
+ensures: result >= 152;
+body: n + 152;
+
+atom clamp_153(x: i64)
+requires: true;
+ensures: result >= 0 && result <= 153;
+body: if x < 0 { 0 } else { if x > 153 { 153 } else { x } };
+
+atom div_154(a: Nat, b: Pos)
+requires: b > 0;
+ensures: result >= 0;
+body: a / b;
+
+atom max_155(a: i64, b: i64)
+requires: true;
+ensures: result >= a && result >= b;
+body: if a > b { a } else { b };
+
+atom add_156(n: Nat)
+requires: n >= 0;
+ensures: result >= 156;
+body: n + 156;
+
+atom clamp_157(x: i64)
+requires: true;
+ensures: result >= 0 && result <= 157;
+body: if x < 0 { 0 } else { if x > 157 { 157 } else { x } };
+
+atom div_158(a: Nat, b: Pos)
+requires: b > 0;
+ensures: result >= 0;
+body: a / b;
+
+atom max_159(a: i64, b: i64)
+requires: true;
+ensures: result >= a && result >= b;
+body: if a > b { a } else { b };
+
+atom add_160(n: Nat)
+requires: n >= 0;
+ensures: result >= 160;
+body: n + 160;
+
+atom clamp_161(x: i64)
+requires: true;
+ensures: result >= 0 && result <= 161;
+body: if x < 0 { 0 } else { if x > 161 { 161 } else { x } };
+
+atom div_162(a: Nat, b: Pos)
+requires: b > 0;
+ensures: result >= 0;
+body: a / b;
+
+atom max_163(a: i64, b: i64)
+requires: true;
+ensures: result >= a && result >= b;
+body: if a > b { a } else { b };
+
+atom add_164(n: Nat)
+requires: n >= 0;
+ensures: result >= 164;
+body: n + 164;
+
+atom clamp_165(x: i64)
+requires: true;
+ensures: result >= 0 && result <= 165;
+body: if x < 0 { 0 } else { if x > 165 { 165 } else { x } };
+
+atom div_166(a: Nat, b: Pos)
+requires: b > 0;
+ensures: result >= 0;
+body: a / b;
+
+atom max_167(a: i64, b: i64)
+requires: true;
+ensures: result >= a && result >= b;
+body: if a > b { a } else { b };
+
+atom add_168(n: Nat)
+requires: n >= 0;
+ensures: result >= 168;
+body: n + 168;
+
+atom clamp_169(x: i64)
+requires: true;
+ensures: result >= 0 && result <= 169;
+body: if x < 0 { 0 } else { if x > 169 { 169 } else { x } };
+
+atom div_170(a: Nat, b: Pos)
+requires: b > 0;
+ensures: result >= 0;
+body: a / b;
+
+atom max_171(a: i64, b: i64)
+requires: true;
+ensures: result >= a && result >= b;
+body: if a > b { a } else { b };
+
+atom add_172(n: Nat)
+requires: n >= 0;
+ensures: result >= 172;
+body: n + 172;
+
+atom clamp_173(x: i64)
+requires: true;
+ensures: result >= 0 && result <= 173;
+body: if x < 0 { 0 } else { if x > 173 { 173 } else { x } };
+
+atom div_174(a: Nat, b: Pos)
+requires: b > 0;
+ensures: result >= 0;
+body: a / b;
+
+atom max_175(a: i64, b: i64)
+requires: true;
+ensures: result >= a && result >= b;
+body: if a > b { a } else { b };
+
+atom add_176(n: Nat)
+requires: n >= 0;
+ensures: result >= 176;
+body: n + 176;
+
+atom clamp_177(x: i64)
+requires: true;
+ensures: result >= 0 && result <= 177;
+body: if x < 0 { 0 } else { if x > 177 { 177 } else { x } };
+
+atom div_178(a: Nat, b: Pos)
+requires: b > 0;
+ensures: result >= 0;
+body: a / b;
+
+atom max_179(a: i64, b: i64)
+requires: true;
+ensures: result >= a && result >= b;
+body: if a > b { a } else { b };
+
+atom add_180(n: Nat)
+requires: n >= 0;
+ensures: result >= 180;
+body: n + 180;
+
+atom clamp_181(x: i64)
+requires: true;
+ensures: result >= 0 && result <= 181;
+body: if x < 0 { 0 } else { if x > 181 { 181 } else { x } };
+
+atom div_182(a: Nat, b: Pos)
+requires: b > 0;
+ensures: result >= 0;
+body: a / b;
+
+atom max_183(a: i64, b: i64)
+requires: true;
+ensures: result >= a && result >= b;
+body: if a > b { a } else { b };
+
+atom add_184(n: Nat)
+requires: n >= 0;
+ensures: result >= 184;
+body: n + 184;
+
+atom clamp_185(x: i64)
+requires: true;
+ensures: result >= 0 && result <= 185;
+body: if x < 0 { 0 } else { if x > 185 { 185 } else { x } };
+
+atom div_186(a: Nat, b: Pos)
+requires: b > 0;
+ensures: result >= 0;
+body: a / b;
+
+atom max_187(a: i64, b: i64)
+requires: true;
+ensures: result >= a && result >= b;
+body: if a > b { a } else { b };
+
+atom add_188(n: Nat)
+requires: n >= 0;
+ensures: result >= 188;
+body: n + 188;
+
+atom clamp_189(x: i64)
+requires: true;
+ensures: result >= 0 && result <= 189;
+body: if x < 0 { 0 } else { if x > 189 { 189 } else { x } };
+
+atom div_190(a: Nat, b: Pos)
+requires: b > 0;
+ensures: result >= 0;
+body: a / b;
+
+atom max_191(a: i64, b: i64)
+requires: true;
+ensures: result >= a && result >= b;
+body: if a > b { a } else { b };
+
+atom add_192(n: Nat)
+requires: n >= 0;
+ensures: result >= 192;
+body: n + 192;
+
+atom clamp_193(x: i64)
+requires: true;
+ensures: result >= 0 && result <= 193;
+body: if x < 0 { 0 } else { if x > 193 { 193 } else { x } };
+
+atom div_194(a: Nat, b: Pos)
+requires: b > 0;
+ensures: result >= 0;
+body: a / b;
+
+atom max_195(a: i64, b: i64)
+requires: true;
+ensures: result >= a && result >= b;
+body: if a > b { a } else { b };
+
+atom add_196(n: Nat)
+requires: n >= 0;
+ensures: result >= 196;
+body: n + 196;
+
+atom clamp_197(x: i64)
+requires: true;
+ensures: result >= 0 && result <= 197;
+body: if x < 0 { 0 } else { if x > 197 { 197 } else { x } };
+
+atom div_198(a: Nat, b: Pos)
+requires: b > 0;
+ensures: result >= 0;
+body: a / b;
+
+atom max_199(a: i64, b: i64)
+requires: true;
+ensures: result >= a && result >= b;
+body: if a > b { a } else { b };
@@ -0,0 +1,17 @@
+// =============================================================
+// Test: 義務単位キャッシュ（per-obligation cache）
+// =============================================================
+// 20 箇所の配列アクセス（境界チェック義務 20 件）を持つ atom。
+// 1回目の `mumei verify` で全義務を証明し .mumei_build_cache に保存する。
+// ensures だけを編集して再実行すると、境界チェック 20 件は
+// 「obligation(s) reused」として再利用され、ensures 義務のみ再証明される。
+//
+//   time mumei verify tests/test_obligation_cache.mm   # 1回目: 全義務を証明
+//   (ensures の result >= 0 を result >= 0 && result <= 1900 に変更)
+//   time mumei verify tests/test_obligation_cache.mm   # 2回目: ensures のみ再証明
+atom sum_prefix20(arr: i64, n: i64)
+requires: n >= 20 && len_arr == n && forall(i, 0, n, arr[i] >= 0 && arr[i] <= 100);
+ensures: result >= 0;
+body: {
+    arr[0] + arr[1] + arr[2] + arr[3] + arr[4] + arr[5] + arr[6] + arr[7] + arr[8] + arr[9] + arr[10] + arr[11] + arr[12] + arr[13] + arr[14] + arr[15] + arr[16] + arr[17] + arr[18] + arr[19]
+};
@@ -0,0 +1,69 @@
+// =============================================================
+// Test: exclusive リソースの critical section からの値の持ち出し
+// =============================================================
+// acquire ledger { ... } の中で計算した値は ledger の汚染ラベルを持つ。
+// ラベル付きの値がブロックの外で atom の結果・atom 呼び出しの実引数に
+// 流れると警告（[proof] strict_resources = true ではエラー）になる。
+resource ledger priority: 1 mode: exclusive;
+resource config priority: 2 mode: shared;
+
+atom audit(note: Str)
+requires: true;
+ensures: result >= 0;
+body: len(note);
+
+atom bump(ref mut n: i64)
+requires: true;
+ensures: true;
+body: n;
+
+// --- 警告: Str の値をブロックの結果として持ち出す ---
+atom leak_entry(entry: Str)
+resources: [ledger];
+requires: true;
+ensures: true;
+body: acquire ledger { entry };
+
+// --- 警告: ブロックの結果を let 経由でブロック外の呼び出しに渡す ---
+atom forward_entry(entry: Str)
+resources: [ledger];
+requires: true;
+ensures: true;
+body: {
+    let held = acquire ledger { entry };
+    audit(held)
+};
+
+// --- 警告: スカラーでも ref mut で貸し出すのはコピーではない ---
+atom lend_balance(balance: i64)
+resources: [ledger];
+requires: true;
+ensures: true;
+body: {
+    let held = acquire ledger { balance };
+    bump(ref mut held)
+};
+
+// --- OK: 呼び出しを critical section の中で済ませる ---
+atom record_entry(entry: Str)
+resources: [ledger];
+requires: true;
+ensures: result >= 0;
+body: acquire ledger { audit(entry) };
+
+// --- OK: スカラーのコピーは既定では対象外（strict_resources ではエラー） ---
+atom read_balance(balance: i64)
+resources: [ledger];
+requires: balance >= 0;
+ensures: result >= 0;
+body: {
+    let copy = acquire ledger { balance + 0 };
+    copy
+};
+
+// --- OK: shared リソースの値は持ち出してよい ---
+atom read_setting(name: Str)
+resources: [config];
+requires: true;
+ensures: true;
+body: acquire config { name };
@@ -14,23 +14,43 @@ except FileNotFoundError:
     st.info("No verification reports found. Run the Mumei compiler first.")
     st.stop()
 
-# 状態の表示
-if data["status"] == "failed":
-    st.error(f"❌ Verification Failed: Atom '{data['atom']}' is flawed.")
+# report.json（schema_version 1.x）: モジュール全体または atom 単位の検証結果
+summary = data.get("summary", {})
+st.caption(f"schema {data.get('schema_version', '?')} · {data.get('command', '')} · {data.get('tool', {}).get('z3_version', '')}")
 
-    col1, col2 = st.columns(2)
-    with col1:
-        st.metric("Counter-example: a", data["input_a"])
-    with col2:
-        st.metric("Counter-example: b", data["input_b"])
+col1, col2, col3 = st.columns(3)
+with col1:
+    st.metric("Verified", summary.get("verified", 0))
+with col2:
+    st.metric("Failed", summary.get("failed", 0))
+with col3:
+    st.metric("Skipped", summary.get("skipped", 0))
 
-    st.warning(f"**Reason:** {data['reason']}")
+atoms = data.get("atoms", [])
+if atoms:
+    st.dataframe(pd.DataFrame([
+        {"name": a["name"], "kind": a.get("kind", "atom"), "status": a.get("status", ""), "ms": a.get("duration_ms", 0)}
+        for a in atoms
+    ]))
+
+for a in atoms:
+    if a.get("status") != "failed":
+        continue
+    st.error(f"❌ Verification Failed: Atom '{a['name']}' is flawed.")
+    st.warning(f"**Reason:** {a.get('message', '')}")
+    values = (a.get("counterexample") or {}).get("values", [])
+    if values:
+        cols = st.columns(len(values))
+        for col, v in zip(cols, values):
+            with col:
+                st.metric(f"Counter-example: {v['name']}", v["value"])
 
     # AIへの修正指示用プロンプトの自動生成
     st.code(f"""
     # AI Fix Suggestion:
-    The atom '{data['atom']}' failed verification when b={data['input_b']}.
+    The atom '{a['name']}' failed verification: {a.get('message', '')}
     Please update the 'requires' clause to handle this case.
     """, language="markdown")
-else:
-    st.success(f"✅ Atom '{data['atom']}' is mathematically pure.")
\ No newline at end of file
+
+if summary.get("failed", 0) == 0:
+    st.success("✅ All atoms are mathematically pure.")